@@ -40,6 +40,28 @@ pub fn init(img: Handle, syst: &SystemTable<Boot>) {
     }
 }
 
+/// Get the load options of the boot loader image as the kernel command line.
+///
+/// The options are truncated to fit in the buffer, which always ends with a
+/// NUL.
+pub fn cmdline(img: Handle, syst: &SystemTable<Boot>) -> [u8; minfo::CMDLINE_LEN] {
+    let local_img = syst
+        .boot_services()
+        .handle_protocol::<LoadedImage>(img)
+        .expect_success("Failed to locate loaded image protocol");
+
+    let mut buffer = [0; 4096];
+    let options = unsafe { &*local_img.get() }
+        .load_options(&mut buffer)
+        .unwrap_or("");
+    log::debug!("Command line: {:?}", options);
+
+    let mut ret = [0; minfo::CMDLINE_LEN];
+    let len = options.len().min(minfo::CMDLINE_LEN - 1);
+    ret[..len].copy_from_slice(&options.as_bytes()[..len]);
+    ret
+}
+
 /// Load a file in the local volume.
 ///
 /// # Returns
@@ -22,6 +22,8 @@ mod file;
 mod mem;
 mod outp;
 mod rxx;
+#[cfg(feature = "verify")]
+mod verify;
 
 use core::mem::MaybeUninit;
 
@@ -59,6 +61,7 @@ unsafe fn init_services(img: Handle, syst: &SystemTable<Boot>) {
     /// services.
     fn exit_boot_services_callback(_: uefi::Event) {
         log::debug!("Reaching end of H2O boot loader");
+        outp::detach();
         unsafe { LOGGER.assume_init_mut().disable() };
         uefi::alloc::exit_boot_services();
     }
@@ -84,25 +87,50 @@ unsafe fn init_services(img: Handle, syst: &SystemTable<Boot>) {
 
     mem::init(syst);
     {
-        use archop::reg::cr4;
-        cr4::set(cr4::FSGSBASE);
+        use archop::reg::{cr4, has_fsgsbase};
+        if has_fsgsbase() {
+            cr4::set(cr4::FSGSBASE);
+        }
         cr4::unset(cr4::TSD);
     }
 }
 
+/// Get the resolution set by the `video=<width>x<height>` boot option.
+fn video_option(cmdline: &[u8]) -> Option<(usize, usize)> {
+    let len = cmdline
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(cmdline.len());
+    let option = cmdline[..len]
+        .split(u8::is_ascii_whitespace)
+        .find_map(|opt| opt.strip_prefix(b"video="))?;
+    let option = core::str::from_utf8(option).ok()?;
+    let (width, height) = option.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// The number of the loading steps shown in the progress bar.
+const STEPS: usize = 4;
+
 #[entry]
 fn efi_main(img: Handle, syst: SystemTable<Boot>) -> Status {
     unsafe { init_services(img, &syst) };
     info!("H2O UEFI loader for Oceanic OS");
 
-    outp::choose_mode(&syst, (1024, 768));
+    let cmdline = file::cmdline(img, &syst);
+
+    outp::choose_mode(&syst, video_option(&cmdline));
     outp::draw_logo(&syst);
+    outp::progress(0, STEPS);
+    let framebuffer = outp::framebuffer(&syst);
 
     let (entry, pls_layout, tinit, bootfs) = {
         // Load the TAR archive file.
         let tar = file::load(&syst, "\\EFI\\Oceanic\\H2O.k");
         // Get the files.
         let files = file::tar::untar(unsafe { &*tar });
+        #[cfg(feature = "verify")]
+        verify::verify(&files);
 
         // Map kernel file
         let (h2o_entry, h2o_pls_layout) = {
@@ -115,10 +143,13 @@ fn efi_main(img: Handle, syst: SystemTable<Boot>) -> Status {
             );
             file::elf::map_elf(&syst, h2o)
         };
+        outp::progress(1, STEPS);
 
         let tinit = unsafe { &*file::realloc_file(&syst, files.find("TINIT")) };
+        outp::progress(2, STEPS);
 
         let bootfs = unsafe { &*file::realloc_file(&syst, files.find("BOOT.fs")) };
+        outp::progress(3, STEPS);
 
         mem::alloc(&syst).dealloc_from_slice(tar, mem::EFI_ID_OFFSET);
         (h2o_entry, h2o_pls_layout, tinit, bootfs)
@@ -127,6 +158,7 @@ fn efi_main(img: Handle, syst: SystemTable<Boot>) -> Status {
     // Prepare the data needed for H2O.
     let (efi_mmap_unit, mmap_size_approx) = mem::init_pf(&syst);
     let (rsdp, smbios) = mem::get_rsdp_smbios(&syst);
+    outp::progress(STEPS, STEPS);
 
     // Get the EFI memory map to be parsed in the kernel. So far we cannot parse it
     // in the loader because if we make dynamic space after get the map, the map
@@ -154,6 +186,8 @@ fn efi_main(img: Handle, syst: SystemTable<Boot>) -> Status {
             tinit_len: tinit.len(),
             bootfs_phys: paging::LAddr::new(bootfs.as_ptr() as *mut _).to_paddr(mem::EFI_ID_OFFSET),
             bootfs_len: bootfs.len(),
+            cmdline,
+            framebuffer: Some(framebuffer),
         });
         call_kmain(entry);
     }
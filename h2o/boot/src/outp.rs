@@ -5,6 +5,19 @@ use uefi::{prelude::*, proto::console::gop::*};
 
 static LOGO_FILE: &[u8] = include_bytes!("../../assets/Oceanic.500.bmp");
 static mut RESOLUTION: Option<(usize, usize)> = None;
+/// The graphics output, which is only available until the boot services are
+/// exited.
+static mut GOP: Option<NonNull<GraphicsOutput<'static>>> = None;
+
+/// The position of the progress bar, which is under the logo.
+static mut BAR_POS: Option<(usize, usize)> = None;
+
+const BAR_SIZE: (usize, usize) = (320, 4);
+/// The distance between the logo and the progress bar.
+const BAR_GAP: usize = 32;
+const BAR_BACKGROUND: u32 = 0x30_30_30;
+const BAR_FOREGROUND: u32 = 0xe0_e0_e0;
+const BAR_ERROR: u32 = 0xe0_20_20;
 
 unsafe fn gop<'a>(syst: &SystemTable<Boot>) -> NonNull<GraphicsOutput<'a>> {
     NonNull::new_unchecked(
@@ -15,38 +28,86 @@ unsafe fn gop<'a>(syst: &SystemTable<Boot>) -> NonNull<GraphicsOutput<'a>> {
     )
 }
 
-pub fn choose_mode(syst: &SystemTable<Boot>, preferred_res: (usize, usize)) -> (usize, usize) {
+#[inline]
+fn is_supported(info: &ModeInfo) -> bool {
+    matches!(info.pixel_format(), PixelFormat::Bgr | PixelFormat::Rgb)
+}
+
+/// Set the graphics mode of the resolution `preferred` if available.
+///
+/// Otherwise, the current mode is kept since the firmware usually sets the
+/// native resolution of the display, and the largest mode is the fallback.
+pub fn choose_mode(syst: &SystemTable<Boot>, preferred: Option<(usize, usize)>) -> (usize, usize) {
     log::trace!(
-        "outp::choose_mode: syst = {:?}, preferred_res = {:?}",
+        "outp::choose_mode: syst = {:?}, preferred = {:?}",
         syst as *const _,
-        preferred_res
+        preferred
     );
 
     let mut gop = unsafe { self::gop(syst) };
-    let mode = {
-        let mut selected = None;
-        let modes = unsafe { gop.as_ref() }.modes();
-        for mode in modes {
-            let mode = mode.unwrap();
-
-            let minfo = mode.info();
-            if minfo.resolution() == preferred_res && minfo.pixel_format() == PixelFormat::Bgr {
-                selected = Some(mode);
-            }
+    let current = unsafe { gop.as_ref() }.current_mode_info();
+    let modes = unsafe { gop.as_ref() }
+        .modes()
+        .map(|mode| mode.unwrap())
+        .filter(|mode| is_supported(mode.info()))
+        .collect::<Vec<_>>();
+
+    let area = |mode: &&Mode| {
+        let (width, height) = mode.info().resolution();
+        width * height
+    };
+    let by_preference = preferred.and_then(|res| {
+        let mut modes = modes.iter();
+        modes.find(|mode| mode.info().resolution() == res)
+    });
+    let mode = match by_preference {
+        Some(mode) => Some(mode),
+        None if is_supported(&current) => None,
+        None => {
+            let largest = modes.iter().max_by_key(area);
+            Some(largest.expect("Failed to find a proper mode"))
         }
-        selected.expect("Failed to find a proper mode")
     };
 
     unsafe {
-        RESOLUTION = Some(mode.info().resolution());
-        gop.as_mut()
-            .set_mode(&mode)
-            .expect_success("Failed to set mode");
+        if let Some(mode) = mode {
+            gop.as_mut()
+                .set_mode(mode)
+                .expect_success("Failed to set mode");
+        }
+        GOP = Some(gop.cast());
+        RESOLUTION = Some(gop.as_ref().current_mode_info().resolution());
+        log::info!("Graphics mode: {:?}", RESOLUTION.unwrap());
 
         RESOLUTION.unwrap()
     }
 }
 
+/// Forget the graphics output when exiting the boot services.
+pub fn detach() {
+    unsafe { GOP = None };
+}
+
+/// Get the framebuffer of the current mode, which stays valid after exiting
+/// the boot services.
+pub fn framebuffer(syst: &SystemTable<Boot>) -> minfo::Framebuffer {
+    log::trace!("outp::framebuffer: syst = {:?}", syst as *const _);
+
+    let gop = unsafe { self::gop(syst).as_mut() };
+    let info = gop.current_mode_info();
+    let mut fb = gop.frame_buffer();
+    let (width, height) = info.resolution();
+    minfo::Framebuffer {
+        // The boot services identically map the physical memory.
+        phys: paging::PAddr::new(fb.as_mut_ptr() as usize),
+        size: fb.size(),
+        width: width as u32,
+        height: height as u32,
+        stride: info.stride() as u32,
+        bgr: info.pixel_format() == PixelFormat::Bgr,
+    }
+}
+
 fn get_logo_data() -> (Vec<BltPixel>, (usize, usize)) {
     log::trace!("outp::get_logo_data");
 
@@ -88,4 +149,47 @@ pub fn draw_logo(syst: &SystemTable<Boot>) {
             dims,
         })
         .expect_success("Failed to draw a logo");
+
+    let bar_y = dest.1 + dims.1 + BAR_GAP;
+    if res.0 >= BAR_SIZE.0 && bar_y + BAR_SIZE.1 <= res.1 {
+        unsafe { BAR_POS = Some(((res.0 - BAR_SIZE.0) / 2, bar_y)) };
+    }
+}
+
+/// Fill the progress bar up to `done` out of `total`.
+fn draw_bar(done: usize, total: usize, color: u32) {
+    let (Some(mut gop), Some((x, y))) = (unsafe { GOP }, unsafe { BAR_POS }) else {
+        return;
+    };
+    let gop = unsafe { gop.as_mut() };
+
+    let filled = BAR_SIZE.0 * done.min(total) / total.max(1);
+    let mut fill = |color, x, width| {
+        let op = BltOp::VideoFill {
+            color: BltPixel::from(color),
+            dest: (x, y),
+            dims: (width, BAR_SIZE.1),
+        };
+        // Failing to draw the bar is harmless.
+        let _ = gop.blt(op);
+    };
+    if filled > 0 {
+        fill(color, x, filled);
+    }
+    if filled < BAR_SIZE.0 {
+        fill(BAR_BACKGROUND, x + filled, BAR_SIZE.0 - filled);
+    }
+}
+
+/// Show the progress of loading the system.
+#[inline]
+pub fn progress(done: usize, total: usize) {
+    draw_bar(done, total, BAR_FOREGROUND)
+}
+
+/// Turn the progress bar red to indicate a failure, whose details are left to
+/// the text output.
+#[inline]
+pub fn show_error() {
+    draw_bar(1, 1, BAR_ERROR)
 }
@@ -11,6 +11,7 @@ fn panic_handler(info: &core::panic::PanicInfo) -> ! {
             log::error!("{}", message);
         }
     }
+    crate::outp::show_error();
 
     loop {
         core::hint::spin_loop();
@@ -0,0 +1,55 @@
+//! The verification of the boot files.
+//!
+//! The tarball carries a `SIGNATURE` file, the Ed25519 signature of the
+//! SHA-256 hashes of the files in [`SIGNED_FILES`] concatenated in order,
+//! made with the key whose public half is given in hex by `H2O_BOOT_PUBKEY`
+//! at build time.
+
+use crypto::{ed25519, sha256};
+
+use crate::file::tar::Files;
+
+/// The files signed, in the order of their hashes in the signed message.
+const SIGNED_FILES: [&str; 3] = ["KERNEL", "TINIT", "BOOT.fs"];
+
+const PUBLIC_KEY: [u8; ed25519::PUBLIC_KEY_LEN] = parse_hex(env!("H2O_BOOT_PUBKEY"));
+
+const fn parse_hex(s: &str) -> [u8; ed25519::PUBLIC_KEY_LEN] {
+    const fn digit(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => panic!("Invalid hex digit in H2O_BOOT_PUBKEY"),
+        }
+    }
+
+    let s = s.as_bytes();
+    assert!(
+        s.len() == ed25519::PUBLIC_KEY_LEN * 2,
+        "Invalid length of H2O_BOOT_PUBKEY"
+    );
+    let mut ret = [0; ed25519::PUBLIC_KEY_LEN];
+    let mut i = 0;
+    while i < ret.len() {
+        ret[i] = (digit(s[i * 2]) << 4) | digit(s[i * 2 + 1]);
+        i += 1;
+    }
+    ret
+}
+
+/// Verify the signature of the boot files, refusing to boot if it's invalid.
+pub fn verify(files: &Files) {
+    let mut message = [0; sha256::OUT_LEN * SIGNED_FILES.len()];
+    let (hashes, _) = message.as_chunks_mut::<{ sha256::OUT_LEN }>();
+    for (hash, name) in hashes.iter_mut().zip(SIGNED_FILES) {
+        *hash = sha256::hash(files.find(name));
+    }
+
+    let signature = <&[u8; ed25519::SIGNATURE_LEN]>::try_from(files.find("SIGNATURE"))
+        .expect("Invalid length of the signature");
+    if !ed25519::verify(&PUBLIC_KEY, &message, signature) {
+        panic!("Failed to verify the boot files");
+    }
+    log::info!("Boot files verified");
+}
@@ -23,7 +23,13 @@ fn main() -> Result<(), Box<dyn Error>> {
         asm_build(tram_src, &tram_dst, &[])?;
     }
 
-    for file in Path::new("entry/x86_64").read_dir()?.flatten() {
+    // Sorted so that the objects are linked in the same order every time.
+    let mut files = Path::new("entry/x86_64")
+        .read_dir()?
+        .flatten()
+        .collect::<Vec<_>>();
+    files.sort_by_key(|file| file.file_name());
+    for file in files {
         let mut dst_name = file.file_name().to_string_lossy().to_string();
         dst_name += ".o";
 
@@ -70,3 +70,47 @@ checked_copy:
 .fault:
       add   rdx, 1
       jmp   .ret
+
+global sleep_save:function
+; Save the callee-saved registers and the stack pointer into `rsp`, and call
+; `enter(arg)`. If `enter` returns, so does this function with its return
+; value; otherwise the CPU comes back from `sleep_resume` with 0.
+;
+; fn sleep_save(
+; (rdi) rsp: *mut u64,
+; (rsi) enter: unsafe extern "C" fn(usize) -> usize,
+; (rdx) arg: usize,
+;) -> usize
+sleep_save:
+      push  rbp
+      push  rbx
+      push  r12
+      push  r13
+      push  r14
+      push  r15
+      pushfq
+      mov   [rdi], rsp
+
+      mov   rdi, rdx
+      call  rsi
+      jmp   .restore
+
+global sleep_resume:function
+; Switch back to the stack saved by `sleep_save` and return from there with 0,
+; setting `done` after leaving the current stack.
+;
+; fn sleep_resume((rdi) rsp: u64, (rsi) done: *mut bool) -> !
+sleep_resume:
+      mov   rsp, rdi
+      mov   byte [rsi], 1
+      xor   rax, rax
+
+sleep_save.restore:
+      popfq
+      pop   r15
+      pop   r14
+      pop   r13
+      pop   r12
+      pop   rbx
+      pop   rbp
+      ret
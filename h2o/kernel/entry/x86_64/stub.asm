@@ -4,6 +4,7 @@ USR_CODE_X86 equ 0x18
 USR_DATA_X64 equ 0x20
 USR_CODE_X64 equ 0x28 + 3
 
+FS_BASE           equ 0xc0000100
 GS_BASE           equ 0xc0000101
 KERNEL_GS_BASE    equ 0xc0000102
 
@@ -41,6 +42,7 @@ struc KernelGs
       .syscall_user_stack     resq 1
       .syscall_stack          resq 1
       .kernel_fs              resq 1
+      .fsgsbase               resq 1
 endstruc
 
 %macro align_rsp 1
@@ -85,6 +87,47 @@ endstruc
       pop   rcx
 %endmacro
 
+; push_base(seg, msr): `push_xs` with `rd{fs,gs}base` if available.
+%macro push_base 2
+      cmp   qword [gs:(KernelGs.fsgsbase)], 0
+      je    %%msr
+      push  rcx
+      rd%{1}base  rcx
+      xchg  [rsp], rcx
+      jmp   %%next
+%%msr:
+      push_xs %2
+%%next:
+%endmacro
+
+; pop_base(seg, msr): `pop_xs` with `wr{fs,gs}base` if available.
+%macro pop_base 2
+      cmp   qword [gs:(KernelGs.fsgsbase)], 0
+      je    %%msr
+      xchg  rcx, [rsp]
+      wr%{1}base  rcx
+      pop   rcx
+      jmp   %%next
+%%msr:
+      pop_xs %2
+%%next:
+%endmacro
+
+; Load the kernel's FS base, clobbering rax, rcx and rdx.
+%macro load_kernel_fs 0
+      mov   rax, [gs:(KernelGs.kernel_fs)]
+      cmp   qword [gs:(KernelGs.fsgsbase)], 0
+      je    %%msr
+      wrfsbase rax
+      jmp   %%next
+%%msr:
+      mov   rdx, rax
+      shr   rdx, 32
+      mov   rcx, FS_BASE
+      wrmsr
+%%next:
+%endmacro
+
 ; push_regs(bool save_ret_addr, bool gs_swapped)
 %macro push_regs 2
 %if %1 == 1
@@ -109,15 +152,11 @@ endstruc
       push  r14
       push  r15
 
-      push  rcx
-      rdfsbase  rcx
-      xchg  [rsp], rcx
+      push_base fs, FS_BASE
 %if %2 == 1
       push_xs KERNEL_GS_BASE
 %else
-      push  rcx
-      rdgsbase  rcx
-      xchg  [rsp], rcx
+      push_base gs, GS_BASE
 %endif
 %if %1 == 1
       push  rcx
@@ -150,13 +189,9 @@ endstruc
 %if %1 == 1
       pop_xs KERNEL_GS_BASE
 %else
-      xchg  rcx, [rsp]
-      wrgsbase  rcx
-      pop   rcx
+      pop_base gs, GS_BASE
 %endif
-      xchg  rcx, [rsp]
-      wrfsbase  rcx
-      pop   rcx
+      pop_base fs, FS_BASE
 
       pop   r15
       pop   r14
@@ -204,6 +239,8 @@ ExVec_VmmComm           equ   29
 ApicVec_Timer           equ   0x20
 ApicVec_Error           equ   0x21
 ApicVec_IpiTaskMigrate  equ   0x22
+ApicVec_IpiTlbFlush     equ   0x23
+ApicVec_IpiSuspend      equ   0x24
 ApicVec_Spurious        equ   0xFF
 
 ; define_intr(vec, asm_name, name, err_vec)
@@ -309,6 +346,8 @@ define_intr ExVec_SimdExcep,        rout_simd,              hdl_simd,
 define_intr ApicVec_Timer,          rout_lapic_timer,             hdl_lapic_timer,              -1
 define_intr ApicVec_Error,          rout_lapic_error,             hdl_lapic_error,              -1
 define_intr ApicVec_IpiTaskMigrate, rout_lapic_ipi_task_migrate,  hdl_lapic_ipi_task_migrate,   -1
+define_intr ApicVec_IpiTlbFlush,    rout_lapic_ipi_tlb_flush,     hdl_lapic_ipi_tlb_flush,      -1
+define_intr ApicVec_IpiSuspend,     rout_lapic_ipi_suspend,       hdl_lapic_ipi_suspend,        -1
 define_intr ApicVec_Spurious,       rout_lapic_spurious,          hdl_lapic_spurious,           -1
 
 ; All other interrupts
@@ -353,8 +392,7 @@ intr_entry:
       push_regs   1, 1; The routine has a return address, so we must preserve it.
       lea   rbp, [rsp + 8 + 1]
 
-      mov   rax, [gs:(KernelGs.kernel_fs)]
-      wrfsbase rax
+      load_kernel_fs
 
       align_call  save_regs, r12
 
@@ -409,8 +447,7 @@ rout_syscall:
       push_regs   0, 1
       lea   rbp, [rsp + 8 + 1]
 
-      mov   rax, [gs:(KernelGs.kernel_fs)]
-      wrfsbase rax
+      load_kernel_fs
 
       mov   rcx, GS_BASE
       rdmsr
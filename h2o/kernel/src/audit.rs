@@ -0,0 +1,142 @@
+//! The audit log of the security-relevant events.
+//!
+//! The events are recorded into a ring of [`CAPACITY`] records, where the
+//! oldest ones are overwritten, and read with `sv_audit_read` by the holder of
+//! the root memory resource, which can also choose the kinds recorded with
+//! `sv_audit_filter`. User servers report their own events, such as denied
+//! opens, with `sv_audit_report`.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering::*};
+
+use spin::Mutex;
+use sv_call::audit::{AuditRecord, AUDIT_ALL_KINDS, AUDIT_KIND_COUNT};
+
+use crate::{
+    cpu::time::Instant,
+    sched::{PREEMPT, SCHED},
+};
+
+pub const CAPACITY: usize = 256;
+
+struct Ring {
+    records: [AuditRecord; CAPACITY],
+    /// The sequence number of the next record.
+    next: u64,
+}
+
+const EMPTY: AuditRecord = AuditRecord {
+    seq: 0,
+    time: 0,
+    kind: 0,
+    task: 0,
+    job: 0,
+    args: [0; 3],
+};
+
+static RING: Mutex<Ring> = Mutex::new(Ring {
+    records: [EMPTY; CAPACITY],
+    next: 0,
+});
+
+static FILTER: AtomicU32 = AtomicU32::new(AUDIT_ALL_KINDS);
+
+/// Record an event of `kind` caused by the current task, if the kind is not
+/// filtered out.
+///
+/// The function must not be called in `SCHED.with_current`.
+pub fn record(kind: u32, args: [u64; 3]) {
+    debug_assert!(kind < AUDIT_KIND_COUNT);
+    if FILTER.load(Acquire) & (1 << kind) == 0 {
+        return;
+    }
+    let (task, job) = SCHED
+        .with_current(|cur| Ok((cur.tid().raw(), cur.space().id())))
+        .unwrap_or_default();
+    let time = unsafe { Instant::now().raw() } as u64;
+
+    PREEMPT.scope(|| {
+        let mut ring = RING.lock();
+        let seq = ring.next;
+        ring.next += 1;
+        ring.records[seq as usize % CAPACITY] = AuditRecord {
+            seq,
+            time,
+            kind,
+            task,
+            job,
+            args,
+        };
+    })
+}
+
+/// Copy at most `count` records from the sequence number `from`, skipping the
+/// overwritten ones, and return them with the sequence number to read next.
+fn read(from: u64, count: usize) -> sv_call::Result<(Vec<AuditRecord>, u64)> {
+    let count = count.min(CAPACITY);
+    let mut ret = Vec::new();
+    ret.try_reserve(count).map_err(|_| sv_call::ENOMEM)?;
+    PREEMPT.scope(|| {
+        let ring = RING.lock();
+        let start = from.max(ring.next.saturating_sub(CAPACITY as u64));
+        let end = ring.next.min(start + count as u64);
+        ret.extend((start..end).map(|seq| ring.records[seq as usize % CAPACITY]));
+        Ok((ret, end))
+    })
+}
+
+mod syscall {
+    use core::sync::atomic::Ordering::Release;
+
+    use sv_call::{audit::*, *};
+
+    use crate::{
+        dev::{mem_resource, Resource},
+        sched::SCHED,
+        syscall::{InOut, Out, UserPtr},
+    };
+
+    fn check_root(res: Handle) -> Result {
+        SCHED.with_current(|cur| {
+            let res = cur.space().handles().get::<Resource<usize>>(res)?;
+            res.check_root(mem_resource())
+        })
+    }
+
+    /// Read the records from the sequence number in `seq` into `records`,
+    /// returning the number of the records read and updating `seq` to the
+    /// next one to read.
+    #[syscall]
+    fn audit_read(
+        res: Handle,
+        seq: UserPtr<InOut, u64>,
+        records: UserPtr<Out, AuditRecord>,
+        count: usize,
+    ) -> Result<usize> {
+        check_root(res)?;
+        records.check_slice(count)?;
+        let from = unsafe { seq.read() }?;
+
+        let (data, next) = super::read(from, count)?;
+        records.write_slice(&data)?;
+        seq.out().write(next)?;
+        Ok(data.len())
+    }
+
+    /// Record only the kinds whose bits are set in `mask`.
+    #[syscall]
+    fn audit_filter(res: Handle, mask: u32) -> Result {
+        check_root(res)?;
+        super::FILTER.store(mask & AUDIT_ALL_KINDS, Release);
+        Ok(())
+    }
+
+    #[syscall]
+    fn audit_report(kind: u32, a0: u64, a1: u64, a2: u64) -> Result {
+        if kind >= AUDIT_KIND_COUNT || AUDIT_USER_KINDS & (1 << kind) == 0 {
+            return Err(EINVAL);
+        }
+        super::record(kind, [a0, a1, a2]);
+        Ok(())
+    }
+}
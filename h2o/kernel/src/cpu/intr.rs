@@ -5,7 +5,7 @@ use core::ops::Range;
 
 use archop::Azy;
 
-pub use self::imp::Interrupt;
+pub use self::imp::{balance, Interrupt};
 pub use super::arch::intr as arch;
 use crate::{
     dev::{ioapic, Resource},
@@ -1,23 +1,130 @@
-use alloc::sync::Arc;
+use alloc::{
+    boxed::Box,
+    format,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering::*},
+    time::Duration,
+};
 
 use crossbeam_queue::ArrayQueue;
-use sv_call::Feature;
+use spin::{Mutex, Once};
+use sv_call::{
+    res::{IntrLatency, IntrPollStat},
+    Feature,
+};
 
 use super::arch::Manager;
 use crate::{
-    cpu::time::Instant,
+    cpu::{
+        time::{Instant, Timer},
+        CpuMask,
+    },
     dev::Resource,
-    sched::{task::hdl::DefaultFeature, Event, EventData, SIG_GENERIC},
+    sched::{
+        task::{
+            hdl::{DefaultFeature, KernelObject},
+            kthread::{self, KThread},
+            Priority,
+        },
+        Arsc, Event, EventData, PREEMPT, SIG_GENERIC,
+    },
 };
 
 const MAX_TIMES: usize = 100;
+/// The number of the power-of-2 buckets of the latency histograms in
+/// nanoseconds, covering up to about 18 minutes.
+const LATENCY_BUCKETS: usize = 40;
+
+/// All the live interrupts, for the balancer and the poller.
+static INTERRUPTS: Mutex<Vec<Weak<Interrupt>>> = Mutex::new(Vec::new());
+
+/// The kernel thread polling the interrupts in polled mode, spawned on the
+/// first switch into it.
+static POLLER: Once<KThread> = Once::new();
+/// The timer waking up the poller for the next poll due.
+static POLL_TIMER: Mutex<Option<Arsc<Timer>>> = Mutex::new(None);
+
+#[derive(Debug)]
+struct Route {
+    cpu: usize,
+    affinity: CpuMask,
+}
+
+/// The histogram of the latency from the hard IRQ to the handler.
+#[derive(Debug)]
+struct Latency {
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+    max: AtomicU64,
+}
+
+impl Latency {
+    fn new() -> Self {
+        Latency {
+            buckets: core::array::from_fn(|_| AtomicU64::new(0)),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, latency: Duration) {
+        let ns = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        let index = (u64::BITS - ns.leading_zeros()) as usize;
+        self.buckets[index.min(LATENCY_BUCKETS - 1)].fetch_add(1, Relaxed);
+        self.max.fetch_max(ns, Relaxed);
+    }
+
+    fn stat(&self) -> IntrLatency {
+        let buckets: [u64; LATENCY_BUCKETS] =
+            core::array::from_fn(|index| self.buckets[index].load(Relaxed));
+        let count = buckets.iter().sum();
+        let max = self.max.load(Relaxed);
+
+        // The upper bound of the bucket where the percentile falls in.
+        let percentile = |p: u64| {
+            let rank = (count * p + 99) / 100;
+            let mut acc = 0;
+            let index = buckets.iter().position(|&n| {
+                acc += n;
+                acc >= rank
+            });
+            (1u64 << index.unwrap_or(0)).min(max)
+        };
+        IntrLatency {
+            count,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max,
+        }
+    }
+}
+
+/// The polled mode of an interrupt.
+#[derive(Debug)]
+struct Poll {
+    interval: Duration,
+    /// The number of the polls left before switching back to interrupts.
+    left: u32,
+    next: Instant,
+}
 
 #[derive(Debug)]
 pub struct Interrupt {
     gsi: u32,
-    cpu: usize,
+    route: Mutex<Route>,
+    /// The number of times the interrupt fired on each CPU.
+    counts: Box<[AtomicU64]>,
     last_time: ArrayQueue<Instant>,
+    /// Only measured for threaded interrupts.
+    latency: Option<Latency>,
     level_triggered: bool,
+    poll: Mutex<Option<Poll>>,
+    polling: AtomicBool,
+    polls: AtomicU64,
+    poll_entered: AtomicU64,
+    poll_exhausted: AtomicU64,
     event_data: EventData,
 }
 
@@ -27,7 +134,7 @@ impl Event for Interrupt {
     }
 
     fn wait(&self, waiter: Arc<dyn crate::sched::Waiter>) {
-        if self.level_triggered {
+        if self.level_triggered && !self.polling.load(Acquire) {
             Manager::mask(self.gsi, false).unwrap();
         }
         self.wait_impl(waiter);
@@ -52,60 +159,281 @@ impl Interrupt {
         res: &Resource<u32>,
         gsi: u32,
         cpu: usize,
+        affinity: CpuMask,
         level_triggered: bool,
+        threaded: bool,
     ) -> sv_call::Result<Arc<Self>> {
-        if res.magic_eq(super::gsi_resource()) && res.range().contains(&gsi) {
-            Ok(Arc::try_new(Interrupt {
-                gsi,
-                cpu,
-                last_time: ArrayQueue::new(MAX_TIMES),
-                level_triggered,
-                event_data: EventData::new(0),
-            })?)
-        } else {
-            Err(sv_call::EPERM)
+        let end = gsi.checked_add(1).ok_or(sv_call::ERANGE)?;
+        res.check(super::gsi_resource(), gsi..end)?;
+        let counts = (0..crate::cpu::count())
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        let intr = Arc::try_new(Interrupt {
+            gsi,
+            route: Mutex::new(Route { cpu, affinity }),
+            counts,
+            last_time: ArrayQueue::new(MAX_TIMES),
+            latency: threaded.then(Latency::new),
+            level_triggered,
+            poll: Mutex::new(None),
+            polling: AtomicBool::new(false),
+            polls: AtomicU64::new(0),
+            poll_entered: AtomicU64::new(0),
+            poll_exhausted: AtomicU64::new(0),
+            event_data: EventData::new(0),
+        })?;
+        PREEMPT.scope(|| {
+            let mut intrs = INTERRUPTS.lock();
+            intrs.retain(|intr| intr.strong_count() > 0);
+            intrs.push(Arc::downgrade(&intr));
+        });
+        Ok(intr)
+    }
+
+    /// Take the time of the earliest pending hard IRQ, which is picked up by
+    /// the handler.
+    pub fn last_time(&self) -> Option<Instant> {
+        let time = self.last_time.pop()?;
+        if let Some(ref latency) = self.latency {
+            latency.record(Instant::now() - time);
         }
+        Some(time)
     }
 
     #[inline]
-    pub fn last_time(&self) -> Option<Instant> {
-        self.last_time.pop()
+    pub fn latency(&self) -> Option<IntrLatency> {
+        self.latency.as_ref().map(Latency::stat)
     }
 
     #[inline]
     pub fn gsi(&self) -> u32 {
         self.gsi
     }
+
+    #[inline]
+    pub fn cpu(&self) -> usize {
+        PREEMPT.scope(|| self.route.lock().cpu)
+    }
+
+    #[inline]
+    pub fn counts(&self) -> impl Iterator<Item = u64> + '_ {
+        self.counts.iter().map(|count| count.load(Relaxed))
+    }
+
+    #[inline]
+    fn handler(&self) -> (super::IntrHandler, *mut u8) {
+        (handler, (self as *const Interrupt) as *mut u8)
+    }
+
+    fn migrate(&self, route: &mut Route, cpu: usize) -> sv_call::Result {
+        Manager::migrate(self.gsi, route.cpu, cpu, self.handler())?;
+        route.cpu = cpu;
+        Ok(())
+    }
+
+    /// Restrict the interrupt to the CPUs in `affinity`, moving it if the
+    /// current CPU is not one of them.
+    pub fn set_affinity(&self, affinity: CpuMask) -> sv_call::Result {
+        PREEMPT.scope(|| {
+            let mut route = self.route.lock();
+            let cpu = Manager::select_cpu(&affinity).ok_or(sv_call::EINVAL)?;
+            if !affinity[route.cpu] {
+                self.migrate(&mut route, cpu)?;
+            }
+            route.affinity = affinity;
+            Ok(())
+        })
+    }
+
+    /// Switch the interrupt into polled mode with `interval` and `budget`, or
+    /// renew them if it's already in polled mode.
+    ///
+    /// The interrupt is masked in polled mode, and the poller notifies its
+    /// waiters every `interval` instead, as if it fired. The driver renews the
+    /// budget whenever a poll finds some work, and the interrupt is switched
+    /// back once `budget` polls in a row find none.
+    pub fn start_polling(&self, interval: Duration, budget: u32) -> sv_call::Result {
+        if interval.is_zero() || budget == 0 {
+            return Err(sv_call::EINVAL);
+        }
+        let poller = POLLER.try_call_once(|| {
+            let cpu = unsafe { crate::cpu::id() };
+            kthread::spawn(format!("INTRPOLL{cpu}"), Priority::High, || loop {
+                let next = poll_all();
+                arm_poller(next);
+                kthread::park();
+            })
+        })?;
+
+        PREEMPT.scope(|| {
+            let mut poll = self.poll.lock();
+            if poll.is_none() {
+                Manager::mask(self.gsi, true)?;
+                self.polling.store(true, Release);
+                self.poll_entered.fetch_add(1, Relaxed);
+            }
+            let next = Instant::now() + interval;
+            *poll = Some(Poll {
+                interval,
+                left: budget,
+                next: poll.as_ref().map_or(next, |p| p.next.min(next)),
+            });
+            Ok::<_, sv_call::Error>(())
+        })?;
+        poller.unpark();
+        Ok(())
+    }
+
+    /// Switch the interrupt back from polled mode.
+    pub fn stop_polling(&self) -> sv_call::Result {
+        PREEMPT.scope(|| {
+            let mut poll = self.poll.lock();
+            if poll.take().is_some() {
+                self.polling.store(false, Release);
+                Manager::mask(self.gsi, false)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn poll_stat(&self) -> IntrPollStat {
+        IntrPollStat {
+            polling: self.polling.load(Acquire),
+            polls: self.polls.load(Relaxed),
+            entered: self.poll_entered.load(Relaxed),
+            exhausted: self.poll_exhausted.load(Relaxed),
+        }
+    }
+
+    /// Poll the interrupt if it's due at `now`, returning the time of its next
+    /// poll if it stays in polled mode.
+    fn try_poll(&self, now: Instant) -> Option<Instant> {
+        let (due, next) = PREEMPT.scope(|| {
+            let mut poll = self.poll.lock();
+            let Some(p) = poll.as_mut() else {
+                return (false, None);
+            };
+            if now < p.next {
+                return (false, Some(p.next));
+            }
+            p.left -= 1;
+            p.next = now + p.interval;
+            if p.left > 0 {
+                return (true, Some(p.next));
+            }
+            *poll = None;
+            self.polling.store(false, Release);
+            self.poll_exhausted.fetch_add(1, Relaxed);
+            if let Err(err) = Manager::mask(self.gsi, false) {
+                log::warn!("Failed to unmask GSI {}: {:?}", self.gsi, err);
+            }
+            (true, None)
+        });
+        if due {
+            self.polls.fetch_add(1, Relaxed);
+            // Picked up by the handler like a hard IRQ.
+            self.last_time.force_push(now);
+            self.notify_impl(0, SIG_GENERIC);
+        }
+        next
+    }
+}
+
+/// Poll the interrupts due, returning the time of the next poll if any.
+fn poll_all() -> Option<Instant> {
+    let now = Instant::now();
+    let intrs = PREEMPT.scope(|| INTERRUPTS.lock().clone());
+    { intrs.iter().filter_map(Weak::upgrade) }
+        .filter(|intr| intr.polling.load(Acquire))
+        .filter_map(|intr| intr.try_poll(now))
+        .min()
+}
+
+fn arm_poller(next: Option<Instant>) {
+    fn wake() {
+        if let Some(poller) = POLLER.get() {
+            poller.unpark();
+        }
+    }
+
+    let timer = next.and_then(|next| {
+        let timeout = next.saturating_duration_since(Instant::now());
+        Timer::activate(timeout, wake as fn()).ok()
+    });
+    if next.is_some() && timer.is_none() {
+        // Retry at once rather than leaving the interrupts masked.
+        log::warn!("Failed to arm the interrupt poller");
+        wake();
+    }
+    if let Some(old) = PREEMPT.scope(|| core::mem::replace(&mut *POLL_TIMER.lock(), timer)) {
+        old.cancel(false);
+    }
+}
+
+/// Redistribute the interrupts to the least loaded CPUs within their
+/// affinities, e.g. when some CPU comes online.
+pub fn balance() {
+    let intrs = PREEMPT.scope(|| {
+        let mut intrs = INTERRUPTS.lock();
+        intrs.retain(|intr| intr.strong_count() > 0);
+        intrs.clone()
+    });
+    for intr in intrs.iter().filter_map(Weak::upgrade) {
+        PREEMPT.scope(|| {
+            let mut route = intr.route.lock();
+            let Some(cpu) = Manager::select_cpu(&route.affinity) else {
+                return;
+            };
+            if Manager::load(cpu) + 1 < Manager::load(route.cpu) {
+                if let Err(err) = intr.migrate(&mut route, cpu) {
+                    log::warn!("Failed to move GSI {} to CPU #{}: {:?}", intr.gsi, cpu, err);
+                }
+            }
+        })
+    }
 }
 
 impl Drop for Interrupt {
     fn drop(&mut self) {
         self.cancel();
-        let _ = Manager::deregister(self.gsi, self.cpu);
+        let _ = Manager::deregister(self.gsi, self.route.get_mut().cpu);
     }
 }
 
 unsafe impl DefaultFeature for Interrupt {
     fn default_features() -> Feature {
-        Feature::SEND | Feature::WAIT
+        Feature::SEND | Feature::WAIT | Feature::WRITE
+    }
+}
+
+impl KernelObject for Interrupt {
+    fn event(this: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        Some(Arc::downgrade(this) as _)
     }
 }
 
 fn handler(arg: *mut u8) {
     let intr = unsafe { &*arg.cast::<Interrupt>() };
+    if let Some(count) = intr.counts.get(unsafe { crate::cpu::id() }) {
+        count.fetch_add(1, Relaxed);
+    }
     intr.notify(0, SIG_GENERIC);
 }
 
 mod syscall {
-    use alloc::sync::Arc;
+    use alloc::{sync::Arc, vec::Vec};
 
+    use bitvec::bitarr;
     use sv_call::{res::IntrConfig, *};
 
     use super::*;
     use crate::{
-        cpu::arch::apic::{Polarity, TriggerMode},
+        cpu::{
+            arch::apic::{Polarity, TriggerMode},
+            MAX_CPU,
+        },
         sched::SCHED,
-        syscall::{Out, UserPtr},
+        syscall::{In, Out, UserPtr},
     };
 
     #[syscall]
@@ -122,20 +450,21 @@ mod syscall {
             Polarity::Low
         };
 
-        let cpu = Manager::select_cpu();
+        let affinity = crate::cpu::all_mask();
+        let cpu = Manager::select_cpu(&affinity).ok_or(ENODEV)?;
 
         let intr = SCHED.with_current(|cur| {
             let handles = cur.space().handles();
             let res = handles.get::<Resource<u32>>(res)?;
-            Interrupt::new(&res, gsi, cpu, level_triggered)
+            let threaded = config.contains(IntrConfig::THREADED);
+            Interrupt::new(&res, gsi, cpu, affinity, level_triggered, threaded)
         })?;
 
         Manager::config(gsi, trig_mode, polarity)?;
-        Manager::register(gsi, cpu, (handler, (&*intr as *const Interrupt) as *mut u8))?;
+        Manager::register(gsi, cpu, intr.handler())?;
         Manager::mask(gsi, false)?;
 
-        let event = Arc::downgrade(&intr) as _;
-        SCHED.with_current(|cur| unsafe { cur.space().handles().insert_raw(intr, Some(event)) })
+        SCHED.with_current(|cur| cur.space().handles().insert_raw(intr))
     }
 
     #[syscall]
@@ -149,4 +478,88 @@ mod syscall {
             last_time.write(unsafe { data.raw() })
         })
     }
+
+    #[syscall]
+    fn intr_set_affinity(hdl: Handle, mask: UserPtr<In, u64>, len: usize) -> Result {
+        hdl.check_null()?;
+        mask.check_slice(len)?;
+
+        let mut words = [0; MAX_CPU / u64::BITS as usize];
+        let len = len.min(words.len());
+        unsafe { mask.read_slice(words.as_mut_ptr(), len) }?;
+
+        let mut affinity = bitarr![0; MAX_CPU];
+        for (index, word) in words.iter().enumerate() {
+            for bit in (0..u64::BITS).filter(|bit| word & (1 << bit) != 0) {
+                affinity.set(index * u64::BITS as usize + bit as usize, true);
+            }
+        }
+
+        let intr = SCHED.with_current(|cur| {
+            let intr = cur.space().handles().get::<Interrupt>(hdl)?;
+            if !intr.features().contains(Feature::WRITE) {
+                return Err(EPERM);
+            }
+            Ok(Arc::clone(&intr))
+        })?;
+        intr.set_affinity(affinity)
+    }
+
+    #[syscall]
+    fn intr_stat(hdl: Handle, counts: UserPtr<Out, u64>, len: usize) -> Result<usize> {
+        hdl.check_null()?;
+        counts.check_slice(len)?;
+
+        let intr = SCHED.with_current(|cur| {
+            let intr = cur.space().handles().get::<Interrupt>(hdl)?;
+            Ok(Arc::clone(&intr))
+        })?;
+        let data = intr.counts().take(len).collect::<Vec<_>>();
+        counts.write_slice(&data)?;
+        Ok(intr.counts.len())
+    }
+    #[syscall]
+    fn intr_latency(hdl: Handle, latency: UserPtr<Out, IntrLatency>) -> Result {
+        hdl.check_null()?;
+        latency.check()?;
+
+        let intr = SCHED.with_current(|cur| {
+            let intr = cur.space().handles().get::<Interrupt>(hdl)?;
+            Ok(Arc::clone(&intr))
+        })?;
+        let data = intr.latency().ok_or(ENOENT)?;
+        latency.write(data)
+    }
+
+    /// Switch the interrupt into polled mode, polling it every `interval_us`
+    /// until `budget` polls in a row pass without this being called again, or
+    /// switch it back at once if `interval_us` is 0.
+    #[syscall]
+    fn intr_poll(hdl: Handle, interval_us: u64, budget: u32) -> Result {
+        hdl.check_null()?;
+
+        let intr = SCHED.with_current(|cur| {
+            let intr = cur.space().handles().get::<Interrupt>(hdl)?;
+            if !intr.features().contains(Feature::WRITE) {
+                return Err(EPERM);
+            }
+            Ok(Arc::clone(&intr))
+        })?;
+        match interval_us {
+            0 => intr.stop_polling(),
+            us => intr.start_polling(crate::cpu::time::from_us(us), budget),
+        }
+    }
+
+    #[syscall]
+    fn intr_poll_stat(hdl: Handle, stat: UserPtr<Out, IntrPollStat>) -> Result {
+        hdl.check_null()?;
+        stat.check()?;
+
+        let intr = SCHED.with_current(|cur| {
+            let intr = cur.space().handles().get::<Interrupt>(hdl)?;
+            Ok(Arc::clone(&intr))
+        })?;
+        stat.write(intr.poll_stat())
+    }
 }
@@ -6,6 +6,8 @@ use core::{
     time::Duration,
 };
 
+#[cfg(bench)]
+pub use self::timer::bench as bench_timers;
 pub use self::timer::{tick as timer_tick, Timer};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -110,6 +112,27 @@ pub fn from_us(us: u64) -> Duration {
     }
 }
 
+/// The deadline `timeout` from now, or `None` if it never expires.
+#[inline]
+pub fn deadline_after(timeout: Duration) -> Option<Instant> {
+    (timeout != Duration::MAX).then(|| Instant::now() + timeout)
+}
+
+/// The deadline at `ns` nanoseconds of the clock read by `sv_time_get`, or
+/// `None` if it's `u64::MAX`.
+#[inline]
+pub fn deadline_from_ns(ns: u64) -> Option<Instant> {
+    (ns != u64::MAX).then(|| Instant(u128::from(ns)))
+}
+
+/// The time remaining until `deadline`, which is zero once it's expired.
+#[inline]
+pub fn remaining(deadline: Option<Instant>) -> Duration {
+    deadline.map_or(Duration::MAX, |deadline| {
+        deadline.saturating_duration_since(Instant::now())
+    })
+}
+
 impl core::fmt::Display for Instant {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let ns = unsafe { self.raw() };
@@ -119,7 +142,7 @@ impl core::fmt::Display for Instant {
 }
 
 mod syscall {
-    use sv_call::*;
+    use sv_call::{time::TimeInfo, *};
 
     use crate::syscall::{Out, UserPtr};
 
@@ -129,4 +152,10 @@ mod syscall {
         ptr.write(unsafe { super::Instant::now().raw() })?;
         Ok(())
     }
+
+    #[syscall]
+    pub(super) fn time_info(info: UserPtr<Out, TimeInfo>) -> Result {
+        info.write(super::chip::CLOCK.info())?;
+        Ok(())
+    }
 }
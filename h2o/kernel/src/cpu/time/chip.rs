@@ -1,21 +1,36 @@
-use core::sync::atomic::Ordering::Release;
+use core::{
+    sync::atomic::{AtomicU64, Ordering::*},
+    time::Duration,
+};
 
 use archop::Azy;
+use spin::Mutex;
+pub use sv_call::time::{ClockSource, TimeInfo};
 
 use super::Instant;
-use crate::{cpu::arch::tsc::TSC_CLOCK, dev::hpet::HPET_CLOCK};
+use crate::{
+    cpu::arch::tsc::TSC_CLOCK,
+    dev::{hpet::HPET_CLOCK, pmtmr::PM_TIMER_CLOCK},
+};
 
-pub static CLOCK: Azy<&'static dyn ClockChip> = Azy::new(|| {
-    let ret: &crate::cpu::arch::tsc::TscClock = &TSC_CLOCK;
+pub static CLOCK: Azy<Clock> = Azy::new(|| {
+    let ret = Clock::new();
     crate::logger::HAS_TIME.store(true, Release);
-    ret as _
+    ret
 });
 
-static CALIB_CLOCK: Azy<&'static dyn CalibrationClock> =
-    Azy::new(|| HPET_CLOCK.as_ref().expect("No available clock"));
+/// The interval between the checks of the TSC against the reference clock.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(500);
+/// The maximal drift in parts per million of the TSC before it's considered
+/// unstable.
+const WATCHDOG_MAX_PPM: u128 = 1000;
+
+static WATCHDOG: Mutex<Option<(Instant, Instant)>> = Mutex::new(None);
 
 pub trait ClockChip: Send + Sync {
     fn get(&self) -> Instant;
+
+    fn freq_khz(&self) -> u64;
 }
 
 pub trait CalibrationClock: ClockChip {
@@ -26,12 +41,182 @@ pub trait CalibrationClock: ClockChip {
     unsafe fn cleanup(&self);
 }
 
+pub fn chip(source: ClockSource) -> Option<&'static dyn ClockChip> {
+    match source {
+        ClockSource::Tsc => Some(&*TSC_CLOCK),
+        ClockSource::Hpet => HPET_CLOCK.as_ref().map(|clock| clock as _),
+        ClockSource::PmTimer => PM_TIMER_CLOCK.as_ref().map(|clock| clock as _),
+    }
+}
+
+/// The available calibration clocks, the most precise first.
+pub fn calibration_clocks() -> impl Iterator<Item = (ClockSource, &'static dyn CalibrationClock)> {
+    let hpet = HPET_CLOCK
+        .as_ref()
+        .map(|clock| (ClockSource::Hpet, clock as _));
+    let pm_timer = PM_TIMER_CLOCK
+        .as_ref()
+        .map(|clock| (ClockSource::PmTimer, clock as _));
+    hpet.into_iter().chain(pm_timer)
+}
+
+const SOURCE_SHIFT: u32 = 56;
+const OFFSET_MASK: u64 = (1 << SOURCE_SHIFT) - 1;
+
+/// The switchable clock source of the kernel.
+pub struct Clock {
+    /// The current source in the highest byte, and the signed offset in
+    /// nanoseconds added to its readings in the rest.
+    state: AtomicU64,
+    switches: AtomicU64,
+}
+
+impl Clock {
+    fn new() -> Self {
+        let source = if TSC_CLOCK.invariant {
+            ClockSource::Tsc
+        } else {
+            match calibration_clocks().next() {
+                Some((source, _)) => source,
+                None => {
+                    log::warn!("No stable clock available, falling back to the TSC");
+                    ClockSource::Tsc
+                }
+            }
+        };
+        log::info!("Clock source: {:?}", source);
+        Clock {
+            state: AtomicU64::new(Self::encode(source, 0)),
+            switches: AtomicU64::new(0),
+        }
+    }
+
+    fn encode(source: ClockSource, offset: i64) -> u64 {
+        ((source as u64) << SOURCE_SHIFT) | (offset as u64 & OFFSET_MASK)
+    }
+
+    fn decode(state: u64) -> (ClockSource, i64) {
+        let source = match state >> SOURCE_SHIFT {
+            0 => ClockSource::Tsc,
+            1 => ClockSource::Hpet,
+            _ => ClockSource::PmTimer,
+        };
+        // Sign-extend the offset.
+        let offset = ((state << (u64::BITS - SOURCE_SHIFT)) as i64) >> (u64::BITS - SOURCE_SHIFT);
+        (source, offset)
+    }
+
+    fn read(source: ClockSource, offset: i64) -> u128 {
+        let chip = chip(source).expect("Unavailable clock source");
+        let raw = unsafe { chip.get().raw() };
+        (raw as i128 + offset as i128) as u128
+    }
+
+    pub fn get(&self) -> Instant {
+        let (source, offset) = Self::decode(self.state.load(Acquire));
+        unsafe { Instant::from_raw(Self::read(source, offset)) }
+    }
+
+    #[inline]
+    pub fn source(&self) -> ClockSource {
+        Self::decode(self.state.load(Acquire)).0
+    }
+
+    /// Switch to another clock source, keeping the time monotonic.
+    ///
+    /// Returns `false` if the source is unavailable or already in use.
+    pub fn switch(&self, source: ClockSource) -> bool {
+        let new = match chip(source) {
+            Some(chip) => chip,
+            None => return false,
+        };
+        let res = self.state.fetch_update(AcqRel, Acquire, |state| {
+            let (old, offset) = Self::decode(state);
+            (old != source).then(|| {
+                let now = Self::read(old, offset) as i128;
+                let base = unsafe { new.get().raw() } as i128;
+                Self::encode(source, (now - base) as i64)
+            })
+        });
+        if res.is_err() {
+            return false;
+        }
+        self.switches.fetch_add(1, AcqRel);
+        crate::sched::task::set_vdso_clock(source);
+        true
+    }
+
+    pub fn info(&self) -> TimeInfo {
+        let source = self.source();
+        let freq_khz = chip(source).map_or(0, |chip| chip.freq_khz());
+        TimeInfo {
+            source,
+            invariant_tsc: TSC_CLOCK.invariant,
+            freq_khz,
+            resolution_ns: ((1_000_000 + freq_khz.saturating_sub(1)) / freq_khz.max(1)).max(1),
+            switches: self.switches.load(Acquire),
+        }
+    }
+}
+
+/// Check the TSC against the reference clock, and switch to the latter if the
+/// TSC drifts.
+///
+/// This function should be called periodically from one CPU.
+pub fn watchdog() {
+    if CLOCK.source() != ClockSource::Tsc {
+        return;
+    }
+    let (source, reference) = match calibration_clocks().next() {
+        Some(clock) => clock,
+        None => return,
+    };
+    let mut last = match WATCHDOG.try_lock() {
+        Some(last) => last,
+        None => return,
+    };
+
+    let tsc = TSC_CLOCK.get();
+    let (last_tsc, last_ref) = match *last {
+        Some(last) => last,
+        None => {
+            *last = Some((tsc, reference.get()));
+            return;
+        }
+    };
+    let tsc_delta = tsc.saturating_duration_since(last_tsc);
+    if tsc_delta < WATCHDOG_INTERVAL {
+        return;
+    }
+    let now = reference.get();
+    *last = Some((tsc, now));
+    // The reference counter may have wrapped around during a long interval.
+    if tsc_delta > WATCHDOG_INTERVAL * 4 {
+        return;
+    }
+
+    let ref_delta = now.saturating_duration_since(last_ref).as_nanos();
+    let drift = tsc_delta.as_nanos().abs_diff(ref_delta);
+    if drift * 1_000_000 > ref_delta * WATCHDOG_MAX_PPM {
+        *last = None;
+        drop(last);
+        log::warn!(
+            "The TSC drifted {}ns in {}ns against {:?}, switching the clock source",
+            drift,
+            ref_delta,
+            source
+        );
+        CLOCK.switch(source);
+    }
+}
+
 /// Calibrates a clock chip using a calibration clock.
 ///
 /// # Returns
 ///
 /// The target clock's frequency in kHz.
 pub fn calibrate(
+    clock: &dyn CalibrationClock,
     prepare: impl Fn(),
     get_start: impl Fn() -> u64,
     get_end: impl Fn() -> u64,
@@ -43,14 +228,14 @@ pub fn calibrate(
     for (best, &duration) in best.iter_mut().zip(iter_ms.iter()) {
         for _ in 0..tries {
             unsafe {
-                CALIB_CLOCK.prepare(duration);
+                clock.prepare(duration);
                 prepare();
 
                 let start = get_start();
-                CALIB_CLOCK.cycle(duration);
+                clock.cycle(duration);
                 *best = (*best).min(get_end() - start);
 
-                CALIB_CLOCK.cleanup();
+                clock.cleanup();
                 cleanup();
             }
         }
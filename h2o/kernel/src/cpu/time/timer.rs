@@ -1,82 +1,63 @@
-use alloc::{collections::BinaryHeap, sync::Weak};
+mod wheel;
+
+use alloc::{sync::Weak, vec::Vec};
 use core::{
-    cell::{LazyCell, RefCell},
-    cmp,
+    cell::UnsafeCell,
+    iter,
     sync::atomic::{AtomicBool, Ordering::*},
     time::Duration,
 };
 
-use spin::RwLock;
+use archop::Azy;
+use crossbeam_queue::SegQueue;
+use spin::{Mutex, RwLock};
 use sv_call::ipc::SIG_TIMER;
 
+use self::wheel::Wheel;
 use super::Instant;
-use crate::sched::{ipc::Arsc, task, Event, PREEMPT, SCHED};
-
-#[thread_local]
-static TIMER_QUEUE: LazyCell<TimerQueue> = LazyCell::new(TimerQueue::new);
+use crate::sched::{defer, ipc::Arsc, task, Event, PREEMPT, SCHED};
 
-#[derive(Debug, Clone)]
-struct TimerEntry(Arsc<Timer>);
-
-impl PartialEq for TimerEntry {
-    #[inline]
-    fn eq(&self, other: &Self) -> bool {
-        self.0.deadline == other.0.deadline
-    }
-}
-
-impl Eq for TimerEntry {}
-
-impl PartialOrd for TimerEntry {
-    #[inline]
-    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-        self.0
-            .deadline
-            .partial_cmp(&other.0.deadline)
-            .map(|c| c.reverse())
-    }
-}
-
-impl Ord for TimerEntry {
-    #[inline]
-    fn cmp(&self, other: &Self) -> cmp::Ordering {
-        self.0.deadline.cmp(&other.0.deadline).reverse()
-    }
-}
+static TIMER_QUEUES: Azy<Vec<TimerQueue>> = Azy::new(|| {
+    let now = wheel::granule(Instant::now());
+    iter::repeat_with(|| TimerQueue::new(now))
+        .take(crate::cpu::count())
+        .collect()
+});
 
 struct TimerQueue {
-    inner: RefCell<BinaryHeap<TimerEntry>>,
+    wheel: Mutex<Wheel>,
+    /// The timers expired and waiting to be fired by the deferred work.
+    expired: SegQueue<Arsc<Timer>>,
+    scheduled: AtomicBool,
 }
 
 impl TimerQueue {
-    fn new() -> Self {
+    fn new(now: u64) -> Self {
         TimerQueue {
-            inner: RefCell::new(BinaryHeap::new()),
+            wheel: Mutex::new(Wheel::new(now)),
+            expired: SegQueue::new(),
+            scheduled: AtomicBool::new(false),
         }
     }
 
     #[inline]
-    #[track_caller]
-    fn with_inner<F, R>(&self, func: F) -> R
-    where
-        F: FnOnce(&mut BinaryHeap<TimerEntry>) -> R,
-    {
-        PREEMPT.scope(|| func(&mut self.inner.borrow_mut()))
+    fn push(&self, timer: Arsc<Timer>) {
+        let at = wheel::deadline_granule(timer.deadline);
+        PREEMPT.scope(|| self.wheel.lock().insert(timer, at))
     }
 
     #[inline]
-    fn try_with_inner<F, R>(&self, func: F) -> Option<R>
-    where
-        F: FnOnce(&mut BinaryHeap<TimerEntry>) -> R,
-    {
-        PREEMPT.scope(|| self.inner.try_borrow_mut().ok().map(|mut r| func(&mut r)))
+    fn remove(&self, timer: &Timer) -> Option<Arsc<Timer>> {
+        PREEMPT.scope(|| self.wheel.lock().remove(timer))
     }
 
-    #[inline]
-    fn push(&self, timer: Arsc<Timer>) {
-        self.with_inner(|queue| {
-            queue.push(TimerEntry(timer));
-        })
+    fn fire_expired(&self) {
+        // Cleared before firing, so that the timers expired meanwhile get
+        // another batch.
+        self.scheduled.store(false, SeqCst);
+        while let Some(timer) = self.expired.pop() {
+            timer.fire();
+        }
     }
 }
 
@@ -84,6 +65,8 @@ impl TimerQueue {
 pub enum Callback {
     Task(task::Blocked),
     Event(Weak<dyn Event>),
+    /// A function called in the timer interrupt, which must not block.
+    Func(fn()),
 }
 
 impl From<task::Blocked> for Callback {
@@ -98,6 +81,12 @@ impl From<Weak<dyn Event>> for Callback {
     }
 }
 
+impl From<fn()> for Callback {
+    fn from(func: fn()) -> Self {
+        Self::Func(func)
+    }
+}
+
 impl Callback {
     fn call(self, timer: &Timer) {
         timer.fired.store(true, Release);
@@ -108,6 +97,7 @@ impl Callback {
                     event.notify(0, SIG_TIMER);
                 }
             }
+            Callback::Func(func) => func(),
         }
     }
 
@@ -119,6 +109,7 @@ impl Callback {
                     event.cancel()
                 }
             }
+            Callback::Func(_) => {}
         }
     }
 }
@@ -127,21 +118,36 @@ impl Callback {
 pub struct Timer {
     callback: RwLock<Option<Callback>>,
     deadline: Instant,
+    /// The CPU whose wheel the timer is linked in.
+    cpu: usize,
+    link: UnsafeCell<wheel::Link>,
     fired: AtomicBool,
+    interrupted: AtomicBool,
 }
 
+// SAFETY: The link is only accessed with the wheel of `cpu` locked.
+unsafe impl Send for Timer {}
+unsafe impl Sync for Timer {}
+
 impl Timer {
+    fn new<C: Into<Callback>>(deadline: Instant, callback: C) -> sv_call::Result<Arsc<Self>> {
+        Ok(Arsc::try_new(Timer {
+            callback: RwLock::new(Some(callback.into())),
+            deadline,
+            cpu: unsafe { crate::cpu::id() },
+            link: UnsafeCell::new(Default::default()),
+            fired: AtomicBool::new(false),
+            interrupted: AtomicBool::new(false),
+        })?)
+    }
+
     pub fn activate<C: Into<Callback>>(
         duration: Duration,
         callback: C,
     ) -> sv_call::Result<Arsc<Self>> {
-        let ret = Arsc::try_new(Timer {
-            callback: RwLock::new(Some(callback.into())),
-            deadline: Instant::now() + duration,
-            fired: AtomicBool::new(false),
-        })?;
+        let ret = Timer::new(Instant::now() + duration, callback)?;
         if duration < Duration::MAX {
-            TIMER_QUEUE.push(Arsc::clone(&ret));
+            TIMER_QUEUES[ret.cpu].push(Arsc::clone(&ret));
         }
         Ok(ret)
     }
@@ -149,6 +155,7 @@ impl Timer {
     pub fn cancel(self: &Arsc<Self>, preempt: bool) -> bool {
         match PREEMPT.scope(|| self.callback.write().take()) {
             Some(callback) => {
+                drop(TIMER_QUEUES[self.cpu].remove(self));
                 callback.cancel(preempt);
                 true
             }
@@ -156,6 +163,26 @@ impl Timer {
         }
     }
 
+    /// Cancel the timer on behalf of a signal, so that the blocked task sees
+    /// itself interrupted.
+    pub fn interrupt(self: &Arsc<Self>) -> bool {
+        match PREEMPT.scope(|| self.callback.write().take()) {
+            Some(callback) => {
+                drop(TIMER_QUEUES[self.cpu].remove(self));
+                self.interrupted.store(true, Release);
+                callback.cancel(false);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the callback must be called in the timer interrupt instead of
+    /// the deferred work.
+    fn fires_in_place(&self) -> bool {
+        matches!(*self.callback.read(), Some(Callback::Func(_)))
+    }
+
     fn fire(&self) {
         if let Some(callback) = PREEMPT.scope(|| self.callback.write().take()) {
             callback.call(self);
@@ -165,31 +192,107 @@ impl Timer {
     pub fn is_fired(&self) -> bool {
         self.fired.load(Acquire)
     }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Acquire)
+    }
 }
 
+/// Turn the wheel of the current CPU, firing the timers expired.
+///
+/// The functions are called in place, while the other timers are fired in
+/// batches by the deferred work, so that the interrupt doesn't do unbounded
+/// work in place.
 pub unsafe fn tick() {
-    loop {
-        let now = Instant::now();
-        let timer = TIMER_QUEUE.try_with_inner(|queue| loop {
-            match queue.peek() {
-                Some(TimerEntry(timer))
-                    if timer.callback.try_read().map_or(false, |r| r.is_none()) =>
-                {
-                    queue.pop();
+    let cpu = unsafe { crate::cpu::id() };
+    let queue = &TIMER_QUEUES[cpu];
+    let now = wheel::granule(Instant::now());
+
+    let mut in_place = Vec::new();
+    PREEMPT.scope(|| {
+        // Skipped if the interrupted context is modifying the wheel.
+        if let Some(mut wheel) = queue.wheel.try_lock() {
+            wheel.advance(now, |timer| {
+                if timer.fires_in_place() {
+                    in_place.push(timer)
+                } else {
+                    queue.expired.push(timer)
                 }
-                Some(TimerEntry(timer)) if timer.deadline <= now => {
-                    break queue.pop();
-                }
-                _ => break None,
-            }
-        });
-        match timer {
-            Some(Some(TimerEntry(timer))) => timer.fire(),
-            _ => break,
+            })
         }
+    });
+    in_place.into_iter().for_each(|timer| timer.fire());
+
+    if queue.expired.is_empty() || queue.scheduled.swap(true, SeqCst) {
+        return;
+    }
+    let ret = defer::queue(move || TIMER_QUEUES[cpu].fire_expired());
+    if ret.is_err() {
+        // Fire in place rather than losing the wake-ups.
+        queue.fire_expired();
     }
 }
 
+/// Compare the timing wheel with the binary heap it replaces, where most of
+/// the timers are canceled before expiry like the deadlines of RPCs.
+#[cfg(bench)]
+pub fn bench() {
+    use alloc::collections::BinaryHeap;
+    use core::cmp::Reverse;
+
+    const COUNT: usize = 10000;
+    const CANCELED: usize = COUNT * 9 / 10;
+
+    let start = Instant::now();
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    let timers = (0..COUNT)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let duration = Duration::from_micros(seed % 10_000_000);
+            Timer::new(start + duration, (|| {}) as fn()).unwrap()
+        })
+        .collect::<Vec<_>>();
+    let end = wheel::deadline_granule(start + Duration::from_secs(10));
+    let report = |name: &str, begin: Instant| {
+        let cost = begin.elapsed().as_nanos() / COUNT as u128;
+        log::info!("bench: timer_{name} = {cost} ns");
+    };
+
+    let mut heap = BinaryHeap::new();
+    let begin = Instant::now();
+    for (index, timer) in timers.iter().enumerate() {
+        heap.push(Reverse((timer.deadline, index)));
+    }
+    report("heap_insert", begin);
+    // Canceled lazily, and popped when they reach the top.
+    let mut canceled = alloc::vec![false; COUNT];
+    let begin = Instant::now();
+    canceled[..CANCELED].fill(true);
+    report("heap_cancel", begin);
+    let begin = Instant::now();
+    while let Some(Reverse((_, index))) = heap.pop() {
+        core::hint::black_box(canceled[index]);
+    }
+    report("heap_expire", begin);
+
+    let mut wheel = Wheel::new(wheel::granule(start));
+    let begin = Instant::now();
+    for timer in &timers {
+        wheel.insert(Arsc::clone(timer), wheel::deadline_granule(timer.deadline));
+    }
+    report("wheel_insert", begin);
+    let begin = Instant::now();
+    for timer in &timers[..CANCELED] {
+        drop(wheel.remove(timer));
+    }
+    report("wheel_cancel", begin);
+    let begin = Instant::now();
+    wheel.advance(end, |timer| drop(core::hint::black_box(timer)));
+    report("wheel_expire", begin);
+}
+
 mod syscall {
     use alloc::sync::{Arc, Weak};
 
@@ -199,7 +302,10 @@ mod syscall {
     use super::Timer;
     use crate::{
         cpu::time,
-        sched::{task::hdl::DefaultFeature, Arsc, Event, EventData, SCHED},
+        sched::{
+            task::hdl::{DefaultFeature, KernelObject},
+            Arsc, Event, EventData, SCHED,
+        },
     };
 
     #[derive(Debug, Default)]
@@ -234,11 +340,16 @@ mod syscall {
         }
     }
 
+    impl KernelObject for TimerEvent {
+        fn event(this: &Arc<Self>) -> Option<Weak<dyn Event>> {
+            Some(Arc::downgrade(this) as _)
+        }
+    }
+
     #[syscall]
     fn timer_new() -> Result<Handle> {
         let event = Arc::try_new(TimerEvent::default())?;
-        let e = Arc::downgrade(&event);
-        SCHED.with_current(|cur| cur.space().handles().insert_raw(event, Some(e)))
+        SCHED.with_current(|cur| cur.space().handles().insert_raw(event))
     }
 
     #[syscall]
@@ -264,3 +375,25 @@ mod syscall {
         })
     }
 }
+
+#[cfg(ktest)]
+mod ktests {
+    use alloc::sync::Arc;
+
+    use super::*;
+    use crate::{ktest::case, sched::BasicEvent};
+
+    case! {
+        fn timer_interrupt_cancels_once() {
+            let event = BasicEvent::new(0);
+            let timer = Timer::activate(Duration::MAX, Arc::downgrade(&event) as Weak<dyn Event>)
+                .unwrap();
+
+            assert!(timer.interrupt());
+            assert!(timer.is_interrupted());
+            assert!(!timer.is_fired());
+            assert!(!timer.interrupt());
+            assert!(!timer.cancel(false));
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! The hierarchical timing wheels of the timers.
+//!
+//! A wheel has [`LEVELS`] levels of [`SLOTS`] slots, where a slot of level `n`
+//! spans `SLOTS.pow(n)` granules of `1 << GRANULARITY_SHIFT` nanoseconds. A
+//! timer is linked into the slot of the lowest level its deadline falls
+//! within, and every time the lower level wraps around, the next slot of the
+//! upper level is cascaded down. Inserting and removing timers costs O(1)
+//! however many are pending, and turning the wheel by a granule costs O(1)
+//! plus the timers expired or cascaded.
+//!
+//! The timers are linked in place, so the wheel doesn't allocate.
+
+use core::ptr::NonNull;
+
+use super::Timer;
+use crate::{cpu::time::Instant, sched::Arsc};
+
+/// The granules are 2^20 ns, about a millisecond.
+pub const GRANULARITY_SHIFT: u32 = 20;
+const SLOT_SHIFT: u32 = 6;
+const SLOTS: usize = 1 << SLOT_SHIFT;
+const SLOT_MASK: u64 = SLOTS as u64 - 1;
+const LEVELS: usize = 4;
+/// The farthest granule ahead a timer is linked at. The farther ones are
+/// linked there and cascaded again until they come within the range.
+const MAX_DELTA: u64 = (1 << (SLOT_SHIFT * LEVELS as u32)) - 1;
+
+/// The granule of `instant`, rounded down.
+#[inline]
+pub fn granule(instant: Instant) -> u64 {
+    // SAFETY: The instants are in nanoseconds.
+    let nanos = unsafe { instant.raw() };
+    (nanos >> GRANULARITY_SHIFT).min(u64::MAX as u128) as u64
+}
+
+/// The granule of `deadline`, rounded up so that no timer fires early.
+#[inline]
+pub fn deadline_granule(deadline: Instant) -> u64 {
+    let nanos = unsafe { deadline.raw() };
+    let nanos = nanos.saturating_add((1 << GRANULARITY_SHIFT) - 1);
+    (nanos >> GRANULARITY_SHIFT).min(u64::MAX as u128) as u64
+}
+
+/// The links of a timer in the slot of a wheel, only accessed with the wheel
+/// locked.
+#[derive(Debug, Default)]
+pub struct Link {
+    prev: Option<NonNull<Timer>>,
+    next: Option<NonNull<Timer>>,
+    slot: Option<usize>,
+}
+
+pub struct Wheel {
+    slots: [Option<NonNull<Timer>>; LEVELS * SLOTS],
+    /// The next granule to expire.
+    now: u64,
+    len: usize,
+}
+
+// SAFETY: The timers linked are only accessed with the wheel locked.
+unsafe impl Send for Wheel {}
+
+impl Wheel {
+    pub const fn new(now: u64) -> Self {
+        Wheel {
+            slots: [None; LEVELS * SLOTS],
+            now,
+            len: 0,
+        }
+    }
+
+    /// Link `timer` into the slot of the granule `at`, or of the next granule
+    /// to expire if it's already due.
+    pub fn insert(&mut self, timer: Arsc<Timer>, at: u64) {
+        let delta = at.saturating_sub(self.now).min(MAX_DELTA);
+        let at = self.now + delta;
+        let level = (0..LEVELS)
+            .find(|&level| delta >> (SLOT_SHIFT * (level as u32 + 1)) == 0)
+            .unwrap_or(LEVELS - 1);
+        let index = (at >> (SLOT_SHIFT * level as u32)) & SLOT_MASK;
+        self.link(level * SLOTS + index as usize, timer)
+    }
+
+    /// Unlink `timer` if it's linked in the wheel.
+    pub fn remove(&mut self, timer: &Timer) -> Option<Arsc<Timer>> {
+        // SAFETY: The wheel is locked.
+        let slot = unsafe { (*timer.link.get()).slot };
+        slot.map(|_| unsafe { self.unlink(NonNull::from(timer)) })
+    }
+
+    /// Turn the wheel to the granule `to`, passing the timers expired meanwhile
+    /// to `expire`.
+    pub fn advance<F>(&mut self, to: u64, mut expire: F)
+    where
+        F: FnMut(Arsc<Timer>),
+    {
+        while self.now <= to {
+            if self.len == 0 {
+                // Nothing to cascade or expire on the way.
+                self.now = to + 1;
+                break;
+            }
+            let index = (self.now & SLOT_MASK) as usize;
+            if index == 0 {
+                self.cascade(1);
+            }
+            while let Some(head) = self.slots[index] {
+                expire(unsafe { self.unlink(head) });
+            }
+            self.now += 1;
+        }
+    }
+
+    /// Cascade the current slot of `level` down to the lower levels, and the
+    /// upper levels as well if it wraps around.
+    fn cascade(&mut self, level: usize) {
+        if level >= LEVELS {
+            return;
+        }
+        let index = (self.now >> (SLOT_SHIFT * level as u32)) & SLOT_MASK;
+        let slot = level * SLOTS + index as usize;
+        while let Some(head) = self.slots[slot] {
+            let timer = unsafe { self.unlink(head) };
+            let at = deadline_granule(timer.deadline);
+            self.insert(timer, at);
+        }
+        if index == 0 {
+            self.cascade(level + 1);
+        }
+    }
+
+    fn link(&mut self, slot: usize, timer: Arsc<Timer>) {
+        // The wheel owns a reference of every timer linked in it.
+        let ptr = unsafe { NonNull::new_unchecked(Arsc::into_raw(timer).cast_mut()) };
+        let head = self.slots[slot].replace(ptr);
+        // SAFETY: The wheel is locked.
+        unsafe {
+            if let Some(head) = head {
+                (*head.as_ref().link.get()).prev = Some(ptr);
+            }
+            *ptr.as_ref().link.get() = Link {
+                prev: None,
+                next: head,
+                slot: Some(slot),
+            };
+        }
+        self.len += 1;
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be linked in the wheel.
+    unsafe fn unlink(&mut self, ptr: NonNull<Timer>) -> Arsc<Timer> {
+        let link = core::mem::take(&mut *ptr.as_ref().link.get());
+        let slot = link.slot.expect("The timer is not linked");
+        match link.prev {
+            Some(prev) => (*prev.as_ref().link.get()).next = link.next,
+            None => self.slots[slot] = link.next,
+        }
+        if let Some(next) = link.next {
+            (*next.as_ref().link.get()).prev = link.prev;
+        }
+        self.len -= 1;
+        Arsc::from_raw(ptr.as_ptr())
+    }
+}
+
+#[cfg(ktest)]
+mod ktests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::ktest::case;
+
+    fn timer(at: u64) -> Arsc<Timer> {
+        let deadline = unsafe { Instant::from_raw((at as u128) << GRANULARITY_SHIFT) };
+        Timer::new(deadline, (|| {}) as fn()).unwrap()
+    }
+
+    case! {
+        fn timer_wheel_cascade() {
+            const START: u64 = 100;
+            let ats = [START, START + 63, START + 64, 5000, 300_000, START + MAX_DELTA + 10];
+            let timers = ats.iter().map(|&at| timer(at)).collect::<Vec<_>>();
+
+            let mut wheel = Wheel::new(START);
+            for (timer, &at) in timers.iter().zip(&ats) {
+                wheel.insert(Arsc::clone(timer), at);
+            }
+            let removed = wheel.remove(&timers[2]).unwrap();
+            assert!(Arsc::ptr_eq(&removed, &timers[2]));
+            assert!(wheel.remove(&timers[2]).is_none());
+
+            for (index, &at) in ats.iter().enumerate().filter(|&(index, _)| index != 2) {
+                let mut expired = Vec::new();
+                wheel.advance(at - 1, |timer| expired.push(timer));
+                assert!(expired.is_empty(), "timer {index} expired early");
+                wheel.advance(at, |timer| expired.push(timer));
+                assert_eq!(expired.len(), 1, "timer {index} not expired");
+                assert!(Arsc::ptr_eq(&expired[0], &timers[index]));
+            }
+            assert_eq!(wheel.len, 0);
+        }
+    }
+}
@@ -18,7 +18,7 @@ use crate::mem::space::{self, Flags, PhysTrait};
 
 pub static LAPIC_ID: RwLock<BTreeMap<usize, u32>> = RwLock::new(BTreeMap::new());
 static LAPIC_BASE: Azy<usize> = Azy::new(|| {
-    let phys = space::new_phys(PAddr::new(minfo::LAPIC_BASE), PAGE_SIZE)
+    let phys = space::new_mmio(PAddr::new(minfo::LAPIC_BASE), PAGE_SIZE)
         .expect("Failed to acquire LAPIC base");
     let layout = space::page_aligned(phys.len());
     space::KRL
@@ -35,6 +35,9 @@ static LAPIC_BASE: Azy<usize> = Azy::new(|| {
 #[thread_local]
 static mut LAPIC: Option<Lapic> = None;
 
+const APIC_BASE_X2: u64 = 1 << 10;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
 /// Get the per-CPU instance of Local APIC.
 pub unsafe fn lapic<F, R>(f: F) -> R
 where
@@ -218,22 +221,37 @@ impl Lapic {
         }
     }
 
+    /// Whether to operate in x2APIC mode.
+    ///
+    /// The mode can be disabled by the boot option `apic.x2apic=off`, unless
+    /// the firmware has already enabled it, since switching back requires
+    /// disabling the Local APIC as a whole.
+    fn use_x2apic() -> bool {
+        let enabled = unsafe { msr::read(msr::APIC_BASE) } & APIC_BASE_X2 != 0;
+        let has_x2apic = CpuId::new()
+            .get_feature_info()
+            .map_or(false, |f| f.has_x2apic());
+        enabled || (has_x2apic && crate::cmdline_option("apic.x2apic") != Some("off"))
+    }
+
     pub fn new() -> Self {
-        let mut ty = {
-            let has_x2apic = CpuId::new()
-                .get_feature_info()
-                .map_or(false, |f| f.has_x2apic());
-            if has_x2apic {
-                // SAFETY: Enabling Local X2 APIC if possible.
-                unsafe {
-                    let val = msr::read(msr::APIC_BASE);
-                    msr::write(msr::APIC_BASE, val | (1 << 10));
-                }
-                LapicType::X2
-            } else {
-                LapicType::X1(LAddr::from(*LAPIC_BASE))
+        let mut ty = if Self::use_x2apic() {
+            // SAFETY: Enabling Local X2 APIC if possible.
+            unsafe {
+                let val = msr::read(msr::APIC_BASE);
+                msr::write(msr::APIC_BASE, val | APIC_BASE_ENABLE | APIC_BASE_X2);
             }
+            LapicType::X2
+        } else {
+            LapicType::X1(LAddr::from(*LAPIC_BASE))
         };
+        if unsafe { crate::cpu::is_bsp() } {
+            let mode = match ty {
+                LapicType::X1(_) => "xAPIC",
+                LapicType::X2 => "x2APIC",
+            };
+            log::info!("Local APIC in {} mode", mode);
+        }
 
         // Get the LAPIC ID.
         let mut id = unsafe { Self::read_reg_32(&mut ty, msr::X2APICID) };
@@ -363,6 +381,14 @@ impl Lapic {
             msr::X2APIC_ICR,
             u32::from(icr_low) as u64 | ((icr_high as u64) << 32),
         );
+        // Only the MMIO interface needs to wait for the delivery, which x2APIC
+        // doesn't report.
+        if let LapicType::X1(_) = self.ty {
+            const PENDING: u32 = 1 << 12;
+            while Self::read_reg_32(&mut self.ty, msr::X2APIC_ICR) & PENDING != 0 {
+                core::hint::spin_loop();
+            }
+        }
     }
 }
 
@@ -386,7 +412,7 @@ pub unsafe fn error_handler() {
 pub unsafe fn init() {
     let mut lapic = Lapic::new();
     lapic.enable();
-    lapic.activate_timer(timer::TimerMode::Periodic, 7, 512);
+    timer::init(&mut lapic);
 
     LAPIC = Some(lapic);
 }
@@ -5,25 +5,25 @@ use core::{
     time::Duration,
 };
 
+use bitvec::bitarr;
 use modular_bitfield::prelude::*;
 use paging::PAddr;
 
-use super::{DelivMode, TriggerMode};
+use super::{DelivMode, TriggerMode, LAPIC_ID};
 use crate::{
     cpu::{
         arch::{
             apic::{ipi, lapic},
-            intr,
+            intr::def::ApicVec,
             seg::{alloc_pls, ndt::Segment},
         },
         time::{delay, Instant},
+        CpuMask, MAX_CPU,
     },
     mem::space::init_pgc,
     sched::PREEMPT,
 };
 
-// pub fn ipi_handler() {}
-
 #[derive(Debug, Clone, Copy, BitfieldSpecifier)]
 #[repr(u64)]
 pub enum Shorthand {
@@ -91,16 +91,18 @@ pub struct TramHeader {
 }
 
 impl TramHeader {
-    pub unsafe fn new() -> TramHeader {
+    pub unsafe fn new(kmain: *mut u8) -> TramHeader {
         use archop::{msr, reg};
 
         TramHeader {
             booted: AtomicBool::new(true),
             subheader: UnsafeCell::new(core::mem::zeroed()),
             pgc: init_pgc(),
-            kmain: crate::kmain_ap as *mut _,
+            kmain,
             init_efer: msr::read(msr::EFER),
-            init_cr4: reg::cr4::read(),
+            // CR4.PCIDE can't be set before CR3 is loaded with PCID 0, so
+            // it's enabled again afterwards.
+            init_cr4: reg::cr4::read() & !reg::cr4::PCIDE,
             init_cr0: reg::cr0::read(),
             gdt: {
                 use crate::cpu::arch::seg::attrs;
@@ -134,17 +136,23 @@ impl TramHeader {
 
         let pls = alloc_pls().map_or(null_mut(), |ptr| ptr.as_ptr()) as u64;
 
+        self.set_subheader(stack, pls);
+    }
+
+    /// Set the stack and the PLS of the next CPU started from the trampoline.
+    pub unsafe fn set_subheader(&self, stack: u64, pls: u64) {
         let ptr = self.subheader.get();
         ptr.write(TramSubheader { stack, pls });
     }
 }
 
+/// Copy the trampoline to [`minfo::TRAMPOLINE_RANGE`], whose started CPUs call
+/// `kmain`, and return its header and the start-up vector.
+///
 /// # Safety
 ///
-/// This function must be called after Local APIC initialization.
-pub unsafe fn start_cpus(aps: &[acpi::platform::Processor]) -> usize {
-    let _ = Instant::now();
-
+/// The caller must ensure that no CPU is running the trampoline.
+pub unsafe fn load_tram(kmain: *mut u8) -> (&'static TramHeader, u8) {
     static TRAM_DATA: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/tram"));
 
     let base_phys = PAddr::new(minfo::TRAMPOLINE_RANGE.start);
@@ -158,11 +166,38 @@ pub unsafe fn start_cpus(aps: &[acpi::platform::Processor]) -> usize {
         slice.copy_from_slice(TRAM_DATA);
     }
 
-    let header = {
-        let header = ptr.add(16).cast::<TramHeader>();
-        unsafe { header.write(TramHeader::new()) };
-        &*header
-    };
+    let header = ptr.add(16).cast::<TramHeader>();
+    unsafe { header.write(TramHeader::new(kmain)) };
+    (&*header, base_vec)
+}
+
+/// Start the CPU with the LAPIC ID `id` from the trampoline loaded with
+/// `header`, returning whether it's booted.
+///
+/// # Safety
+///
+/// The caller must ensure that the subheader is set up for the CPU.
+pub unsafe fn start_cpu(header: &TramHeader, base_vec: u8, id: u32) -> bool {
+    lapic(|lapic| {
+        lapic.send_ipi(0, DelivMode::Init, ipi::Shorthand::None, id);
+        delay(Duration::from_millis(50));
+
+        lapic.send_ipi(base_vec, DelivMode::StartUp, ipi::Shorthand::None, id);
+        if header.test_booted() {
+            return true;
+        }
+        lapic.send_ipi(base_vec, DelivMode::StartUp, ipi::Shorthand::None, id);
+        header.test_booted()
+    })
+}
+
+/// # Safety
+///
+/// This function must be called after Local APIC initialization.
+pub unsafe fn start_cpus(aps: &[acpi::platform::Processor]) -> usize {
+    let _ = Instant::now();
+
+    let (header, base_vec) = load_tram(crate::kmain_ap as *mut _);
 
     let mut cnt = aps.len();
 
@@ -173,24 +208,53 @@ pub unsafe fn start_cpus(aps: &[acpi::platform::Processor]) -> usize {
         delay(Duration::from_millis(5));
         header.allocate_subheader();
 
-        lapic(|lapic| {
-            lapic.send_ipi(0, DelivMode::Init, ipi::Shorthand::None, id);
-            delay(Duration::from_millis(50));
+        if !start_cpu(header, base_vec, id) {
+            log::warn!("CPU with LAPIC ID {} failed to boot", id);
+            cnt -= 1;
+        }
+    }
 
-            lapic.send_ipi(base_vec, DelivMode::StartUp, ipi::Shorthand::None, id);
+    cnt
+}
 
-            if !header.test_booted() {
-                lapic.send_ipi(base_vec, DelivMode::StartUp, ipi::Shorthand::None, id);
+/// The destination CPUs of an IPI.
+#[derive(Debug, Clone, Copy)]
+pub enum Dest<'a> {
+    Single(usize),
+    Mask(&'a CpuMask),
+    /// All the CPUs but the current one.
+    Others,
+}
 
-                if !header.test_booted() {
-                    log::warn!("CPU with LAPIC ID {} failed to boot", id);
-                    cnt -= 1;
-                }
-            }
-        });
+/// Send an IPI of `vec` to `dest`, skipping CPUs that are not online.
+///
+/// # Safety
+///
+/// The caller must ensure that the handler of `vec` is ready on the
+/// destination CPUs.
+pub unsafe fn send(dest: Dest, vec: ApicVec) {
+    let vec = vec as u8;
+    let send_one = |cpu: usize| match PREEMPT.scope(|| LAPIC_ID.read().get(&cpu).copied()) {
+        Some(id) => lapic(|lapic| lapic.send_ipi(vec, DelivMode::Fixed, Shorthand::None, id)),
+        None => log::warn!("CPU #{} not present", cpu),
+    };
+    match dest {
+        Dest::Single(cpu) => send_one(cpu),
+        Dest::Mask(mask) => mask.iter_ones().for_each(send_one),
+        Dest::Others => lapic(|lapic| lapic.send_ipi(vec, DelivMode::Fixed, Shorthand::Others, 0)),
     }
+}
 
-    cnt
+/// The online CPUs other than the current one.
+fn others() -> CpuMask {
+    let cur = unsafe { crate::cpu::id() };
+    let mut mask = bitarr![0; MAX_CPU];
+    PREEMPT.scope(|| {
+        { LAPIC_ID.read().keys() }
+            .filter(|&&cpu| cpu != cur)
+            .for_each(|&cpu| mask.set(cpu, true))
+    });
+    mask
 }
 
 /// # Safety
@@ -198,15 +262,93 @@ pub unsafe fn start_cpus(aps: &[acpi::platform::Processor]) -> usize {
 /// This function must be called only by the scheduler of the current CPU and
 /// the caller must ensure that `cpu` is valid.
 pub unsafe fn task_migrate(cpu: usize) {
-    match PREEMPT.scope(|| super::LAPIC_ID.read().get(&cpu).copied()) {
-        Some(id) => lapic(|lapic| {
-            lapic.send_ipi(
-                intr::def::ApicVec::IpiTaskMigrate as u8,
-                DelivMode::Fixed,
-                Shorthand::None,
-                id,
-            )
-        }),
-        None => log::warn!("CPU #{} not present", cpu),
-    };
+    send(Dest::Single(cpu), ApicVec::IpiTaskMigrate)
+}
+
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(10);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_FLUSH: AtomicBool = AtomicBool::new(false);
+/// The pending TLB flush requests of every CPU.
+static TLB_FLUSH: [AtomicBool; MAX_CPU] = [NO_FLUSH; MAX_CPU];
+
+fn flush_local() -> bool {
+    let cur = unsafe { crate::cpu::id() };
+    let pending = TLB_FLUSH[cur].swap(false, Ordering::Acquire);
+    if pending {
+        unsafe { crate::mem::space::flush_tlb() };
+    }
+    pending
+}
+
+/// Request the CPUs in `mask` to flush their TLBs through `dest`, and wait
+/// for their responses, returning `false` if some of them timed out.
+unsafe fn flush_tlb(dest: Dest, mask: &CpuMask) -> bool {
+    mask.iter_ones()
+        .for_each(|cpu| TLB_FLUSH[cpu].store(true, Ordering::Release));
+    send(dest, ApicVec::IpiTlbFlush);
+
+    let instant = Instant::now();
+    loop {
+        // Serve the requests from other CPUs that are waiting for us.
+        flush_local();
+        let pending = |cpu: usize| TLB_FLUSH[cpu].load(Ordering::Acquire);
+        if mask.iter_ones().all(|cpu| !pending(cpu)) {
+            break true;
+        }
+        if instant.elapsed() >= FLUSH_TIMEOUT {
+            break false;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Flush the TLBs of the other online CPUs after the page tables of the
+/// current CPU is modified.
+pub fn tlb_shootdown() {
+    // The paging routines only invalidate the entries of the current space,
+    // but with PCIDs those of the other spaces may be cached as well.
+    if archop::reg::has_pcid() {
+        PREEMPT.scope(|| unsafe { crate::mem::space::flush_tlb() });
+    }
+    let others = others();
+    if others.not_any() {
+        return;
+    }
+    if !unsafe { flush_tlb(Dest::Mask(&others), &others) } {
+        log::warn!("TLB shootdown timed out");
+    }
+}
+
+/// # Safety
+///
+/// The caller must ensure that this function is only called by the TLB flush
+/// IPI handler.
+pub unsafe fn tlb_flush_handler() {
+    lapic(|lapic| lapic.eoi());
+    flush_local();
+}
+
+/// Check that IPIs are delivered to all the online CPUs, in every way of
+/// sending.
+///
+/// It runs only once, on the CPU that comes online last.
+pub fn test_delivery() {
+    static TESTED: AtomicBool = AtomicBool::new(false);
+
+    if PREEMPT.scope(|| LAPIC_ID.read().len()) < crate::cpu::count()
+        || TESTED.swap(true, Ordering::SeqCst)
+    {
+        return;
+    }
+    let others = others();
+    for cpu in others.iter_ones() {
+        let mut mask = bitarr![0; MAX_CPU];
+        mask.set(cpu, true);
+        let ret = unsafe { flush_tlb(Dest::Single(cpu), &mask) };
+        assert!(ret, "IPI to CPU #{} not delivered", cpu);
+    }
+    assert!(unsafe { flush_tlb(Dest::Mask(&others), &others) });
+    assert!(unsafe { flush_tlb(Dest::Others, &others) });
+    log::trace!("IPIs delivered to {} other CPUs", others.count_ones());
 }
@@ -1,9 +1,13 @@
-use core::ops::Range;
+use core::{cell::RefCell, ops::Range};
 
+use archop::{msr, Azy};
 use modular_bitfield::prelude::*;
 
-use super::LocalEntry;
-use crate::cpu::time::Instant;
+use super::{Lapic, LocalEntry};
+use crate::cpu::time::{
+    chip::{calibrate, calibration_clocks},
+    Instant,
+};
 
 #[derive(Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[repr(u32)]
@@ -16,6 +20,31 @@ pub enum TimerMode {
 
 pub const DIV: Range<u8> = 0..8;
 
+/// The divider of the timer's input clock, as the power of 2.
+const TIMER_DIV: u8 = 4;
+
+/// The default frequency of the scheduler tick, overridden by the
+/// `sched.tick_hz` boot option.
+pub const DEFAULT_TICK_HZ: u64 = 1000;
+const TICK_HZ_RANGE: core::ops::RangeInclusive<u64> = 100..=10000;
+
+pub static TICK_HZ: Azy<u64> = Azy::new(|| {
+    let hz = match crate::cmdline_option("sched.tick_hz").map(str::parse::<u64>) {
+        Some(Ok(hz)) if TICK_HZ_RANGE.contains(&hz) => hz,
+        Some(_) => {
+            log::warn!(
+                "Invalid tick frequency, expected within {:?}, using {}",
+                TICK_HZ_RANGE,
+                DEFAULT_TICK_HZ
+            );
+            DEFAULT_TICK_HZ
+        }
+        None => DEFAULT_TICK_HZ,
+    };
+    log::info!("Scheduler tick frequency: {} Hz", hz);
+    hz
+});
+
 /// # Safety
 ///
 /// WARNING: This function modifies the architecture's basic registers. Be sure
@@ -23,7 +52,7 @@ pub const DIV: Range<u8> = 0..8;
 ///
 /// The caller must ensure that IDT is initialized before LAPIC Timer's
 /// activation and that `div` is within the range [`DIV`].
-pub unsafe fn activate(lapic: &mut super::Lapic, mode: TimerMode, div: u8, init_value: u32) {
+pub unsafe fn activate(lapic: &mut Lapic, mode: TimerMode, div: u8, init_value: u32) {
     /// # Safety
     ///
     /// The caller must ensure that `div` is within the range [`DIV`].
@@ -38,12 +67,9 @@ pub unsafe fn activate(lapic: &mut super::Lapic, mode: TimerMode, div: u8, init_
     let encdiv = unsafe { encode_div(div) };
     let timer_val = LocalEntry::new().with_timer_mode(mode).with_vec(vec);
 
-    // SAFETY: Those MSRs are per-cpu and only 1 timer object is available in the
-    // context.
+    // SAFETY: Those MSRs are per-cpu and only 1 timer object is available in
+    // the context.
     unsafe {
-        use archop::msr;
-
-        use super::Lapic;
         Lapic::write_reg_32(&mut lapic.ty, msr::X2APIC_DIV_CONF, encdiv.into());
         Lapic::write_reg_32(&mut lapic.ty, msr::X2APIC_LVT_TIMER, timer_val.into());
         if matches!(mode, TimerMode::TscDeadline) {
@@ -54,6 +80,61 @@ pub unsafe fn activate(lapic: &mut super::Lapic, mode: TimerMode, div: u8, init_
     }
 }
 
+/// Measure the frequency of the timer's divided clock in kHz.
+///
+/// The local APIC timers of all the CPUs share the same clock, so it's only
+/// measured once.
+fn timer_khz(lapic: &mut Lapic) -> u64 {
+    static KHZ: spin::Once<u64> = spin::Once::new();
+    *KHZ.call_once(|| {
+        let (_, clock) = calibration_clocks()
+            .next()
+            .expect("No available calibration clock");
+        let lapic = RefCell::new(lapic);
+        let elapsed = || {
+            let cur =
+                unsafe { Lapic::read_reg_32(&mut lapic.borrow_mut().ty, msr::X2APIC_CUR_COUNT) };
+            (u32::MAX - cur) as u64
+        };
+        let khz = calibrate(
+            clock,
+            || unsafe {
+                activate(
+                    &mut lapic.borrow_mut(),
+                    TimerMode::OneShot,
+                    TIMER_DIV,
+                    u32::MAX,
+                );
+                // Mask the interrupt during calibration.
+                let lvt = LocalEntry::new().with_mask(true);
+                Lapic::write_reg_32(
+                    &mut lapic.borrow_mut().ty,
+                    msr::X2APIC_LVT_TIMER,
+                    lvt.into(),
+                );
+            },
+            elapsed,
+            elapsed,
+            || unsafe {
+                Lapic::write_reg_32(&mut lapic.borrow_mut().ty, msr::X2APIC_INIT_COUNT, 0)
+            },
+        );
+        log::info!("Local APIC timer frequency: {} KHz", khz << TIMER_DIV);
+        khz
+    })
+}
+
+/// Start the periodic scheduler tick on the current CPU.
+///
+/// # Safety
+///
+/// The caller must ensure that IDT is initialized before the activation.
+pub unsafe fn init(lapic: &mut Lapic) {
+    let khz = timer_khz(lapic);
+    let init_value = (khz * 1000 / *TICK_HZ).clamp(1, u32::MAX as u64);
+    activate(lapic, TimerMode::Periodic, TIMER_DIV, init_value as u32);
+}
+
 /// # Safety
 ///
 /// The caller must ensure that this function is called only by interrupt
@@ -63,5 +144,8 @@ pub unsafe fn timer_handler() {
     super::lapic(|lapic| lapic.eoi());
 
     crate::cpu::time::timer_tick();
+    if crate::cpu::is_bsp() {
+        crate::cpu::time::chip::watchdog();
+    }
     crate::sched::SCHED.tick(Instant::now());
 }
@@ -18,6 +18,9 @@ data  equ   16
 
 start:
       cli
+      ; The firmware may jump here with any data segment when waking up.
+      xor   ax, ax
+      mov   ds, ax
 
 o32   lgdt  [.gdtr]
 
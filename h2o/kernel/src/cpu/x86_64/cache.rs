@@ -0,0 +1,76 @@
+//! The memory types of the physical memory, determined by the MTRRs set up by
+//! the firmware and the PAT set up here.
+
+use core::arch::asm;
+
+use archop::msr;
+
+const MTRRCAP_VCNT: u64 = 0xff;
+const MTRR_DEF_TYPE_E: u64 = 1 << 11;
+const MTRR_PHYSMASK_V: u64 = 1 << 11;
+const MTRR_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+const VARIABLE_MTRRS: [(msr::Msr, msr::Msr); 8] = [
+    (msr::MTRR_PHYSBASE0, msr::MTRR_PHYSMASK0),
+    (msr::MTRR_PHYSBASE1, msr::MTRR_PHYSMASK1),
+    (msr::MTRR_PHYSBASE2, msr::MTRR_PHYSMASK2),
+    (msr::MTRR_PHYSBASE3, msr::MTRR_PHYSMASK3),
+    (msr::MTRR_PHYSBASE4, msr::MTRR_PHYSMASK4),
+    (msr::MTRR_PHYSBASE5, msr::MTRR_PHYSMASK5),
+    (msr::MTRR_PHYSBASE6, msr::MTRR_PHYSMASK6),
+    (msr::MTRR_PHYSBASE7, msr::MTRR_PHYSMASK7),
+];
+
+fn type_name(ty: u64) -> &'static str {
+    match ty {
+        0 => "UC",
+        1 => "WC",
+        4 => "WT",
+        5 => "WP",
+        6 => "WB",
+        _ => "??",
+    }
+}
+
+/// Program the PAT of the current CPU with [`paging::PAT`].
+///
+/// # Safety
+///
+/// This function must be called during the initialization of every CPU,
+/// before any page is mapped write-combining.
+pub unsafe fn init_pat() {
+    // Write back the caches of the old memory types before switching.
+    asm!("wbinvd");
+    msr::write(msr::CR_PAT, paging::PAT);
+    crate::mem::space::flush_tlb();
+    asm!("wbinvd");
+}
+
+/// Log the variable MTRRs set up by the firmware.
+///
+/// # Safety
+///
+/// The caller must ensure that the CPU supports MTRRs.
+pub unsafe fn dump_mtrrs() {
+    let def_type = msr::read(msr::MTRR_DEF_TYPE);
+    if def_type & MTRR_DEF_TYPE_E == 0 {
+        log::debug!("MTRRs disabled");
+        return;
+    }
+    log::debug!("MTRR default type: {}", type_name(def_type & 0xff));
+
+    let count = (msr::read(msr::MTRRCAP) & MTRRCAP_VCNT) as usize;
+    for &(base, mask) in VARIABLE_MTRRS.iter().take(count) {
+        let (base, mask) = (msr::read(base), msr::read(mask));
+        if mask & MTRR_PHYSMASK_V == 0 {
+            continue;
+        }
+        let size = (!(mask & MTRR_ADDR_MASK) & MTRR_ADDR_MASK) + 0x1000;
+        log::debug!(
+            "MTRR {:#x}..{:#x}: {}",
+            base & MTRR_ADDR_MASK,
+            (base & MTRR_ADDR_MASK) + size,
+            type_name(base & 0xff)
+        );
+    }
+}
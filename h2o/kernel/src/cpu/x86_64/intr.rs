@@ -22,6 +22,8 @@ use crate::{
     dev::ioapic,
     mem::space::PageFaultErrCode,
     sched::{
+        account,
+        rcu::Rcu,
         task::{self, ctx::arch::Frame},
         PREEMPT, SCHED,
     },
@@ -33,9 +35,16 @@ static MANAGER: Azy<Vec<Manager>> = Azy::new(|| {
         .collect()
 });
 
+/// The handler of a vector, which is looked up without locks on every
+/// interrupt.
+struct Slot(IntrHandler, *mut u8);
+
+unsafe impl Send for Slot {}
+unsafe impl Sync for Slot {}
+
 pub struct Manager {
     map: Mutex<RangeMap<u8, ()>>,
-    slots: [Mutex<Option<(IntrHandler, *mut u8)>>; u8::MAX as usize + 1],
+    slots: [Rcu<Slot>; u8::MAX as usize + 1],
     count: AtomicUsize,
 }
 
@@ -46,20 +55,19 @@ impl Manager {
     pub fn new() -> Self {
         Manager {
             map: Mutex::new(RangeMap::new(ALLOC_VEC)),
-            slots: array![_ => Mutex::new(None); 256],
+            slots: array![_ => Rcu::empty(); 256],
             count: AtomicUsize::new(0),
         }
     }
 
     pub fn invoke(vec: u8) {
-        PREEMPT.scope(|| {
-            let manager = &MANAGER[unsafe { crate::cpu::id() }];
-            if let Some((handler, arg)) = *manager.slots[vec as usize].lock() {
-                handler(arg);
-            } else {
-                log::trace!("Unhandled interrupt #{:?}", vec);
-            }
-        })
+        let pree = PREEMPT.lock();
+        let manager = &MANAGER[unsafe { crate::cpu::id() }];
+        if let Some(&Slot(handler, arg)) = manager.slots[vec as usize].read(&pree) {
+            handler(arg);
+        } else {
+            log::trace!("Unhandled interrupt #{:?}", vec);
+        }
     }
 
     #[inline]
@@ -77,19 +85,24 @@ impl Manager {
         PREEMPT.scope(|| unsafe { ioapic::chip().lock().eoi(gsi) })
     }
 
-    pub fn select_cpu() -> usize {
+    /// Select the least loaded online CPU in `affinity`.
+    pub fn select_cpu(affinity: &crate::cpu::CpuMask) -> Option<usize> {
+        let online = LAPIC_ID.read();
         MANAGER
             .iter()
             .enumerate()
-            .fold((usize::MAX, usize::MAX), |(acc, iacc), (index, manager)| {
-                let value = manager.count.load(Ordering::Acquire);
-                if value < acc {
-                    (value, index)
-                } else {
-                    (acc, iacc)
-                }
-            })
-            .1
+            .filter(|&(index, _)| affinity[index])
+            .filter(|(index, _)| online.contains_key(index))
+            .min_by_key(|(_, manager)| manager.count.load(Ordering::Acquire))
+            .map(|(index, _)| index)
+    }
+
+    /// The number of vectors allocated on `cpu`.
+    #[inline]
+    pub fn load(cpu: usize) -> usize {
+        MANAGER
+            .get(cpu)
+            .map_or(usize::MAX, |manager| manager.count.load(Ordering::Acquire))
     }
 
     pub fn register(gsi: u32, cpu: usize, handler: (IntrHandler, *mut u8)) -> sv_call::Result {
@@ -113,7 +126,7 @@ impl Manager {
             sv_call::ENOMEM,
         )?;
 
-        *manager.slots[vec as usize].lock() = Some(handler);
+        manager.slots[vec as usize].replace(Some(Slot(handler.0, handler.1)));
         unsafe { ioapic.config_dest(gsi, vec, apic_id) }?;
 
         Ok(())
@@ -131,7 +144,7 @@ impl Manager {
         }
         let manager = MANAGER.get(cpu).ok_or(sv_call::ENODEV)?;
 
-        *manager.slots[vec as usize].lock() = None;
+        manager.slots[vec as usize].replace(None);
         unsafe { ioapic.deconfig(gsi) }?;
 
         {
@@ -143,6 +156,56 @@ impl Manager {
         Ok(())
     }
 
+    /// Move the handler of `gsi` from `from` to a new vector on `to`,
+    /// keeping its mask state.
+    pub fn migrate(
+        gsi: u32,
+        from: usize,
+        to: usize,
+        handler: (IntrHandler, *mut u8),
+    ) -> sv_call::Result {
+        if from == to {
+            return Ok(());
+        }
+        let _pree = PREEMPT.lock();
+        let mut ioapic = ioapic::chip().lock();
+        let entry = ioapic.get_entry(gsi)?;
+
+        let old_vec = entry.vec();
+        if !ALLOC_VEC.contains(&old_vec) {
+            return Err(sv_call::ENOENT);
+        }
+
+        let apic_id = *LAPIC_ID.read().get(&to).ok_or(sv_call::EINVAL)?;
+        let src = MANAGER.get(from).ok_or(sv_call::ENODEV)?;
+        let dst = MANAGER.get(to).ok_or(sv_call::ENODEV)?;
+
+        let vec = dst.map.lock().allocate_with(
+            1,
+            |_| {
+                dst.count.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+            sv_call::ENOMEM,
+        )?;
+        dst.slots[vec as usize].replace(Some(Slot(handler.0, handler.1)));
+        unsafe {
+            ioapic.config_dest(gsi, vec, apic_id)?;
+            ioapic.mask(gsi, entry.mask())?;
+        }
+
+        // A level-triggered interrupt in flight on the old vector is delivered
+        // again to the new one since it's not acknowledged.
+        src.slots[old_vec as usize].replace(None);
+        {
+            let mut lock = src.map.lock();
+            src.count.fetch_sub(1, Ordering::SeqCst);
+            lock.remove(old_vec);
+        }
+
+        Ok(())
+    }
+
     pub fn allocate_msi(num_vec: u8, cpu: usize) -> sv_call::Result<Msi> {
         const MAX_NUM_VEC: u8 = 32;
         let num_vec = num_vec
@@ -190,10 +253,12 @@ impl Default for Manager {
 /// This function must only be called from its assembly routine `rout_XX`.
 #[no_mangle]
 unsafe extern "C" fn common_interrupt(frame: *mut Frame) {
-    let vec = unsafe { &*frame }.errc_vec as u8;
-    Manager::invoke(vec);
-    super::apic::lapic(|lapic| lapic.eoi());
-    crate::sched::SCHED.tick(Instant::now());
+    let frame = unsafe { &*frame };
+    account::irq(frame.is_user(), || {
+        Manager::invoke(frame.errc_vec as u8);
+        super::apic::lapic(|lapic| lapic.eoi());
+        crate::sched::SCHED.tick(Instant::now());
+    })
 }
 
 /// Generic exception handler.
@@ -211,7 +276,11 @@ unsafe fn exception(frame_ptr: *mut Frame, vec: def::ExVec) {
                 #[cfg(debug_assertions)]
                 {
                     let _ = SCHED.with_current(|cur| {
-                        log::warn!("Unhandled exception from task {}.", cur.tid().raw());
+                        log::warn!(
+                            "Unhandled exception from task {} {:?}.",
+                            cur.tid().raw(),
+                            cur.tid().name()
+                        );
                         Ok(())
                     });
 
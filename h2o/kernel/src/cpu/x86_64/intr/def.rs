@@ -62,6 +62,8 @@ pub enum ApicVec {
     Timer = 0x20,
     Error = 0x21,
     IpiTaskMigrate = 0x22,
+    IpiTlbFlush = 0x23,
+    IpiSuspend = 0x24,
     Spurious = 0xFF,
 }
 
@@ -151,6 +153,8 @@ pub static IDT_INIT: &[IdtInit] = &[
     single_ent!(ApicVec::Timer, lapic_timer, 0, 0),
     single_ent!(ApicVec::Error, lapic_error, 0, 0),
     single_ent!(ApicVec::IpiTaskMigrate, lapic_ipi_task_migrate, 0, 0),
+    single_ent!(ApicVec::IpiTlbFlush, lapic_ipi_tlb_flush, 0, 0),
+    single_ent!(ApicVec::IpiSuspend, lapic_ipi_suspend, 0, 0),
     single_ent!(ApicVec::Spurious, lapic_spurious, 0, 0),
     // All other allocable interrupts
     Multiple(repeat::repeat! {"&[" for i in 0x40..0xFF {
@@ -182,20 +186,34 @@ hdl!(
 
 // Local APIC interrupts
 
-hdl!(lapic_timer, |_frame| {
-    crate::cpu::arch::apic::timer::timer_handler();
+/// Run the `handler` of a local APIC interrupt, charging its time to the
+/// hardware interrupts.
+unsafe fn apic(frame: *mut Frame, handler: unsafe fn()) {
+    crate::sched::account::irq((*frame).is_user(), || handler());
+}
+
+hdl!(lapic_timer, |frame| {
+    apic(frame, crate::cpu::arch::apic::timer::timer_handler);
+});
+
+hdl!(lapic_error, |frame| {
+    apic(frame, crate::cpu::arch::apic::error_handler);
+});
+
+hdl!(lapic_ipi_task_migrate, |frame| {
+    apic(frame, crate::sched::task_migrate_handler);
 });
 
-hdl!(lapic_error, |_frame| {
-    crate::cpu::arch::apic::error_handler();
+hdl!(lapic_ipi_tlb_flush, |frame| {
+    apic(frame, crate::cpu::arch::apic::ipi::tlb_flush_handler);
 });
 
-hdl!(lapic_ipi_task_migrate, |_frame| {
-    crate::sched::task_migrate_handler();
+hdl!(lapic_ipi_suspend, |frame| {
+    apic(frame, crate::cpu::arch::power::suspend_handler);
 });
 
-hdl!(lapic_spurious, |_frame| {
-    crate::cpu::arch::apic::spurious_handler();
+hdl!(lapic_spurious, |frame| {
+    apic(frame, crate::cpu::arch::apic::spurious_handler);
 });
 
 // All other allocable interrupts
@@ -1,5 +1,7 @@
 pub mod apic;
+mod cache;
 pub mod intr;
+pub mod power;
 pub mod seg;
 pub mod syscall;
 pub mod tsc;
@@ -69,6 +71,8 @@ pub struct KernelGs {
     syscall_user_stack: *mut u8,
     syscall_stack: LAddr,
     kernel_fs: LAddr,
+    /// Whether the entry stubs can use `{rd,wr}{fs,gs}base` instead of MSRs.
+    fsgsbase: u64,
 }
 
 #[thread_local]
@@ -77,6 +81,7 @@ pub static KERNEL_GS: Lazy<KernelGs> = Lazy::new(|| KernelGs {
     syscall_user_stack: null_mut(),
     syscall_stack: unsafe { syscall::init() }.expect("Memory allocation failed"),
     kernel_fs: LAddr::from(unsafe { archop::reg::read_fs() } as usize),
+    fsgsbase: archop::reg::has_fsgsbase() as u64,
 });
 
 impl KernelGs {
@@ -141,11 +146,18 @@ impl KernelGs {
 /// bootstrap CPU.
 pub unsafe fn init() {
     archop::fpu::init();
+    // The application CPUs inherit the control registers from here.
+    if archop::reg::has_smap() {
+        archop::reg::cr4::set(archop::reg::cr4::SMAP);
+    }
 
     seg::init();
+    cache::init_pat();
+    cache::dump_mtrrs();
 
     // SAFETY: During bootstrap initialization.
     unsafe { KERNEL_GS.load() };
+    unsafe { crate::mem::space::enable_pcid() };
 
     apic::init();
 
@@ -168,9 +180,11 @@ pub unsafe fn init() {
 /// each application CPU.
 pub unsafe fn init_ap() {
     seg::init_ap();
+    cache::init_pat();
 
     // SAFETY: During bootstrap initialization.
     unsafe { KERNEL_GS.load() };
+    unsafe { crate::mem::space::enable_pcid() };
 
     apic::init();
     intr::init();
@@ -0,0 +1,267 @@
+//! Saving and restoring the states of the CPUs across S3.
+//!
+//! Every CPU saves its context and parks itself in the handler of the suspend
+//! IPI. The bootstrap CPU then enters S3, and the firmware wakes it up through
+//! the AP trampoline, which calls [`resume_entry`]. After that, the parked CPUs
+//! are restarted with the same trampoline one by one, each returning from its
+//! handler as if nothing happened.
+//!
+//! The TSCs are written back to the values saved before parking, so they may
+//! drift apart from each other by the time spent in parking.
+
+use alloc::vec::Vec;
+use core::{
+    hint,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering::*},
+    time::Duration,
+};
+
+use archop::{msr, reg, Azy};
+use spin::Mutex;
+use sv_call::{Result, EBUSY, EIO};
+
+use super::{
+    apic::{self, ipi, LAPIC_ID},
+    cache,
+    intr::def::ApicVec,
+    seg, syscall, MAX_CPU,
+};
+use crate::{
+    cpu::time::Instant,
+    dev::{ioapic, sleep::SleepRegs},
+    sched::PREEMPT,
+};
+
+const PARK_TIMEOUT: Duration = Duration::from_secs(1);
+const RESUME_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy)]
+struct Context {
+    rsp: u64,
+    cr3: u64,
+    gs_base: u64,
+    kernel_gs_base: u64,
+    fs_base: u64,
+    tsc: u64,
+}
+
+impl Context {
+    const ZERO: Context = Context {
+        rsp: 0,
+        cr3: 0,
+        gs_base: 0,
+        kernel_gs_base: 0,
+        fs_base: 0,
+        tsc: 0,
+    };
+}
+
+/// The request of the current suspension.
+struct Request {
+    /// Whether the CPUs are still allowed to park, cleared when the bootstrap
+    /// CPU gives up waiting for them.
+    active: bool,
+    regs: Option<SleepRegs>,
+    ioapic: Vec<u64>,
+    result: Option<Result>,
+}
+
+static REQUEST: Mutex<Request> = Mutex::new(Request {
+    active: false,
+    regs: None,
+    ioapic: Vec::new(),
+    result: None,
+});
+
+static mut CONTEXTS: [Context; MAX_CPU] = [Context::ZERO; MAX_CPU];
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NOT_PARKED: AtomicBool = AtomicBool::new(false);
+static PARKED: [AtomicBool; MAX_CPU] = [NOT_PARKED; MAX_CPU];
+
+/// The CPU being restarted from the trampoline.
+static RESUMING: AtomicUsize = AtomicUsize::new(0);
+/// Set by the restarted CPU after it leaves the stack of the trampoline.
+static RESUMED: AtomicBool = AtomicBool::new(false);
+/// Set by the bootstrap CPU after all the CPUs are restarted.
+static DONE: AtomicBool = AtomicBool::new(false);
+
+/// The stack shared by the CPUs restarted from the trampoline.
+static RESUME_STACK: Azy<u64> = Azy::new(|| {
+    crate::mem::alloc_system_stack()
+        .expect("System memory allocation failed")
+        .as_ptr() as u64
+});
+
+extern "C" {
+    fn sleep_save(rsp: *mut u64, enter: unsafe extern "C" fn(usize) -> usize, arg: usize) -> usize;
+
+    fn sleep_resume(rsp: u64, done: *mut bool) -> !;
+}
+
+/// Put the system into S3, and return after it wakes up.
+///
+/// # Safety
+///
+/// The caller must ensure that the tasks and the devices that can't survive
+/// the loss of the hardware states are stopped.
+pub unsafe fn suspend(regs: SleepRegs) -> Result {
+    Azy::force(&RESUME_STACK);
+    PREEMPT.scope(|| {
+        {
+            let mut request = REQUEST.lock();
+            request.active = true;
+            request.regs = Some(regs);
+            request.result = None;
+        }
+        DONE.store(false, SeqCst);
+
+        ipi::send(ipi::Dest::Others, ApicVec::IpiSuspend);
+        save_and_park();
+
+        while !DONE.load(Acquire) {
+            hint::spin_loop();
+        }
+        REQUEST.lock().result.take().unwrap_or(Err(EIO))
+    })
+}
+
+/// # Safety
+///
+/// The caller must ensure that this function is only called by the suspend
+/// IPI handler.
+pub unsafe fn suspend_handler() {
+    apic::lapic(|lapic| lapic.eoi());
+    save_and_park();
+}
+
+/// Save the context of the current CPU, and park it or let the system sleep
+/// if it's the bootstrap CPU.
+unsafe fn save_and_park() {
+    let cpu = crate::cpu::id();
+    let ctx = &mut CONTEXTS[cpu];
+    ctx.cr3 = reg::cr3::read();
+    ctx.gs_base = msr::read(msr::GS_BASE);
+    ctx.kernel_gs_base = msr::read(msr::KERNEL_GS_BASE);
+    ctx.fs_base = msr::read(msr::FS_BASE);
+    ctx.tsc = msr::rdtsc();
+
+    let enter: unsafe extern "C" fn(usize) -> usize = if cpu == 0 { enter_sleep } else { park };
+    let ret = sleep_save(&mut ctx.rsp, enter, cpu);
+    if cpu == 0 {
+        wake(ret == 0);
+    }
+}
+
+unsafe extern "C" fn park(cpu: usize) -> usize {
+    {
+        let request = REQUEST.lock();
+        if !request.active {
+            return 1;
+        }
+        PARKED[cpu].store(true, SeqCst);
+    }
+    archop::halt_loop(Some(false))
+}
+
+unsafe extern "C" fn enter_sleep(_: usize) -> usize {
+    let others = crate::cpu::count() - 1;
+    let instant = Instant::now();
+    loop {
+        let mut request = REQUEST.lock();
+        if PARKED.iter().filter(|parked| parked.load(SeqCst)).count() >= others {
+            break;
+        }
+        if instant.elapsed() >= PARK_TIMEOUT {
+            log::warn!("power: some CPUs failed to park");
+            request.active = false;
+            request.result = Some(Err(EBUSY));
+            return 1;
+        }
+        drop(request);
+        hint::spin_loop();
+    }
+
+    // The lock must not be held in S3, or it will be held forever.
+    let regs = {
+        let mut request = REQUEST.lock();
+        request.ioapic = ioapic::chip().lock().save();
+        request.regs.take()
+    };
+    let regs = match regs {
+        Some(regs) => regs,
+        None => return 1,
+    };
+
+    let (header, _) = ipi::load_tram(resume_entry as *mut _);
+    header.set_subheader(*RESUME_STACK, CONTEXTS[0].fs_base);
+    RESUMING.store(0, SeqCst);
+
+    let ret = crate::dev::sleep::enter(&regs, minfo::TRAMPOLINE_RANGE.start as u32);
+    REQUEST.lock().result = Some(ret);
+    1
+}
+
+/// Restore the devices after the bootstrap CPU wakes up, and restart the
+/// parked CPUs.
+unsafe fn wake(resumed: bool) {
+    {
+        let mut request = REQUEST.lock();
+        request.active = false;
+        if resumed {
+            ioapic::chip().lock().restore(&request.ioapic);
+            request.result = Some(Ok(()));
+        }
+        request.ioapic = Vec::new();
+    }
+
+    let (header, base_vec) = ipi::load_tram(resume_entry as *mut _);
+    for cpu in 1..MAX_CPU {
+        if !PARKED[cpu].swap(false, SeqCst) {
+            continue;
+        }
+        let id = match LAPIC_ID.read().get(&cpu) {
+            Some(&id) => id,
+            None => continue,
+        };
+        header.set_subheader(*RESUME_STACK, CONTEXTS[cpu].fs_base);
+        RESUMING.store(cpu, SeqCst);
+        RESUMED.store(false, SeqCst);
+
+        if !ipi::start_cpu(header, base_vec, id) || !wait_resumed() {
+            log::error!("power: CPU #{} failed to resume", cpu);
+        }
+    }
+    DONE.store(true, Release);
+}
+
+fn wait_resumed() -> bool {
+    let instant = Instant::now();
+    while !RESUMED.load(Acquire) {
+        if instant.elapsed() >= RESUME_TIMEOUT {
+            return false;
+        }
+        hint::spin_loop();
+    }
+    true
+}
+
+/// The entry of the CPUs restarted from the trampoline, which already has the
+/// PLS loaded.
+unsafe extern "C" fn resume_entry() -> ! {
+    let cpu = RESUMING.load(SeqCst);
+    let ctx = &CONTEXTS[cpu];
+    msr::write(msr::TSC_AUX, cpu as u64);
+    reg::cr3::write(ctx.cr3);
+    crate::mem::space::enable_pcid();
+    msr::write(msr::GS_BASE, ctx.gs_base);
+    msr::write(msr::KERNEL_GS_BASE, ctx.kernel_gs_base);
+
+    seg::reload();
+    cache::init_pat();
+    syscall::reload();
+    msr::write(msr::TIME_STAMP_COUNTER, ctx.tsc);
+    apic::init();
+
+    sleep_resume(ctx.rsp, &RESUMED as *const AtomicBool as *mut bool)
+}
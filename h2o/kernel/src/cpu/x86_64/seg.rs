@@ -198,3 +198,10 @@ pub(super) unsafe fn init_ap() {
     ndt::init();
     idt::init();
 }
+
+/// Reload segmentation structures after the current CPU is reset.
+#[inline]
+pub(super) unsafe fn reload() {
+    ndt::reload();
+    idt::init();
+}
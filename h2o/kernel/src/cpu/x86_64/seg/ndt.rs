@@ -300,3 +300,18 @@ pub unsafe fn init() {
         load_tss(GDT_TR);
     }
 }
+
+/// Reload NDT after the current CPU is reset, such as waking up from the
+/// system sleep.
+///
+/// # Safety
+///
+/// See [`init`] for more details.
+pub unsafe fn reload() {
+    // `ltr` faults on the TSS descriptor marked busy by the last load.
+    const TSS_BUSY: u8 = 0x02;
+    let attr = (*GDT.export_fp().base).add(GDT_TR.into_val() as usize + 5);
+    attr.write_volatile(attr.read_volatile() & !TSS_BUSY);
+
+    unsafe { init() };
+}
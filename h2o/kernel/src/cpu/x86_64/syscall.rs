@@ -4,7 +4,10 @@ use archop::{msr, reg};
 use paging::LAddr;
 
 use super::seg::ndt::{INTR_CODE, USR_CODE_X86};
-use crate::sched::task::ctx::arch::Frame;
+use crate::sched::{
+    account::{self, Category},
+    task::ctx::arch::Frame,
+};
 
 extern "C" {
     fn rout_syscall();
@@ -18,24 +21,41 @@ pub unsafe fn init() -> sv_call::Result<LAddr> {
         .as_ptr()
         .sub(size_of::<usize>());
 
+    reload();
+
+    Ok(LAddr::new(stack))
+}
+
+/// Set up the MSRs of the `syscall` instruction, which are lost when the CPU
+/// is reset.
+///
+/// # Safety
+///
+/// This function modifies the architecture's basic registers.
+pub unsafe fn reload() {
     let star = (USR_CODE_X86.into_val() as u64) << 48 | (INTR_CODE.into_val() as u64) << 32;
     msr::write(msr::STAR, star);
     msr::write(msr::LSTAR, rout_syscall as usize as u64);
-    msr::write(msr::FMASK, reg::rflags::IF | reg::rflags::TF);
+    // Clear AC as well so that userspace can't turn off SMAP for the kernel.
+    msr::write(
+        msr::FMASK,
+        reg::rflags::IF | reg::rflags::TF | reg::rflags::AC,
+    );
 
     let efer = msr::read(msr::EFER);
     msr::write(msr::EFER, efer | 1);
-
-    Ok(LAddr::new(stack))
 }
 
 #[no_mangle]
 unsafe extern "C" fn hdl_syscall(frame: *const Frame) {
     let syscall = (*frame).syscall_args();
 
+    // Syscalls always come from the user mode.
+    let left = account::enter(Category::User, Category::Kernel);
     archop::resume_intr(None);
     let res = crate::syscall::handle(syscall);
     archop::pause_intr();
+    account::switch(left);
 
     let _ = crate::sched::SCHED.with_current(|cur| {
         cur.kstack_mut().task_frame_mut().set_syscall_retval(res);
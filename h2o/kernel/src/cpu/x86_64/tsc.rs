@@ -2,27 +2,53 @@ use archop::{msr::rdtsc, Azy};
 use raw_cpuid::CpuId;
 
 use crate::cpu::time::{
-    chip::{factor_from_freq, ClockChip},
+    chip::{calibrate, calibration_clocks, factor_from_freq, ClockChip},
     Instant,
 };
 
+/// The maximal difference in parts per million allowed between the
+/// calibrations against different clocks.
+const CALIB_MAX_PPM: u64 = 10_000;
+
 pub static TSC_CLOCK: Azy<TscClock> = Azy::new(|| {
-    if CpuId::new()
+    let invariant = CpuId::new()
         .get_advanced_power_mgmt_info()
-        .map_or(true, |info| !info.has_invariant_tsc())
-    {
-        log::warn!("The TSC is not invariant. Ticks will be unreliable.");
+        .map_or(false, |info| info.has_invariant_tsc());
+    if !invariant {
+        log::warn!("The TSC is not invariant and won't be the clock source.");
+    }
+
+    let mut results = calibration_clocks()
+        .map(|(source, clock)| (source, calibrate(clock, || {}, rdtsc, rdtsc, || {})));
+    let (source, khz) = results.next().expect("No available calibration clock");
+    for (other, other_khz) in results {
+        if khz.abs_diff(other_khz) * 1_000_000 > khz * CALIB_MAX_PPM {
+            log::warn!(
+                "TSC calibration mismatch: {} KHz against {:?}, {} KHz against {:?}",
+                khz,
+                source,
+                other_khz,
+                other
+            );
+        }
     }
 
-    let khz = crate::cpu::time::chip::calibrate(|| {}, rdtsc, rdtsc, || {});
     let initial = rdtsc();
     let (mul, sft) = factor_from_freq(khz);
     log::info!("CPU Timestamp frequency: {} KHz", khz);
-    TscClock { initial, mul, sft }
+    TscClock {
+        initial,
+        khz,
+        invariant,
+        mul,
+        sft,
+    }
 });
 
 pub struct TscClock {
     pub initial: u64,
+    pub khz: u64,
+    pub invariant: bool,
     pub mul: u128,
     pub sft: u128,
 }
@@ -33,4 +59,8 @@ impl ClockChip for TscClock {
         let ns = (val as u128 * self.mul) >> self.sft;
         unsafe { Instant::from_raw(ns) }
     }
+
+    fn freq_khz(&self) -> u64 {
+        self.khz
+    }
 }
@@ -1,5 +1,7 @@
 pub mod acpi;
+mod power;
 mod res;
+pub mod watchdog;
 
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "x86_64")] {
@@ -47,43 +49,33 @@ mod syscall {
 
     #[syscall]
     fn pio_acq(res: Handle, base: u16, size: u16) -> Result {
+        let end = base.checked_add(size).ok_or(ERANGE)?;
         SCHED.with_current(|cur| {
             let res = cur.space().handles().get::<Resource<u16>>(res)?;
             if !{ res.features() }.contains(Feature::READ | Feature::WRITE) {
                 return Err(EPERM);
             }
-            if res.magic_eq(pio_resource())
-                && res.range().start <= base
-                && base + size <= res.range().end
-            {
-                drop(res);
-                let io_bitmap = cur.io_bitmap_mut().get_or_insert_with(|| bitvec![1; 65536]);
-                io_bitmap[(base as usize)..(size as usize)].fill(false);
-                unsafe { KERNEL_GS.update_tss_io_bitmap(cur.io_bitmap_mut().as_deref()) };
-                Ok(())
-            } else {
-                Err(EPERM)
-            }
+            res.check(pio_resource(), base..end)?;
+            drop(res);
+            let io_bitmap = cur.io_bitmap_mut().get_or_insert_with(|| bitvec![1; 65536]);
+            io_bitmap[(base as usize)..(end as usize)].fill(false);
+            unsafe { KERNEL_GS.update_tss_io_bitmap(cur.io_bitmap_mut().as_deref()) };
+            Ok(())
         })
     }
 
     #[syscall]
     fn pio_rel(res: Handle, base: u16, size: u16) -> Result {
+        let end = base.checked_add(size).ok_or(ERANGE)?;
         SCHED.with_current(|cur| {
             let res = cur.space().handles().get::<Resource<u16>>(res)?;
-            if res.magic_eq(pio_resource())
-                && res.range().start <= base
-                && base + size <= res.range().end
-            {
-                drop(res);
-                if let Some(io_bitmap) = cur.io_bitmap_mut() {
-                    io_bitmap[(base as usize)..(size as usize)].fill(true);
-                }
-                unsafe { KERNEL_GS.update_tss_io_bitmap(cur.io_bitmap_mut().as_deref()) };
-                Ok(())
-            } else {
-                Err(EPERM)
+            res.check(pio_resource(), base..end)?;
+            drop(res);
+            if let Some(io_bitmap) = cur.io_bitmap_mut() {
+                io_bitmap[(base as usize)..(end as usize)].fill(true);
             }
+            unsafe { KERNEL_GS.update_tss_io_bitmap(cur.io_bitmap_mut().as_deref()) };
+            Ok(())
         })
     }
 }
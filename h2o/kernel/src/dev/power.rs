@@ -0,0 +1,56 @@
+//! The system power management.
+
+use core::time::Duration;
+
+use spin::Mutex;
+use sv_call::{Result, EBUSY};
+
+use crate::sched::task;
+
+const FREEZE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Put the system into S3 after freezing the user tasks, and return after it
+/// wakes up.
+///
+/// The suspension is aborted with `EBUSY` if the user tasks can't be frozen in
+/// time.
+///
+/// The devices should have been quiesced by their drivers in advance.
+pub fn suspend() -> Result {
+    static SUSPENDING: Mutex<()> = Mutex::new(());
+    let _guard = SUSPENDING.try_lock().ok_or(EBUSY)?;
+
+    let regs = super::sleep::regs()?;
+    let frozen = task::freeze(FREEZE_TIMEOUT).inspect_err(|err| {
+        log::warn!("Failed to freeze the user tasks, aborting the suspension: {err:?}")
+    })?;
+
+    log::info!("Entering S3");
+    // SAFETY: The user tasks are frozen and the devices are quiesced.
+    let ret = unsafe { crate::cpu::arch::power::suspend(regs) };
+    log::info!("Leaving S3: {:?}", ret);
+
+    drop(frozen);
+    ret
+}
+
+mod syscall {
+    use sv_call::*;
+
+    use crate::{
+        dev::{mem_resource, Resource},
+        sched::SCHED,
+    };
+
+    #[syscall]
+    fn system_ctl(res: Handle, op: u32) -> Result {
+        SCHED.with_current(|cur| {
+            let res = cur.space().handles().get::<Resource<usize>>(res)?;
+            res.check_root(mem_resource())
+        })?;
+        match op {
+            res::SYSTEM_CTL_SUSPEND => super::suspend(),
+            _ => Err(EINVAL),
+        }
+    }
+}
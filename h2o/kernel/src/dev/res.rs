@@ -1,3 +1,13 @@
+//! Hierarchical resource ranges.
+//!
+//! A resource grants access to a range of some kind of numbers: physical
+//! addresses, I/O ports or GSIs. Every kind has a root resource of the whole
+//! range, given to the first user task, and a random magic shared by all the
+//! resources derived from it. A resource can be split into disjoint narrower
+//! ones with `sv_res_alloc` and delegated to other tasks, which can only
+//! create the objects covered by their ranges. The operations beyond any
+//! specific range require the root resource itself.
+
 use alloc::sync::{Arc, Weak};
 use core::{any::Any, ops::Range};
 
@@ -5,7 +15,10 @@ use collection_ex::RangeMap;
 use spin::Mutex;
 use sv_call::Feature;
 
-use crate::sched::{task::hdl::DefaultFeature, PREEMPT};
+use crate::sched::{
+    task::hdl::{DefaultFeature, KernelObject},
+    Event, PREEMPT,
+};
 
 pub struct Resource<T: Ord + Copy> {
     magic: u64,
@@ -61,6 +74,23 @@ impl<T: Ord + Copy> Resource<T> {
     pub fn magic_eq(&self, other: &Self) -> bool {
         self.magic == other.magic
     }
+
+    /// Check if the resource is derived from `root` and covers `range`.
+    pub fn check(&self, root: &Self, range: Range<T>) -> sv_call::Result {
+        if range.start > range.end {
+            return Err(sv_call::EINVAL);
+        }
+        if self.magic_eq(root) && self.range.start <= range.start && range.end <= self.range.end {
+            Ok(())
+        } else {
+            Err(sv_call::EPERM)
+        }
+    }
+
+    /// Check if the resource covers the whole range of `root`.
+    pub fn check_root(&self, root: &Self) -> sv_call::Result {
+        self.check(root, root.range())
+    }
 }
 
 impl<T: Ord + Copy> Drop for Resource<T> {
@@ -77,36 +107,45 @@ unsafe impl<T: Ord + Copy + Send + Sync + Any> DefaultFeature for Resource<T> {
     }
 }
 
+impl<T: Ord + Copy + Send + Sync + Any> KernelObject for Resource<T> {
+    fn event(_: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        None
+    }
+}
+
 mod syscall {
-    use core::{any::Any, ops::Add};
+    use core::{any::Any, ops::Range};
 
     use sv_call::*;
 
     use crate::{dev::Resource, sched::SCHED};
 
-    fn res_alloc_typed<T: Ord + Copy + Send + Sync + Any + Add<Output = T>>(
+    fn res_alloc_typed<T: Ord + Copy + Send + Sync + Any>(
         hdl: Handle,
-        base: T,
-        size: T,
+        range: Range<T>,
     ) -> Result<Handle> {
         SCHED.with_current(|cur| {
             let res = cur.space().handles().get::<Resource<T>>(hdl)?;
             if !res.features().contains(Feature::SYNC) {
                 return Err(EPERM);
             }
-            let sub = res.allocate(base..(base + size)).ok_or(ENOMEM)?;
+            let sub = res.allocate(range).ok_or(ENOMEM)?;
             drop(res);
-            cur.space().handles().insert_raw(sub, None)
+            cur.space().handles().insert_raw(sub)
         })
     }
 
     #[syscall]
     fn res_alloc(hdl: Handle, ty: u32, base: usize, size: usize) -> Result<Handle> {
-        match ty {
-            res::RES_MEM => res_alloc_typed(hdl, base, size),
-            res::RES_PIO => res_alloc_typed(hdl, u16::try_from(base)?, u16::try_from(size)?),
-            res::RES_GSI => res_alloc_typed(hdl, u32::try_from(base)?, u32::try_from(size)?),
+        let end = base.checked_add(size).ok_or(ERANGE)?;
+        let ret = match ty {
+            res::RES_MEM => res_alloc_typed(hdl, base..end),
+            res::RES_PIO => res_alloc_typed(hdl, u16::try_from(base)?..u16::try_from(end)?),
+            res::RES_GSI => res_alloc_typed(hdl, u32::try_from(base)?..u32::try_from(end)?),
             _ => Err(ETYPE),
-        }
+        }?;
+        let args = [ty.into(), base as u64, size as u64];
+        crate::audit::record(audit::AUDIT_RES_GRANT, args);
+        Ok(ret)
     }
 }
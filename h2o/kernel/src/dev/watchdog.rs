@@ -0,0 +1,186 @@
+//! The software watchdog.
+//!
+//! A privileged service arms the watchdog with a timeout, and must pet it
+//! within every period of the timeout until it closes the handle. Otherwise,
+//! the scheduler diagnostics are dumped to the log when it expires, and the
+//! system is reset if requested.
+//!
+//! A hardware watchdog registered with [`register_backend`] is armed along
+//! with it at twice the timeout, which catches the hangs where the timer
+//! interrupts no longer fire.
+
+use alloc::sync::{Arc, Weak};
+use core::{ptr, time::Duration};
+
+use spin::{Mutex, Once};
+use sv_call::{Feature, KernelFeatures, Result, EBUSY};
+
+use crate::{
+    cpu::time::Timer,
+    sched::{
+        task::hdl::{DefaultFeature, KernelObject},
+        Arsc, Event, PREEMPT,
+    },
+};
+
+/// A hardware watchdog of the platform.
+pub trait Backend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Arm the watchdog with `timeout`, rounded to its granularity.
+    fn start(&self, timeout: Duration) -> Result;
+
+    fn pet(&self);
+
+    fn stop(&self);
+}
+
+static BACKEND: Once<&'static dyn Backend> = Once::new();
+static ARMED: Mutex<Option<Weak<Watchdog>>> = Mutex::new(None);
+
+/// Register the hardware watchdog of the platform, which fails if there's
+/// already one.
+pub fn register_backend(backend: &'static dyn Backend) -> Result {
+    let mut registered = false;
+    BACKEND.call_once(|| {
+        registered = true;
+        backend
+    });
+    if !registered {
+        return Err(EBUSY);
+    }
+    log::info!("Watchdog backend: {}", backend.name());
+    crate::sched::task::add_vdso_features(KernelFeatures::HW_WATCHDOG);
+    Ok(())
+}
+
+pub fn has_backend() -> bool {
+    BACKEND.get().is_some()
+}
+
+#[derive(Debug)]
+pub struct Watchdog {
+    timeout: Duration,
+    reset: bool,
+    timer: Mutex<Option<Arsc<Timer>>>,
+}
+
+unsafe impl Send for Watchdog {}
+unsafe impl Sync for Watchdog {}
+
+unsafe impl DefaultFeature for Watchdog {
+    fn default_features() -> Feature {
+        Feature::SEND | Feature::WRITE
+    }
+}
+
+impl KernelObject for Watchdog {
+    fn event(_: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        None
+    }
+}
+
+impl Watchdog {
+    /// Arm the watchdog, which fails if there's already one.
+    pub fn new(timeout: Duration, reset: bool) -> Result<Arc<Self>> {
+        let wdog = Arc::try_new(Watchdog {
+            timeout,
+            reset,
+            timer: Mutex::new(None),
+        })?;
+        PREEMPT.scope(|| {
+            let mut armed = ARMED.lock();
+            if matches!(&*armed, Some(armed) if armed.strong_count() > 0) {
+                return Err(EBUSY);
+            }
+            *armed = Some(Arc::downgrade(&wdog));
+            Ok(())
+        })?;
+
+        if let Some(backend) = BACKEND.get() {
+            backend.start(timeout * 2)?;
+        }
+        wdog.pet()?;
+        Ok(wdog)
+    }
+
+    /// Restart the countdown of the watchdog.
+    pub fn pet(&self) -> Result {
+        let timer = Timer::activate(self.timeout, expire as fn())?;
+        if let Some(old) = PREEMPT.scope(|| self.timer.lock().replace(timer)) {
+            old.cancel(false);
+        }
+        if let Some(backend) = BACKEND.get() {
+            backend.pet();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer.get_mut().take() {
+            timer.cancel(false);
+        }
+        let armed = PREEMPT.scope(|| {
+            let mut armed = ARMED.lock();
+            let is_self = matches!(&*armed, Some(armed) if ptr::eq(armed.as_ptr(), self));
+            if is_self {
+                *armed = None;
+            }
+            is_self
+        });
+        if let (true, Some(backend)) = (armed, BACKEND.get()) {
+            backend.stop();
+        }
+    }
+}
+
+fn expire() {
+    let wdog = PREEMPT.scope(|| ARMED.lock().as_ref().and_then(Weak::upgrade));
+    let Some(wdog) = wdog else { return };
+
+    log::error!("Watchdog expired: not petted in {:?}", wdog.timeout);
+    crate::sched::dump();
+    if wdog.reset {
+        log::error!("Resetting the system");
+        super::reset::reset();
+    }
+}
+
+mod syscall {
+    use sv_call::{res::WATCHDOG_RESET, *};
+
+    use super::Watchdog;
+    use crate::{
+        cpu::time,
+        dev::{mem_resource, Resource},
+        sched::SCHED,
+    };
+
+    #[syscall]
+    fn wdog_new(res: Handle, timeout_us: u64, options: u32) -> Result<Handle> {
+        if timeout_us == 0 || options & !WATCHDOG_RESET != 0 {
+            return Err(EINVAL);
+        }
+        SCHED.with_current(|cur| {
+            let res = cur.space().handles().get::<Resource<usize>>(res)?;
+            res.check_root(mem_resource())
+        })?;
+
+        let timeout = time::from_us(timeout_us);
+        let wdog = Watchdog::new(timeout, options & WATCHDOG_RESET != 0)?;
+        SCHED.with_current(|cur| cur.space().handles().insert_raw(wdog))
+    }
+
+    #[syscall]
+    fn wdog_pet(hdl: Handle) -> Result {
+        SCHED.with_current(|cur| {
+            let wdog = cur.space().handles().get::<Watchdog>(hdl)?;
+            if !wdog.features().contains(Feature::WRITE) {
+                return Err(EPERM);
+            }
+            wdog.pet()
+        })
+    }
+}
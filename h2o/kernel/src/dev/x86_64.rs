@@ -1,6 +1,9 @@
 pub mod hpet;
 pub mod ioapic;
 pub mod lpic;
+pub mod pmtmr;
+pub mod reset;
+pub mod sleep;
 
 /// Initialize interrupt chips.
 ///
@@ -60,7 +60,7 @@ unsafe impl Sync for Hpet {}
 
 impl Hpet {
     unsafe fn new(data: acpi::HpetInfo) -> Result<Self, &'static str> {
-        let phys = space::new_phys(PAddr::new(data.base_address), PAGE_SIZE)
+        let phys = space::new_mmio(PAddr::new(data.base_address), PAGE_SIZE)
             .map_err(|_| "Failed to acquire memory for HPET")?;
         let addr = space::KRL
             .map(
@@ -132,6 +132,7 @@ impl Hpet {
 pub struct HpetClock {
     canary: Canary<HpetClock>,
     hpet: Arsc<RwLock<Hpet>>,
+    khz: u64,
     mul: u128,
     sft: u128,
 }
@@ -142,6 +143,10 @@ impl ClockChip for HpetClock {
         let val = unsafe { (*self.hpet.as_mut_ptr()).counter() };
         unsafe { Instant::from_raw((val as u128 * self.mul) >> self.sft) }
     }
+
+    fn freq_khz(&self) -> u64 {
+        self.khz
+    }
 }
 
 impl CalibrationClock for HpetClock {
@@ -160,8 +165,7 @@ impl CalibrationClock for HpetClock {
     }
 
     unsafe fn cleanup(&self) {
-        let hpet = &mut *self.hpet.as_mut_ptr();
-        hpet.enable(false);
+        // Keep the counter running since it can also be the clock source.
         self.hpet.force_write_unlock();
     }
 }
@@ -186,6 +190,7 @@ impl HpetClock {
         Some(HpetClock {
             canary: Canary::new(),
             hpet,
+            khz,
             mul,
             sft,
         })
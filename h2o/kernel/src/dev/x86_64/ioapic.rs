@@ -173,7 +173,7 @@ impl Ioapic {
             address: paddr,
             global_system_interrupt_base: gsi_base,
         } = node;
-        let phys = space::new_phys(PAddr::new(*paddr as usize), PAGE_SIZE)
+        let phys = space::new_mmio(PAddr::new(*paddr as usize), PAGE_SIZE)
             .expect("Failed to acquire memory for I/O APIC");
         let addr = space::KRL
             .map(
@@ -403,4 +403,27 @@ impl Ioapics {
         }
         Ok(())
     }
+
+    /// Read all the redirection entries, which are lost in the system sleep.
+    pub fn save(&mut self) -> Vec<u64> {
+        let mut entries = Vec::new();
+        for (_, (_, chip)) in self.ioapic_data.iter_mut() {
+            entries.extend((0..chip.size() as u8).map(|pin| unsafe { chip.read_ioredtbl(pin) }));
+        }
+        entries
+    }
+
+    /// Write back the redirection entries read by [`Ioapics::save`].
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the entries are not modified after saved.
+    pub unsafe fn restore(&mut self, entries: &[u64]) {
+        let mut entries = entries.iter();
+        for (_, (_, chip)) in self.ioapic_data.iter_mut() {
+            for (pin, &entry) in (0..chip.size() as u8).zip(&mut entries) {
+                chip.write_ioredtbl(pin, entry);
+            }
+        }
+    }
 }
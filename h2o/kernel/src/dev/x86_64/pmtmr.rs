@@ -0,0 +1,104 @@
+use core::sync::atomic::{AtomicU64, Ordering::*};
+
+use acpi::platform::address::AddressSpace;
+use archop::{
+    io::{Io, Port},
+    Azy,
+};
+
+use crate::cpu::time::{
+    chip::{CalibrationClock, ClockChip},
+    Instant,
+};
+
+/// The fixed frequency of the ACPI PM timer.
+const PM_TIMER_FREQ: u64 = 3_579_545;
+
+pub static PM_TIMER_CLOCK: Azy<Option<PmTimerClock>> = Azy::new(PmTimerClock::new);
+
+/// The ACPI power management timer.
+///
+/// The hardware counter is only 24 or 32 bits wide, so it's extended to 64
+/// bits in software and must be read at least once per half of its period.
+pub struct PmTimerClock {
+    port: Port<u32>,
+    mask: u32,
+    extended: AtomicU64,
+    mul: u128,
+    sft: u128,
+}
+
+impl PmTimerClock {
+    fn new() -> Option<Self> {
+        let info = crate::dev::acpi::platform_info().pm_timer.as_ref()?;
+        if !matches!(info.base.address_space, AddressSpace::SystemIo) {
+            log::warn!("PM timer: only port I/O is supported");
+            return None;
+        }
+        let port = u16::try_from(info.base.address).ok()?;
+        let mask = if info.supports_32bit {
+            u32::MAX
+        } else {
+            (1 << 24) - 1
+        };
+
+        let sft = 32;
+        let mul = ((1_000_000_000 << sft) + (PM_TIMER_FREQ as u128 >> 1)) / PM_TIMER_FREQ as u128;
+        log::info!("PM timer frequency: {} KHz", PM_TIMER_FREQ / 1000);
+
+        // SAFETY: The port is reported by the firmware.
+        let port = unsafe { Port::<u32>::new(port) };
+        let initial = unsafe { port.read() } & mask;
+        Some(PmTimerClock {
+            port,
+            mask,
+            extended: AtomicU64::new(initial.into()),
+            mul,
+            sft,
+        })
+    }
+
+    fn counter(&self) -> u64 {
+        let raw = unsafe { self.port.read() } & self.mask;
+        let mut last = self.extended.load(Acquire);
+        loop {
+            let delta = raw.wrapping_sub(last as u32) & self.mask;
+            // Another CPU has already read a newer value.
+            if delta > self.mask >> 1 {
+                break last;
+            }
+            let new = last + delta as u64;
+            match self
+                .extended
+                .compare_exchange_weak(last, new, AcqRel, Acquire)
+            {
+                Ok(_) => break new,
+                Err(value) => last = value,
+            }
+        }
+    }
+}
+
+impl ClockChip for PmTimerClock {
+    fn get(&self) -> Instant {
+        let val = self.counter();
+        unsafe { Instant::from_raw((val as u128 * self.mul) >> self.sft) }
+    }
+
+    fn freq_khz(&self) -> u64 {
+        PM_TIMER_FREQ / 1000
+    }
+}
+
+impl CalibrationClock for PmTimerClock {
+    unsafe fn prepare(&self, _: u64) {}
+
+    unsafe fn cycle(&self, ms: u64) {
+        let ticks = ms * PM_TIMER_FREQ / 1000;
+
+        let start = self.counter();
+        while self.counter() - start < ticks {}
+    }
+
+    unsafe fn cleanup(&self) {}
+}
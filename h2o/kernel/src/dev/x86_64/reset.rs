@@ -0,0 +1,43 @@
+//! Resetting the system.
+//!
+//! The ACPI reset register is written first if the firmware provides one in
+//! the I/O space, then the reset control register of the chipset, and at last
+//! the keyboard controller.
+
+use acpi::{fadt::Fadt, platform::address::AddressSpace, sdt::Signature};
+use archop::io::{Io, Port};
+
+use crate::dev::acpi::tables;
+
+const RESET_CONTROL: u16 = 0xcf9;
+/// `SYS_RST | RST_CPU`, a full reset.
+const RESET_CONTROL_FULL: u8 = 0x06;
+const KBD_COMMAND: u16 = 0x64;
+const KBD_PULSE_RESET: u8 = 0xfe;
+
+/// Find the ACPI reset register and its value.
+fn acpi_reset() -> Option<(u16, u8)> {
+    let fadt = unsafe { tables().get_sdt::<Fadt>(Signature::FADT) }.ok()??;
+    if !{ fadt.flags }.supports_system_reset_via_fadt() {
+        return None;
+    }
+    let reg = fadt.reset_register().ok()?;
+    if !matches!(reg.address_space, AddressSpace::SystemIo) {
+        return None;
+    }
+    Some((u16::try_from(reg.address).ok()?, fadt.reset_value))
+}
+
+/// Reset the system, which never returns.
+pub fn reset() -> ! {
+    let acpi = acpi_reset();
+    // SAFETY: The system is being reset.
+    unsafe {
+        if let Some((port, value)) = acpi {
+            Port::<u8>::new(port).write(value);
+        }
+        Port::<u8>::new(RESET_CONTROL).write(RESET_CONTROL_FULL);
+        Port::<u8>::new(KBD_COMMAND).write(KBD_PULSE_RESET);
+        archop::halt_loop(Some(false))
+    }
+}
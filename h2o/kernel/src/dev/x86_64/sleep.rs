@@ -0,0 +1,204 @@
+//! Entering the ACPI sleep state S3 (a.k.a. suspend-to-RAM).
+//!
+//! There's no AML interpreter in the kernel, so the `SLP_TYPx` values are
+//! found by scanning the DSDT for the `\_S3_` package, and the `_PTS` and
+//! `_WAK` methods are not evaluated, which most firmwares tolerate.
+
+use acpi::{fadt::Fadt, platform::address::AddressSpace, sdt::Signature};
+use archop::io::{Io, Port};
+use paging::PAddr;
+use sv_call::{Result, EINVAL, EIO, ENOSYS};
+
+use crate::dev::acpi::tables;
+
+const SLP_TYP_SHIFT: u16 = 10;
+const SLP_TYP_MASK: u16 = 0b111 << SLP_TYP_SHIFT;
+const SLP_EN: u16 = 1 << 13;
+
+const AML_ZERO_OP: u8 = 0x00;
+const AML_ONE_OP: u8 = 0x01;
+const AML_NAME_OP: u8 = 0x08;
+const AML_BYTE_PREFIX: u8 = 0x0a;
+const AML_PACKAGE_OP: u8 = 0x12;
+
+/// The offset of `Firmware_Waking_Vector` in the FACS.
+const FACS_WAKING_VECTOR: usize = 12;
+/// The offset of `X_Firmware_Waking_Vector` in the FACS.
+const FACS_X_WAKING_VECTOR: usize = 24;
+
+/// The registers and the values to enter S3.
+pub struct SleepRegs {
+    pm1a_cnt: u16,
+    pm1b_cnt: Option<u16>,
+    slp_typ: (u16, u16),
+    facs: PAddr,
+}
+
+fn io_port(addr: acpi::platform::address::GenericAddress) -> Result<u16> {
+    if !matches!(addr.address_space, AddressSpace::SystemIo) {
+        log::warn!("sleep: only port I/O PM1 control blocks are supported");
+        return Err(ENOSYS);
+    }
+    u16::try_from(addr.address).map_err(|_| EINVAL)
+}
+
+/// Read an integer constant of at most one byte in an AML stream.
+fn read_byte_const(aml: &mut &[u8]) -> Option<u8> {
+    let (value, len) = match *aml.first()? {
+        AML_ZERO_OP => (0, 1),
+        AML_ONE_OP => (1, 1),
+        AML_BYTE_PREFIX => (*aml.get(1)?, 2),
+        _ => return None,
+    };
+    *aml = &aml[len..];
+    Some(value)
+}
+
+/// Get the values of `SLP_TYPa` and `SLP_TYPb` of the sleep state `Sx`.
+fn sleep_type(aml: &[u8], state: u8) -> Option<(u8, u8)> {
+    let name = [b'_', b'S', b'0' + state, b'_'];
+    let start = (1..aml.len().saturating_sub(3)).find(|&i| {
+        aml[i..i + 4] == name && matches!(aml[..i], [.., AML_NAME_OP] | [.., AML_NAME_OP, b'\\'])
+    })?;
+
+    let mut rest = aml.get(start + 4..)?;
+    if *rest.first()? != AML_PACKAGE_OP {
+        return None;
+    }
+    // The number of the following bytes of `PkgLength` is in its top 2 bits.
+    let pkg_len = (*rest.get(1)? >> 6) as usize + 1;
+    // Skip `PackageOp`, `PkgLength` and `NumElements`.
+    rest = rest.get(1 + pkg_len + 1..)?;
+
+    let slp_typa = read_byte_const(&mut rest)?;
+    let slp_typb = read_byte_const(&mut rest)?;
+    Some((slp_typa, slp_typb))
+}
+
+/// Find the registers and the values to enter S3, which fails if the
+/// platform doesn't support it.
+pub fn regs() -> Result<SleepRegs> {
+    let tables = tables();
+    let fadt = unsafe { tables.get_sdt::<Fadt>(Signature::FADT) }
+        .ok()
+        .flatten()
+        .ok_or(ENOSYS)?;
+    if { fadt.flags }.system_is_hw_reduced_acpi() {
+        log::warn!("sleep: hardware-reduced ACPI is not supported");
+        return Err(ENOSYS);
+    }
+
+    let pm1a_cnt = io_port(fadt.pm1a_control_block().map_err(|_| EIO)?)?;
+    let pm1b_cnt = match fadt.pm1b_control_block().map_err(|_| EIO)? {
+        Some(addr) => Some(io_port(addr)?),
+        None => None,
+    };
+    let facs = PAddr::new(fadt.facs_address().map_err(|_| ENOSYS)?);
+
+    let dsdt = tables.dsdt.as_ref().ok_or(ENOSYS)?;
+    let aml = unsafe {
+        let ptr = PAddr::new(dsdt.address).to_laddr(minfo::ID_OFFSET);
+        core::slice::from_raw_parts(*ptr, dsdt.length as usize)
+    };
+    let (slp_typa, slp_typb) = sleep_type(aml, 3).ok_or_else(|| {
+        log::warn!("sleep: S3 is not supported by the firmware");
+        ENOSYS
+    })?;
+
+    Ok(SleepRegs {
+        pm1a_cnt,
+        pm1b_cnt,
+        slp_typ: (slp_typa.into(), slp_typb.into()),
+        facs,
+    })
+}
+
+/// Enter S3, where the firmware jumps to the real-mode code at
+/// `waking_vector` when the system wakes up.
+///
+/// Returns an error if the system fails to sleep.
+///
+/// # Safety
+///
+/// The caller must ensure that the states of all the CPUs are saved, and all
+/// the other CPUs are halted.
+pub unsafe fn enter(regs: &SleepRegs, waking_vector: u32) -> Result {
+    let facs = *regs.facs.to_laddr(minfo::ID_OFFSET);
+    facs.add(FACS_WAKING_VECTOR)
+        .cast::<u32>()
+        .write_volatile(waking_vector);
+    facs.add(FACS_X_WAKING_VECTOR)
+        .cast::<u64>()
+        .write_volatile(0);
+
+    let write = |port: u16, slp_typ: u16| {
+        let mut port = Port::<u16>::new(port);
+        let value = port.read() & !SLP_TYP_MASK;
+        port.write(value | (slp_typ << SLP_TYP_SHIFT) | SLP_EN);
+    };
+
+    core::arch::asm!("wbinvd");
+    write(regs.pm1a_cnt, regs.slp_typ.0);
+    if let Some(pm1b_cnt) = regs.pm1b_cnt {
+        write(pm1b_cnt, regs.slp_typ.1);
+    }
+
+    // The system should have been asleep by now.
+    crate::cpu::time::delay(core::time::Duration::from_millis(100));
+    log::warn!("sleep: failed to enter S3");
+    Err(EIO)
+}
+
+#[cfg(ktest)]
+mod ktests {
+    use super::*;
+    use crate::ktest::case;
+
+    case! {
+        fn sleep_type_reads_package() {
+            // Name (_S3_, Package () { 0x05, 0x05, Zero, Zero })
+            let aml = [
+                AML_NAME_OP, b'_', b'S', b'3', b'_', AML_PACKAGE_OP, 0x08, 0x04,
+                AML_BYTE_PREFIX, 0x05, AML_BYTE_PREFIX, 0x05, AML_ZERO_OP, AML_ZERO_OP,
+            ];
+            assert_eq!(sleep_type(&aml, 3), Some((5, 5)));
+            assert_eq!(sleep_type(&aml, 4), None);
+        }
+
+        fn sleep_type_reads_rooted_name() {
+            // Name (\_S3_, Package () { One, Zero, Zero, Zero })
+            let aml = [
+                AML_NAME_OP, b'\\', b'_', b'S', b'3', b'_', AML_PACKAGE_OP, 0x06, 0x04,
+                AML_ONE_OP, AML_ZERO_OP, AML_ZERO_OP, AML_ZERO_OP,
+            ];
+            assert_eq!(sleep_type(&aml, 3), Some((1, 0)));
+        }
+
+        fn sleep_type_reads_mixed_encodings() {
+            // Name (_S3_, Package () { Zero, 0x07 }) with a 2-byte `PkgLength`,
+            // after a reference to `_S3_` that is not its definition.
+            let aml = [
+                0x70, b'_', b'S', b'3', b'_', 0x60,
+                AML_NAME_OP, b'_', b'S', b'3', b'_', AML_PACKAGE_OP, 0x46, 0x00, 0x02,
+                AML_ZERO_OP, AML_BYTE_PREFIX, 0x07,
+            ];
+            assert_eq!(sleep_type(&aml, 3), Some((0, 7)));
+        }
+
+        fn sleep_type_rejects_malformed() {
+            // Not a package.
+            let aml = [AML_NAME_OP, b'_', b'S', b'3', b'_', AML_ONE_OP];
+            assert_eq!(sleep_type(&aml, 3), None);
+            // Truncated.
+            let aml = [
+                AML_NAME_OP, b'_', b'S', b'3', b'_', AML_PACKAGE_OP, 0x04, 0x02, AML_BYTE_PREFIX,
+            ];
+            assert_eq!(sleep_type(&aml, 3), None);
+            // Not an integer constant.
+            let aml = [
+                AML_NAME_OP, b'_', b'S', b'3', b'_', AML_PACKAGE_OP, 0x04, 0x02, 0x0b, 0x05, 0x00,
+            ];
+            assert_eq!(sleep_type(&aml, 3), None);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Fault injection for exercising the error paths, compiled only with
+//! `--cfg fault_inject`.
+//!
+//! A rule set with `sv_fault_set` fails every Nth operation at an injection
+//! point with the given error, either in a task or in all the user tasks,
+//! where the rules of the task take precedence. Without the cfg, the checks
+//! are no-ops and the syscall fails with `ENOSYS`.
+
+use sv_call::Result;
+
+/// The points where faults are injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Point {
+    /// Memory allocations requested by user tasks.
+    Alloc,
+    /// Insertions into handle tables.
+    Handle,
+    /// Sending packets into channels.
+    Send,
+    /// Calling the syscall of the number.
+    Syscall(usize),
+}
+
+/// Fail the operation at `point` in the current task if a rule says so.
+#[inline]
+pub fn check(point: Point) -> Result {
+    #[cfg(fault_inject)]
+    if imp::ACTIVE.load(core::sync::atomic::Ordering::Acquire) {
+        return imp::check(point);
+    }
+    let _ = point;
+    Ok(())
+}
+
+#[cfg(fault_inject)]
+mod imp {
+    use alloc::vec::Vec;
+    use core::sync::atomic::{AtomicBool, Ordering::*};
+
+    use spin::Mutex;
+    use sv_call::{Error, Result, ENOMEM};
+
+    use super::Point;
+    use crate::sched::{task::Type, PREEMPT, SCHED};
+
+    /// Whether there's any rule, for the fast path of the checks.
+    pub static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+    static RULES: Mutex<Vec<Rule>> = Mutex::new(Vec::new());
+
+    struct Rule {
+        /// The raw TID of the task, or `None` for all the user tasks.
+        task: Option<u64>,
+        point: Point,
+        nth: u32,
+        error: Error,
+        count: u32,
+    }
+
+    pub fn check(point: Point) -> Result {
+        let task = match SCHED.with_current(|cur| Ok((cur.tid().raw(), cur.tid().ty()))) {
+            Ok((_, Type::Kernel)) | Err(_) => return Ok(()),
+            Ok((task, Type::User)) => task,
+        };
+        PREEMPT.scope(|| {
+            let mut rules = RULES.lock();
+            let rule = { rules.iter_mut() }
+                .filter(|rule| rule.point == point && rule.task.map_or(true, |t| t == task))
+                .min_by_key(|rule| rule.task.is_none());
+            match rule {
+                Some(rule) => {
+                    rule.count = rule.count.wrapping_add(1);
+                    if rule.count % rule.nth == 0 {
+                        Err(rule.error)
+                    } else {
+                        Ok(())
+                    }
+                }
+                None => Ok(()),
+            }
+        })
+    }
+
+    /// Replace the rule of `point` in `task`, or remove it if `nth` is 0.
+    pub fn set(task: Option<u64>, point: Point, nth: u32, error: Error) -> Result {
+        PREEMPT.scope(|| {
+            let mut rules = RULES.lock();
+            rules.retain(|rule| rule.task != task || rule.point != point);
+            if nth > 0 {
+                rules.try_reserve(1).map_err(|_| ENOMEM)?;
+                rules.push(Rule {
+                    task,
+                    point,
+                    nth,
+                    error,
+                    count: 0,
+                });
+            }
+            ACTIVE.store(!rules.is_empty(), Release);
+            Ok(())
+        })
+    }
+}
+
+mod syscall {
+    use sv_call::*;
+
+    /// Fail every `nth` operation at the point `ty` with `error`, an encoded
+    /// return value, in the child task `task`, or in all the user tasks if
+    /// it's null. `num` is the number of the syscall for `FAULT_SYSCALL`.
+    #[syscall]
+    fn fault_set(task: Handle, ty: u32, num: usize, nth: u32, error: usize) -> Result {
+        #[cfg(not(fault_inject))]
+        {
+            let _ = (task, ty, num, nth, error);
+            Err(ENOSYS)
+        }
+
+        #[cfg(fault_inject)]
+        {
+            use super::Point;
+            use crate::sched::SCHED;
+
+            let point = match ty {
+                task::FAULT_ALLOC => Point::Alloc,
+                task::FAULT_HANDLE => Point::Handle,
+                task::FAULT_SEND => Point::Send,
+                // The rules must always be able to be removed.
+                task::FAULT_SYSCALL if num != SV_FAULT_SET => Point::Syscall(num),
+                _ => return Err(EINVAL),
+            };
+            let error = Error::try_from_retval(error).ok_or(EINVAL)?;
+            let task = if task == Handle::NULL {
+                None
+            } else {
+                Some(SCHED.with_current(|cur| cur.space().child(task))?.raw())
+            };
+            super::imp::set(task, point, nth, error)
+        }
+    }
+}
@@ -31,8 +31,12 @@
 #![feature(unsize)]
 #![feature(vec_into_raw_parts)]
 
+mod audit;
 pub mod cpu;
 pub mod dev;
+mod fault;
+#[cfg(ktest)]
+mod ktest;
 mod logger;
 mod mem;
 mod rxx;
@@ -49,6 +53,17 @@ fn kargs() -> &'static minfo::KernelArgs {
     unsafe { KARGS.assume_init_ref() }
 }
 
+/// Get the value of the option `key=value` in the boot command line.
+fn cmdline_option(key: &str) -> Option<&'static str> {
+    let cmdline = &kargs().cmdline;
+    let len = cmdline.iter().position(|&b| b == 0).unwrap_or(cmdline.len());
+    let cmdline = core::str::from_utf8(&cmdline[..len]).ok()?;
+    cmdline.split_ascii_whitespace().find_map(|opt| {
+        let (k, v) = opt.split_once('=')?;
+        (k == key).then_some(v)
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn kmain() {
     unsafe {
@@ -70,6 +85,12 @@ pub extern "C" fn kmain() {
 
     unsafe { dev::init() };
 
+    #[cfg(ktest)]
+    ktest::run();
+
+    #[cfg(bench)]
+    cpu::time::bench_timers();
+
     sched::init();
 
     // Test end
@@ -83,6 +104,8 @@ pub fn kmain_ap() {
 
     unsafe { mem::space::init() };
     unsafe { cpu::arch::init_ap() };
+    cpu::intr::balance();
+    cpu::arch::apic::ipi::test_delivery();
 
     sched::init();
 
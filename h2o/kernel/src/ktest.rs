@@ -0,0 +1,127 @@
+//! In-kernel unit tests, compiled only with `--cfg ktest`.
+//!
+//! Cases are declared with [`case!`] next to the code they test and collected
+//! into the `.ktest` section by the linker. They run on the bootstrap CPU
+//! after the devices are initialized and before the first task is spawned, so
+//! they must not block or rely on [`SCHED`](crate::sched::SCHED); waiters can
+//! be replaced with a [`MockWaiter`].
+//!
+//! The runner checks that every case leaves the preemption state as it found
+//! it, and prints `ktest result: ok` at the end, which `cargo xtask test`
+//! looks for in the serial log. A failing case panics the kernel.
+
+use alloc::vec::Vec;
+use core::slice;
+
+use spin::Mutex;
+
+use crate::{
+    cpu::arch::apic::TriggerMode,
+    sched::{Waiter, WaiterData, PREEMPT},
+};
+
+pub struct Case {
+    pub name: &'static str,
+    pub func: fn(),
+}
+
+/// Declare test cases, each of which is a function without arguments.
+macro_rules! case {
+    ($(fn $name:ident() $body:block)*) => {
+        $(
+            fn $name() $body
+
+            const _: () = {
+                #[used]
+                #[link_section = ".ktest"]
+                static CASE: $crate::ktest::Case = $crate::ktest::Case {
+                    name: concat!(module_path!(), "::", stringify!($name)),
+                    func: $name,
+                };
+            };
+        )*
+    };
+}
+pub(crate) use case;
+
+fn cases() -> &'static [Case] {
+    extern "C" {
+        static KTEST_START: Case;
+        static KTEST_END: Case;
+    }
+    // SAFETY: The linker puts all the cases between the 2 symbols.
+    unsafe {
+        let start = &KTEST_START as *const Case;
+        let len = (&KTEST_END as *const Case).offset_from(start) as usize;
+        slice::from_raw_parts(start, len)
+    }
+}
+
+/// Run all the cases whose names contain the boot option `ktest=<filter>`, if
+/// any.
+pub fn run() {
+    let filter = crate::cmdline_option("ktest").unwrap_or_default();
+    let cases = cases().iter().filter(|case| case.name.contains(filter));
+
+    let mut count = 0;
+    for case in cases {
+        log::info!("ktest {} ...", case.name);
+        let preempt = PREEMPT.raw();
+        (case.func)();
+        assert_eq!(
+            PREEMPT.raw(),
+            preempt,
+            "ktest {}: unbalanced preemption state",
+            case.name
+        );
+        count += 1;
+    }
+    log::info!("ktest result: ok, {count} passed");
+}
+
+/// A waiter recording the signals it receives instead of waking up tasks.
+#[derive(Debug)]
+pub struct MockWaiter {
+    data: WaiterData,
+    notified: Mutex<Vec<usize>>,
+    canceled: Mutex<Vec<usize>>,
+}
+
+impl MockWaiter {
+    pub fn new(trigger_mode: TriggerMode, signal: usize) -> Self {
+        Self::with_data(WaiterData::new(trigger_mode, signal))
+    }
+
+    pub fn with_data(data: WaiterData) -> Self {
+        MockWaiter {
+            data,
+            notified: Mutex::new(Vec::new()),
+            canceled: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Take the signals of the notifications received so far.
+    pub fn notified(&self) -> Vec<usize> {
+        PREEMPT.scope(|| core::mem::take(&mut *self.notified.lock()))
+    }
+
+    /// Take the signals of the cancellations received so far.
+    pub fn canceled(&self) -> Vec<usize> {
+        PREEMPT.scope(|| core::mem::take(&mut *self.canceled.lock()))
+    }
+}
+
+impl Waiter for MockWaiter {
+    #[inline]
+    fn waiter_data(&self) -> WaiterData {
+        self.data
+    }
+
+    fn on_cancel(&self, _: *const (), signal: usize) {
+        PREEMPT.scope(|| self.canceled.lock().push(signal));
+    }
+
+    fn on_notify(&self, signal: usize) {
+        PREEMPT.scope(|| self.notified.lock().push(signal));
+    }
+}
@@ -1,4 +1,7 @@
+pub mod earlycon;
 pub mod flags;
+mod font;
+pub mod quota;
 mod serial;
 
 use core::{
@@ -75,6 +78,9 @@ impl log::Log for Logger {
             )
         };
         res.expect("Failed to output");
+        drop(os);
+
+        earlycon::write(record.level(), *record.args());
     }
 
     #[inline]
@@ -88,29 +94,46 @@ static mut LOGGER: MaybeUninit<Logger> = MaybeUninit::uninit();
 /// This function should only be called once before everything else is to be
 /// started up.
 pub unsafe fn init(max_level: log::Level) {
+    earlycon::init();
     let logger = LOGGER.write(Logger::new(max_level));
     log::set_logger(logger).expect("Failed to set the logger");
     log::set_max_level(max_level.to_level_filter());
 }
 
 mod syscall {
+    use alloc::vec::Vec;
     use core::fmt::Write;
 
     use sv_call::*;
 
-    use super::LOGGER;
+    use super::{quota, LOGGER};
     use crate::{
-        sched::PREEMPT,
+        sched::{PREEMPT, SCHED},
         syscall::{In, UserPtr},
     };
 
     #[syscall]
     fn log(buffer: UserPtr<In>, len: usize) -> Result {
         buffer.check_slice(len)?;
-        let string =
-            core::str::from_utf8(unsafe { core::slice::from_raw_parts(buffer.as_ptr(), len) })?;
+        let mut buf = Vec::new();
+        buf.try_reserve_exact(len).map_err(|_| ENOMEM)?;
+        unsafe {
+            buffer.read_slice(buf.as_mut_ptr(), len)?;
+            buf.set_len(len);
+        }
+        let string = core::str::from_utf8(&buf)?;
+        let (tid, dropped) = SCHED.with_current(|cur| {
+            let tid = cur.tid();
+            Ok((tid.raw(), quota::charge(tid.log_quota(), len)))
+        })?;
+        let dropped = dropped.ok_or(EAGAIN)?;
+
         let _pree = PREEMPT.lock();
         let mut os = unsafe { LOGGER.assume_init_ref() }.output.lock();
+        if dropped > 0 {
+            writeln!(os, "[log] {dropped} messages dropped from task #{tid}")
+                .map_err(|_| EFAULT)?;
+        }
         writeln!(os, "{string}").map_err(|_| EFAULT)?;
         Ok(())
     }
@@ -0,0 +1,171 @@
+//! The early console on the framebuffer left by the boot loader.
+//!
+//! Enabled with `earlycon=fb` in the boot command line, it shows the log
+//! records at the level of `Info` and above, so that machines without serial
+//! ports can read the messages and panics before any console service starts.
+//! The framebuffer is accessed through the identity mapping of the boot loader,
+//! so it can be used before the memory management is initialized.
+//!
+//! The text wraps around to the top instead of scrolling, since reading back
+//! from the framebuffer is too slow.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering::*},
+};
+
+use spin::Mutex;
+
+use super::font;
+
+const WHITE: u32 = 0x00ff_ffff;
+const BLACK: u32 = 0;
+
+static CONSOLE: Mutex<Option<Console>> = Mutex::new(None);
+/// Whether the framebuffer still belongs to the kernel.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+struct Console {
+    base: *mut u32,
+    /// The number of pixels in a scan line.
+    stride: usize,
+    scale: usize,
+    cols: usize,
+    rows: usize,
+    col: usize,
+    row: usize,
+    color: u32,
+    red: u32,
+}
+
+// SAFETY: The framebuffer is only accessed with the lock of `CONSOLE`.
+unsafe impl Send for Console {}
+
+impl Console {
+    fn new(fb: &minfo::Framebuffer) -> Option<Self> {
+        let end = fb.phys.checked_add(fb.size)?;
+        if end > minfo::INITIAL_ID_SPACE {
+            return None;
+        }
+        let scale = if fb.width >= 1600 { 2 } else { 1 };
+        let cols = fb.width as usize / (font::WIDTH * scale);
+        let rows = fb.height as usize / (font::HEIGHT * 2 * scale);
+        (cols > 0 && rows > 0).then(|| Console {
+            base: *fb.phys.to_laddr(minfo::ID_OFFSET) as *mut u32,
+            stride: fb.stride as usize,
+            scale,
+            cols,
+            rows,
+            col: 0,
+            row: 0,
+            color: WHITE,
+            red: if fb.bgr { 0x00ff_0000 } else { 0x0000_00ff },
+        })
+    }
+
+    fn cell_size(&self) -> (usize, usize) {
+        (font::WIDTH * self.scale, font::HEIGHT * 2 * self.scale)
+    }
+
+    fn fill_row(&mut self, row: usize) {
+        let (width, height) = self.cell_size();
+        for y in row * height..(row + 1) * height {
+            for x in 0..self.cols * width {
+                // SAFETY: The pixel is inside the framebuffer.
+                unsafe { self.base.add(y * self.stride + x).write_volatile(BLACK) };
+            }
+        }
+    }
+
+    fn draw(&mut self, ch: u8) {
+        let (width, height) = self.cell_size();
+        let glyph = font::glyph(ch);
+        let (x0, y0) = (self.col * width, self.row * height);
+        for y in 0..height {
+            let bits = glyph[y / (2 * self.scale)];
+            for x in 0..width {
+                let color = if bits & (1 << (x / self.scale)) != 0 {
+                    self.color
+                } else {
+                    BLACK
+                };
+                // SAFETY: The pixel is inside the framebuffer.
+                unsafe {
+                    let pixel = self.base.add((y0 + y) * self.stride + x0 + x);
+                    pixel.write_volatile(color);
+                }
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.col = 0;
+        self.row = (self.row + 1) % self.rows;
+        self.fill_row(self.row);
+    }
+
+    fn put(&mut self, ch: u8) {
+        match ch {
+            b'\n' => self.new_line(),
+            b'\r' => self.col = 0,
+            _ => {
+                if self.col == self.cols {
+                    self.new_line();
+                }
+                self.draw(ch);
+                self.col += 1;
+            }
+        }
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.bytes().for_each(|b| self.put(b));
+        Ok(())
+    }
+}
+
+/// Set up the early console if it's enabled in the boot command line.
+pub fn init() {
+    if crate::cmdline_option("earlycon") != Some("fb") {
+        return;
+    }
+    let console = crate::kargs().framebuffer.as_ref().and_then(Console::new);
+    if let Some(mut console) = console {
+        console.fill_row(0);
+        *CONSOLE.lock() = Some(console);
+        ACTIVE.store(true, Release);
+    }
+}
+
+/// Output a log record to the early console, if any.
+pub fn write(level: log::Level, args: fmt::Arguments) {
+    if level > log::Level::Info || !ACTIVE.load(Acquire) {
+        return;
+    }
+    if let Some(console) = &mut *CONSOLE.lock() {
+        console.color = if level == log::Level::Error {
+            console.red
+        } else {
+            WHITE
+        };
+        let _ = fmt::Write::write_fmt(console, format_args!("{level}: {args}\n"));
+    }
+}
+
+/// Stop the output since the framebuffer is given to the user space.
+pub fn release() {
+    ACTIVE.store(false, Release);
+}
+
+/// Take the framebuffer back from the user space to show a panic.
+pub fn reclaim() {
+    if ACTIVE.swap(true, AcqRel) {
+        return;
+    }
+    if let Some(console) = &mut *CONSOLE.lock() {
+        console.row = console.rows - 1;
+        console.new_line();
+    }
+}
@@ -0,0 +1,99 @@
+//! Rate limiting of the log messages written by user tasks.
+//!
+//! Every task has a bucket of bytes refilled over time, and all of them share
+//! a global one in front of the serial console. A message exceeding either
+//! bucket is dropped and counted, and the count is written before the next
+//! message of the task that gets through. The kernel's own messages are never
+//! limited.
+
+use core::time::Duration;
+
+use spin::Mutex;
+
+use crate::cpu::time::Instant;
+
+const TASK_BURST: u64 = 16 * 1024;
+/// The bytes per second.
+const TASK_RATE: u64 = 4 * 1024;
+const GLOBAL_BURST: u64 = 64 * 1024;
+const GLOBAL_RATE: u64 = 16 * 1024;
+
+static GLOBAL: Mutex<Bucket> = Mutex::new(Bucket::new(GLOBAL_BURST, GLOBAL_RATE));
+
+#[derive(Debug)]
+struct Bucket {
+    burst: u64,
+    rate: u64,
+    tokens: u64,
+    last: Option<Instant>,
+}
+
+impl Bucket {
+    const fn new(burst: u64, rate: u64) -> Self {
+        Bucket {
+            burst,
+            rate,
+            tokens: burst,
+            last: None,
+        }
+    }
+
+    /// Refill the bucket and check if it has `len` bytes.
+    fn check(&mut self, now: Instant, len: u64) -> bool {
+        match self.last {
+            Some(last) => {
+                let elapsed = now.saturating_duration_since(last);
+                let refill =
+                    elapsed.as_nanos() * self.rate as u128 / Duration::from_secs(1).as_nanos();
+                // Frequent checks would refill nothing if the time were always
+                // advanced.
+                if refill > 0 {
+                    let refill = u64::try_from(refill).unwrap_or(u64::MAX);
+                    self.tokens = self.tokens.saturating_add(refill).min(self.burst);
+                    self.last = Some(now);
+                }
+            }
+            None => self.last = Some(now),
+        }
+        self.tokens >= len
+    }
+}
+
+/// The log quota of a task.
+#[derive(Debug)]
+pub struct LogQuota {
+    bucket: Bucket,
+    dropped: u64,
+}
+
+impl Default for LogQuota {
+    fn default() -> Self {
+        LogQuota {
+            bucket: Bucket::new(TASK_BURST, TASK_RATE),
+            dropped: 0,
+        }
+    }
+}
+
+/// Charge a message of `len` bytes to `quota`.
+///
+/// Returns the number of the messages dropped since the last one written if
+/// the message can be written, or `None` if it's dropped.
+pub fn charge(quota: &Mutex<LogQuota>, len: usize) -> Option<u64> {
+    let now = Instant::now();
+    // The line feed is charged too, and longer messages than the bursts are
+    // charged as much as the bursts to get through eventually.
+    let len = (len as u64 + 1).min(TASK_BURST);
+
+    let mut quota = quota.lock();
+    let mut global = GLOBAL.lock();
+    // Check both before charging either, so that a dropped message costs
+    // nothing.
+    if !(quota.bucket.check(now, len) && global.check(now, len)) {
+        quota.dropped += 1;
+        return None;
+    }
+    quota.bucket.tokens -= len;
+    global.tokens -= len;
+    Some(core::mem::take(&mut quota.dropped))
+}
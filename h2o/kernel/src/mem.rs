@@ -1,5 +1,6 @@
 mod arena;
 pub mod heap;
+mod hotplug;
 pub mod space;
 mod syscall;
 
@@ -9,7 +10,7 @@ use core::{alloc::Allocator, ptr::NonNull, sync::atomic::AtomicUsize};
 use archop::Azy;
 use iter_ex::PtrIter;
 
-pub use self::arena::Arena;
+pub use self::{arena::Arena, hotplug::overlaps_ram};
 use crate::{dev::Resource, kargs};
 
 pub static MMAP: Azy<PtrIter<pmm::boot::MemRange>> = Azy::new(|| {
@@ -70,3 +71,30 @@ pub fn alloc_system_stack() -> sv_call::Result<NonNull<u8>> {
 pub fn init() {
     Azy::force(&MEM_RESOURCE);
 }
+
+#[cfg(ktest)]
+mod ktests {
+    use paging::{PAGE_SHIFT, PAGE_SIZE};
+
+    use crate::ktest::case;
+
+    case! {
+        fn pmm_pages_are_aligned_and_disjoint() {
+            let a = pmm::alloc_pages(2, None).expect("Failed to allocate pages");
+            let b = pmm::alloc_pages_exact(3, None).expect("Failed to allocate pages");
+            assert_eq!(*a & ((PAGE_SIZE << 2) - 1), 0);
+            assert_eq!(*b & (PAGE_SIZE - 1), 0);
+            assert!(*a + (4 << PAGE_SHIFT) <= *b || *b + (3 << PAGE_SHIFT) <= *a);
+
+            unsafe {
+                pmm::dealloc_pages_exact(3, b);
+                pmm::dealloc_pages(2, a);
+            }
+        }
+
+        fn pmm_rejects_invalid_orders() {
+            assert!(pmm::alloc_pages(pmm::MAX_ORDER, None).is_none());
+            assert!(pmm::alloc_pages_exact(1 << pmm::MAX_ORDER, None).is_none());
+        }
+    }
+}
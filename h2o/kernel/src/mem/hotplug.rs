@@ -0,0 +1,118 @@
+//! Physical memory plugged after boot.
+//!
+//! A hot-added range is identically mapped into the kernel half first. Its
+//! leading pages then hold the page frames the PMM needs for it, and the rest
+//! is handed to the PMM.
+
+use alloc::vec::Vec;
+use core::{mem, ops::Range, sync::atomic::Ordering::SeqCst};
+
+use archop::Azy;
+use bitop_ex::BitOpEx;
+use paging::{LAddr, PAddr, PAGE_MASK, PAGE_SHIFT};
+use pmm::{boot::MemType, HOT_ADD_ALIGN, KMEM_PHYS_BASE, PF_SIZE};
+use spin::Mutex;
+use sv_call::{
+    mem::{Flags, MemRange},
+    Result, EALIGN, EBUFFER, EBUSY, EEXIST, ENOMEM,
+};
+
+use super::{mem_resource, space, ALL_AVAILABLE, MMAP};
+use crate::sched::PREEMPT;
+
+/// The ranges of usable memory exported to the userspace.
+static RANGES: Azy<Mutex<Vec<MemRange>>> = Azy::new(|| {
+    let mut ranges = Vec::new();
+    for mdsc_ptr in &*MMAP {
+        let mdsc = unsafe { &*mdsc_ptr };
+        if matches!(mdsc.ty, MemType::Conventional | MemType::PersistentMemory) {
+            ranges.push(MemRange {
+                base: mdsc.phys as usize,
+                len: (mdsc.page_count as usize) << PAGE_SHIFT,
+                hot_added: false,
+            });
+        }
+    }
+    Mutex::new(ranges)
+});
+
+#[inline]
+pub fn ranges() -> Vec<MemRange> {
+    PREEMPT.scope(|| RANGES.lock().clone())
+}
+
+/// Whether `range` overlaps the usable memory, including the hot-added one.
+pub fn overlaps_ram(range: Range<usize>) -> bool {
+    PREEMPT.scope(|| {
+        { RANGES.lock().iter() }.any(|mem| mem.base < range.end && range.start < mem.base + mem.len)
+    })
+}
+
+/// The page frames the PMM may access when managing `range`.
+fn frames(range: &Range<usize>) -> Range<LAddr> {
+    let shift = HOT_ADD_ALIGN.trailing_zeros() as usize;
+    let start = range.start.round_down_bit(shift) >> PAGE_SHIFT;
+    let end = range.end.round_up_bit(shift) >> PAGE_SHIFT;
+
+    let start = (KMEM_PHYS_BASE + start * PF_SIZE).round_down_bit(PAGE_SHIFT);
+    let end = (KMEM_PHYS_BASE + end * PF_SIZE).round_up_bit(PAGE_SHIFT);
+    LAddr::from(start)..LAddr::from(end)
+}
+
+pub fn hot_add(range: Range<usize>) -> Result {
+    if range.start.contains_bit(PAGE_MASK) || range.end.contains_bit(PAGE_MASK) {
+        return Err(EALIGN);
+    }
+    if range.start >= range.end {
+        return Err(EBUFFER);
+    }
+
+    PREEMPT.scope(|| {
+        let mut ranges = RANGES.lock();
+        if { ranges.iter() }.any(|r| r.base < range.end && range.start < r.base + r.len) {
+            return Err(EEXIST);
+        }
+
+        // Keep devices from acquiring the range.
+        let res = mem_resource();
+        if range.start < res.range().end {
+            let end = range.end.min(res.range().end);
+            mem::forget(res.allocate(range.start..end).ok_or(EBUSY)?);
+        }
+
+        let flags = Flags::READABLE | Flags::WRITABLE;
+        let id =
+            LAddr::from(range.start + minfo::ID_OFFSET)..LAddr::from(range.end + minfo::ID_OFFSET);
+        space::maps_shared(id, flags, |run| Ok(run.start.to_paddr(minfo::ID_OFFSET)))?;
+
+        let mut next = range.start;
+        space::maps_shared(frames(&range), flags, |run| {
+            let len = run.end.val() - run.start.val();
+            if next + len >= range.end {
+                return Err(ENOMEM);
+            }
+            let phys = PAddr::new(next);
+            unsafe { phys.to_laddr(minfo::ID_OFFSET).write_bytes(0, len) };
+            next += len;
+            Ok(phys)
+        })?;
+
+        let n = (range.end - next) >> PAGE_SHIFT;
+        // SAFETY: The range is new to the PMM and its page frames are mapped.
+        unsafe { pmm::hot_add(PAddr::new(next), n) };
+        ALL_AVAILABLE.fetch_add(n << PAGE_SHIFT, SeqCst);
+
+        ranges.push(MemRange {
+            base: range.start,
+            len: range.end - range.start,
+            hot_added: true,
+        });
+        log::info!(
+            "Hot-added memory {:#x}..{:#x} ({:#x} bytes available)",
+            range.start,
+            range.end,
+            n << PAGE_SHIFT
+        );
+        Ok(())
+    })
+}
@@ -3,6 +3,7 @@
 //! This module is responsible for managing system memory and address space in a
 //! higher level, especially for large objects like APIC.
 
+pub mod leak;
 mod phys;
 mod virt;
 
@@ -10,7 +11,7 @@ cfg_if::cfg_if! {
     if #[cfg(target_arch = "x86_64")] {
         #[path = "space/x86_64/mod.rs"]
         mod arch;
-        pub use self::arch::{page_fault, ErrCode as PageFaultErrCode};
+        pub use self::arch::{enable_pcid, flush_tlb, page_fault, ErrCode as PageFaultErrCode};
     }
 }
 
@@ -19,17 +20,23 @@ use core::{
     alloc::Layout,
     ops::{Deref, Range},
     ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    time::Duration,
 };
 
 use archop::Azy;
 use bitop_ex::BitOpEx;
-use paging::{LAddr, PAGE_SHIFT};
+pub use paging::Harvest;
+use paging::{LAddr, PAddr, PAGE_SHIFT, PAGE_SIZE};
 use spin::Mutex;
 pub use sv_call::mem::Flags;
-use sv_call::mem::PhysOptions;
+use sv_call::mem::{MemStat, PhysOptions};
 
 pub use self::{arch::init_pgc, phys::*, virt::*};
-use crate::sched::{task, PREEMPT};
+use crate::{
+    cpu::time::Instant,
+    sched::{task, PREEMPT},
+};
 
 type ArchSpace = arch::Space;
 
@@ -72,11 +79,35 @@ pub fn page_aligned(size: usize) -> Layout {
     unsafe { Layout::from_size_align_unchecked(size, paging::PAGE_LAYOUT.align()) }
 }
 
+/// The shortest window of the working set estimate, within which the last
+/// estimate is returned instead.
+const MIN_WS_WINDOW: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+struct WorkingSet {
+    sampled: Instant,
+    pages: usize,
+    window: Duration,
+}
+
+/// The memory charged to a space.
+#[derive(Debug, Default)]
+struct Charge {
+    /// The bytes of the physical memory mapped in the space.
+    phys: AtomicUsize,
+    /// The pages of the mappings still backed by the zero page.
+    lent: AtomicUsize,
+    /// The bytes of the kernel stacks of the tasks in the space.
+    kstack: AtomicUsize,
+}
+
 #[derive(Debug)]
 pub struct Space {
     arch: ArchSpace,
     root: Arc<Virt>,
     vdso: Mutex<Option<LAddr>>,
+    ws: Mutex<WorkingSet>,
+    charge: Charge,
 }
 
 unsafe impl Send for Space {}
@@ -86,9 +117,15 @@ impl Space {
     /// Create a new address space.
     pub fn try_new(ty: task::Type) -> sv_call::Result<Arc<Self>> {
         Ok(Arc::new_cyclic(|me| Space {
-            arch: ArchSpace::new(),
+            arch: ArchSpace::new(ty),
             root: Virt::new_root(ty, Weak::clone(me)),
             vdso: Mutex::new(None),
+            ws: Mutex::new(WorkingSet {
+                sampled: Instant::now(),
+                pages: 0,
+                window: Duration::ZERO,
+            }),
+            charge: Charge::default(),
         }))
     }
 
@@ -96,6 +133,103 @@ impl Space {
         &self.root
     }
 
+    /// Count the mapped, accessed and dirty pages of `virt` in the space,
+    /// clearing the accessed bits, and the dirty bits as well if
+    /// `clear_dirty`, so that the next harvest only counts the pages touched
+    /// since.
+    ///
+    /// The dirty bits must only be cleared if the caller keeps track of the
+    /// dirty pages itself.
+    pub fn harvest(&self, virt: Range<LAddr>, clear_dirty: bool) -> sv_call::Result<Harvest> {
+        let mut clear = paging::Attr::ACCESSED;
+        if clear_dirty {
+            clear |= paging::Attr::DIRTY;
+        }
+        PREEMPT
+            .scope(|| self.arch.harvest(virt, clear))
+            .map_err(paging_error)
+    }
+
+    /// Unmap the page at `addr` if it's still backed by the zero page,
+    /// returning whether it was.
+    ///
+    /// The next access faults in the page committed in the meantime, just like
+    /// a page of a demand-paged mapping.
+    fn shoot_down_zero(&self, addr: LAddr) -> bool {
+        PREEMPT.scope(|| match self.arch.query(addr) {
+            Ok((paddr, _)) if paddr == zero_page() => {
+                let next = LAddr::from(addr.val() + PAGE_SIZE);
+                self.arch.unmaps(addr..next).is_ok()
+            }
+            _ => false,
+        })
+    }
+
+    /// Charge the kernel stack of a new task in the space.
+    #[inline]
+    pub fn charge_kstack(&self, len: usize) {
+        self.charge.kstack.fetch_add(len, Relaxed);
+    }
+
+    #[inline]
+    pub fn discharge_kstack(&self, len: usize) {
+        self.charge.kstack.fetch_sub(len, Relaxed);
+    }
+
+    /// Sample the usage of the pages of the space, estimating its working set
+    /// with the pages accessed since the last sample.
+    pub fn stat(&self) -> sv_call::Result<MemStat> {
+        let range = self.root.range().clone();
+        PREEMPT.scope(|| {
+            let mut ws = self.ws.lock();
+            let now = Instant::now();
+            let clear = if now - ws.sampled >= MIN_WS_WINDOW {
+                paging::Attr::ACCESSED
+            } else {
+                paging::Attr::empty()
+            };
+            let harvest = self.arch.harvest(range, clear).map_err(paging_error)?;
+            if !clear.is_empty() {
+                *ws = WorkingSet {
+                    sampled: now,
+                    pages: harvest.accessed,
+                    window: now - ws.sampled,
+                };
+            }
+            let phys = self.charge.phys.load(Relaxed);
+            Ok(MemStat {
+                mapped: harvest.mapped,
+                dirty: harvest.dirty,
+                working_set: ws.pages,
+                window_us: ws.window.as_micros() as u64,
+                phys,
+                committed: phys.saturating_sub(self.charge.lent.load(Relaxed) << PAGE_SHIFT),
+                kstack: self.charge.kstack.load(Relaxed),
+            })
+        })
+    }
+
+    /// Translate `addr` to the physical address behind it.
+    ///
+    /// A writable page still backed by the zero page is committed first, so
+    /// that the physical address is not shared with the other lazy mappings,
+    /// and so is a page of a demand-paged mapping not populated yet.
+    pub fn translate(&self, addr: LAddr) -> sv_call::Result<PAddr> {
+        let query = || {
+            PREEMPT
+                .scope(|| self.arch.query(addr))
+                .map_err(paging_error)
+        };
+        match query() {
+            Ok((paddr, _)) if PAddr::new(paddr.round_down_bit(PAGE_SHIFT)) != zero_page() => {
+                return Ok(paddr)
+            }
+            Ok(_) => self.root.resolve_cow(addr)?,
+            Err(err) => self.root.populate(addr, true).map_err(|_| err)?,
+        }
+        query().map(|(paddr, _)| paddr)
+    }
+
     pub fn assert_mapped(&self, base: LAddr, len: usize) {
         PREEMPT.scope(|| {
             for offset in (0..len).step_by(paging::PAGE_SIZE) {
@@ -116,6 +250,40 @@ impl Deref for Space {
     }
 }
 
+/// Map the unmapped pages of `virt` into the kernel half shared by all the
+/// spaces, getting the physical base of each unmapped run from `phys`.
+pub(in crate::mem) fn maps_shared<F>(
+    virt: Range<LAddr>,
+    flags: Flags,
+    mut phys: F,
+) -> sv_call::Result
+where
+    F: FnMut(Range<LAddr>) -> sv_call::Result<PAddr>,
+{
+    if !KRL.arch.has_top_level(virt.clone()) {
+        return Err(sv_call::ERANGE);
+    }
+    let mapped = |addr: usize| PREEMPT.scope(|| KRL.arch.query(LAddr::from(addr)).is_ok());
+
+    let mut addr = virt.start.val();
+    while addr < virt.end.val() {
+        if mapped(addr) {
+            addr += paging::PAGE_SIZE;
+            continue;
+        }
+        let start = addr;
+        while addr < virt.end.val() && !mapped(addr) {
+            addr += paging::PAGE_SIZE;
+        }
+        let run = LAddr::from(start)..LAddr::from(addr);
+        let base = phys(run.clone())?;
+        PREEMPT
+            .scope(|| KRL.arch.maps(run, base, flags))
+            .map_err(paging_error)?;
+    }
+    Ok(())
+}
+
 pub(crate) fn allocate(size: usize, flags: Flags, zeroed: bool) -> sv_call::Result<NonNull<[u8]>> {
     let phys = allocate_phys(
         size.round_up_bit(PAGE_SHIFT),
@@ -144,14 +312,23 @@ pub(crate) unsafe fn unmap(ptr: NonNull<u8>) -> sv_call::Result {
 
         ret.map_or(Err(sv_call::ENOENT), |child| {
             let end = child.end(base);
+            if let Child::Phys(..) = child {
+                KRL.charge.phys.fetch_sub(end.val() - base.val(), Relaxed);
+            }
             let _ = KRL.arch.unmaps(base..end);
             Ok(())
         })
     })
 }
 
-pub fn init_stack(virt: &Arc<Virt>, size: usize) -> sv_call::Result<LAddr> {
-    let flags = Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS;
+/// Allocate and map a stack of `size` bytes for a task of `ty`, returning its
+/// top.
+pub fn init_stack(virt: &Arc<Virt>, size: usize, ty: task::Type) -> sv_call::Result<LAddr> {
+    let mut flags = Flags::READABLE | Flags::WRITABLE;
+    // Kernel stacks must not be user pages, or accessing them faults with SMAP.
+    if ty == task::Type::User {
+        flags |= Flags::USER_ACCESS;
+    }
     let virt = virt.allocate(None, unsafe {
         Layout::from_size_align_unchecked(paging::PAGE_SIZE * 2 + size, paging::PAGE_SIZE)
     })?;
@@ -0,0 +1,224 @@
+//! Tracking of the memory objects for leak hunting.
+//!
+//! Every [`Phys`] and task space is recorded along with its creation site, so
+//! that the ones still alive but no longer in use can be dumped to the kernel
+//! log with [`dump`]. The kernel return addresses of the creation sites are
+//! captured as well with the `leak-backtrace` feature.
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::panic::Location;
+
+use spin::Mutex;
+
+use super::{
+    virt::{Child, Virt},
+    Phys, PhysTrait, KRL,
+};
+use crate::sched::{task::Space as TaskSpace, PREEMPT, SCHED};
+
+#[cfg(feature = "leak-backtrace")]
+const BACKTRACE_DEPTH: usize = 8;
+
+/// The maximum number of the objects listed individually in a dump.
+const MAX_LISTED: usize = 32;
+
+/// The minimum number of the records kept before pruning the dead ones.
+const MIN_PRUNE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Site {
+    location: &'static Location<'static>,
+    #[cfg(feature = "leak-backtrace")]
+    backtrace: [usize; BACKTRACE_DEPTH],
+}
+
+impl Site {
+    #[track_caller]
+    fn capture() -> Self {
+        Site {
+            location: Location::caller(),
+            #[cfg(feature = "leak-backtrace")]
+            backtrace: backtrace(),
+        }
+    }
+}
+
+impl core::fmt::Display for Site {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.location)?;
+        #[cfg(feature = "leak-backtrace")]
+        for addr in self.backtrace.iter().take_while(|&&addr| addr != 0) {
+            write!(f, " <- {addr:#x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Walk the frame pointers of the kernel stack.
+#[cfg(feature = "leak-backtrace")]
+fn backtrace() -> [usize; BACKTRACE_DEPTH] {
+    let mut ret = [0; BACKTRACE_DEPTH];
+    let mut rbp: usize;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp) };
+    for slot in &mut ret {
+        // The entry stubs terminate the chain with an unaligned frame pointer.
+        if rbp < minfo::USER_END || rbp % core::mem::align_of::<usize>() != 0 {
+            break;
+        }
+        // SAFETY: The frame is on the current kernel stack.
+        let (next, ret_addr) = unsafe { (*(rbp as *const usize), *(rbp as *const usize).add(1)) };
+        *slot = ret_addr;
+        rbp = next;
+    }
+    ret
+}
+
+struct Record<T> {
+    obj: Weak<T>,
+    site: Site,
+    tid: Option<u64>,
+}
+
+struct Records<T> {
+    records: Vec<Record<T>>,
+    prune_at: usize,
+}
+
+impl<T> Records<T> {
+    const fn new() -> Self {
+        Records {
+            records: Vec::new(),
+            prune_at: MIN_PRUNE,
+        }
+    }
+
+    fn push(&mut self, obj: &Arc<T>, site: Site, tid: Option<u64>) {
+        if self.records.len() >= self.prune_at {
+            self.records.retain(|record| record.obj.strong_count() > 0);
+            self.prune_at = (self.records.len() * 2).max(MIN_PRUNE);
+        }
+        self.records.push(Record {
+            obj: Arc::downgrade(obj),
+            site,
+            tid,
+        });
+    }
+
+    fn live(&self) -> impl Iterator<Item = (Arc<T>, &Record<T>)> + '_ {
+        self.records
+            .iter()
+            .filter_map(|record| Some((record.obj.upgrade()?, record)))
+    }
+}
+
+static PHYS: Mutex<Records<Phys>> = Mutex::new(Records::new());
+static SPACES: Mutex<Records<TaskSpace>> = Mutex::new(Records::new());
+
+#[inline]
+fn current_tid() -> Option<u64> {
+    SCHED.with_current(|cur| Ok(cur.tid().raw())).ok()
+}
+
+#[track_caller]
+pub fn record_phys(phys: &Arc<Phys>) {
+    let site = Site::capture();
+    let tid = current_tid();
+    PREEMPT.scope(|| PHYS.lock().push(phys, site, tid))
+}
+
+#[track_caller]
+pub fn record_space(space: &Arc<TaskSpace>) {
+    let site = Site::capture();
+    let tid = current_tid();
+    PREEMPT.scope(|| SPACES.lock().push(space, site, tid))
+}
+
+/// Collect the `Phys`s mapped in the tree of `virt`, returning the total
+/// mapped size.
+fn collect_mapped(virt: &Virt, mapped: &mut BTreeSet<*const Phys>) -> (usize, usize) {
+    let children = virt.children.lock();
+    children
+        .values()
+        .fold((0, 0), |(count, size), child| match child {
+            Child::Virt(virt) => {
+                let (c, s) = collect_mapped(virt, mapped);
+                (count + c, size + s)
+            }
+            Child::Phys(phys, .., len) => {
+                mapped.insert(Arc::as_ptr(phys));
+                (count + 1, size + len)
+            }
+            Child::Reserved(_) => (count, size),
+        })
+}
+
+/// Dump the memory objects that are still alive but no longer in use to the
+/// kernel log:
+///
+/// - `Phys`s not mapped anywhere, which are only kept by handles or other
+///   objects;
+/// - Task spaces whose main tasks have exited, or have never started;
+/// - The totals of the live `Phys`s by creation site.
+pub fn dump() {
+    let _pree = PREEMPT.lock();
+
+    let mut mapped = BTreeSet::new();
+    collect_mapped(KRL.root(), &mut mapped);
+
+    log::info!("Task spaces without running tasks:");
+    let spaces = SPACES.lock();
+    let mut count = 0;
+    for (space, record) in spaces.live() {
+        let (maps, size) = collect_mapped(space.mem().root(), &mut mapped);
+        if !space.has_to_stop() {
+            continue;
+        }
+        if count < MAX_LISTED {
+            log::info!(
+                "  {:p}: {maps} mappings ({size:#x} bytes), created by task {:?} at {}",
+                Arc::as_ptr(&space),
+                record.tid,
+                record.site
+            );
+        }
+        count += 1;
+    }
+    log::info!("  Total: {count}");
+    drop(spaces);
+
+    log::info!("Phys objects not mapped:");
+    let phys = PHYS.lock();
+    let mut sites = BTreeMap::<Site, (usize, usize)>::new();
+    let (mut count, mut size) = (0, 0);
+    for (obj, record) in phys.live() {
+        let len = obj.len();
+        let total = sites.entry(record.site).or_default();
+        *total = (total.0 + 1, total.1 + len);
+
+        if mapped.contains(&Arc::as_ptr(&obj)) {
+            continue;
+        }
+        if count < MAX_LISTED {
+            log::info!(
+                "  {:p}: {len:#x} bytes, {} refs, created by task {:?} at {}",
+                Arc::as_ptr(&obj),
+                Arc::strong_count(&obj) - 1,
+                record.tid,
+                record.site
+            );
+        }
+        count += 1;
+        size += len;
+    }
+    drop(phys);
+    log::info!("  Total: {count} ({size:#x} bytes)");
+
+    log::info!("Live phys objects by creation site:");
+    for (site, (count, size)) in sites {
+        log::info!("  {count} ({size:#x} bytes) at {site}");
+    }
+}
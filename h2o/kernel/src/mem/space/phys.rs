@@ -1,5 +1,6 @@
 mod contiguous;
 mod extensible;
+mod mmio;
 
 use alloc::{
     sync::{Arc, Weak},
@@ -7,11 +8,15 @@ use alloc::{
 };
 
 use enum_dispatch::enum_dispatch;
-use paging::PAddr;
+use paging::{LAddr, PAddr};
 use sv_call::{mem::PhysOptions, Feature, Result, EPERM};
 
+use super::Space;
 use crate::{
-    sched::{task::hdl::DefaultFeature, Event},
+    sched::{
+        task::hdl::{DefaultFeature, KernelObject},
+        Event,
+    },
     syscall::{In, Out, UserPtr},
 };
 
@@ -19,6 +24,39 @@ type Cont = self::contiguous::Phys;
 
 type Ext = self::extensible::Phys;
 
+type Mmio = self::mmio::Phys;
+
+pub(super) use self::extensible::zero_page_released;
+pub use self::extensible::{zero_page, zero_page_stat};
+
+/// A mapping of `len` bytes from `offset` of a physical object at `base` in
+/// `space`, which may be backed by the [`zero_page`] until the pages are
+/// committed.
+#[derive(Debug, Clone)]
+pub struct Lender {
+    pub space: Weak<Space>,
+    pub base: LAddr,
+    pub offset: usize,
+    pub len: usize,
+    pub writable: bool,
+}
+
+impl Lender {
+    #[inline]
+    pub fn is(&self, space: &Space, base: LAddr) -> bool {
+        Weak::as_ptr(&self.space) == space as *const _ && self.base == base
+    }
+
+    /// The address where the byte at `offset` of the physical object is
+    /// mapped, if it's covered by the mapping.
+    #[inline]
+    pub fn addr(&self, offset: usize) -> Option<LAddr> {
+        (self.offset..self.offset + self.len)
+            .contains(&offset)
+            .then(|| LAddr::from(self.base.val() + (offset - self.offset)))
+    }
+}
+
 /// # Note
 ///
 /// The task handle map doesn't support dynamic sized objects, and the vtable of
@@ -29,6 +67,7 @@ type Ext = self::extensible::Phys;
 pub enum Phys {
     Cont,
     Ext,
+    Mmio,
 }
 
 #[allow(clippy::len_without_is_empty)]
@@ -40,8 +79,29 @@ pub trait PhysTrait {
 
     fn pin(&self, offset: usize, len: usize, write: bool) -> Result<Vec<(PAddr, usize)>>;
 
+    /// Pin the memory for mapping, where pages not committed yet may be backed
+    /// by the [`zero_page`], which must be mapped read-only and committed on
+    /// the first write fault.
+    #[inline]
+    fn pin_lazy(&self, offset: usize, len: usize, write: bool) -> Result<Vec<(PAddr, usize)>> {
+        self.pin(offset, len, write)
+    }
+
     fn unpin(&self, offset: usize, len: usize);
 
+    /// Register a mapping backed by the [`zero_page`], whose pages are
+    /// shot down once committed by someone else so that the next access
+    /// faults in the committed ones.
+    ///
+    /// The pages committed before the registration are shot down at once.
+    #[inline]
+    fn lend(&self, _: Lender) {}
+
+    /// Unregister the mapping at `base` in `space` registered with
+    /// [`PhysTrait::lend`].
+    #[inline]
+    fn unlend(&self, _: &Space, _: LAddr) {}
+
     fn create_sub(&self, offset: usize, len: usize, copy: bool) -> Result<Arc<Phys>>;
 
     fn base(&self) -> PAddr;
@@ -83,6 +143,35 @@ pub trait PhysTrait {
     }
 }
 
+impl Phys {
+    /// Whether the memory lies outside the RAM, such as the MMIO regions of
+    /// devices.
+    pub fn is_device(&self) -> bool {
+        match self {
+            Phys::Cont(cont) => {
+                let base = *cont.base();
+                !crate::mem::overlaps_ram(base..base + cont.len())
+            }
+            Phys::Ext(_) => false,
+            Phys::Mmio(_) => true,
+        }
+    }
+
+    /// Whether the memory is the MMIO of devices, which must not be cached
+    /// or moved around as RAM.
+    #[inline]
+    pub fn is_mmio(&self) -> bool {
+        matches!(self, Phys::Mmio(_))
+    }
+
+    /// Whether the pages are populated by the faults on the mappings instead
+    /// of when mapped.
+    #[inline]
+    pub fn is_demand(&self) -> bool {
+        matches!(self, Phys::Ext(ext) if ext.is_demand())
+    }
+}
+
 unsafe impl DefaultFeature for Phys {
     fn default_features() -> Feature {
         Feature::SEND
@@ -94,22 +183,56 @@ unsafe impl DefaultFeature for Phys {
     }
 }
 
+impl KernelObject for Phys {
+    fn event(this: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        Some(PhysTrait::event(&**this))
+    }
+}
+
 #[inline]
+#[track_caller]
 pub fn new_phys(base: PAddr, size: usize) -> Result<Arc<Phys>> {
-    Ok(Arc::try_new(Phys::from(Cont::new(base, size)?))?)
+    let ret = Arc::try_new(Phys::from(Cont::new(base, size)?))?;
+    super::leak::record_phys(&ret);
+    Ok(ret)
 }
 
+/// Create an object of the MMIO of devices.
+///
+/// # Errors
+///
+/// Returns `EPERM` if the range overlaps the RAM.
+#[inline]
+#[track_caller]
+pub fn new_mmio(base: PAddr, size: usize) -> Result<Arc<Phys>> {
+    let ret = Arc::try_new(Phys::from(Mmio::new(base, size)?))?;
+    super::leak::record_phys(&ret);
+    Ok(ret)
+}
+
+/// Allocate a block of physical memory.
+///
+/// Non-contiguous memory is always committed lazily. If `options` contains
+/// [`PhysOptions::ZEROED`], its writable mappings are backed by the
+/// [`zero_page`] until written. With [`PhysOptions::DEMAND`], nothing is
+/// mapped until the first access fault on each page.
+///
 /// # Errors
 ///
 /// Returns error if the heap memory is exhausted or the size is zero.
+#[track_caller]
 pub fn allocate_phys(size: usize, options: PhysOptions, contiguous: bool) -> Result<Arc<Phys>> {
     let resizable = options.contains(PhysOptions::RESIZABLE);
-    Ok(Arc::try_new(if contiguous {
-        if resizable {
+    let demand = options.contains(PhysOptions::DEMAND);
+    let ret = Arc::try_new(if contiguous {
+        if resizable || demand {
             return Err(EPERM);
         }
         Phys::from(Cont::allocate(size, options.contains(PhysOptions::ZEROED))?)
     } else {
-        Phys::from(Ext::new(size))
-    })?)
+        let lazy = demand || options.contains(PhysOptions::ZEROED);
+        Phys::from(Ext::new(size, lazy, demand))
+    })?;
+    super::leak::record_phys(&ret);
+    Ok(ret)
 }
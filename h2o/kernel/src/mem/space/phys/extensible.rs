@@ -8,7 +8,6 @@ use core::{
     alloc::Allocator,
     mem,
     ptr::NonNull,
-    slice,
     sync::atomic::{AtomicUsize, Ordering::SeqCst},
 };
 
@@ -21,14 +20,44 @@ use sv_call::{
     EAGAIN, EBUSY, EFAULT, ENOMEM, EPERM, ERANGE,
 };
 
-use super::PhysTrait;
+use super::{Lender, PhysTrait};
 use crate::{
+    mem::space::Space,
     sched::{Arsc, BasicEvent, Event, PREEMPT},
-    syscall::{In, Out, UserPtr},
+    syscall::{copy_from_user, copy_to_user, In, Out, UserPtr},
 };
 
 static ZERO_PAGE: Azy<Page> = Azy::new(|| Page::allocate().unwrap());
 
+/// The number of pages currently mapped to the zero page instead of being
+/// committed.
+static ZERO_MAPPED: AtomicUsize = AtomicUsize::new(0);
+/// The number of pages mapped to the zero page and then copied on write.
+static ZERO_COPIED: AtomicUsize = AtomicUsize::new(0);
+
+/// The physical address of the zero page shared by all the lazy mappings.
+#[inline]
+pub fn zero_page() -> PAddr {
+    ZERO_PAGE.base
+}
+
+/// Returns the number of pages currently saved by mapping the zero page, and
+/// the number of pages copied on write so far.
+#[inline]
+pub fn zero_page_stat() -> (usize, usize) {
+    (ZERO_MAPPED.load(SeqCst), ZERO_COPIED.load(SeqCst))
+}
+
+/// Record that `count` pages are no longer mapped to the zero page, either
+/// because they are copied on write or unmapped.
+#[inline]
+pub(in crate::mem::space) fn zero_page_released(count: usize, copied: bool) {
+    ZERO_MAPPED.fetch_sub(count, SeqCst);
+    if copied {
+        ZERO_COPIED.fetch_add(count, SeqCst);
+    }
+}
+
 #[derive(Debug)]
 struct Page {
     base: PAddr,
@@ -151,9 +180,44 @@ impl PageNode {
     }
 }
 
+/// A zero page mapped by a lender to be shot down, since the page at `index`
+/// is committed.
+#[derive(Debug)]
+struct ShootDown {
+    index: usize,
+    space: Weak<Space>,
+    addr: LAddr,
+    writable: bool,
+    /// Whether the page is committed by a write, copying the zero page.
+    copied: bool,
+}
+
+/// Shoot down the zero pages in `pending`, which sends IPIs and so must be
+/// done after the page list is unlocked.
+fn shoot_down(pending: Vec<ShootDown>) {
+    let mut copied = None;
+    for sd in pending {
+        let Some(space) = sd.space.upgrade() else {
+            continue;
+        };
+        if space.shoot_down_zero(sd.addr) && sd.writable {
+            ZERO_MAPPED.fetch_sub(1, SeqCst);
+            // The page is copied once for all the lenders.
+            if sd.copied && copied.replace(sd.index) != Some(sd.index) {
+                ZERO_COPIED.fetch_add(1, SeqCst);
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct PageList {
     branch: bool,
+    /// Whether uncommitted pages can be mapped to the zero page until written.
+    lazy: bool,
+    /// Whether the mappings are populated by the faults instead of when
+    /// mapped.
+    demand: bool,
 
     parent: Option<Arsc<Phys>>,
     parent_start: usize,
@@ -162,6 +226,10 @@ struct PageList {
     pages: BTreeMap<usize, PageNode>,
     count: usize,
     pin_count: usize,
+    /// The mappings that may be backed by the zero page.
+    lenders: Vec<Lender>,
+    /// The zero pages to be shot down once the list is unlocked.
+    shoot_downs: Vec<ShootDown>,
 }
 
 #[derive(Debug)]
@@ -226,12 +294,55 @@ impl PageList {
     fn commit(&mut self, index: usize, write: bool) -> Result<PAddr, Error> {
         assert!(!self.branch);
         match self.commit_impl(index, write) {
-            Ok(Commit::Ref(base)) => Ok(base),
+            Ok(Commit::Ref(base)) => {
+                if write {
+                    self.shoot_down(index, true);
+                }
+                Ok(base)
+            }
             Ok(Commit::Insert(_)) => unreachable!(),
             Err(err) => Err(err),
         }
     }
 
+    /// Queue the shootdowns of the zero page still mapped by the lenders at
+    /// `index`, which is committed now, so that they don't read stale zeros.
+    fn shoot_down(&mut self, index: usize, copied: bool) {
+        let offset = index << PAGE_SHIFT;
+        for lender in &self.lenders {
+            if let Some(addr) = lender.addr(offset) {
+                self.shoot_downs.push(ShootDown {
+                    index,
+                    space: Weak::clone(&lender.space),
+                    addr,
+                    writable: lender.writable,
+                    copied,
+                });
+            }
+        }
+    }
+
+    fn lend(&mut self, lender: Lender) {
+        let start = lender.offset >> PAGE_SHIFT;
+        let end = (lender.offset + lender.len).div_ceil_bit(PAGE_SHIFT);
+        self.lenders
+            .retain(|old| !old.space.ptr_eq(&lender.space) || old.base != lender.base);
+        self.lenders.push(lender);
+
+        // The pages may be committed by someone else before the registration.
+        let committed = (self.pages.range(start..end))
+            .filter(|(_, node)| node.page.is_some())
+            .map(|(&index, _)| index)
+            .collect::<Vec<_>>();
+        for index in committed {
+            self.shoot_down(index, false);
+        }
+    }
+
+    fn unlend(&mut self, space: &Space, base: LAddr) {
+        self.lenders.retain(|lender| !lender.is(space, base));
+    }
+
     fn decommit(&mut self, index: usize) -> Result<(), Error> {
         if let Entry::Occupied(mut ent) = self.pages.entry(index) {
             if ent.get().pin_count > 0 {
@@ -263,12 +374,16 @@ impl PageList {
                     len: AtomicUsize::new(0),
                     list: Mutex::new(PageList {
                         branch: true,
+                        lazy: false,
+                        demand: false,
                         parent: self.parent.clone(),
                         parent_start: self.parent_start,
                         parent_end: self.parent_end,
                         pages: mem::take(&mut self.pages),
                         count: self.count,
                         pin_count: self.pin_count,
+                        lenders: Vec::new(),
+                        shoot_downs: Vec::new(),
                     }),
                 });
                 Arsc::assume_init(branch)
@@ -280,12 +395,16 @@ impl PageList {
             len: AtomicUsize::new(len),
             list: Mutex::new(PageList {
                 branch: false,
+                lazy: self.lazy,
+                demand: self.demand,
                 parent: Some(branch.clone()),
                 parent_start: start,
                 parent_end: end,
                 pages: BTreeMap::new(),
                 count: end - start,
                 pin_count: 0,
+                lenders: Vec::new(),
+                shoot_downs: Vec::new(),
             }),
         };
 
@@ -318,15 +437,55 @@ impl PageList {
         let bases = (start..end)
             .map(|index| self.commit(index, write).map(|base| (base, PAGE_SIZE)))
             .collect::<Result<Vec<_>, _>>()?;
-        for index in start..end {
+        self.pin_range(start, end, write, |_| true)?;
+        Ok(bases)
+    }
+
+    /// Pin the pages like `pin`, except that uncommitted pages are lent the
+    /// zero page instead of being committed for writable mappings, and are
+    /// left unpinned.
+    fn pin_lazy(
+        &mut self,
+        start: usize,
+        end: usize,
+        write: bool,
+    ) -> Result<Vec<(PAddr, usize)>, Error> {
+        if !(self.lazy && write) {
+            return self.pin(start, end, write);
+        }
+        let bases = (start..end)
+            .map(|index| match self.commit(index, false)? {
+                base if base == ZERO_PAGE.base => Ok((base, PAGE_SIZE)),
+                _ => self.commit(index, true).map(|base| (base, PAGE_SIZE)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let lent = |index: usize| bases[index - start].0 == ZERO_PAGE.base;
+        self.pin_range(start, end, write, |index| !lent(index))?;
+
+        let count = (start..end).filter(|&index| lent(index)).count();
+        ZERO_MAPPED.fetch_add(count, SeqCst);
+        Ok(bases)
+    }
+
+    fn pin_range<F>(
+        &mut self,
+        start: usize,
+        end: usize,
+        write: bool,
+        filter: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(usize) -> bool,
+    {
+        for index in (start..end).filter(|&index| filter(index)) {
             if let Err(err) = self.pin_impl(index, write) {
-                for index in start..index {
+                for index in (start..index).filter(|&index| filter(index)) {
                     self.unpin_impl(index);
                 }
                 return Err(err);
             }
         }
-        Ok(bases)
+        Ok(())
     }
 
     fn unpin_impl(&mut self, index: usize) {
@@ -359,22 +518,43 @@ impl PageList {
 }
 
 impl Phys {
-    pub fn new(len: usize) -> Self {
+    pub fn new(len: usize, lazy: bool, demand: bool) -> Self {
         Phys {
             event: BasicEvent::new(0),
             len: AtomicUsize::new(len),
             list: Mutex::new(PageList {
                 branch: false,
+                lazy,
+                demand,
                 parent: None,
                 parent_start: 0,
                 parent_end: 0,
                 pages: BTreeMap::new(),
                 count: len.div_ceil_bit(PAGE_SHIFT),
                 pin_count: 0,
+                lenders: Vec::new(),
+                shoot_downs: Vec::new(),
             }),
         }
     }
 
+    /// Run `f` with the page list locked, and then shoot down the zero pages it
+    /// has committed.
+    fn with_list<T>(&self, f: impl FnOnce(&mut PageList) -> T) -> T {
+        let (ret, pending) = PREEMPT.scope(|| {
+            let mut list = self.list.lock();
+            let ret = f(&mut list);
+            (ret, mem::take(&mut list.shoot_downs))
+        });
+        shoot_down(pending);
+        ret
+    }
+
+    #[inline]
+    pub fn is_demand(&self) -> bool {
+        PREEMPT.scope(|| self.list.lock().demand)
+    }
+
     pub fn read(&self, pos: usize, len: usize, buffer: UserPtr<Out>) -> Result<usize, Error> {
         let self_len = self.len.load(SeqCst);
         let pos = pos.min(self_len);
@@ -393,9 +573,7 @@ impl Phys {
                     let src = LAddr::from(src.val() + pos_in_page);
                     let len = (len - read_len).min(PAGE_SIZE);
 
-                    let buffer = UserPtr::<Out>::new(buffer.as_ptr().add(read_len));
-                    let src = slice::from_raw_parts(*src, len);
-                    buffer.write_slice(src).map_err(Error::Other)?;
+                    copy_to_user(buffer.as_ptr().add(read_len), *src, len).map_err(Error::Other)?;
 
                     read_len += len;
                     pos_in_page = 0;
@@ -417,23 +595,29 @@ impl Phys {
         let start = pos >> PAGE_SHIFT;
         let end = (pos + len).div_ceil_bit(PAGE_SHIFT);
         let mut pos_in_page = pos - (start << PAGE_SHIFT);
-        for base in (start..end).map(|index| list.commit(index, true)) {
-            match base {
-                Ok(base) => unsafe {
-                    let src = base.to_laddr(minfo::ID_OFFSET);
-                    let src = LAddr::from(src.val() + pos_in_page);
-                    let len = (len - written_len).min(PAGE_SIZE);
-
-                    let buffer = UserPtr::<In>::new(buffer.as_ptr().add(written_len));
-                    buffer.read_slice(*src, len).map_err(Error::Other)?;
-
-                    written_len += len;
-                    pos_in_page = 0;
-                },
-                Err(err) => log::warn!("read error: {err:?}"),
+        let ret = (start..end).try_for_each(|index| match list.commit(index, true) {
+            Ok(base) => unsafe {
+                let src = base.to_laddr(minfo::ID_OFFSET);
+                let src = LAddr::from(src.val() + pos_in_page);
+                let len = (len - written_len).min(PAGE_SIZE);
+
+                copy_from_user(*src, buffer.as_ptr().add(written_len), len)
+                    .map_err(Error::Other)?;
+
+                written_len += len;
+                pos_in_page = 0;
+                Ok(())
+            },
+            Err(err) => {
+                log::warn!("write error: {err:?}");
+                Ok(())
             }
-        }
-        Ok(written_len)
+        });
+        let pending = mem::take(&mut list.shoot_downs);
+        drop(list);
+        shoot_down(pending);
+
+        ret.map(|_| written_len)
     }
 
     // pub fn commit(&self, start: usize, end: usize, write: bool) -> Result<(),
@@ -484,7 +668,21 @@ impl PhysTrait for Phys {
     fn pin(&self, offset: usize, len: usize, write: bool) -> sv_call::Result<Vec<(PAddr, usize)>> {
         let start = offset >> PAGE_SHIFT;
         let end = (offset + len).div_ceil_bit(PAGE_SHIFT);
-        let ret = PREEMPT.scope(|| self.list.lock().pin(start, end, write))?;
+        let ret = self.with_list(|list| list.pin(start, end, write))?;
+        self.event.notify(0, SIG_READ | SIG_WRITE);
+        Ok(ret)
+    }
+
+    #[inline]
+    fn pin_lazy(
+        &self,
+        offset: usize,
+        len: usize,
+        write: bool,
+    ) -> sv_call::Result<Vec<(PAddr, usize)>> {
+        let start = offset >> PAGE_SHIFT;
+        let end = (offset + len).div_ceil_bit(PAGE_SHIFT);
+        let ret = self.with_list(|list| list.pin_lazy(start, end, write))?;
         self.event.notify(0, SIG_READ | SIG_WRITE);
         Ok(ret)
     }
@@ -497,6 +695,16 @@ impl PhysTrait for Phys {
         self.event.notify(0, SIG_READ | SIG_WRITE);
     }
 
+    #[inline]
+    fn lend(&self, lender: Lender) {
+        self.with_list(|list| list.lend(lender))
+    }
+
+    #[inline]
+    fn unlend(&self, space: &Space, base: LAddr) {
+        PREEMPT.scope(|| self.list.lock().unlend(space, base))
+    }
+
     fn create_sub(
         &self,
         offset: usize,
@@ -0,0 +1,98 @@
+//! The physical memory of devices, such as MMIO registers and framebuffers.
+//!
+//! Accesses to the memory may have side effects, so it's only mapped with the
+//! uncached or write-combining attributes, and is never read, written, copied
+//! or committed lazily by the kernel on behalf of user tasks.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use bitop_ex::BitOpEx;
+use paging::{PAddr, PAGE_MASK};
+use sv_call::{Result, EALIGN, EPERM, ERANGE};
+
+use super::PhysTrait;
+use crate::{
+    sched::{BasicEvent, Event},
+    syscall::{In, Out, UserPtr},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phys {
+    base: PAddr,
+    len: usize,
+}
+
+impl Phys {
+    /// # Errors
+    ///
+    /// Returns `EPERM` if the range overlaps the RAM.
+    pub fn new(base: PAddr, len: usize) -> Result<Self> {
+        if base.contains_bit(PAGE_MASK) || len.contains_bit(PAGE_MASK) {
+            return Err(EALIGN);
+        }
+        if len == 0 {
+            return Err(ERANGE);
+        }
+        let end = base.checked_add(len).ok_or(ERANGE)?;
+        if crate::mem::overlaps_ram(*base..end) {
+            return Err(EPERM);
+        }
+        Ok(Phys { base, len })
+    }
+}
+
+impl PhysTrait for Phys {
+    fn event(&self) -> Weak<dyn Event> {
+        Weak::<BasicEvent>::new()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn pin(&self, offset: usize, len: usize, _: bool) -> Result<Vec<(PAddr, usize)>> {
+        let base = PAddr::new(*self.base + offset);
+        let len = self.len.saturating_sub(offset).min(len);
+        Ok((len > 0).then_some((base, len)).into_iter().collect())
+    }
+
+    fn unpin(&self, _: usize, _: usize) {}
+
+    fn create_sub(&self, offset: usize, len: usize, copy: bool) -> Result<Arc<super::Phys>> {
+        // Copying would read the registers.
+        if copy {
+            return Err(EPERM);
+        }
+        if offset.contains_bit(PAGE_MASK) || len.contains_bit(PAGE_MASK) {
+            return Err(EALIGN);
+        }
+        let end = offset.wrapping_add(len);
+        if !(offset < end && end <= self.len) {
+            return Err(ERANGE);
+        }
+        let sub = Phys {
+            base: PAddr::new(*self.base + offset),
+            len,
+        };
+        Ok(Arc::try_new(sub.into())?)
+    }
+
+    fn base(&self) -> PAddr {
+        self.base
+    }
+
+    fn resize(&self, _: usize, _: bool) -> Result {
+        Err(EPERM)
+    }
+
+    fn read(&self, _: usize, _: usize, _: UserPtr<Out>) -> Result<usize> {
+        Err(EPERM)
+    }
+
+    fn write(&self, _: usize, _: usize, _: UserPtr<In>) -> Result<usize> {
+        Err(EPERM)
+    }
+}
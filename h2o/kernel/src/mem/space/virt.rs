@@ -1,35 +1,67 @@
 use alloc::{
     collections::BTreeMap,
     sync::{Arc, Weak},
+    vec::Vec,
 };
-use core::{alloc::Layout, mem, ops::Range};
+use core::{alloc::Layout, mem, ops::Range, sync::atomic::Ordering::Relaxed};
 
+use archop::Azy;
 use bitop_ex::BitOpEx;
-use paging::{LAddr, PAGE_SHIFT, PAGE_SIZE};
+use paging::{LAddr, PAddr, PAGE_SHIFT, PAGE_SIZE};
 use spin::Mutex;
 use sv_call::{error::*, mem::Flags, Feature, Result};
 
-use super::{paging_error, ty_to_range, Phys, Space};
+use super::{paging_error, ty_to_range, zero_page, zero_page_released, Lender, Phys, Space};
 use crate::{
     mem::space::PhysTrait,
     sched::{
         task,
-        task::{hdl::DefaultFeature, VDSO},
-        PREEMPT,
+        task::{
+            hdl::{DefaultFeature, KernelObject},
+            VDSO,
+        },
+        Event, PREEMPT,
     },
 };
 
+/// The default number of the guard pages, overridden by the `mem.guard_pages`
+/// boot option.
+const DEFAULT_GUARD_PAGES: usize = 1;
+const MAX_GUARD_PAGES: usize = 256;
+
+/// The size of the gaps left between the automatically placed children of
+/// user `Virt`s, so that linear overruns fault instead of running into the
+/// neighbors.
+static GUARD_SIZE: Azy<usize> = Azy::new(|| {
+    let pages = match crate::cmdline_option("mem.guard_pages").map(str::parse::<usize>) {
+        Some(Ok(pages)) if pages <= MAX_GUARD_PAGES => pages,
+        Some(_) => {
+            log::warn!(
+                "Invalid guard pages, expected at most {}, using {}",
+                MAX_GUARD_PAGES,
+                DEFAULT_GUARD_PAGES
+            );
+            DEFAULT_GUARD_PAGES
+        }
+        None => DEFAULT_GUARD_PAGES,
+    };
+    pages << PAGE_SHIFT
+});
+
 #[derive(Debug)]
 pub(super) enum Child {
     Virt(Arc<Virt>),
     Phys(Arc<Phys>, Flags, usize, usize),
+    /// A range kept from automatic placement without being mapped, in which
+    /// mappings can only be placed at explicit offsets.
+    Reserved(usize),
 }
 
 impl Child {
     fn len(&self) -> usize {
         match self {
             Child::Virt(virt) => virt.len(),
-            Child::Phys(.., len) => *len,
+            Child::Phys(.., len) | Child::Reserved(len) => *len,
         }
     }
 
@@ -78,14 +110,22 @@ impl Virt {
         self.range.end.val() == self.range.start.val()
     }
 
+    fn guard_size(&self) -> usize {
+        match self.ty {
+            task::Type::User => *GUARD_SIZE,
+            task::Type::Kernel => 0,
+        }
+    }
+
     pub fn allocate(self: &Arc<Self>, offset: Option<usize>, layout: Layout) -> Result<Weak<Self>> {
         let layout = check_layout(layout)?;
 
         let _pree = PREEMPT.lock();
         let mut children = self.children.lock();
 
-        let range = find_range(&children, &self.range, offset, layout)?;
+        let range = find_range(&children, &self.range, offset, layout, self.guard_size())?;
         let base = range.start;
+        carve(&mut children, &range);
 
         let child = Arc::try_new(Virt {
             ty: self.ty,
@@ -99,6 +139,23 @@ impl Virt {
         Ok(ret)
     }
 
+    /// Keep a range from automatic placement without mapping anything.
+    ///
+    /// Children can be placed in the range at explicit offsets, which takes
+    /// the range they cover out of the reservation for good.
+    pub fn reserve(&self, offset: Option<usize>, layout: Layout) -> Result<LAddr> {
+        let layout = check_layout(layout)?;
+
+        let _pree = PREEMPT.lock();
+        let mut children = self.children.lock();
+
+        let range = find_range(&children, &self.range, offset, layout, self.guard_size())?;
+        let base = range.start;
+        carve(&mut children, &range);
+        let _ = children.insert(base, Child::Reserved(layout.size()));
+        Ok(base)
+    }
+
     pub fn destroy(&self) -> Result {
         if let Some(parent) = self.parent.upgrade() {
             let _ = parent.unmap(self.range.start, self.len(), true);
@@ -106,6 +163,13 @@ impl Virt {
         Ok(())
     }
 
+    /// Map `phys` into the range at `offset`, or at a random place if it's
+    /// `None`.
+    ///
+    /// With [`Flags::REPLACE`], the mappings and reservations in the range
+    /// are removed while the children are locked. If one of them fails to be
+    /// removed, it and the ones after it are kept. If the new mapping fails
+    /// after that, the range is left reserved instead of becoming a gap.
     pub fn map(
         &self,
         offset: Option<usize>,
@@ -125,6 +189,31 @@ impl Virt {
             return Err(EACCES);
         }
 
+        // Mapping the RAM with another memory type conflicts with its
+        // write-back mapping in the kernel.
+        if flags.contains(Flags::WRITE_COMBINING) && !phys.is_device() {
+            return Err(EPERM);
+        }
+        // Speculative accesses to the MMIO through the cache may trigger the
+        // side effects of the registers.
+        let flags = if phys.is_mmio() && !flags.intersects(Flags::UNCACHED | Flags::WRITE_COMBINING)
+        {
+            flags | Flags::UNCACHED
+        } else {
+            flags
+        };
+
+        let guard = if flags.contains(Flags::NO_GUARD) {
+            0
+        } else {
+            self.guard_size()
+        };
+        let replace = flags.contains(Flags::REPLACE);
+        if replace && offset.is_none() {
+            return Err(EINVAL);
+        }
+        let flags = flags - Flags::NO_GUARD - Flags::REPLACE;
+
         let layout = check_layout(layout)?;
         if phys_offset.contains_bit(PAGE_SHIFT) {
             return Err(EALIGN);
@@ -146,27 +235,74 @@ impl Virt {
                 return Err(EACCES);
             }
         }
-        let virt = find_range(&children, &self.range, offset, layout)?;
+        let virt = match offset {
+            Some(offset) if replace => fixed_range(&self.range, offset, layout)?,
+            _ => find_range(&children, &self.range, offset, layout, guard)?,
+        };
         let base = virt.start;
 
+        let demand = phys.is_demand();
+        let write = flags.contains(Flags::WRITABLE);
+        let mut lent = if demand {
+            layout.size() >> PAGE_SHIFT
+        } else {
+            0
+        };
         {
             let mut end = base;
-            let phys = phys.pin(phys_offset, layout.size(), flags.contains(Flags::WRITABLE))?;
-            for (phys_base, len) in phys {
+            // Demand-paged memory is populated by the faults instead.
+            let pages = if demand {
+                Vec::new()
+            } else {
+                phys.pin_lazy(phys_offset, layout.size(), write)?
+            };
+            if replace {
+                let vdso = *space.vdso.lock();
+                if let Err(err) = replace_range(&space, &mut children, &virt, vdso) {
+                    unpin_lazy(&phys, phys_offset, &pages, write);
+                    return Err(err);
+                }
+            }
+            for &(phys_base, len) in &pages {
                 let next = LAddr::from(end.val() + len);
                 let virt = end..next;
+                // The zero page is copied on the first write fault.
+                let flags = if phys_base == zero_page() {
+                    lent += len >> PAGE_SHIFT;
+                    flags - Flags::WRITABLE
+                } else {
+                    flags
+                };
                 if let Err(err) = space.arch.maps(virt, phys_base, flags) {
                     if base < end {
                         let _ = space.arch.unmaps(base..end);
                     }
+                    unpin_lazy(&phys, phys_offset, &pages, write);
+                    if replace {
+                        let _ = children.insert(base, Child::Reserved(layout.size()));
+                    }
                     return Err(paging_error(err));
                 }
                 end = next;
             }
-            assert!(end == virt.end);
+            assert!(demand || end == virt.end);
+        }
+        // The pages lent from the zero page must be shot down once committed by
+        // someone else.
+        if lent > 0 {
+            phys.lend(Lender {
+                space: Arc::downgrade(&space),
+                base,
+                offset: phys_offset,
+                len: layout.size(),
+                writable: write,
+            });
         }
 
+        carve(&mut children, &virt);
         let _ = children.insert(base, Child::Phys(phys, flags, phys_offset, layout.size()));
+        space.charge.phys.fetch_add(layout.size(), Relaxed);
+        space.charge.lent.fetch_add(lent, Relaxed);
 
         if set_vdso {
             *space.vdso.lock() = Some(base);
@@ -175,6 +311,7 @@ impl Virt {
     }
 
     pub fn reprotect(&self, base: LAddr, len: usize, flags: Flags) -> Result {
+        let flags = flags - Flags::NO_GUARD - Flags::REPLACE;
         let start = base;
         let end = LAddr::from(base.val() + len);
 
@@ -211,7 +348,29 @@ impl Virt {
             .range(start..)
             .take_while(|(&base, child)| child.end(base) <= end)
         {
-            { space.arch.reprotect(base..child.end(base), flags) }.map_err(paging_error)?;
+            let flags = match child {
+                // Nothing is mapped in the reservations.
+                Child::Reserved(_) => continue,
+                // The MMIO keeps its cache attributes.
+                Child::Phys(phys, f, ..) if phys.is_mmio() => {
+                    let cache = Flags::UNCACHED | Flags::WRITE_COMBINING;
+                    (flags - cache) | (*f & cache)
+                }
+                _ => flags,
+            };
+            let end = child.end(base);
+            { space.arch.reprotect(base..end, flags) }.map_err(paging_error)?;
+
+            if flags.contains(Flags::WRITABLE) {
+                // Pages backed by the zero page must remain read-only.
+                for addr in (base.val()..end.val()).step_by(PAGE_SIZE) {
+                    let (start, next) = (LAddr::from(addr), LAddr::from(addr + PAGE_SIZE));
+                    if matches!(space.arch.query(start), Ok((paddr, _)) if paddr == zero_page()) {
+                        let flags = flags - Flags::WRITABLE;
+                        { space.arch.reprotect(start..next, flags) }.map_err(paging_error)?;
+                    }
+                }
+            }
         }
 
         Ok(())
@@ -251,17 +410,170 @@ impl Virt {
         children.append(&mut prefix);
         drop(children);
 
-        let mut ret = Ok(None);
+        let mut ret = Ok(());
         for (base, child) in mid {
-            let end = child.end(base);
-            if let Child::Phys(phys, _, offset, len) = child {
-                phys.unpin(offset, len);
-                let r = space.arch.unmaps(base..end);
-                ret = ret.and(r.map_err(paging_error));
+            ret = ret.and(release(&space, base, &child));
+        }
+        ret
+    }
+
+    /// Take the physical memory of the mapping at `base` out of the address
+    /// space, so that it can be moved somewhere else.
+    ///
+    /// The mapping must start at `base` and cover at least `len` bytes, and it
+    /// must be a whole [`Phys`] that no one else refers to, so the caller
+    /// becomes its only owner after the whole mapping is removed.
+    ///
+    /// `prepare` is called once the mapping is checked and before it's
+    /// removed, so that the mapping is left intact if it fails.
+    pub fn take<F, R>(&self, base: LAddr, len: usize, prepare: F) -> Result<(Arc<Phys>, R)>
+    where
+        F: FnOnce() -> Result<R>,
+    {
+        let _pree = PREEMPT.lock();
+        let mut children = self.children.lock();
+        let (&child_base, child) = children.range(..=base).next_back().ok_or(ENOENT)?;
+        if child.end(child_base) <= base {
+            return Err(ENOENT);
+        }
+        match child {
+            Child::Virt(virt) => {
+                let virt = Arc::clone(virt);
+                drop(children);
+                return virt.take(base, len, prepare);
             }
+            Child::Reserved(_) => return Err(ENOENT),
+            Child::Phys(phys, flags, offset, child_len) => {
+                if child_base != base || *child_len < len {
+                    return Err(ERANGE);
+                }
+                let whole = *offset == 0 && *child_len == phys.len().round_up_bit(PAGE_SHIFT);
+                // The MMIO can't be moved as the pages of packets.
+                if phys.is_mmio()
+                    || !whole
+                    || !flags.contains(Flags::WRITABLE)
+                    || Arc::strong_count(phys) > 1
+                {
+                    return Err(EPERM);
+                }
+            }
+        }
+        let space = self.space.upgrade().ok_or(EKILLED)?;
+        let ret = prepare()?;
+        let child = children.remove(&base).unwrap();
+        drop(children);
+
+        release(&space, base, &child)?;
+        match child {
+            Child::Phys(phys, ..) => Ok((phys, ret)),
+            Child::Virt(_) | Child::Reserved(_) => unreachable!(),
+        }
+    }
+
+    /// Resolve a write fault on a writable mapping backed by the zero page,
+    /// committing the faulting page and mapping it writable.
+    pub(super) fn resolve_cow(&self, addr: LAddr) -> Result {
+        let _pree = PREEMPT.lock();
+        let children = self.children.lock();
+        let (&base, child) = children.range(..=addr).next_back().ok_or(ENOENT)?;
+        if child.end(base) <= addr {
+            return Err(ENOENT);
+        }
+        let (phys, flags, offset) = match child {
+            Child::Virt(virt) => {
+                let virt = Arc::clone(virt);
+                drop(children);
+                return virt.resolve_cow(addr);
+            }
+            Child::Phys(phys, flags, offset, _) => (phys, *flags, *offset),
+            Child::Reserved(_) => return Err(ENOENT),
+        };
+        if !flags.contains(Flags::WRITABLE) {
+            return Err(EPERM);
+        }
+        let space = self.space.upgrade().ok_or(EKILLED)?;
+
+        let page = LAddr::from(addr.val().round_down_bit(PAGE_SHIFT));
+        match space.arch.query(page) {
+            Ok((paddr, _)) if paddr == zero_page() => {}
+            // Already resolved by another CPU.
+            Ok(_) => return Ok(()),
+            Err(err) => return Err(paging_error(err)),
         }
 
-        ret.map(|_| {})
+        let phys_offset = offset + (page.val() - base.val());
+        let pinned = phys.pin(phys_offset, PAGE_SIZE, true)?;
+        let (paddr, _) = pinned[0];
+
+        // Committing the page shoots down the zero page of the lenders,
+        // including this mapping, and counts the copy there.
+        let next = LAddr::from(page.val() + PAGE_SIZE);
+        let _ = space.arch.unmaps(page..next);
+        space
+            .arch
+            .maps(page..next, paddr, flags)
+            .map_err(paging_error)?;
+        space.charge.lent.fetch_sub(1, Relaxed);
+        Ok(())
+    }
+
+    /// Resolve the first access fault on a page of a demand-paged mapping, or
+    /// on a page whose zero page has been shot down.
+    ///
+    /// A write commits the page, while a read maps the zero page read-only
+    /// until written unless the page is already committed.
+    pub(super) fn populate(&self, addr: LAddr, write: bool) -> Result {
+        let _pree = PREEMPT.lock();
+        let children = self.children.lock();
+        let (&base, child) = children.range(..=addr).next_back().ok_or(ENOENT)?;
+        if child.end(base) <= addr {
+            return Err(ENOENT);
+        }
+        let (phys, flags, offset) = match child {
+            Child::Virt(virt) => {
+                let virt = Arc::clone(virt);
+                drop(children);
+                return virt.populate(addr, write);
+            }
+            Child::Phys(phys, flags, offset, _) => (phys, *flags, *offset),
+            Child::Reserved(_) => return Err(ENOENT),
+        };
+        let writable = flags.contains(Flags::WRITABLE);
+        if write && !writable {
+            return Err(EPERM);
+        }
+        let space = self.space.upgrade().ok_or(EKILLED)?;
+
+        let page = LAddr::from(addr.val().round_down_bit(PAGE_SHIFT));
+        // Already populated by another CPU.
+        if space.arch.query(page).is_ok() {
+            return Ok(());
+        }
+
+        let phys_offset = offset + (page.val() - base.val());
+        let pinned = if write {
+            phys.pin(phys_offset, PAGE_SIZE, true)?
+        } else {
+            phys.pin_lazy(phys_offset, PAGE_SIZE, writable)?
+        };
+        let (paddr, _) = pinned[0];
+        let lent = paddr == zero_page();
+        // The zero page is copied on the first write fault.
+        let map_flags = if lent { flags - Flags::WRITABLE } else { flags };
+
+        let next = LAddr::from(page.val() + PAGE_SIZE);
+        if let Err(err) = space.arch.maps(page..next, paddr, map_flags) {
+            if !lent {
+                phys.unpin(phys_offset, PAGE_SIZE);
+            } else if writable {
+                zero_page_released(1, false);
+            }
+            return Err(paging_error(err));
+        }
+        if !lent {
+            space.charge.lent.fetch_sub(1, Relaxed);
+        }
+        Ok(())
     }
 }
 
@@ -271,8 +583,12 @@ impl Drop for Virt {
         if let Some(space) = self.space.upgrade() {
             for (base, child) in children {
                 let end = child.end(base);
-                if let Child::Phys(..) = child {
-                    let _ = PREEMPT.scope(|| space.arch.unmaps(base..end));
+                if let Child::Phys(phys, ..) = child {
+                    PREEMPT.scope(|| {
+                        phys.unlend(&space, base);
+                        Scan::new(&space, base, end).discharge(&space, base, end);
+                        let _ = space.arch.unmaps(base..end);
+                    });
                 }
             }
         }
@@ -285,6 +601,12 @@ unsafe impl DefaultFeature for Weak<Virt> {
     }
 }
 
+impl KernelObject for Weak<Virt> {
+    fn event(_: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        None
+    }
+}
+
 impl PartialEq for Virt {
     fn eq(&self, other: &Self) -> bool {
         self.range == other.range && Weak::ptr_eq(&self.space, &other.space)
@@ -305,6 +627,145 @@ impl Ord for Virt {
     }
 }
 
+/// The pages of a mapping, scanned before it's unmapped.
+struct Scan {
+    /// The pages backed by the zero page.
+    zero: usize,
+    /// The pages of demand-paged mappings not populated yet.
+    unpopulated: usize,
+    /// The ranges of the pages mapped with committed ones, which are the only
+    /// ones pinned by the mapping.
+    pinned: Vec<Range<usize>>,
+}
+
+impl Scan {
+    fn new(space: &Space, base: LAddr, end: LAddr) -> Self {
+        let mut ret = Scan {
+            zero: 0,
+            unpopulated: 0,
+            pinned: Vec::new(),
+        };
+        let mut pinned = None;
+        for addr in (base.val()..end.val()).step_by(PAGE_SIZE) {
+            let committed = match space.arch.query(LAddr::from(addr)) {
+                Ok((paddr, _)) if paddr == zero_page() => {
+                    ret.zero += 1;
+                    false
+                }
+                Ok(_) => true,
+                Err(_) => {
+                    ret.unpopulated += 1;
+                    false
+                }
+            };
+            match (committed, pinned) {
+                (true, None) => pinned = Some(addr),
+                (false, Some(start)) => {
+                    ret.pinned.push(start..addr);
+                    pinned = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = pinned {
+            ret.pinned.push(start..end.val());
+        }
+        ret
+    }
+
+    /// Discharge the space of the mapping in `base..end`, whose pages not
+    /// backed by committed ones are lent.
+    fn discharge(&self, space: &Space, base: LAddr, end: LAddr) {
+        space.charge.phys.fetch_sub(end.val() - base.val(), Relaxed);
+        space
+            .charge
+            .lent
+            .fetch_sub(self.zero + self.unpopulated, Relaxed);
+    }
+}
+
+/// Remove the page table entries of a child that has been taken out of the
+/// child map.
+///
+/// Nothing is changed if it fails, so that the child can be put back.
+fn release(space: &Arc<Space>, base: LAddr, child: &Child) -> Result {
+    let end = child.end(base);
+    if let Child::Phys(phys, flags, offset, len) = child {
+        // Stop the shootdowns first, which would change the pages scanned.
+        phys.unlend(space, base);
+        let scan = Scan::new(space, base, end);
+        if let Err(err) = space.arch.unmaps(base..end) {
+            phys.lend(Lender {
+                space: Arc::downgrade(space),
+                base,
+                offset: *offset,
+                len: *len,
+                writable: flags.contains(Flags::WRITABLE),
+            });
+            return Err(paging_error(err));
+        }
+
+        scan.discharge(space, base, end);
+        if flags.contains(Flags::WRITABLE) {
+            zero_page_released(scan.zero, false);
+        }
+        // Unpinning the pages not pinned by the mapping would drop the pins of
+        // other mappings committing them later.
+        for range in scan.pinned {
+            phys.unpin(offset + (range.start - base.val()), range.end - range.start);
+        }
+    }
+    Ok(())
+}
+
+/// Undo [`PhysTrait::pin_lazy`] of `pages` from `offset` of `phys`, which are
+/// pinned except the ones lent from the zero page.
+fn unpin_lazy(phys: &Phys, offset: usize, pages: &[(PAddr, usize)], write: bool) {
+    let mut offset = offset;
+    let mut zero = 0;
+    for &(base, len) in pages {
+        if base == zero_page() {
+            zero += len >> PAGE_SHIFT;
+        } else {
+            phys.unpin(offset, len);
+        }
+        offset += len;
+    }
+    if write {
+        zero_page_released(zero, false);
+    }
+}
+
+/// Remove the mappings and the reservations in `request` for a replacing
+/// mapping, as checked by [`check_replace`].
+///
+/// If a mapping fails to be released, it's put back along with the ones not
+/// released yet, and the ranges of the ones already released are reserved
+/// instead of becoming gaps.
+fn replace_range(
+    space: &Arc<Space>,
+    map: &mut ChildMap,
+    request: &Range<LAddr>,
+    vdso: Option<LAddr>,
+) -> Result {
+    check_replace(map, request, vdso)?;
+    let mut children = take_range(map, request).into_iter();
+    let mut released = Vec::new();
+    while let Some((base, child)) = children.next() {
+        if let Err(err) = release(space, base, &child) {
+            let reserved = released
+                .into_iter()
+                .map(|(base, len)| (base, Child::Reserved(len)));
+            map.extend(reserved);
+            let _ = map.insert(base, child);
+            map.extend(children);
+            return Err(err);
+        }
+        released.push((base, child.len()));
+    }
+    Ok(())
+}
+
 fn check_layout(layout: Layout) -> Result<Layout> {
     if layout.size() == 0 {
         return Err(ERANGE);
@@ -327,47 +788,128 @@ fn check_vdso(vdso: Option<LAddr>, base: LAddr, end: LAddr) -> bool {
     }
 }
 
+/// Find the range of a new child, keeping `guard` bytes away from the other
+/// children if it's placed automatically.
 fn find_range(
     map: &BTreeMap<LAddr, Child>,
     range: &Range<LAddr>,
     offset: Option<usize>,
     layout: Layout,
+    guard: usize,
 ) -> Result<Range<LAddr>> {
-    let base = match offset {
+    match offset {
         Some(offset) => {
-            let base = LAddr::from({ range.start.val() }.checked_add(offset).ok_or(ERANGE)?);
-            let end = LAddr::from(base.val().checked_add(layout.size()).ok_or(ERANGE)?);
-            if base.val().contains_bit(PAGE_SHIFT) {
-                return Err(EALIGN);
-            }
-            if !(range.start <= base && end <= range.end) {
-                return Err(ERANGE);
-            }
-            if !check_alloc(map, base..end) {
+            let ret = fixed_range(range, offset, layout)?;
+            if !check_alloc(map, ret.clone()) {
                 return Err(EEXIST);
             }
-            base
+            Ok(ret)
         }
-        None => find_alloc(map, range, layout).ok_or(ENOMEM)?,
-    };
+        None => {
+            let base = find_alloc(map, range, layout, guard).ok_or(ENOMEM)?;
+            Ok(base..LAddr::from(base.val() + layout.size()))
+        }
+    }
+}
+
+/// Get the range of a new child at `offset`, regardless of the other children.
+fn fixed_range(range: &Range<LAddr>, offset: usize, layout: Layout) -> Result<Range<LAddr>> {
+    let base = LAddr::from({ range.start.val() }.checked_add(offset).ok_or(ERANGE)?);
+    let end = LAddr::from(base.val().checked_add(layout.size()).ok_or(ERANGE)?);
+    if base.val().contains_bit(PAGE_SHIFT) {
+        return Err(EALIGN);
+    }
+    if !(range.start <= base && end <= range.end) {
+        return Err(ERANGE);
+    }
+    Ok(base..end)
+}
+
+/// Check if the children overlapping `request` can be replaced, that is, only
+/// the mappings within it and the reservations.
+fn check_replace(map: &ChildMap, request: &Range<LAddr>, vdso: Option<LAddr>) -> Result {
+    for (&base, child) in { map.range(..request.end).rev() }
+        .take_while(|(&base, child)| request.start < child.end(base))
+    {
+        let end = child.end(base);
+        match child {
+            Child::Reserved(_) => {}
+            Child::Virt(_) => return Err(EPERM),
+            Child::Phys(..) => {
+                if !(request.start <= base && end <= request.end) {
+                    return Err(ERANGE);
+                }
+                if !check_vdso(vdso, base, end) {
+                    return Err(EACCES);
+                }
+            }
+        }
+    }
+    Ok(())
+}
 
-    Ok(base..LAddr::from(base.val() + layout.size()))
+/// Take the children within `request` out of the map, cutting the
+/// reservations across its bounds.
+fn take_range(map: &mut ChildMap, request: &Range<LAddr>) -> ChildMap {
+    if let Some((&base, &Child::Reserved(len))) = map.range(..request.start).next_back() {
+        let end = base.val() + len;
+        if end > request.start.val() {
+            let _ = map.insert(base, Child::Reserved(request.start.val() - base.val()));
+            let _ = map.insert(request.start, Child::Reserved(end - request.start.val()));
+        }
+    }
+    let mut mid = map.split_off(&request.start);
+    let mut suffix = mid.split_off(&request.end);
+    if let Some((&base, &Child::Reserved(len))) = mid.iter().next_back() {
+        let end = base.val() + len;
+        if end > request.end.val() {
+            let _ = mid.insert(base, Child::Reserved(request.end.val() - base.val()));
+            let _ = suffix.insert(request.end, Child::Reserved(end - request.end.val()));
+        }
+    }
+    map.append(&mut suffix);
+    mid
 }
 
+/// Check if `request` overlaps no child, or lies within a reservation.
 fn check_alloc(map: &ChildMap, request: Range<LAddr>) -> bool {
-    let prev = map.range(..request.end).next();
-    !matches!(prev, Some((&base, prev)) if prev.end(base) > request.start)
+    match map.range(..request.end).next_back() {
+        Some((&base, prev)) if prev.end(base) > request.start => {
+            matches!(prev, Child::Reserved(_))
+                && base <= request.start
+                && request.end <= prev.end(base)
+        }
+        _ => true,
+    }
+}
+
+/// Take the range of a new child out of the reservation it lies within, if
+/// any.
+fn carve(map: &mut ChildMap, request: &Range<LAddr>) {
+    let base = match map.range(..request.end).next_back() {
+        Some((&base, Child::Reserved(len))) if base.val() + len > request.start.val() => base,
+        _ => return,
+    };
+    let end = map.remove(&base).unwrap().end(base);
+    if base < request.start {
+        let len = request.start.val() - base.val();
+        let _ = map.insert(base, Child::Reserved(len));
+    }
+    if request.end < end {
+        let len = end.val() - request.end.val();
+        let _ = map.insert(request.end, Child::Reserved(len));
+    }
 }
 
 #[inline]
-fn find_alloc(map: &ChildMap, range: &Range<LAddr>, layout: Layout) -> Option<LAddr> {
+fn find_alloc(map: &ChildMap, range: &Range<LAddr>, layout: Layout, guard: usize) -> Option<LAddr> {
     #[cfg(debug_assertions)]
     const ASLR_BIT: usize = 1;
     #[cfg(not(debug_assertions))]
     const ASLR_BIT: usize = 35;
     let mask = (1 << ASLR_BIT) - 1;
-    let (ret, cnt) = try_find_alloc(map, range, layout, rand() & mask);
-    ret.or_else(|| try_find_alloc(map, range, layout, rand() % cnt).0)
+    let (ret, cnt) = try_find_alloc(map, range, layout, guard, rand() & mask);
+    ret.or_else(|| try_find_alloc(map, range, layout, guard, rand() % cnt).0)
 }
 
 #[inline]
@@ -379,15 +921,23 @@ fn try_find_alloc(
     map: &ChildMap,
     range: &Range<LAddr>,
     layout: Layout,
+    guard: usize,
     mut rand_n: usize,
 ) -> (Option<LAddr>, usize) {
     let mut cnt = 0;
     let bit = layout.align().msb();
     let ret = gaps(map, range, |gap| {
-        let (base, end) = (gap.start.val(), gap.end.val());
+        let (mut base, mut end) = (gap.start.val(), gap.end.val());
+        // Only the sides next to other children are guarded.
+        if gap.start != range.start {
+            base = base.checked_add(guard)?;
+        }
+        if gap.end != range.end {
+            end = end.checked_sub(guard)?;
+        }
         let base = base.round_up_bit(bit);
         let end = end.round_down_bit(bit);
-        if layout.size() <= end - base {
+        if base <= end && layout.size() <= end - base {
             let n = ((end - base - layout.size()) >> bit) + 1;
             cnt += n;
             if rand_n < n {
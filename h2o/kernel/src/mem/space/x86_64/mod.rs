@@ -3,6 +3,8 @@
 //! This module is specific for x86_64 mode. It wraps the cr3's root page table
 //! and the methods of x86_64 paging.
 
+mod pcid;
+
 use alloc::{alloc::Global, boxed::Box};
 use core::{alloc::Allocator, ops::Range};
 
@@ -12,8 +14,12 @@ use minfo::KERNEL_ALLOCABLE_RANGE;
 use paging::{Attr, LAddr, Level, PAddr, Table};
 use spin::Mutex;
 
+pub use self::pcid::{enable as enable_pcid, flush_all as flush_tlb};
 use super::Flags;
-use crate::sched::{task::ctx::x86_64::Frame, SCHED};
+use crate::sched::{
+    task::{self, ctx::x86_64::Frame},
+    SCHED,
+};
 
 /// The root page table at initialization time.
 static KERNEL_ROOT: Azy<(Box<Table>, u64)> = Azy::new(|| {
@@ -60,6 +66,7 @@ pub struct Space {
     canary: Canary<Space>,
     root_table: Mutex<Box<Table>>,
     cr3: PAddr,
+    pcid: pcid::Tag,
 }
 
 impl Space {
@@ -71,6 +78,7 @@ impl Space {
             .user_access(flags.contains(Flags::USER_ACCESS))
             .executable(flags.contains(Flags::EXECUTABLE))
             .cache(uncached, uncached)
+            .write_combining(flags.contains(Flags::WRITE_COMBINING))
             .build()
     }
 
@@ -87,6 +95,8 @@ impl Space {
         }
         if attr.contains(paging::Attr::CACHE_DISABLE) {
             flags |= Flags::UNCACHED;
+        } else if attr.contains(paging::Attr::WRITE_THRU) {
+            flags |= Flags::WRITE_COMBINING;
         }
         flags
     }
@@ -95,7 +105,7 @@ impl Space {
     ///
     /// The space's root page table must contains the page tables of the kernel
     /// half otherwise if loaded the kernel will crash due to #PF.
-    pub fn new() -> Space {
+    pub fn new(ty: task::Type) -> Space {
         let rt = box Table::zeroed();
         let cr3 = Box::into_raw(rt);
 
@@ -103,6 +113,7 @@ impl Space {
             canary: Canary::new(),
             root_table: Mutex::new(unsafe { Box::from_raw(cr3) }),
             cr3: LAddr::new(cr3.cast()).to_paddr(minfo::ID_OFFSET),
+            pcid: pcid::Tag::new(ty == task::Type::Kernel),
         };
 
         {
@@ -136,6 +147,23 @@ impl Space {
         paging::maps(&mut self.root_table.lock(), &map_info, &mut PageAlloc)
     }
 
+    /// Whether the top-level entries of `virt` are present.
+    ///
+    /// The kernel half of the top-level table is copied into every space on
+    /// its creation, so only mappings under present entries are shared.
+    pub(in crate::mem) fn has_top_level(&self, virt: Range<LAddr>) -> bool {
+        if virt.start >= virt.end {
+            return true;
+        }
+        let start = Level::P4.addr_idx(virt.start, false);
+        let end = Level::P4.addr_idx(LAddr::from(virt.end.val() - 1), false);
+
+        let root = self.root_table.lock();
+        root[start..=end]
+            .iter()
+            .all(|ent| ent.get(Level::P4).1.contains(Attr::PRESENT))
+    }
+
     pub(in crate::mem) fn reprotect(
         &self,
         virt: Range<LAddr>,
@@ -149,7 +177,25 @@ impl Space {
             id_off: minfo::ID_OFFSET,
         };
 
-        paging::reprotect(&mut self.root_table.lock(), &reprotect_info, &mut PageAlloc)
+        paging::reprotect(&mut self.root_table.lock(), &reprotect_info, &mut PageAlloc)?;
+        crate::cpu::arch::apic::ipi::tlb_shootdown();
+        Ok(())
+    }
+
+    /// Harvest the accessed and dirty bits of `virt`, shooting down the TLB
+    /// entries of the cleared pages on all the CPUs.
+    pub(in crate::mem) fn harvest(
+        &self,
+        virt: Range<LAddr>,
+        clear: Attr,
+    ) -> Result<paging::Harvest, paging::Error> {
+        self.canary.assert();
+
+        let ret = paging::harvest(&mut self.root_table.lock(), virt, minfo::ID_OFFSET, clear)?;
+        if !clear.is_empty() {
+            crate::cpu::arch::apic::ipi::tlb_shootdown();
+        }
+        Ok(ret)
     }
 
     #[allow(dead_code)]
@@ -166,11 +212,17 @@ impl Space {
     ) -> Result<Option<PAddr>, paging::Error> {
         self.canary.assert();
 
-        let mut lck = self.root_table.lock();
-        let phys = paging::query(&lck, virt.start, minfo::ID_OFFSET)
-            .ok()
-            .map(|(phys, _)| phys);
-        paging::unmaps(&mut lck, virt, minfo::ID_OFFSET, &mut PageAlloc).map(|_| phys)
+        let phys = {
+            let mut lck = self.root_table.lock();
+            let phys = paging::query(&lck, virt.start, minfo::ID_OFFSET)
+                .ok()
+                .map(|(phys, _)| phys);
+            paging::unmaps(&mut lck, virt, minfo::ID_OFFSET, &mut PageAlloc)?;
+            phys
+        };
+        // Other CPUs may still cache the stale entries.
+        crate::cpu::arch::apic::ipi::tlb_shootdown();
+        Ok(phys)
     }
 
     /// # Safety
@@ -178,7 +230,7 @@ impl Space {
     /// The caller must ensure that loading the space is safe and not cause any
     /// #PF.
     pub(in crate::mem) unsafe fn load(&self) {
-        archop::reg::cr3::write(*self.cr3 as u64);
+        archop::reg::cr3::write(pcid::cr3(*self.cr3 as u64, &self.pcid));
     }
 }
 
@@ -232,6 +284,26 @@ pub unsafe fn page_fault(frame: &mut Frame, errc: u64) -> bool {
     match ErrCode::from_bits(errc) {
         // So far neither has been supported.
         Some(code) if !code.contains(ErrCode::PROT_KEY | ErrCode::SHADOW_STACK) => {
+            if (minfo::USER_BASE..minfo::USER_END).contains(&(addr as usize)) {
+                let addr = LAddr::from(addr as usize);
+                let write = code.contains(ErrCode::WRITE);
+                let res = super::with_current(|space| {
+                    if !code.contains(ErrCode::PRESENT) {
+                        // Accessing a page of a demand-paged mapping first, or one
+                        // shot down from the zero page.
+                        space.root.populate(addr, write)
+                    } else if write {
+                        // Writing to a user page backed by the zero page.
+                        space.root.resolve_cow(addr)
+                    } else {
+                        Err(sv_call::EPERM)
+                    }
+                });
+                if res.is_ok() {
+                    return true;
+                }
+            }
+
             if SCHED
                 .with_current(|cur| cur.kstack_mut().pf_resume(frame, errc, addr))
                 .is_ok()
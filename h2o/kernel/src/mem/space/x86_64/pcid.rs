@@ -0,0 +1,155 @@
+//! Process-context identifiers (PCIDs) of the memory spaces.
+//!
+//! With PCIDs, the TLB entries are tagged with the PCID of the space that
+//! loaded them, so switching spaces doesn't need to flush the TLB. PCID 0 is
+//! kept by the kernel space, which is flushed every time it's loaded. The
+//! other PCIDs are allocated to user spaces on their first load, along with
+//! the generation of the allocation.
+//!
+//! When the PCIDs run out, the generation rolls over and the allocation starts
+//! over. A CPU flushes all of its TLB entries before loading a space of a newer
+//! generation, so the entries left by the previous owners of the PCIDs are
+//! never used, and a space of an older generation gets a new PCID on its next
+//! load.
+//!
+//! The kernel half of the mappings is shared by all the spaces, but tagged
+//! separately in each of them, so TLB shootdowns flush the entries of all the
+//! PCIDs instead of only the current one.
+
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicU64, Ordering::*},
+};
+
+use spin::Mutex;
+
+const NR_PCID: u64 = 1 << 12;
+const PCID_MASK: u64 = NR_PCID - 1;
+/// Keeps the TLB entries of the PCID loaded into CR3.
+const NO_FLUSH: u64 = 1 << 63;
+
+/// The tag of the kernel space, which always uses PCID 0.
+const KERNEL: u64 = 0;
+/// The tag of a user space without a PCID, older than any generation.
+const UNTAGGED: u64 = NR_PCID;
+
+/// Flushes the entries of all the PCIDs but the global ones.
+const INVPCID_ALL_NON_GLOBAL: u64 = 3;
+
+struct Alloc {
+    generation: u64,
+    next: u64,
+}
+
+static ALLOC: Mutex<Alloc> = Mutex::new(Alloc {
+    generation: 2,
+    next: 1,
+});
+/// The current generation, mirrored from [`ALLOC`] for the fast path.
+static GENERATION: AtomicU64 = AtomicU64::new(2);
+
+/// The generation of the PCIDs loaded on the current CPU.
+#[thread_local]
+static mut CPU_GENERATION: u64 = 0;
+
+/// The PCID of a space and its generation, packed as `generation << 12 |
+/// pcid`.
+#[derive(Debug)]
+pub struct Tag(AtomicU64);
+
+impl Tag {
+    pub fn new(kernel: bool) -> Self {
+        Tag(AtomicU64::new(if kernel { KERNEL } else { UNTAGGED }))
+    }
+}
+
+#[inline]
+pub fn enabled() -> bool {
+    archop::reg::has_pcid()
+}
+
+/// Enable PCIDs on the current CPU if they're supported, keeping the current
+/// space loaded.
+///
+/// # Safety
+///
+/// The caller must ensure that this function is only called during the
+/// initialization or the resumption of the current CPU.
+pub unsafe fn enable() {
+    if !enabled() {
+        return;
+    }
+    use archop::reg::{cr3, cr4};
+    let cr3 = cr3::read();
+    // CR4.PCIDE can only be set with PCID 0 loaded.
+    cr3::write(cr3 & !PCID_MASK);
+    cr4::set(cr4::PCIDE);
+    cr3::write(cr3);
+}
+
+/// Flush the TLB entries of all the spaces on the current CPU.
+///
+/// # Safety
+///
+/// The caller must ensure that the current CPU is not migrated in the call.
+pub unsafe fn flush_all() {
+    if enabled() {
+        let desc = [0u64; 2];
+        asm!(
+            "invpcid {}, [{}]",
+            in(reg) INVPCID_ALL_NON_GLOBAL,
+            in(reg) desc.as_ptr(),
+            options(nostack)
+        );
+    } else {
+        // Reloading CR3 flushes all the non-global entries, which is all of
+        // them since we don't use global pages.
+        archop::reg::cr3::write(archop::reg::cr3::read());
+    }
+}
+
+/// Get the value of CR3 that loads the root table `root` with the PCID of
+/// `tag`, allocating one if necessary.
+///
+/// # Safety
+///
+/// The caller must ensure that the returned value is loaded into CR3 at once
+/// on the current CPU with the preemption disabled.
+pub unsafe fn cr3(root: u64, tag: &Tag) -> u64 {
+    if !enabled() {
+        return root;
+    }
+    let raw = tag.0.load(Acquire);
+    if raw == KERNEL {
+        return root;
+    }
+
+    let generation = GENERATION.load(Acquire);
+    if raw >> 12 == generation && CPU_GENERATION == generation {
+        return root | (raw & PCID_MASK) | NO_FLUSH;
+    }
+    slow_path(root, tag)
+}
+
+#[cold]
+unsafe fn slow_path(root: u64, tag: &Tag) -> u64 {
+    let mut alloc = ALLOC.lock();
+    let mut raw = tag.0.load(Acquire);
+    if raw >> 12 != alloc.generation {
+        if alloc.next == NR_PCID {
+            alloc.generation += 1;
+            alloc.next = 1;
+            GENERATION.store(alloc.generation, Release);
+            log::trace!("PCID generation rolled over to {}", alloc.generation);
+        }
+        raw = alloc.generation << 12 | alloc.next;
+        alloc.next += 1;
+        tag.0.store(raw, Release);
+    }
+    if CPU_GENERATION != alloc.generation {
+        CPU_GENERATION = alloc.generation;
+        drop(alloc);
+        flush_all();
+    }
+    root | (raw & PCID_MASK) | NO_FLUSH
+}
@@ -7,7 +7,7 @@ use core::{alloc::Layout, ptr};
 use bitop_ex::BitOpEx;
 use paging::LAddr;
 use sv_call::{
-    mem::{Flags, IoVec, MemInfo, PhysOptions, VirtMapInfo},
+    mem::{Flags, IoVec, MemInfo, MemRange, MemStat, PhysOptions, VirtMapInfo},
     *,
 };
 
@@ -29,11 +29,19 @@ fn check_flags(flags: Flags) -> Result<Flags> {
     if !flags.contains(Flags::USER_ACCESS) {
         return Err(EPERM);
     }
+    if flags.contains(Flags::UNCACHED | Flags::WRITE_COMBINING) {
+        return Err(EINVAL);
+    }
     Ok(flags)
 }
 
 fn features_to_flags(feat: Feature) -> Flags {
-    let mut flags = Flags::USER_ACCESS;
+    // The cache attributes and the placement don't require any feature.
+    let mut flags = Flags::USER_ACCESS
+        | Flags::UNCACHED
+        | Flags::WRITE_COMBINING
+        | Flags::NO_GUARD
+        | Flags::REPLACE;
     if feat.contains(Feature::READ) {
         flags |= Flags::READABLE;
     }
@@ -48,11 +56,9 @@ fn features_to_flags(feat: Feature) -> Flags {
 
 #[syscall]
 fn phys_alloc(size: usize, options: PhysOptions) -> Result<Handle> {
+    crate::fault::check(crate::fault::Point::Alloc)?;
     let phys = PREEMPT.scope(|| space::allocate_phys(size, options, false))?;
-    SCHED.with_current(|cur| {
-        let event = phys.event();
-        cur.space().handles().insert_raw(phys, Some(event))
-    })
+    SCHED.with_current(|cur| cur.space().handles().insert_raw(phys))
 }
 
 #[syscall]
@@ -175,12 +181,13 @@ fn phys_sub(hdl: Handle, offset: usize, len: usize, copy: bool) -> Result<Handle
     }
 
     let sub = phys.create_sub(offset, len, copy)?;
+    space::leak::record_phys(&sub);
     SCHED.with_current(|cur| {
         let handles = cur.space().handles();
-        let event = sub.event();
         if copy {
-            handles.insert_raw(sub, Some(event))
+            handles.insert_raw(sub)
         } else {
+            let event = sub.event();
             unsafe { handles.insert_raw_unchecked(sub, feat, Some(event)) }
         }
     })
@@ -212,7 +219,7 @@ fn space_new(root_virt: UserPtr<Out, Handle>) -> Result<Handle> {
     SCHED.with_current(|cur| {
         let space = TaskSpace::new()?;
         let virt = Arc::downgrade(space.mem().root());
-        let ret = cur.space().handles().insert_raw(space, None)?;
+        let ret = cur.space().handles().insert_raw(space)?;
         let virt = unsafe {
             cur.space().handles().insert_unchecked(
                 virt,
@@ -228,6 +235,7 @@ fn space_new(root_virt: UserPtr<Out, Handle>) -> Result<Handle> {
 #[syscall]
 fn virt_alloc(hdl: Handle, offset: usize, size: usize, align: usize) -> Result<Handle> {
     hdl.check_null()?;
+    crate::fault::check(crate::fault::Point::Alloc)?;
     SCHED.with_current(|cur| {
         let virt_obj = cur.space().handles().get::<Weak<space::Virt>>(hdl)?;
         let virt = virt_obj.upgrade().ok_or(EKILLED)?;
@@ -236,7 +244,21 @@ fn virt_alloc(hdl: Handle, offset: usize, size: usize, align: usize) -> Result<H
             (offset != usize::MAX).then_some(offset),
             Layout::from_size_align(size, align)?,
         )?;
-        cur.space().handles().insert(sub, None)
+        cur.space().handles().insert(sub)
+    })
+}
+
+#[syscall]
+fn virt_reserve(hdl: Handle, offset: usize, size: usize, align: usize) -> Result<*mut u8> {
+    hdl.check_null()?;
+    SCHED.with_current(|cur| {
+        let virt = cur.space().handles().get::<Weak<space::Virt>>(hdl)?;
+        let virt = virt.upgrade().ok_or(EKILLED)?;
+        let base = virt.reserve(
+            (offset != usize::MAX).then_some(offset),
+            Layout::from_size_align(size, align)?,
+        )?;
+        Ok(*base)
     })
 }
 
@@ -324,6 +346,55 @@ fn mem_info(info: UserPtr<Out, MemInfo>) -> Result {
     })
 }
 
+/// Sample the usage of the pages of `space` and the memory charged to it, or
+/// of the current space if it's null.
+#[syscall]
+fn mem_stat(space: Handle, stat: UserPtr<Out, MemStat>) -> Result {
+    stat.check()?;
+    let space = SCHED.with_current(|cur| {
+        if space.is_null() {
+            return Ok(Arc::clone(cur.space().mem()));
+        }
+        let space = cur.space().handles().get::<TaskSpace>(space)?;
+        if !space.features().contains(Feature::READ) {
+            return Err(EPERM);
+        }
+        Ok(Arc::clone(space.mem()))
+    })?;
+    stat.write(space.stat()?)
+}
+
+#[syscall]
+fn mem_map(ranges: UserPtr<Out, MemRange>, len: usize) -> Result<usize> {
+    ranges.check_slice(len)?;
+    let data = super::hotplug::ranges();
+    ranges.write_slice(&data[..len.min(data.len())])?;
+    Ok(data.len())
+}
+
+#[syscall]
+fn mem_hot_add(res: Handle, base: usize, size: usize) -> Result {
+    let end = base.checked_add(size).ok_or(ERANGE)?;
+    SCHED.with_current(|cur| {
+        let res = cur.space().handles().get::<Resource<usize>>(res)?;
+        res.check(super::mem_resource(), base..end)
+    })?;
+    super::hotplug::hot_add(base..end)
+}
+
+/// Dump the memory objects that are alive but no longer in use to the kernel
+/// log, for hunting memory leaks, along with the faults of the user accesses.
+#[syscall]
+fn mem_inspect(res: Handle) -> Result {
+    SCHED.with_current(|cur| {
+        let res = cur.space().handles().get::<Resource<usize>>(res)?;
+        res.check_root(super::mem_resource())
+    })?;
+    space::leak::dump();
+    crate::syscall::dump_faults();
+    Ok(())
+}
+
 #[syscall]
 fn phys_acq(res: Handle, addr: usize, size: usize) -> Result<Handle> {
     if addr.contains_bit(paging::PAGE_MASK) || size.contains_bit(paging::PAGE_MASK) {
@@ -339,14 +410,18 @@ fn phys_acq(res: Handle, addr: usize, size: usize) -> Result<Handle> {
         if addr == 0 {
             drop(res);
             let phys = space::allocate_phys(size, PhysOptions::ZEROED, true)?;
-            return unsafe { cur.space().handles().insert_raw(phys, None) };
+            return cur.space().handles().insert_raw(phys);
         }
 
-        if !(res.range().start <= addr && addr + size <= res.range().end) {
-            return Err(EPERM);
-        }
+        let end = addr.checked_add(size).ok_or(ERANGE)?;
+        res.check(super::mem_resource(), addr..end)?;
         drop(res);
-        let phys = space::new_phys(paging::PAddr::new(addr), size)?;
-        unsafe { cur.space().handles().insert_raw(phys, None) }
+        let base = paging::PAddr::new(addr);
+        let phys = if super::overlaps_ram(addr..addr + size) {
+            space::new_phys(base, size)?
+        } else {
+            space::new_mmio(base, size)?
+        };
+        cur.space().handles().insert_raw(phys)
     })
 }
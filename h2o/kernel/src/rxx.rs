@@ -1,6 +1,18 @@
+use core::sync::atomic::{AtomicBool, Ordering::SeqCst};
+
 #[panic_handler]
 fn panic_handler(info: &core::panic::PanicInfo) -> ! {
+    static PANICKED: AtomicBool = AtomicBool::new(false);
+
+    crate::logger::earlycon::reclaim();
     log::error!("CPU #{} {}", unsafe { crate::cpu::id() }, info);
+    // Don't touch the scheduler again if it panics itself while reporting.
+    if !PANICKED.swap(true, SeqCst) {
+        let _ = crate::sched::SCHED.with_current(|cur| {
+            log::error!("Current task: {} {:?}", cur.tid().raw(), cur.tid().name());
+            Ok(())
+        });
+    }
     unsafe { archop::halt_loop(Some(true)) }
 }
 
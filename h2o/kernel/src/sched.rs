@@ -3,10 +3,10 @@ pub mod ipc;
 pub mod task;
 pub mod wait;
 
-pub use self::imp::{deque, epoch};
+pub use self::imp::{account, defer, deque, epoch, rcu};
 pub(crate) use self::{
     imp::{
-        task_migrate_handler,
+        dump, task_migrate_handler,
         waiter::{Blocker, Dispatcher},
         PREEMPT, SCHED,
     },
@@ -16,4 +16,5 @@ pub(crate) use self::{
 #[inline]
 pub fn init() {
     task::init();
+    defer::init();
 }
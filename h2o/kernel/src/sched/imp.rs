@@ -1,11 +1,15 @@
+pub mod account;
+pub mod defer;
 pub mod deque;
 pub mod epoch;
+pub mod rcu;
 pub mod waiter;
 
 use alloc::vec::Vec;
 use core::{
     assert_matches::assert_matches,
     cell::UnsafeCell,
+    cmp::Ordering,
     hint,
     sync::atomic::{AtomicU64, Ordering::*},
     time::Duration,
@@ -38,6 +42,8 @@ pub static SCHED: Lazy<Scheduler> = Lazy::new(|| Scheduler {
     cpu: unsafe { crate::cpu::id() },
     current: UnsafeCell::new(None),
     run_queue: Worker::new_fifo(),
+    yield_to: UnsafeCell::new(None),
+    handoff: UnsafeCell::new(None),
 });
 
 #[thread_local]
@@ -60,6 +66,12 @@ pub struct Scheduler {
     cpu: usize,
     run_queue: Worker<task::Ready>,
     current: UnsafeCell<Option<task::Ready>>,
+    /// The raw TID of the task the current task yields to, set by
+    /// [`Scheduler::yield_to`].
+    yield_to: UnsafeCell<Option<u64>>,
+    /// The task to run right after the current one, taking precedence over
+    /// the run queue.
+    handoff: UnsafeCell<Option<task::Ready>>,
 }
 
 impl Scheduler {
@@ -68,10 +80,20 @@ impl Scheduler {
 
         let time_slice = MIN_TIME_GRAN;
         let affinity = task.affinity();
-        let cpu = select_cpu(&affinity, self.cpu, task.last_cpu()).expect("Zero affinity");
+        // Keep the target of a directed yield local so it can be handed off.
+        let cpu = if self.is_yielded_to(task.tid().raw()) && affinity[self.cpu] {
+            self.cpu
+        } else {
+            select_cpu(&affinity, self.cpu, task.last_cpu()).expect("Zero affinity")
+        };
         let task = task::IntoReady::into_ready(task, cpu, time_slice);
 
-        log::trace!("Unblocking task {:?}, P{}", task.tid.raw(), PREEMPT.raw());
+        log::trace!(
+            "Unblocking task {:?} {:?}, P{}",
+            task.tid.raw(),
+            task.tid.name(),
+            PREEMPT.raw()
+        );
         if cpu == self.cpu {
             self.enqueue(task, PREEMPT.lock(), preempt);
         } else {
@@ -84,12 +106,32 @@ impl Scheduler {
         SCHED_INFO[self.cpu]
             .expected_runtime
             .fetch_add(task.time_slice.as_micros() as u64, Release);
+
+        // SAFETY: We have `pree`, which means preemption is disabled.
+        let yield_to = unsafe { &mut *self.yield_to.get() };
+        if *yield_to == Some(task.tid.raw()) {
+            log::trace!(
+                "Handing off to task {:?} {:?}, P{}",
+                task.tid.raw(),
+                task.tid.name(),
+                PREEMPT.raw(),
+            );
+            *yield_to = None;
+            // SAFETY: We have `pree`, which means preemption is disabled.
+            let handoff = unsafe { &mut *self.handoff.get() };
+            if let Some(prev) = handoff.replace(task) {
+                self.run_queue.push(prev);
+            }
+            return;
+        }
+
         // SAFETY: We have `pree`, which means preemption is disabled.
         match unsafe { &*self.current.get() } {
             Some(ref cur) if preempt && Self::should_preempt(cur, &task) => {
                 log::trace!(
-                    "Preempting to task {:?}, P{}",
+                    "Preempting to task {:?} {:?}, P{}",
                     task.tid.raw(),
+                    task.tid.name(),
                     PREEMPT.raw(),
                 );
                 let _ = self.schedule_impl(Instant::now(), pree, Some(task), |mut task| {
@@ -123,11 +165,35 @@ impl Scheduler {
         self.current.get()
     }
 
+    /// Hint that the current task is going to wait for the task of `tid`, so
+    /// that the latter runs right after the former on this CPU if it's woken
+    /// up before the former is switched out.
+    ///
+    /// The hint is dropped when the current task is switched out, or replaced
+    /// by another one.
+    pub fn yield_to(&self, tid: u64) {
+        self.canary.assert();
+        // SAFETY: Preemption is disabled in the scope.
+        PREEMPT.scope(|| unsafe { *self.yield_to.get() = Some(tid) });
+    }
+
+    #[inline]
+    fn is_yielded_to(&self, tid: u64) -> bool {
+        // SAFETY: Preemption is disabled in the scope.
+        PREEMPT.scope(|| unsafe { *self.yield_to.get() == Some(tid) })
+    }
+
+    /// Block the current task until it's woken up or `duration` elapses.
+    ///
+    /// An `interruptible` wait fails with `EINTR` if the task has a pending
+    /// signal, and is woken up early if a signal is raised during the wait,
+    /// which is reported by [`Timer::is_interrupted`].
     pub fn block_current<T>(
         &self,
         guard: T,
         wq: Option<&SegQueue<Arsc<Timer>>>,
         duration: Duration,
+        interruptible: bool,
         block_desc: &'static str,
     ) -> sv_call::Result<Arsc<Timer>> {
         self.canary.assert();
@@ -135,13 +201,24 @@ impl Scheduler {
         let pree = PREEMPT.lock();
 
         // SAFETY: We have `pree`, which means preemption is disabled.
-        log::trace!(
-            "Blocking task {:?}, P{}",
-            unsafe { &*self.current.get() }.as_ref().unwrap().tid.raw(),
-            PREEMPT.raw(),
-        );
+        let tid = match unsafe { &*self.current.get() } {
+            Some(cur) if interruptible => {
+                if cur.tid.with_signal(|sig| sig.is_some()) {
+                    return Err(sv_call::EINTR);
+                }
+                Some(task::Tid::clone(&cur.tid))
+            }
+            _ => None,
+        };
 
+        // SAFETY: We have `pree`, which means preemption is disabled.
         if let Some(current) = unsafe { &*self.current() } {
+            log::trace!(
+                "Blocking task {:?} {:?}, P{}",
+                current.tid.raw(),
+                current.tid.name(),
+                PREEMPT.raw(),
+            );
             SCHED_INFO[self.cpu]
                 .expected_runtime
                 .fetch_sub(current.time_slice.as_micros() as u64, Release);
@@ -154,13 +231,20 @@ impl Scheduler {
                 wq.push(Arsc::clone(&timer));
             }
             drop(guard);
+            if let Some(tid) = tid {
+                tid.set_blocker(&timer);
+            }
             Ok(timer)
         })
     }
 
     #[inline]
     fn should_preempt(cur: &task::Ready, task: &task::Ready) -> bool {
-        cur.runtime > task.runtime + WAKE_TIME_GRAN
+        match task.tid.priority().cmp(&cur.tid.priority()) {
+            Ordering::Greater => true,
+            Ordering::Less => false,
+            Ordering::Equal => cur.runtime > task.runtime + WAKE_TIME_GRAN,
+        }
     }
 
     /// # Panics
@@ -171,13 +255,13 @@ impl Scheduler {
         let pree = PREEMPT.lock();
 
         // SAFETY: We have `pree`, which means preemption is disabled.
-        log::trace!(
-            "Exiting task {:?}, P{}",
-            unsafe { &*self.current.get() }.as_ref().unwrap().tid.raw(),
-            PREEMPT.raw(),
-        );
-
         if let Some(current) = unsafe { &*self.current() } {
+            log::trace!(
+                "Exiting task {:?} {:?}, P{}",
+                current.tid.raw(),
+                current.tid.name(),
+                PREEMPT.raw(),
+            );
             SCHED_INFO[self.cpu]
                 .expected_runtime
                 .fetch_sub(current.time_slice.as_micros() as u64, Release);
@@ -204,6 +288,7 @@ impl Scheduler {
                 PREEMPT.lock()
             }
         };
+        rcu::quiesce(&pree);
 
         if unsafe { self.update(cur_time) } {
             let ret = self.schedule(cur_time, pree);
@@ -214,6 +299,12 @@ impl Scheduler {
         }
     }
 
+    /// Handle the pending signal of the current task, which blocks it until
+    /// it's resumed if suspended, or never returns if killed.
+    pub fn handle_signal(&self) {
+        let _ = self.check_signal(Instant::now(), PREEMPT.lock());
+    }
+
     fn check_signal<'a>(
         &'a self,
         cur_time: Instant,
@@ -228,8 +319,9 @@ impl Scheduler {
 
         if cur.space().has_to_stop() {
             log::trace!(
-                "Killing task {:?}, P{} due to main task stopped",
+                "Killing task {:?} {:?}, P{} due to main task stopped",
                 cur.tid.raw(),
+                cur.tid.name(),
                 PREEMPT.raw()
             );
 
@@ -244,19 +336,29 @@ impl Scheduler {
 
         match ti.with_signal(|sig| sig.take()) {
             Some(task::Signal::Kill) => {
-                log::trace!("Killing task {:?}, P{}", cur.tid.raw(), PREEMPT.raw());
+                log::trace!(
+                    "Killing task {:?} {:?}, P{}",
+                    cur.tid.raw(),
+                    cur.tid.name(),
+                    PREEMPT.raw()
+                );
 
                 self.kill(cur, cur_time, pree)
             }
             Some(task::Signal::Suspend(slot)) => {
-                log::trace!("Suspending task {:?}, P{}", cur.tid.raw(), PREEMPT.raw());
+                log::trace!(
+                    "Suspending task {:?} {:?}, P{}",
+                    cur.tid.raw(),
+                    cur.tid.name(),
+                    PREEMPT.raw()
+                );
 
                 SCHED_INFO[self.cpu]
                     .expected_runtime
                     .fetch_sub(cur.time_slice.as_micros() as u64, Release);
 
                 let ret = self.schedule_impl(cur_time, pree, None, |task| {
-                    *slot.lock() = Some(task::Ready::block(task, "task_ctl_suspend"));
+                    *slot.lock() = Some(task::Ready::suspend(task));
                     Ok(())
                 });
                 assert_matches!(ret, Ok(()) | Err(sv_call::ENOENT));
@@ -281,7 +383,7 @@ impl Scheduler {
     unsafe fn update(&self, cur_time: Instant) -> bool {
         self.canary.assert();
 
-        let sole = self.run_queue.is_empty();
+        let sole = self.run_queue.is_empty() && (*self.handoff.get()).is_none();
         let cur = match *self.current.get() {
             Some(ref mut task) => task,
             None => return !sole,
@@ -289,14 +391,9 @@ impl Scheduler {
         log::trace!("Updating task {:?}'s timer slice", cur.tid.raw());
 
         match cur.running_state.start_time() {
-            Some(start_time) => {
-                // FIXME: Some platforms like QEMU don't support invariant TSC, so the assert
-                // below can really fail. By far, comment it out to avoid kernel panic.
-                //
-                // debug_assert!(cur_time > start_time);
-                let runtime_delta = cur_time.saturating_duration_since(start_time);
-                cur.runtime += runtime_delta;
-                if cur.time_slice < runtime_delta && !sole {
+            Some(_) => {
+                cur.account(cur_time);
+                if cur.time_slice < cur.slice_used && !sole {
                     cur.running_state = task::RunningState::NEED_RESCHED;
                     true
                 } else {
@@ -338,23 +435,45 @@ impl Scheduler {
     {
         self.canary.assert();
 
-        let mut next = match next {
+        // SAFETY: We have `pree`, which means preemption is disabled.
+        let handoff = unsafe { &mut *self.handoff.get() };
+        let mut next = match next.or_else(|| handoff.take()) {
             Some(next) => next,
             None => match self.run_queue.pop() {
                 Some(task) => task,
                 None => return Err(sv_call::ENOENT),
             },
         };
-        log::trace!("Switching to task {:?}, P{}", next.tid.raw(), PREEMPT.raw());
+        // The hint is only valid for the current task.
+        unsafe { *self.yield_to.get() = None };
+        log::trace!(
+            "Switching to task {:?} {:?}, P{}",
+            next.tid.raw(),
+            next.tid.name(),
+            PREEMPT.raw()
+        );
+
+        rcu::quiesce(&pree);
 
+        if let Some(woken_at) = next.woken_at.take() {
+            let latency = cur_time.saturating_duration_since(woken_at);
+            task::stat::record_wake(next.tid.stats(), latency);
+        }
         next.running_state = task::RunningState::running(cur_time);
+        next.slice_used = Duration::ZERO;
         next.cpu = self.cpu;
         let new = next.kstack.kframe_ptr();
 
         // SAFETY: We have `pree`, which means preemption is disabled.
+        let category = unsafe { account::switch(next.category) };
         let cur_slot = unsafe { &mut *self.current.get() };
         let (old, ret) = match cur_slot.replace(next) {
             Some(mut prev) => {
+                prev.category = category;
+                // Account the runtime at every switch point, so tasks that
+                // never see a tick are still charged for their time.
+                prev.account(cur_time);
+                task::stat::record_slice(prev.tid.stats(), prev.slice_used, prev.time_slice);
                 let kframe_mut = prev.kstack.kframe_ptr_mut();
                 let ret = func(prev);
 
@@ -439,10 +558,71 @@ pub unsafe fn task_migrate_handler() {
             Steal::Empty => break,
             Steal::Retry => hint::spin_loop(),
             Steal::Success(task) => {
-                log::trace!("Migrating task {:?}, P{}", task.tid.raw(), PREEMPT.raw());
+                log::trace!(
+                    "Migrating task {:?} {:?}, P{}",
+                    task.tid.raw(),
+                    task.tid.name(),
+                    PREEMPT.raw()
+                );
                 let pree = PREEMPT.lock();
                 SCHED.enqueue(task, pree, true);
             }
         }
     }
 }
+
+/// Log the states of the schedulers for the diagnostics of hangs.
+///
+/// Only the current task of the calling CPU is known, since the others can't
+/// be read without stopping their CPUs.
+pub fn dump() {
+    for (cpu, info) in SCHED_INFO.iter().enumerate() {
+        log::error!(
+            "CPU #{cpu}: expected runtime {}us, {} task(s) migrating",
+            info.expected_runtime(),
+            info.migration_queue.len()
+        );
+        if let Some(mut stat) = account::stat(cpu) {
+            defer::stat(cpu, &mut stat);
+            log::error!(
+                "CPU #{cpu}: user {}ns, kernel {}ns, hardirq {}ns, deferred {}ns, idle {}ns, \
+                {} deferred work(s) pending",
+                stat.user,
+                stat.kernel,
+                stat.hardirq,
+                stat.deferred,
+                stat.idle,
+                stat.defer_queued - stat.defer_run
+            );
+        }
+    }
+    log::error!(
+        "Dumped on CPU #{}, P{}, {} task(s) in the run queue",
+        SCHED.cpu,
+        PREEMPT.raw(),
+        SCHED.run_queue.len()
+    );
+    let _ = SCHED.with_current(|cur| {
+        log::error!("Current task: {:?} {:?}", cur.tid.raw(), cur.name());
+        Ok(())
+    });
+    task::kthread::dump();
+    task::inherit::dump();
+
+    let stats = task::stat::system();
+    for (name, query) in [
+        ("wake latency", sv_call::task::TASK_STAT_WAKE_LATENCY),
+        ("slice usage", sv_call::task::TASK_STAT_SLICE_USAGE),
+    ] {
+        if let Ok(stat) = stats.stat(query, 0) {
+            log::error!(
+                "System {name}: count {}, p99 {}, max {}",
+                stat.count,
+                stat.p99,
+                stat.max
+            );
+        }
+    }
+    #[cfg(feature = "event-history")]
+    super::ipc::history::dump();
+}
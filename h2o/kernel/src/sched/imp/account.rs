@@ -0,0 +1,125 @@
+//! The time accounting of every CPU by what it's doing.
+//!
+//! A CPU is in one of the [`Category`]s at a time, switched at the entries
+//! and exits of the syscalls and the interrupt handlers, around the deferred
+//! work and in the idle loop. The time since the last switch is charged to
+//! the category left.
+//!
+//! Every task keeps the category it's switched out in, and restores it when
+//! it's switched back in, so a task preempted in the middle of the deferred
+//! work goes on being charged for it.
+
+use alloc::vec::Vec;
+use core::{
+    cell::Cell,
+    iter,
+    sync::atomic::{AtomicU64, Ordering::*},
+};
+
+use archop::Azy;
+use sv_call::task::CpuStat;
+
+use crate::cpu::{time::Instant, Lazy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    User,
+    Kernel,
+    HardIrq,
+    Deferred,
+    Idle,
+}
+
+const NR_CATEGORIES: usize = 5;
+
+static TIMES: Azy<Vec<[AtomicU64; NR_CATEGORIES]>> = Azy::new(|| {
+    iter::repeat_with(Default::default)
+        .take(crate::cpu::count())
+        .collect()
+});
+
+/// The category of the current CPU and the time it's switched to.
+#[thread_local]
+static CURRENT: Lazy<Cell<(Category, Instant)>> =
+    Lazy::new(|| Cell::new((Category::Kernel, Instant::now())));
+
+/// Switch the current CPU to `category`, returning the one left.
+///
+/// # Safety
+///
+/// The caller must ensure that the interrupts are disabled.
+pub unsafe fn switch(category: Category) -> Category {
+    let (left, _) = CURRENT.get();
+    enter(left, category)
+}
+
+/// Switch the current CPU to `category`, charging the time since the last
+/// switch to `left` instead of the current category, which is useful when
+/// the CPU is known to come from the user mode. Returns `left`.
+///
+/// # Safety
+///
+/// The caller must ensure that the interrupts are disabled.
+pub unsafe fn enter(left: Category, category: Category) -> Category {
+    let now = Instant::now();
+    let (_, since) = CURRENT.replace((category, now));
+    let nanos = now.saturating_duration_since(since).as_nanos() as u64;
+    TIMES[crate::cpu::id()][left as usize].fetch_add(nanos, Relaxed);
+    left
+}
+
+/// Run an interrupt handler `func` as [`Category::HardIrq`], where `user`
+/// tells whether the interrupt comes from the user mode.
+///
+/// # Safety
+///
+/// The caller must ensure that the interrupts are disabled.
+pub unsafe fn irq<R>(user: bool, func: impl FnOnce() -> R) -> R {
+    let left = if user {
+        enter(Category::User, Category::HardIrq)
+    } else {
+        switch(Category::HardIrq)
+    };
+    let ret = func();
+    switch(left);
+    ret
+}
+
+/// Get the times of `cpu` charged to each category.
+pub fn stat(cpu: usize) -> Option<CpuStat> {
+    let times = TIMES.get(cpu)?;
+    let time = |category: Category| times[category as usize].load(Relaxed);
+    Some(CpuStat {
+        user: time(Category::User),
+        kernel: time(Category::Kernel),
+        hardirq: time(Category::HardIrq),
+        deferred: time(Category::Deferred),
+        idle: time(Category::Idle),
+        ..Default::default()
+    })
+}
+
+#[cfg(ktest)]
+mod ktests {
+    use core::hint;
+
+    use super::*;
+    use crate::{ktest::case, sched::PREEMPT};
+
+    case! {
+        fn account_charges_left_category() {
+            let cpu = unsafe { crate::cpu::id() };
+            let before = stat(cpu).unwrap().deferred;
+
+            let left = PREEMPT.scope(|| unsafe { switch(Category::Deferred) });
+            let start = Instant::now();
+            while start.elapsed().is_zero() {
+                hint::spin_loop();
+            }
+            let deferred = PREEMPT.scope(|| unsafe { switch(left) });
+
+            assert_eq!(deferred, Category::Deferred);
+            assert!(stat(cpu).unwrap().deferred > before);
+        }
+    }
+}
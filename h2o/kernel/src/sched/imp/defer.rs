@@ -0,0 +1,111 @@
+//! The deferred work, run out of the syscall and interrupt contexts.
+//!
+//! Every CPU has an executor thread draining the queue of the work deferred on
+//! it, in the order queued, with the time charged to [`Category::Deferred`].
+//! The work is deferred by the code that shouldn't do unbounded work in place,
+//! like waking up the waiters of the events notified.
+//!
+//! The work queued before the executor of the CPU starts is run when it does.
+
+use alloc::{boxed::Box, format, vec::Vec};
+use core::{
+    iter,
+    sync::atomic::{AtomicU64, Ordering::*},
+};
+
+use archop::Azy;
+use crossbeam_queue::SegQueue;
+use spin::Once;
+use sv_call::task::CpuStat;
+
+use super::{
+    account::{self, Category},
+    PREEMPT,
+};
+use crate::{
+    cpu::time::Instant,
+    sched::task::{
+        kthread::{self, KThread},
+        Priority,
+    },
+};
+
+type Work = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct Queue {
+    works: SegQueue<(Work, Instant)>,
+    executor: Once<KThread>,
+    queued: AtomicU64,
+    run: AtomicU64,
+    max_pending: AtomicU64,
+    max_latency: AtomicU64,
+}
+
+static QUEUES: Azy<Vec<Queue>> = Azy::new(|| {
+    iter::repeat_with(Default::default)
+        .take(crate::cpu::count())
+        .collect()
+});
+
+/// Start the executor of the current CPU.
+pub(in crate::sched) fn init() {
+    let cpu = unsafe { crate::cpu::id() };
+    QUEUES[cpu].executor.call_once(|| {
+        kthread::spawn(format!("DEFER{cpu}"), Priority::High, move || loop {
+            run(&QUEUES[cpu]);
+            kthread::park();
+        })
+        .expect("Failed to spawn the deferred-work executor")
+    });
+}
+
+/// Defer `func` to the executor of the current CPU.
+pub fn queue<F>(func: F) -> sv_call::Result
+where
+    F: FnOnce() + Send + 'static,
+{
+    let work: Work = Box::try_new(func)?;
+    PREEMPT.scope(|| {
+        let queue = &QUEUES[unsafe { crate::cpu::id() }];
+        queue.works.push((work, Instant::now()));
+        queue.queued.fetch_add(1, Relaxed);
+        queue
+            .max_pending
+            .fetch_max(queue.works.len() as u64, Relaxed);
+        if let Some(executor) = queue.executor.get() {
+            executor.unpark();
+        }
+    });
+    Ok(())
+}
+
+/// Run the work deferred on the current CPU in place, since the executors
+/// aren't started in the tests.
+#[cfg(ktest)]
+pub fn run_pending() {
+    run(&QUEUES[unsafe { crate::cpu::id() }]);
+}
+
+fn run(queue: &Queue) {
+    let left = PREEMPT.scope(|| unsafe { account::switch(Category::Deferred) });
+    while let Some((work, queued_at)) = queue.works.pop() {
+        let latency = Instant::now().saturating_duration_since(queued_at);
+        queue
+            .max_latency
+            .fetch_max(latency.as_nanos() as u64, Relaxed);
+        work();
+        queue.run.fetch_add(1, Relaxed);
+    }
+    PREEMPT.scope(|| unsafe { account::switch(left) });
+}
+
+/// Fill the statistics of the deferred work of `cpu` into `stat`.
+pub fn stat(cpu: usize, stat: &mut CpuStat) {
+    if let Some(queue) = QUEUES.get(cpu) {
+        stat.defer_queued = queue.queued.load(Relaxed);
+        stat.defer_run = queue.run.load(Relaxed);
+        stat.defer_max_pending = queue.max_pending.load(Relaxed);
+        stat.defer_max_latency = queue.max_latency.load(Relaxed);
+    }
+}
@@ -0,0 +1,161 @@
+//! Read-mostly synchronization with quiescent-state based reclamation.
+//!
+//! Readers access an [`Rcu`] without any lock or atomic read-modify-write,
+//! while writers replace its value and retire the old one, which is freed
+//! after a grace period.
+//!
+//! # Read-side critical sections
+//!
+//! A read-side critical section is a region where `PREEMPT` is locked, which
+//! disables both interrupts and scheduling on the CPU. The references from
+//! [`Rcu::read`] are bound to the lifetime of the guard, so they can't escape
+//! the section.
+//!
+//! # Quiescent states
+//!
+//! A CPU passes a quiescent state when it can't be in any read-side critical
+//! section, that is at every timer tick, every context switch, and every
+//! round of the idle loop, where [`quiesce`] is called with no outer guard of
+//! `PREEMPT`.
+//!
+//! # Deferred-free rules
+//!
+//! - An object must be unlinked from every [`Rcu`] before it's retired, so that
+//!   no new reader can find it afterwards.
+//! - A retired object is dropped once all the CPUs have passed a quiescent
+//!   state after it's retired, on whichever CPU that notices it first, so it
+//!   must be [`Send`], and its `Drop` must not block or rely on the CPU it runs
+//!   on.
+//! - Retiring never waits for the grace period, so it's allowed in interrupt
+//!   handlers and with `PREEMPT` locked. The memory is held meanwhile, and a
+//!   CPU that stops passing quiescent states delays all the reclamation.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    any::Any,
+    marker::PhantomData,
+    mem, ptr,
+    sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering::*},
+};
+
+use archop::{Azy, PreemptStateGuard};
+use spin::Mutex;
+
+use super::PREEMPT;
+
+static EPOCH: AtomicU64 = AtomicU64::new(1);
+
+/// The epochs observed by the CPUs at their last quiescent states.
+static QUIESCED: Azy<Vec<AtomicU64>> = Azy::new(|| {
+    core::iter::repeat_with(|| AtomicU64::new(0))
+        .take(crate::cpu::count())
+        .collect()
+});
+
+struct Deferred {
+    epoch: u64,
+    _obj: Box<dyn Any + Send>,
+}
+
+static DEFERRED: Mutex<Vec<Deferred>> = Mutex::new(Vec::new());
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Retire `obj`, which is dropped after the current grace period.
+///
+/// See the [module-level documentation](self) for the rules.
+pub fn retire<T: Send + 'static>(obj: Box<T>) {
+    PREEMPT.scope(|| {
+        let epoch = EPOCH.fetch_add(1, SeqCst);
+        DEFERRED.lock().push(Deferred { epoch, _obj: obj });
+        PENDING.fetch_add(1, Release);
+    })
+}
+
+/// Report a quiescent state of the current CPU, and drop the retired objects
+/// whose grace periods have elapsed.
+///
+/// Nothing is reported unless `_pree` is the outermost guard of `PREEMPT`,
+/// since the outer ones may protect read-side critical sections.
+pub fn quiesce(_pree: &PreemptStateGuard) {
+    if PREEMPT.raw() != 1 {
+        return;
+    }
+    let cpu = unsafe { crate::cpu::id() };
+    QUIESCED[cpu].store(EPOCH.load(SeqCst), SeqCst);
+
+    if PENDING.load(Acquire) == 0 {
+        return;
+    }
+    let min = QUIESCED.iter().map(|q| q.load(SeqCst)).min().unwrap_or(0);
+    let expired = {
+        let mut deferred = DEFERRED.lock();
+        let (expired, rest) = mem::take(&mut *deferred)
+            .into_iter()
+            .partition::<Vec<_>, _>(|d| d.epoch < min);
+        *deferred = rest;
+        PENDING.fetch_sub(expired.len(), Release);
+        expired
+    };
+    drop(expired);
+}
+
+/// A pointer to a read-mostly value, which is read without locks and replaced
+/// with the old value retired.
+pub struct Rcu<T: Send + 'static> {
+    ptr: AtomicPtr<T>,
+    _marker: PhantomData<Box<T>>,
+}
+
+unsafe impl<T: Send + Sync + 'static> Sync for Rcu<T> {}
+
+impl<T: Send + 'static> Rcu<T> {
+    pub const fn empty() -> Self {
+        Rcu {
+            ptr: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn new(value: T) -> Self {
+        Rcu {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(value))),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Read the value in the read-side critical section of `_pree`.
+    #[inline]
+    pub fn read<'a>(&'a self, _pree: &'a PreemptStateGuard) -> Option<&'a T> {
+        // SAFETY: The value is retired after being unlinked, and isn't dropped
+        // until the current CPU passes a quiescent state, which is after
+        // `_pree` is dropped.
+        unsafe { self.ptr.load(Acquire).as_ref() }
+    }
+
+    /// Replace the value, retiring the old one if any.
+    pub fn replace(&self, value: Option<T>) {
+        let new = value.map_or(ptr::null_mut(), |value| Box::into_raw(Box::new(value)));
+        let old = self.ptr.swap(new, AcqRel);
+        if !old.is_null() {
+            // SAFETY: The old value is unlinked and owned by us now.
+            retire(unsafe { Box::from_raw(old) });
+        }
+    }
+}
+
+impl<T: Send + 'static> Default for Rcu<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<T: Send + 'static> Drop for Rcu<T> {
+    fn drop(&mut self) {
+        let ptr = *self.ptr.get_mut();
+        if !ptr.is_null() {
+            // SAFETY: We have the exclusive access, so no reader refers to it.
+            drop(unsafe { Box::from_raw(ptr) });
+        }
+    }
+}
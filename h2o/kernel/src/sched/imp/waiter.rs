@@ -14,18 +14,23 @@ use spin::Mutex;
 use sv_call::{
     call::Syscall,
     ipc::{SIG_READ, SIG_WRITE},
-    Feature, Result, ENOSPC,
+    Feature, Result, ENOENT, ENOSPC, EPIPE,
 };
 
 use super::PREEMPT;
 use crate::{
     cpu::arch::apic::TriggerMode,
-    sched::{task::hdl::DefaultFeature, wait::WaitObject, BasicEvent, Event, Waiter, WaiterData},
+    sched::{
+        task::hdl::{DefaultFeature, KernelObject},
+        wait::WaitObject,
+        BasicEvent, Event, Waiter, WaiterData,
+    },
 };
 
 #[derive(Debug)]
 pub struct Blocker {
     wake_all: bool,
+    consume: bool,
     wo: WaitObject,
     event: Weak<dyn Event>,
     waiter_data: WaiterData,
@@ -38,26 +43,46 @@ impl Blocker {
         level_triggered: bool,
         wake_all: bool,
         signal: usize,
+    ) -> Arc<Self> {
+        let waiter_data = WaiterData::new(
+            if level_triggered {
+                TriggerMode::Level
+            } else {
+                TriggerMode::Edge
+            },
+            signal,
+        );
+        Self::with_data(event, waiter_data, wake_all, false)
+    }
+
+    /// Create a blocker with `waiter_data`, which clears the matched bits of
+    /// the event's signal when detached if `consume` is set.
+    pub fn with_data(
+        event: &Arc<dyn Event>,
+        waiter_data: WaiterData,
+        wake_all: bool,
+        consume: bool,
     ) -> Arc<Self> {
         let ret = Arc::new(Blocker {
             wake_all,
+            consume,
             wo: WaitObject::new(),
             event: Arc::downgrade(event) as _,
-            waiter_data: WaiterData::new(
-                if level_triggered {
-                    TriggerMode::Level
-                } else {
-                    TriggerMode::Edge
-                },
-                signal,
-            ),
+            waiter_data,
             status: Mutex::new((true, 0)),
         });
+        #[cfg(feature = "event-history")]
+        crate::sched::ipc::history::watch(event, &(Arc::clone(&ret) as _));
         event.wait(Arc::clone(&ret) as _);
         ret
     }
 
-    pub fn wait(&self, pree: Option<PreemptStateGuard>, timeout: Duration) -> sv_call::Result {
+    pub fn wait(
+        &self,
+        pree: Option<PreemptStateGuard>,
+        timeout: Duration,
+        interruptible: bool,
+    ) -> sv_call::Result {
         let pree = match pree {
             Some(pree) => pree,
             None => PREEMPT.lock(),
@@ -68,16 +93,32 @@ impl Blocker {
         } else if self.event.strong_count() == 0 {
             Err(sv_call::EPIPE)
         } else {
-            self.wo.wait((status, pree), timeout, "Blocker::wait")
+            let ret = self
+                .wo
+                .wait((status, pree), timeout, interruptible, "Blocker::wait");
+            #[cfg(feature = "event-history")]
+            if let (Err(sv_call::ETIME), Some(event)) = (ret, self.event.upgrade()) {
+                let key = self as *const _ as *const () as usize;
+                crate::sched::ipc::history::check(&*event, key, &self.waiter_data);
+            }
+            ret
         }
     }
 
     pub fn detach(self: Arc<Self>) -> (bool, usize) {
         let (has_signal, signal) = PREEMPT.scope(|| *self.status.lock());
         if let Some(event) = self.event.upgrade() {
-            let (wait_for, wake_all) = (self.waiter_data().signal(), self.wake_all);
+            let (waiter_data, wake_all, consume) = (self.waiter_data, self.wake_all, self.consume);
+            let wait_for = waiter_data.signal();
             let (not_signaled, newer) = event.unwait(&(self as _));
             let has_signal = !not_signaled && has_signal;
+            if consume && has_signal {
+                // Another waiter may have consumed the signal in the meantime.
+                return match event.consume_signal(&waiter_data) {
+                    Some(signal) => (true, signal),
+                    None => (false, newer),
+                };
+            }
             if !wake_all && has_signal {
                 event.notify(wait_for, 0);
             }
@@ -197,6 +238,27 @@ impl Dispatcher {
         *signal_slot = signal;
         Some((canceled, res))
     }
+
+    /// Change the signal a pending request waits for without re-registering
+    /// it, returning the current signal of its event.
+    ///
+    /// The snapshot is taken after the change, so any later notification will
+    /// be checked against the new signal. A level-triggered request already
+    /// satisfied by the snapshot is made ready right away.
+    pub fn update(self: &Arc<Self>, key: usize, signal: usize) -> Result<usize> {
+        let event = PREEMPT.scope(|| {
+            let mut pending = self.pending.lock();
+            let req = pending.iter_mut().find(|req| req.key == key);
+            let req = req.ok_or(ENOENT)?;
+            req.waiter_data = req.waiter_data.with_signal(signal);
+            req.event.upgrade().ok_or(EPIPE)
+        })?;
+
+        let snapshot = event.event_data().signal().load(SeqCst);
+        let (ptr, _) = Arc::as_ptr(&event).to_raw_parts();
+        self.try_on_notify(ptr, snapshot, true);
+        Ok(snapshot)
+    }
 }
 
 impl Waiter for Dispatcher {
@@ -268,3 +330,9 @@ unsafe impl DefaultFeature for Dispatcher {
         Feature::SEND | Feature::SYNC | Feature::READ | Feature::WRITE | Feature::WAIT
     }
 }
+
+impl KernelObject for Dispatcher {
+    fn event(this: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        Some(this.event())
+    }
+}
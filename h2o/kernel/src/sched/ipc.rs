@@ -1,52 +1,149 @@
 mod arsc;
 pub mod basic;
 mod channel;
+mod dgram;
+#[cfg(feature = "event-history")]
+pub mod history;
+mod port;
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 use core::{
     fmt::Debug,
     hash::BuildHasherDefault,
     hint,
-    sync::atomic::{AtomicUsize, Ordering::SeqCst},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::SeqCst},
 };
 
 use collection_ex::{CHashMap, FnvHasher};
+use crossbeam_queue::SegQueue;
 pub use sv_call::ipc::{SIG_GENERIC, SIG_READ, SIG_TIMER, SIG_WRITE};
 
 pub use self::{
     arsc::Arsc,
     channel::{Channel, Packet},
+    dgram::Datagram,
+    port::Port,
 };
-use super::PREEMPT;
+use super::{defer, PREEMPT};
 use crate::cpu::arch::apic::TriggerMode;
 
 type BH = BuildHasherDefault<FnvHasher>;
 
+/// A change of the signal of an event, not yet delivered to the waiters.
+#[derive(Debug, Clone, Copy)]
+struct Note {
+    seq: u64,
+    signal: usize,
+    cleared: bool,
+}
+
+/// The waiters of an event and the notifications pending for them, shared
+/// with the deferred work delivering the notifications.
+#[derive(Debug, Default)]
+struct Wakes {
+    /// The waiters with the sequence numbers of the first notifications they
+    /// can receive.
+    waiters: CHashMap<usize, (u64, Arc<dyn Waiter>), BH>,
+    notes: SegQueue<Note>,
+    seq: AtomicU64,
+    /// Whether a delivery is queued to the deferred-work executor.
+    scheduled: AtomicBool,
+    #[cfg(feature = "event-history")]
+    history: history::History,
+}
+
+impl Wakes {
+    /// Record a notification and queue a delivery if none is queued yet, so
+    /// the notifications in a burst are delivered in one batch.
+    fn push(self: &Arc<Self>, event: *const (), signal: usize, cleared: bool) {
+        #[cfg(feature = "event-history")]
+        self.history.record(
+            if cleared {
+                history::Op::Clear
+            } else {
+                history::Op::Set
+            },
+            signal,
+            0,
+        );
+        let seq = self.seq.fetch_add(1, SeqCst);
+        self.notes.push(Note {
+            seq,
+            signal,
+            cleared,
+        });
+        if self.scheduled.swap(true, SeqCst) {
+            return;
+        }
+
+        let wakes = Arc::clone(self);
+        let event = event as usize;
+        let ret = defer::queue(move || {
+            // Cleared before delivering, so that the notifications recorded
+            // meanwhile get another delivery.
+            wakes.scheduled.store(false, SeqCst);
+            wakes.deliver(event as *const ());
+        });
+        if ret.is_err() {
+            // Deliver in place rather than losing the wake-ups.
+            self.scheduled.store(false, SeqCst);
+            self.deliver(event as *const ());
+        }
+    }
+
+    /// Deliver the pending notifications in one walk of the waiters, each
+    /// waiter only receiving the ones recorded after it's armed.
+    fn deliver(&self, event: *const ()) {
+        let mut notes = Vec::new();
+        while let Some(note) = self.notes.pop() {
+            notes.push(note);
+        }
+        if notes.is_empty() {
+            return;
+        }
+
+        PREEMPT.scope(|| {
+            self.waiters.retain(|_key, (armed, waiter)| {
+                let exact = waiter.waiter_data().matching() == SignalMatch::Exact;
+                let woken = { notes.iter() }
+                    .filter(|note| note.seq >= *armed && (exact || !note.cleared))
+                    .find(|note| waiter.try_on_notify(event, note.signal, false));
+                #[cfg(feature = "event-history")]
+                if let Some(note) = woken {
+                    self.history.record(history::Op::Wake, note.signal, *_key);
+                }
+                woken.is_none()
+            })
+        });
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct EventData {
-    waiters: CHashMap<usize, Arc<dyn Waiter>, BH>,
+    wakes: Arc<Wakes>,
     signal: AtomicUsize,
 }
 
 impl EventData {
     pub fn new(init_signal: usize) -> Self {
         EventData {
-            waiters: Default::default(),
+            wakes: Default::default(),
             signal: AtomicUsize::new(init_signal),
         }
     }
 
-    #[inline]
-    pub fn waiters(&self) -> &CHashMap<usize, Arc<dyn Waiter>, BH> {
-        &self.waiters
-    }
-
     #[inline]
     pub fn signal(&self) -> &AtomicUsize {
         &self.signal
     }
 }
 
+/// An object with a signal that waiters wait on.
+///
+/// Notifying an event only updates the signal and records the change, which
+/// takes constant time even in the interrupt handlers. The waiters are woken
+/// up by the deferred-work executor, which delivers all the changes recorded
+/// since its last run in one batch.
 pub trait Event: Debug + Send + Sync {
     fn event_data(&self) -> &EventData;
 
@@ -56,12 +153,20 @@ pub trait Event: Debug + Send + Sync {
     }
 
     fn wait_impl(&self, waiter: Arc<dyn Waiter>) {
+        let wakes = &self.event_data().wakes;
+        // Taken before the signal, so that the changes after the signal is
+        // checked are all delivered to the waiter.
+        let armed = wakes.seq.load(SeqCst);
         let signal = self.event_data().signal().load(SeqCst);
+        let (key, _) = Arc::as_ptr(&waiter).to_raw_parts();
+        #[cfg(feature = "event-history")]
+        wakes.history.record(history::Op::Wait, signal, key as _);
         if waiter.try_on_notify(self as *const _ as _, signal, true) {
+            #[cfg(feature = "event-history")]
+            wakes.history.record(history::Op::Wake, signal, key as _);
             return;
         }
-        let (key, _) = Arc::as_ptr(&waiter).to_raw_parts();
-        PREEMPT.scope(|| self.event_data().waiters.insert(key as _, waiter));
+        PREEMPT.scope(|| wakes.waiters.insert(key as _, (armed, waiter)));
     }
 
     fn unwait(&self, waiter: &Arc<dyn Waiter>) -> (bool, usize) {
@@ -69,18 +174,35 @@ pub trait Event: Debug + Send + Sync {
         let ret = PREEMPT.scope(|| {
             let (other, _) = Arc::as_ptr(waiter).to_raw_parts();
             self.event_data()
+                .wakes
                 .waiters
                 .remove(&(other as usize))
                 .is_some()
         });
+        #[cfg(feature = "event-history")]
+        if ret {
+            let (key, _) = Arc::as_ptr(waiter).to_raw_parts();
+            self.event_data()
+                .wakes
+                .history
+                .record(history::Op::Unwait, signal, key as _);
+        }
         (ret, signal)
     }
 
     fn cancel(&self) {
+        // The waiters get the notifications before the cancellation, as if
+        // they're delivered in place.
+        self.flush();
         let signal = self.event_data().signal.load(SeqCst);
-
-        let waiters = PREEMPT.scope(|| self.event_data().waiters.take());
-        for (_, waiter) in waiters {
+        #[cfg(feature = "event-history")]
+        self.event_data()
+            .wakes
+            .history
+            .record(history::Op::Cancel, signal, 0);
+
+        let waiters = PREEMPT.scope(|| self.event_data().wakes.waiters.take());
+        for (_, (_, waiter)) in waiters {
             waiter.on_cancel(self as *const _ as _, signal);
         }
     }
@@ -91,37 +213,86 @@ pub trait Event: Debug + Send + Sync {
     }
 
     fn notify_impl(&self, clear: usize, set: usize) -> usize {
+        let (signal, wake) = self.update_signal(clear, set);
+        if let Some(cleared) = wake {
+            self.wake_waiters(signal, cleared);
+        }
+        signal
+    }
+
+    /// Update the signal, returning the new one and, if it's changed, whether
+    /// it's changed by clearing bits only.
+    fn update_signal(&self, clear: usize, set: usize) -> (usize, Option<bool>) {
         let mut prev = self.event_data().signal.load(SeqCst);
-        let signal = loop {
+        loop {
             let new = (prev & !clear) | set;
             if prev == new {
-                return prev;
+                return (prev, None);
             }
             match self
                 .event_data()
                 .signal
                 .compare_exchange_weak(prev, new, SeqCst, SeqCst)
             {
-                Ok(_) if prev & new == new => return new,
-                Ok(_) => break new,
+                Ok(_) => return (new, Some(prev & new == new)),
                 Err(signal) => {
                     prev = signal;
                     hint::spin_loop()
                 }
             }
-        };
-        PREEMPT.scope(|| {
-            self.event_data()
-                .waiters
-                .retain(|_, waiter| !waiter.try_on_notify(self as *const _ as _, signal, false))
-        });
-        signal
+        }
+    }
+
+    /// Wake up the waiters satisfied by `signal`, or only the exact-match ones
+    /// if the signal is changed by clearing bits.
+    ///
+    /// The waiters are woken up later by the deferred-work executor.
+    #[inline]
+    fn wake_waiters(&self, signal: usize, cleared: bool) {
+        let event = self as *const _ as _;
+        self.event_data().wakes.push(event, signal, cleared);
+    }
+
+    /// Deliver the pending notifications in place.
+    #[inline]
+    fn flush(&self) {
+        self.event_data().wakes.deliver(self as *const _ as _);
+    }
+
+    /// Clear the bits waited for by `waiter_data` if the signal still
+    /// satisfies it, returning the signal before clearing.
+    fn consume_signal(&self, waiter_data: &WaiterData) -> Option<usize> {
+        let prev = self
+            .event_data()
+            .signal
+            .fetch_update(SeqCst, SeqCst, |prev| {
+                let matched = waiter_data.can_signal(prev, false);
+                matched.then_some(prev & !waiter_data.signal())
+            })
+            .ok()?;
+        let new = prev & !waiter_data.signal();
+        if new != prev {
+            self.wake_waiters(new, true);
+        }
+        Some(prev)
     }
 }
 
+/// How the signal of an event is matched against the bits a waiter waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalMatch {
+    /// Any of the bits is set.
+    Any,
+    /// All of the bits are set.
+    All,
+    /// The signal equals the bits exactly.
+    Exact,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct WaiterData {
     trigger_mode: TriggerMode,
+    matching: SignalMatch,
     signal: usize,
 }
 
@@ -129,6 +300,7 @@ impl WaiterData {
     pub fn new(trigger_mode: TriggerMode, signal: usize) -> Self {
         WaiterData {
             trigger_mode,
+            matching: SignalMatch::All,
             signal,
         }
     }
@@ -137,16 +309,49 @@ impl WaiterData {
         self.trigger_mode
     }
 
+    pub fn matching(&self) -> SignalMatch {
+        self.matching
+    }
+
     pub fn signal(&self) -> usize {
         self.signal
     }
 
+    #[inline]
+    pub fn with_signal(self, signal: usize) -> Self {
+        WaiterData { signal, ..self }
+    }
+
+    #[inline]
+    pub fn with_matching(self, matching: SignalMatch) -> Self {
+        WaiterData { matching, ..self }
+    }
+
+    /// Whether the waiter should be woken up by the event's `signal`.
+    ///
+    /// Both modes require `signal` to match the bits the waiter waits for (see
+    /// [`SignalMatch`]). On top of that:
+    ///
+    /// - A level-triggered waiter is also satisfied by the signal present at
+    ///   the time it's armed (`on_wait`).
+    /// - An edge-triggered waiter is only satisfied by later notifications that
+    ///   set at least one bit, even if the bits it waits for were already set.
+    ///   Notifications that only clear bits wake up exact-match waiters alone.
+    ///
+    /// Edge-triggered waiters that need the state before arming should take
+    /// a snapshot of it after arming, e.g. with [`Dispatcher::update`], so
+    /// that no notification falls in between.
+    ///
+    /// [`Dispatcher::update`]: crate::sched::Dispatcher::update
     #[inline]
     pub fn can_signal(&self, signal: usize, on_wait: bool) -> bool {
         if on_wait && self.trigger_mode == TriggerMode::Edge {
-            false
-        } else {
-            self.signal & !signal == 0
+            return false;
+        }
+        match self.matching {
+            SignalMatch::Any => self.signal & signal != 0,
+            SignalMatch::All => self.signal & !signal == 0,
+            SignalMatch::Exact => self.signal == signal,
         }
     }
 }
@@ -169,20 +374,19 @@ pub trait Waiter: Debug + Send + Sync {
 }
 
 mod syscall {
-    use sv_call::{call::Syscall, *};
+    use sv_call::{call::Syscall, ipc::WaitOptions, *};
 
     use super::*;
     use crate::{
         cpu::{arch::apic::TriggerMode, time},
-        sched::{BasicEvent, Blocker, Dispatcher, WaiterData, SCHED},
-        syscall::{In, Out, UserPtr},
+        sched::{BasicEvent, Blocker, Dispatcher, SignalMatch, WaiterData, SCHED},
+        syscall::{restart_until, In, Out, UserPtr},
     };
 
     #[syscall]
     fn event_new(init_signal: usize) -> Result<Handle> {
         let obj = BasicEvent::new(init_signal);
-        let event = Arc::downgrade(&obj) as _;
-        SCHED.with_current(|cur| cur.space().handles().insert_raw(obj, Some(event)))
+        SCHED.with_current(|cur| cur.space().handles().insert_raw(obj))
     }
 
     #[syscall]
@@ -217,6 +421,33 @@ mod syscall {
         level_triggered: bool,
         wake_all: bool,
         signal: usize,
+    ) -> Result<usize> {
+        let deadline = time::deadline_after(time::from_us(timeout_us));
+        restart_until(deadline, |deadline| {
+            obj_wait_impl(hdl, deadline, level_triggered, wake_all, signal)
+        })
+    }
+
+    /// Like `obj_wait`, but until the deadline of `deadline_ns` nanoseconds
+    /// of the clock, which stays the same when the call is restarted.
+    #[syscall(restart)]
+    fn obj_wait_until(
+        hdl: Handle,
+        deadline_ns: u64,
+        level_triggered: bool,
+        wake_all: bool,
+        signal: usize,
+    ) -> Result<usize> {
+        let deadline = time::deadline_from_ns(deadline_ns);
+        obj_wait_impl(hdl, deadline, level_triggered, wake_all, signal)
+    }
+
+    fn obj_wait_impl(
+        hdl: Handle,
+        deadline: Option<time::Instant>,
+        level_triggered: bool,
+        wake_all: bool,
+        signal: usize,
     ) -> Result<usize> {
         let pree = PREEMPT.lock();
         let cur = unsafe { (*SCHED.current()).as_ref().ok_or(ESRCH) }?;
@@ -229,7 +460,13 @@ mod syscall {
         drop(obj);
 
         let blocker = Blocker::new(&event, level_triggered, wake_all, signal);
-        blocker.wait(Some(pree), time::from_us(timeout_us))?;
+        if let Err(err) = blocker.wait(Some(pree), time::remaining(deadline), true) {
+            // Don't leave the blocker behind for the restarted wait.
+            if err == EINTR {
+                blocker.detach();
+            }
+            return Err(err);
+        }
 
         let (detach_ret, signal) = blocker.detach();
         if !detach_ret {
@@ -238,11 +475,97 @@ mod syscall {
         Ok(signal)
     }
 
+    /// Wait for the signal of the object to match `signal` as specified by
+    /// `options`, returning the signal matched.
+    ///
+    /// With `CONSUME`, the wait goes on if the matched bits are cleared by
+    /// another waiter first.
+    #[syscall]
+    fn obj_wait_match(
+        hdl: Handle,
+        timeout_us: u64,
+        options: WaitOptions,
+        signal: usize,
+    ) -> Result<usize> {
+        let deadline = time::deadline_after(time::from_us(timeout_us));
+        restart_until(deadline, |deadline| {
+            obj_wait_match_impl(hdl, deadline, options, signal)
+        })
+    }
+
+    /// Like `obj_wait_match`, but until the deadline of `deadline_ns`
+    /// nanoseconds of the clock, which stays the same when the call is
+    /// restarted.
+    #[syscall(restart)]
+    fn obj_wait_match_until(
+        hdl: Handle,
+        deadline_ns: u64,
+        options: WaitOptions,
+        signal: usize,
+    ) -> Result<usize> {
+        let deadline = time::deadline_from_ns(deadline_ns);
+        obj_wait_match_impl(hdl, deadline, options, signal)
+    }
+
+    fn obj_wait_match_impl(
+        hdl: Handle,
+        deadline: Option<time::Instant>,
+        options: WaitOptions,
+        signal: usize,
+    ) -> Result<usize> {
+        if options.contains(WaitOptions::MATCH_ANY | WaitOptions::MATCH_EXACT) {
+            return Err(EINVAL);
+        }
+        let pree = PREEMPT.lock();
+        let cur = unsafe { (*SCHED.current()).as_ref().ok_or(ESRCH) }?;
+
+        let obj = cur.space().handles().get_ref(hdl)?;
+        if !obj.features().contains(Feature::WAIT) {
+            return Err(EPERM);
+        }
+        let event = obj.event().upgrade().ok_or(EPIPE)?;
+        drop(obj);
+
+        let trigger_mode = if options.contains(WaitOptions::LEVEL_TRIGGERED) {
+            TriggerMode::Level
+        } else {
+            TriggerMode::Edge
+        };
+        let matching = if options.contains(WaitOptions::MATCH_ANY) {
+            SignalMatch::Any
+        } else if options.contains(WaitOptions::MATCH_EXACT) {
+            SignalMatch::Exact
+        } else {
+            SignalMatch::All
+        };
+        let waiter_data = WaiterData::new(trigger_mode, signal).with_matching(matching);
+        let wake_all = options.contains(WaitOptions::WAKE_ALL);
+        let consume = options.contains(WaitOptions::CONSUME);
+
+        let mut pree = Some(pree);
+        loop {
+            let blocker = Blocker::with_data(&event, waiter_data, wake_all, consume);
+            if let Err(err) = blocker.wait(pree.take(), time::remaining(deadline), true) {
+                if err == EINTR {
+                    blocker.detach();
+                }
+                return Err(err);
+            }
+
+            let (detach_ret, signal) = blocker.detach();
+            if detach_ret {
+                break Ok(signal);
+            }
+            if !consume || time::remaining(deadline).is_zero() {
+                break Err(ETIME);
+            }
+        }
+    }
+
     #[syscall]
     fn disp_new(capacity: usize) -> Result<Handle> {
         let disp = Dispatcher::new(capacity)?;
-        let event = disp.event();
-        SCHED.with_current(|cur| cur.space().handles().insert_raw(disp, Some(event)))
+        SCHED.with_current(|cur| cur.space().handles().insert_raw(disp))
     }
 
     #[syscall]
@@ -317,4 +640,193 @@ mod syscall {
         }
         Ok(key)
     }
+
+    #[syscall]
+    fn disp_update(
+        disp: Handle,
+        key: usize,
+        signal: usize,
+        snapshot: UserPtr<Out, usize>,
+    ) -> Result {
+        disp.check_null()?;
+
+        let disp = SCHED.with_current(|cur| {
+            let disp = cur.space().handles().get::<Dispatcher>(disp)?;
+            if !disp.features().contains(Feature::WRITE) {
+                return Err(EPERM);
+            }
+            Ok(Arc::clone(&disp))
+        })?;
+        let current = disp.update(key, signal)?;
+
+        if !snapshot.as_ptr().is_null() {
+            snapshot.write(current)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(ktest)]
+mod ktests {
+    use alloc::vec;
+
+    use sv_call::task::CpuStat;
+
+    use super::{basic::BasicEvent, *};
+    use crate::ktest::{case, MockWaiter};
+
+    /// Notify `event` and deliver the notification, as the executor would.
+    fn notify(event: &BasicEvent, clear: usize, set: usize) -> usize {
+        let signal = event.notify(clear, set);
+        defer::run_pending();
+        signal
+    }
+
+    fn deferred_works() -> u64 {
+        let mut stat = CpuStat::default();
+        defer::stat(unsafe { crate::cpu::id() }, &mut stat);
+        stat.defer_queued
+    }
+
+    case! {
+        fn notify_wakes_matching_waiters() {
+            let event = BasicEvent::new(0);
+            let waiter = Arc::new(MockWaiter::new(TriggerMode::Level, SIG_READ));
+            event.wait(waiter.clone());
+
+            assert_eq!(notify(&event, 0, SIG_WRITE), SIG_WRITE);
+            assert!(waiter.notified().is_empty());
+
+            assert_eq!(notify(&event, 0, SIG_READ), SIG_READ | SIG_WRITE);
+            assert_eq!(waiter.notified(), vec![SIG_READ | SIG_WRITE]);
+            // Woken waiters are removed from the event.
+            notify(&event, SIG_READ, 0);
+            notify(&event, 0, SIG_READ);
+            assert!(waiter.notified().is_empty());
+        }
+
+        fn notify_ignores_unchanged_and_cleared_signals() {
+            let event = BasicEvent::new(SIG_READ);
+            let waiter = Arc::new(MockWaiter::new(TriggerMode::Edge, SIG_WRITE));
+            event.wait(waiter.clone());
+
+            assert_eq!(notify(&event, 0, SIG_READ), SIG_READ);
+            assert_eq!(notify(&event, SIG_READ, 0), 0);
+            assert!(waiter.notified().is_empty());
+
+            notify(&event, 0, SIG_WRITE);
+            assert_eq!(waiter.notified(), vec![SIG_WRITE]);
+        }
+
+        fn wait_honors_trigger_modes() {
+            let event = BasicEvent::new(SIG_READ);
+            let level = Arc::new(MockWaiter::new(TriggerMode::Level, SIG_READ));
+            let edge = Arc::new(MockWaiter::new(TriggerMode::Edge, SIG_READ));
+            event.wait(level.clone());
+            event.wait(edge.clone());
+
+            assert_eq!(level.notified(), vec![SIG_READ]);
+            assert!(edge.notified().is_empty());
+
+            notify(&event, 0, SIG_WRITE);
+            assert_eq!(edge.notified(), vec![SIG_READ | SIG_WRITE]);
+        }
+
+        fn match_modes_and_consume() {
+            let event = BasicEvent::new(SIG_READ | SIG_WRITE);
+            let data = WaiterData::new(TriggerMode::Edge, SIG_READ);
+            let any = data
+                .with_signal(SIG_TIMER | SIG_GENERIC)
+                .with_matching(SignalMatch::Any);
+            let any = Arc::new(MockWaiter::with_data(any));
+            let exact = Arc::new(MockWaiter::with_data(data.with_matching(SignalMatch::Exact)));
+            event.wait(any.clone());
+            event.wait(exact.clone());
+
+            notify(&event, 0, SIG_TIMER);
+            assert_eq!(any.notified(), vec![SIG_READ | SIG_WRITE | SIG_TIMER]);
+            assert!(exact.notified().is_empty());
+            // Clearing bits wakes up exact-match waiters.
+            notify(&event, SIG_WRITE | SIG_TIMER, 0);
+            assert_eq!(exact.notified(), vec![SIG_READ]);
+
+            assert_eq!(event.consume_signal(&data), Some(SIG_READ));
+            assert_eq!(event.consume_signal(&data), None);
+            assert_eq!(event.event_data().signal().load(SeqCst), 0);
+            defer::run_pending();
+        }
+
+        fn cancel_and_unwait() {
+            let event = BasicEvent::new(0);
+            let w1 = Arc::new(MockWaiter::new(TriggerMode::Level, SIG_READ));
+            let w2: Arc<dyn Waiter> = Arc::new(MockWaiter::new(TriggerMode::Level, SIG_READ));
+            event.wait(w1.clone());
+            event.wait(w2.clone());
+
+            assert_eq!(event.unwait(&w2), (true, 0));
+            assert_eq!(event.unwait(&w2), (false, 0));
+
+            event.cancel();
+            assert_eq!(w1.canceled(), vec![0]);
+            assert!(w1.notified().is_empty());
+        }
+
+        fn notify_batches_deferred_wakes() {
+            let event = BasicEvent::new(0);
+            let waiters = [SIG_READ, SIG_WRITE, SIG_TIMER]
+                .map(|signal| Arc::new(MockWaiter::new(TriggerMode::Edge, signal)));
+            for waiter in &waiters {
+                event.wait(waiter.clone());
+            }
+
+            let queued = deferred_works();
+            event.notify(0, SIG_READ);
+            event.notify(0, SIG_WRITE);
+            event.notify(SIG_READ | SIG_WRITE, SIG_TIMER);
+            // Only the signal is updated in place, with a single delivery
+            // queued for the burst.
+            assert_eq!(event.event_data().signal().load(SeqCst), SIG_TIMER);
+            assert_eq!(deferred_works(), queued + 1);
+            assert!(waiters.iter().all(|waiter| waiter.notified().is_empty()));
+
+            defer::run_pending();
+            // Every waiter gets the first notification it matches, even if
+            // the bits are cleared afterwards.
+            assert_eq!(waiters[0].notified(), vec![SIG_READ]);
+            assert_eq!(waiters[1].notified(), vec![SIG_READ | SIG_WRITE]);
+            assert_eq!(waiters[2].notified(), vec![SIG_TIMER]);
+        }
+
+        fn deferred_wakes_not_lost() {
+            let event = BasicEvent::new(0);
+            let early = Arc::new(MockWaiter::new(TriggerMode::Edge, SIG_READ));
+            event.wait(early.clone());
+
+            event.notify(0, SIG_READ);
+            // Armed after the notification, so not woken up by it.
+            let late = Arc::new(MockWaiter::new(TriggerMode::Edge, SIG_READ));
+            event.wait(late.clone());
+            defer::run_pending();
+            assert_eq!(early.notified(), vec![SIG_READ]);
+            assert!(late.notified().is_empty());
+
+            // Notifications after a delivery starts queue another one.
+            event.notify(SIG_READ, 0);
+            let queued = deferred_works();
+            defer::run_pending();
+            event.notify(0, SIG_READ);
+            assert_eq!(deferred_works(), queued + 1);
+            defer::run_pending();
+            assert_eq!(late.notified(), vec![SIG_READ]);
+
+            // Cancelling delivers the pending notifications first.
+            let waiter = Arc::new(MockWaiter::new(TriggerMode::Edge, SIG_WRITE));
+            event.wait(waiter.clone());
+            event.notify(0, SIG_WRITE);
+            event.cancel();
+            assert_eq!(waiter.notified(), vec![SIG_READ | SIG_WRITE]);
+            assert!(waiter.canceled().is_empty());
+            defer::run_pending();
+        }
+    }
 }
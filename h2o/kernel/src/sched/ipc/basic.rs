@@ -1,7 +1,9 @@
+use alloc::sync::Weak;
+
 use sv_call::Feature;
 
 use super::*;
-use crate::sched::task::hdl::DefaultFeature;
+use crate::sched::task::hdl::{DefaultFeature, KernelObject};
 
 #[derive(Debug, Default)]
 pub struct BasicEvent {
@@ -30,3 +32,9 @@ unsafe impl DefaultFeature for BasicEvent {
         Feature::SEND | Feature::SYNC | Feature::WAIT | Feature::EXECUTE
     }
 }
+
+impl KernelObject for BasicEvent {
+    fn event(this: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        Some(Arc::downgrade(this) as _)
+    }
+}
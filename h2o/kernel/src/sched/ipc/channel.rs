@@ -1,3 +1,5 @@
+#[cfg(ktest)]
+mod fuzz;
 mod syscall;
 
 use alloc::{
@@ -6,18 +8,28 @@ use alloc::{
 };
 use core::{
     mem,
-    sync::atomic::{AtomicU64, Ordering::SeqCst},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering::*},
 };
 
 use bytes::Bytes;
 use crossbeam_queue::SegQueue;
 use spin::Mutex;
-use sv_call::Feature;
+use sv_call::{
+    ipc::{ChanCredit, ChanInfo, ChanPeerId, ChanTaskId},
+    Feature,
+};
 
-use super::{Event, SIG_READ};
-use crate::sched::{
-    task::hdl::{self, DefaultFeature},
-    BasicEvent, PREEMPT, SCHED,
+use super::{Event, SIG_READ, SIG_WRITE};
+use crate::{
+    cpu::time::Instant,
+    mem::space::Phys,
+    sched::{
+        task::{
+            hdl::{self, DefaultFeature, KernelObject},
+            Tid, WeakTid,
+        },
+        BasicEvent, PREEMPT, SCHED,
+    },
 };
 
 const MAX_QUEUE_SIZE: usize = 2048;
@@ -27,6 +39,9 @@ pub struct Packet {
     id: usize,
     objects: Vec<hdl::Ref>,
     buffer: Bytes,
+    /// The size of the pages moved into the last object instead of the
+    /// buffer, or 0 if there's none.
+    payload: usize,
 }
 
 unsafe impl Send for Packet {}
@@ -39,6 +54,36 @@ impl Packet {
             id,
             objects,
             buffer,
+            payload: 0,
+        }
+    }
+
+    /// Create a packet taking the ownership of `buffer` without copying it.
+    pub fn with_buffer(id: usize, objects: Vec<hdl::Ref>, buffer: Vec<u8>) -> Self {
+        Packet {
+            id,
+            objects,
+            buffer: Bytes::from(buffer),
+            payload: 0,
+        }
+    }
+
+    /// Create a packet whose payload is moved along with `pages` of `size`
+    /// bytes instead of being copied.
+    pub fn with_pages(
+        id: usize,
+        mut objects: Vec<hdl::Ref>,
+        pages: Arc<Phys>,
+        size: usize,
+    ) -> Self {
+        // `Phys` always comes with its event, which the features require.
+        let pages = hdl::Ref::from_raw(pages).expect("Phys without its event");
+        objects.push(pages);
+        Packet {
+            id,
+            objects,
+            buffer: Bytes::new(),
+            payload: size,
         }
     }
 
@@ -56,50 +101,199 @@ impl Packet {
     pub fn object_count(&self) -> usize {
         self.objects.len()
     }
+
+    /// The size of the payload, either in the buffer or in the moved pages.
+    #[inline]
+    pub fn size(&self) -> usize {
+        if self.payload > 0 {
+            self.payload
+        } else {
+            self.buffer.len()
+        }
+    }
+}
+
+/// The credit that the peer has for sending packets into a channel side.
+#[derive(Debug)]
+struct Credit {
+    window: ChanCredit,
+    avail: Mutex<ChanCredit>,
+}
+
+impl Credit {
+    fn new(window: ChanCredit) -> Self {
+        Credit {
+            window,
+            avail: Mutex::new(window),
+        }
+    }
+
+    /// The credit charged for a packet of `size` bytes.
+    ///
+    /// A packet larger than the window, which is only possible with moved
+    /// pages, is charged the whole window so that it can still be sent once
+    /// all the credit is granted back.
+    #[inline]
+    fn charge(&self, size: usize) -> usize {
+        size.min(self.window.bytes)
+    }
+
+    /// Consume the credit for a packet of `size` bytes, clearing `SIG_WRITE`
+    /// of the sender's `event` once the credit is exhausted.
+    fn consume(&self, size: usize, event: &BasicEvent) -> sv_call::Result {
+        let size = self.charge(size);
+        PREEMPT.scope(|| {
+            let mut avail = self.avail.lock();
+            if avail.packets == 0 || avail.bytes < size {
+                event.notify(SIG_WRITE, 0);
+                return Err(sv_call::EAGAIN);
+            }
+            avail.packets -= 1;
+            avail.bytes -= size;
+            if avail.packets == 0 || avail.bytes == 0 {
+                event.notify(SIG_WRITE, 0);
+            }
+            Ok(())
+        })
+    }
+
+    /// Grant the credit of a received packet of `size` bytes back to the
+    /// sender, raising `SIG_WRITE` of the sender's `event`.
+    fn replenish(&self, size: usize, event: Option<&BasicEvent>) {
+        let size = self.charge(size);
+        PREEMPT.scope(|| {
+            let mut avail = self.avail.lock();
+            avail.packets = (avail.packets + 1).min(self.window.packets);
+            avail.bytes = (avail.bytes + size).min(self.window.bytes);
+            if let Some(event) = event {
+                event.notify(0, SIG_WRITE);
+            }
+        })
+    }
+}
+
+/// The room and the credit reserved for a packet in the peer of a channel.
+#[derive(Debug)]
+#[must_use = "The credit is granted back at once if dropped"]
+pub struct Reservation {
+    me: Arc<ChannelSide>,
+    peer: Option<Arc<ChannelSide>>,
+    size: usize,
+}
+
+impl Reservation {
+    /// Send `msg` into the peer, which must be of the reserved size.
+    pub fn commit(mut self, msg: &mut Packet) {
+        debug_assert_eq!(msg.size(), self.size);
+        let peer = self.peer.take().unwrap();
+        peer.msgs.push(mem::take(msg));
+        peer.stats
+            .peak_queue_len
+            .fetch_max(peer.msgs.len(), Relaxed);
+        self.me.stats.sent(self.size);
+        peer.event.notify(0, SIG_READ);
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(credit) = self.peer.as_ref().and_then(|peer| peer.credit.as_ref()) {
+            credit.replenish(self.size, Some(&self.me.event));
+        }
+    }
+}
+
+/// The traffic counters of a channel side.
+#[derive(Debug, Default)]
+struct Stats {
+    msgs_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    msgs_received: AtomicU64,
+    bytes_received: AtomicU64,
+    peak_queue_len: AtomicUsize,
+    peer_closed: AtomicU64,
+}
+
+impl Stats {
+    fn sent(&self, size: usize) {
+        self.msgs_sent.fetch_add(1, Relaxed);
+        self.bytes_sent.fetch_add(size as u64, Relaxed);
+    }
+
+    fn received(&self, size: usize) {
+        self.msgs_received.fetch_add(1, Relaxed);
+        self.bytes_received.fetch_add(size as u64, Relaxed);
+    }
 }
 
 #[derive(Debug)]
 struct ChannelSide {
     msgs: SegQueue<Packet>,
     event: Arc<BasicEvent>,
+    credit: Option<Credit>,
+    stats: Stats,
+    identity: Mutex<ChanPeerId>,
+    /// The task serving this side, namely the last one receiving from it.
+    server: Mutex<WeakTid>,
 }
 
-impl Default for ChannelSide {
-    #[inline]
-    fn default() -> Self {
+impl ChannelSide {
+    fn new(credit: Option<ChanCredit>) -> Self {
         ChannelSide {
             msgs: SegQueue::new(),
-            event: BasicEvent::new(0),
+            event: BasicEvent::new(if credit.is_some() { SIG_WRITE } else { 0 }),
+            credit: credit.map(Credit::new),
+            stats: Stats::default(),
+            identity: Mutex::new(ChanPeerId::default()),
+            server: Mutex::new(WeakTid::new()),
         }
     }
 }
 
+impl Default for ChannelSide {
+    #[inline]
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
 #[derive(Debug)]
 pub struct Channel {
     peer_id: u64,
     me: Arc<ChannelSide>,
     peer: Weak<ChannelSide>,
     head: Mutex<Option<Packet>>,
+    zero_copy: bool,
 }
 
 impl Channel {
+    #[inline]
     pub fn new() -> (Self, Self) {
+        Self::with_options(None, false)
+    }
+
+    /// Create a pair of channels, enabling flow control on both directions if
+    /// `credit` is specified, and accepting packets with moved pages if
+    /// `zero_copy` is set.
+    pub fn with_options(credit: Option<ChanCredit>, zero_copy: bool) -> (Self, Self) {
         static PEER_ID: AtomicU64 = AtomicU64::new(0);
         let peer_id = PEER_ID.fetch_add(1, SeqCst);
 
-        let q1 = Arc::new(ChannelSide::default());
-        let q2 = Arc::new(ChannelSide::default());
+        let q1 = Arc::new(ChannelSide::new(credit));
+        let q2 = Arc::new(ChannelSide::new(credit));
         let c1 = Channel {
             peer_id,
             me: Arc::clone(&q1),
             peer: Arc::downgrade(&q2),
             head: Mutex::new(None),
+            zero_copy,
         };
         let c2 = Channel {
             peer_id,
             me: q2,
             peer: Arc::downgrade(&q1),
             head: Mutex::new(None),
+            zero_copy,
         };
         (c1, c2)
     }
@@ -114,18 +308,98 @@ impl Channel {
         &self.me.event
     }
 
+    /// Record `task` as both the creator and the holder of this side.
+    pub fn set_creator(&self, task: ChanTaskId) {
+        PREEMPT.scope(|| {
+            *self.me.identity.lock() = ChanPeerId {
+                creator: task,
+                holder: task,
+            }
+        })
+    }
+
+    /// Record `task` as the holder of this side, which is the last one that
+    /// sends packets through it.
+    pub fn set_holder(&self, task: ChanTaskId) {
+        PREEMPT.scope(|| self.me.identity.lock().holder = task)
+    }
+
+    /// Record `task` as the server of this side, which callers through the
+    /// peer donate their priorities to.
+    pub fn set_server(&self, task: WeakTid) {
+        PREEMPT.scope(|| *self.me.server.lock() = task)
+    }
+
+    /// Get the task serving the peer, if it's still alive.
+    pub fn peer_server(&self) -> Option<Tid> {
+        let peer = self.peer.upgrade()?;
+        PREEMPT.scope(|| peer.server.lock().upgrade())
+    }
+
+    /// Get the identity of the peer.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the peer is closed.
+    pub fn peer_identity(&self) -> sv_call::Result<ChanPeerId> {
+        let peer = self.peer.upgrade().ok_or(sv_call::EPIPE)?;
+        Ok(PREEMPT.scope(|| *peer.identity.lock()))
+    }
+
+    #[inline]
+    pub fn zero_copy(&self) -> bool {
+        self.zero_copy
+    }
+
     /// # Errors
     ///
-    /// Returns error if the peer is closed or if the channel is full.
+    /// Returns error if the peer is closed, if the channel is full or if the
+    /// credit for flow control is exhausted.
+    #[inline]
     pub fn send(&self, msg: &mut Packet) -> sv_call::Result {
+        self.reserve(msg.size())?.commit(msg);
+        Ok(())
+    }
+
+    /// Reserve the room and the credit for a packet of `size` bytes, so that
+    /// sending it can't fail once the resources of the packet are taken.
+    ///
+    /// The credit is granted back if the reservation is dropped without being
+    /// committed.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the peer is closed, if the channel is full or if the
+    /// credit for flow control is exhausted.
+    pub fn reserve(&self, size: usize) -> sv_call::Result<Reservation> {
         let peer = self.peer.upgrade().ok_or(sv_call::EPIPE)?;
+        crate::fault::check(crate::fault::Point::Send)?;
         if peer.msgs.len() >= MAX_QUEUE_SIZE {
-            Err(sv_call::ENOSPC)
-        } else {
-            peer.msgs.push(mem::take(msg));
-            peer.event.notify(0, SIG_READ);
-            Ok(())
+            return Err(sv_call::ENOSPC);
+        }
+        // Moved pages are charged by the size of the payload as well.
+        if let Some(ref credit) = peer.credit {
+            credit.consume(size, &self.me.event)?;
         }
+        Ok(Reservation {
+            me: Arc::clone(&self.me),
+            peer: Some(peer),
+            size,
+        })
+    }
+
+    /// Deliver a packet to this side as if it's sent by the peer, even if the
+    /// peer is closed.
+    ///
+    /// The packet consumes no credit for flow control, and may exceed the
+    /// size limit of the queue so that delivering it never fails.
+    pub fn enqueue(&self, msg: Packet) {
+        self.me.msgs.push(msg);
+        self.me
+            .stats
+            .peak_queue_len
+            .fetch_max(self.me.msgs.len(), Relaxed);
+        self.me.event.notify(0, SIG_READ);
     }
 
     /// # Errors
@@ -157,23 +431,55 @@ impl Channel {
             *head = Some(packet);
             Err(sv_call::EBUFFER)
         } else {
+            if let Some(ref credit) = self.me.credit {
+                let peer = self.peer.upgrade();
+                credit.replenish(packet.size(), peer.as_deref().map(|peer| &*peer.event));
+            }
+            self.me.stats.received(packet.size());
             Ok(packet)
         };
-        *buffer_cap = buffer_size;
+        // Moved pages are reported with the size of the payload.
+        *buffer_cap = match ret {
+            Ok(ref packet) => packet.size(),
+            Err(_) => buffer_size,
+        };
         *handle_cap = handle_count;
         ret
     }
+
+    pub fn info(&self) -> ChanInfo {
+        let stats = &self.me.stats;
+        let pending = PREEMPT.scope(|| self.head.lock().is_some());
+        ChanInfo {
+            peer_id: self.peer_id,
+            msgs_sent: stats.msgs_sent.load(Relaxed),
+            bytes_sent: stats.bytes_sent.load(Relaxed),
+            msgs_received: stats.msgs_received.load(Relaxed),
+            bytes_received: stats.bytes_received.load(Relaxed),
+            queue_len: self.me.msgs.len() + pending as usize,
+            peak_queue_len: stats.peak_queue_len.load(Relaxed),
+            peer_closed: stats.peer_closed.load(Acquire),
+        }
+    }
 }
 
 unsafe impl DefaultFeature for Channel {
     fn default_features() -> Feature {
-        Feature::SEND | Feature::READ | Feature::WRITE | Feature::WAIT
+        Feature::SEND | Feature::READ | Feature::WRITE | Feature::WAIT | Feature::IDENTIFY
+    }
+}
+
+impl KernelObject for Channel {
+    fn event(this: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        Some(Arc::downgrade(&this.me.event) as _)
     }
 }
 
 impl Drop for Channel {
     fn drop(&mut self) {
         if let Some(peer) = self.peer.upgrade() {
+            let now = unsafe { Instant::now().raw() } as u64;
+            peer.stats.peer_closed.store(now.max(1), Release);
             peer.event.cancel();
         }
     }
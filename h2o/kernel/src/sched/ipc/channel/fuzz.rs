@@ -0,0 +1,408 @@
+//! An adversarial test of channels.
+//!
+//! Several simulated tasks send packets of random sizes and handle counts,
+//! receive them with random capacities, wait for them and close the channel
+//! sides at random moments. Every result is checked against a model of the
+//! queues, the flow control credits and the counters of the channels, and no
+//! handle may be leaked or dropped twice at the end.
+//!
+//! The cases run before the scheduler starts, so the tasks are interleaved on
+//! the bootstrap CPU at the granularity of single operations. The seed is
+//! logged and can be fixed with the boot option `ktest_seed=<n>`.
+
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+use sv_call::{ipc::ChanCredit, Feature, EAGAIN, EBUFFER, ENOENT, ENOSPC, EPIPE};
+
+use super::{Channel, Packet, MAX_QUEUE_SIZE};
+use crate::{
+    cpu::arch::apic::TriggerMode,
+    ktest::{case, MockWaiter},
+    sched::{
+        defer,
+        task::hdl::{self, DefaultFeature, KernelObject},
+        Event, Waiter, SIG_READ,
+    },
+};
+
+const PAIRS: usize = 4;
+const TASKS: usize = 6;
+const STEPS: usize = 20000;
+const MAX_SIZE: usize = 300;
+const MAX_HANDLES: usize = 4;
+const CREDIT: ChanCredit = ChanCredit {
+    packets: 8,
+    bytes: 1024,
+};
+
+static TOKENS: AtomicUsize = AtomicUsize::new(0);
+
+/// An object carried by the packets, counting its live instances.
+#[derive(Debug)]
+struct Token;
+
+impl Token {
+    fn new() -> Arc<Self> {
+        TOKENS.fetch_add(1, SeqCst);
+        Arc::new(Token)
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        TOKENS.fetch_sub(1, SeqCst);
+    }
+}
+
+unsafe impl DefaultFeature for Token {
+    fn default_features() -> Feature {
+        Feature::SEND
+    }
+}
+
+impl KernelObject for Token {
+    fn event(_: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        None
+    }
+}
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// A packet in flight, identified by its sequence number in its direction.
+#[derive(Debug, Clone, Copy)]
+struct Sent {
+    seq: usize,
+    size: usize,
+    handles: usize,
+}
+
+fn byte(seq: usize, index: usize) -> u8 {
+    (seq as u8).wrapping_mul(31).wrapping_add(index as u8)
+}
+
+/// A channel side and the model of the packets sent to it.
+#[derive(Default)]
+struct Side {
+    chan: Option<Channel>,
+    queue: VecDeque<Sent>,
+    /// Whether the front of `queue` is kept aside after an `EBUFFER`.
+    head: bool,
+    /// The credit left for sending packets to this side.
+    credit: Option<ChanCredit>,
+    next_send: usize,
+    next_recv: usize,
+    msgs_sent: u64,
+    bytes_sent: u64,
+    msgs_received: u64,
+    bytes_received: u64,
+    peer_closed: bool,
+}
+
+struct Pair {
+    sides: [Side; 2],
+}
+
+impl Pair {
+    fn open(rng: &mut Rng) -> Self {
+        let credit = rng.chance(50).then_some(CREDIT);
+        let (c1, c2) = Channel::with_options(credit, false);
+        let side = |chan| Side {
+            chan: Some(chan),
+            credit,
+            ..Default::default()
+        };
+        Pair {
+            sides: [side(c1), side(c2)],
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Task {
+    Idle,
+    /// Blocked on `obj_wait` for `SIG_READ` of a side.
+    Waiting {
+        pair: usize,
+        side: usize,
+        waiter: Arc<MockWaiter>,
+    },
+}
+
+struct Fuzzer {
+    rng: Rng,
+    pairs: Vec<Pair>,
+    tasks: Vec<Task>,
+}
+
+impl Fuzzer {
+    fn new(seed: u64) -> Self {
+        let mut rng = Rng(seed | 1);
+        let pairs = (0..PAIRS).map(|_| Pair::open(&mut rng)).collect();
+        let tasks = (0..TASKS).map(|_| Task::Idle).collect();
+        Fuzzer { rng, pairs, tasks }
+    }
+
+    /// Pick an open side, reopening a pair whose both sides are closed.
+    fn pick(&mut self) -> (usize, usize) {
+        let pair = self.rng.below(PAIRS);
+        let sides = &self.pairs[pair].sides;
+        if sides.iter().all(|side| side.chan.is_none()) {
+            self.pairs[pair] = Pair::open(&mut self.rng);
+        }
+        let side = self.rng.below(2);
+        if self.pairs[pair].sides[side].chan.is_some() {
+            (pair, side)
+        } else {
+            (pair, 1 - side)
+        }
+    }
+
+    fn send(&mut self, pair: usize, side: usize) {
+        let size = self.rng.below(MAX_SIZE + 1);
+        let handles = self.rng.below(MAX_HANDLES + 1);
+        let [me, peer] = sides_mut(&mut self.pairs[pair], side);
+
+        let seq = me.next_send;
+        let data = (0..size).map(|i| byte(seq, i)).collect::<Vec<_>>();
+        let objects = (0..handles)
+            .map(|_| hdl::Ref::from_raw(Token::new()).unwrap() as hdl::Ref)
+            .collect();
+        let mut packet = Packet::new(seq, objects, &data);
+
+        let expected = if peer.chan.is_none() {
+            Err(EPIPE)
+        } else if peer.queue.len() - peer.head as usize >= MAX_QUEUE_SIZE {
+            Err(ENOSPC)
+        } else {
+            match &mut peer.credit {
+                Some(credit) if credit.packets == 0 || credit.bytes < size => Err(EAGAIN),
+                Some(credit) => {
+                    credit.packets -= 1;
+                    credit.bytes -= size;
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        };
+        let ret = me.chan.as_ref().unwrap().send(&mut packet);
+        assert_eq!(ret, expected, "send of packet {seq}");
+
+        if ret.is_ok() {
+            assert_eq!(packet.object_count(), 0, "the handles must be moved");
+            peer.queue.push_back(Sent { seq, size, handles });
+            me.next_send += 1;
+            me.msgs_sent += 1;
+            me.bytes_sent += size as u64;
+        }
+    }
+
+    /// Receive a packet like `chan_recv`, returning whether one is received.
+    fn receive(&mut self, pair: usize, side: usize, buffer_cap: usize, handle_cap: usize) -> bool {
+        let [me, peer] = sides_mut(&mut self.pairs[pair], side);
+        let chan = me.chan.as_ref().unwrap();
+
+        let (mut buffer_size, mut handle_count) = (buffer_cap, handle_cap);
+        let ret = chan.receive(&mut buffer_size, &mut handle_count);
+        let Some(&front) = me.queue.front() else {
+            let expected = if peer.chan.is_some() { ENOENT } else { EPIPE };
+            assert_eq!(ret.err(), Some(expected));
+            return false;
+        };
+        assert_eq!((buffer_size, handle_count), (front.size, front.handles));
+
+        if front.size > buffer_cap || front.handles > handle_cap {
+            assert_eq!(ret.err(), Some(EBUFFER));
+            me.head = true;
+            return false;
+        }
+
+        let packet = ret.expect("Failed to receive a queued packet");
+        assert_eq!(packet.id, front.seq, "packets must be received in order");
+        assert_eq!(front.seq, me.next_recv);
+        assert_eq!(packet.object_count(), front.handles);
+        let expected = (0..front.size).map(|i| byte(front.seq, i));
+        assert!(
+            packet.buffer().iter().copied().eq(expected),
+            "corrupted buffer"
+        );
+        drop(packet);
+
+        me.queue.pop_front();
+        me.head = false;
+        me.next_recv += 1;
+        me.msgs_received += 1;
+        me.bytes_received += front.size as u64;
+        if let Some(credit) = &mut me.credit {
+            credit.packets = (credit.packets + 1).min(CREDIT.packets);
+            credit.bytes = (credit.bytes + front.size).min(CREDIT.bytes);
+        }
+        chan.event().notify(SIG_READ, 0);
+        true
+    }
+
+    /// Drain a side and wait for it to become readable, like a task looping on
+    /// `chan_recv` and `obj_wait`.
+    fn wait(&mut self, task: usize, pair: usize, side: usize) {
+        while self.receive(pair, side, MAX_SIZE, MAX_HANDLES) {}
+        if self.pairs[pair].sides[1 - side].chan.is_none() {
+            return;
+        }
+        let waiter = Arc::new(MockWaiter::new(TriggerMode::Level, SIG_READ));
+        let event = self.pairs[pair].sides[side].chan.as_ref().unwrap().event();
+        event.wait(waiter.clone());
+        self.tasks[task] = Task::Waiting { pair, side, waiter };
+    }
+
+    fn close(&mut self, pair: usize, side: usize) {
+        for task in &mut self.tasks {
+            if let Task::Waiting {
+                pair: p,
+                side: s,
+                waiter,
+            } = task
+            {
+                if (*p, *s) == (pair, side) {
+                    let event = self.pairs[pair].sides[side].chan.as_ref().unwrap().event();
+                    event.unwait(&(waiter.clone() as Arc<dyn Waiter>));
+                    *task = Task::Idle;
+                }
+            }
+        }
+
+        let [me, peer] = sides_mut(&mut self.pairs[pair], side);
+        me.chan = None;
+        me.queue.clear();
+        me.head = false;
+        peer.peer_closed = true;
+
+        // The tasks waiting on the peer are woken up by the cancellation.
+        for task in &mut self.tasks {
+            if let Task::Waiting {
+                pair: p,
+                side: s,
+                waiter,
+            } = task
+            {
+                if (*p, *s) == (pair, 1 - side) {
+                    assert!(!waiter.canceled().is_empty(), "waiter not canceled");
+                    *task = Task::Idle;
+                }
+            }
+        }
+    }
+
+    fn check_info(&self, pair: usize, side: usize) {
+        let me = &self.pairs[pair].sides[side];
+        let info = me.chan.as_ref().unwrap().info();
+        assert_eq!(info.queue_len, me.queue.len());
+        assert!(info.peak_queue_len >= me.queue.len() - me.head as usize);
+        assert_eq!(info.msgs_sent, me.msgs_sent);
+        assert_eq!(info.bytes_sent, me.bytes_sent);
+        assert_eq!(info.msgs_received, me.msgs_received);
+        assert_eq!(info.bytes_received, me.bytes_received);
+        assert_eq!(info.peer_closed != 0, me.peer_closed);
+    }
+
+    /// Wake up the waiting tasks whose sides have become readable, and check
+    /// that no wakeup is lost.
+    fn wake(&mut self) {
+        for task in &mut self.tasks {
+            let Task::Waiting { pair, side, waiter } = task else {
+                continue;
+            };
+            let notified = !waiter.notified().is_empty();
+            let readable = !self.pairs[*pair].sides[*side].queue.is_empty();
+            assert!(notified || !readable, "lost wakeup on pair {pair}");
+            if notified {
+                *task = Task::Idle;
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        // As if the deferred-work executor runs between the operations.
+        defer::run_pending();
+        let task = self.rng.below(TASKS);
+        if let Task::Waiting { .. } = self.tasks[task] {
+            return self.wake();
+        }
+
+        let (pair, side) = self.pick();
+        match self.rng.below(100) {
+            0..=44 => self.send(pair, side),
+            45..=74 => {
+                let buffer_cap = if self.rng.chance(80) {
+                    MAX_SIZE
+                } else {
+                    self.rng.below(MAX_SIZE + 1)
+                };
+                let handle_cap = if self.rng.chance(80) {
+                    MAX_HANDLES
+                } else {
+                    self.rng.below(MAX_HANDLES + 1)
+                };
+                self.receive(pair, side, buffer_cap, handle_cap);
+            }
+            75..=86 => self.wait(task, pair, side),
+            87..=96 => self.check_info(pair, side),
+            _ => self.close(pair, side),
+        }
+        self.wake();
+    }
+}
+
+fn sides_mut(pair: &mut Pair, side: usize) -> [&mut Side; 2] {
+    let [s0, s1] = &mut pair.sides;
+    if side == 0 {
+        [s0, s1]
+    } else {
+        [s1, s0]
+    }
+}
+
+case! {
+    fn channel_fuzz() {
+        let seed = crate::cmdline_option("ktest_seed")
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(archop::rand::get);
+        log::info!("channel fuzz seed: {seed}");
+
+        let live = TOKENS.load(SeqCst);
+        let mut fuzzer = Fuzzer::new(seed);
+        for _ in 0..STEPS {
+            fuzzer.step();
+        }
+        for task in &mut fuzzer.tasks {
+            if let Task::Waiting { pair, side, waiter } = task {
+                let event = fuzzer.pairs[*pair].sides[*side].chan.as_ref().unwrap().event();
+                event.unwait(&(waiter.clone() as Arc<dyn Waiter>));
+            }
+        }
+        drop(fuzzer);
+        assert_eq!(TOKENS.load(SeqCst), live, "leaked or doubly dropped handles");
+    }
+}
@@ -1,27 +1,74 @@
-use core::slice;
+use core::time::Duration;
 
+use bitop_ex::BitOpEx;
+use paging::{LAddr, PAGE_SHIFT};
 use sv_call::{
-    ipc::{RawPacket, MAX_BUFFER_SIZE, MAX_HANDLE_COUNT},
+    ipc::{
+        ChanCredit, ChanInfo, ChanOptions, ChanPeerId, ChanTaskId, RawPacket, RawSegment,
+        MAX_BUFFER_SIZE, MAX_HANDLE_COUNT, MAX_SEGMENT_COUNT,
+    },
     *,
 };
 
 use super::*;
 use crate::{
-    sched::SIG_READ,
-    syscall::{In, InOut, Out, UserPtr},
+    cpu::time,
+    sched::{
+        task::{hdl::HandleMap, inherit, Ready},
+        Blocker, SIG_READ,
+    },
+    syscall::{copy_to_user, In, InOut, Out, UserPtr},
 };
 
+#[inline]
+fn task_id(cur: &Ready) -> ChanTaskId {
+    ChanTaskId {
+        task: cur.tid().raw(),
+        job: cur.space().id(),
+    }
+}
+
 #[syscall]
 fn chan_new(p1: UserPtr<Out, Handle>, p2: UserPtr<Out, Handle>) -> Result {
+    chan_new_impl(None, false, p1, p2)
+}
+
+#[syscall]
+fn chan_new_with(
+    options: ChanOptions,
+    credit: UserPtr<In, ChanCredit>,
+    p1: UserPtr<Out, Handle>,
+    p2: UserPtr<Out, Handle>,
+) -> Result {
+    let credit = if options.contains(ChanOptions::FLOW_CONTROL) {
+        let credit = unsafe { credit.read()? };
+        // Every packet must be able to be sent with a full credit window.
+        if credit.packets == 0 || credit.bytes < MAX_BUFFER_SIZE {
+            return Err(EINVAL);
+        }
+        Some(credit)
+    } else {
+        None
+    };
+    let zero_copy = options.contains(ChanOptions::ZERO_COPY);
+    chan_new_impl(credit, zero_copy, p1, p2)
+}
+
+fn chan_new_impl(
+    credit: Option<ChanCredit>,
+    zero_copy: bool,
+    p1: UserPtr<Out, Handle>,
+    p2: UserPtr<Out, Handle>,
+) -> Result {
     p1.check()?;
     p2.check()?;
     SCHED.with_current(|cur| {
-        let (c1, c2) = Channel::new();
+        let (c1, c2) = Channel::with_options(credit, zero_copy);
+        c1.set_creator(task_id(cur));
+        c2.set_creator(task_id(cur));
         let map = cur.space().handles();
-        let e1 = Arc::downgrade(&c1.me.event) as _;
-        let e2 = Arc::downgrade(&c2.me.event) as _;
-        let h1 = map.insert(c1, Some(e1))?;
-        let h2 = map.insert(c2, Some(e2))?;
+        let h1 = map.insert(c1)?;
+        let h2 = map.insert(c2)?;
         unsafe {
             p1.write(h1)?;
             p2.write(h2)
@@ -29,24 +76,42 @@ fn chan_new(p1: UserPtr<Out, Handle>, p2: UserPtr<Out, Handle>) -> Result {
     })
 }
 
-fn chan_send_impl<F, R>(hdl: Handle, packet: UserPtr<In, RawPacket>, send: F) -> Result<R>
-where
-    F: FnOnce(&Channel, &mut Packet) -> Result<R>,
-{
+/// Send a packet through `hdl`, whose buffer is gathered from `segments`
+/// instead if it's not `None`.
+fn chan_send_impl(
+    hdl: Handle,
+    packet: UserPtr<In, RawPacket>,
+    segments: Option<&[RawSegment]>,
+) -> Result {
     hdl.check_null()?;
 
-    let packet = unsafe { packet.read()? };
-    if packet.buffer_size > MAX_BUFFER_SIZE || packet.handle_count >= MAX_HANDLE_COUNT {
+    let mut packet = unsafe { packet.read()? };
+    if packet.handle_count >= MAX_HANDLE_COUNT {
         return Err(ENOMEM);
     }
-    UserPtr::<In, Handle>::new(packet.handles).check_slice(packet.handle_count)?;
-    UserPtr::<In>::new(packet.buffer).check_slice(packet.buffer_size)?;
+    if let Some(segments) = segments {
+        packet.buffer_size = segments_len(segments)?;
+        // Gathered buffers can't be moved.
+        if packet.buffer_size > MAX_BUFFER_SIZE {
+            return Err(ENOMEM);
+        }
+    } else {
+        UserPtr::<In>::new(packet.buffer).check_slice(packet.buffer_size)?;
+    }
+    // Larger buffers can only be moved, which is checked against the channel.
+    let zero_copy = packet.buffer_size > MAX_BUFFER_SIZE;
 
-    let handles = unsafe { slice::from_raw_parts(packet.handles, packet.handle_count) };
+    let handles = read_slice(packet.handles, packet.handle_count)?;
     if handles.contains(&hdl) {
         return Err(EPERM);
     }
-    let buffer = unsafe { slice::from_raw_parts(packet.buffer, packet.buffer_size) };
+    // Copy the buffer before the handles are taken, so that they're kept if it
+    // faults.
+    let buffer = match segments {
+        Some(segments) => gather(segments, packet.buffer_size)?,
+        None if zero_copy => Vec::new(),
+        None => read_slice(packet.buffer, packet.buffer_size)?,
+    };
 
     SCHED.with_current(|cur| {
         let map = cur.space().handles();
@@ -56,13 +121,82 @@ where
         }
         let channel = Arc::clone(&obj);
         drop(obj);
+        if zero_copy && !channel.zero_copy() {
+            return Err(ENOMEM);
+        }
 
-        let objects = map.send(handles, &channel)?;
-        let mut packet = Packet::new(packet.id, objects, buffer);
-        send(&channel, &mut packet)
+        // The packet is reserved before the handles are taken, and both before
+        // the pages are taken, so that nothing is consumed if sending fails.
+        let reservation = channel.reserve(packet.buffer_size)?;
+        let (pages, objects) = if zero_copy {
+            let base = LAddr::from(packet.buffer as usize);
+            let len = packet.buffer_size.round_up_bit(PAGE_SHIFT);
+            let root = cur.space().root();
+            let (pages, objects) = root.take(base, len, || map.send(&handles, &channel))?;
+            (Some(pages), objects)
+        } else {
+            (None, map.send(&handles, &channel)?)
+        };
+        channel.set_holder(task_id(cur));
+        let mut packet = match pages {
+            Some(pages) => Packet::with_pages(packet.id, objects, pages, packet.buffer_size),
+            None => Packet::with_buffer(packet.id, objects, buffer),
+        };
+        reservation.commit(&mut packet);
+        Ok(())
     })
 }
 
+fn read_slice<T: Copy>(ptr: *const T, len: usize) -> Result<Vec<T>> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).map_err(|_| ENOMEM)?;
+    unsafe {
+        UserPtr::<In, T>::new(ptr as *mut T).read_slice(buf.as_mut_ptr(), len)?;
+        buf.set_len(len);
+    }
+    Ok(buf)
+}
+
+/// Read the segments of a buffer, which are at most [`MAX_SEGMENT_COUNT`].
+fn read_segments(segments: UserPtr<In, RawSegment>, count: usize) -> Result<Vec<RawSegment>> {
+    if count > MAX_SEGMENT_COUNT {
+        return Err(EINVAL);
+    }
+    read_slice(segments.as_ptr(), count)
+}
+
+fn segments_len(segments: &[RawSegment]) -> Result<usize> {
+    segments
+        .iter()
+        .try_fold(0usize, |len, seg| len.checked_add(seg.len))
+        .ok_or(ENOMEM)
+}
+
+fn gather(segments: &[RawSegment], len: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).map_err(|_| ENOMEM)?;
+    for seg in segments {
+        unsafe {
+            let dst = buf.as_mut_ptr().add(buf.len());
+            UserPtr::<In>::new(seg.ptr).read_slice(dst, seg.len)?;
+            buf.set_len(buf.len() + seg.len);
+        }
+    }
+    Ok(buf)
+}
+
+fn scatter(segments: &[RawSegment], mut data: &[u8]) -> Result {
+    for seg in segments {
+        if data.is_empty() {
+            break;
+        }
+        let len = seg.len.min(data.len());
+        unsafe { copy_to_user(seg.ptr, data.as_ptr(), len) }?;
+        data = &data[len..];
+    }
+    Ok(())
+}
+
 #[inline]
 fn read_raw(packet_ptr: UserPtr<In, RawPacket>) -> Result<RawPacket> {
     let raw = unsafe { packet_ptr.read()? };
@@ -75,15 +209,27 @@ fn read_raw(packet_ptr: UserPtr<In, RawPacket>) -> Result<RawPacket> {
 #[inline]
 fn receive_handles<E: ?Sized + Event>(
     res: Result<Packet>,
-    map: &crate::sched::task::hdl::HandleMap,
+    map: &HandleMap,
     raw: &mut RawPacket,
     event: Arc<E>,
 ) -> Result<Packet> {
     match res {
         Ok(mut packet) => {
-            let handles = unsafe { slice::from_raw_parts_mut(raw.handles, raw.handle_cap) };
-            map.receive(&mut packet.objects, handles);
+            let mut handles = Vec::new();
+            handles
+                .try_reserve_exact(packet.objects.len())
+                .map_err(|_| ENOMEM)?;
+            handles.resize(packet.objects.len(), Handle::NULL);
+            map.receive(&mut packet.objects, &mut handles);
             event.notify(SIG_READ, 0);
+            if let Err(err) = UserPtr::<Out, Handle>::new(raw.handles).write_slice(&handles) {
+                // The caller never gets the handles, so they're dropped instead
+                // of leaking in its table.
+                for &hdl in handles.iter().filter(|&&hdl| hdl != Handle::NULL) {
+                    let _ = map.remove_ref(hdl);
+                }
+                return Err(err);
+            }
             Ok(packet)
         }
         Err(e) => Err(e),
@@ -96,10 +242,9 @@ fn write_raw_with_rest_of_packet(
     mut raw: RawPacket,
     res: Result<Packet>,
 ) -> Result {
-    let ret = res.map(|packet| unsafe {
+    let ret = res.and_then(|packet| unsafe {
         raw.id = packet.id;
-        raw.buffer
-            .copy_from_nonoverlapping(packet.buffer().as_ptr(), packet.buffer().len());
+        copy_to_user(raw.buffer, packet.buffer().as_ptr(), packet.buffer().len())
     });
 
     unsafe { packet_ptr.write(raw) }?;
@@ -108,29 +253,221 @@ fn write_raw_with_rest_of_packet(
 
 #[syscall]
 fn chan_send(hdl: Handle, packet: UserPtr<In, RawPacket>) -> Result {
-    chan_send_impl(hdl, packet, |channel, packet| channel.send(packet))
+    chan_send_impl(hdl, packet, None)
 }
 
+/// Like `chan_send`, but gathers the buffer from the `count` segments in
+/// `segments`, ignoring the buffer of `packet`.
+///
+/// The gathered buffer can't exceed `MAX_BUFFER_SIZE`.
 #[syscall]
-fn chan_recv(hdl: Handle, packet_ptr: UserPtr<InOut, RawPacket>) -> Result {
+fn chan_sendv(
+    hdl: Handle,
+    packet: UserPtr<In, RawPacket>,
+    segments: UserPtr<In, RawSegment>,
+    count: usize,
+) -> Result {
+    let segments = read_segments(segments, count)?;
+    chan_send_impl(hdl, packet, Some(&segments))
+}
+
+#[syscall]
+fn chan_info(hdl: Handle, info: UserPtr<Out, ChanInfo>) -> Result {
     hdl.check_null()?;
 
-    let mut raw = read_raw(packet_ptr.r#in())?;
+    let data = SCHED.with_current(|cur| {
+        let channel = cur.space().handles().get::<Channel>(hdl)?;
+        Ok(channel.info())
+    })?;
+    info.write(data)
+}
 
-    let res = SCHED.with_current(|cur| {
-        let map = cur.space().handles();
-        let channel = map.get::<Channel>(hdl)?;
-        if !channel.features().contains(Feature::READ) {
+#[syscall]
+fn chan_peer_id(hdl: Handle, id: UserPtr<Out, ChanPeerId>) -> Result {
+    hdl.check_null()?;
+
+    let data = SCHED.with_current(|cur| {
+        let channel = cur.space().handles().get::<Channel>(hdl)?;
+        if !channel.features().contains(Feature::IDENTIFY) {
             return Err(EPERM);
         }
+        channel.peer_identity()
+    })?;
+    id.write(data)
+}
 
-        raw.buffer_size = raw.buffer_cap;
-        raw.handle_count = raw.handle_cap;
-        let res = channel.receive(&mut raw.buffer_size, &mut raw.handle_count);
-        let event = (**channel).event().clone();
-        drop(channel);
-        receive_handles(res, map, &mut raw, event)
-    });
+#[syscall]
+fn chan_recv(hdl: Handle, packet_ptr: UserPtr<InOut, RawPacket>) -> Result {
+    hdl.check_null()?;
+
+    let mut raw = read_raw(packet_ptr.r#in())?;
+
+    let res = SCHED.with_current(|cur| chan_recv_impl(cur, hdl, &mut raw));
 
     write_raw_with_rest_of_packet(packet_ptr.out(), raw, res)
 }
+
+/// Like `chan_recv`, but scatters the buffer into the `count` segments in
+/// `segments` in order, ignoring the buffer of `packet`.
+///
+/// `buffer_size` is written back with the size of the packet, and the
+/// segments' total size is taken as the capacity of the buffer.
+#[syscall]
+fn chan_recvv(
+    hdl: Handle,
+    packet_ptr: UserPtr<InOut, RawPacket>,
+    segments: UserPtr<In, RawSegment>,
+    count: usize,
+) -> Result {
+    hdl.check_null()?;
+
+    let segments = read_segments(segments, count)?;
+    for seg in &segments {
+        UserPtr::<Out>::new(seg.ptr).check_slice(seg.len)?;
+    }
+    let mut raw = unsafe { packet_ptr.r#in().read()? };
+    UserPtr::<Out, Handle>::new(raw.handles).check_slice(raw.handle_cap)?;
+    raw.buffer_cap = segments_len(&segments)?;
+
+    let res = SCHED.with_current(|cur| chan_recv_impl(cur, hdl, &mut raw));
+    let ret = res.and_then(|packet| {
+        raw.id = packet.id;
+        scatter(&segments, packet.buffer())
+    });
+
+    unsafe { packet_ptr.out().write(raw) }?;
+    ret
+}
+
+fn chan_recv_impl(cur: &Ready, hdl: Handle, raw: &mut RawPacket) -> Result<Packet> {
+    let map = cur.space().handles();
+    let channel = map.get::<Channel>(hdl)?;
+    if !channel.features().contains(Feature::READ) {
+        return Err(EPERM);
+    }
+    // Receiving, even without any packet, serves the callers through the peer.
+    channel.set_server(cur.tid().downgrade());
+
+    raw.buffer_size = raw.buffer_cap;
+    raw.handle_count = raw.handle_cap;
+    let res = channel.receive(&mut raw.buffer_size, &mut raw.handle_count);
+    let event = (**channel).event().clone();
+    drop(channel);
+    receive_handles(res, map, raw, event)
+}
+
+/// Send a packet through `hdl`, and then receive one from `hdl`, or from
+/// `other` if it's not null, waiting until either of them is readable.
+///
+/// Returns the handle of the channel the packet is received from. `hdl` is
+/// tried first, so `EBUFFER` comes from `other` only if `hdl` has no packet.
+/// Once the packet is sent, `recv` is written back even if the call fails
+/// afterwards, with zero sizes unless it fails with `EBUFFER`.
+///
+/// The current task donates its priority to the task serving the peer of
+/// `hdl` until the call returns or times out.
+#[syscall]
+fn chan_send_recv(
+    hdl: Handle,
+    send: UserPtr<In, RawPacket>,
+    other: Handle,
+    recv: UserPtr<InOut, RawPacket>,
+    timeout_us: u64,
+) -> Result<Handle> {
+    let deadline = time::deadline_after(time::from_us(timeout_us));
+    chan_send_recv_impl(hdl, send, other, recv, deadline)
+}
+
+/// Like `chan_send_recv`, but receives until the deadline of `deadline_ns`
+/// nanoseconds of the clock.
+#[syscall]
+fn chan_send_recv_until(
+    hdl: Handle,
+    send: UserPtr<In, RawPacket>,
+    other: Handle,
+    recv: UserPtr<InOut, RawPacket>,
+    deadline_ns: u64,
+) -> Result<Handle> {
+    let deadline = time::deadline_from_ns(deadline_ns);
+    chan_send_recv_impl(hdl, send, other, recv, deadline)
+}
+
+fn chan_send_recv_impl(
+    hdl: Handle,
+    send: UserPtr<In, RawPacket>,
+    other: Handle,
+    recv: UserPtr<InOut, RawPacket>,
+    deadline: Option<Instant>,
+) -> Result<Handle> {
+    hdl.check_null()?;
+    let mut raw = read_raw(recv.r#in())?;
+
+    // Check the receiving sides before sending, so that nothing fails in
+    // between.
+    let (events, _donor) = SCHED.with_current(|cur| {
+        let map = cur.space().handles();
+        let event = |hdl| {
+            let channel = map.get::<Channel>(hdl)?;
+            if !channel.features().contains(Feature::READ | Feature::WAIT) {
+                return Err(EPERM);
+            }
+            channel.event().upgrade().ok_or(EPIPE)
+        };
+        let other = (!other.is_null()).then(|| event(other)).transpose()?;
+        let events = (event(hdl)?, other);
+
+        // Donate before sending, so that the server preempts others with the
+        // priority once woken up by the packet.
+        let server = map.get::<Channel>(hdl)?.peer_server();
+        let donor = server.map(|server| inherit::donate(cur.tid(), server, deadline));
+        Ok((events, donor))
+    })?;
+
+    chan_send_impl(hdl, send, None)?;
+
+    loop {
+        let mut from = hdl;
+        let res = SCHED.with_current(|cur| match chan_recv_impl(cur, hdl, &mut raw) {
+            Err(ENOENT) if !other.is_null() => {
+                from = other;
+                chan_recv_impl(cur, other, &mut raw)
+            }
+            res => res,
+        });
+        let res = match res {
+            Err(ENOENT) => {
+                let remaining = time::remaining(deadline);
+                if !remaining.is_zero() {
+                    match wait_readable(&events, remaining) {
+                        Ok(()) => continue,
+                        Err(err) => Err(err),
+                    }
+                } else {
+                    Err(ETIME)
+                }
+            }
+            res => res,
+        };
+        if let Err(err) = res {
+            if err != EBUFFER {
+                raw.buffer_size = 0;
+                raw.handle_count = 0;
+            }
+        }
+        break write_raw_with_rest_of_packet(recv.out(), raw, res).map(|_| from);
+    }
+}
+
+/// Wait until either of the channels is readable.
+fn wait_readable(events: &(Arc<dyn Event>, Option<Arc<dyn Event>>), timeout: Duration) -> Result {
+    let blocker = Blocker::new(&events.0, true, true, SIG_READ);
+    if let Some(ref other) = events.1 {
+        other.wait(Arc::clone(&blocker) as _);
+    }
+    let ret = blocker.wait(None, timeout, true);
+    if let Some(ref other) = events.1 {
+        other.unwait(&(Arc::clone(&blocker) as _));
+    }
+    blocker.detach();
+    ret
+}
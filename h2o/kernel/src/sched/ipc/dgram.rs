@@ -0,0 +1,283 @@
+//! The datagram endpoints, delivering unordered messages on a best-effort
+//! basis.
+//!
+//! Every endpoint belongs to a bus and has an address unique in it. A datagram
+//! is sent to either an endpoint of the bus or all the other ones, and it's
+//! dropped silently if the queue of the receiver is full, so a sender is never
+//! blocked by a slow receiver. Datagrams carry no handles.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicU64, Ordering::*};
+
+use crossbeam_queue::SegQueue;
+use spin::Mutex;
+use sv_call::{
+    ipc::{DGRAM_BROADCAST, DGRAM_QUEUE_SIZE},
+    Feature,
+};
+
+use super::{Event, SIG_READ};
+use crate::sched::{
+    task::hdl::{DefaultFeature, KernelObject},
+    BasicEvent, PREEMPT,
+};
+
+#[derive(Debug, Default)]
+struct Bus {
+    members: Mutex<Vec<(u64, Weak<Datagram>)>>,
+    next_addr: AtomicU64,
+}
+
+#[derive(Debug)]
+pub struct Datagram {
+    bus: Arc<Bus>,
+    addr: u64,
+    msgs: SegQueue<(u64, Vec<u8>)>,
+    event: Arc<BasicEvent>,
+    dropped: AtomicU64,
+}
+
+impl Datagram {
+    /// Create an endpoint on the bus of `bus`, or on a new bus if it's `None`.
+    pub fn new(bus: Option<&Datagram>) -> sv_call::Result<Arc<Self>> {
+        let bus = bus.map_or_else(Default::default, |other| Arc::clone(&other.bus));
+        let addr = bus.next_addr.fetch_add(1, Relaxed);
+        let ret = Arc::try_new(Datagram {
+            bus: Arc::clone(&bus),
+            addr,
+            msgs: SegQueue::new(),
+            event: BasicEvent::new(0),
+            dropped: AtomicU64::new(0),
+        })?;
+        PREEMPT.scope(|| {
+            let mut members = bus.members.lock();
+            members.try_reserve(1).map_err(|_| sv_call::ENOMEM)?;
+            members.push((addr, Arc::downgrade(&ret)));
+            Ok::<_, sv_call::Error>(())
+        })?;
+        Ok(ret)
+    }
+
+    #[inline]
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    #[inline]
+    pub fn event(&self) -> &Arc<BasicEvent> {
+        &self.event
+    }
+
+    /// The number of the datagrams dropped because the queue was full.
+    #[inline]
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Relaxed)
+    }
+
+    /// Send `data` to the endpoint of `addr`, or to all the other endpoints of
+    /// the bus if it's [`DGRAM_BROADCAST`].
+    ///
+    /// # Errors
+    ///
+    /// Returns error if no endpoint has the address. The datagram dropped by
+    /// a full receiver is not an error.
+    pub fn send_to(&self, addr: u64, data: &[u8]) -> sv_call::Result {
+        let receivers = PREEMPT.scope(|| {
+            let members = self.bus.members.lock();
+            let mut receivers = Vec::new();
+            receivers
+                .try_reserve(members.len())
+                .map_err(|_| sv_call::ENOMEM)?;
+            receivers.extend(
+                { members.iter() }
+                    .filter(|(a, _)| match addr {
+                        DGRAM_BROADCAST => *a != self.addr,
+                        addr => *a == addr,
+                    })
+                    .filter_map(|(_, member)| member.upgrade()),
+            );
+            Ok::<_, sv_call::Error>(receivers)
+        })?;
+        if addr != DGRAM_BROADCAST && receivers.is_empty() {
+            return Err(sv_call::ENOENT);
+        }
+
+        for receiver in receivers {
+            if receiver.msgs.len() >= DGRAM_QUEUE_SIZE {
+                receiver.dropped.fetch_add(1, Relaxed);
+                continue;
+            }
+            let mut buf = Vec::new();
+            if buf.try_reserve_exact(data.len()).is_err() {
+                receiver.dropped.fetch_add(1, Relaxed);
+                continue;
+            }
+            buf.extend_from_slice(data);
+            receiver.msgs.push((self.addr, buf));
+            receiver.event.notify(0, SIG_READ);
+        }
+        Ok(())
+    }
+
+    /// Receive a datagram with the address of its sender.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if there's no datagram.
+    pub fn recv_from(&self) -> sv_call::Result<(u64, Vec<u8>)> {
+        let ret = self.msgs.pop().ok_or(sv_call::ENOENT)?;
+        if self.msgs.is_empty() {
+            self.event.notify(SIG_READ, 0);
+            // A datagram may arrive before the signal is cleared.
+            if !self.msgs.is_empty() {
+                self.event.notify(0, SIG_READ);
+            }
+        }
+        Ok(ret)
+    }
+}
+
+unsafe impl DefaultFeature for Datagram {
+    fn default_features() -> Feature {
+        Feature::SEND | Feature::SYNC | Feature::READ | Feature::WRITE | Feature::WAIT
+    }
+}
+
+impl KernelObject for Datagram {
+    fn event(this: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        Some(Arc::downgrade(&this.event) as _)
+    }
+}
+
+impl Drop for Datagram {
+    fn drop(&mut self) {
+        PREEMPT.scope(|| {
+            let mut members = self.bus.members.lock();
+            members.retain(|(addr, _)| *addr != self.addr);
+        });
+        self.event.cancel();
+    }
+}
+
+mod syscall {
+    use sv_call::{ipc::MAX_BUFFER_SIZE, *};
+
+    use super::*;
+    use crate::{
+        sched::SCHED,
+        syscall::{In, Out, UserPtr},
+    };
+
+    /// Create a datagram endpoint on the bus of `bus`, or on a new bus if it's
+    /// null, writing its address to `addr`.
+    #[syscall]
+    fn dgram_new(bus: Handle, addr: UserPtr<Out, u64>) -> Result<Handle> {
+        addr.check()?;
+        SCHED.with_current(|cur| {
+            let map = cur.space().handles();
+            let obj = if bus.is_null() {
+                Datagram::new(None)?
+            } else {
+                let bus = map.get::<Datagram>(bus)?;
+                if !bus.features().contains(Feature::READ) {
+                    return Err(EPERM);
+                }
+                let bus: &Datagram = &bus;
+                Datagram::new(Some(bus))?
+            };
+            addr.write(obj.addr())?;
+            map.insert_raw(obj)
+        })
+    }
+
+    /// Send the datagram in `buffer` to the endpoint of `addr` on the bus of
+    /// `hdl`, or to all the other endpoints if it's `DGRAM_BROADCAST`.
+    #[syscall]
+    fn dgram_send_to(hdl: Handle, addr: u64, buffer: UserPtr<In>, len: usize) -> Result {
+        hdl.check_null()?;
+        if len > MAX_BUFFER_SIZE {
+            return Err(ENOMEM);
+        }
+        let mut data = Vec::new();
+        data.try_reserve_exact(len).map_err(|_| ENOMEM)?;
+        unsafe {
+            buffer.read_slice(data.as_mut_ptr(), len)?;
+            data.set_len(len);
+        }
+
+        let obj = SCHED.with_current(|cur| {
+            let obj = cur.space().handles().get::<Datagram>(hdl)?;
+            if !obj.features().contains(Feature::WRITE) {
+                return Err(EPERM);
+            }
+            Ok(Arc::clone(&obj))
+        })?;
+        obj.send_to(addr, &data)
+    }
+
+    /// Receive a datagram into `buffer` from `hdl`, writing the address of its
+    /// sender to `from`.
+    ///
+    /// The datagram is truncated to `len` bytes. Returns its whole size.
+    #[syscall]
+    fn dgram_recv_from(
+        hdl: Handle,
+        buffer: UserPtr<Out>,
+        len: usize,
+        from: UserPtr<Out, u64>,
+    ) -> Result<usize> {
+        hdl.check_null()?;
+        buffer.check_slice(len)?;
+        from.check()?;
+
+        let obj = SCHED.with_current(|cur| {
+            let obj = cur.space().handles().get::<Datagram>(hdl)?;
+            if !obj.features().contains(Feature::READ) {
+                return Err(EPERM);
+            }
+            Ok(Arc::clone(&obj))
+        })?;
+        let (addr, data) = obj.recv_from()?;
+        let count = data.len().min(len);
+        if count > 0 {
+            buffer.write_slice(&data[..count])?;
+        }
+        from.write(addr)?;
+        Ok(data.len())
+    }
+}
+
+#[cfg(ktest)]
+mod ktests {
+    use super::*;
+    use crate::{ktest::case, sched::defer};
+
+    case! {
+        fn dgram_broadcast_and_drop() {
+            let a = Datagram::new(None).unwrap();
+            let b = Datagram::new(Some(&*a)).unwrap();
+            let c = Datagram::new(Some(&*b)).unwrap();
+            assert_eq!(a.send_to(5, b"x"), Err(sv_call::ENOENT));
+
+            a.send_to(DGRAM_BROADCAST, b"hello").unwrap();
+            assert_eq!(a.recv_from(), Err(sv_call::ENOENT));
+            for ep in [&b, &c] {
+                assert_eq!(ep.recv_from(), Ok((a.addr(), b"hello".to_vec())));
+                assert_eq!(ep.event().event_data().signal().load(SeqCst), 0);
+            }
+
+            for _ in 0..=DGRAM_QUEUE_SIZE {
+                c.send_to(b.addr(), b"").unwrap();
+            }
+            assert_eq!(b.dropped(), 1);
+            assert_eq!(b.event().event_data().signal().load(SeqCst), SIG_READ);
+
+            drop(c);
+            assert_eq!(a.send_to(2, b""), Err(sv_call::ENOENT));
+            defer::run_pending();
+        }
+    }
+}
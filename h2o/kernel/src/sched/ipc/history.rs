@@ -0,0 +1,247 @@
+//! The signal history of the events, for debugging lost wake-ups.
+//!
+//! Every event records its last [`HISTORY_LEN`] operations: the changes of its
+//! signal, and the waiters armed, woken up and removed. The blockers are
+//! watched, so that the histories of the events with tasks still blocked on
+//! them are dumped to the kernel log by [`dump`] along with the scheduler
+//! diagnostics.
+//!
+//! A blocker that stays armed through a change of the signal satisfying it is
+//! reported as a lost wake-up, either when it times out or when it's dumped,
+//! which panics with the `event-history-assert` feature.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::Ordering::{Acquire, SeqCst};
+
+use spin::Mutex;
+
+use super::{Event, SignalMatch, Waiter, WaiterData};
+use crate::{cpu::time::Instant, logger::HAS_TIME, sched::PREEMPT};
+
+/// The number of the operations recorded for every event.
+pub const HISTORY_LEN: usize = 32;
+
+/// The minimum number of the watched waiters kept before pruning the dead
+/// ones.
+const MIN_PRUNE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// The signal is changed by setting bits.
+    Set,
+    /// The signal is changed by clearing bits only.
+    Clear,
+    Wait,
+    Wake,
+    Unwait,
+    Cancel,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    time: Instant,
+    op: Op,
+    signal: usize,
+    waiter: usize,
+}
+
+#[derive(Debug)]
+struct Ring {
+    entries: [Option<Entry>; HISTORY_LEN],
+    next: usize,
+}
+
+impl Ring {
+    /// The entries from the oldest to the newest.
+    fn iter(&self) -> impl Iterator<Item = &Entry> + '_ {
+        let (newer, older) = self.entries.split_at(self.next);
+        older.iter().chain(newer).flatten()
+    }
+}
+
+#[derive(Debug)]
+pub struct History {
+    ring: Mutex<Ring>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            ring: Mutex::new(Ring {
+                entries: [None; HISTORY_LEN],
+                next: 0,
+            }),
+        }
+    }
+}
+
+impl History {
+    /// Record an operation, with the signal after it and the key of the
+    /// waiter involved if any.
+    pub fn record(&self, op: Op, signal: usize, waiter: usize) {
+        let time = HAS_TIME
+            .load(Acquire)
+            .then(Instant::now)
+            .unwrap_or(unsafe { Instant::from_raw(0) });
+        PREEMPT.scope(|| {
+            let mut ring = self.ring.lock();
+            let next = ring.next;
+            ring.entries[next] = Some(Entry {
+                time,
+                op,
+                signal,
+                waiter,
+            });
+            ring.next = (next + 1) % HISTORY_LEN;
+        })
+    }
+
+    /// Whether the waiter of `key` stays armed through a change of the signal
+    /// satisfying `waiter_data`.
+    ///
+    /// Returns `false` if its arming is no longer recorded.
+    pub fn missed(&self, key: usize, waiter_data: &WaiterData) -> bool {
+        let exact = waiter_data.matching() == SignalMatch::Exact;
+        let (mut armed, mut missed) = (false, false);
+        PREEMPT.scope(|| {
+            for entry in self.ring.lock().iter() {
+                match entry.op {
+                    Op::Wait if entry.waiter == key => (armed, missed) = (true, false),
+                    Op::Wake | Op::Unwait if entry.waiter == key => armed = false,
+                    Op::Cancel => armed = false,
+                    Op::Set | Op::Clear if armed && (exact || entry.op == Op::Set) => {
+                        missed |= waiter_data.can_signal(entry.signal, false)
+                    }
+                    _ => {}
+                }
+            }
+        });
+        armed && missed
+    }
+
+    pub fn dump(&self) {
+        let entries = PREEMPT.scope(|| self.ring.lock().iter().copied().collect::<Vec<_>>());
+        for entry in entries {
+            log::error!(
+                "  [{}] {:?} signal {:#x}, waiter {:#x}",
+                entry.time,
+                entry.op,
+                entry.signal,
+                entry.waiter
+            );
+        }
+    }
+}
+
+struct Watched {
+    event: Weak<dyn Event>,
+    waiter: Weak<dyn Waiter>,
+}
+
+struct WatchList {
+    list: Vec<Watched>,
+    prune_at: usize,
+}
+
+static WATCHED: Mutex<WatchList> = Mutex::new(WatchList {
+    list: Vec::new(),
+    prune_at: MIN_PRUNE,
+});
+
+#[inline]
+fn waiter_key(waiter: &Arc<dyn Waiter>) -> usize {
+    let (key, _) = Arc::as_ptr(waiter).to_raw_parts();
+    key as usize
+}
+
+/// Watch `waiter` on `event`, dumping the history of the event if it's still
+/// armed when the scheduler diagnostics are dumped.
+pub fn watch(event: &Arc<dyn Event>, waiter: &Arc<dyn Waiter>) {
+    PREEMPT.scope(|| {
+        let mut watched = WATCHED.lock();
+        if watched.list.len() >= watched.prune_at {
+            watched.list.retain(|w| w.waiter.strong_count() > 0);
+            watched.prune_at = (watched.list.len() * 2).max(MIN_PRUNE);
+        }
+        watched.list.push(Watched {
+            event: Arc::downgrade(event),
+            waiter: Arc::downgrade(waiter),
+        })
+    })
+}
+
+fn report(event: &dyn Event, key: usize) {
+    let signal = event.event_data().signal().load(SeqCst);
+    log::error!("Lost wake-up of waiter {key:#x} on event {event:p}, signal {signal:#x}:");
+    event.event_data().wakes.history.dump();
+    #[cfg(feature = "event-history-assert")]
+    panic!("Lost wake-up of waiter {key:#x}");
+}
+
+/// Check the waiter of `key` timed out on `event` for a lost wake-up.
+pub fn check(event: &dyn Event, key: usize, waiter_data: &WaiterData) {
+    // The pending notifications are not lost.
+    event.flush();
+    let wakes = &event.event_data().wakes;
+    let armed = PREEMPT.scope(|| wakes.waiters.contains_key(&key));
+    if armed && wakes.history.missed(key, waiter_data) {
+        report(event, key);
+    }
+}
+
+/// Dump the histories of the events with watched waiters still armed on them
+/// to the kernel log.
+pub fn dump() {
+    let watched = PREEMPT.scope(|| {
+        let watched = WATCHED.lock();
+        { watched.list.iter() }
+            .filter_map(|w| Some((w.event.upgrade()?, w.waiter.upgrade()?)))
+            .collect::<Vec<_>>()
+    });
+    for (event, waiter) in watched {
+        let key = waiter_key(&waiter);
+        let wakes = &event.event_data().wakes;
+        if !PREEMPT.scope(|| wakes.waiters.contains_key(&key)) {
+            continue;
+        }
+        if wakes.history.missed(key, &waiter.waiter_data()) {
+            report(&*event, key);
+            continue;
+        }
+        log::error!(
+            "Waiter {key:#x} armed on event {event:p}, signal {:#x}:",
+            event.event_data().signal().load(SeqCst)
+        );
+        wakes.history.dump();
+    }
+}
+
+#[cfg(ktest)]
+mod ktests {
+    use super::*;
+    use crate::{cpu::arch::apic::TriggerMode, ktest::case};
+
+    case! {
+        fn history_missed() {
+            let history = History::default();
+            let edge = WaiterData::new(TriggerMode::Edge, 0b10);
+            history.record(Op::Set, 0b10, 0);
+            history.record(Op::Wait, 0b10, 1);
+            assert!(!history.missed(1, &edge));
+
+            history.record(Op::Set, 0b11, 0);
+            assert!(history.missed(1, &edge));
+            history.record(Op::Wake, 0b11, 1);
+            assert!(!history.missed(1, &edge));
+
+            // Overwritten by the later operations.
+            for _ in 0..HISTORY_LEN {
+                history.record(Op::Set, 0b10, 0);
+            }
+            assert!(!history.missed(1, &edge));
+        }
+    }
+}
@@ -0,0 +1,375 @@
+//! The ports, aggregating the readiness of many objects.
+//!
+//! An object is bound to a port once with a key chosen by the user, and every
+//! time its signal satisfies the binding, a record with the key is queued in
+//! the port. The binding is re-armed when its record is dequeued, so each
+//! binding has at most one record queued, and a level-triggered binding still
+//! satisfied fires again right away. Unlike a dispatcher, nothing needs to be
+//! pushed again after each wake-up, and the records are dequeued in batches.
+
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crossbeam_queue::SegQueue;
+use spin::Mutex;
+use sv_call::{ipc::PortRecord, Feature, Result, EEXIST, ENOENT, ENOSPC};
+
+use super::{Event, Waiter, WaiterData, SIG_READ};
+use crate::sched::{
+    task::hdl::{DefaultFeature, KernelObject},
+    BasicEvent, PREEMPT,
+};
+
+#[derive(Debug)]
+struct Binding {
+    key: u64,
+    port: Weak<Port>,
+    event: Weak<dyn Event>,
+    waiter_data: WaiterData,
+}
+
+impl Binding {
+    fn fire(&self, signal: usize, canceled: bool) {
+        if let Some(port) = self.port.upgrade() {
+            port.ready.push((
+                PortRecord {
+                    key: self.key,
+                    signal,
+                    canceled,
+                },
+                self as *const _ as usize,
+            ));
+            port.event.notify(0, SIG_READ);
+        }
+    }
+}
+
+impl Waiter for Binding {
+    #[inline]
+    fn waiter_data(&self) -> WaiterData {
+        self.waiter_data
+    }
+
+    fn on_cancel(&self, _: *const (), signal: usize) {
+        self.fire(signal, true)
+    }
+
+    fn on_notify(&self, signal: usize) {
+        self.fire(signal, false)
+    }
+}
+
+#[derive(Debug)]
+pub struct Port {
+    event: Arc<BasicEvent>,
+    capacity: usize,
+    bindings: Mutex<BTreeMap<u64, Arc<Binding>>>,
+    /// The records with the addresses of the bindings firing them, so that
+    /// the ones of the bindings removed since are told apart.
+    ready: SegQueue<(PortRecord, usize)>,
+}
+
+impl Port {
+    pub fn new(capacity: usize) -> Result<Arc<Self>> {
+        Ok(Arc::try_new(Port {
+            event: BasicEvent::new(0),
+            capacity,
+            bindings: Mutex::new(BTreeMap::new()),
+            ready: SegQueue::new(),
+        })?)
+    }
+
+    #[inline]
+    pub fn event(&self) -> &Arc<BasicEvent> {
+        &self.event
+    }
+
+    /// Bind `event` to the port with `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the key is already bound or the port is full.
+    pub fn bind(
+        self: &Arc<Self>,
+        event: &Arc<dyn Event>,
+        key: u64,
+        waiter_data: WaiterData,
+    ) -> Result {
+        let binding = Arc::try_new(Binding {
+            key,
+            port: Arc::downgrade(self),
+            event: Arc::downgrade(event),
+            waiter_data,
+        })?;
+        PREEMPT.scope(|| {
+            let mut bindings = self.bindings.lock();
+            if bindings.contains_key(&key) {
+                return Err(EEXIST);
+            }
+            if bindings.len() >= self.capacity {
+                return Err(ENOSPC);
+            }
+            bindings.insert(key, Arc::clone(&binding));
+            Ok(())
+        })?;
+        event.wait(binding);
+        Ok(())
+    }
+
+    /// Remove the binding of `key`, dropping its queued record if any.
+    pub fn unbind(&self, key: u64) -> Result {
+        let binding = PREEMPT.scope(|| self.bindings.lock().remove(&key));
+        let binding = binding.ok_or(ENOENT)?;
+        if let Some(event) = binding.event.upgrade() {
+            event.unwait(&(binding as _));
+        }
+        Ok(())
+    }
+
+    /// Dequeue at most `count` records, re-arming their bindings.
+    ///
+    /// The bindings of the canceled records are removed.
+    pub fn dequeue(&self, count: usize) -> Result<Vec<PortRecord>> {
+        let count = count.min(self.ready.len());
+        let mut ret = Vec::new();
+        let mut rearm = Vec::new();
+        ret.try_reserve(count).map_err(|_| sv_call::ENOMEM)?;
+        rearm.try_reserve(count).map_err(|_| sv_call::ENOMEM)?;
+
+        while ret.len() < count {
+            let Some((record, addr)) = self.ready.pop() else {
+                break;
+            };
+            let binding = PREEMPT.scope(|| {
+                let mut bindings = self.bindings.lock();
+                let binding = bindings.get(&record.key)?;
+                // Unbound, maybe bound again with the same key, since fired.
+                if Arc::as_ptr(binding) as usize != addr {
+                    return None;
+                }
+                if record.canceled {
+                    bindings.remove(&record.key)
+                } else {
+                    Some(Arc::clone(binding))
+                }
+            });
+            let Some(binding) = binding else {
+                continue;
+            };
+            if !record.canceled {
+                rearm.push(binding);
+            }
+            ret.push(record);
+        }
+
+        // Re-armed after all the records are dequeued, so that a binding
+        // still satisfied doesn't show up twice in a batch.
+        for binding in rearm {
+            match binding.event.upgrade() {
+                Some(event) => event.wait(binding),
+                // The event is gone without being canceled.
+                None => PREEMPT.scope(|| {
+                    let mut bindings = self.bindings.lock();
+                    if bindings
+                        .get(&binding.key)
+                        .map_or(false, |b| Arc::ptr_eq(b, &binding))
+                    {
+                        bindings.remove(&binding.key);
+                    }
+                }),
+            }
+        }
+
+        if self.ready.is_empty() {
+            self.event.notify(SIG_READ, 0);
+            // A record may be queued before the signal is cleared.
+            if !self.ready.is_empty() {
+                self.event.notify(0, SIG_READ);
+            }
+        }
+        if ret.is_empty() {
+            return Err(ENOENT);
+        }
+        Ok(ret)
+    }
+}
+
+impl Drop for Port {
+    fn drop(&mut self) {
+        let bindings = core::mem::take(self.bindings.get_mut());
+        for (_, binding) in bindings {
+            if let Some(event) = binding.event.upgrade() {
+                event.unwait(&(binding as _));
+            }
+        }
+        self.event.cancel();
+    }
+}
+
+unsafe impl DefaultFeature for Port {
+    fn default_features() -> Feature {
+        Feature::SEND | Feature::SYNC | Feature::READ | Feature::WRITE | Feature::WAIT
+    }
+}
+
+impl KernelObject for Port {
+    fn event(this: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        Some(Arc::downgrade(&this.event) as _)
+    }
+}
+
+mod syscall {
+    use sv_call::{ipc::WaitOptions, *};
+
+    use super::*;
+    use crate::{
+        cpu::arch::apic::TriggerMode,
+        sched::{SignalMatch, SCHED},
+        syscall::{Out, UserPtr},
+    };
+
+    #[syscall]
+    fn port_new(capacity: usize) -> Result<Handle> {
+        let port = Port::new(capacity)?;
+        SCHED.with_current(|cur| cur.space().handles().insert_raw(port))
+    }
+
+    /// Bind the object of `hdl` to the port with `key`, queuing a record
+    /// every time its signal matches `signal` as specified by `options`.
+    ///
+    /// `WAKE_ALL` and `CONSUME` are not supported.
+    #[syscall]
+    fn port_bind(
+        port: Handle,
+        hdl: Handle,
+        key: u64,
+        options: WaitOptions,
+        signal: usize,
+    ) -> Result {
+        port.check_null()?;
+        hdl.check_null()?;
+        if options.intersects(WaitOptions::WAKE_ALL | WaitOptions::CONSUME)
+            || options.contains(WaitOptions::MATCH_ANY | WaitOptions::MATCH_EXACT)
+        {
+            return Err(EINVAL);
+        }
+        let trigger_mode = if options.contains(WaitOptions::LEVEL_TRIGGERED) {
+            TriggerMode::Level
+        } else {
+            TriggerMode::Edge
+        };
+        let matching = if options.contains(WaitOptions::MATCH_ANY) {
+            SignalMatch::Any
+        } else if options.contains(WaitOptions::MATCH_EXACT) {
+            SignalMatch::Exact
+        } else {
+            SignalMatch::All
+        };
+        let waiter_data = WaiterData::new(trigger_mode, signal).with_matching(matching);
+
+        let (port, event) = SCHED.with_current(|cur| {
+            let obj = cur.space().handles().get_ref(hdl)?;
+            let port = cur.space().handles().get::<Port>(port)?;
+            if !obj.features().contains(Feature::WAIT) {
+                return Err(EPERM);
+            }
+            if !port.features().contains(Feature::WRITE) {
+                return Err(EPERM);
+            }
+            let event = obj.event().upgrade().ok_or(EPIPE)?;
+            Ok((Arc::clone(&port), event))
+        })?;
+        port.bind(&event, key, waiter_data)
+    }
+
+    #[syscall]
+    fn port_unbind(port: Handle, key: u64) -> Result {
+        port.check_null()?;
+        let port = SCHED.with_current(|cur| {
+            let port = cur.space().handles().get::<Port>(port)?;
+            if !port.features().contains(Feature::WRITE) {
+                return Err(EPERM);
+            }
+            Ok(Arc::clone(&port))
+        })?;
+        port.unbind(key)
+    }
+
+    /// Dequeue at most `count` records into `records`, returning the number
+    /// of them, or `ENOENT` if there's none.
+    ///
+    /// Wait for `SIG_READ` on the port for the records to come.
+    #[syscall]
+    fn port_dequeue(
+        port: Handle,
+        records: UserPtr<Out, PortRecord>,
+        count: usize,
+    ) -> Result<usize> {
+        port.check_null()?;
+        records.check_slice(count)?;
+        let port = SCHED.with_current(|cur| {
+            let port = cur.space().handles().get::<Port>(port)?;
+            if !port.features().contains(Feature::READ) {
+                return Err(EPERM);
+            }
+            Ok(Arc::clone(&port))
+        })?;
+        let ret = port.dequeue(count)?;
+        records.write_slice(&ret)?;
+        Ok(ret.len())
+    }
+}
+
+#[cfg(ktest)]
+mod ktests {
+    use alloc::vec;
+    use core::sync::atomic::Ordering::SeqCst;
+
+    use super::*;
+    use crate::{cpu::arch::apic::TriggerMode, ktest::case, sched::defer};
+
+    fn record(key: u64, signal: usize, canceled: bool) -> PortRecord {
+        PortRecord {
+            key,
+            signal,
+            canceled,
+        }
+    }
+
+    case! {
+        fn port_rearm_and_cancel() {
+            let port = Port::new(2).unwrap();
+            let a = BasicEvent::new(0);
+            let b = BasicEvent::new(SIG_READ);
+            let level = WaiterData::new(TriggerMode::Level, SIG_READ);
+            let edge = WaiterData::new(TriggerMode::Edge, SIG_READ);
+            port.bind(&(a.clone() as _), 1, edge).unwrap();
+            port.bind(&(b.clone() as _), 2, level).unwrap();
+            assert_eq!(port.bind(&(b.clone() as _), 2, level), Err(EEXIST));
+            assert_eq!(port.bind(&(b.clone() as _), 3, level), Err(ENOSPC));
+
+            // Only the level-triggered binding is satisfied at once, and it's
+            // satisfied again as soon as it's re-armed.
+            assert_eq!(port.dequeue(8), Ok(vec![record(2, SIG_READ, false)]));
+            assert_eq!(port.dequeue(8), Ok(vec![record(2, SIG_READ, false)]));
+
+            b.notify(SIG_READ, 0);
+            a.notify(0, SIG_READ);
+            defer::run_pending();
+            let records = vec![record(2, SIG_READ, false), record(1, SIG_READ, false)];
+            assert_eq!(port.dequeue(8), Ok(records));
+            assert_eq!(port.dequeue(8), Err(ENOENT));
+            assert_eq!(port.event().event_data().signal().load(SeqCst), 0);
+
+            a.cancel();
+            port.unbind(2).unwrap();
+            b.notify(0, SIG_READ);
+            defer::run_pending();
+            assert_eq!(port.dequeue(8), Ok(vec![record(1, SIG_READ, true)]));
+            assert_eq!(port.unbind(1), Err(ENOENT));
+        }
+    }
+}
@@ -2,11 +2,16 @@ mod boot;
 pub mod ctx;
 mod elf;
 mod excep;
+pub mod filter;
+mod freeze;
 pub mod hdl;
 mod idle;
+pub mod inherit;
+pub mod kthread;
 mod sig;
 mod sm;
 mod space;
+pub mod stat;
 mod syscall;
 mod tid;
 
@@ -17,7 +22,15 @@ use paging::LAddr;
 #[cfg(target_arch = "x86_64")]
 pub use self::ctx::arch::{DEFAULT_STACK_LAYOUT, DEFAULT_STACK_SIZE};
 use self::elf::from_elf;
-pub use self::{boot::VDSO, excep::dispatch_exception, sig::Signal, sm::*, space::Space, tid::Tid};
+pub use self::{
+    boot::{add_vdso_features, set_vdso_clock, VDSO},
+    excep::dispatch_exception,
+    freeze::{freeze, Frozen},
+    sig::Signal,
+    sm::*,
+    space::Space,
+    tid::{Tid, WeakTid},
+};
 use super::{ipc::Channel, Arsc, PREEMPT};
 use crate::cpu::{CpuMask, Lazy};
 
@@ -27,6 +40,34 @@ pub enum Type {
     User,
 }
 
+/// The precedence of a task over the others on its CPU when it's woken up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Priority {
+    /// Never preempts the running task.
+    Low,
+    #[default]
+    Normal,
+    /// Always preempts the running task of a lower priority.
+    High,
+}
+
+impl Priority {
+    /// Get the priority of `raw`, which is the same as `TASK_PRIO_*`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `raw` is not a valid priority.
+    #[inline]
+    pub fn from_raw(raw: u8) -> Self {
+        match raw {
+            0 => Priority::Low,
+            1 => Priority::Normal,
+            2 => Priority::High,
+            _ => panic!("Invalid priority {raw}"),
+        }
+    }
+}
+
 impl Type {
     /// # Errors
     ///
@@ -44,7 +85,7 @@ impl Type {
 
 #[inline(never)]
 pub(super) fn init() {
-    Lazy::force(&idle::CTX_DROPPER);
+    idle::init_ctx_dropper();
     Lazy::force(&idle::IDLE);
 }
 
@@ -75,7 +116,9 @@ fn exec_inner(
         .excep_chan(Arsc::try_new(Default::default())?)
         .name(name.unwrap_or(format!("{}.func{}", cur.name(), archop::rand::get())))
         .ty(ty)
+        .job(space.id())
         .affinity(affinity.unwrap_or_else(|| cur.affinity()))
+        .syscall_filter(cur.syscall_filter())
         .build()
         .unwrap();
 
@@ -105,11 +148,7 @@ fn exec(
     let cur = super::SCHED.with_current(|cur| Ok(cur.tid().clone()))?;
     let init = exec_inner(cur, name, None, None, space, init_chan, starter)?;
     super::SCHED.with_current(|cur| {
-        let event = Arc::downgrade(&init.tid().event) as _;
-        let handle = cur
-            .space()
-            .handles()
-            .insert(init.tid().clone(), Some(event))?;
+        let handle = cur.space().handles().insert(init.tid().clone())?;
         Ok((init, handle))
     })
 }
@@ -127,7 +166,9 @@ fn create(
         .excep_chan(Arsc::try_new(Default::default())?)
         .name(name.unwrap_or(format!("{}.func{}", cur.name(), archop::rand::get())))
         .ty(ty)
+        .job(space.id())
         .affinity(cur.affinity())
+        .syscall_filter(cur.syscall_filter())
         .build()
         .unwrap();
 
@@ -141,11 +182,7 @@ fn create(
     let init = Init::new(tid, space, kstack, ext_frame);
 
     super::SCHED.with_current(|cur| {
-        let event = Arc::downgrade(&init.tid().event) as _;
-        let handle = cur
-            .space()
-            .handles()
-            .insert(init.tid().clone(), Some(event))?;
+        let handle = cur.space().handles().insert(init.tid().clone())?;
         Ok((init, handle))
     })
 }
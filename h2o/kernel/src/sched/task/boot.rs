@@ -1,14 +1,20 @@
 use alloc::{sync::Weak, vec::Vec};
-use core::mem;
+use core::{
+    mem, ptr,
+    sync::atomic::{AtomicU64, Ordering::SeqCst},
+};
 
 use archop::Azy;
 use bitop_ex::BitOpEx;
-use sv_call::Feature;
+use sv_call::{Feature, KernelFeatures};
 use targs::Targs;
 
 use super::{hdl::DefaultFeature, *};
 use crate::{
-    cpu::arch::tsc::TSC_CLOCK,
+    cpu::{
+        arch::tsc::TSC_CLOCK,
+        time::chip::{ClockSource, CLOCK},
+    },
     mem::space::{self, Flags, Phys, PhysTrait, Virt},
     sched::SCHED,
 };
@@ -53,6 +59,57 @@ fn flags_to_feat(flags: Flags) -> Feature {
     feat
 }
 
+fn constants() -> *mut sv_call::Constants {
+    #[allow(clippy::zero_prefixed_literal)]
+    let offset = include!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/target/constant_offset.rs"
+    ));
+    unsafe { VDSO.1.base().to_laddr(minfo::ID_OFFSET).add(offset) }.cast()
+}
+
+/// Tell the VDSO the current clock source, so that it reads the time from the
+/// kernel if the TSC is no longer reliable.
+pub fn set_vdso_clock(source: ClockSource) {
+    unsafe { ptr::addr_of_mut!((*constants()).clock_source).write_volatile(source) }
+}
+
+/// Tell the VDSO the additional `features` found after the boot.
+pub fn add_vdso_features(features: KernelFeatures) {
+    unsafe {
+        let ptr = ptr::addr_of_mut!((*constants()).features);
+        (*ptr.cast::<AtomicU64>()).fetch_or(features.bits(), SeqCst);
+    }
+}
+
+fn kernel_features() -> KernelFeatures {
+    let mut features = KernelFeatures::empty();
+    if archop::rand::has_builtin() {
+        features |= KernelFeatures::BUILTIN_RAND;
+    }
+    if crate::dev::sleep::regs().is_ok() {
+        features |= KernelFeatures::SUSPEND;
+    }
+    if crate::dev::watchdog::has_backend() {
+        features |= KernelFeatures::HW_WATCHDOG;
+    }
+    if cfg!(ktest) {
+        features |= KernelFeatures::KTEST;
+    }
+    if cfg!(bench) {
+        features |= KernelFeatures::BENCH;
+    }
+    features
+}
+
+fn syscall_bitmap() -> [u64; sv_call::SYSCALL_WORDS] {
+    let mut bitmap = [0; sv_call::SYSCALL_WORDS];
+    for num in 0..crate::syscall::count() {
+        bitmap[num / 64] |= 1 << (num % 64);
+    }
+    bitmap
+}
+
 pub fn setup() {
     unsafe {
         let constants = sv_call::Constants {
@@ -61,17 +118,11 @@ pub fn setup() {
             ticks_shift: TSC_CLOCK.sft,
             has_builtin_rand: archop::rand::has_builtin(),
             num_cpus: crate::cpu::count(),
+            clock_source: CLOCK.source(),
+            features: kernel_features().bits(),
+            syscalls: syscall_bitmap(),
         };
-
-        #[allow(clippy::zero_prefixed_literal)]
-        let offset = include!(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/target/constant_offset.rs"
-        ));
-        let ptr = { VDSO.1.base().to_laddr(minfo::ID_OFFSET) }
-            .add(offset)
-            .cast::<sv_call::Constants>();
-        ptr.write(constants);
+        self::constants().write(constants);
     }
 
     let mut objects = Vec::<hdl::Ref>::new();
@@ -80,13 +131,13 @@ pub fn setup() {
     // `targs::HandleIndex`.
 
     let mem_res = Arc::clone(crate::dev::mem_resource());
-    objects.push(hdl::Ref::from_raw(mem_res, None).expect("Failed to create memory resource"));
+    objects.push(hdl::Ref::from_raw(mem_res).expect("Failed to create memory resource"));
 
     let pio_res = Arc::clone(crate::dev::pio_resource());
-    objects.push(hdl::Ref::from_raw(pio_res, None).expect("Failed to create port I/O resource"));
+    objects.push(hdl::Ref::from_raw(pio_res).expect("Failed to create port I/O resource"));
 
     let gsi_res = Arc::clone(crate::dev::gsi_resource());
-    objects.push(hdl::Ref::from_raw(gsi_res, None).expect("Failed to create GSI resource"));
+    objects.push(hdl::Ref::from_raw(gsi_res).expect("Failed to create GSI resource"));
 
     unsafe {
         objects.push(
@@ -111,17 +162,38 @@ pub fn setup() {
         );
     }
 
+    let framebuffer = crate::kargs().framebuffer.map(|fb| {
+        let flags = Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS;
+        let phys = space::new_mmio(fb.phys, fb.size.round_up_bit(paging::PAGE_SHIFT))
+            .expect("Failed to create framebuffer object");
+        let obj = unsafe { hdl::Ref::from_raw_unchecked(phys, flags_to_feat(flags), None) };
+        objects.push(obj.expect("Failed to create framebuffer reference"));
+        crate::logger::earlycon::release();
+
+        targs::Framebuffer {
+            width: fb.width,
+            height: fb.height,
+            stride: fb.stride,
+            format: if fb.bgr {
+                targs::FB_FORMAT_BGRX
+            } else {
+                targs::FB_FORMAT_RGBX
+            },
+        }
+    });
+
     let buf = {
         let targs = Targs {
             rsdp: *crate::kargs().rsdp,
             smbios: *crate::kargs().smbios,
+            cmdline: crate::kargs().cmdline,
+            framebuffer: framebuffer.unwrap_or_default(),
         };
         unsafe { mem::transmute::<_, [u8; mem::size_of::<Targs>()]>(targs) }
     };
 
     let (me, chan) = Channel::new();
-    let event = Arc::downgrade(chan.event()) as _;
-    let chan = unsafe { hdl::Ref::try_new(chan, Some(event)).expect("Failed to create channel") };
+    let chan = hdl::Ref::try_new(chan).expect("Failed to create channel");
     me.send(&mut crate::sched::ipc::Packet::new(0, objects, &buf))
         .expect("Failed to send message");
     let image = unsafe {
@@ -138,5 +210,9 @@ pub fn setup() {
         chan,
     )
     .expect("Failed to initialize TINIT");
+
+    let (saved, _) = space::zero_page_stat();
+    log::info!("{saved} pages saved by the zero page for TINIT");
+
     SCHED.unblock(tinit, true);
 }
@@ -111,6 +111,12 @@ impl Frame {
         self.rsi = entry.args[1];
     }
 
+    /// Whether the frame is saved from the user mode.
+    #[inline]
+    pub fn is_user(&self) -> bool {
+        self.cs == SegSelector::into_val(USR_CODE_X64) as u64
+    }
+
     #[inline]
     pub fn set_args(&mut self, arg0: u64, arg1: u64) {
         self.rdi = arg0;
@@ -206,6 +212,18 @@ impl Frame {
         Ok(())
     }
 
+    /// # Errors
+    ///
+    /// Returns error if `fs_base` is invalid.
+    #[inline]
+    pub fn set_fs_base(&mut self, fs_base: u64) -> sv_call::Result<()> {
+        if !archop::canonical(LAddr::from(fs_base)) {
+            return Err(sv_call::EINVAL);
+        }
+        self.fs_base = fs_base;
+        Ok(())
+    }
+
     const RFLAGS: &'static str =
         "CF - PF - AF - ZF SF TF IF DF OF IOPLL IOPLH NT - RF VM AC VIF VIP ID";
 
@@ -261,7 +279,12 @@ unsafe extern "C" fn save_regs() {
 pub(super) unsafe extern "C" fn switch_finishing(pree_value: usize, pree_flags: u64) {
     let _pree = PREEMPT.from_raw(pree_value, pree_flags);
     if let Some(ref cur) = *crate::sched::SCHED.current() {
-        log::trace!("Switched to task {:?}, P{}", cur.tid().raw(), PREEMPT.raw());
+        log::trace!(
+            "Switched to task {:?} {:?}, P{}",
+            cur.tid().raw(),
+            cur.tid().name(),
+            PREEMPT.raw()
+        );
         debug_assert!(!cur.running_state.not_running());
 
         let tss_rsp0 = cur.kstack.top().val() as u64;
@@ -95,7 +95,10 @@ fn load_prog(
                 let csize = cend - cstart;
                 log::trace!("Copying {:?}", dst..LAddr::from(dst.val() + csize));
 
-                dst.copy_from_nonoverlapping(src, csize);
+                // The tail of the file is copied into the new space.
+                space::with(space, |_| {
+                    crate::syscall::with_user_access(|| dst.copy_from_nonoverlapping(src, csize))
+                });
             }
         }
     }
@@ -159,7 +162,7 @@ pub fn from_elf(
     let init_chan = space.handles().insert_ref(init_chan)?;
 
     let (entry, stack_size) = load_elf(space.mem(), &file, image)?;
-    let stack = space::init_stack(space.mem(), stack_size)?;
+    let stack = space::init_stack(space.mem(), stack_size, super::Type::User)?;
 
     let starter = super::Starter {
         entry,
@@ -179,9 +182,6 @@ pub fn from_elf(
         &starter,
     )?;
 
-    crate::sched::SCHED.with_current(|cur| {
-        let event = Arc::downgrade(&ret.tid().event) as _;
-        cur.space().handles().insert(ret.tid().clone(), Some(event))
-    })?;
+    crate::sched::SCHED.with_current(|cur| cur.space().handles().insert(ret.tid().clone()))?;
     Ok(ret)
 }
@@ -7,7 +7,9 @@ use core::{
 
 use archop::reg::cr2;
 use bytes::Buf;
-use sv_call::task::excep::{Exception, ExceptionResult, EXRES_CODE_RECOVERED};
+use sv_call::task::excep::{
+    Exception, ExceptionResult, EXRES_CODE_RECOVERED, EXVEC_SYSCALL_DENIED,
+};
 
 use super::ctx::x86_64::Frame;
 use crate::{
@@ -16,6 +18,27 @@ use crate::{
 };
 
 pub fn dispatch_exception(frame: &mut Frame, vec: ExVec) -> bool {
+    dispatch(Exception {
+        vec: vec as u8,
+        errc: frame.errc_vec,
+        cr2: match vec {
+            ExVec::PageFault => cr2::read(),
+            _ => 0,
+        },
+    })
+}
+
+/// Report the syscall `num` denied by the filter of the current task to its
+/// exception channel. Returns whether the exception is recovered.
+pub fn dispatch_syscall_denied(num: usize) -> bool {
+    dispatch(Exception {
+        vec: EXVEC_SYSCALL_DENIED,
+        errc: num as u64,
+        cr2: 0,
+    })
+}
+
+fn dispatch(excep: Exception) -> bool {
     let slot = match SCHED.with_current(|cur| Ok(cur.tid.excep_chan())) {
         Ok(slot) => slot,
         _ => return false,
@@ -26,16 +49,7 @@ pub fn dispatch_exception(frame: &mut Frame, vec: ExVec) -> bool {
         _ => return false,
     };
 
-    let data: [u8; mem::size_of::<Exception>()] = unsafe {
-        mem::transmute(Exception {
-            vec: vec as u8,
-            errc: unsafe { frame.errc_vec },
-            cr2: match vec {
-                ExVec::PageFault => cr2::read(),
-                _ => 0,
-            },
-        })
-    };
+    let data: [u8; mem::size_of::<Exception>()] = unsafe { mem::transmute(excep) };
 
     let mut excep = Packet::new(0, Default::default(), &data);
     if excep_chan.send(&mut excep).is_err() {
@@ -49,7 +63,7 @@ pub fn dispatch_exception(frame: &mut Frame, vec: ExVec) -> bool {
         false,
         SIG_READ,
     );
-    if blocker.wait(None, Duration::MAX).is_err() {
+    if blocker.wait(None, Duration::MAX, false).is_err() {
         return false;
     }
     if !blocker.detach().0 {
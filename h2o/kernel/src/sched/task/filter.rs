@@ -0,0 +1,153 @@
+//! The syscall filters of the tasks.
+//!
+//! A filter is a bitmap of the syscall numbers a task is allowed to call,
+//! installed by its parent before starting it or by the task itself. Another
+//! filter installed later only narrows the allowed syscalls, and the tasks
+//! created by a filtered task inherit its filter, so a sandboxed task can't
+//! escape it. `sv_task_exit` is always allowed.
+
+use alloc::vec::Vec;
+
+use sv_call::{Result, EPERM, SV_TASK_EXIT};
+
+use crate::sched::SCHED;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// What to do with a denied syscall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    /// Fail the syscall with `EPERM`.
+    Error,
+    /// Report the syscall to the exception channel of the task, and kill it
+    /// unless the exception is recovered.
+    Kill,
+}
+
+#[derive(Debug)]
+pub struct SyscallFilter {
+    allowed: Vec<u64>,
+    action: Action,
+}
+
+impl SyscallFilter {
+    /// Create a filter allowing the syscalls whose numbers are set in the
+    /// bitmap `allowed`.
+    #[inline]
+    pub fn new(allowed: Vec<u64>, action: Action) -> Self {
+        SyscallFilter { allowed, action }
+    }
+
+    #[inline]
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    pub fn allows(&self, num: usize) -> bool {
+        let word = self.allowed.get(num / WORD_BITS).copied().unwrap_or(0);
+        num == SV_TASK_EXIT || word & (1 << (num % WORD_BITS)) != 0
+    }
+
+    /// Narrow the filter with `other`, allowing only the syscalls allowed by
+    /// both, with the stricter action.
+    pub fn intersect(&self, other: &Self) -> Result<Self> {
+        let len = self.allowed.len().min(other.allowed.len());
+        let mut allowed = Vec::new();
+        allowed
+            .try_reserve_exact(len)
+            .map_err(|_| sv_call::ENOMEM)?;
+        allowed.extend((self.allowed.iter().zip(&other.allowed)).map(|(a, b)| a & b));
+        Ok(SyscallFilter {
+            allowed,
+            action: self.action.max(other.action),
+        })
+    }
+}
+
+/// Check the syscall `num` against the filter of the current task.
+///
+/// # Errors
+///
+/// Returns `EPERM` if the syscall is denied. The current task is killed
+/// instead if the action of the filter says so.
+pub fn check(num: usize) -> Result {
+    let filter = match SCHED.with_current(|cur| Ok(cur.tid().syscall_filter())) {
+        Ok(Some(filter)) => filter,
+        _ => return Ok(()),
+    };
+    if filter.allows(num) {
+        return Ok(());
+    }
+
+    crate::audit::record(sv_call::audit::AUDIT_SYSCALL_DENIED, [num as u64, 0, 0]);
+    if filter.action() == Action::Kill && !super::excep::dispatch_syscall_denied(num) {
+        SCHED.exit_current(EPERM.into_retval(), true)
+    }
+    Err(EPERM)
+}
+
+mod syscall {
+    use sv_call::{task::*, *};
+
+    use super::*;
+    use crate::syscall::{In, UserPtr};
+
+    /// Restrict the syscalls of the child task `hdl`, or the current task if
+    /// it's null, to those whose numbers are set in the bitmap of `count`
+    /// words in `allowed`. The other syscalls are handled according to
+    /// `action` (`SYSCALL_FILTER_*`).
+    ///
+    /// The filter of a child should be installed before starting it.
+    #[syscall]
+    fn task_filter(hdl: Handle, allowed: UserPtr<In, u64>, count: usize, action: u32) -> Result {
+        let action = match action {
+            SYSCALL_FILTER_ERROR => Action::Error,
+            SYSCALL_FILTER_KILL => Action::Kill,
+            _ => return Err(EINVAL),
+        };
+        if count > crate::syscall::count().div_ceil(WORD_BITS) {
+            return Err(ERANGE);
+        }
+        let mut words = Vec::new();
+        words.try_reserve_exact(count).map_err(|_| ENOMEM)?;
+        if count > 0 {
+            unsafe {
+                allowed.read_slice(words.as_mut_ptr(), count)?;
+                words.set_len(count);
+            }
+        }
+
+        let tid = SCHED.with_current(|cur| {
+            if hdl == Handle::NULL {
+                Ok(cur.tid().clone())
+            } else {
+                cur.space().child(hdl)
+            }
+        })?;
+        tid.restrict_syscalls(SyscallFilter::new(words, action))
+    }
+}
+
+#[cfg(ktest)]
+mod ktests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::ktest::case;
+
+    case! {
+        fn syscall_filter_intersect() {
+            // `sv_task_exit` is always allowed whatever its number is.
+            let denies = |f: &SyscallFilter, num| num == SV_TASK_EXIT || !f.allows(num);
+
+            let a = SyscallFilter::new(vec![0b1011, u64::MAX], Action::Error);
+            let b = SyscallFilter::new(vec![0b0110], Action::Kill);
+            assert!(a.allows(3) && a.allows(64) && denies(&a, 2) && denies(&a, 128));
+
+            let c = a.intersect(&b).unwrap();
+            assert_eq!(c.action(), Action::Kill);
+            assert!(c.allows(1) && denies(&c, 0) && denies(&c, 2) && denies(&c, 64));
+            assert!(c.allows(SV_TASK_EXIT));
+        }
+    }
+}
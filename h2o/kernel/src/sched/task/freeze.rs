@@ -0,0 +1,95 @@
+//! Freezing the user tasks before the system sleeps.
+
+use alloc::vec::Vec;
+use core::{hint, time::Duration};
+
+use spin::Mutex;
+use sv_call::{Result, EBUSY};
+
+use super::{tid, Signal, State, Suspended, Tid, Type};
+use crate::{
+    cpu::time::Instant,
+    sched::{Arsc, PREEMPT, SCHED},
+};
+
+#[derive(Debug)]
+struct Slot {
+    slot: Arsc<Mutex<Option<Suspended>>>,
+    tid: Tid,
+}
+
+impl Slot {
+    fn signal(&self) -> Signal {
+        Signal::Suspend(Arsc::clone(&self.slot))
+    }
+
+    fn is_frozen(&self) -> bool {
+        PREEMPT.scope(|| self.slot.lock().is_some())
+            || matches!(self.tid.state(), State::Exiting | State::Zombie)
+    }
+}
+
+/// The frozen user tasks, which are thawed when dropped.
+#[derive(Debug)]
+pub struct Frozen {
+    slots: Vec<Slot>,
+}
+
+impl Drop for Frozen {
+    fn drop(&mut self) {
+        for slot in self.slots.drain(..) {
+            match PREEMPT.scope(|| slot.slot.lock().take()) {
+                Some(task) => SCHED.unblock(task, true),
+                None => slot.tid.with_signal(|sig| {
+                    if matches!(sig, Some(sig) if sig == &mut slot.signal()) {
+                        *sig = None;
+                    }
+                }),
+            }
+        }
+    }
+}
+
+/// Suspend all the user tasks but the current one, and wait at most `timeout`
+/// for them to be off the CPUs.
+///
+/// Tasks blocked in uninterruptible waits are not waited for, since they're
+/// frozen as soon as they're woken up with the signal still pending.
+///
+/// Returns `EBUSY` if any task is still running after `timeout`, in which case
+/// the ones already frozen are thawed.
+pub fn freeze(timeout: Duration) -> Result<Frozen> {
+    let cur = SCHED.with_current(|cur| Ok(cur.tid().clone()))?;
+    let mut frozen = Frozen { slots: Vec::new() };
+    for tid in tid::all() {
+        if tid == cur || tid.ty() == Type::Kernel {
+            continue;
+        }
+        let slot = Slot {
+            slot: Arsc::try_new(Mutex::new(None))?,
+            tid,
+        };
+        let signaled = slot.tid.with_signal(|sig| match sig {
+            // Killed or already suspended by its owner.
+            Some(_) => false,
+            None => {
+                *sig = Some(slot.signal());
+                true
+            }
+        });
+        if signaled {
+            slot.tid.interrupt();
+            frozen.slots.push(slot);
+        }
+    }
+
+    let instant = Instant::now();
+    while !frozen.slots.iter().all(Slot::is_frozen) {
+        if instant.elapsed() >= timeout {
+            // Dropping the frozen tasks thaws them.
+            return Err(EBUSY);
+        }
+        hint::spin_loop();
+    }
+    Ok(frozen)
+}
@@ -1,30 +1,31 @@
 mod node;
+mod table;
 
 use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
-use core::{
-    any::Any,
-    hash::BuildHasherDefault,
-    mem,
-    ops::Deref,
-    ptr::NonNull,
-    sync::atomic::{AtomicU32, Ordering::SeqCst},
-};
+use core::{any::Any, mem, ops::Deref, ptr::NonNull};
 
-use collection_ex::{CHashMap, FnvHasher};
-use sv_call::{Feature, Result, EINVAL, ETYPE};
+use sv_call::{task::HandleTransfer, Feature, Result, EINVAL, ETYPE};
 
 pub use self::node::{Ref, MAX_HANDLE_COUNT};
+use self::table::Table;
 use crate::sched::{ipc::Channel, Event, PREEMPT};
 
-type BH = BuildHasherDefault<FnvHasher>;
-
 pub unsafe trait DefaultFeature: Any + Send + Sync {
     fn default_features() -> Feature;
 }
 
+/// The objects inserted into the handle maps with their default features and
+/// their events wired up.
+pub trait KernelObject: DefaultFeature {
+    /// The event waited on through the handles of the object, or `None` if it
+    /// can't be waited on, in which case it must not have [`Feature::WAIT`] by
+    /// default.
+    fn event(this: &Arc<Self>) -> Option<Weak<dyn Event>>;
+}
+
 unsafe impl<T: DefaultFeature + ?Sized> DefaultFeature for crate::sched::Arsc<T> {
     fn default_features() -> Feature {
         T::default_features()
@@ -38,8 +39,7 @@ unsafe impl<T: DefaultFeature + ?Sized> DefaultFeature for alloc::sync::Arc<T> {
 }
 
 pub struct RefGuard<'a, T: ?Sized + 'a> {
-    _guard: collection_ex::CHashMapReadGuard<'a, u32, Ref, BH>,
-    _handle: sv_call::Handle,
+    _guard: table::Guard<'a>,
     object: NonNull<Ref<T>>,
 }
 
@@ -52,20 +52,22 @@ impl<'a, T: ?Sized + 'a> Deref for RefGuard<'a, T> {
     }
 }
 
+/// The handles of a task, stored in a generational slot table.
+///
+/// A handle value is the key of its slot in the table mixed with a random
+/// number, so a stale handle is rejected even if its slot is reused.
 #[derive(Debug)]
 pub struct HandleMap {
-    list: CHashMap<u32, Ref, BH>,
+    table: Table,
     mix: u32,
-    next_id: AtomicU32,
 }
 
 impl HandleMap {
     #[inline]
     pub fn new() -> Self {
         HandleMap {
-            list: CHashMap::default(),
+            table: Table::new(),
             mix: archop::rand::get() as u32,
-            next_id: AtomicU32::new(1),
         }
     }
 
@@ -75,32 +77,26 @@ impl HandleMap {
 
     #[inline]
     pub fn get_ref(&self, handle: sv_call::Handle) -> Result<RefGuard<'_, dyn Any + Send + Sync>> {
-        let key = self.decode(handle);
-        self.list.get(&key).ok_or(EINVAL).map(|guard| {
-            let object = NonNull::from(&guard as &Ref);
-            RefGuard {
-                _guard: guard,
-                _handle: handle,
-                object,
-            }
+        let guard = self.table.get(self.decode(handle))?;
+        let object = NonNull::from(&*guard);
+        Ok(RefGuard {
+            _guard: guard,
+            object,
         })
     }
 
     #[inline]
     pub fn get<T: Send + Any>(&self, handle: sv_call::Handle) -> Result<RefGuard<'_, T>> {
-        let key = self.decode(handle);
-        self.list.get(&key).ok_or(EINVAL).and_then(|guard| {
-            if guard.is::<T>() {
-                let object = NonNull::from(guard.downcast_ref::<T>().unwrap());
-                Ok(RefGuard {
-                    _guard: guard,
-                    _handle: handle,
-                    object,
-                })
-            } else {
-                Err(ETYPE)
-            }
-        })
+        let guard = self.table.get(self.decode(handle))?;
+        if guard.is::<T>() {
+            let object = NonNull::from(guard.downcast_ref::<T>().unwrap());
+            Ok(RefGuard {
+                _guard: guard,
+                object,
+            })
+        } else {
+            Err(ETYPE)
+        }
     }
 
     #[inline]
@@ -113,19 +109,19 @@ impl HandleMap {
 
     #[inline]
     pub fn insert_ref(&self, value: Ref) -> Result<sv_call::Handle> {
-        let key = self.next_id.fetch_add(1, SeqCst);
-        let old = PREEMPT.scope(|| self.list.insert(key, value));
-        assert!(old.is_none());
+        crate::fault::check(crate::fault::Point::Handle)?;
+        self.insert_key(value)
+    }
+
+    fn insert_key(&self, value: Ref) -> Result<sv_call::Handle> {
+        // The null handle is never handed out.
+        let key = PREEMPT.scope(|| self.table.insert(value, self.mix))?;
         Ok(sv_call::Handle::new(key ^ self.mix))
     }
 
     #[inline]
-    pub fn insert_raw<T: DefaultFeature>(
-        &self,
-        obj: Arc<T>,
-        event: Option<Weak<dyn Event>>,
-    ) -> Result<sv_call::Handle> {
-        self.insert_ref(Ref::from_raw(obj, event)?)
+    pub fn insert_raw<T: KernelObject>(&self, obj: Arc<T>) -> Result<sv_call::Handle> {
+        self.insert_ref(Ref::from_raw(obj)?)
     }
 
     /// # Safety
@@ -159,44 +155,42 @@ impl HandleMap {
     }
 
     #[inline]
-    pub fn insert<T: DefaultFeature>(
-        &self,
-        data: T,
-        event: Option<Weak<dyn Event>>,
-    ) -> Result<sv_call::Handle> {
-        unsafe { self.insert_unchecked(data, T::default_features(), event) }
+    pub fn insert<T: KernelObject>(&self, data: T) -> Result<sv_call::Handle> {
+        self.insert_ref(Ref::try_new(data)?)
     }
 
     #[inline]
     pub fn remove_ref(&self, handle: sv_call::Handle) -> Result<Ref> {
         let key = self.decode(handle);
-        PREEMPT.scope(|| self.list.remove(&key).ok_or(EINVAL))
+        let res = PREEMPT.scope(|| self.table.remove(key, |_| Ok(())));
+        res.map_err(|err| err.unwrap_or(EINVAL))
     }
 
     pub fn remove<T: Send + Sync + Any>(&self, handle: sv_call::Handle) -> Result<Ref<T>> {
         let key = self.decode(handle);
-        let res = self
-            .list
-            .try_remove(&key, |obj| if obj.is::<T>() { Ok(()) } else { Err(ETYPE) });
+        let res = PREEMPT.scope(|| {
+            self.table
+                .remove(key, |obj| if obj.is::<T>() { Ok(()) } else { Err(ETYPE) })
+        });
         res.map_err(|err| err.unwrap_or(EINVAL))
             .map(|obj| obj.downcast().unwrap())
     }
 
     fn merge(&self, objects: Vec<Ref>) -> impl Iterator<Item = Result<sv_call::Handle>> + '_ {
-        objects.into_iter().map(|obj| self.insert_ref(obj))
+        // Putting the objects back must not fail with injected faults.
+        objects.into_iter().map(|obj| self.insert_key(obj))
     }
 
-    fn split(&self, handles: &[sv_call::Handle], src: &Channel) -> Result<Vec<Ref>> {
+    /// Remove `handles` all at once if `pred` allows every one of them with
+    /// its index, or none of them otherwise.
+    fn split<F>(&self, handles: &[sv_call::Handle], pred: F) -> Result<Vec<Ref>>
+    where
+        F: Fn(usize, &Ref) -> Result,
+    {
         let mut result = Vec::with_capacity(handles.len());
-        for handle in handles.iter().copied() {
+        for (index, handle) in handles.iter().copied().enumerate() {
             let key = self.decode(handle);
-            let res = self
-                .list
-                .try_remove(&key, |value| match value.downcast_ref::<Channel>() {
-                    Ok(chan) if chan.peer_eq(src) => Err(sv_call::EPERM),
-                    Err(_) if !value.features().contains(Feature::SEND) => Err(sv_call::EPERM),
-                    _ => Ok(()),
-                });
+            let res = self.table.remove(key, |value| pred(index, value));
             match res.map_err(|err| err.unwrap_or(EINVAL)) {
                 Ok(obj) => result.push(obj),
                 Err(err) => {
@@ -212,14 +206,48 @@ impl HandleMap {
         if handles.is_empty() {
             return Ok(Vec::new());
         }
-        PREEMPT.scope(|| self.split(handles, src))
+        PREEMPT.scope(|| {
+            self.split(handles, |_, value| match value.downcast_ref::<Channel>() {
+                Ok(chan) if chan.peer_eq(src) => Err(sv_call::EPERM),
+                Err(_) if !value.features().contains(Feature::SEND) => Err(sv_call::EPERM),
+                _ => Ok(()),
+            })
+        })
+    }
+
+    /// Remove the handles to be transferred to another task, with their
+    /// features masked by the requested ones, along with the handles in
+    /// `moved`, all at once or none of them.
+    pub fn transfer(
+        &self,
+        transfers: &[HandleTransfer],
+        moved: &[sv_call::Handle],
+    ) -> Result<(Vec<Ref>, Vec<Ref>)> {
+        let handles = transfers.iter().map(|t| t.handle);
+        let handles = handles.chain(moved.iter().copied()).collect::<Vec<_>>();
+        let mut objects = PREEMPT.scope(|| {
+            self.split(&handles, |index, value| {
+                if index >= transfers.len() || value.features().contains(Feature::SEND) {
+                    Ok(())
+                } else {
+                    Err(sv_call::EPERM)
+                }
+            })
+        })?;
+        let moved = objects.split_off(transfers.len());
+        for (obj, transfer) in objects.iter_mut().zip(transfers) {
+            let features = obj.features() & transfer.features;
+            obj.set_features(features)?;
+        }
+        Ok((objects, moved))
     }
 
     #[inline]
     pub fn receive(&self, other: &mut Vec<Ref>, handles: &mut [sv_call::Handle]) {
         PREEMPT.scope(|| {
             for (hdl, obj) in handles.iter_mut().zip(self.merge(mem::take(other))) {
-                *hdl = obj.unwrap();
+                // The object is dropped if the table is full.
+                *hdl = obj.unwrap_or(sv_call::Handle::NULL);
             }
         })
     }
@@ -251,7 +279,12 @@ mod syscall {
         let old = unsafe { hdl_ptr.read() }?;
         old.check_null()?;
         let mut obj = SCHED.with_current(|cur| cur.space().handles().remove_ref(old))?;
+        let orig = obj.features();
         let ret = obj.set_features(feat);
+        if ret.is_err() {
+            let args = [old.raw().into(), orig.bits(), feat.bits()];
+            crate::audit::record(audit::AUDIT_FEAT_ESCALATION, args);
+        }
         let new = SCHED.with_current(|cur| cur.space().handles().insert_ref(obj))?;
         unsafe { hdl_ptr.write(new) }?;
         ret
@@ -7,7 +7,7 @@ use core::{
 
 use sv_call::{Feature, Result};
 
-use super::DefaultFeature;
+use super::KernelObject;
 use crate::sched::Event;
 
 pub const MAX_HANDLE_COUNT: usize = 1 << 16;
@@ -57,18 +57,20 @@ impl<T: ?Sized> Ref<T> {
     }
 
     #[inline]
-    pub fn try_new(data: T, event: Option<Weak<dyn Event>>) -> sv_call::Result<Self>
+    pub fn try_new(data: T) -> sv_call::Result<Self>
     where
-        T: DefaultFeature + Sized,
+        T: KernelObject + Sized,
     {
-        unsafe { Self::try_new_unchecked(data, T::default_features(), event) }
+        Self::from_raw(Arc::try_new(data)?)
     }
 
+    /// Wrap `obj` with its default features and its event.
     #[inline]
-    pub fn from_raw(obj: Arc<T>, event: Option<Weak<dyn Event>>) -> sv_call::Result<Self>
+    pub fn from_raw(obj: Arc<T>) -> sv_call::Result<Self>
     where
-        T: DefaultFeature,
+        T: KernelObject,
     {
+        let event = T::event(&obj);
         unsafe { Self::from_raw_unchecked(obj, T::default_features(), event) }
     }
 
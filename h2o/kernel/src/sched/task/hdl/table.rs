@@ -0,0 +1,314 @@
+//! A generational slot table with lock-free lookups.
+//!
+//! A key is made of the index of its slot and the generation of the slot when
+//! the value was inserted, so keys of removed values are caught even if the
+//! slot is reused later.
+//!
+//! Readers only increment the reader count of the slot, and removers mark the
+//! slot as being removed before waiting for the existing readers to leave, so
+//! the value is never dropped under a reader.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    array,
+    cell::UnsafeCell,
+    fmt, hint,
+    mem::MaybeUninit,
+    ops::Deref,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicU64, Ordering::*},
+};
+
+use spin::Mutex;
+use sv_call::{Error, Result, EINVAL, ENOMEM};
+
+use super::{Ref, MAX_HANDLE_COUNT};
+
+const CHUNK_SHIFT: usize = 8;
+const CHUNK_SIZE: usize = 1 << CHUNK_SHIFT;
+const NUM_CHUNKS: usize = MAX_HANDLE_COUNT / CHUNK_SIZE;
+
+const INDEX_BITS: u32 = MAX_HANDLE_COUNT.trailing_zeros();
+const INDEX_MASK: u32 = (1 << INDEX_BITS) - 1;
+
+/// Set while the value of the slot is being removed, which turns new readers
+/// and removers away.
+const REMOVING: u64 = 1 << 31;
+const READERS: u64 = REMOVING - 1;
+
+#[inline]
+fn key(index: u32, gen: u32) -> u32 {
+    gen << INDEX_BITS | index
+}
+
+/// A slot of the table.
+///
+/// The upper half of the state is the generation of the slot, which is odd if
+/// the slot is occupied, and the lower half contains the number of readers.
+struct Slot {
+    state: AtomicU64,
+    value: UnsafeCell<MaybeUninit<Ref>>,
+}
+
+unsafe impl Sync for Slot {}
+
+impl Slot {
+    #[inline]
+    fn gen(state: u64) -> u32 {
+        (state >> 32) as u32
+    }
+
+    /// Whether the slot is occupied by the value of the generation in `key`.
+    #[inline]
+    fn matches(state: u64, key: u32) -> bool {
+        let gen = Self::gen(state);
+        gen & 1 != 0 && gen << INDEX_BITS == key & !INDEX_MASK && state & REMOVING == 0
+    }
+
+    /// Mark the slot with `mark`, failing if it's not occupied by the value of
+    /// `key`.
+    fn mark(&self, key: u32, mark: impl Fn(u64) -> u64) -> Result<u64> {
+        let mut state = self.state.load(Acquire);
+        loop {
+            if !Self::matches(state, key) {
+                return Err(EINVAL);
+            }
+            match self
+                .state
+                .compare_exchange_weak(state, mark(state), Acquire, Acquire)
+            {
+                Ok(_) => break Ok(state),
+                Err(s) => state = s,
+            }
+        }
+    }
+
+    fn take<F>(&self, key: u32, pred: F) -> core::result::Result<Ref, Option<Error>>
+    where
+        F: FnOnce(&Ref) -> Result,
+    {
+        let state = self.mark(key, |state| state | REMOVING).map_err(Some)?;
+
+        // SAFETY: The slot is occupied and we're the only remover.
+        let value = unsafe { (*self.value.get()).assume_init_ref() };
+        if let Err(err) = pred(value) {
+            self.state.fetch_and(!REMOVING, Release);
+            return Err(Some(err));
+        }
+
+        while self.state.load(Acquire) & READERS != 0 {
+            hint::spin_loop();
+        }
+        // SAFETY: No one else can access the value any longer.
+        let value = unsafe { (*self.value.get()).assume_init_read() };
+        let gen = Self::gen(state).wrapping_add(1);
+        self.state.store((gen as u64) << 32, Release);
+        Ok(value)
+    }
+}
+
+type Chunk = [Slot; CHUNK_SIZE];
+
+struct Alloc {
+    free: Vec<u32>,
+    next: u32,
+}
+
+pub struct Table {
+    chunks: [AtomicPtr<Chunk>; NUM_CHUNKS],
+    alloc: Mutex<Alloc>,
+}
+
+unsafe impl Send for Table {}
+unsafe impl Sync for Table {}
+
+impl Table {
+    pub fn new() -> Self {
+        Table {
+            chunks: array::from_fn(|_| AtomicPtr::new(ptr::null_mut())),
+            alloc: Mutex::new(Alloc {
+                free: Vec::new(),
+                next: 0,
+            }),
+        }
+    }
+
+    fn slot(&self, key: u32) -> Option<&Slot> {
+        let index = (key & INDEX_MASK) as usize;
+        let chunk = self.chunks[index >> CHUNK_SHIFT].load(Acquire);
+        // SAFETY: Chunks are never freed until the table is dropped.
+        unsafe { chunk.as_ref() }.map(|chunk| &chunk[index & (CHUNK_SIZE - 1)])
+    }
+
+    /// Get the value of `key`, which is kept from being removed until the
+    /// guard is dropped.
+    pub fn get(&self, key: u32) -> Result<Guard<'_>> {
+        let slot = self.slot(key).ok_or(EINVAL)?;
+        slot.mark(key, |state| state + 1)?;
+        Ok(Guard { slot })
+    }
+
+    /// Insert a value, returning its key, which never equals `reserved`.
+    ///
+    /// Must be called with preemption disabled.
+    pub fn insert(&self, value: Ref, reserved: u32) -> Result<u32> {
+        let index = {
+            let mut alloc = self.alloc.lock();
+            match alloc.free.pop() {
+                Some(index) => index,
+                None => {
+                    let index = alloc.next;
+                    if index as usize >= MAX_HANDLE_COUNT {
+                        return Err(ENOMEM);
+                    }
+                    let chunk = &self.chunks[index as usize >> CHUNK_SHIFT];
+                    if chunk.load(Relaxed).is_null() {
+                        // SAFETY: Zeroed slots are vacant.
+                        let new = unsafe { Box::<Chunk>::try_new_zeroed()?.assume_init() };
+                        chunk.store(Box::into_raw(new), Release);
+                    }
+                    alloc.next += 1;
+                    index
+                }
+            }
+        };
+        // The slot is vacant and owned by us before being published.
+        let slot = self.slot(index).unwrap();
+        let mut gen = Slot::gen(slot.state.load(Acquire)).wrapping_add(1);
+        while key(index, gen) == reserved {
+            gen = gen.wrapping_add(2);
+        }
+        unsafe { (*slot.value.get()).write(value) };
+        slot.state.store((gen as u64) << 32, Release);
+        Ok(key(index, gen))
+    }
+
+    /// Remove the value of `key` if `pred` allows, waiting for its readers to
+    /// leave.
+    ///
+    /// Must be called with preemption disabled.
+    pub fn remove<F>(&self, key: u32, pred: F) -> core::result::Result<Ref, Option<Error>>
+    where
+        F: FnOnce(&Ref) -> Result,
+    {
+        let slot = self.slot(key).ok_or(None)?;
+        let value = slot.take(key, pred)?;
+        self.alloc.lock().free.push(key & INDEX_MASK);
+        Ok(value)
+    }
+
+    fn len(&self) -> usize {
+        let alloc = self.alloc.lock();
+        alloc.next as usize - alloc.free.len()
+    }
+}
+
+impl Default for Table {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Table").field("len", &self.len()).finish()
+    }
+}
+
+impl Drop for Table {
+    fn drop(&mut self) {
+        for chunk in &mut self.chunks {
+            let chunk = *chunk.get_mut();
+            if chunk.is_null() {
+                continue;
+            }
+            // SAFETY: The chunk is allocated in `insert` and owned by us.
+            let mut chunk = unsafe { Box::from_raw(chunk) };
+            for slot in chunk.iter_mut() {
+                if Slot::gen(*slot.state.get_mut()) & 1 != 0 {
+                    // SAFETY: The slot is occupied.
+                    unsafe { slot.value.get_mut().assume_init_drop() };
+                }
+            }
+        }
+    }
+}
+
+/// The guard of a value in the table, keeping it from being removed.
+pub struct Guard<'a> {
+    slot: &'a Slot,
+}
+
+impl Deref for Guard<'_> {
+    type Target = Ref;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: The value can't be removed while we're reading it.
+        unsafe { (*self.slot.value.get()).assume_init_ref() }
+    }
+}
+
+impl Drop for Guard<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.slot.state.fetch_sub(1, Release);
+    }
+}
+
+#[cfg(ktest)]
+mod ktests {
+    use sv_call::{Feature, EPERM};
+
+    use super::*;
+    use crate::{ktest::case, sched::PREEMPT};
+
+    fn value(data: u64) -> Ref {
+        // SAFETY: `u64` is `Send` and `Sync`.
+        unsafe { Ref::try_new_unchecked(data, Feature::SEND, None) }.unwrap()
+    }
+
+    fn data(guard: &Guard) -> u64 {
+        let data: &u64 = guard.downcast_ref::<u64>().unwrap();
+        *data
+    }
+
+    case! {
+        fn table_rejects_stale_keys() {
+            let table = Table::new();
+            PREEMPT.scope(|| {
+                let k1 = table.insert(value(1), u32::MAX).unwrap();
+                assert_eq!(data(&table.get(k1).unwrap()), 1);
+                assert!(table.remove(k1, |_| Ok(())).is_ok());
+
+                let k2 = table.insert(value(2), u32::MAX).unwrap();
+                assert_eq!(k1 & INDEX_MASK, k2 & INDEX_MASK);
+                assert_ne!(k1, k2);
+                assert_eq!(table.get(k1).err(), Some(EINVAL));
+                assert!(matches!(table.remove(k1, |_| Ok(())), Err(Some(EINVAL))));
+                assert_eq!(data(&table.get(k2).unwrap()), 2);
+            })
+        }
+
+        fn table_skips_reserved_keys() {
+            let table = Table::new();
+            PREEMPT.scope(|| {
+                let reserved = key(0, 1);
+                let k = table.insert(value(1), reserved).unwrap();
+                assert_ne!(k, reserved);
+                assert_eq!(table.get(reserved).err(), Some(EINVAL));
+            })
+        }
+
+        fn table_keeps_values_refused_by_predicates() {
+            let table = Table::new();
+            PREEMPT.scope(|| {
+                let k = table.insert(value(1), u32::MAX).unwrap();
+                assert!(matches!(table.remove(k, |_| Err(EPERM)), Err(Some(EPERM))));
+                assert_eq!(data(&table.get(k).unwrap()), 1);
+                assert_eq!(table.len(), 1);
+            })
+        }
+    }
+}
@@ -3,17 +3,42 @@ use crossbeam_queue::SegQueue;
 use super::*;
 use crate::{cpu::Lazy, mem::space};
 
+/// The contexts of the exited tasks on the CPU, waiting for
+/// [`CTX_DROPPER`].
+#[thread_local]
+static DEAD_CTX: Lazy<SegQueue<Exiting>> = Lazy::new(SegQueue::new);
+
 /// Context dropper - used for dropping kernel stacks of threads.
 ///
 /// The function [`task_exit`] runs on its own thread stack, so we cannot drop
 /// its kernel stack immediately, or the current context will crash. That's
-/// where context dropper tasks come into play. They collect the kernel stack
-/// from its CPU-local queue and drop it.
+/// where the context dropper threads come into play. They collect the kernel
+/// stacks from their CPU-local queues and drop them.
 ///
 /// [`task_exit`]: crate::sched::task::syscall::task_exit
 #[thread_local]
-pub(super) static CTX_DROPPER: Lazy<SegQueue<alloc::boxed::Box<Context>>> =
-    Lazy::new(SegQueue::new);
+static CTX_DROPPER: Lazy<kthread::KThread> = Lazy::new(|| {
+    let cpu = unsafe { crate::cpu::id() };
+    kthread::spawn(format!("CTXDROP{cpu}"), Priority::Low, || loop {
+        while let Some(task) = DEAD_CTX.pop() {
+            Exiting::reap(task);
+        }
+        kthread::park();
+    })
+    .expect("Failed to spawn the context dropper")
+});
+
+pub(super) fn init_ctx_dropper() {
+    Lazy::force(&DEAD_CTX);
+    Lazy::force(&CTX_DROPPER);
+}
+
+/// Queue the context of an exited task to be dropped after the CPU switches
+/// away from it.
+pub(super) fn drop_ctx(task: Exiting) {
+    DEAD_CTX.push(task);
+    CTX_DROPPER.unpark();
+}
 
 #[thread_local]
 pub(super) static IDLE: Lazy<Tid> = Lazy::new(|| {
@@ -29,7 +54,7 @@ pub(super) static IDLE: Lazy<Tid> = Lazy::new(|| {
         .unwrap();
 
     let space = super::Space::new_current();
-    let stack = space::init_stack(space.mem(), DEFAULT_STACK_SIZE)
+    let stack = space::init_stack(space.mem(), DEFAULT_STACK_SIZE, Type::Kernel)
         .expect("Failed to initialize stack for IDLE");
 
     let entry = ctx::Entry {
@@ -58,7 +83,11 @@ fn idle(cpu: usize, fs_base: u64) -> ! {
     }
 
     loop {
-        drop(CTX_DROPPER.pop());
+        let pree = crate::sched::PREEMPT.lock();
+        crate::sched::rcu::quiesce(&pree);
+        // SAFETY: We have `pree`, which means the interrupts are disabled.
+        unsafe { crate::sched::account::switch(crate::sched::account::Category::Idle) };
+        drop(pree);
         let _ = crate::sched::SCHED.with_current(|cur| {
             cur.running_state = RunningState::NEED_RESCHED;
             Ok(())
@@ -0,0 +1,196 @@
+//! Priority inheritance across channel calls.
+//!
+//! A task blocked in a call (`chan_send_recv`) donates its priority to the
+//! task serving the other side of the channel, namely the last one receiving
+//! from it, so that a server of a lower priority can't hold up the caller
+//! behind the tasks in between. If the server is blocked in a call itself, the
+//! donation is propagated down the chain, up to [`MAX_CHAIN`] tasks.
+//!
+//! A donation lasts until the call returns, or until the deadline of the call
+//! expires, after which the caller no longer waits for the reply.
+//!
+//! The donations and withdrawals are recorded in the trace ring, which is
+//! dumped to the kernel log along with the scheduler diagnostics.
+
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use super::{tid::WeakTid, Priority, Tid};
+use crate::{cpu::time::Instant, sched::PREEMPT};
+
+/// The maximum number of the tasks a donation is propagated to.
+pub const MAX_CHAIN: usize = 4;
+
+/// The number of the operations recorded in the trace ring.
+const TRACE_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+struct Donation {
+    donor: u64,
+    priority: Priority,
+    deadline: Option<Instant>,
+}
+
+impl Donation {
+    #[inline]
+    fn is_valid(&self, now: Instant) -> bool {
+        self.deadline.map_or(true, |deadline| now < deadline)
+    }
+}
+
+/// The state of priority inheritance of a task.
+#[derive(Debug, Default)]
+pub struct Inherit {
+    /// The donations of the callers blocked on the task.
+    donations: Vec<Donation>,
+    /// The tasks the task donates to while blocked in a call, from its server
+    /// down the chain.
+    donees: Vec<WeakTid>,
+}
+
+impl Inherit {
+    /// The highest priority donated to the task and not expired yet.
+    pub fn inherited(&self) -> Option<Priority> {
+        if self.donations.is_empty() {
+            return None;
+        }
+        let now = Instant::now();
+        (self.donations.iter())
+            .filter(|donation| donation.is_valid(now))
+            .map(|donation| donation.priority)
+            .max()
+    }
+}
+
+/// The donations of a caller, withdrawn when dropped.
+#[derive(Debug)]
+#[must_use = "The donations are withdrawn at once if dropped"]
+pub struct Donor {
+    donor: Tid,
+    donees: Vec<WeakTid>,
+}
+
+/// Donate the priority of `donor` to `server` and the tasks down the chain it
+/// calls, until the returned [`Donor`] is dropped or `deadline` expires.
+pub fn donate(donor: &Tid, server: Tid, deadline: Option<Instant>) -> Donor {
+    let priority = donor.priority();
+    let mut donees = Vec::<WeakTid>::new();
+    let mut next = Some(server);
+    while let Some(task) = next.take() {
+        let depth = donees.len();
+        // A cycle of calls can't be served anyway.
+        if task.raw() == donor.raw() || donees.iter().any(|d| d.raw() == Some(task.raw())) {
+            break;
+        }
+        if depth >= MAX_CHAIN {
+            record(Op::Truncate, donor.raw(), task.raw(), priority, depth);
+            break;
+        }
+        next = PREEMPT.scope(|| {
+            let mut inherit = task.inherit().lock();
+            inherit.donations.push(Donation {
+                donor: donor.raw(),
+                priority,
+                deadline,
+            });
+            inherit.donees.first().and_then(WeakTid::upgrade)
+        });
+        record(Op::Donate, donor.raw(), task.raw(), priority, depth);
+        donees.push(task.downgrade());
+    }
+    PREEMPT.scope(|| donor.inherit().lock().donees = donees.clone());
+    Donor {
+        donor: donor.clone(),
+        donees,
+    }
+}
+
+impl Drop for Donor {
+    fn drop(&mut self) {
+        let raw = self.donor.raw();
+        for (depth, donee) in self.donees.iter().enumerate() {
+            let Some(task) = donee.upgrade() else {
+                continue;
+            };
+            PREEMPT.scope(|| {
+                let mut inherit = task.inherit().lock();
+                inherit.donations.retain(|donation| donation.donor != raw);
+            });
+            record(Op::Withdraw, raw, task.raw(), task.priority(), depth);
+        }
+        PREEMPT.scope(|| self.donor.inherit().lock().donees.clear());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// The priority is donated to a task in the chain.
+    Donate,
+    /// The donation is withdrawn, with the priority of the donee left.
+    Withdraw,
+    /// The chain is longer than [`MAX_CHAIN`].
+    Truncate,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    time: Instant,
+    op: Op,
+    donor: u64,
+    donee: u64,
+    priority: Priority,
+    depth: usize,
+}
+
+struct Ring {
+    entries: [Option<Entry>; TRACE_LEN],
+    next: usize,
+}
+
+static TRACE: Mutex<Ring> = Mutex::new(Ring {
+    entries: [None; TRACE_LEN],
+    next: 0,
+});
+
+fn record(op: Op, donor: u64, donee: u64, priority: Priority, depth: usize) {
+    let entry = Entry {
+        time: Instant::now(),
+        op,
+        donor,
+        donee,
+        priority,
+        depth,
+    };
+    PREEMPT.scope(|| {
+        let mut ring = TRACE.lock();
+        let next = ring.next;
+        ring.entries[next] = Some(entry);
+        ring.next = (next + 1) % TRACE_LEN;
+    })
+}
+
+/// Log the trace ring from the oldest operation to the newest.
+pub fn dump() {
+    let entries = PREEMPT.scope(|| {
+        let ring = TRACE.lock();
+        let (newer, older) = ring.entries.split_at(ring.next);
+        older
+            .iter()
+            .chain(newer)
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>()
+    });
+    for entry in entries {
+        log::error!(
+            "[{}] {:?} #{} -> #{}: {:?} at depth {}",
+            entry.time,
+            entry.op,
+            entry.donor,
+            entry.donee,
+            entry.priority,
+            entry.depth
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Kernel threads, running the in-kernel services.
+//!
+//! A kernel thread runs a closure in the kernel space, bound to the CPU it's
+//! spawned on since the per-CPU data are addressed through its segment bases.
+//! It's an ordinary kernel task otherwise: its runtime and scheduling events
+//! are accounted like the others, and it's listed by [`dump`] along with the
+//! scheduler diagnostics.
+//!
+//! A thread sleeps with [`park`] until its [`KThread`] handle is unparked. An
+//! unpark before the park isn't lost, so the usual pattern is draining some
+//! work queue before parking, and unparking after pushing to it.
+
+use alloc::{boxed::Box, collections::BTreeMap};
+use core::{
+    mem,
+    sync::atomic::{AtomicBool, Ordering::*},
+    time::Duration,
+};
+
+use spin::Mutex;
+
+use super::*;
+use crate::{
+    mem::space,
+    sched::{wait::WaitObject, SCHED},
+};
+
+static KTHREADS: Mutex<BTreeMap<u64, Arc<Inner>>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug)]
+struct Inner {
+    tid: Tid,
+    /// Whether the thread is unparked since its last park.
+    token: Mutex<bool>,
+    parked: AtomicBool,
+    wo: WaitObject,
+}
+
+impl Inner {
+    fn park(&self) {
+        let pree = PREEMPT.lock();
+        let mut token = self.token.lock();
+        if !mem::replace(&mut *token, false) {
+            self.parked.store(true, Release);
+            // The lock is released only after the thread is queued, so an
+            // unpark in between can't be missed.
+            let _ = self
+                .wo
+                .wait((token, pree), Duration::MAX, false, "kthread::park");
+            self.parked.store(false, Release);
+            PREEMPT.scope(|| *self.token.lock() = false);
+        }
+    }
+
+    fn unpark(&self) {
+        PREEMPT.scope(|| *self.token.lock() = true);
+        self.wo.notify(0, false);
+    }
+}
+
+/// The handle of a kernel thread.
+#[derive(Debug, Clone)]
+pub struct KThread {
+    inner: Arc<Inner>,
+}
+
+impl KThread {
+    #[inline]
+    pub fn tid(&self) -> &Tid {
+        &self.inner.tid
+    }
+
+    /// Wake up the thread if it's parked, or make its next park return at
+    /// once otherwise.
+    #[inline]
+    pub fn unpark(&self) {
+        self.inner.unpark()
+    }
+}
+
+/// Spawn a kernel thread named `name` on the current CPU, running `func` until
+/// it returns.
+pub fn spawn<F>(name: String, priority: Priority, func: F) -> sv_call::Result<KThread>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let ti = TaskInfo::builder()
+        .from(Default::default())
+        .excep_chan(Arsc::try_new(Default::default())?)
+        .name(name)
+        .ty(Type::Kernel)
+        .priority(priority)
+        .affinity(crate::cpu::current_mask())
+        .build()
+        .unwrap();
+
+    let func: Box<dyn FnOnce() + Send> = Box::try_new(func)?;
+    let func = Box::try_new(func)?;
+    let space = Space::new_kernel()?;
+    let tid = tid::allocate(ti)?;
+
+    let res = space::init_stack(space.mem(), DEFAULT_STACK_SIZE, Type::Kernel).and_then(|stack| {
+        let inner = Arc::try_new(Inner {
+            tid: tid.clone(),
+            token: Mutex::new(false),
+            parked: AtomicBool::new(false),
+            wo: WaitObject::new(),
+        })?;
+        Ok((stack, inner))
+    });
+    let (stack, inner) = match res {
+        Ok(ret) => ret,
+        Err(err) => {
+            tid::deallocate(tid);
+            return Err(err);
+        }
+    };
+    space.set_main(&tid);
+
+    let fs_base = unsafe { archop::reg::read_fs() };
+    let entry = ctx::Entry {
+        entry: LAddr::new(entry as *mut u8),
+        stack,
+        args: [Box::into_raw(func) as u64, fs_base],
+    };
+    let kstack = ctx::Kstack::new(Some(entry), Type::Kernel);
+    PREEMPT.scope(|| KTHREADS.lock().insert(tid.raw(), Arc::clone(&inner)));
+
+    let init = Init::new(tid, space, kstack, ctx::ExtFrame::zeroed());
+    SCHED.unblock(init, true);
+    Ok(KThread { inner })
+}
+
+fn entry(func: *mut Box<dyn FnOnce() + Send>, fs_base: u64) -> ! {
+    unsafe { archop::reg::write_fs(fs_base) };
+
+    // SAFETY: The closure is leaked by `spawn` for this thread only.
+    let func = unsafe { Box::from_raw(func) };
+    func();
+
+    let raw = SCHED.with_current(|cur| Ok(cur.tid().raw()));
+    if let Ok(raw) = raw {
+        PREEMPT.scope(|| KTHREADS.lock().remove(&raw));
+    }
+    SCHED.exit_current(0, false)
+}
+
+/// Block the current kernel thread until it's unparked.
+///
+/// # Panics
+///
+/// Panics if the current task is not a kernel thread.
+pub fn park() {
+    let raw = SCHED
+        .with_current(|cur| Ok(cur.tid().raw()))
+        .expect("No current task");
+    let inner = PREEMPT.scope(|| KTHREADS.lock().get(&raw).cloned());
+    inner.expect("Not a kernel thread").park();
+}
+
+/// Log the kernel threads for the diagnostics of hangs.
+pub fn dump() {
+    PREEMPT.scope(|| {
+        for (raw, inner) in KTHREADS.lock().iter() {
+            log::error!(
+                "Kernel thread {raw} {:?}: {:?}, runtime {:?}{}",
+                inner.tid.name(),
+                inner.tid.priority(),
+                inner.tid.runtime(),
+                if inner.parked.load(Acquire) {
+                    ", parked"
+                } else {
+                    ""
+                }
+            );
+        }
+    });
+}
@@ -5,7 +5,7 @@ use crate::sched::Arsc;
 #[derive(Debug, Clone)]
 pub enum Signal {
     Kill,
-    Suspend(Arsc<Mutex<Option<super::Blocked>>>),
+    Suspend(Arsc<Mutex<Option<super::Suspended>>>),
 }
 
 impl PartialEq for Signal {
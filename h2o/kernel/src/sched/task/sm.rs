@@ -3,41 +3,99 @@ use core::{
     fmt,
     mem::ManuallyDrop,
     ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering::*},
     time::Duration,
 };
 
 use bitvec::prelude::BitVec;
 use derive_builder::Builder;
 use spin::Mutex;
+use sv_call::task::TASK_LOCAL_SLOTS;
 
 use super::{
-    ctx, idle,
+    ctx,
+    filter::SyscallFilter,
+    idle,
+    inherit::Inherit,
     sig::Signal,
+    stat::{self, Stats},
     tid::{self, WeakTid},
-    Space, Tid, Type,
+    Priority, Space, Tid, Type,
 };
 use crate::{
-    cpu::{time::Instant, CpuMask},
-    sched::{ipc::Channel, Arsc, BasicEvent, Event, PREEMPT, SIG_READ},
+    cpu::{
+        time::{Instant, Timer},
+        CpuMask,
+    },
+    logger::quota::LogQuota,
+    sched::{account::Category, ipc::Channel, wait::WaitCell, Arsc, BasicEvent, PREEMPT},
 };
 
 #[derive(Debug, Builder)]
 #[builder(no_std, pattern = "owned")]
 pub struct TaskInfo {
     from: WeakTid,
+    /// The return value, raising `SIG_GENERIC` on the task's event when set.
     #[builder(setter(skip))]
-    ret_cell: Mutex<Option<usize>>,
-    #[builder(setter(skip))]
-    pub(super) event: Arc<BasicEvent>,
+    retval: WaitCell<usize>,
     excep_chan: Arsc<Mutex<Option<Channel>>>,
 
-    name: String,
+    /// The name of the task, replaceable with `task_set_name`.
+    #[builder(setter(custom))]
+    name: Mutex<Arc<str>>,
     ty: Type,
+    /// The job of the task, namely the id of the space it runs in, or 0 for
+    /// the kernel threads.
+    #[builder(default)]
+    job: u64,
+    /// The raw [`Priority`] set for the task, excluding the inherited ones.
+    #[builder(default = "AtomicU8::new(Priority::Normal as u8)", setter(custom))]
+    priority: AtomicU8,
+    #[builder(setter(skip))]
+    inherit: Mutex<Inherit>,
 
     affinity: CpuMask,
+    /// The filter of the syscalls the task is allowed to call, inherited by
+    /// the tasks it creates.
+    #[builder(default, setter(custom))]
+    syscall_filter: Mutex<Option<Arc<SyscallFilter>>>,
 
+    /// The raw [`State`] of the task, only changed by the transitions of the
+    /// typed states of its context.
+    #[builder(setter(skip))]
+    state: AtomicU8,
     #[builder(setter(skip))]
     signal: Mutex<Option<Signal>>,
+    /// The timer of the current interruptible wait.
+    #[builder(setter(skip))]
+    blocker: Mutex<Option<Arsc<Timer>>>,
+    /// The total running time of the task in nanoseconds.
+    #[builder(setter(skip))]
+    runtime: AtomicU64,
+    #[builder(setter(skip))]
+    stats: Stats,
+    #[builder(setter(skip))]
+    log_quota: Mutex<LogQuota>,
+    /// The values of the task-local slots, only accessed by the task itself.
+    #[builder(setter(skip))]
+    locals: [AtomicUsize; TASK_LOCAL_SLOTS as usize],
+}
+
+impl TaskInfoBuilder {
+    pub fn name(mut self, name: String) -> Self {
+        self.name = Some(Mutex::new(Arc::from(name)));
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(AtomicU8::new(priority as u8));
+        self
+    }
+
+    pub fn syscall_filter(mut self, filter: Option<Arc<SyscallFilter>>) -> Self {
+        self.syscall_filter = Some(Mutex::new(filter));
+        self
+    }
 }
 
 impl TaskInfo {
@@ -52,8 +110,13 @@ impl TaskInfo {
     }
 
     #[inline]
-    pub fn name(&self) -> &str {
-        &self.name
+    pub fn name(&self) -> Arc<str> {
+        PREEMPT.scope(|| Arc::clone(&self.name.lock()))
+    }
+
+    #[inline]
+    pub fn set_name(&self, name: Arc<str>) {
+        PREEMPT.scope(|| *self.name.lock() = name)
     }
 
     #[inline]
@@ -61,14 +124,100 @@ impl TaskInfo {
         self.ty
     }
 
+    #[inline]
+    pub fn job(&self) -> u64 {
+        self.job
+    }
+
+    /// The priority of the task, including the ones inherited from the
+    /// callers blocked on it.
+    #[inline]
+    pub fn priority(&self) -> Priority {
+        let inherited = PREEMPT.scope(|| self.inherit.lock().inherited());
+        self.base_priority().max(inherited.unwrap_or(Priority::Low))
+    }
+
+    /// The priority set for the task, excluding the inherited ones.
+    #[inline]
+    pub fn base_priority(&self) -> Priority {
+        Priority::from_raw(self.priority.load(Acquire))
+    }
+
+    #[inline]
+    pub fn set_priority(&self, priority: Priority) {
+        self.priority.store(priority as u8, Release)
+    }
+
+    #[inline]
+    pub(super) fn inherit(&self) -> &Mutex<Inherit> {
+        &self.inherit
+    }
+
     #[inline]
     pub fn affinity(&self) -> crate::cpu::CpuMask {
         self.affinity
     }
 
     #[inline]
-    pub fn ret_cell(&self) -> &Mutex<Option<usize>> {
-        &self.ret_cell
+    pub fn syscall_filter(&self) -> Option<Arc<SyscallFilter>> {
+        PREEMPT.scope(|| self.syscall_filter.lock().clone())
+    }
+
+    /// Install `filter` on the task, narrowing the one already installed if
+    /// any.
+    pub fn restrict_syscalls(&self, filter: SyscallFilter) -> sv_call::Result {
+        PREEMPT.scope(|| {
+            let mut slot = self.syscall_filter.lock();
+            let filter = match &*slot {
+                Some(old) => old.intersect(&filter)?,
+                None => filter,
+            };
+            *slot = Some(Arc::try_new(filter)?);
+            Ok(())
+        })
+    }
+
+    /// The return value of the task, or `None` if it's still running.
+    #[inline]
+    pub fn retval(&self) -> Option<usize> {
+        self.retval.get()
+    }
+
+    #[inline]
+    pub fn event(&self) -> &Arc<BasicEvent> {
+        self.retval.event()
+    }
+
+    #[inline]
+    pub fn runtime(&self) -> Duration {
+        Duration::from_nanos(self.runtime.load(Acquire))
+    }
+
+    #[inline]
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    #[inline]
+    pub fn state(&self) -> State {
+        State::from_raw(self.state.load(Acquire))
+    }
+
+    fn transit(&self, from: State, to: State) {
+        let ret = self.try_transit(from, to);
+        debug_assert_eq!(ret, Ok(()), "Invalid transition {from:?} -> {to:?}");
+    }
+
+    /// Move the task from `from` to `to`, which fails with the current state
+    /// if the transition is invalid or the task is not in `from`.
+    fn try_transit(&self, from: State, to: State) -> Result<(), State> {
+        if !from.can_move_to(to) {
+            return Err(self.state());
+        }
+        (self.state)
+            .compare_exchange(from as u8, to as u8, AcqRel, Acquire)
+            .map(drop)
+            .map_err(State::from_raw)
     }
 
     #[inline]
@@ -79,12 +228,98 @@ impl TaskInfo {
         PREEMPT.scope(|| func(&mut self.signal.lock()))
     }
 
+    /// Register the timer of an interruptible wait of the task, interrupting
+    /// it at once if a signal is already pending.
+    pub fn set_blocker(&self, timer: &Arsc<Timer>) {
+        PREEMPT.scope(|| *self.blocker.lock() = Some(Arsc::clone(timer)));
+        if self.with_signal(|sig| sig.is_some()) {
+            timer.interrupt();
+        }
+    }
+
+    /// Interrupt the current interruptible wait of the task, if any, after a
+    /// signal is raised.
+    pub fn interrupt(&self) {
+        if let Some(timer) = PREEMPT.scope(|| self.blocker.lock().take()) {
+            timer.interrupt();
+        }
+    }
+
+    #[inline]
+    pub fn log_quota(&self) -> &Mutex<LogQuota> {
+        &self.log_quota
+    }
+
+    #[inline]
+    pub fn local(&self, slot: u32) -> Option<&AtomicUsize> {
+        self.locals.get(usize::try_from(slot).ok()?)
+    }
+
     #[inline]
     pub fn excep_chan(&self) -> Arsc<Mutex<Option<Channel>>> {
         Arsc::clone(&self.excep_chan)
     }
 }
 
+/// The states in the lifecycle of a task.
+///
+/// Each state but [`State::Zombie`] has a type holding the context of the
+/// task, and the task only moves between the states by the transition
+/// functions consuming one type and returning another, so invalid transitions
+/// are rejected at compile time. The state is also recorded in the task info
+/// for those holding only the TID.
+///
+/// ```text
+/// Init -> Ready -> Exiting -> Zombie
+///         ^   |
+///         |   +--> Blocked ---+
+///         |   +--> Suspended -+
+///         +-------------------+
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum State {
+    /// Created but never run, as [`Init`].
+    Init,
+    /// In a run queue or running on a CPU, as [`Ready`].
+    Ready,
+    /// Waiting for an event or a timer, as [`Blocked`].
+    Blocked,
+    /// Stopped by a suspension token or freezing, as [`Suspended`].
+    Suspended,
+    /// Exited with its context waiting to be dropped, as [`Exiting`].
+    Exiting,
+    /// Exited with its context dropped, leaving only its TID and return value.
+    Zombie,
+}
+
+impl State {
+    pub const ALL: [State; 6] = [
+        State::Init,
+        State::Ready,
+        State::Blocked,
+        State::Suspended,
+        State::Exiting,
+        State::Zombie,
+    ];
+
+    fn from_raw(raw: u8) -> Self {
+        Self::ALL[raw as usize]
+    }
+
+    /// Whether a task can move from this state to `to`.
+    pub const fn can_move_to(self, to: State) -> bool {
+        use State::*;
+        matches!(
+            (self, to),
+            (Init, Ready)
+                | (Ready, Blocked | Suspended | Exiting)
+                | (Blocked | Suspended, Ready)
+                | (Exiting, Zombie)
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct Context {
     pub(in crate::sched) tid: ManuallyDrop<Tid>,
@@ -96,6 +331,8 @@ pub struct Context {
 
     pub(in crate::sched) cpu: usize,
     pub(in crate::sched) runtime: Duration,
+    /// The time accounting category the task is switched out in.
+    pub(in crate::sched) category: Category,
 }
 
 impl Context {
@@ -120,6 +357,12 @@ impl Context {
     }
 }
 
+impl Drop for Context {
+    fn drop(&mut self) {
+        self.space.mem().discharge_kstack(ctx::KSTACK_SIZE);
+    }
+}
+
 #[derive(Clone, Copy)]
 pub union RunningState {
     start_time: Instant,
@@ -185,6 +428,11 @@ pub struct Init {
 }
 
 impl IntoReady for Init {
+    #[inline]
+    fn tid(&self) -> &Tid {
+        &self.ctx.tid
+    }
+
     #[inline]
     fn last_cpu(&self) -> Option<usize> {
         None
@@ -198,17 +446,21 @@ impl IntoReady for Init {
     #[inline]
     fn into_ready(this: Self, cpu: usize, time_slice: Duration) -> Ready {
         let mut ctx = this.ctx;
+        ctx.tid.transit(State::Init, State::Ready);
         ctx.cpu = cpu;
         Ready {
             ctx,
             running_state: RunningState::NOT_RUNNING,
             time_slice,
+            slice_used: Duration::ZERO,
+            woken_at: None,
         }
     }
 }
 
 impl Init {
     pub fn new(tid: Tid, space: Arc<Space>, kstack: ctx::Kstack, ext_frame: ctx::ExtFrame) -> Self {
+        space.mem().charge_kstack(ctx::KSTACK_SIZE);
         Init {
             ctx: Box::new(Context {
                 tid: ManuallyDrop::new(tid),
@@ -218,6 +470,7 @@ impl Init {
                 io_bitmap: None,
                 cpu: 0,
                 runtime: Duration::new(0, 0),
+                category: Category::Kernel,
             }),
         }
     }
@@ -234,9 +487,14 @@ pub struct Ready {
 
     pub(in crate::sched) running_state: RunningState,
     pub(in crate::sched) time_slice: Duration,
+    pub(in crate::sched) slice_used: Duration,
+    /// The time the task is woken up, taken when it first runs afterwards.
+    pub(in crate::sched) woken_at: Option<Instant>,
 }
 
 pub trait IntoReady {
+    fn tid(&self) -> &Tid;
+
     fn last_cpu(&self) -> Option<usize>;
 
     fn affinity(&self) -> CpuMask;
@@ -245,20 +503,53 @@ pub trait IntoReady {
 }
 
 impl Ready {
+    /// Add the time since the last accounting point to the runtime of the
+    /// task, and make `now` the new accounting point.
+    pub(in crate::sched) fn account(&mut self, now: Instant) {
+        if let Some(start_time) = self.running_state.start_time() {
+            // FIXME: Some platforms like QEMU don't support invariant TSC, so
+            // the time may go backwards slightly across CPUs.
+            let delta = now.saturating_duration_since(start_time);
+            self.ctx.runtime += delta;
+            self.slice_used += delta;
+            let nanos = delta.as_nanos() as u64;
+            self.ctx.tid.runtime.fetch_add(nanos, Release);
+            self.running_state = RunningState::running(now);
+        }
+    }
+
     #[inline]
     pub fn block(this: Self, block_desc: &'static str) -> Blocked {
+        this.ctx.tid.transit(State::Ready, State::Blocked);
         Blocked {
             ctx: this.ctx,
             block_desc,
+            since: Instant::now(),
         }
     }
 
-    pub fn exit(mut this: Self, retval: usize) {
+    #[inline]
+    pub fn suspend(this: Self) -> Suspended {
+        this.ctx.tid.transit(State::Ready, State::Suspended);
+        Suspended {
+            ctx: this.ctx,
+            since: Instant::now(),
+        }
+    }
+
+    /// Exit the task with `retval`, removing it from the TID map and queuing
+    /// its context to be reaped after the CPU switches away from it.
+    pub fn exit(this: Self, retval: usize) {
+        idle::drop_ctx(Self::into_exiting(this, retval));
+    }
+
+    fn into_exiting(mut this: Self, retval: usize) -> Exiting {
         // SAFETY: The context won't be dropped twice.
-        tid::deallocate(unsafe { ManuallyDrop::take(&mut this.ctx.tid) });
-        *this.ctx.tid.ret_cell.lock() = Some(retval);
-        this.ctx.tid.event.notify(0, SIG_READ);
-        idle::CTX_DROPPER.push(this.ctx);
+        let tid = unsafe { ManuallyDrop::take(&mut this.ctx.tid) };
+        tid::deallocate(tid.clone());
+        let _ = tid.retval.set(retval);
+        tid.transit(State::Ready, State::Exiting);
+        Exiting { ctx: this.ctx, tid }
     }
 }
 
@@ -282,9 +573,15 @@ impl DerefMut for Ready {
 pub struct Blocked {
     ctx: Box<Context>,
     block_desc: &'static str,
+    since: Instant,
 }
 
 impl IntoReady for Blocked {
+    #[inline]
+    fn tid(&self) -> &Tid {
+        &self.ctx.tid
+    }
+
     #[inline]
     fn last_cpu(&self) -> Option<usize> {
         Some(self.ctx.cpu)
@@ -295,14 +592,19 @@ impl IntoReady for Blocked {
         self.ctx.tid.affinity()
     }
 
-    #[inline]
     fn into_ready(this: Self, cpu: usize, time_slice: Duration) -> Ready {
+        let now = Instant::now();
         let mut ctx = this.ctx;
+        ctx.tid.transit(State::Blocked, State::Ready);
+        let duration = now.saturating_duration_since(this.since);
+        stat::record_block(ctx.tid.stats(), this.block_desc, duration);
         ctx.cpu = cpu;
         Ready {
             ctx,
             running_state: RunningState::NOT_RUNNING,
             time_slice,
+            slice_used: Duration::ZERO,
+            woken_at: Some(now),
         }
     }
 }
@@ -317,6 +619,57 @@ impl Blocked {
     pub fn block_desc(&self) -> &'static str {
         self.block_desc
     }
+}
+
+/// A task stopped by a suspension token or freezing, whose registers and
+/// memory can be accessed until it's resumed.
+#[derive(Debug)]
+pub struct Suspended {
+    ctx: Box<Context>,
+    since: Instant,
+}
+
+impl IntoReady for Suspended {
+    #[inline]
+    fn tid(&self) -> &Tid {
+        &self.ctx.tid
+    }
+
+    #[inline]
+    fn last_cpu(&self) -> Option<usize> {
+        Some(self.ctx.cpu)
+    }
+
+    #[inline]
+    fn affinity(&self) -> CpuMask {
+        self.ctx.tid.affinity()
+    }
+
+    fn into_ready(this: Self, cpu: usize, time_slice: Duration) -> Ready {
+        let now = Instant::now();
+        let mut ctx = this.ctx;
+        ctx.tid.transit(State::Suspended, State::Ready);
+        let duration = now.saturating_duration_since(this.since);
+        stat::record_block(ctx.tid.stats(), Self::BLOCK_DESC, duration);
+        ctx.cpu = cpu;
+        Ready {
+            ctx,
+            running_state: RunningState::NOT_RUNNING,
+            time_slice,
+            slice_used: Duration::ZERO,
+            woken_at: Some(now),
+        }
+    }
+}
+
+impl Suspended {
+    /// The reason the suspension is recorded as in the blocking statistics.
+    pub const BLOCK_DESC: &'static str = "task_ctl_suspend";
+
+    #[inline]
+    pub fn tid(&self) -> &Tid {
+        &self.ctx.tid
+    }
 
     #[inline]
     pub fn space(&self) -> &Arc<Space> {
@@ -343,3 +696,101 @@ impl Blocked {
         &mut self.ctx.ext_frame
     }
 }
+
+/// An exited task whose context is still in use until the CPU switches away
+/// from it.
+#[derive(Debug)]
+pub struct Exiting {
+    ctx: Box<Context>,
+    tid: Tid,
+}
+
+impl Exiting {
+    /// Drop the context, leaving the task a zombie.
+    pub fn reap(this: Self) {
+        drop(this.ctx);
+        this.tid.transit(State::Exiting, State::Zombie);
+    }
+}
+
+#[cfg(ktest)]
+mod ktests {
+    use super::*;
+    use crate::ktest::case;
+
+    case! {
+        fn running_state_transitions() {
+            let state = RunningState::NOT_RUNNING;
+            assert!(state.not_running());
+            assert!(!state.needs_resched());
+            assert!(state.start_time().is_none());
+
+            let now = Instant::now();
+            let state = RunningState::running(now);
+            assert!(!state.not_running());
+            assert!(!state.needs_resched());
+            assert_eq!(state.start_time(), Some(now));
+
+            let state = RunningState::NEED_RESCHED;
+            assert!(!state.not_running());
+            assert!(state.needs_resched());
+            assert!(state.start_time().is_none());
+        }
+
+        fn task_lifecycle() {
+            use State::*;
+            let ti = TaskInfo::builder()
+                .from(Default::default())
+                .excep_chan(Arsc::try_new(Default::default()).unwrap())
+                .name("ktest".into())
+                .ty(Type::Kernel)
+                .affinity(crate::cpu::current_mask())
+                .build()
+                .unwrap();
+            let tid = tid::allocate(ti).unwrap();
+            let space = Space::new_kernel().unwrap();
+            let kstack = ctx::Kstack::new(None, Type::Kernel);
+            let slice = Duration::from_millis(1);
+
+            // Every transition out of the current state but `allowed` fails
+            // without changing the state.
+            let check = |cur: State, allowed: &[State]| {
+                assert_eq!(tid.state(), cur);
+                for to in State::ALL.into_iter().filter(|to| !allowed.contains(to)) {
+                    assert_eq!(tid.try_transit(cur, to), Err(cur), "{cur:?} -> {to:?}");
+                }
+                // Not in the state the transition starts from.
+                for from in State::ALL.into_iter().filter(|&from| from != cur) {
+                    for to in State::ALL.into_iter().filter(|&to| from.can_move_to(to)) {
+                        assert_eq!(tid.try_transit(from, to), Err(cur), "{from:?} -> {to:?}");
+                    }
+                }
+                assert_eq!(tid.state(), cur);
+            };
+
+            let init = Init::new(tid.clone(), space, kstack, ctx::ExtFrame::zeroed());
+            check(Init, &[Ready]);
+            let ready = IntoReady::into_ready(init, 0, slice);
+            check(Ready, &[Blocked, Suspended, Exiting]);
+
+            let blocked = Ready::block(ready, "ktest");
+            assert_eq!(blocked.block_desc(), "ktest");
+            check(Blocked, &[Ready]);
+            let ready = IntoReady::into_ready(blocked, 0, slice);
+            assert!(ready.woken_at.is_some());
+            check(Ready, &[Blocked, Suspended, Exiting]);
+
+            let suspended = Ready::suspend(ready);
+            check(Suspended, &[Ready]);
+            let ready = IntoReady::into_ready(suspended, 0, slice);
+            check(Ready, &[Blocked, Suspended, Exiting]);
+
+            let exiting = Ready::into_exiting(ready, 12345);
+            check(Exiting, &[Zombie]);
+            assert_eq!(tid.retval(), Some(12345));
+
+            Exiting::reap(exiting);
+            check(Zombie, &[]);
+        }
+    }
+}
@@ -1,48 +1,69 @@
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use core::sync::atomic::{AtomicU64, Ordering::*};
 
 use sv_call::Feature;
 
 use super::{
-    hdl::{DefaultFeature, HandleMap},
+    hdl::{DefaultFeature, HandleMap, KernelObject},
     Tid,
 };
-use crate::{
-    mem,
-    sched::wait::{Futex, FutexKey, FutexRef, Futexes},
-};
+use crate::{mem, sched::Event};
 
 #[derive(Debug)]
 pub struct Space {
+    id: u64,
     mem: Arc<mem::space::Space>,
     handles: HandleMap,
-    futexes: Futexes,
     main: AtomicU64,
 }
 
+fn next_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    NEXT_ID.fetch_add(1, Relaxed)
+}
+
 unsafe impl Send for Space {}
 unsafe impl Sync for Space {}
 
 impl Space {
+    #[track_caller]
     pub fn new() -> sv_call::Result<Arc<Self>> {
         let mem = mem::space::Space::try_new(super::Type::User)?;
-        Ok(Arc::try_new(Space {
+        let ret = Arc::try_new(Space {
+            id: next_id(),
             mem,
             handles: HandleMap::new(),
-            futexes: Default::default(),
+            main: AtomicU64::new(0),
+        })?;
+        mem::space::leak::record_space(&ret);
+        Ok(ret)
+    }
+
+    /// Create a space of the kernel threads, sharing the kernel memory space.
+    pub fn new_kernel() -> sv_call::Result<Arc<Self>> {
+        Ok(Arc::try_new(Space {
+            id: next_id(),
+            mem: Arc::clone(&mem::space::KRL),
+            handles: HandleMap::new(),
             main: AtomicU64::new(0),
         })?)
     }
 
     pub fn new_current() -> Arc<Self> {
         Arc::new(Space {
+            id: next_id(),
             mem: mem::space::with_current(Arc::clone),
             handles: HandleMap::new(),
-            futexes: Default::default(),
             main: AtomicU64::new(0),
         })
     }
 
+    /// The job id of the tasks in the space, unique during the boot.
+    #[inline]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
     #[inline]
     pub fn mem(&self) -> &Arc<mem::space::Space> {
         &self.mem
@@ -68,20 +89,6 @@ impl Space {
         &self.handles
     }
 
-    /// # Safety
-    ///
-    /// The function must be called when `PREEMPT` is disabled or locked.
-    pub unsafe fn futex(&self, key: FutexKey) -> FutexRef {
-        self.futexes.get_or_insert(key, Futex::new(key)).downgrade()
-    }
-
-    /// # Safety
-    ///
-    /// The function must be called when `PREEMPT` is disabled or locked.
-    pub unsafe fn try_drop_futex(&self, key: FutexKey) {
-        let _ = self.futexes.remove_if(&key, |futex| futex.is_empty());
-    }
-
     pub fn child(&self, hdl: sv_call::Handle) -> sv_call::Result<Tid> {
         super::PREEMPT.scope(|| {
             self.handles().get::<Tid>(hdl).and_then(|obj| {
@@ -100,3 +107,9 @@ unsafe impl DefaultFeature for Space {
         Feature::READ | Feature::WRITE
     }
 }
+
+impl KernelObject for Space {
+    fn event(_: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        None
+    }
+}
@@ -0,0 +1,162 @@
+//! The histograms of the scheduling events of every task, aggregated into
+//! system-wide ones as well:
+//!
+//! - The latency from waking up to running;
+//! - The usage of the time slice when switched out;
+//! - The duration of blocking, by the reason passed to the scheduler.
+
+use alloc::vec::Vec;
+use core::{
+    sync::atomic::{AtomicU64, Ordering::*},
+    time::Duration,
+};
+
+use spin::Mutex;
+use sv_call::task::{
+    SchedStat, SCHED_HIST_BUCKETS, SCHED_REASON_LEN, TASK_NAME_LEN, TASK_STAT_BLOCK,
+    TASK_STAT_SLICE_USAGE, TASK_STAT_WAKE_LATENCY,
+};
+
+use crate::sched::PREEMPT;
+
+#[derive(Debug)]
+struct Hist {
+    buckets: [AtomicU64; SCHED_HIST_BUCKETS],
+    max: AtomicU64,
+}
+
+impl Hist {
+    const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU64 = AtomicU64::new(0);
+        Hist {
+            buckets: [ZERO; SCHED_HIST_BUCKETS],
+            max: ZERO,
+        }
+    }
+
+    fn record(&self, value: u64) {
+        let index = (u64::BITS - value.leading_zeros()) as usize;
+        self.buckets[index.min(SCHED_HIST_BUCKETS - 1)].fetch_add(1, Relaxed);
+        self.max.fetch_max(value, Relaxed);
+    }
+
+    fn stat(&self) -> SchedStat {
+        let buckets: [u64; SCHED_HIST_BUCKETS] =
+            core::array::from_fn(|index| self.buckets[index].load(Relaxed));
+        let count = buckets.iter().sum();
+        let max = self.max.load(Relaxed);
+
+        // The upper bound of the bucket where the percentile falls in.
+        let percentile = |p: u64| {
+            let rank = (count * p + 99) / 100;
+            let mut acc = 0;
+            let index = buckets.iter().position(|&n| {
+                acc += n;
+                acc >= rank
+            });
+            (1u64 << index.unwrap_or(0)).min(max)
+        };
+        SchedStat {
+            count,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max,
+            buckets,
+            reason: [0; SCHED_REASON_LEN],
+            name: [0; TASK_NAME_LEN],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Stats {
+    wake_latency: Hist,
+    slice_usage: Hist,
+    /// The blocking durations in the order the reasons are first seen.
+    block: Mutex<Vec<(&'static str, Hist)>>,
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Stats {
+            wake_latency: Hist::new(),
+            slice_usage: Hist::new(),
+            block: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn record_block(&self, reason: &'static str, nanos: u64) {
+        PREEMPT.scope(|| {
+            let mut block = self.block.lock();
+            match block.iter().find(|(r, _)| *r == reason) {
+                Some((_, hist)) => hist.record(nanos),
+                None => {
+                    let hist = Hist::new();
+                    hist.record(nanos);
+                    block.push((reason, hist));
+                }
+            }
+        })
+    }
+
+    /// Get the histogram of `query`, where `index` selects the blocking
+    /// reason.
+    pub fn stat(&self, query: u32, index: usize) -> sv_call::Result<SchedStat> {
+        match query {
+            TASK_STAT_WAKE_LATENCY => Ok(self.wake_latency.stat()),
+            TASK_STAT_SLICE_USAGE => Ok(self.slice_usage.stat()),
+            TASK_STAT_BLOCK => PREEMPT.scope(|| {
+                let block = self.block.lock();
+                let (reason, hist) = block.get(index).ok_or(sv_call::ENOENT)?;
+                let mut ret = hist.stat();
+                let len = reason.len().min(SCHED_REASON_LEN);
+                ret.reason[..len].copy_from_slice(&reason.as_bytes()[..len]);
+                Ok(ret)
+            }),
+            _ => Err(sv_call::EINVAL),
+        }
+    }
+}
+
+impl Default for Stats {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static SYSTEM: Stats = Stats::new();
+
+#[inline]
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// The system-wide histograms of all the tasks.
+#[inline]
+pub fn system() -> &'static Stats {
+    &SYSTEM
+}
+
+pub(in crate::sched) fn record_wake(stats: &Stats, latency: Duration) {
+    let value = nanos(latency);
+    stats.wake_latency.record(value);
+    SYSTEM.wake_latency.record(value);
+}
+
+pub(in crate::sched) fn record_slice(stats: &Stats, used: Duration, time_slice: Duration) {
+    if time_slice.is_zero() {
+        return;
+    }
+    let value = u64::try_from(used.as_nanos() * 1000 / time_slice.as_nanos()).unwrap_or(u64::MAX);
+    stats.slice_usage.record(value);
+    SYSTEM.slice_usage.record(value);
+}
+
+pub(in crate::sched) fn record_block(stats: &Stats, reason: &'static str, duration: Duration) {
+    let value = nanos(duration);
+    stats.record_block(reason, value);
+    SYSTEM.record_block(reason, value);
+}
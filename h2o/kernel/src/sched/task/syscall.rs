@@ -1,23 +1,32 @@
-use alloc::{string::String, sync::Arc, vec::Vec};
-use core::{hint, slice, time::Duration};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{hint, sync::atomic::Ordering::Relaxed, time::Duration};
 
 use paging::LAddr;
 use spin::Mutex;
 use sv_call::*;
 
 use super::{
-    hdl::{DefaultFeature, Ref},
-    Blocked, RunningState, Signal, Space, Tid,
+    hdl::{DefaultFeature, KernelObject, Ref},
+    Priority, RunningState, Signal, Space, Suspended, Tid,
 };
 use crate::{
-    cpu::time::Instant,
-    sched::{imp::MIN_TIME_GRAN, Arsc, PREEMPT, SCHED},
-    syscall::{In, InOut, Out, UserPtr},
+    cpu::time::{self, Instant},
+    dev::{mem_resource, Resource},
+    sched::{
+        imp::MIN_TIME_GRAN,
+        ipc::{Channel, Packet},
+        Arsc, Event, PREEMPT, SCHED,
+    },
+    syscall::{copy_from_user, copy_to_user, restart_until, In, InOut, Out, UserPtr},
 };
 
 #[derive(Debug)]
 struct SuspendToken {
-    slot: Arsc<Mutex<Option<super::Blocked>>>,
+    slot: Arsc<Mutex<Option<Suspended>>>,
     tid: Tid,
 }
 
@@ -47,6 +56,12 @@ unsafe impl DefaultFeature for SuspendToken {
     }
 }
 
+impl KernelObject for SuspendToken {
+    fn event(_: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        None
+    }
+}
+
 #[syscall]
 fn task_exit(retval: usize, kill_all: bool) -> Result {
     SCHED.exit_current(retval, kill_all);
@@ -65,12 +80,74 @@ fn task_sleep(ms: u32) -> Result {
 
         Ok(())
     } else {
-        SCHED
-            .block_current((), None, Duration::from_millis(u64::from(ms)), "task_sleep")
-            .map(|_| ())
+        let deadline = time::deadline_after(Duration::from_millis(u64::from(ms)));
+        restart_until(deadline, |deadline| {
+            let remaining = time::remaining(deadline);
+            if remaining.is_zero() {
+                return Ok(());
+            }
+            SCHED
+                .block_current((), None, remaining, true, "task_sleep")
+                .map(|_| ())
+        })
     }
 }
 
+/// Sleep until the deadline of `deadline_ns` nanoseconds of the clock, which
+/// unlike the duration of `task_sleep` isn't stretched by the time spent
+/// before the call.
+#[syscall(restart)]
+fn task_sleep_until(deadline_ns: u64) -> Result {
+    let remaining = time::remaining(time::deadline_from_ns(deadline_ns));
+    if remaining.is_zero() {
+        return Ok(());
+    }
+    SCHED
+        .block_current((), None, remaining, true, "task_sleep_until")
+        .map(|_| ())
+}
+
+/// Hint the scheduler to run the task of `hdl` right after the current one on
+/// this CPU, if the former is woken up by the latter before it's switched out,
+/// e.g. when sending a request to a server and waiting for the reply.
+#[syscall]
+fn task_yield_to(hdl: Handle) -> Result {
+    hdl.check_null()?;
+
+    let tid = SCHED.with_current(|cur| {
+        let tid = cur.space().handles().get::<Tid>(hdl)?;
+        Ok(tid.raw())
+    })?;
+    SCHED.yield_to(tid);
+    Ok(())
+}
+
+#[syscall]
+fn task_local_get(slot: u32, value: UserPtr<Out, usize>) -> Result {
+    value.check()?;
+    let data = SCHED.with_current(|cur| {
+        let local = cur.tid().local(slot).ok_or(ERANGE)?;
+        Ok(local.load(Relaxed))
+    })?;
+    value.write(data)
+}
+
+#[syscall]
+fn task_local_set(slot: u32, value: usize) -> Result {
+    SCHED.with_current(|cur| {
+        let local = cur.tid().local(slot).ok_or(ERANGE)?;
+        local.store(value, Relaxed);
+        Ok(())
+    })
+}
+
+/// Set the thread pointer (the FS base on x86_64) of the current task, for the
+/// processors where userspace can't do it directly.
+#[syscall]
+fn task_set_tls(value: usize) -> Result {
+    SCHED.with_current(|cur| cur.kstack_mut().task_frame_mut().set_fs_base(value as u64))
+}
+
 fn get_name(ptr: UserPtr<In>, len: usize) -> Result<Option<String>> {
     if !ptr.as_ptr().is_null() {
         let mut buf = Vec::<u8>::with_capacity(len);
@@ -125,6 +202,135 @@ fn task_exec(ci: UserPtr<In, task::ExecInfo>) -> Result<Handle> {
     Ok(hdl)
 }
 
+fn read_slice<T: Copy>(ptr: *const T, len: usize) -> Result<Vec<T>> {
+    let mut buf = Vec::<T>::with_capacity(len);
+    if len > 0 {
+        unsafe {
+            UserPtr::<In, T>::new(ptr as *mut T).read_slice(buf.as_mut_ptr(), len)?;
+            buf.set_len(len);
+        }
+    }
+    Ok(buf)
+}
+
+/// Serialize the startup arguments for `task_spawn`, in the same layout as
+/// `svrt::StartupArgs` serialized by `solvent_rpc`.
+///
+/// `transfers` must be sorted by their handle infos.
+fn startup_args(transfers: &[task::HandleTransfer], args: &[u8], env: &[u8]) -> Vec<u8> {
+    let usize_len = core::mem::size_of::<usize>();
+    let mut buffer = Vec::with_capacity(
+        usize_len * 6 + transfers.len() * core::mem::size_of::<u32>() + args.len() + env.len(),
+    );
+    buffer.extend_from_slice(&task::STARTUP_ARGS_MAGIC.to_ne_bytes());
+    buffer.extend_from_slice(&task::STARTUP_ARGS.to_ne_bytes());
+
+    // The handle map is followed by the number of the handles inside, which
+    // is one for each entry.
+    buffer.extend_from_slice(&transfers.len().to_ne_bytes());
+    buffer.extend_from_slice(&transfers.len().to_ne_bytes());
+    for transfer in transfers {
+        buffer.extend_from_slice(&transfer.info.to_ne_bytes());
+    }
+    buffer.extend_from_slice(&args.len().to_ne_bytes());
+    buffer.extend_from_slice(args);
+    buffer.extend_from_slice(&env.len().to_ne_bytes());
+    buffer.extend_from_slice(env);
+    buffer
+}
+
+#[syscall]
+fn task_spawn(ci: UserPtr<In, task::ExecInfo>, si: UserPtr<In, task::SpawnInfo>) -> Result<Handle> {
+    let ci = unsafe { ci.read()? };
+    let si = unsafe { si.read()? };
+
+    let name = get_name(UserPtr::<In, _>::new(ci.name as *mut u8), ci.name_len)?;
+    UserPtr::<In, _>::new(ci.entry).check()?;
+    UserPtr::<In, _>::new(ci.stack).check()?;
+
+    if si.transfer_count > ipc::MAX_HANDLE_COUNT {
+        return Err(ENOMEM);
+    }
+    let mut transfers = read_slice(si.transfers, si.transfer_count)?;
+    // Handles are ordered by their infos in the startup arguments.
+    transfers.sort_unstable_by_key(|transfer| transfer.info.to_ne_bytes());
+    let duplicate = transfers.windows(2).any(|w| w[0].info == w[1].info);
+    let reserved = |transfer: &task::HandleTransfer| {
+        transfer.handle == ci.init_chan || transfer.handle == ci.space
+    };
+    if duplicate || transfers.iter().any(reserved) {
+        return Err(EINVAL);
+    }
+
+    let args = read_slice(si.args, si.args_len)?;
+    let env = read_slice(si.env, si.env_len)?;
+    let buffer = startup_args(&transfers, &args, &env);
+    if buffer.len() > ipc::MAX_BUFFER_SIZE {
+        return Err(EBUFFER);
+    }
+
+    // Everything that may fail is done before the handles are removed from
+    // the caller, so that nothing is consumed on failure. Until then, the init
+    // channel is shared with the new space, whose task doesn't run yet.
+    let (init_chan, space) = SCHED.with_current(|cur| {
+        let handles = cur.space().handles();
+        // Without an init channel from the caller, the packet is the only one
+        // the new task can receive from its own.
+        let init_chan = if ci.init_chan == Handle::NULL {
+            let (chan, _) = Channel::new();
+            Ref::try_new(chan)?
+        } else {
+            let chan = handles.get::<Channel>(ci.init_chan)?;
+            let mut shared = Ref::from_raw(Arc::clone(&chan))?;
+            shared.set_features(chan.features())?;
+            shared
+        };
+        let space = if ci.space == Handle::NULL {
+            Arc::clone(cur.space())
+        } else {
+            Arc::clone(&handles.get::<Space>(ci.space)?)
+        };
+        Ok((init_chan, space))
+    })?;
+
+    let chan = Arc::clone(&init_chan);
+    let init_chan = PREEMPT.scope(|| space.handles().insert_ref(init_chan))?;
+    let starter = super::Starter {
+        entry: LAddr::new(ci.entry),
+        stack: LAddr::new(ci.stack),
+        arg: ci.arg,
+    };
+    let (task, hdl) = match super::exec(name, Arc::clone(&space), init_chan, &starter) {
+        Ok(ret) => ret,
+        Err(err) => {
+            let _ = space.handles().remove_ref(init_chan);
+            return Err(err);
+        }
+    };
+
+    let moved = [ci.init_chan, ci.space]
+        .into_iter()
+        .filter(|&handle| handle != Handle::NULL)
+        .collect::<Vec<_>>();
+    let res = SCHED.with_current(|cur| cur.space().handles().transfer(&transfers, &moved));
+    let objects = match res {
+        // The removed init channel and space are already shared.
+        Ok((objects, _)) => objects,
+        Err(err) => {
+            // The new task never runs, so it's dropped along with its handles.
+            let _ = SCHED.with_current(|cur| cur.space().handles().remove_ref(hdl));
+            let _ = space.handles().remove_ref(init_chan);
+            drop(task);
+            return Err(err);
+        }
+    };
+    chan.enqueue(Packet::new(0, objects, &buffer));
+
+    SCHED.unblock(task, true);
+
+    Ok(hdl)
+}
+
 #[syscall]
 fn task_new(
     name: UserPtr<In>,
@@ -158,10 +364,11 @@ fn task_new(
 
     let (task, hdl) = super::create(name, space, init_chan)?;
 
-    let task = super::Ready::block(
-        super::IntoReady::into_ready(task, unsafe { crate::cpu::id() }, MIN_TIME_GRAN),
-        "task_ctl_suspend",
-    );
+    let task = super::Ready::suspend(super::IntoReady::into_ready(
+        task,
+        unsafe { crate::cpu::id() },
+        MIN_TIME_GRAN,
+    ));
 
     let tid = task.tid().clone();
     let st_data = unsafe {
@@ -171,7 +378,7 @@ fn task_new(
             tid,
         }
     };
-    SCHED.with_current(|cur| st.write(cur.space().handles().insert(st_data, None)?))?;
+    SCHED.with_current(|cur| st.write(cur.space().handles().insert(st_data)?))?;
 
     Ok(hdl)
 }
@@ -182,16 +389,174 @@ fn task_join(hdl: Handle, retval: UserPtr<Out, usize>) -> Result {
 
     SCHED.with_current(|cur| {
         let handles = cur.space().handles();
-        let val = match handles.get::<Tid>(hdl) {
-            Ok(tid) => tid.ret_cell().lock().ok_or(ENOENT)?,
-            Err(e) => return Err(e),
-        };
+        let val = handles.get::<Tid>(hdl)?.retval().ok_or(ENOENT)?;
 
         drop(handles.remove::<Tid>(hdl));
         unsafe { retval.write(val) }
     })
 }
 
+/// Query the return value of an exited task without consuming its handle.
+///
+/// The task handle raises `SIG_GENERIC` on exit, so the caller can wait for it
+/// with `obj_wait` or a dispatcher before querying.
+#[syscall]
+fn task_retval(hdl: Handle) -> Result<usize> {
+    hdl.check_null()?;
+
+    SCHED.with_current(|cur| {
+        let tid = cur.space().handles().get::<Tid>(hdl)?;
+        if !tid.features().contains(Feature::READ) {
+            return Err(EPERM);
+        }
+        tid.retval().ok_or(ENOENT)
+    })
+}
+
+#[syscall]
+fn task_runtime(hdl: Handle) -> Result<u64> {
+    hdl.check_null()?;
+
+    SCHED.with_current(|cur| {
+        let tid = cur.space().handles().get::<Tid>(hdl)?;
+        if !tid.features().contains(Feature::READ) {
+            return Err(EPERM);
+        }
+        Ok(tid.runtime().as_nanos() as u64)
+    })
+}
+
+/// Get the job of the task of `hdl`, or the current task if `hdl` is null.
+///
+/// The tasks sharing a space belong to the same job, which is stopped as a
+/// whole when its main task exits.
+#[syscall]
+fn task_job(hdl: Handle) -> Result<u64> {
+    task_or_current(hdl, Feature::READ).map(|tid| tid.job())
+}
+
+/// Get the priority of the task of `hdl`, or the current task if `hdl` is
+/// null, including the ones inherited from the callers blocked on it (see
+/// `TASK_PRIO_*`).
+#[syscall]
+fn task_priority(hdl: Handle) -> Result<usize> {
+    task_or_current(hdl, Feature::READ).map(|tid| tid.priority() as usize)
+}
+
+/// Set the priority of the task of `hdl`, or the current task if `hdl` is
+/// null (see `TASK_PRIO_*`).
+///
+/// The priority can't be higher than the one set for the current task, or
+/// any task could starve the others.
+#[syscall]
+fn task_set_priority(hdl: Handle, priority: u32) -> Result {
+    if priority > task::TASK_PRIO_HIGH {
+        return Err(EINVAL);
+    }
+    let priority = Priority::from_raw(priority as u8);
+    let tid = task_or_current(hdl, Feature::WRITE)?;
+    let limit = SCHED.with_current(|cur| Ok(cur.tid().base_priority()))?;
+    if priority > limit {
+        return Err(EPERM);
+    }
+    tid.set_priority(priority);
+    Ok(())
+}
+
+/// Get the task of `hdl` with `feat`, or the current task if `hdl` is null.
+fn task_or_current(hdl: Handle, feat: Feature) -> Result<Tid> {
+    SCHED.with_current(|cur| {
+        if hdl == Handle::NULL {
+            return Ok(cur.tid().clone());
+        }
+        let tid = cur.space().handles().get::<Tid>(hdl)?;
+        if !tid.features().contains(feat) {
+            return Err(EPERM);
+        }
+        Ok(Tid::clone(&tid))
+    })
+}
+
+/// Rename the task of `hdl`, or the current task if `hdl` is null.
+#[syscall]
+fn task_set_name(hdl: Handle, name: UserPtr<In>, len: usize) -> Result {
+    if len > task::TASK_NAME_LEN {
+        return Err(ERANGE);
+    }
+    let name = get_name(name, len)?.ok_or(EINVAL)?;
+
+    let tid = task_or_current(hdl, Feature::WRITE)?;
+    tid.set_name(Arc::from(name));
+    Ok(())
+}
+
+/// Get the name of the task of `hdl`, or the current task if `hdl` is null,
+/// truncated to `len` bytes. Returns the length of the whole name.
+#[syscall]
+fn task_get_name(hdl: Handle, buf: UserPtr<Out>, len: usize) -> Result<usize> {
+    let name = task_or_current(hdl, Feature::READ)?.name();
+    let count = name.len().min(len);
+    if count > 0 {
+        buf.write_slice(&name.as_bytes()[..count])?;
+    }
+    Ok(name.len())
+}
+
+/// Get the histogram of the scheduling statistic `query` of the task (see
+/// `TASK_STAT_*`), where `index` selects the blocking reason.
+#[syscall]
+fn task_stat(hdl: Handle, query: u32, index: usize, stat: UserPtr<Out, task::SchedStat>) -> Result {
+    hdl.check_null()?;
+    stat.check()?;
+
+    let data = SCHED.with_current(|cur| {
+        let tid = cur.space().handles().get::<Tid>(hdl)?;
+        if !tid.features().contains(Feature::READ) {
+            return Err(EPERM);
+        }
+        let mut data = tid.stats().stat(query, index)?;
+        let name = tid.name();
+        let len = name.len().min(task::TASK_NAME_LEN);
+        data.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Ok(data)
+    })?;
+    stat.write(data)
+}
+
+/// Get the system-wide histogram of the scheduling statistic `query`, which
+/// requires the root memory resource.
+#[syscall]
+fn sched_stat(
+    res: Handle,
+    query: u32,
+    index: usize,
+    stat: UserPtr<Out, task::SchedStat>,
+) -> Result {
+    stat.check()?;
+    SCHED.with_current(|cur| {
+        let res = cur.space().handles().get::<Resource<usize>>(res)?;
+        res.check_root(mem_resource())
+    })?;
+
+    let data = super::stat::system().stat(query, index)?;
+    stat.write(data)
+}
+
+/// Get the time accounting of `cpu` and the statistics of its deferred work,
+/// which requires the root memory resource.
+#[syscall]
+fn cpu_stat(res: Handle, cpu: usize, stat: UserPtr<Out, task::CpuStat>) -> Result {
+    stat.check()?;
+    SCHED.with_current(|cur| {
+        let res = cur.space().handles().get::<Resource<usize>>(res)?;
+        res.check_root(mem_resource())
+    })?;
+
+    let mut data = crate::sched::account::stat(cpu).ok_or(ENOENT)?;
+    crate::sched::defer::stat(cpu, &mut data);
+    stat.write(data)
+}
+
 #[syscall]
 fn task_ctl(hdl: Handle, op: u32, data: UserPtr<InOut, Handle>) -> Result {
     hdl.check_null()?;
@@ -202,6 +567,8 @@ fn task_ctl(hdl: Handle, op: u32, data: UserPtr<InOut, Handle>) -> Result {
         task::TASK_CTL_KILL => {
             let child = cur.child(hdl)?;
             child.with_signal(|sig| *sig = Some(Signal::Kill));
+            child.interrupt();
+            crate::audit::record(audit::AUDIT_TASK_KILL, [child.raw(), 0, 0]);
 
             Ok(())
         }
@@ -223,8 +590,9 @@ fn task_ctl(hdl: Handle, op: u32, data: UserPtr<InOut, Handle>) -> Result {
                     Ok(())
                 }
             })?;
+            st.tid.interrupt();
 
-            let out = super::PREEMPT.scope(|| cur.handles().insert(st, None))?;
+            let out = super::PREEMPT.scope(|| cur.handles().insert(st))?;
             unsafe { data.write(out)? };
 
             Ok(())
@@ -234,7 +602,7 @@ fn task_ctl(hdl: Handle, op: u32, data: UserPtr<InOut, Handle>) -> Result {
 }
 
 fn read_regs(
-    task: &Blocked,
+    task: &Suspended,
     feat: Feature,
     addr: usize,
     data: UserPtr<Out>,
@@ -264,7 +632,7 @@ fn read_regs(
 }
 
 fn write_regs(
-    task: &mut Blocked,
+    task: &mut Suspended,
     feat: Feature,
     addr: usize,
     data: UserPtr<In>,
@@ -295,7 +663,51 @@ fn write_regs(
     }
 }
 
-fn create_excep_chan(task: &Blocked, feat: Feature) -> Result<crate::sched::ipc::Channel> {
+/// Read the memory of the task through a kernel buffer, since it's in another
+/// space.
+fn read_mem(
+    task: &Suspended,
+    feat: Feature,
+    addr: usize,
+    data: UserPtr<Out>,
+    len: usize,
+) -> Result {
+    if !feat.contains(Feature::READ) {
+        return Err(EPERM);
+    }
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).map_err(|_| ENOMEM)?;
+    unsafe {
+        crate::mem::space::with(task.space().mem(), |_| {
+            copy_from_user(buf.as_mut_ptr(), addr as *const u8, len)
+        })?;
+        buf.set_len(len);
+    }
+    data.write_slice(&buf)
+}
+
+fn write_mem(
+    task: &Suspended,
+    feat: Feature,
+    addr: usize,
+    data: UserPtr<In>,
+    len: usize,
+) -> Result {
+    if !feat.contains(Feature::WRITE) {
+        return Err(EPERM);
+    }
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).map_err(|_| ENOMEM)?;
+    unsafe {
+        data.read_slice(buf.as_mut_ptr(), len)?;
+        buf.set_len(len);
+        crate::mem::space::with(task.space().mem(), |_| {
+            copy_to_user(addr as *mut u8, buf.as_ptr(), len)
+        })
+    }
+}
+
+fn create_excep_chan(task: &Suspended, feat: Feature) -> Result<crate::sched::ipc::Channel> {
     if !feat.contains(Feature::READ) {
         return Err(EPERM);
     }
@@ -333,32 +745,15 @@ fn task_debug(hdl: Handle, op: u32, addr: usize, data: UserPtr<InOut, u8>, len:
     let ret = match op {
         task::TASK_DBG_READ_REG => read_regs(&task, feat, addr, data.out(), len),
         task::TASK_DBG_WRITE_REG => write_regs(&mut task, feat, addr, data.r#in(), len),
-        task::TASK_DBG_READ_MEM => unsafe {
-            crate::mem::space::with(task.space().mem(), |_| {
-                if !feat.contains(Feature::READ) {
-                    return Err(EPERM);
-                }
-                let slice = slice::from_raw_parts(addr as *mut u8, len);
-                data.write_slice(slice)
-            })
-        },
-        task::TASK_DBG_WRITE_MEM => unsafe {
-            crate::mem::space::with(task.space().mem(), |_| {
-                if !feat.contains(Feature::WRITE) {
-                    return Err(EPERM);
-                }
-                data.read_slice(addr as *mut u8, len)
-            })
-        },
+        task::TASK_DBG_READ_MEM => read_mem(&task, feat, addr, data.out(), len),
+        task::TASK_DBG_WRITE_MEM => write_mem(&task, feat, addr, data.r#in(), len),
         task::TASK_DBG_EXCEP_HDL => {
             if len < core::mem::size_of::<Handle>() {
                 Err(EBUFFER)
             } else {
                 let hdl = SCHED.with_current(|cur| {
-                    create_excep_chan(&task, feat).and_then(|chan| {
-                        let event = Arc::downgrade(chan.event()) as _;
-                        cur.space().handles().insert(chan, Some(event))
-                    })
+                    create_excep_chan(&task, feat)
+                        .and_then(|chan| cur.space().handles().insert(chan))
                 })?;
 
                 unsafe { data.cast::<Handle>().write(hdl) }
@@ -1,5 +1,9 @@
-use alloc::sync::{Arc, Weak};
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use core::{
+    cell::RefCell,
     hash::BuildHasherDefault,
     num::NonZeroU64,
     ops::Deref,
@@ -10,8 +14,11 @@ use archop::Azy;
 use collection_ex::{CHashMap, FnvHasher};
 use sv_call::Feature;
 
-use super::{hdl::DefaultFeature, TaskInfo};
-use crate::sched::PREEMPT;
+use super::{
+    hdl::{DefaultFeature, KernelObject},
+    TaskInfo,
+};
+use crate::sched::{Event, PREEMPT};
 
 pub const NR_TASKS: usize = 65536;
 
@@ -91,7 +98,13 @@ impl PartialEq for Tid {
 
 unsafe impl DefaultFeature for Tid {
     fn default_features() -> Feature {
-        Feature::SEND | Feature::EXECUTE | Feature::WAIT
+        Feature::SEND | Feature::SYNC | Feature::READ | Feature::EXECUTE | Feature::WAIT
+    }
+}
+
+impl KernelObject for Tid {
+    fn event(this: &Arc<Self>) -> Option<Weak<dyn Event>> {
+        Some(Arc::downgrade(this.event()) as _)
     }
 }
 
@@ -135,6 +148,21 @@ pub fn deallocate(tid: Tid) -> bool {
         .is_some()
 }
 
+/// Get all the tasks alive.
+pub fn all() -> Vec<Tid> {
+    let tids = RefCell::new(Vec::new());
+    PREEMPT.scope(|| {
+        TI_MAP.retain(|&raw, ti| {
+            if let Some(raw) = NonZeroU64::new(raw) {
+                let ti = Arc::clone(ti);
+                tids.borrow_mut().push(Tid { raw, ti });
+            }
+            true
+        })
+    });
+    tids.into_inner()
+}
+
 #[inline]
 pub fn init() {
     Azy::force(&TI_MAP);
@@ -1,10 +1,11 @@
+mod cell;
 mod futex;
 
 use core::time::Duration;
 
 use crossbeam_queue::SegQueue;
 
-pub use self::futex::*;
+pub use self::{cell::WaitCell, futex::*};
 use super::{ipc::Arsc, *};
 use crate::cpu::time::Timer;
 
@@ -24,19 +25,31 @@ impl WaitObject {
         }
     }
 
+    /// Block the current task until notified, failing with `ETIME` if
+    /// `timeout` elapses, or with `EINTR` if the wait is `interruptible` and a
+    /// signal is raised on the task.
     #[inline]
     pub fn wait<T>(
         &self,
         guard: T,
         timeout: Duration,
+        interruptible: bool,
         block_desc: &'static str,
     ) -> sv_call::Result {
-        let timer = SCHED.block_current(guard, Some(&self.wait_queue), timeout, block_desc);
+        let timer = SCHED.block_current(
+            guard,
+            Some(&self.wait_queue),
+            timeout,
+            interruptible,
+            block_desc,
+        );
         timer.and_then(|timer| {
-            if !timer.is_fired() {
-                Ok(())
-            } else {
+            if timer.is_fired() {
                 Err(sv_call::ETIME)
+            } else if timer.is_interrupted() {
+                Err(sv_call::EINTR)
+            } else {
+                Ok(())
             }
         })
     }
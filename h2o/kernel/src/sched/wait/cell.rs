@@ -0,0 +1,97 @@
+use alloc::sync::Arc;
+use core::time::Duration;
+
+use spin::Mutex;
+
+use super::WaitObject;
+use crate::sched::{BasicEvent, Event, PREEMPT, SIG_GENERIC};
+
+/// A cell that can be set only once, broadcasting its value to all the
+/// waiters.
+///
+/// Taking the value clones it, so any number of waiters can receive it, either
+/// by blocking in [`WaitCell::wait`] or by waiting for the signal on the
+/// event of the cell.
+#[derive(Debug)]
+pub struct WaitCell<T> {
+    value: Mutex<Option<T>>,
+    wo: WaitObject,
+    event: Arc<BasicEvent>,
+    signal: usize,
+}
+
+impl<T: Clone> WaitCell<T> {
+    /// Create an empty cell whose event raises `signal` when it's set.
+    pub fn new(signal: usize) -> Self {
+        WaitCell {
+            value: Mutex::new(None),
+            wo: WaitObject::new(),
+            event: BasicEvent::new(0),
+            signal,
+        }
+    }
+
+    #[inline]
+    pub fn event(&self) -> &Arc<BasicEvent> {
+        &self.event
+    }
+
+    /// Set the value of the cell and wake up all the waiters.
+    ///
+    /// # Errors
+    ///
+    /// Returns the value back if the cell is already set.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        PREEMPT.scope(|| {
+            let mut slot = self.value.lock();
+            match *slot {
+                Some(_) => Err(value),
+                None => {
+                    *slot = Some(value);
+                    Ok(())
+                }
+            }
+        })?;
+        self.wo.notify(0, false);
+        self.event.notify(0, self.signal);
+        Ok(())
+    }
+
+    /// Get a clone of the value, or `None` if the cell is not set yet.
+    #[inline]
+    pub fn get(&self) -> Option<T> {
+        PREEMPT.scope(|| self.value.lock().clone())
+    }
+
+    /// Block the current task until the cell is set, and get a clone of the
+    /// value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ETIME` if the cell isn't set within `timeout`, or `EINTR` if
+    /// the wait is `interruptible` and interrupted by a signal.
+    pub fn wait(
+        &self,
+        timeout: Duration,
+        interruptible: bool,
+        block_desc: &'static str,
+    ) -> sv_call::Result<T> {
+        let pree = PREEMPT.lock();
+        let slot = self.value.lock();
+        if let Some(ref value) = *slot {
+            return Ok(value.clone());
+        }
+        // The lock is released only after the current task is queued, so the
+        // notification in `set` can't be missed.
+        self.wo
+            .wait((slot, pree), timeout, interruptible, block_desc)?;
+        self.get().ok_or(sv_call::ETIME)
+    }
+}
+
+impl<T: Clone> Default for WaitCell<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new(SIG_GENERIC)
+    }
+}
@@ -1,15 +1,53 @@
-use core::{fmt, hash::BuildHasherDefault, intrinsics, time::Duration};
-
+//! The futexes, blocking the tasks on the values of user memory.
+//!
+//! A futex is keyed on the physical address behind the user address, so the
+//! tasks sharing the memory in different spaces meet on the same futex. A
+//! writable page still backed by the zero page is committed before it's keyed.
+//!
+//! The futexes are created on demand and dropped when no task waits on them.
+//! Since the physical page may be reused once unmapped, the waiters must check
+//! their conditions again after waking up.
+
+use alloc::sync::Arc;
+use core::{fmt, hash::BuildHasherDefault, time::Duration};
+
+use archop::Azy;
 use collection_ex::{CHashMap, FnvHasher};
+use paging::{LAddr, PAddr};
 use sv_call::*;
 
 use super::WaitObject;
+use crate::syscall::{In, UserPtr};
 
 type BH = BuildHasherDefault<FnvHasher>;
-pub type FutexKey = crate::syscall::UserPtr<crate::syscall::In, u64>;
+pub type FutexKey = PAddr;
 pub type FutexRef<'a> = collection_ex::CHashMapReadGuard<'a, FutexKey, Futex, BH>;
 pub type Futexes = CHashMap<FutexKey, Futex, BH>;
 
+static FUTEXES: Azy<Futexes> = Azy::new(Default::default);
+
+/// Get the key of the futex at `ptr` in the current space.
+pub fn futex_key(ptr: UserPtr<In, u64>) -> Result<FutexKey> {
+    ptr.check()?;
+    let addr = LAddr::from(ptr.as_ptr() as usize);
+    let space = crate::mem::space::with_current(Arc::clone);
+    space.translate(addr)
+}
+
+/// # Safety
+///
+/// The function must be called when `PREEMPT` is disabled or locked.
+pub unsafe fn futex(key: FutexKey) -> FutexRef<'static> {
+    FUTEXES.get_or_insert(key, Futex::new(key)).downgrade()
+}
+
+/// # Safety
+///
+/// The function must be called when `PREEMPT` is disabled or locked.
+pub unsafe fn try_drop_futex(key: FutexKey) {
+    let _ = FUTEXES.remove_if(&key, |futex| futex.is_empty());
+}
+
 pub struct Futex {
     key: FutexKey,
     wo: WaitObject,
@@ -37,12 +75,18 @@ impl Futex {
         self.wo.wait_queue.is_empty()
     }
 
-    fn wait<T>(this: FutexRef<'_>, guard: T, val: u64, timeout: Duration) -> Result {
-        let ptr = this.key.as_ptr();
-        if unsafe { intrinsics::atomic_load_seqcst(ptr) } == val {
+    fn wait<T>(
+        this: FutexRef<'_>,
+        guard: T,
+        ptr: UserPtr<In, u64>,
+        val: u64,
+        timeout: Duration,
+    ) -> Result {
+        // An aligned 8-byte copy is a single access, as atomic as the load.
+        if unsafe { ptr.read() }? == val {
             unsafe {
                 let wo = &*(&this.wo as *const WaitObject);
-                wo.wait((this, guard), timeout, "Futex::wait")
+                wo.wait((this, guard), timeout, true, "Futex::wait")
             }
         } else {
             Err(EINVAL)
@@ -72,35 +116,38 @@ impl Futex {
 mod syscall {
     use sv_call::*;
 
-    use super::Futex;
+    use super::{futex, futex_key, try_drop_futex, Futex};
     use crate::{
         cpu::time,
-        sched::{PREEMPT, SCHED},
-        syscall::{In, InOut, UserPtr},
+        sched::PREEMPT,
+        syscall::{restart_until, In, InOut, UserPtr},
     };
 
     #[syscall]
     fn futex_wait(ptr: UserPtr<In, u64>, expected: u64, timeout_us: u64) -> Result {
-        let _ = unsafe { ptr.read() }?;
+        let key = futex_key(ptr)?;
 
-        let pree = PREEMPT.lock();
-        let futex = unsafe { (*SCHED.current()).as_ref().unwrap().space.futex(ptr) };
-        let ret = Futex::wait(futex, pree, expected, time::from_us(timeout_us));
+        let deadline = time::deadline_after(time::from_us(timeout_us));
+        restart_until(deadline, |deadline| {
+            let pree = PREEMPT.lock();
+            let futex = unsafe { futex(key) };
+            let ret = Futex::wait(futex, pree, ptr, expected, time::remaining(deadline));
 
-        SCHED.with_current(|cur| {
-            unsafe { cur.space.try_drop_futex(ptr) };
-            Ok(())
-        })?;
+            PREEMPT.scope(|| unsafe { try_drop_futex(key) });
 
-        ret
+            ret
+        })
     }
 
     #[syscall]
     fn futex_wake(ptr: UserPtr<In, u64>, num: usize) -> Result<usize> {
-        let _ = unsafe { ptr.read() }?;
-        SCHED.with_current(|cur| {
-            let futex = unsafe { cur.space.futex(ptr) };
-            futex.wake(num)
+        let key = futex_key(ptr)?;
+        PREEMPT.scope(|| {
+            let futex = unsafe { futex(key) };
+            let ret = futex.wake(num);
+            drop(futex);
+            unsafe { try_drop_futex(key) };
+            ret
         })
     }
 
@@ -111,17 +158,20 @@ mod syscall {
         other: UserPtr<In, u64>,
         requeue_num: UserPtr<InOut, usize>,
     ) -> Result {
-        let _ = unsafe { ptr.read() }?;
-        let _ = unsafe { other.read() }?;
+        let (key, other) = (futex_key(ptr)?, futex_key(other)?);
         let (wake, requeue) = unsafe { (wake_num.read()?, requeue_num.read()?) };
 
-        let pree = PREEMPT.lock();
-        let futex = unsafe { (*SCHED.current()).as_ref().unwrap().space.futex(ptr) };
-        let other = unsafe { (*SCHED.current()).as_ref().unwrap().space.futex(other) };
-
-        let wake = futex.wake(wake)?;
-        let requeue = futex.requeue(&other, requeue)?;
-        drop(pree);
+        let (wake, requeue) = PREEMPT.scope(|| {
+            let (futex, other_futex) = unsafe { (futex(key), futex(other)) };
+            let wake = futex.wake(wake)?;
+            let requeue = futex.requeue(&other_futex, requeue)?;
+            drop((futex, other_futex));
+            unsafe {
+                try_drop_futex(key);
+                try_drop_futex(other);
+            }
+            Ok::<_, Error>((wake, requeue))
+        })?;
 
         wake_num.write(wake)?;
         requeue_num.write(requeue)?;
@@ -34,19 +34,67 @@
 //! ```
 //!
 //! And the `xtask` will generate the wrapper stub and the caller stub for you.
+//!
+//! The handler must match the prototype, or it fails to compile: the return
+//! value is wrapped in `Result`, and a pointer argument `*const T` or `*mut T`
+//! can be taken as `UserPtr<In, T>` or `UserPtr<Out, T>` (or
+//! `UserPtr<InOut, T>`) respectively.
+//!
+//! ## Interruption
+//!
+//! Interruptible waits fail with `EINTR` when a signal (killing or suspension)
+//! is raised on the waiting task. A syscall declared with `#[syscall(restart)]`
+//! handles the signal and restarts itself with the same arguments instead of
+//! returning `EINTR` to the user. A wait with a relative timeout restarts with
+//! [`restart_until`] instead, so that the timeout is converted to a deadline
+//! only once and not stretched by the restarts.
 
 mod user_ptr;
 
 use sv_call::{call::Syscall, *};
 
 pub use self::user_ptr::*;
+use crate::cpu::time::Instant;
 
 type SyscallWrapper = unsafe extern "C" fn(usize, usize, usize, usize, usize) -> usize;
 static SYSCALL_TABLE: &[SyscallWrapper] =
     &include!(concat!(env!("CARGO_MANIFEST_DIR"), "/target/wrapper.rs"));
 
+/// Prepare to restart a syscall interrupted with `EINTR` by handling the
+/// pending signal of the current task.
+///
+/// Called by the wrappers of the syscalls declared with `#[syscall(restart)]`.
+pub fn restart() {
+    crate::sched::SCHED.handle_signal();
+}
+
+/// Call `wait` until it's not interrupted with `EINTR`, handling the pending
+/// signal in between, with the same `deadline` for every call.
+pub fn restart_until<F, R>(deadline: Option<Instant>, mut wait: F) -> Result<R>
+where
+    F: FnMut(Option<Instant>) -> Result<R>,
+{
+    loop {
+        match wait(deadline) {
+            Err(EINTR) => restart(),
+            res => break res,
+        }
+    }
+}
+
+/// The number of the syscalls served by the kernel.
+pub fn count() -> usize {
+    SYSCALL_TABLE.len()
+}
+
 pub fn handle(syscall: Syscall) -> usize {
     let args = syscall.args;
+    if let Err(err) = crate::fault::check(crate::fault::Point::Syscall(syscall.num)) {
+        return err.into_retval();
+    }
+    if let Err(err) = crate::sched::task::filter::check(syscall.num) {
+        return err.into_retval();
+    }
     match SYSCALL_TABLE.get(syscall.num).copied() {
         Some(handler) => unsafe { handler(args[0], args[1], args[2], args[3], args[4]) },
         _ => ESPRT.into_retval(),
@@ -1,6 +1,29 @@
-use core::{fmt, hash::Hash, marker::PhantomData, mem, mem::MaybeUninit, num::NonZeroU64};
-
-use sv_call::{Result, SerdeReg};
+//! Access to the user memory.
+//!
+//! The kernel doesn't dereference user pointers directly. The accesses go
+//! through [`copy_from_user`] and [`copy_to_user`] (or the methods of
+//! [`UserPtr`] built on them), which check the range against the user address
+//! space, open the user pages for the kernel if SMAP is enabled, and recover
+//! from page faults with the `pf_resume` slot of the current task. The rest
+//! are wrapped in [`with_user_access`].
+//!
+//! The faults are counted by the call sites of the accesses, which are logged
+//! when their counts reach powers of 2 and dumped with [`dump_faults`].
+
+use core::{
+    arch::asm,
+    fmt,
+    hash::Hash,
+    marker::PhantomData,
+    mem,
+    mem::MaybeUninit,
+    num::NonZeroU64,
+    panic::Location,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicU64, Ordering::*},
+};
+
+use sv_call::{Abi, Result, SerdeReg};
 
 pub use self::types::*;
 use crate::{mem::space::PageFaultErrCode, sched::SCHED};
@@ -50,11 +73,8 @@ impl<T: PtrType, D> UserPtr<T, D> {
     /// Returns error if the pointer range is unaligned or out of user address
     /// space.
     pub fn check_slice(&self, len: usize) -> Result<()> {
-        check_ptr(
-            self.data.cast(),
-            mem::size_of::<D>() * len,
-            mem::align_of::<D>(),
-        )
+        let size = mem::size_of::<D>().checked_mul(len).ok_or(sv_call::EPERM)?;
+        check_ptr(self.data.cast(), size, mem::align_of::<D>())
     }
 
     #[inline]
@@ -76,19 +96,16 @@ impl<T: InPtrType, D> UserPtr<T, D> {
     ///
     /// Behavior is undefined if the pointer don't point to a properly
     /// initialized value of type `T`.
+    #[track_caller]
     pub unsafe fn read(&self) -> Result<D> {
         self.check()?;
 
-        let pf_resume = SCHED.with_current(|cur| Ok(cur.kstack_mut().pf_resume_mut()))?;
-
         let mut data = MaybeUninit::<D>::uninit();
-        checked_copy(
+        copy_from_user(
             data.as_mut_ptr().cast(),
             self.data.cast(),
-            pf_resume,
             mem::size_of::<D>(),
-        )
-        .into_result()?;
+        )?;
 
         Ok(data.assume_init())
     }
@@ -102,18 +119,11 @@ impl<T: InPtrType, D> UserPtr<T, D> {
     ///
     /// Behavior is undefined if the pointer don't point to a properly
     /// initialized array of type `T`.
+    #[track_caller]
     pub unsafe fn read_slice(&self, out: *mut D, count: usize) -> Result<()> {
         self.check_slice(count)?;
 
-        let pf_resume = SCHED.with_current(|cur| Ok(cur.kstack_mut().pf_resume_mut()))?;
-
-        checked_copy(
-            out.cast(),
-            self.data.cast(),
-            pf_resume,
-            count * mem::size_of::<D>(),
-        )
-        .into_result()
+        copy_from_user(out.cast(), self.data.cast(), count * mem::size_of::<D>())
     }
 
     #[inline]
@@ -130,19 +140,16 @@ impl<T: OutPtrType, D> UserPtr<T, D> {
     ///
     /// Returns error if the pointer is invalid for writes or if the pointer is
     /// unaligned.
+    #[track_caller]
     pub fn write(&self, value: D) -> Result<()> {
         self.check()?;
 
         unsafe {
-            let pf_resume = SCHED.with_current(|cur| Ok(cur.kstack_mut().pf_resume_mut()))?;
-
-            checked_copy(
+            copy_to_user(
                 self.data.cast(),
                 ((&value) as *const D).cast(),
-                pf_resume,
                 mem::size_of::<D>(),
             )
-            .into_result()
         }
     }
 
@@ -150,19 +157,16 @@ impl<T: OutPtrType, D> UserPtr<T, D> {
     ///
     /// Returns error if the pointer is invalid for writes or if the pointer is
     /// unaligned.
+    #[track_caller]
     pub fn write_slice(&self, value: &[D]) -> Result<()> {
         self.check_slice(value.len())?;
 
         unsafe {
-            let pf_resume = SCHED.with_current(|cur| Ok(cur.kstack_mut().pf_resume_mut()))?;
-
-            checked_copy(
+            copy_to_user(
                 self.data.cast(),
                 value.as_ptr().cast(),
-                pf_resume,
-                value.len() * mem::size_of::<D>(),
+                mem::size_of_val(value),
             )
-            .into_result()
         }
     }
 
@@ -196,6 +200,78 @@ impl<T: PtrType, D> SerdeReg for UserPtr<T, D> {
     }
 }
 
+impl<D> Abi for UserPtr<In, D> {
+    type Abi = *const D;
+}
+
+impl<D> Abi for UserPtr<Out, D> {
+    type Abi = *mut D;
+}
+
+impl<D> Abi for UserPtr<InOut, D> {
+    type Abi = *mut D;
+}
+
+/// Copy `len` bytes from the user address `src` to the kernel buffer `dst`.
+///
+/// # Errors
+///
+/// Returns error if the source range is out of user address space or faults
+/// when read.
+///
+/// # Safety
+///
+/// `dst` must be valid for writes of `len` bytes.
+#[track_caller]
+pub unsafe fn copy_from_user(dst: *mut u8, src: *const u8, len: usize) -> Result<()> {
+    check_ptr(src as *mut u8, len, 1)?;
+    guarded_copy(dst, src, len)
+}
+
+/// Copy `len` bytes from the kernel buffer `src` to the user address `dst`.
+///
+/// # Errors
+///
+/// Returns error if the destination range is out of user address space or
+/// faults when written.
+///
+/// # Safety
+///
+/// `src` must be valid for reads of `len` bytes.
+#[track_caller]
+pub unsafe fn copy_to_user(dst: *mut u8, src: *const u8, len: usize) -> Result<()> {
+    check_ptr(dst, len, 1)?;
+    guarded_copy(dst, src, len)
+}
+
+#[track_caller]
+unsafe fn guarded_copy(dst: *mut u8, src: *const u8, len: usize) -> Result<()> {
+    if len == 0 {
+        return Ok(());
+    }
+    let pf_resume = SCHED.with_current(|cur| Ok(cur.kstack_mut().pf_resume_mut()))?;
+    let ret = with_user_access(|| checked_copy(dst, src, pf_resume, len));
+    ret.into_result(Location::caller())
+}
+
+/// Run `func` with the user pages accessible to the kernel.
+///
+/// It's only for the accesses that [`copy_from_user`] and [`copy_to_user`]
+/// can't express, such as atomic operations and copying within the space of
+/// another task, which must be checked against the user address space and
+/// must not fault.
+pub fn with_user_access<R>(func: impl FnOnce() -> R) -> R {
+    let smap = archop::reg::has_smap();
+    if smap {
+        unsafe { asm!("stac", options(nomem, nostack)) };
+    }
+    let ret = func();
+    if smap {
+        unsafe { asm!("clac", options(nomem, nostack)) };
+    }
+    ret
+}
+
 fn check_ptr(ptr: *mut u8, size: usize, align: usize) -> Result<()> {
     let is_in_range =
         minfo::USER_BASE <= ptr as usize && (ptr as usize).saturating_add(size) <= minfo::USER_END;
@@ -239,13 +315,16 @@ struct CheckedCopyRet {
 }
 
 impl CheckedCopyRet {
-    fn into_result(self) -> Result<()> {
+    fn into_result(self, site: &'static Location<'static>) -> Result<()> {
         if self.errc != PageFaultErrCode::empty() || self.addr_p1 != 0 {
-            log::warn!(
-                "Page fault at {:#x} during user pointer access, error code: {:?}",
-                self.addr_p1 - 1,
-                self.errc
-            );
+            let count = record_fault(site);
+            if count.is_power_of_two() {
+                log::warn!(
+                    "User access fault #{count} at {:#x} from {site}, error code: {:?}",
+                    self.addr_p1 - 1,
+                    self.errc
+                );
+            }
             Err(sv_call::EPERM)
         } else {
             Ok(())
@@ -253,6 +332,66 @@ impl CheckedCopyRet {
     }
 }
 
+/// The maximum number of the call sites whose faults are counted separately.
+const MAX_FAULT_SITES: usize = 64;
+
+struct FaultSite {
+    location: AtomicPtr<Location<'static>>,
+    count: AtomicU64,
+}
+
+impl FaultSite {
+    const fn new() -> Self {
+        FaultSite {
+            location: AtomicPtr::new(ptr::null_mut()),
+            count: AtomicU64::new(0),
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_FAULT: FaultSite = FaultSite::new();
+/// The fault counts of the call sites, where the last slot is shared by the
+/// sites beyond the capacity.
+static FAULT_SITES: [FaultSite; MAX_FAULT_SITES] = [NO_FAULT; MAX_FAULT_SITES];
+
+/// Count a fault at `site`, returning the count of its slot.
+fn record_fault(site: &'static Location<'static>) -> u64 {
+    let site = (site as *const Location).cast_mut();
+    let (last, sites) = FAULT_SITES.split_last().unwrap();
+    let slot = sites
+        .iter()
+        .find(|slot| {
+            match slot
+                .location
+                .compare_exchange(ptr::null_mut(), site, AcqRel, Acquire)
+            {
+                Ok(_) => true,
+                Err(location) => location == site,
+            }
+        })
+        .unwrap_or(last);
+    slot.count.fetch_add(1, Relaxed) + 1
+}
+
+/// Log the fault counts of the call sites of the user accesses.
+pub fn dump_faults() {
+    let (last, sites) = FAULT_SITES.split_last().unwrap();
+    for slot in sites {
+        let location = slot.location.load(Acquire);
+        if location.is_null() {
+            break;
+        }
+        // SAFETY: The locations are static.
+        let location = unsafe { &*location };
+        log::info!("{location}: {} faults", slot.count.load(Relaxed));
+    }
+    let rest = last.count.load(Relaxed);
+    if rest > 0 {
+        log::info!("Other sites: {rest} faults");
+    }
+}
+
 extern "C" {
     fn checked_copy(
         dst: *mut u8,
@@ -261,3 +400,61 @@ extern "C" {
         count: usize,
     ) -> CheckedCopyRet;
 }
+
+#[cfg(ktest)]
+mod ktests {
+    use sv_call::{EALIGN, EPERM};
+
+    use super::*;
+    use crate::ktest::case;
+
+    /// The pointers that must be rejected before any access, along with the
+    /// lengths accessed from them.
+    fn hostile() -> [(usize, usize); 7] {
+        let local = 0u64;
+        [
+            (0, 1),
+            (minfo::USER_BASE - 1, 2),
+            (minfo::USER_END - 8, 16),
+            (0x8000_0000_0000, 8),
+            (usize::MAX - 7, 16),
+            (minfo::ID_OFFSET, 8),
+            (&local as *const u64 as usize, 8),
+        ]
+    }
+
+    case! {
+        fn user_copy_rejects_hostile_pointers() {
+            let mut buf = [0u8; 16];
+            for (addr, len) in hostile() {
+                let ret = unsafe { copy_from_user(buf.as_mut_ptr(), addr as *const u8, len) };
+                assert_eq!(ret, Err(EPERM), "read from {addr:#x}");
+                let ret = unsafe { copy_to_user(addr as *mut u8, buf.as_ptr(), len) };
+                assert_eq!(ret, Err(EPERM), "write to {addr:#x}");
+            }
+
+            for _ in 0..1000 {
+                let addr = archop::rand::get() as usize | minfo::USER_END;
+                let ret = unsafe { copy_from_user(buf.as_mut_ptr(), addr as *const u8, 1) };
+                assert_eq!(ret, Err(EPERM), "read from {addr:#x}");
+            }
+        }
+
+        fn user_ptr_checks_overflow_and_alignment() {
+            let ptr = UserPtr::<In, u64>::new(minfo::USER_BASE as *mut u64);
+            assert_eq!(ptr.check_slice(usize::MAX / 4), Err(EPERM));
+            assert_eq!(ptr.check_slice(4), Ok(()));
+
+            let ptr = UserPtr::<Out, u64>::new((minfo::USER_BASE + 1) as *mut u64);
+            assert_eq!(ptr.check(), Err(EALIGN));
+            assert_eq!(ptr.write(0), Err(EALIGN));
+        }
+
+        fn empty_user_copy_touches_nothing() {
+            let mut buf = [0u8; 1];
+            let addr = minfo::ID_OFFSET as *const u8;
+            let ret = unsafe { copy_from_user(buf.as_mut_ptr(), addr, 0) };
+            assert_eq!(ret, Ok(()));
+        }
+    }
+}
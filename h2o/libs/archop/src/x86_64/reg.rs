@@ -1,3 +1,5 @@
+use crate::Azy;
+
 macro_rules! rw_simple {
       ($name:ident {$($cons:ident = $bit:expr),*; $($cons0:ident: $tty:ty = $bit0:expr),*}) => {
             #[doc = concat!("The operations of ", stringify!($name), ".")]
@@ -167,11 +169,60 @@ pub mod rflags {
     pub const USER_ACCESS: u64 = CF | PF | AF | ZF | SF | TF | DF | OF | NT | AC | ID;
 }
 
+static FSGSBASE: Azy<bool> = Azy::new(|| {
+    let cpuid = raw_cpuid::CpuId::new();
+    let efi = cpuid.get_extended_feature_info();
+    efi.map_or(false, |efi| efi.has_fsgsbase())
+});
+
+/// Whether the processor supports the `{rd,wr}{fs,gs}base` instructions.
+///
+/// They can only be used once `CR4.FSGSBASE` is set, which the boot loader
+/// does when this returns `true`.
+#[inline]
+pub fn has_fsgsbase() -> bool {
+    *FSGSBASE
+}
+
+static SMAP: Azy<bool> = Azy::new(|| {
+    let cpuid = raw_cpuid::CpuId::new();
+    let efi = cpuid.get_extended_feature_info();
+    efi.map_or(false, |efi| efi.has_smap())
+});
+
+/// Whether the processor supports supervisor mode access prevention.
+///
+/// The `stac` and `clac` instructions can only be used when this returns
+/// `true`.
+#[inline]
+pub fn has_smap() -> bool {
+    *SMAP
+}
+
+static PCID: Azy<bool> = Azy::new(|| {
+    let cpuid = raw_cpuid::CpuId::new();
+    let pcid = cpuid.get_feature_info().map_or(false, |fi| fi.has_pcid());
+    let efi = cpuid.get_extended_feature_info();
+    pcid && efi.map_or(false, |efi| efi.has_invpcid())
+});
+
+/// Whether the processor supports process-context identifiers along with the
+/// `invpcid` instruction.
+///
+/// `CR4.PCIDE` can only be set when this returns `true`.
+#[inline]
+pub fn has_pcid() -> bool {
+    *PCID
+}
+
 /// # Safety
 ///
 /// The caller is responsible for the validity of the architecture context.
 #[inline]
 pub unsafe fn read_fs() -> u64 {
+    if !has_fsgsbase() {
+        return crate::msr::read(crate::msr::FS_BASE);
+    }
     let mut ret;
     core::arch::asm!("rdfsbase {}", out(reg) ret, options(nostack));
     ret
@@ -182,6 +233,9 @@ pub unsafe fn read_fs() -> u64 {
 /// The caller is responsible for the validity of the architecture context.
 #[inline]
 pub unsafe fn write_fs(value: u64) {
+    if !has_fsgsbase() {
+        return crate::msr::write(crate::msr::FS_BASE, value);
+    }
     core::arch::asm!("wrfsbase {}", in(reg) value, options(nostack));
 }
 
@@ -190,6 +244,9 @@ pub unsafe fn write_fs(value: u64) {
 /// The caller is responsible for the validity of the architecture context.
 #[inline]
 pub unsafe fn read_gs() -> u64 {
+    if !has_fsgsbase() {
+        return crate::msr::read(crate::msr::GS_BASE);
+    }
     let mut ret;
     core::arch::asm!("rdgsbase {}", out(reg) ret, options(nostack));
     ret
@@ -200,5 +257,8 @@ pub unsafe fn read_gs() -> u64 {
 /// The caller is responsible for the validity of the architecture context.
 #[inline]
 pub unsafe fn write_gs(value: u64) {
+    if !has_fsgsbase() {
+        return crate::msr::write(crate::msr::GS_BASE, value);
+    }
     core::arch::asm!("wrgsbase {}", in(reg) value, options(nostack));
 }
@@ -0,0 +1,219 @@
+//! A minimal one-shot BLAKE3 hasher.
+
+pub const OUT_LEN: usize = 32;
+
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+/// Enough for inputs of up to 2^64 bytes.
+const MAX_DEPTH: usize = 54;
+
+const CHUNK_START: u32 = 1 << 0;
+const CHUNK_END: u32 = 1 << 1;
+const PARENT: u32 = 1 << 2;
+const ROOT: u32 = 1 << 3;
+
+const IV: [u32; 8] = [
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+];
+
+const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+#[inline(always)]
+fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+    state[d] = (state[d] ^ state[a]).rotate_right(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(12);
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+    state[d] = (state[d] ^ state[a]).rotate_right(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+fn round(state: &mut [u32; 16], m: &[u32; 16]) {
+    // Columns.
+    g(state, 0, 4, 8, 12, m[0], m[1]);
+    g(state, 1, 5, 9, 13, m[2], m[3]);
+    g(state, 2, 6, 10, 14, m[4], m[5]);
+    g(state, 3, 7, 11, 15, m[6], m[7]);
+    // Diagonals.
+    g(state, 0, 5, 10, 15, m[8], m[9]);
+    g(state, 1, 6, 11, 12, m[10], m[11]);
+    g(state, 2, 7, 8, 13, m[12], m[13]);
+    g(state, 3, 4, 9, 14, m[14], m[15]);
+}
+
+fn compress(
+    cv: &[u32; 8],
+    block: &[u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+) -> [u32; 8] {
+    let mut state = [
+        cv[0],
+        cv[1],
+        cv[2],
+        cv[3],
+        cv[4],
+        cv[5],
+        cv[6],
+        cv[7],
+        IV[0],
+        IV[1],
+        IV[2],
+        IV[3],
+        counter as u32,
+        (counter >> 32) as u32,
+        block_len,
+        flags,
+    ];
+    let mut block = *block;
+    for i in 0..7 {
+        round(&mut state, &block);
+        if i < 6 {
+            block = MSG_PERMUTATION.map(|j| block[j]);
+        }
+    }
+    let mut ret = [0; 8];
+    for (i, ret) in ret.iter_mut().enumerate() {
+        *ret = state[i] ^ state[i + 8];
+    }
+    ret
+}
+
+fn block_words(block: &[u8]) -> [u32; 16] {
+    let mut buf = [0; BLOCK_LEN];
+    buf[..block.len()].copy_from_slice(block);
+    let mut ret = [0; 16];
+    for (word, bytes) in ret.iter_mut().zip(buf.as_chunks().0) {
+        *word = u32::from_le_bytes(*bytes);
+    }
+    ret
+}
+
+/// The last compression of a node, deferred until we know whether it's the
+/// root.
+struct Output {
+    cv: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+}
+
+impl Output {
+    fn chunk(chunk: &[u8], counter: u64) -> Self {
+        let num_blocks = chunk.len().div_ceil(BLOCK_LEN).max(1);
+        let mut cv = IV;
+        let mut blocks = chunk.chunks(BLOCK_LEN);
+        for index in 0.. {
+            let block = blocks.next().unwrap_or_default();
+            let mut flags = if index == 0 { CHUNK_START } else { 0 };
+            if index + 1 == num_blocks {
+                flags |= CHUNK_END;
+                return Output {
+                    cv,
+                    block: block_words(block),
+                    counter,
+                    block_len: block.len() as u32,
+                    flags,
+                };
+            }
+            cv = compress(&cv, &block_words(block), counter, BLOCK_LEN as u32, flags);
+        }
+        unreachable!()
+    }
+
+    fn parent(left: &[u32; 8], right: &[u32; 8]) -> Self {
+        let mut block = [0; 16];
+        block[..8].copy_from_slice(left);
+        block[8..].copy_from_slice(right);
+        Output {
+            cv: IV,
+            block,
+            counter: 0,
+            block_len: BLOCK_LEN as u32,
+            flags: PARENT,
+        }
+    }
+
+    fn chaining_value(&self) -> [u32; 8] {
+        compress(
+            &self.cv,
+            &self.block,
+            self.counter,
+            self.block_len,
+            self.flags,
+        )
+    }
+
+    fn root_hash(&self) -> [u8; OUT_LEN] {
+        let words = compress(&self.cv, &self.block, 0, self.block_len, self.flags | ROOT);
+        let mut ret = [0; OUT_LEN];
+        for (bytes, word) in ret.as_chunks_mut().0.iter_mut().zip(words) {
+            *bytes = word.to_le_bytes();
+        }
+        ret
+    }
+}
+
+/// Calculate the BLAKE3 hash of the data.
+pub fn hash(data: &[u8]) -> [u8; OUT_LEN] {
+    let num_chunks = data.len().div_ceil(CHUNK_LEN).max(1);
+    let mut stack = [[0; 8]; MAX_DEPTH];
+    let mut len = 0;
+
+    let mut chunks = data.chunks(CHUNK_LEN);
+    for counter in 0..(num_chunks - 1) {
+        let mut cv = Output::chunk(chunks.next().unwrap(), counter as u64).chaining_value();
+        // Merge the complete subtrees, whose number is the count of trailing
+        // zeros of the total number of chunks.
+        let mut total = counter + 1;
+        while total & 1 == 0 {
+            len -= 1;
+            cv = Output::parent(&stack[len], &cv).chaining_value();
+            total >>= 1;
+        }
+        stack[len] = cv;
+        len += 1;
+    }
+
+    let last = chunks.next().unwrap_or_default();
+    let mut output = Output::chunk(last, (num_chunks - 1) as u64);
+    while len > 0 {
+        len -= 1;
+        output = Output::parent(&stack[len], &output.chaining_value());
+    }
+    output.root_hash()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::hex;
+
+    #[test]
+    fn known_hashes() {
+        assert_eq!(
+            hex(hash(b"")),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+        assert_eq!(
+            hex(hash(b"abc")),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+
+        let data = (0..3073)
+            .map(|i| (i % 251) as u8)
+            .collect::<std::vec::Vec<_>>();
+        assert_eq!(
+            hex(hash(&data[..1025])),
+            "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444"
+        );
+        assert_eq!(
+            hex(hash(&data)),
+            "7124b49501012f81cc7f11ca069ec9226cecb8a2c850cfe644e327d22d3e1cd3"
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! The verification of Ed25519 signatures (RFC 8032).
+//!
+//! The field elements are 16 limbs of 16 bits in `i64`s with the carries
+//! propagated lazily, after TweetNaCl.
+
+use crate::sha512::Sha512;
+
+pub const PUBLIC_KEY_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+
+type Fe = [i64; 16];
+
+/// A point in the extended coordinates (X, Y, Z, T).
+type Point = [Fe; 4];
+
+const FE0: Fe = [0; 16];
+const FE1: Fe = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+/// -121665 / 121666.
+const D: Fe = [
+    0x78a3, 0x1359, 0x4dca, 0x75eb, 0xd8ab, 0x4141, 0x0a4d, 0x0070, 0xe898, 0x7779, 0x4079, 0x8cc7,
+    0xfe73, 0x2b6f, 0x6cee, 0x5203,
+];
+const D2: Fe = [
+    0xf159, 0x26b2, 0x9b94, 0xebd6, 0xb156, 0x8283, 0x149a, 0x00e0, 0xd130, 0xeef3, 0x80f2, 0x198e,
+    0xfce7, 0x56df, 0xd9dc, 0x2406,
+];
+/// The coordinates of the base point.
+const X: Fe = [
+    0xd51a, 0x8f25, 0x2d60, 0xc956, 0xa7b2, 0x9525, 0xc760, 0x692c, 0xdc5c, 0xfdd6, 0xe231, 0xc0a4,
+    0x53fe, 0xcd6e, 0x36d3, 0x2169,
+];
+const Y: Fe = [
+    0x6658, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+    0x6666, 0x6666, 0x6666, 0x6666,
+];
+/// The square root of -1.
+const I: Fe = [
+    0xa0b0, 0x4a0e, 0x1b27, 0xc4ee, 0xe478, 0xad2f, 0x1806, 0x2f43, 0xd7a7, 0x3dfb, 0x0099, 0x2b4d,
+    0xdf0b, 0x4fc1, 0x2480, 0x2b83,
+];
+
+/// The order of the base point, in little-endian bytes.
+const L: [i64; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+fn carry(o: &mut Fe) {
+    for i in 0..16 {
+        o[i] += 1 << 16;
+        let c = o[i] >> 16;
+        if i < 15 {
+            o[i + 1] += c - 1;
+        } else {
+            o[0] += 38 * (c - 1);
+        }
+        o[i] -= c << 16;
+    }
+}
+
+/// Swap `p` and `q` if `b` is 1.
+fn select(p: &mut Fe, q: &mut Fe, b: i64) {
+    let c = !(b - 1);
+    for (p, q) in p.iter_mut().zip(q.iter_mut()) {
+        let t = c & (*p ^ *q);
+        *p ^= t;
+        *q ^= t;
+    }
+}
+
+fn pack_fe(n: &Fe) -> [u8; 32] {
+    let mut t = *n;
+    carry(&mut t);
+    carry(&mut t);
+    carry(&mut t);
+    for _ in 0..2 {
+        let mut m = FE0;
+        m[0] = t[0] - 0xffed;
+        for i in 1..15 {
+            m[i] = t[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xffff;
+        }
+        m[15] = t[15] - 0x7fff - ((m[14] >> 16) & 1);
+        let b = (m[15] >> 16) & 1;
+        m[14] &= 0xffff;
+        select(&mut t, &mut m, 1 - b);
+    }
+    let mut ret = [0; 32];
+    for (bytes, limb) in ret.as_chunks_mut().0.iter_mut().zip(t) {
+        *bytes = (limb as u16).to_le_bytes();
+    }
+    ret
+}
+
+fn unpack_fe(n: &[u8; 32]) -> Fe {
+    let mut ret = FE0;
+    for (limb, bytes) in ret.iter_mut().zip(n.as_chunks().0) {
+        *limb = i64::from(u16::from_le_bytes(*bytes));
+    }
+    ret[15] &= 0x7fff;
+    ret
+}
+
+fn neq(a: &Fe, b: &Fe) -> bool {
+    pack_fe(a) != pack_fe(b)
+}
+
+fn parity(a: &Fe) -> u8 {
+    pack_fe(a)[0] & 1
+}
+
+fn add_fe(a: &Fe, b: &Fe) -> Fe {
+    core::array::from_fn(|i| a[i] + b[i])
+}
+
+fn sub_fe(a: &Fe, b: &Fe) -> Fe {
+    core::array::from_fn(|i| a[i] - b[i])
+}
+
+fn mul_fe(a: &Fe, b: &Fe) -> Fe {
+    let mut t = [0; 31];
+    for i in 0..16 {
+        for j in 0..16 {
+            t[i + j] += a[i] * b[j];
+        }
+    }
+    for i in 0..15 {
+        t[i] += 38 * t[i + 16];
+    }
+    let mut ret = FE0;
+    ret.copy_from_slice(&t[..16]);
+    carry(&mut ret);
+    carry(&mut ret);
+    ret
+}
+
+fn square_fe(a: &Fe) -> Fe {
+    mul_fe(a, a)
+}
+
+/// Raise `i` to the power of 2^252 - 3, for the square roots.
+fn pow2523(i: &Fe) -> Fe {
+    let mut c = *i;
+    for a in (0..=250).rev() {
+        c = square_fe(&c);
+        if a != 1 {
+            c = mul_fe(&c, i);
+        }
+    }
+    c
+}
+
+fn invert_fe(i: &Fe) -> Fe {
+    let mut c = *i;
+    for a in (0..=253).rev() {
+        c = square_fe(&c);
+        if a != 2 && a != 4 {
+            c = mul_fe(&c, i);
+        }
+    }
+    c
+}
+
+fn add(p: &mut Point, q: &Point) {
+    let a = mul_fe(&sub_fe(&p[1], &p[0]), &sub_fe(&q[1], &q[0]));
+    let b = mul_fe(&add_fe(&p[0], &p[1]), &add_fe(&q[0], &q[1]));
+    let c = mul_fe(&mul_fe(&p[3], &q[3]), &D2);
+    let d = mul_fe(&p[2], &q[2]);
+    let d = add_fe(&d, &d);
+    let e = sub_fe(&b, &a);
+    let f = sub_fe(&d, &c);
+    let g = add_fe(&d, &c);
+    let h = add_fe(&b, &a);
+
+    p[0] = mul_fe(&e, &f);
+    p[1] = mul_fe(&h, &g);
+    p[2] = mul_fe(&g, &f);
+    p[3] = mul_fe(&e, &h);
+}
+
+fn swap(p: &mut Point, q: &mut Point, b: i64) {
+    for (p, q) in p.iter_mut().zip(q.iter_mut()) {
+        select(p, q, b);
+    }
+}
+
+fn pack(p: &Point) -> [u8; 32] {
+    let zi = invert_fe(&p[2]);
+    let tx = mul_fe(&p[0], &zi);
+    let ty = mul_fe(&p[1], &zi);
+    let mut ret = pack_fe(&ty);
+    ret[31] ^= parity(&tx) << 7;
+    ret
+}
+
+/// Multiply `q` by the little-endian scalar `s`.
+fn scalar_mul(mut q: Point, s: &[u8; 32]) -> Point {
+    let mut p = [FE0, FE1, FE1, FE0];
+    for i in (0..256).rev() {
+        let b = i64::from((s[i / 8] >> (i & 7)) & 1);
+        swap(&mut p, &mut q, b);
+        add(&mut q, &p);
+        let p2 = p;
+        add(&mut p, &p2);
+        swap(&mut p, &mut q, b);
+    }
+    p
+}
+
+fn scalar_mul_base(s: &[u8; 32]) -> Point {
+    scalar_mul([X, Y, FE1, mul_fe(&X, &Y)], s)
+}
+
+/// Decode the point `p`, and negate it.
+fn unpack_neg(p: &[u8; 32]) -> Option<Point> {
+    let y = unpack_fe(p);
+    let num = square_fe(&y);
+    let den = mul_fe(&num, &D);
+    let num = sub_fe(&num, &FE1);
+    let den = add_fe(&FE1, &den);
+
+    // x = sqrt(num / den) = num * den^3 * (num * den^7)^((p - 5) / 8)
+    let den2 = square_fe(&den);
+    let den4 = square_fe(&den2);
+    let den6 = mul_fe(&den4, &den2);
+    let t = mul_fe(&mul_fe(&den6, &num), &den);
+    let t = mul_fe(&mul_fe(&pow2523(&t), &num), &den);
+    let mut x = mul_fe(&mul_fe(&t, &den), &den);
+
+    let check = |x: &Fe| !neq(&mul_fe(&square_fe(x), &den), &num);
+    if !check(&x) {
+        x = mul_fe(&x, &I);
+    }
+    if !check(&x) {
+        return None;
+    }
+    if parity(&x) == p[31] >> 7 {
+        x = sub_fe(&FE0, &x);
+    }
+    Some([x, y, FE1, mul_fe(&x, &y)])
+}
+
+/// Reduce the little-endian number `x` modulo [`L`].
+fn reduce(x: &[u8; 64]) -> [u8; 32] {
+    let mut x = x.map(i64::from);
+    for i in (32..64).rev() {
+        let mut carry = 0;
+        for j in (i - 32)..(i - 12) {
+            x[j] += carry - 16 * x[i] * L[j - (i - 32)];
+            carry = (x[j] + 128) >> 8;
+            x[j] -= carry << 8;
+        }
+        x[i - 12] += carry;
+        x[i] = 0;
+    }
+    let mut carry = 0;
+    for j in 0..32 {
+        x[j] += carry - (x[31] >> 4) * L[j];
+        carry = x[j] >> 8;
+        x[j] &= 255;
+    }
+    for j in 0..32 {
+        x[j] -= carry * L[j];
+    }
+    let mut ret = [0; 32];
+    for i in 0..32 {
+        x[i + 1] += x[i] >> 8;
+        ret[i] = (x[i] & 255) as u8;
+    }
+    ret
+}
+
+/// Whether the little-endian scalar `s` is less than [`L`].
+fn is_canonical(s: &[u8; 32]) -> bool {
+    for (s, l) in s.iter().zip(L).rev() {
+        match i64::from(*s).cmp(&l) {
+            core::cmp::Ordering::Less => return true,
+            core::cmp::Ordering::Greater => return false,
+            core::cmp::Ordering::Equal => {}
+        }
+    }
+    false
+}
+
+/// Verify the Ed25519 `signature` of `message` by `public_key`.
+///
+/// The signatures with non-canonical scalars are rejected.
+pub fn verify(
+    public_key: &[u8; PUBLIC_KEY_LEN],
+    message: &[u8],
+    signature: &[u8; SIGNATURE_LEN],
+) -> bool {
+    let (r, s) = signature.split_at(32);
+    let (r, s): (&[u8; 32], &[u8; 32]) = (r.try_into().unwrap(), s.try_into().unwrap());
+    if !is_canonical(s) {
+        return false;
+    }
+    let Some(neg_a) = unpack_neg(public_key) else {
+        return false;
+    };
+
+    let mut hasher = Sha512::new();
+    hasher.update(r);
+    hasher.update(public_key);
+    hasher.update(message);
+    let h = reduce(&hasher.finalize());
+
+    // R = [s]B - [h]A
+    let mut p = scalar_mul(neg_a, &h);
+    add(&mut p, &scalar_mul_base(s));
+    pack(&p) == *r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex<const N: usize>(s: &str) -> [u8; N] {
+        core::array::from_fn(|i| u8::from_str_radix(&s[i * 2..][..2], 16).unwrap())
+    }
+
+    #[test]
+    fn rfc8032_vectors() {
+        let key = unhex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let sig = unhex(
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        );
+        assert!(verify(&key, b"", &sig));
+        assert!(!verify(&key, b"\x00", &sig));
+
+        let key = unhex("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+        let mut sig = unhex(
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+             085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        );
+        assert!(verify(&key, b"\x72", &sig));
+        sig[32] ^= 1;
+        assert!(!verify(&key, b"\x72", &sig));
+        // Adding `L` to the scalar keeps the equation.
+        sig[32] ^= 1;
+        sig[63] ^= 0x10;
+        assert!(!verify(&key, b"\x72", &sig));
+    }
+}
@@ -0,0 +1,23 @@
+//! The hash and signature primitives for the kernel and the `no_std`
+//! userspace.
+//!
+//! Only what the boot chain needs is here: BLAKE3 for the bootfs entries,
+//! SHA-256 for the boot images, and the verification of Ed25519 signatures.
+//! Nothing handles secrets, so nothing is made constant-time.
+
+#![no_std]
+
+pub mod blake3;
+pub mod ed25519;
+pub mod sha256;
+pub mod sha512;
+
+#[cfg(test)]
+extern crate std;
+
+#[cfg(test)]
+mod tests {
+    pub fn hex(data: impl AsRef<[u8]>) -> std::string::String {
+        data.as_ref().iter().map(|b| std::format!("{b:02x}")).collect()
+    }
+}
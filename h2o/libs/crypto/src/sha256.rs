@@ -0,0 +1,253 @@
+//! SHA-256, using the SHA extensions of x86_64 if the `sha-ni` feature is
+//! enabled and the CPU has them.
+
+pub const OUT_LEN: usize = 32;
+
+const BLOCK_LEN: usize = 64;
+
+const IV: [u32; 8] = [
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+];
+
+const K: [u32; 64] = [
+    0x428A2F98, 0x71374491, 0xB5C0FBCF, 0xE9B5DBA5, 0x3956C25B, 0x59F111F1, 0x923F82A4, 0xAB1C5ED5,
+    0xD807AA98, 0x12835B01, 0x243185BE, 0x550C7DC3, 0x72BE5D74, 0x80DEB1FE, 0x9BDC06A7, 0xC19BF174,
+    0xE49B69C1, 0xEFBE4786, 0x0FC19DC6, 0x240CA1CC, 0x2DE92C6F, 0x4A7484AA, 0x5CB0A9DC, 0x76F988DA,
+    0x983E5152, 0xA831C66D, 0xB00327C8, 0xBF597FC7, 0xC6E00BF3, 0xD5A79147, 0x06CA6351, 0x14292967,
+    0x27B70A85, 0x2E1B2138, 0x4D2C6DFC, 0x53380D13, 0x650A7354, 0x766A0ABB, 0x81C2C92E, 0x92722C85,
+    0xA2BFE8A1, 0xA81A664B, 0xC24B8B70, 0xC76C51A3, 0xD192E819, 0xD6990624, 0xF40E3585, 0x106AA070,
+    0x19A4C116, 0x1E376C08, 0x2748774C, 0x34B0BCB5, 0x391C0CB3, 0x4ED8AA4A, 0x5B9CCA4F, 0x682E6FF3,
+    0x748F82EE, 0x78A5636F, 0x84C87814, 0x8CC70208, 0x90BEFFFA, 0xA4506CEB, 0xBEF9A3F7, 0xC67178F2,
+];
+
+/// An incremental SHA-256 hasher.
+#[derive(Debug, Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buf: [u8; BLOCK_LEN],
+    buf_len: usize,
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    pub const fn new() -> Self {
+        Sha256 {
+            state: IV,
+            buf: [0; BLOCK_LEN],
+            buf_len: 0,
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len = self.len.wrapping_add(data.len() as u64);
+        if self.buf_len > 0 {
+            let len = (BLOCK_LEN - self.buf_len).min(data.len());
+            self.buf[self.buf_len..][..len].copy_from_slice(&data[..len]);
+            self.buf_len += len;
+            data = &data[len..];
+            if self.buf_len < BLOCK_LEN {
+                return;
+            }
+            compress(&mut self.state, &self.buf);
+            self.buf_len = 0;
+        }
+        let len = data.len() - data.len() % BLOCK_LEN;
+        compress(&mut self.state, &data[..len]);
+        self.buf[..data.len() - len].copy_from_slice(&data[len..]);
+        self.buf_len = data.len() - len;
+    }
+
+    pub fn finalize(mut self) -> [u8; OUT_LEN] {
+        let bits = self.len.wrapping_mul(8);
+        let pad = (BLOCK_LEN * 2 - 1 - 8 - self.buf_len) % BLOCK_LEN;
+        self.update(&[0x80]);
+        self.update(&[0; BLOCK_LEN][..pad]);
+        self.update(&bits.to_be_bytes());
+        debug_assert_eq!(self.buf_len, 0);
+
+        let mut ret = [0; OUT_LEN];
+        for (bytes, word) in ret.as_chunks_mut().0.iter_mut().zip(self.state) {
+            *bytes = word.to_be_bytes();
+        }
+        ret
+    }
+}
+
+/// Calculate the SHA-256 hash of the data.
+pub fn hash(data: &[u8]) -> [u8; OUT_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Process `blocks`, whose length is a multiple of [`BLOCK_LEN`].
+fn compress(state: &mut [u32; 8], blocks: &[u8]) {
+    #[cfg(all(target_arch = "x86_64", feature = "sha-ni"))]
+    if ni::available() {
+        // SAFETY: The CPU has the instructions.
+        return unsafe { ni::compress(state, blocks) };
+    }
+    compress_soft(state, blocks)
+}
+
+fn compress_soft(state: &mut [u32; 8], blocks: &[u8]) {
+    for block in blocks.as_chunks::<BLOCK_LEN>().0 {
+        let mut w = [0; 64];
+        for (word, bytes) in w.iter_mut().zip(block.as_chunks().0) {
+            *word = u32::from_be_bytes(*bytes);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = (w[i - 16].wrapping_add(s0))
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let mut s = *state;
+        for (k, w) in K.iter().zip(w) {
+            let [a, b, c, d, e, f, g, h] = s;
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = (h.wrapping_add(s1).wrapping_add(ch))
+                .wrapping_add(*k)
+                .wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            s = [t1.wrapping_add(t2), a, b, c, d.wrapping_add(t1), e, f, g];
+        }
+        for (state, s) in state.iter_mut().zip(s) {
+            *state = state.wrapping_add(s);
+        }
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "sha-ni"))]
+mod ni {
+    use core::{
+        arch::x86_64::*,
+        sync::atomic::{AtomicU8, Ordering::Relaxed},
+    };
+
+    use super::{BLOCK_LEN, K};
+
+    const UNKNOWN: u8 = 0;
+    const PRESENT: u8 = 1;
+    const ABSENT: u8 = 2;
+
+    static AVAILABLE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+    pub fn available() -> bool {
+        match AVAILABLE.load(Relaxed) {
+            UNKNOWN => {
+                let ret = detect();
+                AVAILABLE.store(if ret { PRESENT } else { ABSENT }, Relaxed);
+                ret
+            }
+            state => state == PRESENT,
+        }
+    }
+
+    fn detect() -> bool {
+        if __cpuid(0).eax < 7 {
+            return false;
+        }
+        let (leaf1, leaf7) = (__cpuid(1), __cpuid_count(7, 0));
+        let ssse3 = leaf1.ecx & (1 << 9) != 0;
+        let sse41 = leaf1.ecx & (1 << 19) != 0;
+        let sha = leaf7.ebx & (1 << 29) != 0;
+        ssse3 && sse41 && sha
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must have the SHA extensions, SSSE3 and SSE4.1.
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    pub unsafe fn compress(state: &mut [u32; 8], blocks: &[u8]) {
+        let mask = _mm_set_epi64x(0x0C0D_0E0F_0809_0A0B, 0x0405_0607_0001_0203);
+        let dcba = _mm_loadu_si128(state.as_ptr().cast());
+        let efgh = _mm_loadu_si128(state.as_ptr().add(4).cast());
+        let cdab = _mm_shuffle_epi32(dcba, 0xB1);
+        let efgh = _mm_shuffle_epi32(efgh, 0x1B);
+        let mut abef = _mm_alignr_epi8(cdab, efgh, 8);
+        let mut cdgh = _mm_blend_epi16(efgh, cdab, 0xF0);
+
+        for block in blocks.as_chunks::<BLOCK_LEN>().0 {
+            let (abef_saved, cdgh_saved) = (abef, cdgh);
+            let mut w = [_mm_setzero_si128(); 4];
+            for (i, w) in w.iter_mut().enumerate() {
+                let data = _mm_loadu_si128(block.as_ptr().add(i * 16).cast());
+                *w = _mm_shuffle_epi8(data, mask);
+            }
+            // `w` is a ring of the last 4 groups of the message schedule.
+            for i in 0..16 {
+                if i >= 4 {
+                    let t = _mm_sha256msg1_epu32(w[i % 4], w[(i + 1) % 4]);
+                    let t = _mm_add_epi32(t, _mm_alignr_epi8(w[(i + 3) % 4], w[(i + 2) % 4], 4));
+                    w[i % 4] = _mm_sha256msg2_epu32(t, w[(i + 3) % 4]);
+                }
+                let k = _mm_loadu_si128(K.as_ptr().add(i * 4).cast());
+                let t = _mm_add_epi32(w[i % 4], k);
+                cdgh = _mm_sha256rnds2_epu32(cdgh, abef, t);
+                abef = _mm_sha256rnds2_epu32(abef, cdgh, _mm_shuffle_epi32(t, 0x0E));
+            }
+            abef = _mm_add_epi32(abef, abef_saved);
+            cdgh = _mm_add_epi32(cdgh, cdgh_saved);
+        }
+
+        let feba = _mm_shuffle_epi32(abef, 0x1B);
+        let dchg = _mm_shuffle_epi32(cdgh, 0xB1);
+        let dcba = _mm_blend_epi16(feba, dchg, 0xF0);
+        let hgef = _mm_alignr_epi8(dchg, feba, 8);
+        _mm_storeu_si128(state.as_mut_ptr().cast(), dcba);
+        _mm_storeu_si128(state.as_mut_ptr().add(4).cast(), hgef);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::hex;
+
+    #[test]
+    fn known_hashes() {
+        assert_eq!(
+            hex(hash(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(hash(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(hash(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn incremental() {
+        let data = (0..1000).map(|i| (i % 251) as u8).collect::<std::vec::Vec<_>>();
+        let mut hasher = Sha256::new();
+        for chunk in data.chunks(37) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finalize(), hash(&data));
+
+        #[cfg(all(target_arch = "x86_64", feature = "sha-ni"))]
+        if ni::available() {
+            let (mut soft, mut accel) = (IV, IV);
+            compress_soft(&mut soft, &data[..960]);
+            unsafe { ni::compress(&mut accel, &data[..960]) };
+            assert_eq!(soft, accel);
+        }
+    }
+}
@@ -34,6 +34,22 @@ pub const ID_OFFSET: usize = KERNEL_SPACE_START;
 
 // Kernel args
 
+/// The maximal length of the boot command line, including the trailing NUL.
+pub const CMDLINE_LEN: usize = 256;
+
+/// The linear framebuffer set up by the boot loader, with 32 bits per pixel.
+#[derive(Debug, Copy, Clone)]
+pub struct Framebuffer {
+    pub phys: paging::PAddr,
+    pub size: usize,
+    pub width: u32,
+    pub height: u32,
+    /// The number of pixels in a scan line, which may exceed the width.
+    pub stride: u32,
+    /// Whether the pixels are in the BGRX order instead of RGBX.
+    pub bgr: bool,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct KernelArgs {
     pub rsdp: paging::PAddr,
@@ -50,4 +66,9 @@ pub struct KernelArgs {
 
     pub bootfs_phys: paging::PAddr,
     pub bootfs_len: usize,
+
+    /// The NUL-terminated load options of the boot loader.
+    pub cmdline: [u8; CMDLINE_LEN],
+
+    pub framebuffer: Option<Framebuffer>,
 }
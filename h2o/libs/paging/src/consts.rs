@@ -12,6 +12,11 @@ pub const CANONICAL_PREFIX: usize = 0xFFFF_0000_0000_0000;
 
 pub const RECURSIVE_IDX: usize = 510;
 
+/// The page attribute table set up on every CPU, which is the power-up default
+/// except that the entry selected by `WRITE_THRU` alone is write-combining
+/// instead of write-through.
+pub const PAT: u64 = 0x0007_0406_0007_0106;
+
 #[derive(Copy, Clone, Debug)]
 pub enum Error {
     OutOfMemory,
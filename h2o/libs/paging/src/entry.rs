@@ -1,6 +1,7 @@
 use core::{
     ops::{Deref, DerefMut},
     ptr::NonNull,
+    sync::atomic::{AtomicU64, Ordering::SeqCst},
 };
 
 use bitflags::bitflags;
@@ -111,6 +112,17 @@ impl AttrBuilder {
         self
     }
 
+    /// Select the write-combining entry of [`crate::PAT`], which overrides
+    /// the other cache attributes.
+    #[inline]
+    pub fn write_combining(mut self, write_combining: bool) -> Self {
+        if write_combining {
+            self.attr &= !Attr::CACHE_DISABLE;
+            self.attr |= Attr::WRITE_THRU;
+        }
+        self
+    }
+
     pub fn build(self) -> Attr {
         self.attr
     }
@@ -142,6 +154,13 @@ impl Entry {
         self.0 = 0;
     }
 
+    /// Clear `attr` atomically against the updates from the hardware, such as
+    /// the accessed and dirty bits, and return the old attributes.
+    pub fn fetch_clear(&mut self, attr: Attr) -> Attr {
+        let atomic = unsafe { &*(self as *mut Entry).cast::<AtomicU64>() };
+        Attr::from_bits_truncate(atomic.fetch_and(!attr.bits, SeqCst))
+    }
+
     pub(crate) fn get_table(&self, id_off: usize, level: Level) -> Option<NonNull<Table>> {
         let (phys, attr) = self.get(Level::Pt);
         if attr.contains(Attr::PRESENT) && attr.has_table(level) {
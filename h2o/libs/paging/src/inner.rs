@@ -249,6 +249,46 @@ pub(crate) fn drop_page(
     }
 }
 
+pub(crate) fn harvest(
+    table: &mut Table,
+    level: Level,
+    virt: Range<LAddr>,
+    id_off: usize,
+    clear: Attr,
+    ret: &mut Harvest,
+) {
+    let mut addr = virt.start.val();
+    while addr < virt.end.val() {
+        let size = level.page_size();
+        let next = (addr & !(size - 1))
+            .checked_add(size)
+            .map_or(virt.end.val(), |next| next.min(virt.end.val()));
+        let item = &mut table[level.addr_idx(LAddr::from(addr), false)];
+
+        if item.is_leaf(level) {
+            let attr = item.fetch_clear(clear);
+            let pages = size >> PAGE_SHIFT;
+            ret.mapped += pages;
+            if attr.contains(Attr::ACCESSED) {
+                ret.accessed += pages;
+            }
+            if attr.contains(Attr::DIRTY) {
+                ret.dirty += pages;
+            }
+            // The hardware won't set the bits again while the TLB caches them
+            // as set.
+            if attr.intersects(clear) {
+                unsafe { invalidate_page(LAddr::from(addr)) };
+            }
+        } else if let Some(mut sub) = item.get_table(id_off, level) {
+            let lower = level.decrease().expect("Too low level");
+            let range = LAddr::from(addr)..LAddr::from(next);
+            harvest(unsafe { sub.as_mut() }, lower, range, id_off, clear, ret);
+        }
+        addr = next;
+    }
+}
+
 pub(crate) fn check(virt: &Range<LAddr>, phys: Option<PAddr>) -> Result<(), Error> {
     log::trace!("paging::check: virt = {:?}, phys = {:?}", virt, phys);
 
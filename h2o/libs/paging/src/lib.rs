@@ -146,6 +146,36 @@ pub fn query(root_table: &Table, virt: LAddr, id_off: usize) -> Result<(PAddr, A
     inner::get_page(root_table, virt, id_off)
 }
 
+/// The pages counted by [`harvest`], in the unit of the smallest pages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Harvest {
+    pub mapped: usize,
+    pub accessed: usize,
+    pub dirty: usize,
+}
+
+/// Count the mapped, accessed and dirty pages of `virt`, and clear the bits in
+/// `clear` (at most [`Attr::ACCESSED`] and [`Attr::DIRTY`]) of every page
+/// atomically, so that the next harvest only counts the pages touched since.
+///
+/// A large page is counted as a whole even if `virt` covers part of it.
+///
+/// The TLB entries of the cleared pages are invalidated on the current CPU
+/// only, and the caller must shoot down the ones of the others.
+pub fn harvest(
+    root_table: &mut Table,
+    virt: Range<LAddr>,
+    id_off: usize,
+    clear: Attr,
+) -> Result<Harvest, Error> {
+    inner::check(&virt, None)?;
+
+    let clear = clear & (Attr::ACCESSED | Attr::DIRTY);
+    let mut ret = Harvest::default();
+    inner::harvest(root_table, Level::P4, virt, id_off, clear, &mut ret);
+    Ok(ret)
+}
+
 pub fn unmaps(
     root_table: &mut Table,
     mut virt: Range<LAddr>,
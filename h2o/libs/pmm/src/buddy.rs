@@ -78,6 +78,10 @@ const PF_LISTS_SIZE: usize = PF_LIST_SIZE * (PfType::Max as usize) * NR_ORDERS;
 /// The size of structure [`PageFrame`].
 pub const PF_SIZE: usize = core::mem::size_of::<PageFrame>();
 
+/// The alignment of the page frames that must be accessible around a
+/// hot-added range, since buddies are looked up within the largest pages.
+pub const HOT_ADD_ALIGN: usize = PAGE_SIZE << (MAX_ORDER - 1);
+
 /// The spinlock for the PMM.
 ///
 /// The PMM is single-cpued, so only one cpu / thread can access PMM at one
@@ -169,6 +173,20 @@ fn pft_base(pftype: PfType) -> *const PageFrame {
     }
 }
 
+/// The base of all the page frame structs.
+#[cfg(not(test))]
+#[inline]
+fn pf_base() -> *const PageFrame {
+    KMEM_PHYS_BASE as *const PageFrame
+}
+
+/// The base of all the page frame structs, simulated by the host tests.
+#[cfg(test)]
+#[inline]
+fn pf_base() -> *const PageFrame {
+    tests::PF_BASE.load(core::sync::atomic::Ordering::Acquire)
+}
+
 /// Convert a physical address to its corresponding page frame struct.
 ///
 /// # Examples
@@ -183,10 +201,7 @@ fn pft_base(pftype: PfType) -> *const PageFrame {
 /// physical memory range.
 #[inline]
 unsafe fn page_frame(addr: PAddr) -> &'static PageFrame {
-    (KMEM_PHYS_BASE as *const PageFrame)
-        .add(*addr >> PAGE_SHIFT)
-        .as_ref()
-        .unwrap()
+    pf_base().add(*addr >> PAGE_SHIFT).as_ref().unwrap()
 }
 
 // #[inline]
@@ -214,10 +229,7 @@ unsafe fn page_frame(addr: PAddr) -> &'static PageFrame {
 /// as generated by an invalid call from [`page_frame`]).
 #[inline]
 unsafe fn page_address(page: &PageFrame) -> PAddr {
-    PAddr::new(
-        ((page as *const PageFrame).offset_from(KMEM_PHYS_BASE as *const PageFrame) << PAGE_SHIFT)
-            as usize,
-    )
+    PAddr::new(((page as *const PageFrame).offset_from(pf_base()) << PAGE_SHIFT) as usize)
 }
 
 /// Convert a page frame to its corresponding PFN.
@@ -734,16 +746,106 @@ pub fn dump_data(pftype: PfType) {
     }
 }
 
+fn init_lists() {
+    for i in ORDERS {
+        *(pf_list_mut(PfType::Low, i).unwrap()) = PfList::new(PFAdapter::new());
+        *(pf_list_mut(PfType::High, i).unwrap()) = PfList::new(PFAdapter::new());
+    }
+}
+
 /// Initialize PMM module.
 ///
 /// Unfortunately, we must initialize every free list manually, and it takes a
 /// long time.
 pub fn init(mmap: &PtrIter<crate::boot::MemRange>, reserved_range: Range<usize>) -> (usize, usize) {
-    for i in ORDERS {
-        *(pf_list_mut(PfType::Low, i).unwrap()) = PfList::new(PFAdapter::new());
-        *(pf_list_mut(PfType::High, i).unwrap()) = PfList::new(PFAdapter::new());
-    }
+    init_lists();
 
     // NOTE: There we trust the `mmap` is valid.
     unsafe { parse_mmap(mmap, reserved_range) }
 }
+
+/// Add a range of physical memory plugged after initialization to the PMM.
+///
+/// # Safety
+///
+/// The range must be usable and unknown to the PMM, and the page frames of
+/// the range aligned to [`HOT_ADD_ALIGN`] must be accessible and zeroed if
+/// not in use yet.
+pub unsafe fn hot_add(addr: PAddr, n: usize) {
+    debug_assert_eq!(*addr & (PAGE_SIZE - 1), 0);
+    dealloc_pages_exact(n, addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::AtomicPtr;
+
+    use super::*;
+
+    pub(super) static PF_BASE: AtomicPtr<PageFrame> = AtomicPtr::new(core::ptr::null_mut());
+
+    /// The number of the simulated page frames, which covers every buddy of
+    /// the pages below [`NR_PAGES`].
+    const NR_FRAMES: usize = 4096;
+    const NR_PAGES: usize = 1024;
+
+    /// Simulate a physical memory of [`NR_PAGES`] free pages starting from 0.
+    fn setup() {
+        let frames = (0..NR_FRAMES)
+            .map(|_| PageFrame {
+                link: LinkedListLink::new(),
+                order: Cell::new(0),
+            })
+            .collect::<Vec<_>>();
+        PF_BASE.store(
+            Box::leak(frames.into_boxed_slice()).as_mut_ptr(),
+            core::sync::atomic::Ordering::Release,
+        );
+        init_lists();
+        unsafe { dealloc_pages_exact(NR_PAGES, PAddr::new(0)) };
+    }
+
+    // The PMM is global, so all the checks are in one test.
+    #[test]
+    fn buddy() {
+        setup();
+        let max_order = NR_PAGES.log2f();
+
+        // All the free pages are merged into one.
+        let all = alloc_pages(max_order, None).unwrap();
+        assert_eq!(*all, 0);
+        assert!(alloc_pages(0, None).is_none());
+        unsafe { dealloc_pages(max_order, all) };
+
+        // Splitting and merging.
+        let a = alloc_pages(0, Some(PfType::Low)).unwrap();
+        let b = alloc_pages(1, Some(PfType::Low)).unwrap();
+        let c = alloc_pages_exact(3, None).unwrap();
+        assert_eq!(*a & (PAGE_SIZE - 1), 0);
+        assert_eq!(*b & ((PAGE_SIZE << 1) - 1), 0);
+        let mut ranges = [(*a, 1), (*b, 2), (*c, 3)];
+        ranges.sort();
+        for w in ranges.windows(2) {
+            assert!(w[0].0 + (w[0].1 << PAGE_SHIFT) <= w[1].0);
+        }
+        unsafe {
+            dealloc_pages(0, a);
+            dealloc_pages(1, b);
+            dealloc_pages_exact(3, c);
+        }
+        let all = alloc_pages(max_order, None).unwrap();
+        assert_eq!(*all, 0);
+        unsafe { dealloc_pages(max_order, all) };
+
+        // Out of range.
+        assert!(alloc_pages(MAX_ORDER, None).is_none());
+        assert!(alloc_pages(0, Some(PfType::High)).is_none());
+
+        // Hot-added pages are merged with their buddies.
+        unsafe { hot_add(PAddr::new(NR_PAGES << PAGE_SHIFT), NR_PAGES) };
+        let all = alloc_pages(max_order + 1, None).unwrap();
+        assert_eq!(*all, 0);
+        assert!(alloc_pages(0, None).is_none());
+        unsafe { dealloc_pages(max_order + 1, all) };
+    }
+}
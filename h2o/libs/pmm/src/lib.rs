@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(nonnull_slice_from_raw_parts)]
 
 pub mod boot;
@@ -9,8 +9,8 @@ pub use paging::{LAddr, PAddr, PAGE_SHIFT, PAGE_SIZE};
 #[cfg(debug_assertions)]
 pub use self::buddy::dump_data;
 pub use self::buddy::{
-    alloc_pages, alloc_pages_exact, dealloc_pages, dealloc_pages_exact, PfType, MAX_ORDER,
-    NR_ORDERS, ORDERS, PF_SIZE,
+    alloc_pages, alloc_pages_exact, dealloc_pages, dealloc_pages_exact, hot_add, PfType,
+    HOT_ADD_ALIGN, MAX_ORDER, NR_ORDERS, ORDERS, PF_SIZE,
 };
 
 pub const KMEM_PHYS_BASE: usize = 0xFFFF_9000_0000_0000;
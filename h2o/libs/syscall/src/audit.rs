@@ -0,0 +1,44 @@
+//! The kinds and the records of the audit log.
+//!
+//! Each kind has a bit in the filter mask of `sv_audit_filter`, and the
+//! meaning of the arguments of a record depends on its kind.
+
+/// Attempts to add features to a handle. The arguments are the handle, the
+/// features it has and the ones requested.
+pub const AUDIT_FEAT_ESCALATION: u32 = 0;
+/// Killing a child task. The first argument is the raw TID of the child.
+pub const AUDIT_TASK_KILL: u32 = 1;
+/// Allocating a sub-resource. The arguments are the type of the resource, and
+/// the base and the size of the range granted.
+pub const AUDIT_RES_GRANT: u32 = 2;
+/// Opens denied by a server, reported with `sv_audit_report`. The arguments
+/// are the task and the job of the client, and the permissions required.
+pub const AUDIT_OPEN_DENIED: u32 = 3;
+/// Events defined by the system services, reported with `sv_audit_report`.
+pub const AUDIT_SERVICE: u32 = 4;
+/// Syscalls denied by the filter of the task. The first argument is the
+/// number of the syscall.
+pub const AUDIT_SYSCALL_DENIED: u32 = 5;
+
+pub const AUDIT_KIND_COUNT: u32 = 6;
+
+/// The kinds that user tasks are allowed to report.
+pub const AUDIT_USER_KINDS: u32 = (1 << AUDIT_OPEN_DENIED) | (1 << AUDIT_SERVICE);
+/// All the kinds, which are all recorded by default.
+pub const AUDIT_ALL_KINDS: u32 = (1 << AUDIT_KIND_COUNT) - 1;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct AuditRecord {
+    /// The sequence number of the record, which skips the ones overwritten
+    /// before being read.
+    pub seq: u64,
+    /// The time in nanoseconds when the event occurred.
+    pub time: u64,
+    pub kind: u32,
+    /// The raw TID of the task that caused or reported the event.
+    pub task: u64,
+    /// The job (task space) of the task.
+    pub job: u64,
+    pub args: [u64; 3],
+}
@@ -5,6 +5,7 @@ pub(crate) mod hdl;
 #[cfg(all(not(feature = "stub"), feature = "call"))]
 mod raw;
 pub(crate) mod reg;
+pub mod sig;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 #[repr(C)]
@@ -15,24 +16,38 @@ pub struct Syscall {
 
 #[cfg(all(not(feature = "stub"), feature = "call"))]
 use crate::{
-    c_ty::*, ipc::RawPacket, mem::*, res::IntrConfig, task::ExecInfo, Feature, Handle, SerdeReg,
+    audit::AuditRecord,
+    c_ty::*,
+    ipc::{
+        ChanCredit, ChanInfo, ChanOptions, ChanPeerId, PortRecord, RawPacket, RawSegment,
+        WaitOptions,
+    },
+    mem::*,
+    res::{IntrConfig, IntrLatency, IntrPollStat},
+    task::{CpuStat, ExecInfo, SchedStat, SpawnInfo},
+    time::TimeInfo,
+    Feature, Handle, SerdeReg,
 };
 
 #[cfg(feature = "vdso")]
 #[no_mangle]
-pub unsafe extern "C" fn sv_time_get(ptr: *mut ()) -> crate::c_ty::Status {
+pub unsafe extern "C" fn sv_time_get(ptr: *mut u128) -> crate::c_ty::Status {
+    let c = crate::constants();
+    if c.clock_source != crate::time::ClockSource::Tsc {
+        let ret = raw::syscall(crate::SV_TIME_GET, ptr as usize, 0, 0, 0, 0);
+        return SerdeReg::decode(ret);
+    }
+
     let ticks = {
         let (eax, edx): (u32, u32);
         core::arch::asm!("rdtsc", out("eax")eax, out("edx")edx);
         ((edx as u64) << 32) | (eax as u64)
     };
 
-    let c = crate::constants();
-
     let val = ticks - c.ticks_offset;
     let ns = (val as u128 * c.ticks_multiplier) >> c.ticks_shift;
 
-    ptr.cast::<u128>().write(ns);
+    ptr.write(ns);
 
     Status::from_res(Ok(()))
 }
@@ -77,5 +92,18 @@ pub extern "C" fn sv_cpu_num() -> crate::c_ty::StatusOrValue {
     crate::c_ty::StatusOrValue::from_res(Ok(crate::constants().num_cpus as u64))
 }
 
+#[cfg(feature = "vdso")]
+#[no_mangle]
+pub extern "C" fn sv_kern_feat() -> crate::c_ty::StatusOrValue {
+    crate::c_ty::StatusOrValue::from_res(Ok(crate::constants().features))
+}
+
+#[cfg(feature = "vdso")]
+#[no_mangle]
+pub extern "C" fn sv_call_avail(num: usize) -> crate::c_ty::StatusOrValue {
+    let avail = crate::constants().has_syscall(num);
+    crate::c_ty::StatusOrValue::from_res(Ok(avail as u64))
+}
+
 #[cfg(all(not(feature = "stub"), feature = "call"))]
 include!(concat!(env!("CARGO_MANIFEST_DIR"), "/target/call.rs"));
@@ -70,3 +70,28 @@ impl SerdeReg for () {
     #[inline]
     fn decode(_: usize) {}
 }
+
+/// Implement [`SerdeReg`] and [`Abi`](crate::Abi) for bitflags types, passed
+/// as their bits and truncated on decoding.
+macro_rules! serde_reg_bitflags {
+    ($($ty:ty: $bits:ty),* $(,)?) => {
+        $(
+            impl $crate::SerdeReg for $ty {
+                #[inline]
+                fn encode(self) -> usize {
+                    self.bits() as usize
+                }
+
+                #[inline]
+                fn decode(val: usize) -> Self {
+                    Self::from_bits_truncate(val as $bits)
+                }
+            }
+
+            impl $crate::Abi for $ty {
+                type Abi = Self;
+            }
+        )*
+    };
+}
+pub(crate) use serde_reg_bitflags;
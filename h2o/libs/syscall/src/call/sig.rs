@@ -0,0 +1,59 @@
+//! The signatures of the syscalls, generated from the signature files in
+//! `h2o/kernel/syscall`.
+//!
+//! Every `#[syscall]` handler in the kernel is checked against its signature
+//! at compile time, with the types of its arguments and return value mapped by
+//! [`Abi`], so that a handler taking its arguments in another order or of
+//! other types than the callers pass fails to build.
+
+#![allow(non_camel_case_types)]
+
+use core::marker::PhantomData;
+
+use crate::{
+    audit::AuditRecord,
+    ipc::{
+        ChanCredit, ChanInfo, ChanOptions, ChanPeerId, PortRecord, RawPacket, RawSegment,
+        WaitOptions,
+    },
+    mem::*,
+    res::{IntrConfig, IntrLatency, IntrPollStat},
+    task::{CpuStat, ExecInfo, SchedStat, SpawnInfo},
+    time::TimeInfo,
+    Feature, Handle, Result, Syscall,
+};
+
+/// The type of a syscall argument or return value as declared in the
+/// signatures.
+pub trait Abi {
+    type Abi;
+}
+
+macro_rules! abi_self {
+    ($($ty:ty),* $(,)?) => {
+        $(impl Abi for $ty {
+            type Abi = Self;
+        })*
+    };
+}
+abi_self!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+abi_self!(bool, (), Handle);
+
+impl<T> Abi for *const T {
+    type Abi = Self;
+}
+
+impl<T> Abi for *mut T {
+    type Abi = Self;
+}
+
+/// Handlers return the value or the error, encoded into one register.
+impl<T: Abi> Abi for Result<T> {
+    type Abi = T::Abi;
+}
+
+/// Fails to compile unless `T` and `U` are the same type.
+#[doc(hidden)]
+pub const fn assert_same<T>(_: PhantomData<T>, _: PhantomData<T>) {}
+
+include!(concat!(env!("CARGO_MANIFEST_DIR"), "/target/sig.rs"));
@@ -1,10 +1,12 @@
 pub mod c_ty;
+mod kind;
 
 use core::{
     fmt::{Debug, Display},
     ops::Range,
 };
 
+pub use self::kind::ErrorKind;
 use crate::SerdeReg;
 
 pub const ERRC_RANGE: Range<i32> = 1..35;
@@ -0,0 +1,88 @@
+use super::*;
+
+/// The general categories of errors, resembling `std::io::ErrorKind`.
+///
+/// Match on the kind instead of raw error numbers when only the category of an
+/// error is concerned.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    NotFound,
+    PermissionDenied,
+    AlreadyExists,
+    WouldBlock,
+    TimedOut,
+    BrokenPipe,
+    Interrupted,
+    InvalidInput,
+    InvalidData,
+    UnexpectedEof,
+    OutOfMemory,
+    ResourceBusy,
+    NotADirectory,
+    IsADirectory,
+    StorageFull,
+    Unsupported,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::NotFound => "entity not found",
+            ErrorKind::PermissionDenied => "permission denied",
+            ErrorKind::AlreadyExists => "entity already exists",
+            ErrorKind::WouldBlock => "operation would block",
+            ErrorKind::TimedOut => "timed out",
+            ErrorKind::BrokenPipe => "broken pipe",
+            ErrorKind::Interrupted => "operation interrupted",
+            ErrorKind::InvalidInput => "invalid input parameter",
+            ErrorKind::InvalidData => "invalid data",
+            ErrorKind::UnexpectedEof => "unexpected end of file",
+            ErrorKind::OutOfMemory => "out of memory",
+            ErrorKind::ResourceBusy => "resource busy",
+            ErrorKind::NotADirectory => "not a directory",
+            ErrorKind::IsADirectory => "is a directory",
+            ErrorKind::StorageFull => "no storage space",
+            ErrorKind::Unsupported => "unsupported",
+            ErrorKind::Other => "other error",
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            EPERM | EACCES | EROFS => ErrorKind::PermissionDenied,
+            ENOENT | ESRCH | ENXIO | ECHILD | ENODEV => ErrorKind::NotFound,
+            EINTR => ErrorKind::Interrupted,
+            ENOEXEC => ErrorKind::InvalidData,
+            E2BIG | EBADF | EFAULT | ENOTBLK | EINVAL | EFBIG | ESPIPE | EDOM | ERANGE
+            | EBUFFER | EALIGN | ETYPE => ErrorKind::InvalidInput,
+            EAGAIN => ErrorKind::WouldBlock,
+            ENOMEM => ErrorKind::OutOfMemory,
+            EBUSY | ETXTBSY => ErrorKind::ResourceBusy,
+            EEXIST => ErrorKind::AlreadyExists,
+            ENOTDIR => ErrorKind::NotADirectory,
+            EISDIR => ErrorKind::IsADirectory,
+            ENOSPC => ErrorKind::StorageFull,
+            EXDEV | ENOTTY | ESPRT => ErrorKind::Unsupported,
+            EPIPE => ErrorKind::BrokenPipe,
+            ETIME => ErrorKind::TimedOut,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl From<Error> for ErrorKind {
+    #[inline]
+    fn from(value: Error) -> Self {
+        value.kind()
+    }
+}
@@ -1,4 +1,4 @@
-use crate::SerdeReg;
+use crate::call::reg::serde_reg_bitflags;
 
 bitflags::bitflags! {
     #[repr(transparent)]
@@ -9,15 +9,8 @@ bitflags::bitflags! {
         const WRITE = 1 << 3;
         const EXECUTE = 1 << 4;
         const WAIT = 1 << 5;
+        const IDENTIFY = 1 << 6;
     }
 }
 
-impl SerdeReg for Feature {
-    fn encode(self) -> usize {
-        self.bits as usize
-    }
-
-    fn decode(val: usize) -> Self {
-        Feature::from_bits_truncate(val as u64)
-    }
-}
+serde_reg_bitflags!(Feature: u64);
@@ -1,4 +1,6 @@
-use crate::Handle;
+use bitflags::bitflags;
+
+use crate::{call::reg::serde_reg_bitflags, Handle};
 
 #[derive(Debug, Copy, Clone)]
 #[repr(C)]
@@ -12,10 +14,138 @@ pub struct RawPacket {
     pub buffer_cap: usize,
 }
 
+/// A segment of the buffer of a packet, sent or received with
+/// `sv_chan_sendv` and `sv_chan_recvv`.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct RawSegment {
+    pub ptr: *mut u8,
+    pub len: usize,
+}
+
 pub const MAX_HANDLE_COUNT: usize = 256;
+pub const MAX_SEGMENT_COUNT: usize = 64;
 pub const MAX_BUFFER_SIZE: usize = crate::mem::PAGE_SIZE;
 
 pub const SIG_GENERIC: usize = 0b0000_0001;
 pub const SIG_READ: usize = 0b0000_0010;
 pub const SIG_WRITE: usize = 0b0000_0100;
 pub const SIG_TIMER: usize = 0b0000_1000;
+
+bitflags! {
+    /// Options for waiting on the signal of an object.
+    ///
+    /// The signal matches if it contains all the bits waited for, unless
+    /// `MATCH_ANY` or `MATCH_EXACT` is set.
+    #[derive(Default)]
+    #[repr(transparent)]
+    pub struct WaitOptions: u32 {
+        /// Also match the signal present when the wait starts.
+        const LEVEL_TRIGGERED = 1;
+        /// Wake up all the tasks waiting on the same object.
+        const WAKE_ALL = 1 << 1;
+        /// Match if the signal contains any of the bits waited for.
+        const MATCH_ANY = 1 << 2;
+        /// Match only if the signal equals the bits waited for exactly.
+        ///
+        /// Notifications that only clear bits can also wake up these waits.
+        const MATCH_EXACT = 1 << 3;
+        /// Clear the matched bits of the signal atomically on wake-up, so that
+        /// only one waiter observes them.
+        const CONSUME = 1 << 4;
+    }
+}
+
+serde_reg_bitflags!(WaitOptions: u32);
+
+bitflags! {
+    /// Options for creating a pair of channels.
+    #[derive(Default)]
+    #[repr(transparent)]
+    pub struct ChanOptions: u32 {
+        /// Enable credit-based flow control with the credit passed along.
+        const FLOW_CONTROL = 1;
+        /// Move the pages of large packets instead of copying them.
+        ///
+        /// A packet whose `buffer_size` exceeds [`MAX_BUFFER_SIZE`] must be
+        /// sent from a buffer at the start of a whole writable mapping of a
+        /// physical object referred to by nothing else. The whole mapping is
+        /// removed from the sender, and the physical object is received as
+        /// the last handle of the packet, with `buffer_size` set to the
+        /// payload size and nothing copied into the buffer.
+        const ZERO_COPY = 2;
+    }
+}
+
+serde_reg_bitflags!(ChanOptions: u32);
+
+/// The credit window granted to each side of a flow-controlled channel.
+///
+/// A sender consumes 1 packet and the size of the buffer from the credit for
+/// every packet sent, and gets them back when the peer receives the packet.
+/// Sending returns `EAGAIN` if the credit is exhausted, and `SIG_WRITE` is
+/// raised when it's replenished.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct ChanCredit {
+    pub packets: usize,
+    pub bytes: usize,
+}
+
+/// The task using a channel side and the job (task space) it belongs to, where
+/// 0 stands for the kernel.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct ChanTaskId {
+    pub task: u64,
+    pub job: u64,
+}
+
+/// The identity of the peer of a channel side.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct ChanPeerId {
+    /// The task that created the channel.
+    pub creator: ChanTaskId,
+    /// The task that sent the last packet through the peer, or the creator if
+    /// nothing has been sent yet.
+    pub holder: ChanTaskId,
+}
+
+/// The traffic statistics of a channel side.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct ChanInfo {
+    /// The id shared by both sides of the channel.
+    pub peer_id: u64,
+    pub msgs_sent: u64,
+    pub bytes_sent: u64,
+    pub msgs_received: u64,
+    pub bytes_received: u64,
+    /// The number of packets waiting to be received.
+    pub queue_len: usize,
+    pub peak_queue_len: usize,
+    /// The time in nanoseconds when the peer was closed, or 0 if it's still
+    /// alive.
+    pub peer_closed: u64,
+}
+
+/// The address of `sv_dgram_send_to` sending to every other endpoint of the
+/// bus.
+pub const DGRAM_BROADCAST: u64 = u64::MAX;
+/// The maximum number of datagrams queued in an endpoint, beyond which the
+/// incoming ones are dropped.
+pub const DGRAM_QUEUE_SIZE: usize = 256;
+
+/// A readiness record of an object bound to a port, dequeued with
+/// `sv_port_dequeue`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct PortRecord {
+    /// The key the object is bound with.
+    pub key: u64,
+    /// The signal of the object that satisfied the binding.
+    pub signal: usize,
+    /// Whether the object is gone, in which case the binding is removed.
+    pub canceled: bool,
+}
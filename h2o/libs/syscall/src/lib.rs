@@ -7,6 +7,7 @@
 #![feature(linkage)]
 #![feature(macro_metavar_expr)]
 
+pub mod audit;
 pub mod call;
 pub mod error;
 pub mod feat;
@@ -15,16 +16,19 @@ pub mod mem;
 pub mod res;
 #[cfg(feature = "stub")]
 pub mod stub;
+pub mod sys;
 pub mod task;
+pub mod time;
 
 pub use sv_gen::*;
 
 #[cfg(feature = "stub")]
 pub use self::stub::*;
 pub use self::{
-    call::{hdl::Handle, reg::*, Syscall, *},
+    call::{hdl::Handle, reg::*, sig::Abi, Syscall, *},
     error::*,
     feat::*,
+    sys::*,
 };
 
 #[derive(Debug, Copy, Clone)]
@@ -35,6 +39,13 @@ pub struct Constants {
     pub ticks_shift: u128,
     pub has_builtin_rand: bool,
     pub num_cpus: usize,
+    /// The time is read from the TSC with the ticks above only if the source
+    /// is [`time::ClockSource::Tsc`], or from the kernel otherwise.
+    pub clock_source: time::ClockSource,
+    /// The bits of [`sys::KernelFeatures`].
+    pub features: u64,
+    /// The bitmap of the syscall numbers served by the kernel.
+    pub syscalls: [u64; sys::SYSCALL_WORDS],
 }
 
 impl Constants {
@@ -45,8 +56,15 @@ impl Constants {
             ticks_shift: 0,
             has_builtin_rand: false,
             num_cpus: 1,
+            clock_source: time::ClockSource::Tsc,
+            features: 0,
+            syscalls: [0; sys::SYSCALL_WORDS],
         }
     }
+
+    pub const fn has_syscall(&self, num: usize) -> bool {
+        num < crate::SYSCALL_COUNT && self.syscalls[num / 64] & (1 << (num % 64)) != 0
+    }
 }
 
 #[cfg(feature = "vdso")]
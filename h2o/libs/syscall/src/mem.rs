@@ -1,6 +1,6 @@
 use bitflags::bitflags;
 
-use crate::SerdeReg;
+use crate::call::reg::serde_reg_bitflags;
 
 bitflags! {
     /// Flags to describe a block of memory.
@@ -11,6 +11,17 @@ bitflags! {
         const WRITABLE    = 1 << 2;
         const EXECUTABLE  = 1 << 3;
         const UNCACHED    = 1 << 4;
+        /// Only for the memory outside the RAM, such as framebuffers. Cannot
+        /// be combined with `UNCACHED`.
+        const WRITE_COMBINING = 1 << 5;
+        /// Place the mapping right next to the others instead of leaving guard
+        /// gaps around it, for the users requiring contiguity.
+        const NO_GUARD = 1 << 6;
+        /// Map at the explicit offset even if it's occupied, removing the
+        /// mappings and reservations there in the same step, so that nothing
+        /// else can be placed in the range meanwhile. The mappings must lie
+        /// within the range.
+        const REPLACE = 1 << 7;
     }
 
     #[derive(Default)]
@@ -18,32 +29,14 @@ bitflags! {
     pub struct PhysOptions: u32 {
         const RESIZABLE = 1 << 0;
         const ZEROED = 1 << 1;
+        /// Populate the pages on the first access to the mappings instead of
+        /// when mapped, so that a large range can be reserved without
+        /// committing it. Implies `ZEROED`, and not for contiguous memory.
+        const DEMAND = 1 << 2;
     }
 }
 
-impl SerdeReg for Flags {
-    #[inline]
-    fn encode(self) -> usize {
-        self.bits() as usize
-    }
-
-    #[inline]
-    fn decode(val: usize) -> Self {
-        Self::from_bits_truncate(val as u32)
-    }
-}
-
-impl SerdeReg for PhysOptions {
-    #[inline]
-    fn encode(self) -> usize {
-        self.bits() as usize
-    }
-
-    #[inline]
-    fn decode(val: usize) -> Self {
-        Self::from_bits_truncate(val as u32)
-    }
-}
+serde_reg_bitflags!(Flags: u32, PhysOptions: u32);
 
 #[derive(Debug, Default)]
 #[repr(C)]
@@ -52,6 +45,41 @@ pub struct MemInfo {
     pub current_used: usize,
 }
 
+/// The usage of the pages of a space and the memory charged to it, sampled by
+/// `sv_mem_stat`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct MemStat {
+    /// The pages mapped in the space.
+    pub mapped: usize,
+    /// The pages written since they were mapped.
+    pub dirty: usize,
+    /// The estimated working set, i.e. the pages accessed in the last sampling
+    /// window.
+    pub working_set: usize,
+    /// The length of the last sampling window in microseconds, or 0 if the
+    /// space hasn't been sampled yet.
+    pub window_us: u64,
+    /// The bytes of the physical memory mapped in the space, including the
+    /// ones shared with other spaces.
+    pub phys: usize,
+    /// The bytes of the mapped physical memory committed, i.e. not backed by
+    /// the zero page until written.
+    pub committed: usize,
+    /// The bytes of the kernel stacks of the tasks in the space.
+    pub kstack: usize,
+}
+
+/// A range of physical memory usable by the kernel.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct MemRange {
+    pub base: usize,
+    pub len: usize,
+    /// Whether the range is plugged after boot.
+    pub hot_added: bool,
+}
+
 pub const PAGE_SHIFT: usize = 12;
 pub const PAGE_SIZE: usize = 4096;
 
@@ -1,25 +1,55 @@
 use bitflags::bitflags;
 
-use crate::SerdeReg;
+use crate::call::reg::serde_reg_bitflags;
 
 pub const RES_MEM: u32 = 0;
 pub const RES_PIO: u32 = 1;
 pub const RES_GSI: u32 = 2;
 
+pub const SYSTEM_CTL_SUSPEND: u32 = 1;
+
+/// Reset the system when the watchdog expires, after dumping the diagnostics.
+pub const WATCHDOG_RESET: u32 = 1;
+
 bitflags! {
     #[repr(transparent)]
     pub struct IntrConfig: u32 {
         const ACTIVE_HIGH     = 0b01;
         const LEVEL_TRIGGERED = 0b10;
+        /// The interrupt is handled by a dedicated task, with the latency
+        /// from the hard IRQ to the handler recorded.
+        const THREADED        = 0b100;
     }
 }
 
-impl SerdeReg for IntrConfig {
-    fn encode(self) -> usize {
-        self.bits() as usize
-    }
+serde_reg_bitflags!(IntrConfig: u32);
 
-    fn decode(val: usize) -> Self {
-        Self::from_bits_truncate(val as u32)
-    }
+/// The latency from the hard IRQ to the handler of a threaded interrupt, in
+/// nanoseconds.
+///
+/// The percentiles are upper bounds, measured with power-of-2 granularity.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct IntrLatency {
+    /// The number of the interrupts handled.
+    pub count: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+/// The statistics of the polled mode of an interrupt.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct IntrPollStat {
+    /// Whether the interrupt is in polled mode now.
+    pub polling: bool,
+    /// The number of the polls.
+    pub polls: u64,
+    /// The number of the switches into polled mode.
+    pub entered: u64,
+    /// The number of the switches back to interrupts on running out of the
+    /// budget.
+    pub exhausted: u64,
 }
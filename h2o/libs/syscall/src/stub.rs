@@ -1,5 +1,15 @@
 use crate::{
-    c_ty::*, ipc::RawPacket, mem::*, res::IntrConfig, task::ExecInfo, Feature, Handle, Syscall,
+    audit::AuditRecord,
+    c_ty::*,
+    ipc::{
+        ChanCredit, ChanInfo, ChanOptions, ChanPeerId, PortRecord, RawPacket, RawSegment,
+        WaitOptions,
+    },
+    mem::*,
+    res::{IntrConfig, IntrLatency, IntrPollStat},
+    task::{CpuStat, ExecInfo, SchedStat, SpawnInfo},
+    time::TimeInfo,
+    Feature, Handle, Syscall,
 };
 
 include!(concat!(env!("CARGO_MANIFEST_DIR"), "/target/stub.rs"));
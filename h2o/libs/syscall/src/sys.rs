@@ -0,0 +1,37 @@
+//! The features of the running kernel, kept in the constants region of the
+//! VDSO so that the libraries can select their paths at runtime without
+//! entering the kernel.
+
+/// The words of the syscall bitmap in [`crate::Constants`].
+pub const SYSCALL_WORDS: usize = (crate::SYSCALL_COUNT + 63) / 64;
+
+bitflags::bitflags! {
+    #[repr(transparent)]
+    pub struct KernelFeatures: u64 {
+        /// `sv_random` reads from a hardware random number generator.
+        const BUILTIN_RAND = 1 << 0;
+        /// The system can be suspended to RAM.
+        const SUSPEND = 1 << 1;
+        /// A hardware watchdog backs the software one.
+        const HW_WATCHDOG = 1 << 2;
+        /// The kernel is built with its tests.
+        const KTEST = 1 << 3;
+        /// The system is built to run the benchmarks after booting.
+        const BENCH = 1 << 4;
+    }
+}
+
+/// Get the features of the running kernel.
+#[cfg(feature = "stub")]
+pub fn features() -> KernelFeatures {
+    let bits = unsafe { crate::sv_kern_feat() }.into_res().unwrap_or(0);
+    KernelFeatures::from_bits_truncate(bits)
+}
+
+/// Check whether the running kernel serves the syscall numbered `num`, such
+/// as [`crate::SV_CHAN_SEND`].
+#[cfg(feature = "stub")]
+pub fn has_syscall(num: usize) -> bool {
+    let res = unsafe { crate::sv_call_avail(num) }.into_res();
+    matches!(res, Ok(avail) if avail != 0)
+}
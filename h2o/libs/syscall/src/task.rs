@@ -1,22 +1,49 @@
 pub mod ctx;
 pub mod excep;
 
-use crate::Handle;
+use crate::{Feature, Handle};
 
 pub const DEFAULT_STACK_SIZE: usize = 256 * 1024;
 
 pub const TASK_CTL_KILL: u32 = 1;
 pub const TASK_CTL_SUSPEND: u32 = 2;
 
+/// Never preempts the running task when woken up.
+pub const TASK_PRIO_LOW: u32 = 0;
+pub const TASK_PRIO_NORMAL: u32 = 1;
+/// Always preempts the running task of a lower priority when woken up.
+pub const TASK_PRIO_HIGH: u32 = 2;
+
 pub const TASK_DBG_READ_REG: u32 = 1;
 pub const TASK_DBG_WRITE_REG: u32 = 2;
 pub const TASK_DBG_READ_MEM: u32 = 3;
 pub const TASK_DBG_WRITE_MEM: u32 = 4;
 pub const TASK_DBG_EXCEP_HDL: u32 = 5;
 
+/// The number of the task-local slots of every task.
+pub const TASK_LOCAL_SLOTS: u32 = 4;
+/// The task-local slot holding the pointer to the TCB, for the early paths
+/// where the thread pointer is not set up yet.
+pub const TASK_LOCAL_TCB: u32 = 0;
+
 pub const TASK_DBGADDR_GPR: usize = 0x1000;
 pub const TASK_DBGADDR_FPU: usize = 0x2000;
 
+/// Fail memory allocations of user tasks.
+pub const FAULT_ALLOC: u32 = 1;
+/// Fail insertions into handle tables.
+pub const FAULT_HANDLE: u32 = 2;
+/// Fail sending packets into channels.
+pub const FAULT_SEND: u32 = 3;
+/// Fail the syscall of the given number.
+pub const FAULT_SYSCALL: u32 = 4;
+
+/// Fail the syscalls denied by the filter of the task with `EPERM`.
+pub const SYSCALL_FILTER_ERROR: u32 = 0;
+/// Report the syscalls denied by the filter of the task to its exception
+/// channel, and kill it unless the exception is recovered.
+pub const SYSCALL_FILTER_KILL: u32 = 1;
+
 #[derive(Debug, Copy, Clone)]
 #[repr(C)]
 pub struct ExecInfo {
@@ -28,3 +55,99 @@ pub struct ExecInfo {
     pub init_chan: Handle,
     pub arg: u64,
 }
+
+/// The method id of the startup arguments packet sent by `sv_task_spawn`.
+pub const STARTUP_ARGS: usize = 0x1873ddab8;
+/// The magic number leading the startup arguments packet, the same as the one
+/// of RPC packets.
+pub const STARTUP_ARGS_MAGIC: usize = 0xac84fb7c0391;
+
+/// A handle moved from the caller into a task spawned by `sv_task_spawn`.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct HandleTransfer {
+    pub handle: Handle,
+    /// The mask of the features of the transferred handle.
+    pub features: Feature,
+    /// The raw handle info identifying the handle in the startup arguments.
+    pub info: u32,
+}
+
+/// The additional information for `sv_task_spawn`.
+///
+/// The transferred handles and the NUL-separated argument and environment
+/// blocks are serialized into a startup arguments packet, which is delivered
+/// to the init channel of the new task after all the packets already queued
+/// in it.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct SpawnInfo {
+    pub transfers: *const HandleTransfer,
+    pub transfer_count: usize,
+    pub args: *const u8,
+    pub args_len: usize,
+    pub env: *const u8,
+    pub env_len: usize,
+}
+
+/// The latency from waking up to running, in nanoseconds.
+pub const TASK_STAT_WAKE_LATENCY: u32 = 0;
+/// The part of the time slice used when switched out, in per mille, which
+/// exceeds 1000 if the task overran its slice.
+pub const TASK_STAT_SLICE_USAGE: u32 = 1;
+/// The duration of blocking for the reason of the given index, in
+/// nanoseconds.
+pub const TASK_STAT_BLOCK: u32 = 2;
+
+/// The number of the power-of-2 buckets of the scheduling histograms.
+pub const SCHED_HIST_BUCKETS: usize = 32;
+/// The maximum length of the blocking reasons reported.
+pub const SCHED_REASON_LEN: usize = 32;
+/// The maximum length of the task names set by `sv_task_set_name`, which is
+/// also the length of the names reported by `sv_task_stat`.
+pub const TASK_NAME_LEN: usize = 32;
+
+/// A histogram of a scheduling statistic of a task or the whole system.
+///
+/// Bucket `i` counts the values below `2^i` and at least `2^(i-1)`, with the
+/// last bucket counting all the larger values as well. The percentiles are
+/// upper bounds, measured with power-of-2 granularity.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct SchedStat {
+    pub count: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+    pub buckets: [u64; SCHED_HIST_BUCKETS],
+    /// The NUL-padded blocking reason, only set for `TASK_STAT_BLOCK`.
+    pub reason: [u8; SCHED_REASON_LEN],
+    /// The NUL-padded name of the task, truncated to `TASK_NAME_LEN` bytes and
+    /// only set by `sv_task_stat`.
+    pub name: [u8; TASK_NAME_LEN],
+}
+
+/// The time accounting of a CPU and the statistics of its deferred work.
+///
+/// The times are in nanoseconds, charged to what the CPU is doing: running
+/// in user mode, in the kernel, in interrupt handlers, running the deferred
+/// work, or idling.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct CpuStat {
+    pub user: u64,
+    pub kernel: u64,
+    pub hardirq: u64,
+    pub deferred: u64,
+    pub idle: u64,
+    /// The number of the deferred work items queued.
+    pub defer_queued: u64,
+    /// The number of the deferred work items run.
+    pub defer_run: u64,
+    /// The maximum length of the deferred work queue.
+    pub defer_max_pending: u64,
+    /// The maximum latency from queuing a deferred work item to running it,
+    /// in nanoseconds.
+    pub defer_max_latency: u64,
+}
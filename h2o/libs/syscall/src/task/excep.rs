@@ -6,6 +6,10 @@ pub struct Exception {
     pub cr2: u64,
 }
 
+/// The vector of the exceptions reporting a syscall denied by the filter of the
+/// task, where `errc` is the number of the syscall.
+pub const EXVEC_SYSCALL_DENIED: u8 = 0xff;
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct ExceptionResult {
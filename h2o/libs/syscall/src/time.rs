@@ -0,0 +1,32 @@
+/// The hardware counter from which the kernel reads the time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ClockSource {
+    /// The CPU timestamp counter, readable directly from the user space.
+    Tsc = 0,
+    /// The high precision event timer.
+    Hpet = 1,
+    /// The ACPI power management timer.
+    PmTimer = 2,
+}
+
+impl Default for ClockSource {
+    #[inline]
+    fn default() -> Self {
+        ClockSource::Tsc
+    }
+}
+
+/// The information of the current clock source.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct TimeInfo {
+    pub source: ClockSource,
+    /// Whether the TSC keeps a constant rate across all the power states.
+    pub invariant_tsc: bool,
+    pub freq_khz: u64,
+    /// The minimal interval in nanoseconds that the source can distinguish.
+    pub resolution_ns: u64,
+    /// How many times the kernel has switched the source due to instability.
+    pub switches: u64,
+}
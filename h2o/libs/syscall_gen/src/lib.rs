@@ -26,13 +26,25 @@ where
     }
 }
 
+/// Generate the wrapper of a syscall handler.
+///
+/// Accepts an optional `restart` flag, which makes the wrapper handle the
+/// pending signal and call the handler again if it's interrupted with `EINTR`
+/// instead of returning to the caller.
 #[proc_macro_attribute]
 pub fn syscall(args: TokenStream, item_fn: TokenStream) -> TokenStream {
-    assert!(
-        args.to_string().is_empty(),
-        "This macro don't receive any arguments"
-    );
-    let func = parse_macro_input!(item_fn as syscall_fn::SyscallFn);
+    let flags = parse_macro_input!(args with Punctuated::<Ident, Token![,]>::parse_terminated);
+    let mut func = parse_macro_input!(item_fn as syscall_fn::SyscallFn);
+    for flag in flags {
+        match &*flag.to_string() {
+            "restart" => func.restart = true,
+            _ => {
+                return Error::new(flag.span(), "unknown syscall flag")
+                    .to_compile_error()
+                    .into()
+            }
+        }
+    }
 
     quote!(#func).into()
 }
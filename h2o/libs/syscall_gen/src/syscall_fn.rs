@@ -8,6 +8,7 @@ pub struct SyscallFn {
     args: Punctuated<FnArg, Token![,]>,
     output: ReturnType,
     body: Block,
+    pub restart: bool,
 }
 
 impl Parse for SyscallFn {
@@ -30,6 +31,7 @@ impl Parse for SyscallFn {
             args,
             output,
             body,
+            restart: false,
         })
     }
 }
@@ -43,6 +45,7 @@ impl ToTokens for SyscallFn {
             args,
             output,
             body,
+            restart,
         } = self;
         let ty = match output {
             ReturnType::Default => parse_quote!(()),
@@ -54,6 +57,22 @@ impl ToTokens for SyscallFn {
         };
         orig.to_tokens(tokens);
 
+        // Check the handler against the signature the callers are generated
+        // from.
+        let arg_tys = self.args.iter().map(|a| match a {
+            FnArg::Typed(PatType { ty, .. }) => ty,
+            _ => panic!("Function only receive typed args"),
+        });
+        let check: ItemConst = parse_quote! {
+            const _: () = sv_call::sig::assert_same(
+                core::marker::PhantomData::<sv_call::sig::#ident>,
+                core::marker::PhantomData::<
+                    fn(#(<#arg_tys as sv_call::Abi>::Abi),*) -> <#ty as sv_call::Abi>::Abi
+                >,
+            );
+        };
+        check.to_tokens(tokens);
+
         let wrapper_ident = format_ident!("wrapper_{}", self.ident);
 
         let wrapper_args = crate::wrap_args(
@@ -89,11 +108,26 @@ impl ToTokens for SyscallFn {
             None,
         );
 
-        let wrapper: ItemFn = parse_quote! {
-            #[no_mangle]
-            extern "C" fn #wrapper_ident (#wrapper_args) -> usize {
-                let ret = #ident (#wrapper_args_into);
-                sv_call::SerdeReg::encode(ret)
+        let wrapper: ItemFn = if *restart {
+            parse_quote! {
+                #[no_mangle]
+                extern "C" fn #wrapper_ident (#wrapper_args) -> usize {
+                    loop {
+                        let ret = sv_call::SerdeReg::encode(#ident (#wrapper_args_into));
+                        if ret != sv_call::EINTR.into_retval() {
+                            break ret;
+                        }
+                        crate::syscall::restart();
+                    }
+                }
+            }
+        } else {
+            parse_quote! {
+                #[no_mangle]
+                extern "C" fn #wrapper_ident (#wrapper_args) -> usize {
+                    let ret = #ident (#wrapper_args_into);
+                    sv_call::SerdeReg::encode(ret)
+                }
             }
         };
         wrapper.to_tokens(tokens);
@@ -9,14 +9,71 @@ pub enum HandleIndex {
     Vdso = 3,
     Bootfs = 4,
     RootVirt = 5,
+    /// Only sent if [`Targs::framebuffer`] is present.
+    Framebuffer = 6,
 
     Len,
 }
 
+/// The maximal length of the boot command line, including the trailing NUL.
+pub const CMDLINE_LEN: usize = 256;
+
+/// The pixels are in the BGRX order.
+pub const FB_FORMAT_BGRX: u32 = 0;
+/// The pixels are in the RGBX order.
+pub const FB_FORMAT_RGBX: u32 = 1;
+
+/// The linear framebuffer left by the boot loader, with 32 bits per pixel.
 #[derive(Debug, Copy, Clone, Default)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    /// The number of pixels in a scan line.
+    pub stride: u32,
+    pub format: u32,
+}
+
+impl Framebuffer {
+    #[inline]
+    pub fn is_present(&self) -> bool {
+        self.width != 0
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub struct Targs {
     pub rsdp: usize,
     pub smbios: usize,
+    /// The NUL-terminated boot command line.
+    pub cmdline: [u8; CMDLINE_LEN],
+    /// The mode of the framebuffer, whose width is 0 if there's none.
+    pub framebuffer: Framebuffer,
+}
+
+impl Targs {
+    /// Iterate over the whitespace-separated options in the command line.
+    pub fn options(&self) -> impl Iterator<Item = &[u8]> {
+        let len = self.cmdline.iter().position(|&b| b == 0);
+        self.cmdline[..len.unwrap_or(CMDLINE_LEN)]
+            .split(u8::is_ascii_whitespace)
+            .filter(|opt| !opt.is_empty())
+    }
+
+    /// Check whether the command line contains the option.
+    pub fn has_option(&self, option: &str) -> bool {
+        self.options().any(|opt| opt == option.as_bytes())
+    }
+}
+
+impl Default for Targs {
+    fn default() -> Self {
+        Targs {
+            rsdp: 0,
+            smbios: 0,
+            cmdline: [0; CMDLINE_LEN],
+            framebuffer: Framebuffer::default(),
+        }
+    }
 }
 
 unsafe impl plain::Plain for Targs {}
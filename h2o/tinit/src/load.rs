@@ -30,11 +30,12 @@ fn load_segs(
             let last = interp.pop();
             assert_eq!(last, Some(0), "Not a valid c string");
 
-            let data = bootfs.find(&interp, b'/').ok_or(ENOENT).inspect_err(|_| {
-                log::error!("Failed to find the interpreter for the executable")
-            })?;
+            let data = bootfs
+                .find_entry(&interp, b'/')
+                .ok_or(ENOENT)
+                .inspect_err(|_| log::error!("Failed to find the interpreter for the executable"))?;
 
-            crate::sub_phys(data, bootfs, bootfs_phys)?
+            crate::file_phys(data, bootfs, bootfs_phys)?
         }
         Ok(None) => panic!("Executables cannot be directly executed"),
         Err(err) => return Err(Error::Load(err)),
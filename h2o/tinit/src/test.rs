@@ -1,13 +1,19 @@
 use solvent::prelude::Virt;
 
+mod fault;
+mod hdl;
 mod ipc;
 mod mem;
 mod task;
 mod time;
+mod user;
 
 pub unsafe fn test_syscall(virt: &Virt) {
     let stack = task::test(virt);
     ipc::test(virt, stack);
     mem::test(virt);
     time::test();
+    hdl::test();
+    fault::test();
+    user::test(virt);
 }
@@ -0,0 +1,111 @@
+use sv_call::{
+    ipc::RawPacket,
+    mem::PAGE_SIZE,
+    task::{FAULT_ALLOC, FAULT_HANDLE, FAULT_SEND, FAULT_SYSCALL},
+    *,
+};
+
+/// Set a rule for all the user tasks, where `nth` of 0 removes it.
+unsafe fn set(ty: u32, num: usize, nth: u32, error: Error) -> Result {
+    sv_fault_set(Handle::NULL, ty, num, nth, error.into_retval()).into_res()
+}
+
+pub unsafe fn test() {
+    if set(FAULT_HANDLE, 0, 0, ENOMEM) == Err(ENOSYS) {
+        log::debug!("Fault injection is not enabled, skipping");
+        return;
+    }
+
+    handle();
+    send();
+    alloc();
+    syscall();
+
+    log::info!("fault injection result: ok");
+}
+
+/// Every 3rd handle insertion fails, and the handle table stays consistent.
+unsafe fn handle() {
+    set(FAULT_HANDLE, 0, 3, ENOMEM).expect("Failed to set the rule");
+    let h1 = sv_int_new(1).into_res().expect("Failed to create integer");
+    let h2 = sv_int_new(2).into_res().expect("Failed to create integer");
+    assert_eq!(sv_int_new(3).into_res(), Err(ENOMEM));
+    let h4 = sv_int_new(4).into_res().expect("Failed to create integer");
+    set(FAULT_HANDLE, 0, 0, ENOMEM).expect("Failed to remove the rule");
+
+    assert_eq!(sv_int_get(h1).into_res(), Ok(1));
+    assert_eq!(sv_int_get(h2).into_res(), Ok(2));
+    assert_eq!(sv_int_get(h4).into_res(), Ok(4));
+    for hdl in [h1, h2, h4] {
+        sv_obj_drop(hdl).into_res().expect("Failed to drop integer");
+    }
+}
+
+/// A failed send doesn't enqueue the packet.
+unsafe fn send() {
+    let mut c1 = Handle::NULL;
+    let mut c2 = Handle::NULL;
+    sv_chan_new(&mut c1, &mut c2)
+        .into_res()
+        .expect("Failed to create a channel");
+
+    let mut buf = [1u8, 2, 3];
+    let packet = |buf: &mut [u8]| RawPacket {
+        id: 0,
+        handles: [].as_mut_ptr(),
+        handle_count: 0,
+        handle_cap: 0,
+        buffer: buf.as_mut_ptr(),
+        buffer_size: buf.len(),
+        buffer_cap: buf.len(),
+    };
+
+    set(FAULT_SEND, 0, 1, ENOSPC).expect("Failed to set the rule");
+    let ret = sv_chan_send(c1, &packet(&mut buf));
+    assert_eq!(ret.into_res(), Err(ENOSPC));
+    set(FAULT_SEND, 0, 0, ENOSPC).expect("Failed to remove the rule");
+
+    let mut receivee = packet(&mut buf);
+    assert_eq!(sv_chan_recv(c2, &mut receivee).into_res(), Err(ENOENT));
+
+    sv_chan_send(c1, &packet(&mut buf))
+        .into_res()
+        .expect("Failed to send a packet into the channel");
+    let mut receivee = packet(&mut buf);
+    sv_chan_recv(c2, &mut receivee)
+        .into_res()
+        .expect("Failed to receive a packet from the channel");
+
+    for hdl in [c1, c2] {
+        sv_obj_drop(hdl).into_res().expect("Failed to drop channel");
+    }
+}
+
+/// Every 2nd allocation fails.
+unsafe fn alloc() {
+    set(FAULT_ALLOC, 0, 2, ENOMEM).expect("Failed to set the rule");
+    let phys = sv_phys_alloc(PAGE_SIZE, Default::default())
+        .into_res()
+        .expect("Failed to allocate memory");
+    let ret = sv_phys_alloc(PAGE_SIZE, Default::default());
+    assert_eq!(ret.into_res(), Err(ENOMEM));
+    set(FAULT_ALLOC, 0, 0, ENOMEM).expect("Failed to remove the rule");
+
+    sv_obj_drop(phys).into_res().expect("Failed to drop memory");
+}
+
+/// Syscalls can be failed as a whole, except for the one removing the rules.
+unsafe fn syscall() {
+    let int = sv_int_new(6).into_res().expect("Failed to create integer");
+
+    set(FAULT_SYSCALL, SV_INT_GET, 1, EIO).expect("Failed to set the rule");
+    assert_eq!(sv_int_get(int).into_res(), Err(EIO));
+    assert_eq!(sv_int_get(int).into_res(), Err(EIO));
+    set(FAULT_SYSCALL, SV_INT_GET, 0, EIO).expect("Failed to remove the rule");
+    assert_eq!(sv_int_get(int).into_res(), Ok(6));
+
+    let ret = set(FAULT_SYSCALL, SV_FAULT_SET, 1, EIO);
+    assert_eq!(ret, Err(EINVAL));
+
+    sv_obj_drop(int).into_res().expect("Failed to drop integer");
+}
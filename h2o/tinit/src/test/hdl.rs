@@ -0,0 +1,53 @@
+use alloc::vec::Vec;
+
+use solvent::prelude::Instant;
+use sv_call::*;
+
+const BENCH_ROUNDS: u32 = 100000;
+const BENCH_HANDLES: u64 = 4096;
+
+pub unsafe fn test() {
+    stale();
+    bench(1);
+    bench(BENCH_HANDLES);
+}
+
+/// Handles of dropped objects must stay invalid even if their slots are
+/// reused.
+unsafe fn stale() {
+    let old = sv_int_new(1).into_res().expect("Failed to create integer");
+    sv_obj_drop(old).into_res().expect("Failed to drop integer");
+
+    let new = sv_int_new(2).into_res().expect("Failed to create integer");
+    assert_ne!(old, new);
+    assert_eq!(sv_int_get(old).into_res(), Err(EINVAL));
+    assert_eq!(sv_obj_drop(old).into_res(), Err(EINVAL));
+    assert_eq!(sv_int_get(new).into_res(), Ok(2));
+    sv_obj_drop(new).into_res().expect("Failed to drop integer");
+}
+
+/// Measure the lookup throughput with `count` handles in the table.
+unsafe fn bench(count: u64) {
+    let handles = (0..count)
+        .map(|value| {
+            sv_int_new(value)
+                .into_res()
+                .expect("Failed to create integer")
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    for round in 0..BENCH_ROUNDS {
+        let hdl = handles[round as usize % handles.len()];
+        assert!(sv_int_get(hdl).into_res().is_ok());
+    }
+    let elapsed = start.elapsed();
+    log::info!(
+        "Handle lookup with {count} handles: {:?}/op",
+        elapsed / BENCH_ROUNDS
+    );
+
+    for hdl in handles {
+        sv_obj_drop(hdl).into_res().expect("Failed to drop integer");
+    }
+}
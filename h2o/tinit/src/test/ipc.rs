@@ -1,7 +1,11 @@
 use core::ptr::{self, NonNull};
 
-use solvent::prelude::Virt;
-use sv_call::{ipc::*, task::DEFAULT_STACK_SIZE, *};
+use solvent::prelude::{Flags, Instant, Object, Phys, PhysOptions, Virt, PAGE_SIZE};
+use sv_call::{
+    ipc::*,
+    task::{DEFAULT_STACK_SIZE, TASK_PRIO_HIGH, TASK_PRIO_LOW, TASK_PRIO_NORMAL},
+    *,
+};
 
 pub unsafe fn test(virt: &Virt, stack: (*mut u8, *mut u8, Handle)) {
     #[inline]
@@ -93,6 +97,84 @@ pub unsafe fn test(virt: &Virt, stack: (*mut u8, *mut u8, Handle)) {
         e
     };
 
+    // Flow control.
+    {
+        let credit = ChanCredit {
+            packets: 2,
+            bytes: MAX_BUFFER_SIZE,
+        };
+        let mut f1 = Handle::NULL;
+        let mut f2 = Handle::NULL;
+        let ret = sv_chan_new_with(
+            ChanOptions::FLOW_CONTROL,
+            &ChanCredit::default(),
+            &mut f1,
+            &mut f2,
+        );
+        assert_eq!(ret.into_res(), Err(EINVAL));
+        sv_chan_new_with(ChanOptions::FLOW_CONTROL, &credit, &mut f1, &mut f2)
+            .into_res()
+            .expect("Failed to create a flow-controlled channel");
+
+        let mut buf = [1u8, 2, 3, 4, 5, 6, 7];
+        let sendee = rp(0, &mut [], &mut buf);
+        for _ in 0..credit.packets {
+            sv_obj_wait(f1, 0, true, false, SIG_WRITE)
+                .into_res()
+                .expect("The credit should be available");
+            sv_chan_send(f1, &sendee)
+                .into_res()
+                .expect("Failed to send a packet into the channel");
+        }
+        let ret = sv_chan_send(f1, &sendee);
+        assert_eq!(ret.into_res(), Err(EAGAIN));
+
+        let mut receivee = rp(0, &mut [], &mut buf);
+        sv_chan_recv(f2, &mut receivee)
+            .into_res()
+            .expect("Failed to receive a packet from the channel");
+        sv_obj_wait(f1, 0, true, false, SIG_WRITE)
+            .into_res()
+            .expect("The credit should be replenished");
+        sv_chan_send(f1, &sendee)
+            .into_res()
+            .expect("Failed to send a packet into the channel");
+
+        // Statistics.
+        let mut i1 = ChanInfo::default();
+        let mut i2 = ChanInfo::default();
+        sv_chan_info(f1, &mut i1)
+            .into_res()
+            .expect("Failed to get the channel info");
+        sv_chan_info(f2, &mut i2)
+            .into_res()
+            .expect("Failed to get the channel info");
+        assert_eq!(i1.peer_id, i2.peer_id);
+        assert_eq!((i1.msgs_sent, i1.bytes_sent), (3, 3 * buf.len() as u64));
+        assert_eq!((i2.msgs_received, i2.bytes_received), (1, buf.len() as u64));
+        assert_eq!((i2.queue_len, i2.peak_queue_len), (2, 2));
+        assert_eq!(i2.peer_closed, 0);
+
+        // Peer identity.
+        let mut id = ChanPeerId::default();
+        sv_chan_peer_id(f2, &mut id)
+            .into_res()
+            .expect("Failed to get the peer identity");
+        assert_ne!(id.creator.task, 0);
+        assert_eq!(id.creator, id.holder);
+
+        sv_obj_drop(f1)
+            .into_res()
+            .expect("Failed to drop the channel");
+        sv_chan_info(f2, &mut i2)
+            .into_res()
+            .expect("Failed to get the channel info");
+        assert_ne!(i2.peer_closed, 0);
+        sv_obj_drop(f2)
+            .into_res()
+            .expect("Failed to drop the channel");
+    }
+
     // Multiple tasks.
     {
         const MSG_ID: usize = 123;
@@ -170,7 +252,7 @@ pub unsafe fn test(virt: &Virt, stack: (*mut u8, *mut u8, Handle)) {
             .expect("Failed to drop the event in master");
 
         let mut retval = Default::default();
-        sv_obj_wait(other, u64::MAX, true, false, SIG_READ)
+        sv_obj_wait(other, u64::MAX, true, false, SIG_GENERIC)
             .into_res()
             .expect("Failed to wait for the task");
         sv_task_join(other, &mut retval)
@@ -178,9 +260,517 @@ pub unsafe fn test(virt: &Virt, stack: (*mut u8, *mut u8, Handle)) {
             .expect("Failed to join the task");
     }
 
+    round_trip(stack.0);
+    inherit(stack.0);
+
     virt.unmap(NonNull::new_unchecked(stack.1), DEFAULT_STACK_SIZE, false)
         .expect("Failed to unmap the memory");
     sv_obj_drop(stack.2)
         .into_res()
         .expect("Failed to deallocate the stack memory");
+
+    dispatcher();
+    wait_match();
+    vectored();
+    zero_copy(virt);
+}
+
+/// The latency of request-reply round trips to another task, with and without
+/// directed yields.
+unsafe fn round_trip(stack: *mut u8) {
+    const ROUNDS: u32 = 1000;
+    const STOP_ID: usize = usize::MAX;
+
+    unsafe extern "C" fn echo(init_chan: Handle) {
+        let mut buf = [0; 8];
+        loop {
+            let mut p = RawPacket {
+                id: 0,
+                handles: ptr::null_mut(),
+                handle_count: 0,
+                handle_cap: 0,
+                buffer: buf.as_mut_ptr(),
+                buffer_size: buf.len(),
+                buffer_cap: buf.len(),
+            };
+            sv_obj_wait(init_chan, u64::MAX, true, false, SIG_READ)
+                .into_res()
+                .expect("Failed to wait for the channel");
+            sv_chan_recv(init_chan, &mut p)
+                .into_res()
+                .expect("Failed to receive the request");
+            if p.id == STOP_ID {
+                break;
+            }
+            sv_chan_send(init_chan, &p)
+                .into_res()
+                .expect("Failed to send the reply");
+        }
+        sv_task_exit(0, false)
+            .into_res()
+            .expect("Failed to exit the task");
+    }
+
+    let (mut c1, mut c2) = (Handle::NULL, Handle::NULL);
+    sv_chan_new(&mut c1, &mut c2)
+        .into_res()
+        .expect("Failed to create a channel");
+    let ci = sv_call::task::ExecInfo {
+        name: ptr::null_mut(),
+        name_len: 0,
+        space: Handle::NULL,
+        entry: echo as *mut u8,
+        stack,
+        init_chan: c2,
+        arg: 0,
+    };
+    let other = sv_task_exec(&ci)
+        .into_res()
+        .expect("Failed to create the echo task");
+
+    let mut buf = [0u8; 8];
+    let mut call = |id: usize, yield_to: bool| {
+        let mut p = RawPacket {
+            id,
+            handles: ptr::null_mut(),
+            handle_count: 0,
+            handle_cap: 0,
+            buffer: buf.as_mut_ptr(),
+            buffer_size: buf.len(),
+            buffer_cap: buf.len(),
+        };
+        if yield_to {
+            sv_task_yield_to(other)
+                .into_res()
+                .expect("Failed to yield to the echo task");
+        }
+        sv_chan_send(c1, &p)
+            .into_res()
+            .expect("Failed to send the request");
+        if id == STOP_ID {
+            return;
+        }
+        sv_obj_wait(c1, u64::MAX, true, false, SIG_READ)
+            .into_res()
+            .expect("Failed to wait for the channel");
+        sv_chan_recv(c1, &mut p)
+            .into_res()
+            .expect("Failed to receive the reply");
+        assert_eq!(p.id, id);
+    };
+
+    for yield_to in [false, true] {
+        let start = Instant::now();
+        (1..=ROUNDS as usize).for_each(|id| call(id, yield_to));
+        let elapsed = start.elapsed();
+        log::info!(
+            "IPC round trip (directed yield: {yield_to}): {:?}/op",
+            elapsed / ROUNDS
+        );
+    }
+    call(STOP_ID, false);
+
+    let mut retval = Default::default();
+    sv_obj_wait(other, u64::MAX, true, false, SIG_GENERIC)
+        .into_res()
+        .expect("Failed to wait for the task");
+    sv_task_join(other, &mut retval)
+        .into_res()
+        .expect("Failed to join the task");
+    sv_obj_drop(c1)
+        .into_res()
+        .expect("Failed to drop the channel");
+}
+
+/// A caller blocked in a call donates its priority to the task serving the
+/// channel until the call returns.
+unsafe fn inherit(stack: *mut u8) {
+    const STOP_ID: usize = usize::MAX;
+
+    unsafe extern "C" fn server(init_chan: Handle) {
+        sv_task_set_priority(Handle::NULL, TASK_PRIO_LOW)
+            .into_res()
+            .expect("Failed to set the priority");
+        loop {
+            let mut p = RawPacket {
+                id: 0,
+                handles: ptr::null_mut(),
+                handle_count: 0,
+                handle_cap: 0,
+                buffer: ptr::null_mut(),
+                buffer_size: 0,
+                buffer_cap: 0,
+            };
+            match sv_chan_recv(init_chan, &mut p).into_res() {
+                Err(ENOENT) => {
+                    sv_obj_wait(init_chan, u64::MAX, true, false, SIG_READ)
+                        .into_res()
+                        .expect("Failed to wait for the channel");
+                    continue;
+                }
+                res => res.expect("Failed to receive the request"),
+            }
+            if p.id == STOP_ID {
+                break;
+            }
+            // Reply with the priority serving the request.
+            p.id = sv_task_priority(Handle::NULL)
+                .into_res()
+                .expect("Failed to get the priority");
+            sv_chan_send(init_chan, &p)
+                .into_res()
+                .expect("Failed to send the reply");
+        }
+        sv_task_exit(0, false)
+            .into_res()
+            .expect("Failed to exit the task");
+    }
+
+    let ret = sv_task_set_priority(Handle::NULL, TASK_PRIO_HIGH + 1);
+    assert_eq!(ret.into_res(), Err(EINVAL));
+    // Tasks can't be raised above the priority of the caller.
+    let ret = sv_task_set_priority(Handle::NULL, TASK_PRIO_HIGH);
+    assert_eq!(ret.into_res(), Err(EPERM));
+
+    let (mut c1, mut c2) = (Handle::NULL, Handle::NULL);
+    sv_chan_new(&mut c1, &mut c2)
+        .into_res()
+        .expect("Failed to create a channel");
+    let ci = sv_call::task::ExecInfo {
+        name: ptr::null_mut(),
+        name_len: 0,
+        space: Handle::NULL,
+        entry: server as *mut u8,
+        stack,
+        init_chan: c2,
+        arg: 0,
+    };
+    let other = sv_task_exec(&ci)
+        .into_res()
+        .expect("Failed to create the server task");
+
+    let call = |id: usize| {
+        let send = RawPacket {
+            id,
+            handles: ptr::null_mut(),
+            handle_count: 0,
+            handle_cap: 0,
+            buffer: ptr::null_mut(),
+            buffer_size: 0,
+            buffer_cap: 0,
+        };
+        let mut recv = send;
+        sv_chan_send_recv(c1, &send, Handle::NULL, &mut recv, u64::MAX)
+            .into_res()
+            .expect("Failed to call the server");
+        recv.id
+    };
+    // The server is only known once it has received from the channel.
+    call(1);
+    assert_eq!(call(2), TASK_PRIO_NORMAL as usize);
+    // The donation is withdrawn once the call returns.
+    let priority = sv_task_priority(other)
+        .into_res()
+        .expect("Failed to get the priority");
+    assert_eq!(priority, TASK_PRIO_LOW as usize);
+
+    let stop = RawPacket {
+        id: STOP_ID,
+        handles: ptr::null_mut(),
+        handle_count: 0,
+        handle_cap: 0,
+        buffer: ptr::null_mut(),
+        buffer_size: 0,
+        buffer_cap: 0,
+    };
+    sv_chan_send(c1, &stop)
+        .into_res()
+        .expect("Failed to stop the server");
+
+    let mut retval = Default::default();
+    sv_obj_wait(other, u64::MAX, true, false, SIG_GENERIC)
+        .into_res()
+        .expect("Failed to wait for the task");
+    sv_task_join(other, &mut retval)
+        .into_res()
+        .expect("Failed to join the task");
+    sv_obj_drop(c1)
+        .into_res()
+        .expect("Failed to drop the channel");
+}
+
+/// Moving the pages of large packets.
+unsafe fn zero_copy(virt: &Virt) {
+    const SIZE: usize = PAGE_SIZE + 100;
+    let flags = Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS;
+
+    let phys = Phys::allocate(2 * PAGE_SIZE, PhysOptions::ZEROED).expect("Failed to allocate");
+    let pages = virt
+        .map_phys(None, phys.clone(), flags)
+        .expect("Failed to map the pages");
+    let buf = pages.as_mut_ptr();
+    (0..SIZE).for_each(|i| buf.add(i).write(i as u8));
+
+    let mut packet = RawPacket {
+        id: 0,
+        handles: ptr::null_mut(),
+        handle_count: 0,
+        handle_cap: 0,
+        buffer: buf,
+        buffer_size: SIZE,
+        buffer_cap: SIZE,
+    };
+
+    // Large packets can't be sent through ordinary channels.
+    let (mut c1, mut c2) = (Handle::NULL, Handle::NULL);
+    sv_chan_new(&mut c1, &mut c2)
+        .into_res()
+        .expect("Failed to create a channel");
+    assert_eq!(sv_chan_send(c1, &packet).into_res(), Err(ENOMEM));
+    sv_obj_drop(c1)
+        .into_res()
+        .expect("Failed to drop the channel");
+    sv_obj_drop(c2)
+        .into_res()
+        .expect("Failed to drop the channel");
+
+    let credit = ChanCredit::default();
+    sv_chan_new_with(ChanOptions::ZERO_COPY, &credit, &mut c1, &mut c2)
+        .into_res()
+        .expect("Failed to create a channel");
+
+    // The pages can only be moved from the start of their mapping.
+    let inner = RawPacket {
+        buffer: buf.add(PAGE_SIZE),
+        ..packet
+    };
+    assert_eq!(sv_chan_send(c1, &inner).into_res(), Err(ERANGE));
+    // The pages can't be moved while someone else refers to them.
+    assert_eq!(sv_chan_send(c1, &packet).into_res(), Err(EPERM));
+    drop(phys);
+    sv_chan_send(c1, &packet)
+        .into_res()
+        .expect("Failed to send the pages");
+    // The sender loses its mapping.
+    assert_eq!(sv_chan_send(c1, &packet).into_res(), Err(ENOENT));
+
+    let mut hdl = [Handle::NULL];
+    let mut small = [0u8; 8];
+    let mut recv = RawPacket {
+        handles: hdl.as_mut_ptr(),
+        handle_count: 1,
+        handle_cap: 1,
+        buffer: small.as_mut_ptr(),
+        buffer_size: small.len(),
+        buffer_cap: small.len(),
+        ..packet
+    };
+    sv_chan_recv(c2, &mut recv)
+        .into_res()
+        .expect("Failed to receive the pages");
+    assert_eq!(recv.buffer_size, SIZE);
+    assert_eq!(recv.handle_count, 1);
+
+    let phys = Phys::from_raw(hdl[0]);
+    let data = phys
+        .read(PAGE_SIZE - 2, 4)
+        .expect("Failed to read the pages");
+    let expected = [PAGE_SIZE - 2, PAGE_SIZE - 1, PAGE_SIZE, PAGE_SIZE + 1].map(|i| i as u8);
+    assert_eq!(data, expected);
+
+    sv_obj_drop(c1)
+        .into_res()
+        .expect("Failed to drop the channel");
+    sv_obj_drop(c2)
+        .into_res()
+        .expect("Failed to drop the channel");
+
+    // The moved pages are charged for flow control by the size of the payload.
+    let credit = ChanCredit {
+        packets: 4,
+        bytes: 2 * PAGE_SIZE,
+    };
+    let options = ChanOptions::ZERO_COPY | ChanOptions::FLOW_CONTROL;
+    sv_chan_new_with(options, &credit, &mut c1, &mut c2)
+        .into_res()
+        .expect("Failed to create a channel");
+
+    let phys = Phys::allocate(2 * PAGE_SIZE, PhysOptions::ZEROED).expect("Failed to allocate");
+    let pages = virt
+        .map_phys(None, phys, flags)
+        .expect("Failed to map the pages");
+    let small = RawPacket {
+        buffer: pages.as_mut_ptr(),
+        buffer_size: PAGE_SIZE,
+        buffer_cap: PAGE_SIZE,
+        ..packet
+    };
+    sv_chan_send(c1, &small)
+        .into_res()
+        .expect("Failed to send the packet");
+    let large = RawPacket {
+        buffer: pages.as_mut_ptr(),
+        ..packet
+    };
+    assert_eq!(sv_chan_send(c1, &large).into_res(), Err(EAGAIN));
+
+    let mut recv = small;
+    sv_chan_recv(c2, &mut recv)
+        .into_res()
+        .expect("Failed to receive the packet");
+    sv_chan_send(c1, &large)
+        .into_res()
+        .expect("Failed to send the pages");
+
+    sv_obj_drop(c1)
+        .into_res()
+        .expect("Failed to drop the channel");
+    sv_obj_drop(c2)
+        .into_res()
+        .expect("Failed to drop the channel");
+}
+
+/// The trigger semantics of dispatcher requests.
+unsafe fn dispatcher() {
+    let event = sv_event_new(SIG_GENERIC)
+        .into_res()
+        .expect("Failed to create an event");
+    let disp = sv_disp_new(4)
+        .into_res()
+        .expect("Failed to create a dispatcher");
+    let pop = || -> Result<(usize, usize)> {
+        let mut signal = 0;
+        let key = sv_disp_pop(disp, &mut signal, ptr::null_mut()).into_res()?;
+        Ok((key as usize, signal))
+    };
+
+    // Level-triggered requests see the signal present before arming.
+    let k1 = sv_disp_push(disp, event, true, SIG_GENERIC, ptr::null())
+        .into_res()
+        .expect("Failed to push a request") as usize;
+    assert_eq!(pop(), Ok((k1, SIG_GENERIC)));
+
+    // Edge-triggered ones don't, but can take a snapshot instead.
+    let k2 = sv_disp_push(disp, event, false, SIG_GENERIC, ptr::null())
+        .into_res()
+        .expect("Failed to push a request") as usize;
+    assert_eq!(pop(), Err(ENOENT));
+    let mut snapshot = 0;
+    sv_disp_update(disp, k2, SIG_GENERIC, &mut snapshot)
+        .into_res()
+        .expect("Failed to take a snapshot");
+    assert_eq!(snapshot, SIG_GENERIC);
+
+    // Clearing bits is not an edge, but setting them again is.
+    sv_event_notify(event, SIG_GENERIC, 0)
+        .into_res()
+        .expect("Failed to notify the event");
+    assert_eq!(pop(), Err(ENOENT));
+    sv_event_notify(event, 0, SIG_GENERIC)
+        .into_res()
+        .expect("Failed to notify the event");
+    assert_eq!(pop(), Ok((k2, SIG_GENERIC)));
+    let ret = sv_disp_update(disp, k2, SIG_GENERIC, ptr::null_mut());
+    assert_eq!(ret.into_res(), Err(ENOENT));
+
+    // Changing the signal of a level-triggered request rechecks it at once.
+    let k3 = sv_disp_push(disp, event, true, SIG_READ, ptr::null())
+        .into_res()
+        .expect("Failed to push a request") as usize;
+    assert_eq!(pop(), Err(ENOENT));
+    sv_disp_update(disp, k3, SIG_GENERIC, ptr::null_mut())
+        .into_res()
+        .expect("Failed to update the request");
+    assert_eq!(pop(), Ok((k3, SIG_GENERIC)));
+
+    sv_obj_drop(disp)
+        .into_res()
+        .expect("Failed to drop the dispatcher");
+    sv_obj_drop(event)
+        .into_res()
+        .expect("Failed to drop the event");
+}
+
+/// The matching modes and consumption of `sv_obj_wait_match`.
+unsafe fn wait_match() {
+    let event = sv_event_new(SIG_READ | SIG_WRITE)
+        .into_res()
+        .expect("Failed to create an event");
+    let wait = |options: WaitOptions, signal: usize| {
+        let options = options | WaitOptions::LEVEL_TRIGGERED | WaitOptions::WAKE_ALL;
+        let ret = sv_obj_wait_match(event, 0, options, signal).into_res();
+        ret.map(|signal| signal as usize)
+    };
+
+    assert_eq!(wait(WaitOptions::empty(), SIG_READ | SIG_TIMER), Err(ETIME));
+    let ret = wait(WaitOptions::MATCH_ANY, SIG_READ | SIG_TIMER);
+    assert_eq!(ret, Ok(SIG_READ | SIG_WRITE));
+    assert_eq!(wait(WaitOptions::MATCH_EXACT, SIG_READ), Err(ETIME));
+    let ret = wait(WaitOptions::MATCH_EXACT, SIG_READ | SIG_WRITE);
+    assert_eq!(ret, Ok(SIG_READ | SIG_WRITE));
+    let ret = wait(WaitOptions::MATCH_ANY | WaitOptions::MATCH_EXACT, SIG_READ);
+    assert_eq!(ret, Err(EINVAL));
+
+    // Only the first consumer observes the bits.
+    let ret = wait(WaitOptions::CONSUME, SIG_WRITE);
+    assert_eq!(ret, Ok(SIG_READ | SIG_WRITE));
+    assert_eq!(wait(WaitOptions::CONSUME, SIG_WRITE), Err(ETIME));
+    let ret = sv_event_notify(event, 0, 0).into_res();
+    assert_eq!(ret, Ok(SIG_READ as u64));
+
+    sv_obj_drop(event)
+        .into_res()
+        .expect("Failed to drop the event");
+}
+
+/// Gathering on `sv_chan_sendv` and scattering on `sv_chan_recvv`.
+unsafe fn vectored() {
+    let seg = |buf: &mut [u8]| RawSegment {
+        ptr: buf.as_mut_ptr(),
+        len: buf.len(),
+    };
+    let packet = |id| RawPacket {
+        id,
+        handles: ptr::null_mut(),
+        handle_count: 0,
+        handle_cap: 0,
+        buffer: ptr::null_mut(),
+        buffer_size: 0,
+        buffer_cap: 0,
+    };
+
+    let mut c1 = Handle::NULL;
+    let mut c2 = Handle::NULL;
+    sv_chan_new(&mut c1, &mut c2)
+        .into_res()
+        .expect("Failed to create a channel");
+
+    let (mut header, mut payload) = ([1u8, 2], [3u8, 4, 5, 6, 7]);
+    let segments = [seg(&mut header), seg(&mut []), seg(&mut payload)];
+    let ret = sv_chan_sendv(c1, &packet(1), segments.as_ptr(), MAX_SEGMENT_COUNT + 1);
+    assert_eq!(ret.into_res(), Err(EINVAL));
+    sv_chan_sendv(c1, &packet(1), segments.as_ptr(), segments.len())
+        .into_res()
+        .expect("Failed to send a gathered packet");
+
+    let (mut a, mut b, mut c) = ([0u8; 3], [0u8; 3], [0u8; 4]);
+    let segments = [seg(&mut a[..2]), seg(&mut b[..2])];
+    let mut receivee = packet(0);
+    let ret = sv_chan_recvv(c2, &mut receivee, segments.as_ptr(), segments.len());
+    assert_eq!(ret.into_res(), Err(EBUFFER));
+    assert_eq!(receivee.buffer_size, 7);
+
+    let segments = [seg(&mut a), seg(&mut b), seg(&mut c)];
+    sv_chan_recvv(c2, &mut receivee, segments.as_ptr(), segments.len())
+        .into_res()
+        .expect("Failed to receive a scattered packet");
+    assert_eq!((receivee.id, receivee.buffer_size), (1, 7));
+    assert_eq!((a, b, c), ([1, 2, 3], [4, 5, 6], [7, 0, 0, 0]));
+
+    sv_obj_drop(c1)
+        .into_res()
+        .expect("Failed to drop the channel");
+    sv_obj_drop(c2)
+        .into_res()
+        .expect("Failed to drop the channel");
 }
@@ -1,4 +1,4 @@
-use solvent::prelude::{Flags, Phys, PhysOptions, Virt, PAGE_LAYOUT, PAGE_SIZE};
+use solvent::prelude::{Flags, Phys, PhysOptions, Virt, EINVAL, ERANGE, PAGE_LAYOUT, PAGE_SIZE};
 
 pub unsafe fn test(virt: &Virt) {
     let sub = virt
@@ -14,7 +14,10 @@ pub unsafe fn test(virt: &Virt) {
             Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS,
         )
         .expect("Failed to map memory");
+    // The page is backed by the zero page until written.
+    assert_eq!(unsafe { ptr.as_mut_ptr().read() }, 0);
     unsafe { ptr.as_mut_ptr().write(0x64) };
+    assert_eq!(unsafe { ptr.as_mut_ptr().read() }, 0x64);
     sub.destroy().expect("Failed to destroy sub-virt");
     let buf = phys.read(0, 1).expect("Failed to read memory");
     assert_eq!(&buf, &[0x64]);
@@ -25,4 +28,111 @@ pub unsafe fn test(virt: &Virt) {
     phys.resize(4, true).expect("Failed to resize the phys");
     let buf = phys.read(1, 10).expect("Failed to read from phys");
     assert_eq!(&buf, &[0, 1, 2]);
+
+    replace(virt);
+    demand(virt);
+    shared(virt);
+    stat();
+}
+
+/// Mapping a lazy zeroed object more than once.
+unsafe fn shared(virt: &Virt) {
+    let phys = Phys::allocate(PAGE_SIZE, PhysOptions::ZEROED | PhysOptions::RESIZABLE)
+        .expect("Failed to allocate memory");
+    let flags = Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS;
+    let map = || {
+        virt.map(None, phys.clone(), 0, PAGE_LAYOUT, flags)
+            .expect("Failed to map memory")
+    };
+    let (p1, p2) = (map(), map());
+
+    // The first mapping must not keep reading the zero page after the second
+    // one commits the page.
+    assert_eq!(unsafe { p1.as_mut_ptr().read() }, 0);
+    unsafe { p2.as_mut_ptr().write(0x64) };
+    assert_eq!(unsafe { p1.as_mut_ptr().read() }, 0x64);
+
+    // The page is lent the zero page by the first mapping, so unmapping it
+    // must not drop the pin of the second one committing the page.
+    virt.unmap(p1.cast(), PAGE_SIZE, false)
+        .expect("Failed to unmap memory");
+    assert!(phys.resize(PAGE_SIZE * 2, true).is_err());
+
+    virt.unmap(p2.cast(), PAGE_SIZE, false)
+        .expect("Failed to unmap memory");
+    phys.resize(PAGE_SIZE * 2, true)
+        .expect("Failed to resize the phys");
+}
+
+fn stat() {
+    let stat = solvent::mem::mem_stat().expect("Failed to sample the space");
+    log::debug!("Memory stat: {stat:?}");
+    assert!(stat.mapped > 0);
+    assert!(stat.dirty <= stat.mapped);
+    assert!(stat.working_set <= stat.mapped);
+    assert!(stat.committed <= stat.phys);
+    // At least the kernel stack of the current task.
+    assert!(stat.kstack > 0);
+}
+
+unsafe fn demand(virt: &Virt) {
+    const PAGES: usize = 4096;
+    let layout = unsafe { Virt::page_aligned(PAGE_SIZE * PAGES) };
+    let phys =
+        Phys::allocate(PAGE_SIZE * PAGES, PhysOptions::DEMAND).expect("Failed to allocate memory");
+    let mapped = || solvent::mem::mem_stat().expect("Failed to sample").mapped;
+
+    let before = mapped();
+    let ptr = virt
+        .map(
+            None,
+            phys.clone(),
+            0,
+            layout,
+            Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS,
+        )
+        .expect("Failed to map memory");
+    // Nothing is mapped until accessed.
+    assert!(mapped() < before + PAGES);
+
+    let base = ptr.as_mut_ptr();
+    assert_eq!(unsafe { base.add(PAGE_SIZE * 7).read() }, 0);
+    unsafe { base.add(PAGE_SIZE * 7).write(0x64) };
+    unsafe { base.add(PAGE_SIZE * 100 + 1).write(0x65) };
+    assert_eq!(unsafe { base.add(PAGE_SIZE * 100 + 1).read() }, 0x65);
+    assert!(mapped() < before + PAGES);
+
+    virt.unmap(ptr.cast(), layout.size(), false)
+        .expect("Failed to unmap memory");
+    let buf = phys.read(PAGE_SIZE * 7, 1).expect("Failed to read memory");
+    assert_eq!(&buf, &[0x64]);
+}
+
+unsafe fn replace(virt: &Virt) {
+    let flags = Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS;
+    let layout = unsafe { Virt::page_aligned(PAGE_SIZE * 3) };
+    let sub = virt
+        .allocate(None, layout)
+        .expect("Failed to allocate sub-virt");
+    let layout = unsafe { Virt::page_aligned(PAGE_SIZE * 2) };
+    let old =
+        Phys::allocate(PAGE_SIZE * 2, PhysOptions::ZEROED).expect("Failed to allocate memory");
+    sub.map(Some(PAGE_SIZE), old, 0, layout, flags)
+        .expect("Failed to map memory");
+
+    let new =
+        Phys::allocate(PAGE_SIZE * 2, PhysOptions::ZEROED).expect("Failed to allocate memory");
+    unsafe { new.write(PAGE_SIZE, &[2]) }.expect("Failed to write to phys");
+
+    let ret = sub.map(None, new.clone(), 0, layout, flags | Flags::REPLACE);
+    assert_eq!(ret.err(), Some(EINVAL));
+    // The old mapping must lie within the replaced range.
+    let ret = sub.map(Some(0), new.clone(), 0, layout, flags | Flags::REPLACE);
+    assert_eq!(ret.err(), Some(ERANGE));
+
+    let ptr = sub
+        .map(Some(PAGE_SIZE), new, 0, layout, flags | Flags::REPLACE)
+        .expect("Failed to replace the mapping");
+    assert_eq!(unsafe { ptr.as_mut_ptr().add(PAGE_SIZE).read() }, 2);
+    sub.destroy().expect("Failed to destroy sub-virt");
 }
@@ -2,11 +2,13 @@ use core::{
     arch::asm,
     mem::{size_of, MaybeUninit},
     ptr::null_mut,
+    time::Duration,
 };
 
-use solvent::prelude::{Object, Phys, Virt};
+use solvent::prelude::{Channel, Instant, Object, Phys, Virt, PAGE_SIZE};
+use solvent_rpc::packet;
 use sv_call::{
-    ipc::{RawPacket, SIG_READ},
+    ipc::{RawPacket, SIG_GENERIC, SIG_READ},
     mem::Flags,
     task::{
         ctx::{Gpr, GPR_SIZE},
@@ -15,8 +17,10 @@ use sv_call::{
     },
     *,
 };
+use svrt::{HandleInfo, HandleType, StartupArgs};
 
 const PF_ADDR: usize = 0x1598_0000_0000;
+const SPIN_TIME: Duration = Duration::from_millis(100);
 
 unsafe extern "C" fn func(_: Handle, arg: u32) {
     log::trace!("arg = {}", arg);
@@ -34,6 +38,19 @@ unsafe extern "C" fn func(_: Handle, arg: u32) {
             let ptr = PF_ADDR as *mut u64;
             *ptr = 1;
         },
+        2 => {
+            let start = Instant::now();
+            while start.elapsed() < SPIN_TIME {
+                unsafe { asm!("pause") };
+            }
+        }
+        3 => {
+            let res = sv_task_sleep(0).into_res();
+            sv_task_exit(res.map_or_else(Error::into_retval, |_| 0), false)
+                .into_res()
+                .expect("Failed to exit the task");
+        }
+        4 => unsafe { local_slots() },
         _ => {}
     }
     sv_task_exit(12345, false)
@@ -41,25 +58,173 @@ unsafe extern "C" fn func(_: Handle, arg: u32) {
         .expect("Failed to exit the task");
 }
 
+/// Test the task-local slots and the thread pointer in a fresh task.
+unsafe fn local_slots() {
+    for slot in 0..TASK_LOCAL_SLOTS {
+        let mut value = usize::MAX;
+        sv_task_local_get(slot, &mut value)
+            .into_res()
+            .expect("Failed to get the task-local slot");
+        assert_eq!(value, 0);
+        sv_task_local_set(slot, 0x1000 + slot as usize)
+            .into_res()
+            .expect("Failed to set the task-local slot");
+    }
+    for slot in 0..TASK_LOCAL_SLOTS {
+        let mut value = 0;
+        sv_task_local_get(slot, &mut value)
+            .into_res()
+            .expect("Failed to get the task-local slot");
+        assert_eq!(value, 0x1000 + slot as usize);
+    }
+    for slot in [TASK_LOCAL_SLOTS, u32::MAX] {
+        let mut value = 0;
+        let ret = sv_task_local_get(slot, &mut value);
+        assert_eq!(ret.into_res(), Err(ERANGE));
+        assert_eq!(sv_task_local_set(slot, 1).into_res(), Err(ERANGE));
+    }
+
+    // The syscall is the only way to set the thread pointer without FSGSBASE,
+    // so test it regardless. The task exits right after, so the TLS block may
+    // be on the stack.
+    let tls = [0x5a5a_5a5a_usize];
+    let base = tls.as_ptr() as usize;
+    sv_task_set_tls(base)
+        .into_res()
+        .expect("Failed to set the thread pointer");
+    let value: usize;
+    unsafe { asm!("mov {}, fs:[0]", out(reg) value, options(nostack, readonly)) };
+    assert_eq!(value, tls[0]);
+    if solvent::task::has_fsgsbase() {
+        assert_eq!(solvent::task::get_tls(), Some(base));
+    }
+}
+
+unsafe extern "C" fn spawned(init_chan: Handle, _: u32) {
+    let init_chan = unsafe { Channel::from_raw(init_chan) };
+    let mut packet = Default::default();
+    init_chan
+        .receive(&mut packet)
+        .expect("Failed to receive the startup arguments");
+    let mut args: StartupArgs = packet::deserialize(STARTUP_ARGS, &packet, None)
+        .expect("Failed to deserialize the startup arguments");
+    assert_eq!(args.args, b"args");
+    assert_eq!(args.env, b"env");
+    assert_eq!(args.handles.len(), 1);
+    let phys = args
+        .handles
+        .remove(&HandleType::ProgramPhys.into())
+        .expect("Failed to get the transferred handle");
+    sv_obj_drop(phys)
+        .into_res()
+        .expect("Failed to drop the transferred handle");
+    sv_task_exit(12345, false)
+        .into_res()
+        .expect("Failed to exit the task");
+}
+
 unsafe fn join(normal: Handle, fault: Handle) {
     log::trace!("join: normal = {:?}, fault = {:?}", normal, fault);
     let mut ret = Default::default();
 
-    sv_obj_wait(normal, u64::MAX, true, false, SIG_READ)
+    // Every joiner receives the return value.
+    let other = sv_obj_clone(normal)
+        .into_res()
+        .expect("Failed to clone the task handle");
+    for hdl in [normal, other] {
+        sv_obj_wait(hdl, u64::MAX, true, false, SIG_GENERIC)
+            .into_res()
+            .expect("Failed to wait for the task");
+        let retval = sv_task_retval(hdl)
+            .into_res()
+            .expect("Failed to get the return value of the task");
+        assert_eq!(retval, 12345);
+        sv_task_join(hdl, &mut ret)
+            .into_res()
+            .expect("Failed to join the task");
+        assert_eq!(ret, 12345);
+    }
+
+    sv_obj_wait(fault, u64::MAX, true, false, SIG_GENERIC)
         .into_res()
         .expect("Failed to wait for the task");
-    sv_task_join(normal, &mut ret)
+    sv_task_join(fault, &mut ret)
         .into_res()
         .expect("Failed to join the task");
-    assert_eq!(ret, 12345);
+    assert_eq!(Error::try_from_retval(ret), Some(EFAULT));
+}
+
+unsafe fn runtime(task: Handle) {
+    log::trace!("runtime: task = {:?}", task);
+
+    // The task is created in our space, so it belongs to our job.
+    let job = sv_task_job(task)
+        .into_res()
+        .expect("Failed to get the job of the task");
+    let cur = sv_task_job(Handle::NULL)
+        .into_res()
+        .expect("Failed to get the current job");
+    assert_eq!(job, cur);
 
-    sv_obj_wait(fault, u64::MAX, true, false, SIG_READ)
+    let before = sv_task_runtime(task)
+        .into_res()
+        .expect("Failed to get the runtime of the task");
+    sv_obj_wait(task, u64::MAX, true, false, SIG_GENERIC)
         .into_res()
         .expect("Failed to wait for the task");
-    sv_task_join(fault, &mut ret)
+
+    let runtime = sv_task_runtime(task)
+        .into_res()
+        .expect("Failed to get the runtime of the task");
+    // How much of `SPIN_TIME` the task is accounted for depends on how often
+    // it's preempted, so only check that it has run, and for no longer than
+    // the wall time it spins.
+    assert!(
+        runtime > 0 && runtime >= before,
+        "runtime = {runtime}ns, before = {before}ns"
+    );
+    let runtime = Duration::from_nanos(runtime);
+    assert!(
+        runtime <= SPIN_TIME + Duration::from_millis(10),
+        "runtime = {:?}",
+        runtime
+    );
+
+    let mut stat = MaybeUninit::<SchedStat>::uninit();
+    let ret = sv_task_stat(task, 12345, 0, stat.as_mut_ptr());
+    assert_eq!(ret.into_res(), Err(EINVAL));
+    // The spinning task is switched out at least once, when it exits.
+    sv_task_stat(task, TASK_STAT_SLICE_USAGE, 0, stat.as_mut_ptr())
+        .into_res()
+        .expect("Failed to get the statistics of the task");
+    let stat = stat.assume_init();
+    assert!(stat.count >= 1 && stat.p50 <= stat.p99 && stat.p99 <= stat.max);
+
+    let name = b"spinner";
+    sv_task_set_name(task, name.as_ptr(), name.len())
+        .into_res()
+        .expect("Failed to rename the task");
+    let mut buf = [0u8; 4];
+    let len = sv_task_get_name(task, buf.as_mut_ptr(), buf.len())
+        .into_res()
+        .expect("Failed to get the name of the task");
+    assert_eq!(len as usize, name.len());
+    assert_eq!(&buf, b"spin");
+    let long = [b'a'; TASK_NAME_LEN + 1];
+    let ret = sv_task_set_name(Handle::NULL, long.as_ptr(), long.len());
+    assert_eq!(ret.into_res(), Err(ERANGE));
+
+    let mut stat = MaybeUninit::<SchedStat>::uninit();
+    sv_task_stat(task, TASK_STAT_WAKE_LATENCY, 0, stat.as_mut_ptr())
+        .into_res()
+        .expect("Failed to get the statistics of the task");
+    assert_eq!(&stat.assume_init().name[..=name.len()], b"spinner\0");
+
+    let mut ret = Default::default();
+    sv_task_join(task, &mut ret)
         .into_res()
         .expect("Failed to join the task");
-    assert_eq!(Error::try_from_retval(ret), Some(EFAULT));
+    assert_eq!(ret, 12345);
 }
 
 unsafe fn sleep() {
@@ -67,6 +232,13 @@ unsafe fn sleep() {
     sv_task_sleep(50).into_res().expect("Failed to sleep");
 }
 
+fn features() {
+    log::trace!("features");
+    log::debug!("Kernel features: {:?}", sv_call::features());
+    assert!(sv_call::has_syscall(SV_TASK_EXEC));
+    assert!(!sv_call::has_syscall(SYSCALL_COUNT));
+}
+
 unsafe fn debug_mem(st: Handle) {
     log::trace!("debug_mem: st = {:?}", st);
 
@@ -179,7 +351,7 @@ unsafe fn debug_excep(task: Handle, st: Handle) {
         .into_res()
         .expect("Failed to send exception result");
 
-    sv_obj_wait(task, u64::MAX, true, false, SIG_READ)
+    sv_obj_wait(task, u64::MAX, true, false, SIG_GENERIC)
         .into_res()
         .expect("Failed to wait for the task");
     let mut ret = Default::default();
@@ -215,7 +387,7 @@ unsafe fn kill(task: Handle) {
         .into_res()
         .expect("Failed to kill a task");
 
-    sv_obj_wait(task, u64::MAX, true, false, SIG_READ)
+    sv_obj_wait(task, u64::MAX, true, false, SIG_GENERIC)
         .into_res()
         .expect("Failed to wait for the task");
     let mut ret = Default::default();
@@ -225,6 +397,143 @@ unsafe fn kill(task: Handle) {
     assert_eq!(Error::try_from_retval(ret), Some(EKILLED));
 }
 
+unsafe fn filter(stack_ptr: *mut u8) {
+    log::trace!("filter");
+
+    let mut st = Handle::NULL;
+    let task = sv_task_new(null_mut(), 0, Handle::NULL, Handle::NULL, &mut st)
+        .into_res()
+        .expect("Failed to create task");
+    let frame = Gpr {
+        rip: func as usize as u64,
+        rsp: stack_ptr as u64,
+        rflags: 1 << 9,
+        rdi: 0,
+        rsi: 3,
+        ..Default::default()
+    };
+    sv_task_debug(
+        st,
+        TASK_DBG_WRITE_REG,
+        TASK_DBGADDR_GPR,
+        (&frame as *const Gpr) as *mut u8,
+        core::mem::size_of::<Gpr>(),
+    )
+    .into_res()
+    .expect("Failed to write task's data");
+
+    // Allow nothing but exiting.
+    let ret = sv_task_filter(task, [0u64; 0].as_ptr(), 0, u32::MAX);
+    assert_eq!(ret.into_res(), Err(EINVAL));
+    sv_task_filter(task, [0u64; 0].as_ptr(), 0, SYSCALL_FILTER_ERROR)
+        .into_res()
+        .expect("Failed to filter the syscalls");
+    sv_obj_drop(st)
+        .into_res()
+        .expect("Failed to resume the task");
+
+    sv_obj_wait(task, u64::MAX, true, false, SIG_GENERIC)
+        .into_res()
+        .expect("Failed to wait for the task");
+    let mut ret = Default::default();
+    sv_task_join(task, &mut ret)
+        .into_res()
+        .expect("Failed to join the task");
+    assert_eq!(Error::try_from_retval(ret), Some(EPERM));
+}
+
+/// The startup arguments built by the kernel must be deserialized by the
+/// runtime of the spawned task.
+unsafe fn spawn(stack_ptr: *mut u8) {
+    log::trace!("spawn");
+
+    let mut c1 = Handle::NULL;
+    let mut c2 = Handle::NULL;
+    sv_chan_new(&mut c1, &mut c2)
+        .into_res()
+        .expect("Failed to create channel");
+    sv_obj_drop(c1).into_res().expect("Failed to drop channel");
+    let phys = sv_phys_alloc(PAGE_SIZE, Default::default())
+        .into_res()
+        .expect("Failed to allocate memory");
+
+    let info = HandleInfo::from(HandleType::ProgramPhys);
+    let transfers = [
+        HandleTransfer {
+            handle: phys,
+            features: Feature::all(),
+            info: u32::from_ne_bytes(info.into_bytes()),
+        },
+        HandleTransfer {
+            handle: Handle::NULL,
+            features: Feature::all(),
+            info: u32::from_ne_bytes(HandleInfo::from(HandleType::LoadRpc).into_bytes()),
+        },
+    ];
+    let ci = ExecInfo {
+        name: null_mut(),
+        name_len: 0,
+        space: Handle::NULL,
+        entry: spawned as *mut u8,
+        stack: stack_ptr,
+        init_chan: c2,
+        arg: 0,
+    };
+    let si = SpawnInfo {
+        transfers: transfers.as_ptr(),
+        transfer_count: transfers.len(),
+        args: b"args".as_ptr(),
+        args_len: 4,
+        env: b"env".as_ptr(),
+        env_len: 3,
+    };
+    // Nothing is consumed if spawning fails.
+    let ret = sv_task_spawn(&ci, &si);
+    assert_eq!(ret.into_res(), Err(EINVAL));
+    let si = SpawnInfo {
+        transfer_count: 1,
+        ..si
+    };
+    let task = sv_task_spawn(&ci, &si)
+        .into_res()
+        .expect("Failed to spawn task");
+
+    sv_obj_wait(task, u64::MAX, true, false, SIG_GENERIC)
+        .into_res()
+        .expect("Failed to wait for the task");
+    let mut ret = Default::default();
+    sv_task_join(task, &mut ret)
+        .into_res()
+        .expect("Failed to join the task");
+    assert_eq!(ret, 12345);
+}
+
+/// The task-local slots set by `task` are its own.
+unsafe fn locals(task: Handle) {
+    log::trace!("locals: task = {:?}", task);
+    let get = || {
+        let mut values = [0; TASK_LOCAL_SLOTS as usize];
+        for (slot, value) in (0..).zip(&mut values) {
+            sv_task_local_get(slot, value)
+                .into_res()
+                .expect("Failed to get the task-local slot");
+        }
+        values
+    };
+    let before = get();
+
+    sv_obj_wait(task, u64::MAX, true, false, SIG_GENERIC)
+        .into_res()
+        .expect("Failed to wait for the task");
+    let mut ret = Default::default();
+    sv_task_join(task, &mut ret)
+        .into_res()
+        .expect("Failed to join the task");
+    assert_eq!(ret, 12345);
+
+    assert_eq!(get(), before);
+}
+
 unsafe fn ctl(task: Handle) {
     log::trace!("ctl: task = {:?}", task);
     suspend(task);
@@ -237,6 +546,8 @@ pub unsafe fn test(virt: &Virt) -> (*mut u8, *mut u8, Handle) {
     let ret = sv_task_exec(0x100000000 as *const ExecInfo);
     assert_eq!(ret.into_res(), Err(EPERM));
 
+    features();
+
     let flags = Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS;
     let stack_phys = sv_phys_alloc(DEFAULT_STACK_SIZE, Default::default())
         .into_res()
@@ -274,6 +585,10 @@ pub unsafe fn test(virt: &Virt) -> (*mut u8, *mut u8, Handle) {
         creator(1).into_res().expect("Failed to create task"),
     );
 
+    runtime(creator(2).into_res().expect("Failed to create task"));
+
+    locals(creator(4).into_res().expect("Failed to create task"));
+
     ctl(creator(0).into_res().expect("Failed to create task"));
 
     let mut st = Handle::NULL;
@@ -301,6 +616,8 @@ pub unsafe fn test(virt: &Virt) -> (*mut u8, *mut u8, Handle) {
         t
     };
     debug_excep(task, st);
+    filter(stack_ptr);
+    spawn(stack_ptr);
 
     (stack_ptr, stack_base, stack_phys2)
 }
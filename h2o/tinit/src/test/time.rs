@@ -1,9 +1,22 @@
-use core::ptr;
+use core::{ptr, time::Duration};
 
-use solvent::prelude::{Instant, SIG_READ};
+use solvent::{
+    prelude::{clock_info, ClockSource, Instant, SIG_READ},
+    time::into_ns,
+};
 use sv_call::{ipc::SIG_TIMER, *};
 
 pub unsafe fn test() {
+    let info = clock_info().expect("Failed to get clock info");
+    log::debug!("Clock info: {:?}", info);
+    assert!(info.freq_khz > 0);
+    assert!(info.resolution_ns > 0);
+    if info.source == ClockSource::Tsc {
+        assert_eq!(info.switches, 0);
+    }
+    let (t1, t2) = (Instant::now(), Instant::now());
+    assert!(t1 <= t2);
+
     let timer = sv_timer_new().into_res().expect("Failed to create timer");
     let disp = sv_disp_new(5)
         .into_res()
@@ -28,4 +41,28 @@ pub unsafe fn test() {
         .into_res()
         .expect("Failed to drop dispatcher");
     sv_obj_drop(timer).into_res().expect("Failed to drop timer");
+
+    deadline();
+}
+
+/// The waits until deadlines return at the deadlines, or at once if they're
+/// already passed.
+unsafe fn deadline() {
+    let event = sv_event_new(0).into_res().expect("Failed to create event");
+
+    let deadline = Instant::now() + Duration::from_millis(5);
+    let ret = sv_obj_wait_until(event, into_ns(deadline), true, false, SIG_READ);
+    assert_eq!(ret.into_res(), Err(ETIME));
+    assert!(Instant::now() >= deadline);
+
+    let ret = sv_obj_wait_until(event, into_ns(deadline), true, false, SIG_READ);
+    assert_eq!(ret.into_res(), Err(ETIME));
+
+    let deadline = Instant::now() + Duration::from_millis(5);
+    sv_task_sleep_until(into_ns(deadline))
+        .into_res()
+        .expect("Failed to sleep");
+    assert!(Instant::now() >= deadline);
+
+    sv_obj_drop(event).into_res().expect("Failed to drop event");
 }
@@ -0,0 +1,80 @@
+use core::ptr;
+
+use solvent::prelude::{Flags, Phys, PhysOptions, Virt, PAGE_LAYOUT, PAGE_SIZE};
+use sv_call::{ipc::RawPacket, *};
+
+const KERNEL_ADDR: usize = 0xFFFF_8000_0000_0000;
+
+/// Syscalls given hostile pointers fail without harming the kernel or the
+/// objects involved.
+pub unsafe fn test(virt: &Virt) {
+    let layout = Virt::page_aligned(PAGE_SIZE * 2);
+    let sub = virt
+        .allocate(None, layout)
+        .expect("Failed to allocate sub-virt");
+    let phys = Phys::allocate(PAGE_SIZE, PhysOptions::ZEROED).expect("Failed to allocate memory");
+    let flags = Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS;
+    let mapped = sub
+        .map(Some(0), phys, 0, PAGE_LAYOUT, flags)
+        .expect("Failed to map memory")
+        .as_mut_ptr();
+    // The second page is reserved but never mapped.
+    let unmapped = mapped.add(PAGE_SIZE);
+    let straddling = unmapped.sub(8);
+    let kernel = KERNEL_ADDR as *mut u8;
+
+    for ptr in [unmapped, straddling, kernel] {
+        assert_eq!(sv_log(ptr, 16).into_res(), Err(EPERM));
+        let ret = sv_task_local_get(0, ptr.add(8).cast());
+        assert_eq!(ret.into_res(), Err(EPERM));
+    }
+    let ret = sv_task_local_get(0, mapped.add(1).cast());
+    assert_eq!(ret.into_res(), Err(EALIGN));
+
+    channel(mapped, unmapped, straddling);
+
+    sub.destroy().expect("Failed to destroy sub-virt");
+}
+
+unsafe fn channel(mapped: *mut u8, unmapped: *mut u8, straddling: *mut u8) {
+    let mut c1 = Handle::NULL;
+    let mut c2 = Handle::NULL;
+    sv_chan_new(&mut c1, &mut c2)
+        .into_res()
+        .expect("Failed to create a channel");
+
+    let packet = |handles: *mut Handle, handle_count, buffer, buffer_size| RawPacket {
+        id: 0,
+        handles,
+        handle_count,
+        handle_cap: handle_count,
+        buffer,
+        buffer_size,
+        buffer_cap: buffer_size,
+    };
+
+    // The handles are kept if the buffer faults.
+    let int = sv_int_new(1).into_res().expect("Failed to create integer");
+    let mut handles = [int];
+    let ret = sv_chan_send(c1, &packet(handles.as_mut_ptr(), 1, straddling, 16));
+    assert_eq!(ret.into_res(), Err(EPERM));
+    let ret = sv_chan_send(c1, &packet(unmapped.cast(), 1, mapped, 16));
+    assert_eq!(ret.into_res(), Err(EPERM));
+    assert_eq!(sv_int_get(int).into_res(), Ok(1));
+    assert_eq!(sv_chan_send(c1, ptr::null()).into_res(), Err(EPERM));
+
+    // The packet is consumed even if it can't be written back.
+    sv_chan_send(c1, &packet(handles.as_mut_ptr(), 1, mapped, 16))
+        .into_res()
+        .expect("Failed to send a packet");
+    let mut receivee = packet(handles.as_mut_ptr(), 1, straddling, 16);
+    assert_eq!(sv_chan_recv(c2, &mut receivee).into_res(), Err(EPERM));
+    let mut receivee = packet(ptr::null_mut(), 0, mapped, 16);
+    assert_eq!(sv_chan_recv(c2, &mut receivee).into_res(), Err(ENOENT));
+
+    for hdl in [c1, c2, handles[0]] {
+        sv_obj_drop(hdl)
+            .into_res()
+            .expect("Failed to drop the object");
+    }
+}
@@ -19,12 +19,23 @@ mod mem;
 mod rxx;
 mod test;
 
-use alloc::{ffi::CString, vec, vec::Vec};
-use core::{hint, mem::MaybeUninit, time::Duration};
-
-use bootfs::parse::Directory;
+use alloc::{ffi::CString, format, string::String, vec, vec::Vec};
+use core::{
+    hint,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicBool, Ordering::*},
+    time::Duration,
+};
+
+use bootfs::{
+    parse::{Directory, Entry},
+    Compression,
+};
 use solvent::prelude::*;
-use solvent_rpc::{loader::GET_OBJECT, packet};
+use solvent_rpc::{
+    loader::{ObjectInfo, ObjectQuery, GET_OBJECT, GET_OBJECTS},
+    packet,
+};
 use sv_call::ipc::SIG_READ;
 use svrt::{HandleType, StartupArgs};
 use targs::{HandleIndex, Targs};
@@ -33,6 +44,10 @@ extern crate alloc;
 
 static mut ROOT_VIRT: MaybeUninit<Virt> = MaybeUninit::uninit();
 
+/// Whether to check the bootfs files against their hashes before handing them
+/// out, enabled by the `bootfs.verify` boot option.
+static VERIFY_BOOTFS: AtomicBool = AtomicBool::new(false);
+
 fn is_sub<T>(slice: &[T], parent: &[T]) -> bool {
     let range = parent.as_ptr_range();
     let srange = slice.as_ptr_range();
@@ -53,6 +68,27 @@ fn sub_phys(bin_data: &[u8], bootfs: Directory, bootfs_phys: &Phys) -> Result<Ph
     bootfs_phys.create_sub(offset, bin_data.len().next_multiple_of(PAGE_SIZE), false)
 }
 
+/// Create a physical object of the file's raw content, decompressing it into
+/// a new object if needed.
+fn file_phys(entry: Entry, bootfs: Directory, bootfs_phys: &Phys) -> Result<Phys> {
+    let content = entry.content().left().ok_or(EISDIR)?;
+    if VERIFY_BOOTFS.load(Acquire) && !entry.verify() {
+        let name = String::from_utf8_lossy(entry.name());
+        log::error!("Bootfs file {:?} failed verification", name);
+        return Err(EACCES);
+    }
+    if entry.compression() == Compression::None {
+        return sub_phys(content, bootfs, bootfs_phys);
+    }
+
+    let mut data = vec![0; entry.raw_len()];
+    let len = entry.decompress(&mut data).map_err(|_| EIO)?;
+    let phys = Phys::allocate(len.next_multiple_of(PAGE_SIZE), PhysOptions::ZEROED)?;
+    // SAFETY: The physical object is newly allocated.
+    unsafe { phys.write(0, &data[..len]) }?;
+    Ok(phys)
+}
+
 fn map_bootfs(phys: &Phys, root: &Virt) -> Directory<'static> {
     let ptr = root
         .map_phys(
@@ -64,35 +100,74 @@ fn map_bootfs(phys: &Phys, root: &Virt) -> Directory<'static> {
     Directory::root(unsafe { ptr.as_ref() }).expect("Failed to parse boot filesystem")
 }
 
+/// Find the entry of `path` in the bootfs, looking it up in `search_paths`, or
+/// `lib` if empty, unless it contains a slash.
+fn find_object<'a>(
+    bootfs: Directory<'a>,
+    search_paths: &[CString],
+    path: &CString,
+) -> Option<Entry<'a>> {
+    let path = path.as_bytes();
+    if path.contains(&b'/') {
+        return bootfs.find_entry(path, b'/');
+    }
+    let lookup = |dir: &[u8]| bootfs.find_dir(dir, b'/')?.find_entry(path, b'/');
+    if search_paths.is_empty() {
+        lookup(b"lib")
+    } else {
+        search_paths.iter().find_map(|dir| lookup(dir.as_bytes()))
+    }
+}
+
+fn get_objects(
+    bootfs: Directory,
+    bootfs_phys: &Phys,
+    search_paths: Vec<CString>,
+    queries: Vec<ObjectQuery>,
+) -> Vec<Result<ObjectInfo>> {
+    let get = |query: ObjectQuery| {
+        let entry = find_object(bootfs, &search_paths, &query.path).ok_or(ENOENT)?;
+        let hash = *entry.hash();
+        let phys = if query.cached == hash {
+            None
+        } else {
+            Some(file_phys(entry, bootfs, bootfs_phys)?)
+        };
+        Ok(ObjectInfo { phys, hash })
+    };
+    queries.into_iter().map(get).collect()
+}
+
 fn serve_load(load_rpc: Channel, bootfs: Directory, bootfs_phys: &Phys) -> Error {
     loop {
         let res = load_rpc.handle(|packet| {
-            let paths: Vec<CString> =
-                packet::deserialize(GET_OBJECT, packet, None).map_err(|_| solvent::error::ETYPE)?;
-            let response = {
-                let mut objs = Vec::with_capacity(paths.len());
-                let mut err = None;
-                for (i, path) in paths.into_iter().enumerate() {
-                    let mut root = Vec::from(b"lib/" as &[u8]);
-                    root.append(&mut path.into_bytes());
-                    let obj = bootfs
-                        .find(&root, b'/')
-                        .and_then(|bin| sub_phys(bin, bootfs, bootfs_phys).ok());
-                    match obj {
-                        Some(obj) => objs.push(obj),
-                        None => {
-                            err = Some(i);
-                            break;
-                        }
-                    }
+            let (method, de) =
+                packet::deserialize_metadata(packet).map_err(|_| solvent::error::ETYPE)?;
+            match method {
+                GET_OBJECT => {
+                    let paths: Vec<CString> =
+                        packet::deserialize_body(de, None).map_err(|_| solvent::error::ETYPE)?;
+                    let lib = bootfs.find_dir(b"lib", b'/');
+                    let response: core::result::Result<Vec<_>, usize> = paths
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, path)| {
+                            lib.and_then(|lib| lib.find_entry(path.as_bytes(), b'/'))
+                                .and_then(|bin| file_phys(bin, bootfs, bootfs_phys).ok())
+                                .ok_or(i)
+                        })
+                        .collect();
+                    packet::serialize(GET_OBJECT, response, packet)
                 }
-                match err {
-                    Some(err) => Err(err),
-                    None => Ok(objs),
+                GET_OBJECTS => {
+                    let (search_paths, queries) =
+                        packet::deserialize_body(de, None).map_err(|_| solvent::error::ETYPE)?;
+                    let response = get_objects(bootfs, bootfs_phys, search_paths, queries);
+                    packet::serialize(GET_OBJECTS, response, packet)
                 }
-            };
-            packet::serialize(GET_OBJECT, response, packet).map_err(|_| solvent::error::EFAULT)?;
-            Ok(())
+                _ => return Err(solvent::error::ETYPE),
+            }
+            .map_err(|_| solvent::error::EFAULT)
         });
 
         match res {
@@ -119,11 +194,15 @@ extern "C" fn tmain(init_chan: sv_call::Handle) {
         .0
         .expect("Failed to receive the initial packet");
 
-    let _targs = {
+    let targs = {
         let mut targs = Targs::default();
         plain::copy_from_bytes(&mut targs, &buffer).expect("Failed to get TINIT args");
         targs
     };
+    if targs.has_option("bootfs.verify") {
+        log::info!("Bootfs verification enabled");
+        VERIFY_BOOTFS.store(true, Release);
+    }
 
     let root_virt = unsafe {
         &*ROOT_VIRT.write(Virt::from_raw(
@@ -143,10 +222,10 @@ extern "C" fn tmain(init_chan: sv_call::Handle) {
 
     let bin = {
         let bin_data = bootfs
-            .find(b"bin/progm", b'/')
+            .find_entry(b"bin/progm", b'/')
             .expect("Failed to find progm");
 
-        sub_phys(bin_data, bootfs, &bootfs_phys).expect("Failed to create the physical object")
+        file_phys(bin_data, bootfs, &bootfs_phys).expect("Failed to create the physical object")
     };
 
     let (space, virt) = Space::new();
@@ -189,7 +268,7 @@ extern "C" fn tmain(init_chan: sv_call::Handle) {
         .send(&me, &mut packet)
         .expect("Failed to send dyn loader args");
 
-    let exe_args = StartupArgs {
+    let mut exe_args = StartupArgs {
         handles: [
             (HandleType::RootVirt.into(), Virt::into_raw(virt)),
             (HandleType::VdsoPhys.into(), Phys::into_raw(vdso_phys)),
@@ -203,6 +282,25 @@ extern "C" fn tmain(init_chan: sv_call::Handle) {
         args: Vec::from(b"progm\0" as &[u8]),
         env: vec![0],
     };
+    // SAFETY: The kernel always sends the root memory resource.
+    let mem_res = unsafe { handles[HandleIndex::MemRes as usize].assume_init() };
+    exe_args.handles.insert(HandleType::MemRes.into(), mem_res);
+
+    let fb = targs.framebuffer;
+    if fb.is_present() {
+        let handle = unsafe { handles[HandleIndex::Framebuffer as usize].assume_init() };
+        exe_args
+            .handles
+            .insert(HandleType::FramebufferPhys.into(), handle);
+
+        let format = match fb.format {
+            targs::FB_FORMAT_BGRX => "bgrx",
+            _ => "rgbx",
+        };
+        let (width, height, stride) = (fb.width, fb.height, fb.stride);
+        let mode = format!("FB_MODE={width}x{height}/{stride}/{format}\0");
+        exe_args.env = mode.into_bytes();
+    }
 
     exe_args
         .send(&me, &mut packet)
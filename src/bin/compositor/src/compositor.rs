@@ -0,0 +1,187 @@
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+use solvent::{
+    error::{Result, ENOENT},
+    mem::Phys,
+};
+use solvent_async::sync::channel::Sender;
+use solvent_rpc::{
+    display::{DisplayEventSender, Mode, Rect, Vsync},
+    EventSender,
+};
+use solvent_std::sync::Mutex;
+
+use crate::{screen::Screen, surface::Surface};
+
+/// The number of damaged rectangles beyond which the whole screen is composed
+/// instead.
+const MAX_DAMAGE: usize = 16;
+
+struct Inner {
+    screen: Screen,
+
+    next_conn: u64,
+    conns: BTreeMap<u64, DisplayEventSender>,
+    /// The connections subscribing to the vsync events.
+    vsync: Vec<u64>,
+
+    surfaces: BTreeMap<u64, Surface>,
+    /// The connections owning the surfaces, from the bottom to the top.
+    stack: Vec<u64>,
+    /// The parts of the screen to be composed on the next frame.
+    damage: Vec<Rect>,
+    seq: u64,
+    /// Wakes the frame loop when it's idle.
+    waker: Sender<()>,
+}
+
+impl Inner {
+    fn damage(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+        if self.damage.len() < MAX_DAMAGE {
+            self.damage.push(rect);
+        } else {
+            self.damage = vec![self.screen.bounds()];
+        }
+        let _ = self.waker.try_send(());
+    }
+
+    fn is_busy(&self) -> bool {
+        !self.damage.is_empty() || !self.vsync.is_empty()
+    }
+}
+
+/// The state shared by all the connections and the frame loop.
+pub struct Compositor {
+    inner: Mutex<Inner>,
+}
+
+impl Compositor {
+    pub fn new(screen: Screen, waker: Sender<()>) -> Self {
+        Compositor {
+            inner: Mutex::new(Inner {
+                screen,
+                next_conn: 0,
+                conns: BTreeMap::new(),
+                vsync: Vec::new(),
+                surfaces: BTreeMap::new(),
+                stack: Vec::new(),
+                damage: Vec::new(),
+                seq: 0,
+                waker,
+            }),
+        }
+    }
+
+    #[inline]
+    pub fn mode(&self) -> Mode {
+        self.inner.lock().screen.mode()
+    }
+
+    pub fn connect(&self, sender: DisplayEventSender) -> u64 {
+        let mut inner = self.inner.lock();
+        let id = inner.next_conn;
+        inner.next_conn += 1;
+        inner.conns.insert(id, sender);
+        id
+    }
+
+    pub fn disconnect(&self, conn: u64) {
+        let mut inner = self.inner.lock();
+        inner.conns.remove(&conn);
+        inner.vsync.retain(|&id| id != conn);
+        inner.stack.retain(|&id| id != conn);
+        if let Some(surface) = inner.surfaces.remove(&conn) {
+            inner.damage(surface.rect());
+        }
+    }
+
+    /// Place the surface of `conn` at `rect`, which is recreated if its size
+    /// changes.
+    pub fn configure(&self, conn: u64, rect: Rect) -> Result<Phys> {
+        let mut inner = self.inner.lock();
+        let old = inner.surfaces.get(&conn).map(Surface::rect);
+        let size = (rect.width, rect.height);
+        let resized = old.map_or(true, |old| (old.width, old.height) != size);
+        let buffer = if resized {
+            let surface = Surface::new(rect)?;
+            let buffer = surface.buffer();
+            if inner.surfaces.insert(conn, surface).is_none() {
+                inner.stack.push(conn);
+            }
+            buffer
+        } else {
+            let surface = inner.surfaces.get_mut(&conn).unwrap();
+            surface.move_to(rect.x, rect.y);
+            surface.buffer()
+        };
+        if let Some(old) = old {
+            inner.damage(old);
+        }
+        inner.damage(rect);
+        Ok(buffer)
+    }
+
+    /// Mark the part of the surface of `conn` in `damage`, relative to its
+    /// origin, to be composed.
+    pub fn present(&self, conn: u64, damage: Rect) -> Result {
+        let mut inner = self.inner.lock();
+        let rect = inner.surfaces.get(&conn).ok_or(ENOENT)?.rect();
+        let damage = damage.offset(rect.x, rect.y).intersect(&rect);
+        inner.damage(damage);
+        Ok(())
+    }
+
+    pub fn raise(&self, conn: u64) -> Result {
+        let mut inner = self.inner.lock();
+        let rect = inner.surfaces.get(&conn).ok_or(ENOENT)?.rect();
+        inner.stack.retain(|&id| id != conn);
+        inner.stack.push(conn);
+        inner.damage(rect);
+        Ok(())
+    }
+
+    pub fn subscribe_vsync(&self, conn: u64, enable: bool) {
+        let mut inner = self.inner.lock();
+        inner.vsync.retain(|&id| id != conn);
+        if enable {
+            inner.vsync.push(conn);
+            let _ = inner.waker.try_send(());
+        }
+    }
+
+    /// Whether the frame loop has anything to do.
+    #[inline]
+    pub fn is_busy(&self) -> bool {
+        self.inner.lock().is_busy()
+    }
+
+    /// Compose the damaged parts of the screen and notify the subscribers.
+    pub fn frame(&self, timestamp_ns: u64) {
+        let mut inner = self.inner.lock();
+        let Inner {
+            screen,
+            surfaces,
+            stack,
+            damage,
+            ..
+        } = &mut *inner;
+        for rect in damage.drain(..) {
+            let layers = stack.iter().map(|id| &surfaces[id]);
+            screen.compose(rect, layers);
+        }
+
+        inner.seq += 1;
+        let vsync = Vsync {
+            seq: inner.seq,
+            timestamp_ns,
+        };
+        let failed = { inner.vsync.iter() }
+            .filter(|&id| inner.conns[id].send(vsync).is_err())
+            .copied()
+            .collect::<Vec<_>>();
+        inner.vsync.retain(|id| !failed.contains(id));
+    }
+}
@@ -0,0 +1,31 @@
+use core::time::Duration;
+
+use solvent::time::Instant;
+use solvent_async::{sync::channel::Receiver, time::Sleep};
+use solvent_std::sync::Arsc;
+
+use crate::compositor::Compositor;
+
+/// The interval between the frames, at 60 frames per second.
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// Compose the frames while there's damage or any vsync subscriber, and sleep
+/// until woken otherwise.
+pub async fn run(compositor: Arsc<Compositor>, wakes: Receiver<()>) {
+    let start = Instant::now();
+    let mut deadline = start;
+    loop {
+        if !compositor.is_busy() {
+            if wakes.recv().await.is_err() {
+                break;
+            }
+            deadline = deadline.max(Instant::now());
+        }
+        Sleep::new(deadline).await;
+
+        compositor.frame((Instant::now() - start).as_nanos() as u64);
+
+        // Skip the frames missed, if any.
+        deadline = (deadline + FRAME).max(Instant::now());
+    }
+}
@@ -0,0 +1,79 @@
+//! The compositor, multiplexing the surfaces of its clients on the framebuffer
+//! left by the boot loader.
+//!
+//! Every client connection owns at most one surface, whose buffer is shared
+//! with the client. The damaged parts of the surfaces are composed into the
+//! screen with software blitting at most once per frame. The service is
+//! started by the program manager only if there's a framebuffer, and mounted
+//! at `use/compositor`.
+
+#![no_std]
+#![no_main]
+#![feature(int_roundings)]
+
+mod compositor;
+mod frame;
+mod screen;
+mod serve;
+mod surface;
+
+use futures_lite::future;
+use solvent::prelude::{Channel, Object, Phys};
+use solvent_async::sync::channel;
+use solvent_fs::{rpc::RpcNode, spawner};
+use solvent_rpc::display::{Mode, PixelFormat};
+use solvent_std::sync::Arsc;
+use svrt::HandleType;
+
+use self::{compositor::Compositor, screen::Screen};
+
+extern crate alloc;
+
+/// Parse the mode of the framebuffer in the form of
+/// `<width>x<height>/<stride>/<format>`.
+fn parse_mode(mode: &str) -> Option<(Mode, u32)> {
+    let (size, rest) = mode.split_once('/')?;
+    let (stride, format) = rest.split_once('/')?;
+    let (width, height) = size.split_once('x')?;
+    let format = match format {
+        "bgrx" => PixelFormat::Bgrx,
+        "rgbx" => PixelFormat::Rgbx,
+        _ => return None,
+    };
+    let mode = Mode {
+        width: width.parse().ok()?,
+        height: height.parse().ok()?,
+        format,
+    };
+    Some((mode, stride.parse().ok()?))
+}
+
+async fn main() {
+    let entry = svrt::take_startup_handle(HandleType::ServiceEntry.into());
+    // SAFETY: The handle is given to us by the program manager.
+    let entry = unsafe { Channel::from_raw(entry) };
+    if let Err(err) = solvent_std::logging::attach("compositor") {
+        log::warn!("Failed to attach to the log service: {err:?}");
+    }
+    let framebuffer = svrt::take_startup_handle(HandleType::FramebufferPhys.into());
+    // SAFETY: The handle is given to us by the program manager.
+    let framebuffer = unsafe { Phys::from_raw(framebuffer) };
+
+    let (mode, stride) = solvent_std::env::vars()
+        .find(|(key, _)| key == "FB_MODE")
+        .and_then(|(_, mode)| parse_mode(&mode))
+        .expect("Failed to get the mode of the framebuffer");
+    let screen = Screen::new(framebuffer, mode, stride).expect("Failed to map the framebuffer");
+    log::debug!("{}x{} framebuffer", mode.width, mode.height);
+
+    let (waker, wakes) = channel::bounded(1);
+    let compositor = Arsc::new(Compositor::new(screen, waker));
+    solvent_async::spawn(frame::run(Arsc::clone(&compositor), wakes)).detach();
+
+    let node = RpcNode::new(move |server, _| serve::handle(Arsc::clone(&compositor), server));
+    node.open_conn(spawner(), Default::default(), entry);
+
+    future::pending::<()>().await
+}
+
+solvent_async::entry!(main, solvent_std, None);
@@ -0,0 +1,113 @@
+use alloc::{vec, vec::Vec};
+use core::{mem, ptr, ptr::NonNull};
+
+use solvent::{
+    error::{Result, EINVAL},
+    mem::{Flags, Phys},
+};
+use solvent_rpc::display::{Mode, Rect};
+
+use crate::surface::Surface;
+
+/// The color of the screen not covered by any surface.
+const BACKGROUND: u32 = 0;
+
+/// The framebuffer, into which the surfaces are composed.
+pub struct Screen {
+    mode: Mode,
+    /// The number of pixels in a scan line of the framebuffer.
+    stride: usize,
+    framebuffer: NonNull<u32>,
+    /// A copy of the composed pixels, since reading from the write-combining
+    /// framebuffer is slow.
+    shadow: Vec<u32>,
+}
+
+// SAFETY: The framebuffer is only accessed with exclusive references.
+unsafe impl Send for Screen {}
+
+impl Screen {
+    pub fn new(framebuffer: Phys, mode: Mode, stride: u32) -> Result<Self> {
+        let stride = stride as usize;
+        if stride < mode.width as usize {
+            return Err(EINVAL);
+        }
+        let flags = Flags::READABLE | Flags::WRITABLE | Flags::WRITE_COMBINING;
+        let ptr = svrt::root_virt().map_phys(None, framebuffer, flags)?;
+        if ptr.len() < stride * mode.height as usize * mem::size_of::<u32>() {
+            let _ = svrt::root_virt().unmap(ptr.cast(), ptr.len(), false);
+            return Err(EINVAL);
+        }
+
+        let pixels = mode.width as usize * mode.height as usize;
+        let mut screen = Screen {
+            mode,
+            stride,
+            framebuffer: ptr.cast(),
+            shadow: vec![BACKGROUND; pixels],
+        };
+        screen.flush(screen.bounds());
+        Ok(screen)
+    }
+
+    #[inline]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    #[inline]
+    pub fn bounds(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: self.mode.width,
+            height: self.mode.height,
+        }
+    }
+
+    /// Compose the part of the surfaces in `rect`, given from the bottom to
+    /// the top, and show it on the screen.
+    pub fn compose<'a>(&mut self, rect: Rect, surfaces: impl Iterator<Item = &'a Surface>) {
+        let rect = rect.intersect(&self.bounds());
+        if rect.is_empty() {
+            return;
+        }
+        let width = self.mode.width as usize;
+        let (x, y) = (rect.x as usize, rect.y as usize);
+        for row in y..y + rect.height as usize {
+            self.shadow[row * width + x..][..rect.width as usize].fill(BACKGROUND);
+        }
+
+        for surface in surfaces {
+            let origin = surface.rect();
+            let overlap = rect.intersect(&origin);
+            if overlap.is_empty() {
+                continue;
+            }
+            let (x, y) = (overlap.x as usize, overlap.y as usize);
+            for row in y..y + overlap.height as usize {
+                let dst = &mut self.shadow[row * width + x..][..overlap.width as usize];
+                let sx = (overlap.x - origin.x) as u32;
+                let sy = (row as i32 - origin.y) as u32;
+                surface.read_row(sx, sy, dst);
+            }
+        }
+        self.flush(rect);
+    }
+
+    /// Copy the composed pixels in `rect`, which is inside the screen, into
+    /// the framebuffer.
+    fn flush(&mut self, rect: Rect) {
+        let width = self.mode.width as usize;
+        let (x, y) = (rect.x as usize, rect.y as usize);
+        for row in y..y + rect.height as usize {
+            let src = &self.shadow[row * width + x..][..rect.width as usize];
+            // SAFETY: The row is inside the framebuffer, whose size is checked
+            // in `new`.
+            unsafe {
+                let dst = self.framebuffer.as_ptr().add(row * self.stride + x);
+                ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use futures_lite::StreamExt;
+use solvent_rpc::{
+    display::{DisplayRequest, DisplayServer},
+    Server,
+};
+use solvent_std::sync::Arsc;
+
+use crate::compositor::Compositor;
+
+pub async fn handle(compositor: Arsc<Compositor>, server: DisplayServer) {
+    let (mut stream, sender) = server.serve();
+    let conn = compositor.connect(sender);
+
+    while let Some(request) = stream.next().await {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+        };
+
+        let res = match request {
+            DisplayRequest::Mode { responder } => responder.send(compositor.mode()),
+            DisplayRequest::Configure { rect, responder } => {
+                responder.send(compositor.configure(conn, rect))
+            }
+            DisplayRequest::Present { damage, responder } => {
+                responder.send(compositor.present(conn, damage))
+            }
+            DisplayRequest::Raise { responder } => responder.send(compositor.raise(conn)),
+            DisplayRequest::SubscribeVsync { enable, responder } => {
+                compositor.subscribe_vsync(conn, enable);
+                responder.send(())
+            }
+            DisplayRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+    compositor.disconnect(conn);
+}
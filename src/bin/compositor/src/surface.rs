@@ -0,0 +1,67 @@
+use core::{mem, ptr, ptr::NonNull};
+
+use solvent::{
+    error::{Result, EINVAL},
+    mem::{Flags, Phys, PhysOptions, PAGE_SIZE},
+};
+use solvent_rpc::display::Rect;
+
+/// The surface of a client, whose buffer is shared with the client.
+pub struct Surface {
+    rect: Rect,
+    phys: Phys,
+    buffer: NonNull<[u8]>,
+}
+
+// SAFETY: The buffer is only read with raw copies.
+unsafe impl Send for Surface {}
+
+impl Surface {
+    pub fn new(rect: Rect) -> Result<Self> {
+        if rect.is_empty() {
+            return Err(EINVAL);
+        }
+        let size = (rect.width as usize)
+            .checked_mul(rect.height as usize * mem::size_of::<u32>())
+            .ok_or(EINVAL)?;
+        let phys = Phys::allocate(size.next_multiple_of(PAGE_SIZE), PhysOptions::ZEROED)?;
+        let buffer = svrt::root_virt().map_phys(None, phys.clone(), Flags::READABLE)?;
+        Ok(Surface { rect, phys, buffer })
+    }
+
+    /// The buffer to be mapped by the client.
+    #[inline]
+    pub fn buffer(&self) -> Phys {
+        self.phys.clone()
+    }
+
+    #[inline]
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Move the surface without changing its size.
+    #[inline]
+    pub fn move_to(&mut self, x: i32, y: i32) {
+        self.rect.x = x;
+        self.rect.y = y;
+    }
+
+    /// Read a row of pixels starting at `(x, y)` relative to the surface.
+    pub fn read_row(&self, x: u32, y: u32, dst: &mut [u32]) {
+        assert!(x as usize + dst.len() <= self.rect.width as usize && y < self.rect.height);
+        let offset = y as usize * self.rect.width as usize + x as usize;
+        // SAFETY: The row is inside the buffer. The client may write it at the
+        // same time, which only tears the frame.
+        unsafe {
+            let src = self.buffer.cast::<u32>().as_ptr().add(offset);
+            ptr::copy_nonoverlapping(src, dst.as_mut_ptr(), dst.len())
+        }
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        let _ = svrt::root_virt().unmap(self.buffer.cast(), self.buffer.len(), false);
+    }
+}
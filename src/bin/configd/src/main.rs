@@ -0,0 +1,40 @@
+//! The configuration service, keeping the typed values of dotted keys such as
+//! `display.mode` and notifying the connections watching them of changes.
+//!
+//! The values are persisted to the file at `CONFIG_PATH` if the variable is
+//! given, which should be on writable storage, or kept in memory otherwise.
+//! The service is reached through the entry it's started with, which the
+//! program manager mounts at `use/configd`.
+
+#![no_std]
+#![no_main]
+
+mod serve;
+mod store;
+
+use futures_lite::future;
+use solvent::prelude::{Channel, Object};
+use solvent_fs::{rpc::RpcNode, spawner};
+use solvent_std::sync::Arsc;
+use svrt::HandleType;
+
+use self::store::Store;
+
+extern crate alloc;
+
+async fn main() {
+    let entry = svrt::take_startup_handle(HandleType::ServiceEntry.into());
+    // SAFETY: The handle is given to us by the program manager.
+    let entry = unsafe { Channel::from_raw(entry) };
+
+    let path =
+        solvent_std::env::vars().find_map(|(key, value)| (key == "CONFIG_PATH").then_some(value));
+    let store = Arsc::new(Store::load(path));
+
+    let node = RpcNode::new(move |server, _| serve::handle(Arsc::clone(&store), server));
+    node.open_conn(spawner(), Default::default(), entry);
+
+    future::pending::<()>().await
+}
+
+solvent_async::entry!(main, solvent_std, None);
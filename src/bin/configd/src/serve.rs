@@ -0,0 +1,48 @@
+use futures_lite::StreamExt;
+use solvent_rpc::{
+    config::{ConfigRequest, ConfigServer},
+    Server,
+};
+use solvent_std::sync::Arsc;
+
+use crate::store::Store;
+
+pub async fn handle(store: Arsc<Store>, server: ConfigServer) {
+    let (mut stream, sender) = server.serve();
+    let conn = store.connect(sender);
+
+    while let Some(request) = stream.next().await {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+        };
+
+        let res = match request {
+            ConfigRequest::Get { key, responder } => responder.send(store.get(key)),
+            ConfigRequest::List { prefix, responder } => responder.send(store.list(&prefix)),
+            ConfigRequest::Set {
+                key,
+                value,
+                responder,
+            } => responder.send(store.set(key, value)),
+            ConfigRequest::Remove { key, responder } => responder.send(store.remove(key)),
+            ConfigRequest::Watch { prefix, responder } => responder.send(store.watch(conn, prefix)),
+            ConfigRequest::Unwatch { prefix, responder } => {
+                store.unwatch(conn, &prefix);
+                responder.send(())
+            }
+            ConfigRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+    store.disconnect(conn);
+}
@@ -0,0 +1,203 @@
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use solvent::ipc::Packet;
+use solvent_rpc::{
+    config::{self, Change, ConfigEventSender, Error, Value},
+    io, packet, EventSender,
+};
+use solvent_std::sync::Mutex;
+
+/// The id marking the serialized values in the file.
+const FILE_ID: usize = 0xc0f1_9d00_0000_0001;
+
+struct Watcher {
+    sender: ConfigEventSender,
+    prefixes: Vec<String>,
+}
+
+struct Inner {
+    values: BTreeMap<String, Value>,
+    next_conn: u64,
+    watchers: BTreeMap<u64, Watcher>,
+}
+
+impl Inner {
+    fn notify(&mut self, change: Change) {
+        let key = match &change {
+            Change::Set { key, .. } | Change::Removed { key } => key,
+        };
+        let watching = |w: &Watcher| w.prefixes.iter().any(|p| config::is_under(key, p));
+        let failed = { self.watchers.iter() }
+            .filter(|(_, w)| watching(w))
+            .filter(|(_, w)| w.sender.send(change.clone()).is_err())
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        failed.into_iter().for_each(|id| {
+            self.watchers.remove(&id);
+        });
+    }
+}
+
+pub struct Store {
+    inner: Mutex<Inner>,
+    /// The file the values are persisted to, or `None` if they're kept in
+    /// memory only.
+    path: Option<String>,
+}
+
+fn check_key(key: &str) -> Result<(), Error> {
+    if config::is_valid_key(key) {
+        Ok(())
+    } else {
+        Err(Error::InvalidKey(key.into()))
+    }
+}
+
+fn read(path: &str) -> Result<BTreeMap<String, Value>, io::Error> {
+    let packet = Packet {
+        buffer: solvent_fs::read(path)?,
+        ..Default::default()
+    };
+    let values: Vec<(String, Value)> = packet::deserialize(FILE_ID, &packet, None)?;
+    Ok(values.into_iter().collect())
+}
+
+impl Store {
+    /// Load the values persisted at `path`, or start empty if there's none.
+    pub fn load(path: Option<String>) -> Self {
+        let values = match &path {
+            Some(path) => match read(path) {
+                Ok(values) => values,
+                Err(io::Error::NotFound) => BTreeMap::new(),
+                Err(err) => {
+                    log::warn!("Failed to load the configuration from {path}: {err}");
+                    BTreeMap::new()
+                }
+            },
+            None => {
+                log::info!("No storage given, keeping the configuration in memory");
+                BTreeMap::new()
+            }
+        };
+        Store {
+            inner: Mutex::new(Inner {
+                values,
+                next_conn: 0,
+                watchers: BTreeMap::new(),
+            }),
+            path,
+        }
+    }
+
+    /// Write the values to a temporary file, which then replaces the old
+    /// one, so that the old values survive failures in the middle.
+    fn persist(&self, values: &BTreeMap<String, Value>) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let values = { values.iter() }
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        let mut packet = Default::default();
+        packet::serialize(FILE_ID, values, &mut packet)
+            .map_err(|err| Error::Storage(err.to_string()))?;
+
+        let temp = format!("{path}.tmp");
+        solvent_fs::write(&temp, &packet.buffer)
+            .and_then(|_| solvent_fs::rename(&temp, path))
+            .map_err(|err| Error::Storage(err.to_string()))
+    }
+
+    pub fn get(&self, key: String) -> Result<Value, Error> {
+        let inner = self.inner.lock();
+        inner.values.get(&key).cloned().ok_or(Error::NotFound(key))
+    }
+
+    pub fn list(&self, prefix: &str) -> Vec<(String, Value)> {
+        let inner = self.inner.lock();
+        { inner.values.range::<str, _>(prefix..) }
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .filter(|(key, _)| config::is_under(key, prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Set the value of `key`, which is kept unchanged if it fails to be
+    /// persisted.
+    pub fn set(&self, key: String, value: Value) -> Result<(), Error> {
+        check_key(&key)?;
+        let mut inner = self.inner.lock();
+        let old = inner.values.insert(key.clone(), value.clone());
+        if old.as_ref() == Some(&value) {
+            return Ok(());
+        }
+        if let Err(err) = self.persist(&inner.values) {
+            match old {
+                Some(old) => inner.values.insert(key, old),
+                None => inner.values.remove(&key),
+            };
+            return Err(err);
+        }
+        inner.notify(Change::Set { key, value });
+        Ok(())
+    }
+
+    pub fn remove(&self, key: String) -> Result<(), Error> {
+        let mut inner = self.inner.lock();
+        let old = match inner.values.remove(&key) {
+            Some(old) => old,
+            None => return Err(Error::NotFound(key)),
+        };
+        if let Err(err) = self.persist(&inner.values) {
+            inner.values.insert(key, old);
+            return Err(err);
+        }
+        inner.notify(Change::Removed { key });
+        Ok(())
+    }
+
+    pub fn connect(&self, sender: ConfigEventSender) -> u64 {
+        let mut inner = self.inner.lock();
+        let id = inner.next_conn;
+        inner.next_conn += 1;
+        let watcher = Watcher {
+            sender,
+            prefixes: Vec::new(),
+        };
+        inner.watchers.insert(id, watcher);
+        id
+    }
+
+    #[inline]
+    pub fn disconnect(&self, conn: u64) {
+        self.inner.lock().watchers.remove(&conn);
+    }
+
+    /// Watch the keys under `prefix`, where the empty prefix covers all the
+    /// keys.
+    pub fn watch(&self, conn: u64, prefix: String) -> Result<(), Error> {
+        if !prefix.is_empty() {
+            check_key(&prefix)?;
+        }
+        let mut inner = self.inner.lock();
+        if let Some(watcher) = inner.watchers.get_mut(&conn) {
+            if !watcher.prefixes.contains(&prefix) {
+                watcher.prefixes.push(prefix);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn unwatch(&self, conn: u64, prefix: &str) {
+        let mut inner = self.inner.lock();
+        if let Some(watcher) = inner.watchers.get_mut(&conn) {
+            watcher.prefixes.retain(|p| p != prefix);
+        }
+    }
+}
@@ -1,10 +1,18 @@
+use alloc::{format, vec::Vec};
+
 use futures_lite::StreamExt;
+use solvent::{
+    error::{Result, EEXIST, ENOENT},
+    ipc::Channel,
+};
 use solvent_rpc::{
-    ddk::driver::{DriverRequest, DriverServer},
+    ddk::bus::{BusRequest, BusServer},
+    input::ManagerSyncClient,
     Server,
 };
 
-pub async fn handle_driver(server: DriverServer) {
+/// Serve the root bus, which has no devices of its own for now.
+pub async fn handle_bus(server: BusServer) {
     let (mut stream, _) = server.serve();
     while let Some(request) = stream.next().await {
         let request = match request {
@@ -16,8 +24,21 @@ pub async fn handle_driver(server: DriverServer) {
         };
 
         let res = match request {
-            DriverRequest::CloseConnection { responder } => responder.send(()),
-            DriverRequest::Unknown(_) => {
+            BusRequest::CloseConnection { responder } => responder.send(()),
+            BusRequest::Devices { responder } => responder.send(Vec::new()),
+            BusRequest::Mmio { responder, .. } => responder.send(Err(ENOENT)),
+            BusRequest::Interrupt { responder, .. } => responder.send(Err(ENOENT)),
+            BusRequest::Dma { responder, .. } => responder.send(Err(ENOENT)),
+            BusRequest::Publish {
+                name,
+                node,
+                responder,
+            } => responder.send(publish(&name, node)),
+            BusRequest::RegisterPower { power, responder } => {
+                crate::power::register(power);
+                responder.send(Ok(()))
+            }
+            BusRequest::Unknown(_) => {
                 log::warn!("unknown request received");
                 continue;
             }
@@ -28,3 +49,37 @@ pub async fn handle_driver(server: DriverServer) {
         }
     }
 }
+
+fn publish(name: &str, node: Channel) -> Result {
+    let path = format!("dev/{name}");
+    solvent_fs::fs::local()
+        .mount(&path, node.into())
+        .map_err(|err| {
+            log::warn!("failed to publish {path}: {err}");
+            EEXIST
+        })?;
+    log::debug!("Published {path}");
+
+    if let Some(name) = name.strip_prefix("input/") {
+        add_input(name, &path);
+    }
+    Ok(())
+}
+
+/// Hand a connection to the input device node at `path` to the input manager.
+fn add_input(name: &str, path: &str) {
+    let (device, server) = Channel::new();
+    let (manager, conn) = Channel::new();
+    let res =
+        solvent_fs::open_rpc(path, server).and_then(|_| solvent_fs::open_rpc("use/inputmgr", conn));
+    if let Err(err) = res {
+        log::warn!("failed to connect {path} to the input manager: {err}");
+        return;
+    }
+
+    match ManagerSyncClient::from(manager).add_device(name.into(), device) {
+        Ok(Ok(id)) => log::debug!("Added input device {id}: {name}"),
+        Ok(Err(err)) => log::warn!("failed to add input device {name}: {err}"),
+        Err(err) => log::warn!("failed to add input device {name}: {err}"),
+    }
+}
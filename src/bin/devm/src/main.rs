@@ -2,15 +2,20 @@
 #![no_main]
 
 mod device;
+mod power;
 
 use alloc::vec;
 
-use solvent::prelude::{Channel, Phys};
+use solvent::{
+    dev::MemRes,
+    prelude::{Channel, Object, Phys},
+};
 use solvent_fs::{process::Process, rpc::RpcNode, spawner};
 use solvent_rpc::{
     io::{self, file::PhysOptions, OpenOptions},
     sync::Client,
 };
+use svrt::HandleType;
 
 extern crate alloc;
 
@@ -42,7 +47,11 @@ async fn main() {
         .expect("Failed to build the process");
     log::debug!("Starting the root driver");
 
-    let node = RpcNode::new(|server, _| async move { device::handle_driver(server).await });
+    let mem_res = svrt::try_take_startup_handle(HandleType::MemRes.into()).ok();
+    // SAFETY: The handle is given to us by the program manager.
+    power::serve(mem_res.map(|res| unsafe { MemRes::from_raw(res) }));
+
+    let node = RpcNode::new(|server, _| async move { device::handle_bus(server).await });
     node.open_conn(spawner(), Default::default(), server);
 
     let ret = task.ajoin().await.expect("Failed to join the process");
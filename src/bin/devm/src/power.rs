@@ -0,0 +1,103 @@
+//! The power management of the system.
+//!
+//! The drivers register their power management on the bus. Before the system
+//! sleeps, they're suspended in the reverse order of their registration, so
+//! that the drivers of child devices are suspended before their parents, and
+//! they're resumed in the order after the system wakes up.
+
+use alloc::vec::Vec;
+
+use futures_lite::StreamExt;
+use solvent::{
+    dev::MemRes,
+    error::{Result, ENOSYS, EPIPE},
+    ipc::Channel,
+};
+use solvent_fs::{rpc::RpcNode, spawner};
+use solvent_rpc::{
+    ddk::power::{PowerSyncClient, SystemRequest, SystemServer},
+    Server,
+};
+use solvent_std::sync::{Arsc, Mutex};
+
+static DRIVERS: Mutex<Vec<PowerSyncClient>> = Mutex::new(Vec::new());
+
+/// Add the power management of a driver, whose client connection is `power`.
+pub fn register(power: Channel) {
+    DRIVERS.lock().push(PowerSyncClient::from(power));
+}
+
+/// Serve the power control of the system at `dev/power`, which is only able to
+/// suspend the system with the root memory resource `mem_res`.
+pub fn serve(mem_res: Option<MemRes>) {
+    let mem_res = Arsc::new(mem_res);
+    let (client, conn) = Channel::new();
+    let node = RpcNode::new(move |server, _| handle_system(Arsc::clone(&mem_res), server));
+    node.open_conn(spawner(), Default::default(), conn);
+    if let Err(err) = solvent_fs::fs::local().mount("dev/power", client.into()) {
+        log::warn!("failed to publish dev/power: {err:?}");
+    }
+}
+
+async fn handle_system(mem_res: Arsc<Option<MemRes>>, server: SystemServer) {
+    let (mut stream, _) = server.serve();
+    while let Some(request) = stream.next().await {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+        };
+
+        let res = match request {
+            SystemRequest::Suspend { responder } => {
+                responder.send(mem_res.as_ref().as_ref().map_or(Err(ENOSYS), suspend))
+            }
+            SystemRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+}
+
+fn flatten(res: core::result::Result<Result, solvent_rpc::Error>) -> Result {
+    res.map_err(|err| {
+        log::warn!("power RPC error: {err}");
+        EPIPE
+    })?
+}
+
+fn suspend(mem_res: &MemRes) -> Result {
+    let drivers = DRIVERS.lock();
+
+    let mut suspended = 0;
+    let mut ret = Ok(());
+    for driver in drivers.iter().rev() {
+        match flatten(driver.suspend()) {
+            Ok(()) => suspended += 1,
+            Err(err) => {
+                log::warn!("failed to suspend a driver: {err:?}");
+                ret = Err(err);
+                break;
+            }
+        }
+    }
+    if ret.is_ok() {
+        log::info!("Suspending the system");
+        ret = solvent::dev::suspend(mem_res);
+    }
+
+    let resumed = drivers.len() - suspended;
+    for driver in &drivers[resumed..] {
+        if let Err(err) = flatten(driver.resume()) {
+            log::warn!("failed to resume a driver: {err:?}");
+        }
+    }
+    ret
+}
@@ -4,7 +4,8 @@ use core::{
     ptr::NonNull,
 };
 
-use solvent_async::{global_executor, local_executor};
+use solvent::obj::Ref;
+use solvent_async::{dispatch, global_executor, local_executor};
 use solvent_ddk::ffi::VTable;
 use solvent_fs::fs;
 
@@ -31,6 +32,8 @@ pub fn vtable() -> VTable {
         global_exe: global_executor() as _,
         local_exe: local_executor(|exe| exe as *const _),
         local_fs: fs::local() as *const _,
+        dispatch,
+        root_virt: Ref::into_raw(svrt::root_virt()),
 
         alloc: __h2o_ddk_alloc,
         dealloc: __h2o_ddk_dealloc,
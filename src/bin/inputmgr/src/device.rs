@@ -0,0 +1,41 @@
+use alloc::string::String;
+
+use futures_lite::StreamExt;
+use solvent_rpc::{
+    input::{DeviceClient, DeviceDesc, DeviceEvent, INPUT_EVENT},
+    packet, Client,
+};
+use solvent_std::sync::Arsc;
+
+use crate::manager::Manager;
+
+/// Feed the input of the device `id` to the manager until the device is gone.
+pub async fn run(manager: Arsc<Manager>, id: u64, name: String, client: DeviceClient) {
+    let kind = match client.kind().await {
+        Ok(kind) => kind,
+        Err(err) => {
+            log::warn!("Failed to get the kind of {name}: {err}");
+            return;
+        }
+    };
+    let Some(mut events) = client.event_receiver() else {
+        log::warn!("The events of {name} are already taken");
+        return;
+    };
+
+    log::debug!("Added device {id}: {name} ({kind:?})");
+    manager.add_device(DeviceDesc { id, name, kind });
+
+    while let Some(event) = events.next().await {
+        let res = event.and_then(|DeviceEvent::Unknown(packet)| {
+            packet::deserialize(INPUT_EVENT, &packet, None)
+        });
+        match res {
+            Ok(input) => manager.input(input),
+            Err(err) => log::warn!("Failed to receive the input of device {id}: {err}"),
+        }
+    }
+
+    log::debug!("Removed device {id}");
+    manager.remove_device(id);
+}
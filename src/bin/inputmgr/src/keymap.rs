@@ -0,0 +1,162 @@
+//! The translation of keys into text in the keyboard layouts.
+
+use solvent_rpc::input::{Layout, Modifiers};
+
+/// The usage of the first key in the tables, `A` in the US layout.
+const FIRST: u16 = 0x04;
+
+/// The usage of the caps lock key.
+pub const CAPS_LOCK: u16 = 0x39;
+/// The usage of the left control key, after which are the other modifiers.
+pub const LEFT_CONTROL: u16 = 0xe0;
+
+/// The characters of the keys from [`FIRST`] to the slash key, without and
+/// with the shift key.
+type Table = [(char, char); 53];
+
+const US: Table = [
+    ('a', 'A'),
+    ('b', 'B'),
+    ('c', 'C'),
+    ('d', 'D'),
+    ('e', 'E'),
+    ('f', 'F'),
+    ('g', 'G'),
+    ('h', 'H'),
+    ('i', 'I'),
+    ('j', 'J'),
+    ('k', 'K'),
+    ('l', 'L'),
+    ('m', 'M'),
+    ('n', 'N'),
+    ('o', 'O'),
+    ('p', 'P'),
+    ('q', 'Q'),
+    ('r', 'R'),
+    ('s', 'S'),
+    ('t', 'T'),
+    ('u', 'U'),
+    ('v', 'V'),
+    ('w', 'W'),
+    ('x', 'X'),
+    ('y', 'Y'),
+    ('z', 'Z'),
+    ('1', '!'),
+    ('2', '@'),
+    ('3', '#'),
+    ('4', '$'),
+    ('5', '%'),
+    ('6', '^'),
+    ('7', '&'),
+    ('8', '*'),
+    ('9', '('),
+    ('0', ')'),
+    ('\n', '\n'),
+    ('\x1b', '\x1b'),
+    ('\x08', '\x08'),
+    ('\t', '\t'),
+    (' ', ' '),
+    ('-', '_'),
+    ('=', '+'),
+    ('[', '{'),
+    (']', '}'),
+    ('\\', '|'),
+    ('\\', '|'),
+    (';', ':'),
+    ('\'', '"'),
+    ('`', '~'),
+    (',', '<'),
+    ('.', '>'),
+    ('/', '?'),
+];
+
+/// The Dvorak layout, indexed by the positions of the keys in the US layout.
+const DVORAK: Table = [
+    ('a', 'A'),
+    ('x', 'X'),
+    ('j', 'J'),
+    ('e', 'E'),
+    ('.', '>'),
+    ('u', 'U'),
+    ('i', 'I'),
+    ('d', 'D'),
+    ('c', 'C'),
+    ('h', 'H'),
+    ('t', 'T'),
+    ('n', 'N'),
+    ('m', 'M'),
+    ('b', 'B'),
+    ('r', 'R'),
+    ('l', 'L'),
+    ('\'', '"'),
+    ('p', 'P'),
+    ('o', 'O'),
+    ('y', 'Y'),
+    ('g', 'G'),
+    ('k', 'K'),
+    (',', '<'),
+    ('q', 'Q'),
+    ('f', 'F'),
+    (';', ':'),
+    ('1', '!'),
+    ('2', '@'),
+    ('3', '#'),
+    ('4', '$'),
+    ('5', '%'),
+    ('6', '^'),
+    ('7', '&'),
+    ('8', '*'),
+    ('9', '('),
+    ('0', ')'),
+    ('\n', '\n'),
+    ('\x1b', '\x1b'),
+    ('\x08', '\x08'),
+    ('\t', '\t'),
+    (' ', ' '),
+    ('[', '{'),
+    (']', '}'),
+    ('/', '?'),
+    ('=', '+'),
+    ('\\', '|'),
+    ('\\', '|'),
+    ('s', 'S'),
+    ('-', '_'),
+    ('`', '~'),
+    ('w', 'W'),
+    ('v', 'V'),
+    ('z', 'Z'),
+];
+
+/// The modifier of the modifier key `usage`.
+pub fn modifier(usage: u16) -> Option<Modifiers> {
+    if !(LEFT_CONTROL..LEFT_CONTROL + 8).contains(&usage) {
+        return None;
+    }
+    // The left modifiers are followed by the right ones in the same order.
+    Some(match (usage - LEFT_CONTROL) % 4 {
+        0 => Modifiers::CONTROL,
+        1 => Modifiers::SHIFT,
+        2 => Modifiers::ALT,
+        _ => Modifiers::META,
+    })
+}
+
+/// Translate the key `usage` in `layout`.
+///
+/// Letters are turned into control characters while the control key is held.
+pub fn translate(layout: Layout, usage: u16, modifiers: Modifiers) -> Option<char> {
+    let table = match layout {
+        Layout::Us => &US,
+        Layout::Dvorak => &DVORAK,
+    };
+    let (lower, upper) = *table.get(usize::from(usage.checked_sub(FIRST)?))?;
+
+    let mut shift = modifiers.contains(Modifiers::SHIFT);
+    if lower.is_ascii_alphabetic() {
+        if modifiers.contains(Modifiers::CONTROL) {
+            return Some(char::from(lower as u8 - b'a' + 1));
+        }
+        shift ^= modifiers.contains(Modifiers::CAPS_LOCK);
+    }
+    Some(if shift { upper } else { lower })
+}
@@ -0,0 +1,47 @@
+//! The input manager, aggregating the input devices published by the drivers
+//! and sending their input to the focused client, such as the console.
+//!
+//! Key repeats and the translation of keys into text in the keyboard layouts
+//! are done here, so that the clients receive ready-to-use key events. The
+//! service is reached through the entry it's started with, which the program
+//! manager mounts at `use/inputmgr`.
+
+#![no_std]
+#![no_main]
+
+mod device;
+mod keymap;
+mod manager;
+mod repeat;
+mod serve;
+
+use futures_lite::future;
+use solvent::prelude::{Channel, Object};
+use solvent_async::sync::channel;
+use solvent_fs::{rpc::RpcNode, spawner};
+use solvent_std::sync::Arsc;
+use svrt::HandleType;
+
+use self::manager::Manager;
+
+extern crate alloc;
+
+async fn main() {
+    let entry = svrt::take_startup_handle(HandleType::ServiceEntry.into());
+    // SAFETY: The handle is given to us by the program manager.
+    let entry = unsafe { Channel::from_raw(entry) };
+    if let Err(err) = solvent_std::logging::attach("inputmgr") {
+        log::warn!("Failed to attach to the log service: {err:?}");
+    }
+
+    let (repeater, keys) = channel::unbounded();
+    let manager = Arsc::new(Manager::new(repeater));
+    solvent_async::spawn(repeat::run(Arsc::clone(&manager), keys)).detach();
+
+    let node = RpcNode::new(move |server, _| serve::handle(Arsc::clone(&manager), server));
+    node.open_conn(spawner(), Default::default(), entry);
+
+    future::pending::<()>().await
+}
+
+solvent_async::entry!(main, solvent_std, None);
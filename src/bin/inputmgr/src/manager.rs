@@ -0,0 +1,236 @@
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::time::Duration;
+
+use solvent_async::sync::channel::Sender;
+use solvent_rpc::{
+    input::{DeviceDesc, FocusEvent, InputEvent, KeyState, Layout, ManagerEventSender, Modifiers},
+    EventSender,
+};
+use solvent_std::sync::Mutex;
+
+use crate::keymap;
+
+const DEFAULT_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(33);
+
+struct Inner {
+    next_device: u64,
+    devices: BTreeMap<u64, DeviceDesc>,
+
+    next_conn: u64,
+    conns: BTreeMap<u64, ManagerEventSender>,
+    /// The connections that have been focused, the last of which is the
+    /// current focus.
+    focus: Vec<u64>,
+
+    layout: Layout,
+    /// The modifier keys held on all the keyboards, counted separately for
+    /// the left and the right ones.
+    held: [u32; 8],
+    caps_lock: bool,
+    /// The last pressed key, which is repeated until released.
+    repeating: Option<u16>,
+    delay: Duration,
+    interval: Duration,
+}
+
+impl Inner {
+    fn modifiers(&self) -> Modifiers {
+        let mut modifiers = Modifiers::empty();
+        for (usage, &count) in (keymap::LEFT_CONTROL..).zip(&self.held) {
+            if count > 0 {
+                modifiers |= keymap::modifier(usage).unwrap();
+            }
+        }
+        modifiers.set(Modifiers::CAPS_LOCK, self.caps_lock);
+        modifiers
+    }
+
+    fn send_focused(&mut self, event: FocusEvent) {
+        while let Some(&id) = self.focus.last() {
+            let sender = self.conns.get(&id).unwrap();
+            if sender.send(event.clone()).is_ok() {
+                break;
+            }
+            self.disconnect(id);
+        }
+    }
+
+    fn broadcast(&mut self, event: FocusEvent) {
+        let failed = { self.conns.iter() }
+            .filter(|(_, sender)| sender.send(event.clone()).is_err())
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        failed.into_iter().for_each(|id| self.disconnect(id));
+    }
+
+    fn disconnect(&mut self, id: u64) {
+        self.conns.remove(&id);
+        self.focus.retain(|&focus| focus != id);
+    }
+
+    fn key(&mut self, usage: u16, state: KeyState) {
+        let modifiers = self.modifiers();
+        let text = match state {
+            KeyState::Released => String::new(),
+            _ => { keymap::translate(self.layout, usage, modifiers) }
+                .map(String::from)
+                .unwrap_or_default(),
+        };
+        self.send_focused(FocusEvent::Key {
+            usage,
+            state,
+            modifiers,
+            text,
+        });
+    }
+}
+
+/// The state shared by all the devices and the connections.
+pub struct Manager {
+    inner: Mutex<Inner>,
+    /// Notifies the repeater of the key to be repeated from now on.
+    repeater: Sender<Option<u16>>,
+}
+
+impl Manager {
+    pub fn new(repeater: Sender<Option<u16>>) -> Self {
+        Manager {
+            inner: Mutex::new(Inner {
+                next_device: 1,
+                devices: BTreeMap::new(),
+                next_conn: 0,
+                conns: BTreeMap::new(),
+                focus: Vec::new(),
+                layout: Layout::Us,
+                held: [0; 8],
+                caps_lock: false,
+                repeating: None,
+                delay: DEFAULT_DELAY,
+                interval: DEFAULT_INTERVAL,
+            }),
+            repeater,
+        }
+    }
+
+    pub fn connect(&self, sender: ManagerEventSender) -> u64 {
+        let mut inner = self.inner.lock();
+        let id = inner.next_conn;
+        inner.next_conn += 1;
+        inner.conns.insert(id, sender);
+        id
+    }
+
+    #[inline]
+    pub fn disconnect(&self, id: u64) {
+        self.inner.lock().disconnect(id)
+    }
+
+    pub fn focus(&self, id: u64) {
+        let mut inner = self.inner.lock();
+        inner.focus.retain(|&focus| focus != id);
+        inner.focus.push(id);
+    }
+
+    #[inline]
+    pub fn set_layout(&self, layout: Layout) {
+        self.inner.lock().layout = layout
+    }
+
+    pub fn set_repeat(&self, delay: Duration, interval: Duration) {
+        let mut inner = self.inner.lock();
+        inner.delay = delay;
+        inner.interval = interval;
+    }
+
+    /// The delay before the first repeat and the interval between the
+    /// repeats, or `None` if the repeats are disabled.
+    pub fn repeat(&self) -> Option<(Duration, Duration)> {
+        let inner = self.inner.lock();
+        (!inner.interval.is_zero()).then_some((inner.delay, inner.interval))
+    }
+
+    /// Allocate the id of a device, which is not listed until it's added.
+    pub fn reserve_device(&self) -> u64 {
+        let mut inner = self.inner.lock();
+        let id = inner.next_device;
+        inner.next_device += 1;
+        id
+    }
+
+    pub fn add_device(&self, desc: DeviceDesc) {
+        let mut inner = self.inner.lock();
+        inner.devices.insert(desc.id, desc.clone());
+        inner.broadcast(FocusEvent::DeviceAdded(desc));
+    }
+
+    pub fn remove_device(&self, id: u64) {
+        let mut inner = self.inner.lock();
+        if inner.devices.remove(&id).is_some() {
+            inner.broadcast(FocusEvent::DeviceRemoved { id });
+        }
+    }
+
+    pub fn devices(&self) -> Vec<DeviceDesc> {
+        self.inner.lock().devices.values().cloned().collect()
+    }
+
+    /// Dispatch the input of a device to the focused connection.
+    pub fn input(&self, events: Vec<InputEvent>) {
+        let mut inner = self.inner.lock();
+        for event in events {
+            let event = match event {
+                InputEvent::Key { usage, pressed } => {
+                    self.key(&mut inner, usage, pressed);
+                    continue;
+                }
+                InputEvent::Motion { dx, dy } => FocusEvent::Motion { dx, dy },
+                InputEvent::Button { button, pressed } => FocusEvent::Button { button, pressed },
+                InputEvent::Wheel { delta } => FocusEvent::Wheel { delta },
+            };
+            inner.send_focused(event);
+        }
+    }
+
+    fn key(&self, inner: &mut Inner, usage: u16, pressed: bool) {
+        if keymap::modifier(usage).is_some() {
+            let count = &mut inner.held[usize::from(usage - keymap::LEFT_CONTROL)];
+            *count = if pressed {
+                *count + 1
+            } else {
+                count.saturating_sub(1)
+            };
+        } else if usage == keymap::CAPS_LOCK && pressed {
+            inner.caps_lock = !inner.caps_lock;
+        }
+
+        let state = if pressed {
+            KeyState::Pressed
+        } else {
+            KeyState::Released
+        };
+        inner.key(usage, state);
+
+        // Only the keys producing text are repeated.
+        let repeating = if pressed {
+            let text = keymap::translate(inner.layout, usage, inner.modifiers());
+            text.map(|_| usage)
+        } else if inner.repeating == Some(usage) {
+            None
+        } else {
+            return;
+        };
+        if inner.repeating != repeating {
+            inner.repeating = repeating;
+            let _ = self.repeater.try_send(repeating);
+        }
+    }
+
+    /// Repeat `usage` if it's still held.
+    pub fn repeat_key(&self, usage: u16) {
+        let mut inner = self.inner.lock();
+        if inner.repeating == Some(usage) {
+            inner.key(usage, KeyState::Repeated);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use futures_lite::future;
+use solvent::time::Instant;
+use solvent_async::{sync::channel::Receiver, time::Sleep};
+use solvent_std::sync::Arsc;
+
+use crate::manager::Manager;
+
+/// Repeat the held key, which is given by the manager whenever it changes.
+pub async fn run(manager: Arsc<Manager>, keys: Receiver<Option<u16>>) {
+    let mut next: Option<(u16, Instant)> = None;
+    loop {
+        let key = match next {
+            Some((_, deadline)) => {
+                let key = async { Some(keys.recv().await) };
+                let repeat = async {
+                    Sleep::new(deadline).await;
+                    None
+                };
+                future::or(key, repeat).await
+            }
+            None => Some(keys.recv().await),
+        };
+
+        next = match key {
+            Some(Ok(key)) => key
+                .zip(manager.repeat())
+                .map(|(usage, (delay, _))| (usage, Instant::now() + delay)),
+            Some(Err(_)) => break,
+            None => {
+                let (usage, deadline) = next.unwrap();
+                manager.repeat_key(usage);
+                manager
+                    .repeat()
+                    .map(|(_, interval)| (usage, deadline + interval))
+            }
+        };
+    }
+}
@@ -0,0 +1,66 @@
+use core::time::Duration;
+
+use futures_lite::StreamExt;
+use solvent_rpc::{
+    input::{DeviceClient, ManagerRequest, ManagerServer},
+    Server,
+};
+use solvent_std::sync::Arsc;
+
+use crate::{device, manager::Manager};
+
+pub async fn handle(manager: Arsc<Manager>, server: ManagerServer) {
+    let (mut stream, sender) = server.serve();
+    let conn = manager.connect(sender);
+
+    while let Some(request) = stream.next().await {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+        };
+
+        let res = match request {
+            ManagerRequest::AddDevice {
+                name,
+                device,
+                responder,
+            } => {
+                let id = manager.reserve_device();
+                let client = DeviceClient::new(solvent_async::ipc::Channel::new(device));
+                let task = device::run(Arsc::clone(&manager), id, name, client);
+                solvent_async::spawn(task).detach();
+                responder.send(Ok(id))
+            }
+            ManagerRequest::Devices { responder } => responder.send(manager.devices()),
+            ManagerRequest::Focus { responder } => {
+                manager.focus(conn);
+                responder.send(())
+            }
+            ManagerRequest::SetLayout { layout, responder } => {
+                manager.set_layout(layout);
+                responder.send(())
+            }
+            ManagerRequest::SetRepeat {
+                delay_ms,
+                interval_ms,
+                responder,
+            } => {
+                let delay = Duration::from_millis(delay_ms);
+                manager.set_repeat(delay, Duration::from_millis(interval_ms));
+                responder.send(())
+            }
+            ManagerRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+    manager.disconnect(conn);
+}
@@ -0,0 +1,197 @@
+//! The IPC benchmarks, run once by the program manager after booting if the
+//! kernel is built with `--cfg bench`.
+//!
+//! The program manager starts another instance with the `echo` argument as
+//! the peer of the cross-space benchmark, connected through the entries of
+//! both, so that the round trips switch between the address spaces.
+//!
+//! The contention benchmarks share a synchronous RPC client among several
+//! threads, measuring the cost of multiplexing their calls on one channel.
+//!
+//! Every result is logged as `bench: <name> = <value> <unit>`, and the end of
+//! the suite as `bench result: done`, which `cargo xtask bench` collects from
+//! the serial log and compares against the checked-in baseline.
+
+#![no_std]
+#![no_main]
+
+use alloc::{format, vec, vec::Vec};
+use core::time::Duration;
+
+use futures_lite::StreamExt;
+use solvent::{
+    ipc::{Event, Packet},
+    prelude::{Channel, Handle, Object},
+    time::Instant,
+};
+use solvent_async::{ipc::Channel as AsyncChannel, sync::channel};
+use solvent_rpc::{
+    bench::{EchoClient, EchoRequest, EchoServer, EchoSyncClient},
+    Server,
+};
+use solvent_std::thread;
+use svrt::HandleType;
+
+extern crate alloc;
+
+const ROUNDS: u32 = 10000;
+/// The buffer sizes of the throughput benchmarks.
+const SIZES: &[usize] = &[64, 1024, 4096, 16384];
+/// The handles transferred in every round trip of the handle benchmark.
+const HANDLES: usize = 8;
+/// The numbers of the threads sharing a client in the contention benchmarks.
+const THREADS: &[usize] = &[1, 2, 4];
+
+async fn main() {
+    let peer = svrt::take_startup_handle(HandleType::ServiceEntry.into());
+    // SAFETY: The handle is given to us by the program manager.
+    let peer = AsyncChannel::new(unsafe { Channel::from_raw(peer) });
+    if solvent_std::env::args().any(|arg| arg == "echo") {
+        return echo(peer).await;
+    }
+
+    let latency = round_trip(Vec::new(), Vec::new()).await;
+    report("pingpong_latency", latency.as_nanos(), "ns");
+
+    for &size in SIZES {
+        let time = round_trip(vec![0; size], Vec::new()).await;
+        // The buffer is transferred twice in a round trip.
+        let bytes = 2 * size as u128 * 1_000_000_000;
+        let rate = bytes / time.as_nanos().max(1) / (1 << 20);
+        report(&format!("throughput_{size}"), rate, "MiB/s");
+    }
+
+    let handles = (0..HANDLES)
+        .map(|_| Event::into_raw(Event::new(0)))
+        .collect();
+    let time = round_trip(Vec::new(), handles).await;
+    let cost = time.saturating_sub(latency).as_nanos() / (2 * HANDLES) as u128;
+    report("handle_transfer", cost, "ns");
+
+    let time = rpc_round_trip().await;
+    report("rpc_round_trip", time.as_nanos(), "ns");
+
+    for &threads in THREADS {
+        let time = rpc_contention(threads).await;
+        report(&format!("rpc_contention_{threads}"), time.as_nanos(), "ns");
+    }
+
+    let time = bounce(&peer, &mut Packet::default()).await;
+    drop(peer);
+    report("cross_space_latency", time.as_nanos(), "ns");
+
+    log::info!("bench result: done");
+}
+
+fn report(name: &str, value: u128, unit: &str) {
+    log::info!("bench: {name} = {value} {unit}");
+}
+
+/// Send every packet received back until the channel is closed.
+async fn echo(channel: AsyncChannel) {
+    let mut packet = Packet::default();
+    while channel.receive(&mut packet).await.is_ok() {
+        if channel.send(&mut packet).is_err() {
+            break;
+        }
+    }
+}
+
+/// The average time of bouncing a packet of `buffer` and `handles` off an
+/// echo task.
+async fn round_trip(buffer: Vec<u8>, handles: Vec<Handle>) -> Duration {
+    let (client, server) = Channel::new();
+    let client = AsyncChannel::new(client);
+    let echo = solvent_async::spawn(echo(AsyncChannel::new(server)));
+
+    let mut packet = Packet {
+        buffer,
+        handles,
+        ..Default::default()
+    };
+    let time = bounce(&client, &mut packet).await;
+
+    drop(client);
+    echo.await;
+    for handle in packet.handles {
+        // SAFETY: The handles are the events transferred back.
+        drop(unsafe { Event::from_raw(handle) });
+    }
+    time
+}
+
+/// The average time of bouncing `packet` off the echo peer of `client`.
+async fn bounce(client: &AsyncChannel, packet: &mut Packet) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        client.send(packet).expect("Failed to send the packet");
+        let ret = client.receive(packet).await;
+        ret.expect("Failed to receive the packet");
+    }
+    start.elapsed() / ROUNDS
+}
+
+/// Serve the echo calls until the client is dropped.
+async fn serve_echo(server: EchoServer) {
+    let (mut stream, _) = server.serve();
+    while let Some(Ok(request)) = stream.next().await {
+        if let EchoRequest::Echo { data, responder } = request {
+            let _ = responder.send(data);
+        }
+    }
+}
+
+/// The average time of an echo call through `solvent_rpc`.
+async fn rpc_round_trip() -> Duration {
+    let (client, server) = Channel::new();
+    let server = EchoServer::from(AsyncChannel::new(server));
+    let serve = solvent_async::spawn(serve_echo(server));
+    let client = EchoClient::from(AsyncChannel::new(client));
+
+    let data = vec![0; 64];
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let ret = client.echo(data.clone()).await;
+        ret.expect("Failed to call the echo service");
+    }
+    let time = start.elapsed() / ROUNDS;
+
+    drop(client);
+    serve.await;
+    time
+}
+
+/// The average time of an echo call through a synchronous client shared by
+/// `threads` threads calling concurrently.
+async fn rpc_contention(threads: usize) -> Duration {
+    let (client, server) = Channel::new();
+    let server = EchoServer::from(AsyncChannel::new(server));
+    let serve = solvent_async::spawn(serve_echo(server));
+    let client = EchoSyncClient::from(client);
+
+    let rounds = ROUNDS / threads as u32;
+    let (tx, rx) = channel::bounded(threads);
+    let start = Instant::now();
+    for _ in 0..threads {
+        let client = client.clone();
+        let tx = tx.clone();
+        thread::spawn(move || {
+            let data = vec![0; 64];
+            for _ in 0..rounds {
+                let ret = client.echo(data.clone());
+                ret.expect("Failed to call the echo service");
+            }
+            let _ = tx.send_blocking(());
+        });
+    }
+    drop(tx);
+    // The executor keeps serving while the threads are waited for.
+    while rx.recv().await.is_ok() {}
+    let time = start.elapsed() / (rounds * threads as u32);
+
+    drop(client);
+    serve.await;
+    time
+}
+
+solvent_async::entry!(main, solvent_std, None);
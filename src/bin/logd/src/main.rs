@@ -0,0 +1,205 @@
+//! The log service, draining the shared-memory log rings of the tasks.
+//!
+//! A task attaches through the entry the service is started with, which the
+//! program manager mounts at `use/logd`, and gets a ring of its own and the
+//! doorbell shared by all the rings. The rings are drained whenever the
+//! doorbell is notified, and every [`DRAIN_INTERVAL`] otherwise.
+//!
+//! The records of a drain are merged by their timestamps, labelled with the
+//! names of their tasks, and written to the kernel log in batches, along with
+//! the losses detected by the gaps between the sequence numbers. A ring is
+//! dropped after its last drain once the connection it's attached over is
+//! closed.
+
+#![no_std]
+#![no_main]
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+    time::Duration,
+};
+
+use dbglog::ring::{Reader, RING_SIZE, SIG_DOORBELL};
+use futures_lite::StreamExt;
+use solvent::{
+    error::Error,
+    ipc::Event,
+    mem::{Flags, Phys, PhysOptions},
+    prelude::{Channel, Object},
+    time::Instant,
+};
+use solvent_async::ipc::AsyncObject;
+use solvent_fs::{rpc::RpcNode, spawner};
+use solvent_rpc::{
+    logging::{LogRequest, LogServer},
+    Server,
+};
+use solvent_std::sync::{Lazy, Mutex};
+use svrt::HandleType;
+
+extern crate alloc;
+
+const DRAIN_INTERVAL: Duration = Duration::from_millis(100);
+/// The bytes written to the kernel log in a syscall.
+const BATCH: usize = 1024;
+
+static DOORBELL: Lazy<Event> = Lazy::new(|| Event::new(0));
+static RINGS: Mutex<BTreeMap<u64, Ring>> = Mutex::new(BTreeMap::new());
+
+struct Ring {
+    name: String,
+    reader: Reader,
+    next_seq: u64,
+    mapping: NonNull<[u8]>,
+}
+
+// SAFETY: The mapping is only accessed through the reader.
+unsafe impl Send for Ring {}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        let _ = svrt::root_virt().unmap(self.mapping.cast(), self.mapping.len(), false);
+    }
+}
+
+async fn main() {
+    let entry = svrt::take_startup_handle(HandleType::ServiceEntry.into());
+    // SAFETY: The handle is given to us by the program manager.
+    let entry = unsafe { Channel::from_raw(entry) };
+
+    let node = RpcNode::new(|server, _| async move { handle(server).await });
+    node.open_conn(spawner(), Default::default(), entry);
+
+    let interval = async {
+        loop {
+            if let Err(err) = solvent_async::time::sleep(DRAIN_INTERVAL).await {
+                log::warn!("Failed to sleep: {err:?}");
+            }
+            drain();
+        }
+    };
+    solvent_async::spawn(interval).detach();
+
+    let disp = solvent_async::dispatch();
+    loop {
+        if let Err(err) = DOORBELL.try_wait_with(&disp, true, SIG_DOORBELL).await {
+            log::warn!("Failed to wait for the doorbell: {err:?}");
+        }
+        // Clear the doorbell before draining so that no notification is missed.
+        let _ = DOORBELL.notify(SIG_DOORBELL, 0);
+        drain();
+    }
+}
+
+/// Drain all the rings, and write the merged records to the kernel log.
+fn drain() {
+    let mut lines = Vec::<(Instant, String)>::new();
+    let mut rings = RINGS.lock();
+    for ring in rings.values_mut() {
+        let Ring {
+            name,
+            reader,
+            next_seq,
+            ..
+        } = ring;
+        let res = reader.drain(|record| {
+            if record.seq > *next_seq {
+                let lost = record.seq - *next_seq;
+                let line = format!("[{}] {name}: {lost} records lost", record.time);
+                lines.push((record.time, line));
+            }
+            *next_seq = record.seq + 1;
+            let msg = String::from_utf8_lossy(record.msg);
+            let line = format!("[{}] {name} {}: {msg}", record.time, record.level);
+            lines.push((record.time, line));
+        });
+        if res.is_err() {
+            log::warn!("The ring of {name} is corrupted, dropping its records");
+        }
+    }
+
+    lines.sort_by_key(|(time, _)| *time);
+    let mut batch = String::new();
+    for (_, line) in lines {
+        if !batch.is_empty() && batch.len() + 1 + line.len() > BATCH {
+            write_batch(&mut batch);
+        }
+        if !batch.is_empty() {
+            batch.push('\n');
+        }
+        batch.push_str(&line);
+    }
+    write_batch(&mut batch);
+    // Keep the rings locked until written so that concurrent drains don't
+    // interleave.
+    drop(rings);
+}
+
+fn write_batch(batch: &mut String) {
+    if !batch.is_empty() {
+        // The kernel counts the batches dropped by its rate limit.
+        let _ = dbglog::write_raw(batch);
+        batch.clear();
+    }
+}
+
+fn attach(name: String) -> Result<(u64, (Phys, Event)), Error> {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    let doorbell = Event::try_clone(&DOORBELL)?;
+    let phys = Phys::allocate(RING_SIZE, PhysOptions::ZEROED)?;
+    let flags = Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS;
+    let mapping = svrt::root_virt().map_phys(None, phys.clone(), flags)?;
+    let ring = Ring {
+        name,
+        // SAFETY: The ring is freshly mapped, and only read here.
+        reader: unsafe { Reader::new(mapping) },
+        next_seq: 0,
+        mapping,
+    };
+
+    let id = NEXT_ID.fetch_add(1, Relaxed);
+    log::debug!("{} attached", ring.name);
+    RINGS.lock().insert(id, ring);
+    Ok((id, (phys, doorbell)))
+}
+
+async fn handle(server: LogServer) {
+    let (mut stream, _) = server.serve();
+    let mut attached = Vec::new();
+    while let Some(request) = stream.next().await {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+        };
+        let res = match request {
+            LogRequest::Attach { name, responder } => {
+                let res = attach(name).map(|(id, ret)| {
+                    attached.push(id);
+                    ret
+                });
+                responder.send(res)
+            }
+            LogRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+
+    drain();
+    let mut rings = RINGS.lock();
+    for id in attached {
+        rings.remove(&id);
+    }
+}
+
+solvent_async::entry!(main, solvent_std, None);
@@ -0,0 +1,38 @@
+use alloc::vec;
+use core::time::Duration;
+
+use solvent::mem::mem_info;
+use solvent_rpc::metrics::{MetricKind, Sample};
+
+use crate::registry::REGISTRY;
+
+const SOURCE: &str = "kernel";
+const INTERVAL: Duration = Duration::from_secs(1);
+
+fn gauge(name: &str, value: usize) -> Sample {
+    Sample {
+        name: name.into(),
+        kind: MetricKind::Gauge,
+        value: value as u64,
+    }
+}
+
+/// Read the statistics of the kernel into the registry periodically.
+pub async fn read_stats() {
+    loop {
+        match mem_info() {
+            Ok(info) => REGISTRY.push(
+                SOURCE.into(),
+                vec![
+                    gauge("mem.all_available", info.all_available),
+                    gauge("mem.current_used", info.current_used),
+                ],
+            ),
+            Err(err) => log::warn!("Failed to read the memory info: {err}"),
+        }
+        if let Err(err) = solvent_async::time::sleep(INTERVAL).await {
+            log::warn!("Failed to sleep, stopping reading the kernel stats: {err}");
+            break;
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! The metrics service, aggregating the counters and gauges pushed by the
+//! components of the system and the RPC statistics reported by processes.
+//!
+//! The service is reached through the entry it's started with, which the
+//! program manager mounts at `use/metrics`.
+
+#![no_std]
+#![no_main]
+
+mod kernel;
+mod registry;
+mod serve;
+
+use futures_lite::future;
+use solvent::prelude::{Channel, Object};
+use solvent_fs::{rpc::RpcNode, spawner};
+use svrt::HandleType;
+
+extern crate alloc;
+
+async fn main() {
+    let entry = svrt::take_startup_handle(HandleType::ServiceEntry.into());
+    // SAFETY: The handle is given to us by the program manager.
+    let entry = unsafe { Channel::from_raw(entry) };
+
+    solvent_async::spawn(kernel::read_stats()).detach();
+
+    let node = RpcNode::new(|server, _| async move { serve::handle(server).await });
+    node.open_conn(spawner(), Default::default(), entry);
+
+    future::pending::<()>().await
+}
+
+solvent_async::entry!(main, solvent_std, None);
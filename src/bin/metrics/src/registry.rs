@@ -0,0 +1,84 @@
+use alloc::{
+    collections::{btree_map::Entry, BTreeMap},
+    string::String,
+    vec::Vec,
+};
+
+use solvent_rpc::metrics::{MethodStats, MetricKind, Sample, Snapshot};
+use solvent_std::sync::Mutex;
+
+pub static REGISTRY: Registry = Registry::new();
+
+#[derive(Default)]
+struct Source {
+    samples: BTreeMap<String, (MetricKind, u64)>,
+    rpc: BTreeMap<(bool, usize), MethodStats>,
+}
+
+impl Source {
+    fn snapshot(&self, name: &str) -> Snapshot {
+        Snapshot {
+            source: name.into(),
+            samples: { self.samples.iter() }
+                .map(|(name, &(kind, value))| Sample {
+                    name: name.clone(),
+                    kind,
+                    value,
+                })
+                .collect(),
+            rpc: self.rpc.values().copied().collect(),
+        }
+    }
+}
+
+pub struct Registry {
+    sources: Mutex<BTreeMap<String, Source>>,
+}
+
+impl Registry {
+    const fn new() -> Self {
+        Registry {
+            sources: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Add the counters to and replace the gauges with the samples of
+    /// `source`.
+    ///
+    /// A metric pushed with another kind than before is replaced.
+    pub fn push(&self, source: String, samples: Vec<Sample>) {
+        let mut sources = self.sources.lock();
+        let source = sources.entry(source).or_default();
+        for Sample { name, kind, value } in samples {
+            match source.samples.entry(name) {
+                Entry::Occupied(mut ent) => {
+                    let (old_kind, old_value) = ent.get_mut();
+                    if kind == MetricKind::Counter && *old_kind == kind {
+                        *old_value = old_value.saturating_add(value);
+                    } else {
+                        *ent.get_mut() = (kind, value);
+                    }
+                }
+                Entry::Vacant(ent) => {
+                    ent.insert((kind, value));
+                }
+            }
+        }
+    }
+
+    /// Replace the RPC statistics of `source`, which are cumulative.
+    pub fn report(&self, source: String, stats: Vec<MethodStats>) {
+        let mut sources = self.sources.lock();
+        let source = sources.entry(source).or_default();
+        source
+            .rpc
+            .extend(stats.into_iter().map(|s| ((s.server, s.method), s)));
+    }
+
+    pub fn snapshots(&self) -> Vec<Snapshot> {
+        let sources = self.sources.lock();
+        { sources.iter() }
+            .map(|(name, source)| source.snapshot(name))
+            .collect()
+    }
+}
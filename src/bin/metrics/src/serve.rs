@@ -0,0 +1,90 @@
+use core::time::Duration;
+
+use futures_lite::{future, StreamExt};
+use solvent::time::Instant;
+use solvent_async::time::Sleep;
+use solvent_rpc::{
+    metrics::{MetricsRequest, MetricsServer, REPORT_EVENT},
+    packet, Error, Server,
+};
+
+use crate::registry::REGISTRY;
+
+enum Next {
+    Request(Option<Result<MetricsRequest, Error>>),
+    Report,
+}
+
+pub async fn handle(server: MetricsServer) {
+    let (mut stream, sender) = server.serve();
+    let mut interval = None;
+    let mut deadline = Instant::now();
+    loop {
+        let next = match interval {
+            Some(_) => {
+                let request = async { Next::Request(stream.next().await) };
+                let report = async {
+                    Sleep::new(deadline).await;
+                    Next::Report
+                };
+                future::or(request, report).await
+            }
+            None => Next::Request(stream.next().await),
+        };
+
+        let request = match next {
+            Next::Request(Some(Ok(request))) => request,
+            Next::Request(Some(Err(err))) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+            Next::Request(None) => break,
+            Next::Report => {
+                deadline += interval.unwrap_or_default();
+                let mut packet = Default::default();
+                let res = packet::serialize(REPORT_EVENT, REGISTRY.snapshots(), &mut packet)
+                    .and_then(|_| sender.send_raw(packet));
+                if let Err(err) = res {
+                    log::warn!("Failed to send the report: {err}");
+                }
+                continue;
+            }
+        };
+
+        let res = match request {
+            MetricsRequest::Report {
+                name,
+                stats,
+                responder,
+            } => {
+                REGISTRY.report(name, stats);
+                responder.send(())
+            }
+            MetricsRequest::Push {
+                source,
+                samples,
+                responder,
+            } => {
+                REGISTRY.push(source, samples);
+                responder.send(())
+            }
+            MetricsRequest::Query { responder } => responder.send(REGISTRY.snapshots()),
+            MetricsRequest::Subscribe {
+                interval_ms,
+                responder,
+            } => {
+                interval = (interval_ms > 0).then(|| Duration::from_millis(interval_ms));
+                deadline = Instant::now() + interval.unwrap_or_default();
+                responder.send(())
+            }
+            MetricsRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+}
@@ -1,18 +1,23 @@
-use alloc::{borrow::ToOwned, vec::Vec};
+use alloc::{borrow::ToOwned, vec, vec::Vec};
 use core::{ffi::CStr, ptr::NonNull};
 
 use either::Either;
-use solvent::prelude::{Flags, Object, Phys, PAGE_MASK};
+use solvent::prelude::{Flags, Object, Phys, PhysOptions, PAGE_MASK};
 use solvent_fs::{
     entry::Entry,
     fs,
     mem::{
         dir::{RecursiveBuild, RecursiveBuilder},
         file::MemFile,
+        overlay::OverlayDir,
+        quota::Quota,
     },
 };
 use solvent_rpc::{
-    io::{dir::Directory, OpenOptions, Permission},
+    io::{
+        dir::{Directory, DirectorySyncClient},
+        Error, OpenOptions, Permission,
+    },
     Protocol,
 };
 use solvent_std::{
@@ -21,6 +26,9 @@ use solvent_std::{
 };
 use svrt::HandleType;
 
+/// The writable directories created in the root namespace.
+const ROOT_DIRS: &[&str] = &["etc", "tmp"];
+
 /// # Safety
 ///
 /// The caller must ensure `dir` is the sub slice of `root_virt` and
@@ -50,6 +58,17 @@ unsafe fn build_inner(
                 ret.append(&mut build_inner(root_phys, base, dir_slice));
                 ret.push(RecursiveBuild::Up);
             }
+            Either::Left(_) if dir_entry.compression() != bootfs::Compression::None => {
+                let mut raw = vec![0; dir_entry.raw_len()];
+                let len = dir_entry
+                    .decompress(&mut raw)
+                    .expect("Failed to decompress the file");
+                let data = Phys::allocate((len + PAGE_MASK) & !PAGE_MASK, PhysOptions::ZEROED)
+                    .expect("Failed to allocate phys");
+                unsafe { data.write(0, &raw[..len]) }.expect("Failed to write the file");
+                let file = MemFile::new(data, Permission::READ | Permission::EXECUTE);
+                ret.push(RecursiveBuild::Entry(name, Arsc::new(file)));
+            }
             Either::Left(data) => {
                 let offset = unsafe { data.as_ptr().offset_from(base.as_ptr()) as usize };
                 assert!(
@@ -90,6 +109,36 @@ fn builder(root_phys: &Phys) -> Vec<RecursiveBuild> {
     }
 }
 
+fn insert_file(_: &str, quota: Option<&Arsc<Quota>>) -> Result<Arsc<dyn Entry>, Error> {
+    let phys =
+        Phys::allocate(0, PhysOptions::ZEROED | PhysOptions::RESIZABLE).map_err(Error::Other)?;
+    let perm = Permission::READ | Permission::WRITE;
+    let file = match quota {
+        Some(quota) => MemFile::with_quota(phys, perm, quota)?,
+        None => MemFile::new(phys, perm),
+    };
+    Ok(Arsc::new(file))
+}
+
+fn open(entry: Arsc<dyn Entry>, path: &Path, options: OpenOptions) -> DirectorySyncClient {
+    let (client, server) = Directory::sync_channel();
+    entry
+        .open(
+            solvent_fs::spawner(),
+            Default::default(),
+            path,
+            options,
+            server.try_into().unwrap(),
+        )
+        .expect("Failed to open a connection");
+    client
+}
+
+/// Mount the bootfs read-only at `boot`, and its overlay with a writable
+/// memfs as the root namespace.
+///
+/// The root of the local namespace can't be a mount point, so every top-level
+/// entry of the overlay is mounted instead.
 pub fn mount() {
     static MOUNT: Once = Once::new();
     MOUNT.call_once(|| {
@@ -100,18 +149,30 @@ pub fn mount() {
             .build(Permission::READ | Permission::EXECUTE)
             .expect("Failed to build the bootfs dir");
 
-        let (client, server) = Directory::sync_channel();
-        bootfs
-            .open(
-                solvent_fs::spawner(),
-                Default::default(),
-                Path::new(""),
-                OpenOptions::READ | OpenOptions::EXECUTE,
-                server.try_into().unwrap(),
-            )
-            .expect("Failed to open a connection");
+        let client = open(
+            bootfs.clone(),
+            Path::new(""),
+            OpenOptions::READ | OpenOptions::EXECUTE,
+        );
         fs::local()
             .mount("boot", client.into())
             .expect("Failed to mount to vfs");
+
+        let perm = Permission::READ | Permission::WRITE | Permission::EXECUTE;
+        let root = Arsc::new(OverlayDir::new(bootfs, perm, Arsc::new(insert_file)));
+        let options = OpenOptions::READ | OpenOptions::WRITE | OpenOptions::EXECUTE;
+        for dir in ROOT_DIRS {
+            drop(open(
+                root.clone(),
+                Path::new(dir),
+                options | OpenOptions::CREATE,
+            ));
+        }
+        for name in root.names() {
+            let client = open(root.clone(), Path::new(&name), options);
+            fs::local()
+                .mount(&name, client.into())
+                .expect("Failed to mount to vfs");
+        }
     })
 }
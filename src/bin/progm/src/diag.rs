@@ -0,0 +1,130 @@
+//! The diagnostics bundles of the crash-looping services, kept in a writable
+//! in-memory directory mounted at `diag`.
+//!
+//! The kernel log and the handle tables of other processes can't be read from
+//! userspace yet, so a bundle only holds what the supervisor observes: the
+//! exit code, the panic report and the restart history.
+
+use alloc::{collections::VecDeque, format, string::String};
+use core::{fmt::Write, time::Duration};
+
+use solvent::{
+    prelude::{Phys, PhysOptions},
+    time::Instant,
+};
+use solvent_async::ipc::Channel as AsyncChannel;
+use solvent_fs::{
+    entry::Entry,
+    fs,
+    mem::{dir::MemDirMut, file::MemFile, quota::Quota},
+    spawner,
+};
+use solvent_rpc::{
+    io::{dir::Directory, file::FileClient, Error, OpenOptions, Permission},
+    Protocol,
+};
+use solvent_std::{
+    path::Path,
+    sync::{Arsc, Lazy},
+};
+use svrt::PanicReport;
+
+const DIAG_BYTES: usize = 1 << 20;
+const DIAG_INODES: usize = 64;
+/// The bytes written in a request.
+const CHUNK: usize = 64;
+
+static DIAG: Lazy<Arsc<MemDirMut>> = Lazy::new(|| {
+    let dir = MemDirMut::new(
+        Permission::READ | Permission::WRITE,
+        "".into(),
+        Arsc::new(insert_file),
+    );
+    Arsc::new(dir.with_quota(Quota::new(None, DIAG_BYTES, DIAG_INODES)))
+});
+
+fn insert_file(_: &str, quota: Option<&Arsc<Quota>>) -> Result<Arsc<dyn Entry>, Error> {
+    let phys =
+        Phys::allocate(0, PhysOptions::ZEROED | PhysOptions::RESIZABLE).map_err(Error::Other)?;
+    let perm = Permission::READ | Permission::WRITE;
+    let file = match quota {
+        Some(quota) => MemFile::with_quota(phys, perm, quota)?,
+        None => MemFile::new(phys, perm),
+    };
+    Ok(Arsc::new(file))
+}
+
+pub fn mount() {
+    let (client, server) = Directory::sync_channel();
+    DIAG.clone()
+        .open(
+            spawner(),
+            Default::default(),
+            Path::new(""),
+            OpenOptions::READ | OpenOptions::WRITE,
+            server.try_into().unwrap(),
+        )
+        .expect("Failed to open a connection");
+    fs::local()
+        .mount("diag", client.into())
+        .expect("Failed to mount to vfs");
+}
+
+/// What the supervisor knows about the last exit of a service.
+pub struct Bundle<'a> {
+    pub name: &'a str,
+    pub retval: usize,
+    pub runtime: Duration,
+    pub report: Option<&'a PanicReport>,
+    /// The times of the recent failures.
+    pub failures: &'a VecDeque<Instant>,
+}
+
+impl Bundle<'_> {
+    fn render(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "service: {}", self.name);
+        let _ = writeln!(text, "exit code: {:#x}", self.retval);
+        let _ = writeln!(text, "runtime: {:?}", self.runtime);
+        let _ = writeln!(text, "recent failures:");
+        for time in self.failures {
+            let _ = writeln!(text, "  {time}");
+        }
+        match self.report {
+            Some(report) => {
+                let _ = writeln!(text, "panic: {report}");
+            }
+            None => {
+                let _ = writeln!(text, "panic: none");
+            }
+        }
+        text
+    }
+}
+
+/// Write `bundle` to `diag/<name>`, replacing the previous one of the
+/// service, and return its path.
+pub async fn capture(bundle: Bundle<'_>) -> Result<String, Error> {
+    let (client, conn) = solvent_fs::file::channel();
+    let options = OpenOptions::READ
+        | OpenOptions::WRITE
+        | OpenOptions::CREATE
+        | OpenOptions::TRUNCATE
+        | OpenOptions::EXPECT_FILE;
+    DIAG.clone().open(
+        spawner(),
+        Default::default(),
+        Path::new(bundle.name),
+        options,
+        conn,
+    )?;
+    let file = FileClient::from(AsyncChannel::new(client));
+
+    for buf in bundle.render().as_bytes().chunks(CHUNK) {
+        let mut written = 0;
+        while written < buf.len() {
+            written += file.write(buf[written..].to_vec()).await??;
+        }
+    }
+    Ok(format!("diag/{}", bundle.name))
+}
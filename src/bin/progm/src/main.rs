@@ -3,11 +3,28 @@
 #![feature(slice_ptr_get)]
 
 mod boot;
-
-use alloc::vec;
-
-use solvent_fs::process::Process;
-use solvent_rpc::{io::OpenOptions, sync::Client};
+mod diag;
+mod supervisor;
+
+use alloc::{vec, vec::Vec};
+use core::{iter, time::Duration};
+
+use solvent::{
+    audit,
+    prelude::{Channel, MemRes, Object, Phys},
+    KernelFeatures,
+};
+use solvent_fs::{
+    loader::{get_cached_object_from_dir, get_object_from_dir},
+    process::Process,
+};
+use solvent_rpc::{
+    io::{dir::DirectoryClient, OpenOptions},
+    sync::Client,
+};
+use svrt::HandleType;
+
+use self::supervisor::Service;
 
 extern crate alloc;
 
@@ -20,21 +37,77 @@ async fn main() {
     solvent_std::env::args().for_each(|arg| log::debug!("{arg}"));
 
     solvent_async::test::test_disp().await;
+    solvent_rpc::test::test_rpc().await;
 
     let bootfs = solvent_fs::open_dir("/boot", OpenOptions::READ).expect("Failed to open bootfs");
     let bootfs = bootfs.into_async().expect("Failed to get loader");
 
-    let devm =
-        solvent_fs::loader::get_object_from_dir(solvent_async::dispatch(), &bootfs, "bin/devm")
-            .await
-            .expect("Failed to get executable");
+    let mem_res = match svrt::try_take_startup_handle(HandleType::MemRes.into()) {
+        // SAFETY: The startup handle is the root memory resource.
+        Ok(mem_res) => Some(unsafe { MemRes::from_raw(mem_res) }),
+        Err(_) => {
+            log::warn!("No memory resource, the system can't be suspended or watched");
+            None
+        }
+    };
+
+    diag::mount();
+    supervisor::start(&bootfs, Service::new("logd")).await;
+    if let Some(mem_res) = &mem_res {
+        let mem_res = MemRes::try_clone(mem_res).expect("Failed to clone the memory resource");
+        let vars = solvent_std::env::vars()
+            .filter(|(key, _)| key.starts_with("WATCHDOG_"))
+            .collect::<Vec<_>>();
+        let watchdogd = Service::new("watchdogd").config(move |builder| {
+            let mem_res = MemRes::try_clone(&mem_res).expect("Failed to clone the memory resource");
+            let mem_res = (HandleType::MemRes.into(), MemRes::into_raw(mem_res));
+            // SAFETY: watchdogd takes it as the root memory resource.
+            unsafe { builder.handles(iter::once(mem_res)) };
+            builder.environs(vars.clone());
+        });
+        supervisor::start(&bootfs, watchdogd).await;
+    }
+    supervisor::start(&bootfs, Service::new("configd")).await;
+    supervisor::start(&bootfs, Service::new("metrics")).await;
+    supervisor::start(&bootfs, Service::new("inputmgr")).await;
+    match svrt::try_take_startup_handle(HandleType::FramebufferPhys.into()) {
+        Ok(framebuffer) => {
+            // SAFETY: The startup handle is the framebuffer.
+            let framebuffer = unsafe { Phys::from_raw(framebuffer) };
+            let mode = solvent_std::env::vars().find(|(key, _)| key == "FB_MODE");
+            let compositor = Service::new("compositor").config(move |builder| {
+                let framebuffer = Phys::into_raw(framebuffer.clone());
+                let framebuffer = (HandleType::FramebufferPhys.into(), framebuffer);
+                // SAFETY: The compositor maps the framebuffer.
+                unsafe { builder.handles(iter::once(framebuffer)) };
+                builder.environs(mode.clone());
+            });
+            supervisor::start(&bootfs, compositor).await;
+        }
+        Err(_) => log::info!("No framebuffer, skipping the compositor"),
+    }
+    supervisor::serve_control();
+    if solvent::features().contains(KernelFeatures::BENCH) {
+        run_bench(&bootfs).await;
+    }
+
+    let devm = get_object_from_dir(solvent_async::dispatch(), &bootfs, "bin/devm")
+        .await
+        .expect("Failed to get executable");
 
     let mut vfs = vec![];
     solvent_fs::fs::local()
         .export(&mut vfs)
         .expect("Failed to export vfs");
 
-    let mut task = Process::builder()
+    let mut builder = Process::builder();
+    if let Some(mem_res) = mem_res {
+        set_audit_filter(&mem_res);
+        let mem_res = MemRes::into_raw(mem_res);
+        // SAFETY: devm takes it as the root memory resource.
+        unsafe { builder.handles(iter::once((HandleType::MemRes.into(), mem_res))) };
+    }
+    let mut task = builder
         .executable(devm, "devm")
         .expect("Failed to add executable")
         .load_dirs(vec![bootfs])
@@ -46,11 +119,71 @@ async fn main() {
 
     log::debug!("Waiting for devm");
     let retval = task.ajoin().await.expect("Failed to wait for devm");
+    if let Some(report) = task.panic_report() {
+        log::error!("devm panicked: {report}");
+    }
     assert_eq!(retval, 0, "The process failed: {retval:#x}");
 
+    supervisor::shutdown_control(Duration::from_secs(1)).await;
     log::debug!("Goodbye!");
 }
 
+/// Run the IPC benchmarks to completion, which log their results for
+/// `cargo xtask bench`.
+async fn run_bench(bootfs: &DirectoryClient) {
+    let (entry, peer_entry) = Channel::new();
+    let mut peer = spawn_bench(bootfs, peer_entry, true).await;
+    let mut task = spawn_bench(bootfs, entry, false).await;
+
+    let retval = task.ajoin().await.expect("Failed to wait for ipcbench");
+    if let Some(report) = task.panic_report() {
+        log::error!("ipcbench panicked: {report}");
+    }
+    if retval != 0 {
+        log::error!("The benchmarks failed: {retval:#x}");
+    }
+    // The peer exits once the other end of its entry is closed.
+    let _ = peer.ajoin().await;
+}
+
+async fn spawn_bench(bootfs: &DirectoryClient, entry: Channel, echo: bool) -> Process {
+    let (ipcbench, _) =
+        get_cached_object_from_dir(solvent_async::dispatch(), bootfs, "bin/ipcbench")
+            .await
+            .expect("Failed to get executable");
+    let mut builder = Process::builder();
+    let entry = (HandleType::ServiceEntry.into(), Channel::into_raw(entry));
+    // SAFETY: ipcbench takes it as the connection to its peer.
+    unsafe { builder.handles(iter::once(entry)) };
+    if echo {
+        builder.arg("echo");
+    }
+    builder
+        .executable(ipcbench, "ipcbench")
+        .expect("Failed to add executable")
+        .load_dirs(vec![bootfs.clone()])
+        .expect("Failed to add loader client")
+        .build()
+        .await
+        .expect("Failed to build a process")
+}
+
+/// Set the kinds of the audit events recorded to `AUDIT_FILTER`, the mask of
+/// their bits, before the root memory resource is handed to devm.
+fn set_audit_filter(mem_res: &MemRes) {
+    let Some((_, mask)) = solvent_std::env::vars().find(|(key, _)| key == "AUDIT_FILTER") else {
+        return;
+    };
+    match mask.parse() {
+        Ok(mask) => {
+            if let Err(err) = audit::set_filter(mem_res, mask) {
+                log::warn!("Failed to set the audit filter: {err:?}");
+            }
+        }
+        Err(_) => log::warn!("Invalid audit filter {mask:?}"),
+    }
+}
+
 solvent_async::entry!(main, solvent_std, None);
 
 #[link(name = "ldso")]
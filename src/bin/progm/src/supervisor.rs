@@ -0,0 +1,420 @@
+//! The supervision of the services.
+//!
+//! The instances of all the services are watched by a single
+//! [`JobSupervisor`], each in its own job, and their exits are handled in
+//! separate tasks so that a backing-off service doesn't hold the others up.
+//!
+//! A service exiting with a non-zero code is restarted after a backoff, which
+//! starts at [`MIN_BACKOFF`], doubles on every failure up to [`MAX_BACKOFF`],
+//! and is reset after a run of [`STABLE_RUN`].
+//!
+//! [`STORM_RESTARTS`] failures within [`STORM_WINDOW`] make a restart storm,
+//! which lasts until the next stable run. During a storm, every exit is
+//! captured into a diagnostics bundle by [`diag::capture`] instead of being
+//! logged, and the backoff stays at the maximum.
+//!
+//! The entry of a service is mounted at `use/<name>` only once, and forwards
+//! the opens to the current instance, so that the local FS exported to other
+//! processes needn't be updated on restarts. Every instance can reach the
+//! services started before it in its own `use` directory.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::{iter, mem, time::Duration};
+
+use either::Either;
+use futures_lite::{FutureExt, StreamExt};
+use solvent::{
+    obj::Object,
+    prelude::{Channel, Phys, ENOENT},
+    task::Task,
+    time::Instant,
+};
+use solvent_async::{
+    ipc::Channel as AsyncChannel,
+    sync::channel::{self, Receiver, Sender},
+    task::{Child, JobSupervisor},
+    time,
+};
+use solvent_fs::{
+    loader::{self, get_cached_object_from_dir},
+    process::{Builder, Process},
+    rpc::RpcNode,
+    spawner, Spawner,
+};
+use solvent_rpc::{
+    io::{
+        dir::DirectoryClient,
+        entry::{serve_entry_with, EntryServer, EntrySyncClient},
+        Error,
+    },
+    supervisor::{
+        ServiceState, ServiceStatus, SupervisorEventSender, SupervisorRequest, SupervisorServer,
+    },
+    EventSender, Server,
+};
+use solvent_std::sync::{Arsc, Lazy, Mutex};
+use svrt::HandleType;
+
+use crate::diag;
+
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+const STABLE_RUN: Duration = Duration::from_secs(30);
+const STORM_WINDOW: Duration = Duration::from_secs(10);
+const STORM_RESTARTS: usize = 5;
+
+static STATUS: Mutex<BTreeMap<String, ServiceStatus>> = Mutex::new(BTreeMap::new());
+
+/// The connections to the control protocol, shut down by [`shutdown_control`].
+static CONTROL: Mutex<(u64, BTreeMap<u64, SupervisorEventSender>)> =
+    Mutex::new((0, BTreeMap::new()));
+
+fn update(name: &str, f: impl FnOnce(&mut ServiceStatus)) {
+    let mut status = STATUS.lock();
+    let status = status.entry(name.into()).or_insert_with(|| ServiceStatus {
+        name: name.into(),
+        state: ServiceState::Running,
+        restarts: 0,
+        last_exit: None,
+        backoff_ms: 0,
+        diagnostics: None,
+    });
+    f(status)
+}
+
+/// A service started from `bin/<name>` in the bootfs.
+pub struct Service {
+    name: String,
+    config: Box<dyn Fn(&mut Builder) + Send + Sync>,
+}
+
+impl Service {
+    pub fn new(name: &str) -> Self {
+        Service {
+            name: name.into(),
+            config: Box::new(|_| {}),
+        }
+    }
+
+    /// Add the additional handles and variables to the builder of every
+    /// instance in `config`.
+    pub fn config<F>(mut self, config: F) -> Self
+    where
+        F: Fn(&mut Builder) + Send + Sync + 'static,
+    {
+        self.config = Box::new(config);
+        self
+    }
+}
+
+/// The current instance of a service and the state of its supervision.
+struct Supervised {
+    bootfs: DirectoryClient,
+    service: Service,
+    executable: Phys,
+    proxy: Arsc<Proxy>,
+    process: Process,
+    started: Instant,
+    backoff: Duration,
+    failures: VecDeque<Instant>,
+    storm: bool,
+}
+
+/// The instances to be supervised by the [`JobSupervisor`] of [`supervise`],
+/// which is started on the first access.
+static INSTANCES: Lazy<Sender<Supervised>> = Lazy::new(|| {
+    let (tx, rx) = channel::unbounded();
+    solvent_async::spawn(supervise(rx)).detach();
+    tx
+});
+
+/// The entry of a service, forwarding to its current instance.
+struct Proxy {
+    instance: Mutex<Option<EntrySyncClient>>,
+}
+
+impl Proxy {
+    fn instance(&self) -> Result<EntrySyncClient, Error> {
+        self.instance.lock().clone().ok_or(Error::NotFound)
+    }
+
+    fn serve(self: Arsc<Self>, spawner: Spawner, conn: Channel) {
+        let server = EntryServer::from(AsyncChannel::with_disp(conn, spawner.dispatch()));
+        let (p1, p2) = (self.clone(), self.clone());
+        let s2 = spawner.clone();
+        let task = serve_entry_with(
+            server,
+            move |path, options, conn| p1.instance()?.open(path, options, conn)?,
+            move || p2.instance()?.metadata()?,
+            move |conn| self.clone().serve(s2.clone(), conn),
+        );
+        spawner.spawn(task)
+    }
+}
+
+/// Start the first instance of `service`, mount its entry at `use/<name>`,
+/// and supervise it in the background.
+pub async fn start(bootfs: &DirectoryClient, service: Service) {
+    let name = &service.name;
+    let path = format!("bin/{name}");
+    let (executable, _) = get_cached_object_from_dir(solvent_async::dispatch(), bootfs, path)
+        .await
+        .expect("Failed to get executable");
+
+    let proxy = Arsc::new(Proxy {
+        instance: Mutex::new(None),
+    });
+    let process = spawn_instance(bootfs, &service, executable.clone(), &proxy).await;
+    update(name, |_| {});
+    log::debug!(
+        "Started the service {name}; loader cache: {}",
+        loader::cache_stat()
+    );
+
+    let (client, server) = Channel::new();
+    proxy.clone().serve(spawner(), server);
+    solvent_fs::fs::local()
+        .mount(format!("use/{name}"), client.into())
+        .unwrap_or_else(|err| panic!("Failed to mount the service {name}: {err:?}"));
+
+    let supervised = Supervised {
+        bootfs: bootfs.clone(),
+        service,
+        executable,
+        proxy,
+        process,
+        started: Instant::now(),
+        backoff: MIN_BACKOFF,
+        failures: VecDeque::new(),
+        storm: false,
+    };
+    let _ = INSTANCES.try_send(supervised);
+}
+
+async fn spawn_instance(
+    bootfs: &DirectoryClient,
+    service: &Service,
+    executable: Phys,
+    proxy: &Proxy,
+) -> Process {
+    let name = &service.name;
+    let (instance, server) = Channel::new();
+
+    let mut services = vec![];
+    solvent_fs::fs::local()
+        .export(&mut services)
+        .expect("Failed to export vfs");
+    services.retain(|(path, _)| path.starts_with("use"));
+
+    let mut builder = Process::builder();
+    builder.local_fs(services);
+    (service.config)(&mut builder);
+    let entry = (HandleType::ServiceEntry.into(), Channel::into_raw(server));
+    // SAFETY: The services take the entry as a channel.
+    unsafe { builder.handles(iter::once(entry)) };
+    let process = builder
+        .executable(executable, name)
+        .expect("Failed to add executable")
+        .load_dirs(vec![bootfs.clone()])
+        .expect("Failed to add loader client")
+        .build()
+        .await
+        .unwrap_or_else(|err| panic!("Failed to start the service {name}: {err:?}"));
+    *proxy.instance.lock() = Some(instance.into());
+    process
+}
+
+/// Watch the instances sent to [`INSTANCES`] and handle their exits.
+async fn supervise(instances: Receiver<Supervised>) {
+    let mut jobs = JobSupervisor::new();
+    loop {
+        let event = if jobs.is_empty() {
+            Either::Left(instances.recv().await)
+        } else {
+            let started = async { Either::Left(instances.recv().await) };
+            let exited = async { Either::Right(jobs.next().await) };
+            started.or(exited).await
+        };
+        match event {
+            Either::Left(Ok(supervised)) => watch(&mut jobs, supervised),
+            Either::Left(Err(_)) => return,
+            Either::Right(Some(exit)) => {
+                solvent_async::spawn(restart(exit.key, exit.retval)).detach();
+            }
+            Either::Right(None) => {}
+        }
+    }
+}
+
+fn watch(jobs: &mut JobSupervisor<Supervised>, supervised: Supervised) {
+    let name = supervised.service.name.clone();
+    let task = supervised.process.task().ok_or(ENOENT);
+    let res = task
+        .and_then(Task::try_clone)
+        .and_then(|task| jobs.insert(supervised, Child::new(task)));
+    match res {
+        Ok(job) => log::debug!("Supervising the service {name} in job {job}"),
+        Err(err) => log::error!("Failed to supervise the service {name}: {err:?}"),
+    }
+}
+
+/// Handle the exit of an instance, and send the next instance to
+/// [`INSTANCES`] after the backoff if the service is to be restarted.
+async fn restart(supervised: Supervised, retval: solvent::error::Result<usize>) {
+    let Supervised {
+        bootfs,
+        service,
+        executable,
+        proxy,
+        process,
+        started,
+        mut backoff,
+        mut failures,
+        mut storm,
+    } = supervised;
+    let name = &service.name;
+    let retval = match retval {
+        Ok(retval) => retval,
+        Err(err) => {
+            log::error!("Failed to wait for the service {name}: {err:?}");
+            return;
+        }
+    };
+    let runtime = started.elapsed();
+    proxy.instance.lock().take();
+    update(name, |status| status.last_exit = Some(retval));
+
+    if retval == 0 {
+        log::info!("The service {name} exited");
+        update(name, |status| status.state = ServiceState::Stopped);
+        return;
+    }
+
+    if runtime >= STABLE_RUN {
+        backoff = MIN_BACKOFF;
+        failures.clear();
+        storm = false;
+    }
+    let now = Instant::now();
+    failures.retain(|&time| now - time <= STORM_WINDOW);
+    failures.push_back(now);
+
+    let report = process.panic_report();
+    if !storm && failures.len() >= STORM_RESTARTS {
+        log::error!("The service {name} is crash-looping, see `diag/{name}` for the diagnostics");
+        storm = true;
+    }
+    if storm {
+        let bundle = diag::Bundle {
+            name,
+            retval,
+            runtime,
+            report: report.as_ref(),
+            failures: &failures,
+        };
+        match diag::capture(bundle).await {
+            Ok(path) => update(name, |status| status.diagnostics = Some(path)),
+            Err(err) => log::warn!("Failed to capture the diagnostics of {name}: {err}"),
+        }
+        backoff = MAX_BACKOFF;
+    } else {
+        match &report {
+            Some(report) => log::error!("The service {name} panicked: {report}"),
+            None => log::warn!("The service {name} exited with {retval:#x}"),
+        }
+    }
+
+    update(name, |status| {
+        status.state = if storm {
+            ServiceState::CrashLooping
+        } else {
+            ServiceState::BackingOff
+        };
+        status.backoff_ms = backoff.as_millis() as u64;
+    });
+    if let Err(err) = time::sleep(backoff).await {
+        log::warn!("Failed to back off the service {name}: {err:?}");
+    }
+
+    let process = spawn_instance(&bootfs, &service, executable.clone(), &proxy).await;
+    update(name, |status| {
+        status.state = ServiceState::Running;
+        status.restarts += 1;
+        status.backoff_ms = 0;
+    });
+    let supervised = Supervised {
+        bootfs,
+        service,
+        executable,
+        proxy,
+        process,
+        started: Instant::now(),
+        backoff: (backoff * 2).min(MAX_BACKOFF),
+        failures,
+        storm,
+    };
+    let _ = INSTANCES.try_send(supervised);
+}
+
+async fn handle(server: SupervisorServer) {
+    let (mut stream, event_sender) = server.serve();
+    let key = {
+        let mut control = CONTROL.lock();
+        let (next_key, senders) = &mut *control;
+        *next_key += 1;
+        senders.insert(*next_key, event_sender);
+        *next_key
+    };
+    while let Some(request) = stream.next().await {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+        };
+        let res = match request {
+            SupervisorRequest::Services { responder } => {
+                responder.send(STATUS.lock().values().cloned().collect::<Vec<_>>())
+            }
+            SupervisorRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+    CONTROL.lock().1.remove(&key);
+}
+
+/// Serve the control protocol of the program manager at `use/progm`.
+pub fn serve_control() {
+    let (client, server) = Channel::new();
+    let node = RpcNode::new(|server, _| async move { handle(server).await });
+    node.open_conn(spawner(), Default::default(), server);
+    solvent_fs::fs::local()
+        .mount("use/progm", client.into())
+        .expect("Failed to mount the control protocol");
+}
+
+/// Shut the connections to the control protocol down on an orderly shutdown,
+/// giving the requests in flight `timeout` to be responded to.
+pub async fn shutdown_control(timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    let senders = mem::take(&mut CONTROL.lock().1);
+    for (_, event_sender) in senders {
+        let abandoned = event_sender.shutdown(deadline).await;
+        if abandoned > 0 {
+            log::warn!("{abandoned} control request(s) abandoned on shutdown");
+        }
+    }
+}
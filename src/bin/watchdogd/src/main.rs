@@ -0,0 +1,126 @@
+//! The watchdog service, petting the software watchdog of the kernel as long
+//! as all of its clients keep alive.
+//!
+//! The kernel watchdog is armed with a timeout of `WATCHDOG_TIMEOUT_MS`
+//! milliseconds, and resets the system on expiry if `WATCHDOG_RESET` is `1`.
+//! The clients register and refresh their deadlines through the entry the
+//! service is started with, which the program manager mounts at
+//! `use/watchdogd`.
+
+#![no_std]
+#![no_main]
+
+use alloc::{collections::BTreeMap, string::String};
+use core::time::Duration;
+
+use futures_lite::{future, StreamExt};
+use solvent::{
+    dev::{MemRes, Watchdog},
+    prelude::{Channel, Object},
+    time::Instant,
+};
+use solvent_fs::{rpc::RpcNode, spawner};
+use solvent_rpc::{
+    watchdog::{WatchdogRequest, WatchdogServer},
+    Server,
+};
+use solvent_std::sync::Mutex;
+use svrt::HandleType;
+
+extern crate alloc;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The deadlines of the clients.
+static CLIENTS: Mutex<BTreeMap<String, Instant>> = Mutex::new(BTreeMap::new());
+
+fn var(key: &str) -> Option<String> {
+    solvent_std::env::vars().find_map(|(k, value)| (k == key).then_some(value))
+}
+
+async fn main() {
+    let entry = svrt::take_startup_handle(HandleType::ServiceEntry.into());
+    // SAFETY: The handle is given to us by the program manager.
+    let entry = unsafe { Channel::from_raw(entry) };
+
+    let Ok(res) = svrt::try_take_startup_handle(HandleType::MemRes.into()) else {
+        log::warn!("No memory resource, the watchdog is disabled");
+        return;
+    };
+    // SAFETY: The handle is given to us by the program manager.
+    let res = unsafe { MemRes::from_raw(res) };
+
+    let timeout = var("WATCHDOG_TIMEOUT_MS")
+        .and_then(|ms| ms.parse().ok())
+        .map_or(DEFAULT_TIMEOUT, Duration::from_millis);
+    let reset = var("WATCHDOG_RESET").as_deref() == Some("1");
+    let watchdog = Watchdog::arm(&res, timeout, reset).expect("Failed to arm the watchdog");
+    drop(res);
+    log::info!("Watchdog armed with {timeout:?}, reset: {reset}");
+
+    let node = RpcNode::new(|server, _| async move { handle(server).await });
+    node.open_conn(spawner(), Default::default(), entry);
+
+    pet(watchdog, timeout).await
+}
+
+/// Pet the watchdog every third of its timeout until a client misses its
+/// deadline, after which the watchdog is left to expire.
+async fn pet(watchdog: Watchdog, timeout: Duration) {
+    loop {
+        let now = Instant::now();
+        let missed = { CLIENTS.lock().iter() }
+            .find(|(_, &deadline)| deadline < now)
+            .map(|(name, _)| name.clone());
+        if let Some(name) = missed {
+            log::error!("{name} missed its keepalive deadline, stop petting the watchdog");
+            return future::pending().await;
+        }
+
+        if let Err(err) = watchdog.pet() {
+            log::error!("Failed to pet the watchdog: {err:?}");
+        }
+        if let Err(err) = solvent_async::time::sleep(timeout / 3).await {
+            log::warn!("Failed to sleep: {err:?}");
+        }
+    }
+}
+
+async fn handle(server: WatchdogServer) {
+    let (mut stream, _) = server.serve();
+    while let Some(request) = stream.next().await {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+        };
+        let res = match request {
+            WatchdogRequest::Keepalive {
+                name,
+                timeout_ms,
+                responder,
+            } => {
+                let mut clients = CLIENTS.lock();
+                if timeout_ms == 0 {
+                    clients.remove(&name);
+                } else {
+                    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+                    clients.insert(name, deadline);
+                }
+                drop(clients);
+                responder.send(())
+            }
+            WatchdogRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+}
+
+solvent_async::entry!(main, solvent_std, None);
@@ -0,0 +1,126 @@
+use alloc::vec::Vec;
+
+use futures_lite::StreamExt;
+use solvent::error::{Result, EINVAL, EPIPE, ERANGE};
+use solvent_async::sync::channel::{self, Sender};
+use solvent_rpc::{
+    ddk::block::{BlockInfo, BlockRequest, BlockServer},
+    Server,
+};
+
+use crate::port::{Request, MAX_TRANSFER};
+
+/// A connection to the block device of a port.
+#[derive(Clone)]
+pub struct Disk {
+    pub info: BlockInfo,
+    pub port: Sender<Request>,
+}
+
+impl Disk {
+    /// The block range `[block, block + count)` split into chunks that fit in
+    /// single commands.
+    fn chunks(&self, block: u64, count: usize) -> Result<impl Iterator<Item = (u64, usize)>> {
+        let end = block.checked_add(count as u64).ok_or(ERANGE)?;
+        if count == 0 || end > self.info.block_count {
+            return Err(ERANGE);
+        }
+        let max = MAX_TRANSFER / self.info.block_size;
+        let chunks = (block..end).step_by(max);
+        Ok(chunks.map(move |start| (start, (end - start).min(max as u64) as usize)))
+    }
+
+    async fn read(&self, block: u64, count: usize) -> Result<Vec<u8>> {
+        // Issue all the chunks before waiting for any of them, so that they
+        // can be queued in the device at the same time.
+        let mut replies = Vec::new();
+        for (block, count) in self.chunks(block, count)? {
+            let (reply, rx) = channel::bounded(1);
+            let request = Request::Read {
+                block,
+                count,
+                reply,
+            };
+            self.port.send(request).await.map_err(|_| EPIPE)?;
+            replies.push(rx);
+        }
+
+        let mut data = Vec::with_capacity(count * self.info.block_size);
+        for rx in replies {
+            data.extend(rx.recv().await.map_err(|_| EPIPE)??);
+        }
+        Ok(data)
+    }
+
+    async fn write(&self, block: u64, buf: Vec<u8>) -> Result {
+        let bs = self.info.block_size;
+        if self.info.read_only || buf.len() % bs != 0 {
+            return Err(EINVAL);
+        }
+
+        let mut replies = Vec::new();
+        let chunks = self.chunks(block, buf.len() / bs)?;
+        for ((block, count), buf) in chunks.zip(buf.chunks(MAX_TRANSFER / bs * bs)) {
+            debug_assert_eq!(count * bs, buf.len());
+            let (reply, rx) = channel::bounded(1);
+            let request = Request::Write {
+                block,
+                buf: buf.into(),
+                reply,
+            };
+            self.port.send(request).await.map_err(|_| EPIPE)?;
+            replies.push(rx);
+        }
+
+        for rx in replies {
+            rx.recv().await.map_err(|_| EPIPE)??;
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result {
+        let (reply, rx) = channel::bounded(1);
+        self.port
+            .send(Request::Flush { reply })
+            .await
+            .map_err(|_| EPIPE)?;
+        rx.recv().await.map_err(|_| EPIPE)?
+    }
+}
+
+pub async fn handle(disk: Disk, server: BlockServer) {
+    let (mut stream, _) = server.serve();
+    while let Some(request) = stream.next().await {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+        };
+
+        let res = match request {
+            BlockRequest::CloseConnection { responder } => responder.send(()),
+            BlockRequest::Info { responder } => responder.send(disk.info),
+            BlockRequest::Read {
+                block,
+                count,
+                responder,
+            } => responder.send(disk.read(block, count).await),
+            BlockRequest::Write {
+                block,
+                buf,
+                responder,
+            } => responder.send(disk.write(block, buf).await),
+            BlockRequest::Flush { responder } => responder.send(disk.flush().await),
+            BlockRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! The registers of AHCI host bus adapters, see the AHCI specification 1.3.1.
+
+use oc_driver::mmio::Mmio;
+
+// Generic host control.
+pub const CAP: usize = 0x00;
+pub const GHC: usize = 0x04;
+pub const IS: usize = 0x08;
+pub const PI: usize = 0x0c;
+
+pub const CAP_NCS_SHIFT: u32 = 8;
+pub const CAP_NCS_MASK: u32 = 0x1f;
+pub const CAP_SNCQ: u32 = 1 << 30;
+pub const CAP_S64A: u32 = 1 << 31;
+
+pub const GHC_IE: u32 = 1 << 1;
+pub const GHC_AE: u32 = 1 << 31;
+
+// Port registers, relative to the base of each port.
+const PORT_BASE: usize = 0x100;
+const PORT_SIZE: usize = 0x80;
+
+pub const PX_CLB: usize = 0x00;
+pub const PX_CLBU: usize = 0x04;
+pub const PX_FB: usize = 0x08;
+pub const PX_FBU: usize = 0x0c;
+pub const PX_IS: usize = 0x10;
+pub const PX_IE: usize = 0x14;
+pub const PX_CMD: usize = 0x18;
+pub const PX_TFD: usize = 0x20;
+pub const PX_SIG: usize = 0x24;
+pub const PX_SSTS: usize = 0x28;
+pub const PX_SERR: usize = 0x30;
+pub const PX_SACT: usize = 0x34;
+pub const PX_CI: usize = 0x38;
+
+pub const PX_CMD_ST: u32 = 1 << 0;
+pub const PX_CMD_SUD: u32 = 1 << 1;
+pub const PX_CMD_POD: u32 = 1 << 2;
+pub const PX_CMD_FRE: u32 = 1 << 4;
+pub const PX_CMD_FR: u32 = 1 << 14;
+pub const PX_CMD_CR: u32 = 1 << 15;
+
+pub const PX_IS_DHRS: u32 = 1 << 0;
+pub const PX_IS_PSS: u32 = 1 << 1;
+pub const PX_IS_SDBS: u32 = 1 << 3;
+pub const PX_IS_DPS: u32 = 1 << 5;
+pub const PX_IS_IFS: u32 = 1 << 27;
+pub const PX_IS_HBDS: u32 = 1 << 28;
+pub const PX_IS_HBFS: u32 = 1 << 29;
+pub const PX_IS_TFES: u32 = 1 << 30;
+/// The interrupts that fail every command in flight.
+pub const PX_IS_ERROR: u32 = PX_IS_IFS | PX_IS_HBDS | PX_IS_HBFS | PX_IS_TFES;
+
+pub const PX_TFD_ERR: u32 = 1 << 0;
+pub const PX_TFD_DRQ: u32 = 1 << 3;
+pub const PX_TFD_BSY: u32 = 1 << 7;
+
+/// The device is present and the PHY communication is established.
+pub const PX_SSTS_DET_PRESENT: u32 = 3;
+pub const PX_SIG_ATA: u32 = 0x0000_0101;
+
+/// The HBA memory registers mapped from the last BAR of the controller.
+#[derive(Debug)]
+pub struct Hba {
+    mmio: Mmio,
+}
+
+impl Hba {
+    #[inline]
+    pub fn new(mmio: Mmio) -> Self {
+        Hba { mmio }
+    }
+
+    #[inline]
+    pub fn read(&self, reg: usize) -> u32 {
+        self.mmio.read32(reg)
+    }
+
+    #[inline]
+    pub fn write(&self, reg: usize, value: u32) {
+        self.mmio.write32(reg, value)
+    }
+
+    #[inline]
+    pub fn port_read(&self, port: usize, reg: usize) -> u32 {
+        self.mmio.read32(PORT_BASE + port * PORT_SIZE + reg)
+    }
+
+    #[inline]
+    pub fn port_write(&self, port: usize, reg: usize, value: u32) {
+        self.mmio.write32(PORT_BASE + port * PORT_SIZE + reg, value)
+    }
+
+    /// The number of command slots in each port.
+    #[inline]
+    pub fn slots(&self) -> usize {
+        (((self.read(CAP) >> CAP_NCS_SHIFT) & CAP_NCS_MASK) + 1) as usize
+    }
+}
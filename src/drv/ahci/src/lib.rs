@@ -0,0 +1,109 @@
+//! The driver of AHCI SATA controllers.
+//!
+//! Every port with an ATA device attached is published as a block device
+//! named after the controller and the port, such as `ahci-0x1f2.0`.
+
+#![no_std]
+
+mod block;
+mod hba;
+mod port;
+
+use alloc::{boxed::Box, format, vec::Vec};
+
+use async_trait::async_trait;
+use futures_lite::StreamExt;
+use oc_driver::{
+    bus::{Bus, DeviceInfo},
+    intr::IntrStream,
+    mmio::Mmio,
+    DeviceId, Driver, DriverInfo,
+};
+use solvent::error::Result;
+use solvent_async::sync::channel::{self, Sender};
+use solvent_core::sync::Arsc;
+
+use self::{block::Disk, hba::*, port::Port};
+
+extern crate alloc;
+
+/// The BAR of the HBA memory registers, named ABAR in the specification.
+const ABAR: usize = 5;
+
+struct Ahci {
+    hba: Arsc<Hba>,
+    intr: IntrStream,
+    /// The interrupt notifiers of the running ports.
+    ports: Vec<(usize, Sender<()>)>,
+}
+
+#[async_trait(?Send)]
+impl Driver for Ahci {
+    const INFO: DriverInfo = DriverInfo {
+        name: "ahci",
+        ids: &[DeviceId::Class {
+            class: 0x01,
+            subclass: 0x06,
+            prog_if: Some(0x01),
+        }],
+    };
+
+    async fn bind(bus: Arsc<dyn Bus>, device: DeviceInfo) -> Result<Self> {
+        let hba = Arsc::new(Hba::new(Mmio::new(bus.mmio(device.addr, ABAR).await?)?));
+        let intr = bus.interrupt(device.addr, 0).await?;
+        hba.write(GHC, hba.read(GHC) | GHC_AE);
+
+        let mut ports = Vec::new();
+        let implemented = hba.read(PI);
+        for index in (0..32).filter(|index| implemented & (1 << index) != 0) {
+            let port = match Port::init(&bus, Arsc::clone(&hba), index).await {
+                Ok(Some(port)) => port,
+                Ok(None) => continue,
+                Err(err) => {
+                    log::warn!("{}: failed to initialize port {index}: {err}", device.name);
+                    continue;
+                }
+            };
+
+            let (req_tx, req_rx) = channel::unbounded();
+            let (intr_tx, intr_rx) = channel::bounded(1);
+            let disk = Disk {
+                info: port.info(),
+                port: req_tx,
+            };
+            oc_driver::spawn(port.run(req_rx, intr_rx)).detach();
+            ports.push((index, intr_tx));
+
+            let name = format!("ahci-{:#x}.{index}", device.addr);
+            let serve = move |server| block::handle(disk.clone(), server);
+            if let Err(err) = oc_driver::publish(&*bus, &name, serve).await {
+                log::warn!("{}: failed to publish {name}: {err}", device.name);
+            }
+        }
+
+        hba.write(IS, u32::MAX);
+        hba.write(GHC, hba.read(GHC) | GHC_IE);
+        Ok(Ahci { hba, intr, ports })
+    }
+
+    async fn run(mut self) {
+        while let Some(res) = self.intr.next().await {
+            if let Err(err) = res {
+                log::error!("failed to wait for the interrupt: {err}");
+                break;
+            }
+
+            let is = self.hba.read(IS);
+            for (index, notify) in &self.ports {
+                if is & (1 << index) != 0 {
+                    // The port is already notified if the channel is full.
+                    let _ = notify.try_send(());
+                }
+            }
+            self.hba.write(IS, is);
+        }
+        self.hba.write(GHC, self.hba.read(GHC) & !GHC_IE);
+    }
+}
+
+oc_driver::driver!(Ahci);
@@ -0,0 +1,488 @@
+//! The command engine of a SATA port.
+//!
+//! Each port is driven by its own task, which owns the command slots of the
+//! port. Requests from the block servers are queued until a slot is free, and
+//! the slots are reclaimed when the interrupt of the port is notified.
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::time::Duration;
+
+use futures_lite::future::{self, yield_now};
+use oc_driver::{
+    bus::{Bus, DmaRegion},
+    dma::{DmaBuffer, DmaPool},
+};
+use solvent::{
+    error::{Result, EINVAL, EIO, ERANGE, ETIME},
+    time::Instant,
+};
+use solvent_async::sync::channel::{Receiver, Sender};
+use solvent_core::sync::Arsc;
+use solvent_rpc::ddk::block::BlockInfo;
+
+use crate::hba::*;
+
+/// The maximum bytes transferred by one command, which is the size of the
+/// data buffers of the ports.
+pub const MAX_TRANSFER: usize = 64 * 1024;
+
+const CMD_LIST_OFFSET: usize = 0;
+const RECV_FIS_OFFSET: usize = 0x400;
+const CMD_TABLE_OFFSET: usize = 0x800;
+const CMD_TABLE_SIZE: usize = 0x100;
+const PRDT_OFFSET: usize = 0x80;
+const MEM_SIZE: usize = CMD_TABLE_OFFSET + 32 * CMD_TABLE_SIZE;
+
+const CMD_HEADER_SIZE: usize = 0x20;
+/// The length of a host-to-device register FIS in dwords.
+const CMD_HEADER_CFL: u32 = 5;
+const CMD_HEADER_WRITE: u32 = 1 << 6;
+const CMD_HEADER_PRDTL_SHIFT: u32 = 16;
+const PRD_INTR: u32 = 1 << 31;
+
+const FIS_TYPE_H2D: u8 = 0x27;
+const FIS_H2D_COMMAND: u8 = 0x80;
+const DEVICE_LBA: u8 = 1 << 6;
+
+const ATA_READ_DMA_EXT: u8 = 0x25;
+const ATA_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_READ_FPDMA_QUEUED: u8 = 0x60;
+const ATA_WRITE_FPDMA_QUEUED: u8 = 0x61;
+const ATA_FLUSH_CACHE_EXT: u8 = 0xea;
+const ATA_IDENTIFY_DEVICE: u8 = 0xec;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+pub enum Request {
+    Read {
+        block: u64,
+        count: usize,
+        reply: Sender<Result<Vec<u8>>>,
+    },
+    Write {
+        block: u64,
+        buf: Vec<u8>,
+        reply: Sender<Result>,
+    },
+    Flush {
+        reply: Sender<Result>,
+    },
+}
+
+enum Reply {
+    Read(Sender<Result<Vec<u8>>>, usize),
+    Write(Sender<Result>),
+}
+
+struct Slot {
+    reply: Reply,
+    buf: Option<DmaBuffer>,
+}
+
+impl Slot {
+    fn finish(self, res: Result) {
+        let _ = match self.reply {
+            Reply::Read(reply, len) => reply.try_send(res.and_then(|_| {
+                let mut data = vec![0; len];
+                self.buf.as_ref().unwrap().read(0, &mut data)?;
+                Ok(data)
+            })),
+            Reply::Write(reply) => reply.try_send(res),
+        };
+    }
+}
+
+/// Build a host-to-device register FIS.
+fn h2d_fis(command: u8, lba: u64, count: u16, features: u16) -> [u8; 20] {
+    let lba = lba.to_le_bytes();
+    let [count_lo, count_hi] = count.to_le_bytes();
+    let [feat_lo, feat_hi] = features.to_le_bytes();
+    [
+        FIS_TYPE_H2D,
+        FIS_H2D_COMMAND,
+        command,
+        feat_lo,
+        lba[0],
+        lba[1],
+        lba[2],
+        DEVICE_LBA,
+        lba[3],
+        lba[4],
+        lba[5],
+        feat_hi,
+        count_lo,
+        count_hi,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ]
+}
+
+fn identify_word(data: &[u8], index: usize) -> u16 {
+    u16::from_le_bytes([data[index * 2], data[index * 2 + 1]])
+}
+
+pub struct Port {
+    hba: Arsc<Hba>,
+    index: usize,
+    /// The command list, the received FISes and the command tables.
+    mem: DmaRegion,
+    data: DmaPool,
+
+    info: BlockInfo,
+    ncq: bool,
+    slots: Vec<Option<Slot>>,
+    /// Whether a non-queued command is in flight, which excludes any other
+    /// command.
+    exclusive: bool,
+    pending: VecDeque<Request>,
+}
+
+impl Port {
+    /// Initialize the port `index` of `hba` if an ATA device is attached.
+    pub async fn init(bus: &Arsc<dyn Bus>, hba: Arsc<Hba>, index: usize) -> Result<Option<Self>> {
+        let ssts = hba.port_read(index, PX_SSTS);
+        if ssts & 0xf != PX_SSTS_DET_PRESENT || hba.port_read(index, PX_SIG) != PX_SIG_ATA {
+            return Ok(None);
+        }
+
+        let mem = bus.dma(MEM_SIZE).await?;
+        if mem.addr >> 32 != 0 && hba.read(CAP) & CAP_S64A == 0 {
+            return Err(ERANGE);
+        }
+        // SAFETY: The region is not used by the device yet.
+        unsafe { mem.phys.write(0, &[0; MEM_SIZE]) }?;
+
+        let mut port = Port {
+            data: DmaPool::new(Arsc::clone(bus), MAX_TRANSFER, 2),
+            hba,
+            index,
+            mem,
+            info: BlockInfo {
+                block_size: 512,
+                block_count: 0,
+                read_only: false,
+            },
+            ncq: false,
+            slots: Vec::new(),
+            exclusive: false,
+            pending: VecDeque::new(),
+        };
+        port.stop().await?;
+        port.write(PX_CLB, port.mem.addr as u32);
+        port.write(PX_CLBU, (port.mem.addr >> 32) as u32);
+        let fb = port.mem.addr + RECV_FIS_OFFSET as u64;
+        port.write(PX_FB, fb as u32);
+        port.write(PX_FBU, (fb >> 32) as u32);
+        port.write(PX_SERR, u32::MAX);
+        port.write(PX_IS, u32::MAX);
+        port.start().await?;
+
+        let identify = port.identify().await?;
+        let depth = port.parse_identify(&identify);
+        port.slots = (0..depth).map(|_| None).collect();
+
+        port.write(
+            PX_IE,
+            PX_IS_DHRS | PX_IS_PSS | PX_IS_SDBS | PX_IS_DPS | PX_IS_ERROR,
+        );
+        log::debug!(
+            "port {index}: {} blocks of {} bytes, NCQ {}",
+            port.info.block_count,
+            port.info.block_size,
+            if port.ncq { "enabled" } else { "disabled" }
+        );
+        Ok(Some(port))
+    }
+
+    #[inline]
+    pub fn info(&self) -> BlockInfo {
+        self.info
+    }
+
+    #[inline]
+    fn read(&self, reg: usize) -> u32 {
+        self.hba.port_read(self.index, reg)
+    }
+
+    #[inline]
+    fn write(&self, reg: usize, value: u32) {
+        self.hba.port_write(self.index, reg, value)
+    }
+
+    async fn wait(&self, mut done: impl FnMut() -> bool) -> Result {
+        let deadline = Instant::now() + TIMEOUT;
+        while !done() {
+            if Instant::now() >= deadline {
+                return Err(ETIME);
+            }
+            yield_now().await
+        }
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result {
+        let cmd = self.read(PX_CMD);
+        self.write(PX_CMD, cmd & !PX_CMD_ST);
+        self.wait(|| self.read(PX_CMD) & PX_CMD_CR == 0).await?;
+
+        let cmd = self.read(PX_CMD);
+        self.write(PX_CMD, cmd & !PX_CMD_FRE);
+        self.wait(|| self.read(PX_CMD) & PX_CMD_FR == 0).await
+    }
+
+    async fn start(&self) -> Result {
+        let cmd = self.read(PX_CMD);
+        self.write(PX_CMD, cmd | PX_CMD_SUD | PX_CMD_POD | PX_CMD_FRE);
+        self.wait(|| self.read(PX_TFD) & (PX_TFD_BSY | PX_TFD_DRQ) == 0)
+            .await?;
+
+        let cmd = self.read(PX_CMD);
+        self.write(PX_CMD, cmd | PX_CMD_ST);
+        Ok(())
+    }
+
+    async fn identify(&self) -> Result<Vec<u8>> {
+        let buf = self.data.alloc().await?;
+        let fis = h2d_fis(ATA_IDENTIFY_DEVICE, 0, 0, 0);
+        self.issue(0, &fis, Some((&buf, 512)), false, false)?;
+
+        self.wait(|| self.read(PX_CI) & 1 == 0 || self.read(PX_IS) & PX_IS_ERROR != 0)
+            .await?;
+        let is = self.read(PX_IS);
+        self.write(PX_IS, is);
+        if is & PX_IS_ERROR != 0 || self.read(PX_TFD) & PX_TFD_ERR != 0 {
+            return Err(EIO);
+        }
+
+        let mut data = vec![0; 512];
+        buf.read(0, &mut data)?;
+        Ok(data)
+    }
+
+    /// Parse the identify data of the device, returning the number of commands
+    /// that can be in flight at the same time.
+    fn parse_identify(&mut self, data: &[u8]) -> usize {
+        let count = (100..104).rev().fold(0, |acc, index| {
+            (acc << 16) | u64::from(identify_word(data, index))
+        });
+        self.info.block_count = count;
+
+        // Word 106 is valid if bit 14 is set and bit 15 is cleared.
+        let sector_info = identify_word(data, 106);
+        if sector_info & 0xc000 == 0x4000 && sector_info & (1 << 12) != 0 {
+            let words =
+                u32::from(identify_word(data, 117)) | (u32::from(identify_word(data, 118)) << 16);
+            self.info.block_size = words as usize * 2;
+        }
+
+        let caps = identify_word(data, 76);
+        let queue_depth = (identify_word(data, 75) & 0x1f) as usize + 1;
+        let depth = self.hba.slots().min(queue_depth);
+        self.ncq = self.hba.read(CAP) & CAP_SNCQ != 0 && caps & (1 << 8) != 0 && depth > 1;
+        if self.ncq {
+            depth
+        } else {
+            1
+        }
+    }
+
+    /// Fill the command slot `slot` and issue it.
+    fn issue(
+        &self,
+        slot: usize,
+        fis: &[u8; 20],
+        data: Option<(&DmaBuffer, usize)>,
+        write: bool,
+        queued: bool,
+    ) -> Result {
+        let table = CMD_TABLE_OFFSET + slot * CMD_TABLE_SIZE;
+        let mut buf = [0; PRDT_OFFSET + 16];
+        buf[..fis.len()].copy_from_slice(fis);
+
+        let mut header = CMD_HEADER_CFL;
+        if let Some((data, len)) = data {
+            if len == 0 || len > data.len() {
+                return Err(EINVAL);
+            }
+            let prd = &mut buf[PRDT_OFFSET..];
+            prd[..8].copy_from_slice(&data.addr().to_le_bytes());
+            prd[12..].copy_from_slice(&((len as u32 - 1) | PRD_INTR).to_le_bytes());
+            header |= 1 << CMD_HEADER_PRDTL_SHIFT;
+        }
+        if write {
+            header |= CMD_HEADER_WRITE;
+        }
+
+        let ctba = self.mem.addr + table as u64;
+        let mut cmd_header = [0; CMD_HEADER_SIZE];
+        cmd_header[..4].copy_from_slice(&header.to_le_bytes());
+        cmd_header[8..16].copy_from_slice(&ctba.to_le_bytes());
+
+        // SAFETY: The slot is not in use by the device.
+        unsafe {
+            self.mem.phys.write(table, &buf)?;
+            let offset = CMD_LIST_OFFSET + slot * CMD_HEADER_SIZE;
+            self.mem.phys.write(offset, &cmd_header)?;
+        }
+
+        if queued {
+            self.write(PX_SACT, 1 << slot);
+        }
+        self.write(PX_CI, 1 << slot);
+        Ok(())
+    }
+
+    fn is_idle(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    /// Issue the pending requests to the free slots.
+    async fn dispatch(&mut self) {
+        while let Some(request) = self.pending.front() {
+            let queued = self.ncq && !matches!(request, Request::Flush { .. });
+            if self.exclusive || (!queued && !self.is_idle()) {
+                break;
+            }
+            let Some(slot) = self.slots.iter().position(Option::is_none) else {
+                break;
+            };
+            let request = self.pending.pop_front().unwrap();
+            self.start_request(slot, request, queued).await;
+        }
+    }
+
+    async fn start_request(&mut self, slot: usize, request: Request, queued: bool) {
+        let bs = self.info.block_size;
+        let (fis, buf, len, reply, write) = match request {
+            Request::Read {
+                block,
+                count,
+                reply,
+            } => {
+                let buf = match self.data.alloc().await {
+                    Ok(buf) => buf,
+                    Err(err) => {
+                        let _ = reply.try_send(Err(err));
+                        return;
+                    }
+                };
+                let fis = self.rw_fis(false, queued, slot, block, count);
+                let len = count * bs;
+                (fis, Some(buf), len, Reply::Read(reply, len), false)
+            }
+            Request::Write { block, buf, reply } => {
+                let data = match self.data.alloc().await {
+                    Ok(data) => data,
+                    Err(err) => {
+                        let _ = reply.try_send(Err(err));
+                        return;
+                    }
+                };
+                if let Err(err) = data.write(0, &buf) {
+                    let _ = reply.try_send(Err(err));
+                    return;
+                }
+                let fis = self.rw_fis(true, queued, slot, block, buf.len() / bs);
+                (fis, Some(data), buf.len(), Reply::Write(reply), true)
+            }
+            Request::Flush { reply } => {
+                let fis = h2d_fis(ATA_FLUSH_CACHE_EXT, 0, 0, 0);
+                (fis, None, 0, Reply::Write(reply), false)
+            }
+        };
+
+        let res = self.issue(
+            slot,
+            &fis,
+            buf.as_ref().map(|buf| (buf, len)),
+            write,
+            queued,
+        );
+        let entry = Slot { reply, buf };
+        match res {
+            Ok(()) => {
+                self.exclusive = !queued;
+                self.slots[slot] = Some(entry);
+            }
+            Err(err) => entry.finish(Err(err)),
+        }
+    }
+
+    fn rw_fis(&self, write: bool, queued: bool, tag: usize, block: u64, count: usize) -> [u8; 20] {
+        let count = count as u16;
+        match (write, queued) {
+            (false, true) => h2d_fis(ATA_READ_FPDMA_QUEUED, block, (tag as u16) << 3, count),
+            (true, true) => h2d_fis(ATA_WRITE_FPDMA_QUEUED, block, (tag as u16) << 3, count),
+            (false, false) => h2d_fis(ATA_READ_DMA_EXT, block, count, 0),
+            (true, false) => h2d_fis(ATA_WRITE_DMA_EXT, block, count, 0),
+        }
+    }
+
+    /// Reclaim the slots of the finished commands.
+    async fn complete(&mut self) {
+        let is = self.read(PX_IS);
+        self.write(PX_IS, is);
+
+        if is & PX_IS_ERROR != 0 {
+            log::warn!(
+                "port {}: command failed, IS = {is:#x}, TFD = {:#x}",
+                self.index,
+                self.read(PX_TFD)
+            );
+            self.slots
+                .iter_mut()
+                .filter_map(Option::take)
+                .for_each(|slot| slot.finish(Err(EIO)));
+            self.exclusive = false;
+            if let Err(err) = self.recover().await {
+                log::error!("port {}: failed to recover: {err}", self.index);
+            }
+            return;
+        }
+
+        let active = self.read(PX_CI) | self.read(PX_SACT);
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if active & (1 << index) == 0 {
+                if let Some(slot) = slot.take() {
+                    slot.finish(Ok(()));
+                }
+            }
+        }
+        if self.is_idle() {
+            self.exclusive = false;
+        }
+    }
+
+    async fn recover(&self) -> Result {
+        self.stop().await?;
+        self.write(PX_SERR, u32::MAX);
+        self.write(PX_IS, u32::MAX);
+        self.start().await
+    }
+
+    /// Serve the requests until all the senders are dropped.
+    ///
+    /// `intr` is notified whenever the port raises an interrupt.
+    pub async fn run(mut self, requests: Receiver<Request>, intr: Receiver<()>) {
+        enum Event {
+            Request(Request),
+            Interrupt,
+        }
+
+        loop {
+            self.dispatch().await;
+
+            let request = async { requests.recv().await.ok().map(Event::Request) };
+            let interrupt = async { intr.recv().await.ok().map(|_| Event::Interrupt) };
+            match future::or(interrupt, request).await {
+                Some(Event::Request(request)) => self.pending.push_back(request),
+                Some(Event::Interrupt) => self.complete().await,
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::time::Duration;
+
+use futures_lite::future::yield_now;
+use oc_driver::{
+    bus::Bus,
+    dma::{DmaBuffer, DmaPool},
+};
+use solvent::{
+    error::{Result, EIO, EPIPE, ERANGE, ETIME},
+    mem::PAGE_SIZE,
+    time::Instant,
+};
+use solvent_async::sync::channel::{self, Receiver, Sender};
+use solvent_core::sync::{Arsc, Mutex};
+
+use crate::{regs::*, ring::*};
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Poll `done` until it returns `true`, or time out.
+pub async fn wait(mut done: impl FnMut() -> bool) -> Result {
+    let deadline = Instant::now() + TIMEOUT;
+    while !done() {
+        if Instant::now() >= deadline {
+            return Err(ETIME);
+        }
+        yield_now().await
+    }
+    Ok(())
+}
+
+pub struct Controller {
+    pub regs: Regs,
+    pages: DmaPool,
+    /// The device context base address array.
+    dcbaa: DmaBuffer,
+    _scratchpads: Vec<DmaBuffer>,
+
+    commands: Mutex<Ring>,
+    events: Mutex<EventRing>,
+    /// The waiters of the command completion events, keyed by the addresses
+    /// of the command TRBs.
+    waiters: Mutex<BTreeMap<u64, Sender<Trb>>>,
+    /// The receivers of the transfer events of the endpoints, keyed by the
+    /// slot IDs and the device context indices.
+    endpoints: Mutex<BTreeMap<(u8, u8), Sender<Trb>>>,
+    port_changes: Sender<usize>,
+}
+
+impl Controller {
+    async fn page(pages: &DmaPool, ac64: bool) -> Result<DmaBuffer> {
+        let page = pages.alloc().await?;
+        if !ac64 && page.addr() >> 32 != 0 {
+            return Err(ERANGE);
+        }
+        page.write(0, &[0; PAGE_SIZE])?;
+        Ok(page)
+    }
+
+    /// Reset the controller and start it, returning the receiver of the
+    /// numbers of the ports whose status changed.
+    pub async fn new(bus: Arsc<dyn Bus>, regs: Regs) -> Result<(Arsc<Self>, Receiver<usize>)> {
+        regs.op_write(USBCMD, regs.op_read(USBCMD) & !USBCMD_RS);
+        wait(|| regs.op_read(USBSTS) & USBSTS_HCH != 0).await?;
+        regs.op_write(USBCMD, USBCMD_HCRST);
+        wait(|| regs.op_read(USBCMD) & USBCMD_HCRST == 0).await?;
+        wait(|| regs.op_read(USBSTS) & USBSTS_CNR == 0).await?;
+
+        let ac64 = regs.ac64();
+        let pages = DmaPool::new(bus, PAGE_SIZE, PAGE_SIZE);
+        let dcbaa = Self::page(&pages, ac64).await?;
+
+        let mut scratchpads = Vec::new();
+        let count = regs.max_scratchpads();
+        if count > 0 {
+            let array = Self::page(&pages, ac64).await?;
+            for index in 0..count {
+                let page = Self::page(&pages, ac64).await?;
+                array.write(index * 8, &page.addr().to_le_bytes())?;
+                scratchpads.push(page);
+            }
+            dcbaa.write(0, &array.addr().to_le_bytes())?;
+            scratchpads.push(array);
+        }
+
+        regs.op_write(CONFIG, regs.max_slots() as u32);
+        regs.op_write64(DCBAAP, dcbaa.addr());
+
+        let commands = Ring::new(&pages).await?;
+        regs.op_write64(CRCR, commands.addr());
+
+        let events = EventRing::new(&pages).await?;
+        regs.rt_write(ERSTSZ, 1);
+        regs.rt_write64(ERDP, events.dequeue_addr());
+        regs.rt_write64(ERSTBA, events.erst_addr());
+        regs.rt_write(IMAN, IMAN_IP | IMAN_IE);
+
+        let (port_changes, rx) = channel::unbounded();
+        let ctrl = Controller {
+            regs,
+            pages,
+            dcbaa,
+            _scratchpads: scratchpads,
+            commands: Mutex::new(commands),
+            events: Mutex::new(events),
+            waiters: Mutex::new(BTreeMap::new()),
+            endpoints: Mutex::new(BTreeMap::new()),
+            port_changes,
+        };
+
+        let cmd = ctrl.regs.op_read(USBCMD);
+        ctrl.regs.op_write(USBCMD, cmd | USBCMD_RS | USBCMD_INTE);
+        wait(|| ctrl.regs.op_read(USBSTS) & USBSTS_HCH == 0).await?;
+        Ok((Arsc::new(ctrl), rx))
+    }
+
+    /// Allocate a zeroed page accessible to the controller.
+    pub async fn alloc_page(&self) -> Result<DmaBuffer> {
+        Self::page(&self.pages, self.regs.ac64()).await
+    }
+
+    /// Allocate a transfer ring.
+    pub async fn ring(&self) -> Result<Ring> {
+        Ring::new(&self.pages).await
+    }
+
+    pub fn set_device_context(&self, slot: u8, addr: u64) -> Result {
+        self.dcbaa.write(slot as usize * 8, &addr.to_le_bytes())
+    }
+
+    /// Issue a command, returning its completion event if it succeeds.
+    pub async fn command(&self, trb: Trb) -> Result<Trb> {
+        let (tx, rx) = channel::bounded(1);
+        {
+            let mut commands = self.commands.lock();
+            let addr = commands.push(trb)?;
+            self.waiters.lock().insert(addr, tx);
+        }
+        self.regs.ring(0, 0);
+
+        let event = rx.recv().await.map_err(|_| EPIPE)?;
+        match event.completion_code() {
+            CC_SUCCESS => Ok(event),
+            code => {
+                log::warn!("command {} failed with code {code}", trb.ty());
+                Err(EIO)
+            }
+        }
+    }
+
+    /// Register the endpoint `dci` of the device `slot`, returning the
+    /// receiver of its transfer events.
+    pub fn endpoint(&self, slot: u8, dci: u8) -> Receiver<Trb> {
+        let (tx, rx) = channel::unbounded();
+        self.endpoints.lock().insert((slot, dci), tx);
+        rx
+    }
+
+    pub fn remove_endpoints(&self, slot: u8) {
+        self.endpoints.lock().retain(|&(s, _), _| s != slot);
+    }
+
+    /// Handle the events produced by the controller after an interrupt.
+    pub fn handle_events(&self) -> Result {
+        self.regs.rt_write(IMAN, IMAN_IP | IMAN_IE);
+        self.regs.op_write(USBSTS, USBSTS_EINT);
+
+        let mut events = self.events.lock();
+        while let Some(event) = events.pop()? {
+            match event.ty() {
+                TRB_COMMAND_COMPLETION => {
+                    if let Some(waiter) = self.waiters.lock().remove(&event.param) {
+                        let _ = waiter.try_send(event);
+                    }
+                }
+                TRB_TRANSFER_EVENT => {
+                    let dci = ((event.control >> 16) & 0x1f) as u8;
+                    if let Some(ep) = self.endpoints.lock().get(&(event.slot(), dci)) {
+                        let _ = ep.try_send(event);
+                    }
+                }
+                TRB_PORT_STATUS_CHANGE => {
+                    let _ = self
+                        .port_changes
+                        .try_send((event.param >> 24) as usize & 0xff);
+                }
+                ty => log::debug!("unhandled event {ty}"),
+            }
+        }
+        self.regs.rt_write64(ERDP, events.dequeue_addr() | ERDP_EHB);
+        Ok(())
+    }
+}
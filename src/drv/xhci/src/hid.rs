@@ -0,0 +1,165 @@
+//! HID boot protocol keyboards and mice.
+
+use alloc::vec::Vec;
+
+use solvent::error::{Result, ENOENT};
+use solvent_core::sync::Arsc;
+use solvent_rpc::input::{DeviceKind, InputEvent};
+
+use crate::{
+    input::Broadcaster,
+    usb::{Device, Endpoint, Interface, Setup, REQ_TYPE_CLASS_INTERFACE},
+};
+
+const CLASS_HID: u8 = 3;
+const SUBCLASS_BOOT: u8 = 1;
+const PROTOCOL_KEYBOARD: u8 = 1;
+const PROTOCOL_MOUSE: u8 = 2;
+
+const REQ_SET_IDLE: u8 = 0x0a;
+const REQ_SET_PROTOCOL: u8 = 0x0b;
+const BOOT_PROTOCOL: u16 = 0;
+
+/// The usage ID of the left control key, after which are the other 7
+/// modifiers in the order of the bits of the modifier byte.
+const USAGE_LEFT_CONTROL: u16 = 0xe0;
+/// The usage IDs below which are error codes instead of keys.
+const USAGE_FIRST_KEY: u8 = 4;
+
+/// The kind of the input device of `interface` if it supports the boot
+/// protocol.
+pub fn boot_kind(interface: &Interface) -> Option<DeviceKind> {
+    if interface.class != CLASS_HID || interface.subclass != SUBCLASS_BOOT {
+        return None;
+    }
+    match interface.protocol {
+        PROTOCOL_KEYBOARD => Some(DeviceKind::Keyboard),
+        PROTOCOL_MOUSE => Some(DeviceKind::Pointer),
+        _ => None,
+    }
+}
+
+/// Switch `interface` to the boot protocol and open its interrupt endpoint.
+pub async fn open(device: &Arsc<Device>, interface: &Interface) -> Result<Endpoint> {
+    let desc = { interface.endpoints.iter() }
+        .find(|ep| ep.is_interrupt_in())
+        .ok_or(ENOENT)?;
+    let setup = |request, value| Setup {
+        request_type: REQ_TYPE_CLASS_INTERFACE,
+        request,
+        value,
+        index: interface.number as u16,
+        length: 0,
+    };
+    device
+        .control(setup(REQ_SET_PROTOCOL, BOOT_PROTOCOL), &mut [])
+        .await?;
+    // Report only on changes. Some devices stall the request, which is
+    // harmless.
+    if let Err(err) = device.control(setup(REQ_SET_IDLE, 0), &mut []).await {
+        log::debug!("port {}: SET_IDLE failed: {err}", device.port());
+    }
+    device.interrupt_in(desc).await
+}
+
+/// Translate the differences between two boot keyboard reports.
+fn keyboard_events(old: &[u8; 8], new: &[u8; 8], events: &mut Vec<InputEvent>) {
+    let changed = old[0] ^ new[0];
+    for bit in (0..8).filter(|bit| changed & (1 << bit) != 0) {
+        events.push(InputEvent::Key {
+            usage: USAGE_LEFT_CONTROL + bit,
+            pressed: new[0] & (1 << bit) != 0,
+        });
+    }
+
+    // The reports are filled with error codes on rollover, which are ignored.
+    if new[2..]
+        .iter()
+        .any(|&key| key != 0 && key < USAGE_FIRST_KEY)
+    {
+        return;
+    }
+    let keys = |report: &[u8; 8]| -> Vec<u8> {
+        report[2..]
+            .iter()
+            .copied()
+            .filter(|&key| key >= USAGE_FIRST_KEY)
+            .collect()
+    };
+    let (old, new) = (keys(old), keys(new));
+    for &key in old.iter().filter(|key| !new.contains(key)) {
+        events.push(InputEvent::Key {
+            usage: key as u16,
+            pressed: false,
+        });
+    }
+    for &key in new.iter().filter(|key| !old.contains(key)) {
+        events.push(InputEvent::Key {
+            usage: key as u16,
+            pressed: true,
+        });
+    }
+}
+
+/// Translate a boot mouse report, whose fourth byte is the wheel on most
+/// devices.
+fn mouse_events(buttons: &mut u8, report: &[u8], events: &mut Vec<InputEvent>) {
+    let [new, dx, dy, ref rest @ ..] = *report else {
+        return;
+    };
+    let changed = *buttons ^ new;
+    for button in (0..8).filter(|bit| changed & (1 << bit) != 0) {
+        events.push(InputEvent::Button {
+            button,
+            pressed: new & (1 << button) != 0,
+        });
+    }
+    *buttons = new;
+
+    if dx != 0 || dy != 0 {
+        events.push(InputEvent::Motion {
+            dx: dx as i8 as i32,
+            dy: dy as i8 as i32,
+        });
+    }
+    if let Some(&wheel) = rest.first().filter(|&&wheel| wheel != 0) {
+        events.push(InputEvent::Wheel {
+            delta: wheel as i8 as i32,
+        });
+    }
+}
+
+/// Read the reports of the endpoint until the device is gone, after which the
+/// connections of the input node are closed.
+pub async fn run(mut ep: Endpoint, broadcaster: Arsc<Broadcaster>) {
+    let mut keys = [0; 8];
+    let mut buttons = 0;
+    loop {
+        let report = match ep.read().await {
+            Ok(report) => report,
+            Err(err) => {
+                log::debug!(
+                    "port {}: stopped reading reports: {err}",
+                    ep.device().port()
+                );
+                break;
+            }
+        };
+
+        let mut events = Vec::new();
+        match broadcaster.kind() {
+            DeviceKind::Keyboard => {
+                let Ok(report) = <[u8; 8]>::try_from(&report[..]) else {
+                    continue;
+                };
+                keyboard_events(&keys, &report, &mut events);
+                keys = report;
+            }
+            DeviceKind::Pointer => mouse_events(&mut buttons, &report, &mut events),
+        }
+        if !events.is_empty() {
+            broadcaster.send(&events);
+        }
+    }
+    broadcaster.close();
+}
@@ -0,0 +1,80 @@
+use alloc::vec::Vec;
+
+use futures_lite::StreamExt;
+use solvent_core::sync::{Arsc, Mutex};
+use solvent_rpc::{
+    input::{DeviceEventSender, DeviceKind, DeviceRequest, DeviceServer, InputEvent, INPUT_EVENT},
+    packet, EventSender, Server,
+};
+
+/// Sends the input of a device to all its connections.
+pub struct Broadcaster {
+    kind: DeviceKind,
+    /// The event senders of the connections, or `None` if the device is gone.
+    senders: Mutex<Option<Vec<DeviceEventSender>>>,
+}
+
+impl Broadcaster {
+    pub fn new(kind: DeviceKind) -> Self {
+        Broadcaster {
+            kind,
+            senders: Mutex::new(Some(Vec::new())),
+        }
+    }
+
+    #[inline]
+    pub fn kind(&self) -> DeviceKind {
+        self.kind
+    }
+
+    /// Send `events` to every connection, dropping the closed ones.
+    pub fn send(&self, events: &[InputEvent]) {
+        let mut senders = self.senders.lock();
+        let Some(senders) = senders.as_mut() else {
+            return;
+        };
+        senders.retain(|sender| {
+            let mut packet = Default::default();
+            packet::serialize(INPUT_EVENT, events.to_vec(), &mut packet)
+                .and_then(|_| sender.send_raw(packet))
+                .is_ok()
+        });
+    }
+
+    /// Close all the connections after the device is gone.
+    pub fn close(&self) {
+        let senders = self.senders.lock().take();
+        senders.into_iter().flatten().for_each(EventSender::close);
+    }
+}
+
+pub async fn handle(broadcaster: Arsc<Broadcaster>, server: DeviceServer) {
+    let (mut stream, sender) = server.serve();
+    match broadcaster.senders.lock().as_mut() {
+        Some(senders) => senders.push(sender),
+        None => sender.close(),
+    }
+
+    while let Some(request) = stream.next().await {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+        };
+
+        let res = match request {
+            DeviceRequest::CloseConnection { responder } => responder.send(()),
+            DeviceRequest::Kind { responder } => responder.send(broadcaster.kind),
+            DeviceRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! The driver of xHCI USB host controllers.
+//!
+//! Devices attached to the root hub ports are addressed and configured, and
+//! every HID interface supporting the boot protocol is published as an input
+//! device named after the controller, the port and the interface, such as
+//! `input/xhci-0x18.3.0`. Devices behind external hubs are not supported yet.
+
+#![no_std]
+
+mod controller;
+mod hid;
+mod input;
+mod regs;
+mod ring;
+mod usb;
+
+use alloc::{boxed::Box, collections::BTreeMap, format};
+
+use async_trait::async_trait;
+use futures_lite::StreamExt;
+use oc_driver::{
+    bus::{Bus, DeviceInfo},
+    intr::IntrStream,
+    mmio::Mmio,
+    DeviceId, Driver, DriverInfo,
+};
+use solvent::error::Result;
+use solvent_async::sync::channel::Receiver;
+use solvent_core::sync::Arsc;
+
+use self::{
+    controller::Controller,
+    input::Broadcaster,
+    regs::*,
+    usb::{Device, DESC_DEVICE},
+};
+
+extern crate alloc;
+
+/// The BAR of the capability, operational and runtime registers.
+const BAR: usize = 0;
+
+struct Xhci {
+    ctrl: Arsc<Controller>,
+    intr: IntrStream,
+}
+
+/// Address the device attached to `port` and publish its input interfaces.
+async fn enumerate(
+    bus: &Arsc<dyn Bus>,
+    ctrl: &Arsc<Controller>,
+    addr: u64,
+    port: usize,
+) -> Result<Arsc<Device>> {
+    let device = Arsc::new(Device::attach(ctrl, port).await?);
+    let desc = device.descriptor(DESC_DEVICE, 18).await?;
+    log::debug!(
+        "port {port}: device {:04x}:{:04x}",
+        u16::from_le_bytes([desc[8], desc[9]]),
+        u16::from_le_bytes([desc[10], desc[11]])
+    );
+
+    for interface in device.configure().await? {
+        let Some(kind) = hid::boot_kind(&interface) else {
+            continue;
+        };
+        let ep = match hid::open(&device, &interface).await {
+            Ok(ep) => ep,
+            Err(err) => {
+                log::warn!(
+                    "port {port}: failed to open interface {}: {err}",
+                    interface.number
+                );
+                continue;
+            }
+        };
+        let broadcaster = Arsc::new(Broadcaster::new(kind));
+        oc_driver::spawn(hid::run(ep, Arsc::clone(&broadcaster))).detach();
+
+        let name = format!("input/xhci-{addr:#x}.{port}.{}", interface.number);
+        let serve = move |server| input::handle(Arsc::clone(&broadcaster), server);
+        if let Err(err) = oc_driver::publish(&**bus, &name, serve).await {
+            log::warn!("failed to publish {name}: {err}");
+        }
+    }
+    Ok(device)
+}
+
+/// Track the connections of the root hub ports.
+async fn hub(bus: Arsc<dyn Bus>, ctrl: Arsc<Controller>, addr: u64, changes: Receiver<usize>) {
+    let mut devices = BTreeMap::new();
+    let connected =
+        (1..=ctrl.regs.max_ports()).filter(|&port| ctrl.regs.portsc(port) & PORTSC_CCS != 0);
+    for port in connected {
+        match enumerate(&bus, &ctrl, addr, port).await {
+            Ok(device) => drop(devices.insert(port, device)),
+            Err(err) => log::warn!("port {port}: failed to enumerate the device: {err}"),
+        }
+    }
+
+    while let Ok(port) = changes.recv().await {
+        let portsc = ctrl.regs.portsc(port);
+        ctrl.regs
+            .set_portsc(port, PORTSC_PP | (portsc & PORTSC_CHANGES));
+
+        let connected = portsc & PORTSC_CCS != 0;
+        if connected && !devices.contains_key(&port) {
+            match enumerate(&bus, &ctrl, addr, port).await {
+                Ok(device) => drop(devices.insert(port, device)),
+                Err(err) => log::warn!("port {port}: failed to enumerate the device: {err}"),
+            }
+        } else if !connected {
+            if let Some(device) = devices.remove(&port) {
+                log::debug!("port {port}: device disconnected");
+                device.detach();
+            }
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Driver for Xhci {
+    const INFO: DriverInfo = DriverInfo {
+        name: "xhci",
+        ids: &[DeviceId::Class {
+            class: 0x0c,
+            subclass: 0x03,
+            prog_if: Some(0x30),
+        }],
+    };
+
+    async fn bind(bus: Arsc<dyn Bus>, device: DeviceInfo) -> Result<Self> {
+        let regs = Regs::new(Mmio::new(bus.mmio(device.addr, BAR).await?)?);
+        let intr = bus.interrupt(device.addr, 0).await?;
+        let (ctrl, changes) = Controller::new(Arsc::clone(&bus), regs).await?;
+        log::debug!(
+            "{}: {} slots, {} ports",
+            device.name,
+            ctrl.regs.max_slots(),
+            ctrl.regs.max_ports()
+        );
+
+        oc_driver::spawn(hub(bus, Arsc::clone(&ctrl), device.addr, changes)).detach();
+        Ok(Xhci { ctrl, intr })
+    }
+
+    async fn run(mut self) {
+        while let Some(res) = self.intr.next().await {
+            if let Err(err) = res {
+                log::error!("failed to wait for the interrupt: {err}");
+                break;
+            }
+            if let Err(err) = self.ctrl.handle_events() {
+                log::error!("failed to handle the events: {err}");
+            }
+        }
+        let cmd = self.ctrl.regs.op_read(USBCMD);
+        self.ctrl
+            .regs
+            .op_write(USBCMD, cmd & !(USBCMD_RS | USBCMD_INTE));
+    }
+}
+
+oc_driver::driver!(Xhci);
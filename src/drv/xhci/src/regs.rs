@@ -0,0 +1,141 @@
+//! The registers of xHCI host controllers, see the xHCI specification 1.2.
+
+use oc_driver::mmio::Mmio;
+
+// Capability registers.
+const CAPLENGTH: usize = 0x00;
+const HCSPARAMS1: usize = 0x04;
+const HCSPARAMS2: usize = 0x08;
+const HCCPARAMS1: usize = 0x10;
+const DBOFF: usize = 0x14;
+const RTSOFF: usize = 0x18;
+
+const HCCPARAMS1_AC64: u32 = 1 << 0;
+const HCCPARAMS1_CSZ: u32 = 1 << 2;
+
+// Operational registers.
+pub const USBCMD: usize = 0x00;
+pub const USBSTS: usize = 0x04;
+pub const CRCR: usize = 0x18;
+pub const DCBAAP: usize = 0x30;
+pub const CONFIG: usize = 0x38;
+const PORTSC_BASE: usize = 0x400;
+const PORT_SIZE: usize = 0x10;
+
+pub const USBCMD_RS: u32 = 1 << 0;
+pub const USBCMD_HCRST: u32 = 1 << 1;
+pub const USBCMD_INTE: u32 = 1 << 2;
+
+pub const USBSTS_HCH: u32 = 1 << 0;
+pub const USBSTS_EINT: u32 = 1 << 3;
+pub const USBSTS_CNR: u32 = 1 << 11;
+
+pub const PORTSC_CCS: u32 = 1 << 0;
+pub const PORTSC_PED: u32 = 1 << 1;
+pub const PORTSC_PR: u32 = 1 << 4;
+pub const PORTSC_PP: u32 = 1 << 9;
+pub const PORTSC_SPEED_SHIFT: u32 = 10;
+pub const PORTSC_SPEED_MASK: u32 = 0xf;
+pub const PORTSC_PRC: u32 = 1 << 21;
+/// The status change bits, which are cleared by writing 1.
+pub const PORTSC_CHANGES: u32 = 0x7f << 17;
+
+// Runtime registers of the primary interrupter.
+pub const IMAN: usize = 0x20;
+pub const ERSTSZ: usize = 0x28;
+pub const ERSTBA: usize = 0x30;
+pub const ERDP: usize = 0x38;
+
+pub const IMAN_IP: u32 = 1 << 0;
+pub const IMAN_IE: u32 = 1 << 1;
+pub const ERDP_EHB: u64 = 1 << 3;
+
+/// The registers mapped from the first BAR of the controller.
+#[derive(Debug)]
+pub struct Regs {
+    mmio: Mmio,
+    op: usize,
+    rt: usize,
+    db: usize,
+}
+
+impl Regs {
+    pub fn new(mmio: Mmio) -> Self {
+        let op = (mmio.read32(CAPLENGTH) & 0xff) as usize;
+        let rt = (mmio.read32(RTSOFF) & !0x1f) as usize;
+        let db = (mmio.read32(DBOFF) & !0x3) as usize;
+        Regs { mmio, op, rt, db }
+    }
+
+    pub fn max_slots(&self) -> usize {
+        (self.mmio.read32(HCSPARAMS1) & 0xff) as usize
+    }
+
+    pub fn max_ports(&self) -> usize {
+        (self.mmio.read32(HCSPARAMS1) >> 24) as usize
+    }
+
+    pub fn max_scratchpads(&self) -> usize {
+        let params = self.mmio.read32(HCSPARAMS2);
+        (((params >> 21) & 0x1f) << 5 | (params >> 27)) as usize
+    }
+
+    /// Whether the controller can access memory above 4 GiB.
+    pub fn ac64(&self) -> bool {
+        self.mmio.read32(HCCPARAMS1) & HCCPARAMS1_AC64 != 0
+    }
+
+    /// The size of a context in bytes.
+    pub fn context_size(&self) -> usize {
+        if self.mmio.read32(HCCPARAMS1) & HCCPARAMS1_CSZ != 0 {
+            64
+        } else {
+            32
+        }
+    }
+
+    #[inline]
+    pub fn op_read(&self, reg: usize) -> u32 {
+        self.mmio.read32(self.op + reg)
+    }
+
+    #[inline]
+    pub fn op_write(&self, reg: usize, value: u32) {
+        self.mmio.write32(self.op + reg, value)
+    }
+
+    #[inline]
+    pub fn op_write64(&self, reg: usize, value: u64) {
+        self.mmio.write32(self.op + reg, value as u32);
+        self.mmio.write32(self.op + reg + 4, (value >> 32) as u32);
+    }
+
+    /// Read the status and control register of the 1-based `port`.
+    #[inline]
+    pub fn portsc(&self, port: usize) -> u32 {
+        self.op_read(PORTSC_BASE + (port - 1) * PORT_SIZE)
+    }
+
+    #[inline]
+    pub fn set_portsc(&self, port: usize, value: u32) {
+        self.op_write(PORTSC_BASE + (port - 1) * PORT_SIZE, value)
+    }
+
+    #[inline]
+    pub fn rt_write(&self, reg: usize, value: u32) {
+        self.mmio.write32(self.rt + reg, value)
+    }
+
+    #[inline]
+    pub fn rt_write64(&self, reg: usize, value: u64) {
+        self.mmio.write32(self.rt + reg, value as u32);
+        self.mmio.write32(self.rt + reg + 4, (value >> 32) as u32);
+    }
+
+    /// Ring the doorbell `index` with `target`, where 0 is the host
+    /// controller and the others are device slots.
+    #[inline]
+    pub fn ring(&self, index: usize, target: u32) {
+        self.mmio.write32(self.db + index * 4, target)
+    }
+}
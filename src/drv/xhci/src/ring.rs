@@ -0,0 +1,188 @@
+//! Transfer request blocks and the rings of them.
+
+use oc_driver::dma::{DmaBuffer, DmaPool};
+use solvent::{error::Result, mem::PAGE_SIZE};
+
+pub const TRB_SIZE: usize = 16;
+/// The number of TRBs in a ring, each of which occupies a page.
+const RING_LEN: usize = PAGE_SIZE / TRB_SIZE;
+
+pub const TRB_NORMAL: u32 = 1;
+pub const TRB_SETUP: u32 = 2;
+pub const TRB_DATA: u32 = 3;
+pub const TRB_STATUS: u32 = 4;
+pub const TRB_LINK: u32 = 6;
+pub const TRB_ENABLE_SLOT: u32 = 9;
+pub const TRB_ADDRESS_DEVICE: u32 = 11;
+pub const TRB_CONFIGURE_ENDPOINT: u32 = 12;
+pub const TRB_TRANSFER_EVENT: u32 = 32;
+pub const TRB_COMMAND_COMPLETION: u32 = 33;
+pub const TRB_PORT_STATUS_CHANGE: u32 = 34;
+
+pub const TRB_CYCLE: u32 = 1 << 0;
+/// Toggle the cycle state, in link TRBs.
+const TRB_TC: u32 = 1 << 1;
+/// Interrupt on short packet.
+pub const TRB_ISP: u32 = 1 << 2;
+pub const TRB_IOC: u32 = 1 << 5;
+/// Immediate data, in setup TRBs.
+pub const TRB_IDT: u32 = 1 << 6;
+/// The direction of data and status TRBs.
+pub const TRB_DIR_IN: u32 = 1 << 16;
+const TRB_TYPE_SHIFT: u32 = 10;
+
+pub const CC_SUCCESS: u8 = 1;
+pub const CC_SHORT_PACKET: u8 = 13;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Trb {
+    pub param: u64,
+    pub status: u32,
+    pub control: u32,
+}
+
+impl Trb {
+    pub fn new(ty: u32, param: u64, status: u32, flags: u32) -> Self {
+        Trb {
+            param,
+            status,
+            control: (ty << TRB_TYPE_SHIFT) | flags,
+        }
+    }
+
+    #[inline]
+    pub fn ty(&self) -> u32 {
+        (self.control >> TRB_TYPE_SHIFT) & 0x3f
+    }
+
+    /// The completion code of an event TRB.
+    #[inline]
+    pub fn completion_code(&self) -> u8 {
+        (self.status >> 24) as u8
+    }
+
+    /// The slot ID of a command completion or transfer event TRB.
+    #[inline]
+    pub fn slot(&self) -> u8 {
+        (self.control >> 24) as u8
+    }
+
+    fn to_bytes(self) -> [u8; TRB_SIZE] {
+        let mut bytes = [0; TRB_SIZE];
+        bytes[..8].copy_from_slice(&self.param.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.status.to_le_bytes());
+        bytes[12..].copy_from_slice(&self.control.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; TRB_SIZE]) -> Self {
+        Trb {
+            param: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            status: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            control: u32::from_le_bytes(bytes[12..].try_into().unwrap()),
+        }
+    }
+}
+
+/// A command or transfer ring, where the TRBs are produced by the software.
+pub struct Ring {
+    buf: DmaBuffer,
+    enqueue: usize,
+    cycle: bool,
+}
+
+impl Ring {
+    pub async fn new(pages: &DmaPool) -> Result<Self> {
+        let buf = pages.alloc().await?;
+        buf.write(0, &[0; PAGE_SIZE])?;
+        Ok(Ring {
+            buf,
+            enqueue: 0,
+            cycle: true,
+        })
+    }
+
+    /// The address of the ring, along with its cycle state for the consumer.
+    #[inline]
+    pub fn addr(&self) -> u64 {
+        self.buf.addr() | TRB_CYCLE as u64
+    }
+
+    fn write(&self, index: usize, mut trb: Trb) -> Result {
+        trb.control = (trb.control & !TRB_CYCLE) | self.cycle as u32;
+        self.buf.write(index * TRB_SIZE, &trb.to_bytes())
+    }
+
+    /// Push a TRB into the ring, returning its address.
+    pub fn push(&mut self, trb: Trb) -> Result<u64> {
+        let addr = self.buf.addr() + (self.enqueue * TRB_SIZE) as u64;
+        self.write(self.enqueue, trb)?;
+        self.enqueue += 1;
+
+        if self.enqueue == RING_LEN - 1 {
+            let link = Trb::new(TRB_LINK, self.buf.addr(), 0, TRB_TC);
+            self.write(self.enqueue, link)?;
+            self.enqueue = 0;
+            self.cycle = !self.cycle;
+        }
+        Ok(addr)
+    }
+}
+
+/// The event ring of an interrupter, where the TRBs are produced by the
+/// controller.
+pub struct EventRing {
+    buf: DmaBuffer,
+    /// The event ring segment table with the only segment.
+    erst: DmaBuffer,
+    dequeue: usize,
+    cycle: bool,
+}
+
+impl EventRing {
+    pub async fn new(pages: &DmaPool) -> Result<Self> {
+        let buf = pages.alloc().await?;
+        buf.write(0, &[0; PAGE_SIZE])?;
+
+        let erst = pages.alloc().await?;
+        let mut entry = [0; 16];
+        entry[..8].copy_from_slice(&buf.addr().to_le_bytes());
+        entry[8..12].copy_from_slice(&(RING_LEN as u32).to_le_bytes());
+        erst.write(0, &entry)?;
+
+        Ok(EventRing {
+            buf,
+            erst,
+            dequeue: 0,
+            cycle: true,
+        })
+    }
+
+    #[inline]
+    pub fn erst_addr(&self) -> u64 {
+        self.erst.addr()
+    }
+
+    /// The address of the TRB to be dequeued next.
+    #[inline]
+    pub fn dequeue_addr(&self) -> u64 {
+        self.buf.addr() + (self.dequeue * TRB_SIZE) as u64
+    }
+
+    /// Pop the next event if the controller has produced it.
+    pub fn pop(&mut self) -> Result<Option<Trb>> {
+        let mut bytes = [0; TRB_SIZE];
+        self.buf.read(self.dequeue * TRB_SIZE, &mut bytes)?;
+        let trb = Trb::from_bytes(&bytes);
+        if (trb.control & TRB_CYCLE != 0) != self.cycle {
+            return Ok(None);
+        }
+
+        self.dequeue += 1;
+        if self.dequeue == RING_LEN {
+            self.dequeue = 0;
+            self.cycle = !self.cycle;
+        }
+        Ok(Some(trb))
+    }
+}
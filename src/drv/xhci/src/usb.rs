@@ -0,0 +1,430 @@
+//! The USB device core: enumeration, descriptors and transfers.
+
+use alloc::{vec, vec::Vec};
+
+use oc_driver::dma::DmaBuffer;
+use solvent::error::{Result, EINVAL, EIO, EPIPE};
+use solvent_async::sync::channel::Receiver;
+use solvent_core::sync::{Arsc, Mutex};
+
+use crate::{controller::*, regs::*, ring::*};
+
+pub const DESC_DEVICE: u8 = 1;
+pub const DESC_CONFIGURATION: u8 = 2;
+const DESC_INTERFACE: u8 = 4;
+const DESC_ENDPOINT: u8 = 5;
+
+const REQ_GET_DESCRIPTOR: u8 = 6;
+const REQ_SET_CONFIGURATION: u8 = 9;
+
+/// Device-to-host, standard, to the device.
+pub const REQ_TYPE_IN: u8 = 0x80;
+/// Host-to-device, standard, to the device.
+pub const REQ_TYPE_OUT: u8 = 0x00;
+/// Host-to-device, class-specific, to an interface.
+pub const REQ_TYPE_CLASS_INTERFACE: u8 = 0x21;
+
+const SPEED_FULL: u32 = 1;
+const SPEED_LOW: u32 = 2;
+const SPEED_HIGH: u32 = 3;
+
+const EP_TYPE_INTERRUPT_IN: u32 = 7;
+const EP_TYPE_CONTROL: u32 = 4;
+const EP_ATTR_INTERRUPT: u8 = 3;
+/// The number of retries on transaction errors.
+const EP_CERR: u32 = 3;
+
+/// A setup packet of a control transfer.
+#[derive(Debug, Clone, Copy)]
+pub struct Setup {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl Setup {
+    fn to_trb(self) -> Trb {
+        let param = u64::from(self.request_type)
+            | u64::from(self.request) << 8
+            | u64::from(self.value) << 16
+            | u64::from(self.index) << 32
+            | u64::from(self.length) << 48;
+        let trt = match (self.length, self.request_type & REQ_TYPE_IN) {
+            (0, _) => 0,
+            (_, 0) => 2,
+            _ => 3,
+        };
+        Trb::new(TRB_SETUP, param, 8, TRB_IDT | (trt << 16))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EndpointDesc {
+    pub address: u8,
+    pub attributes: u8,
+    pub max_packet: u16,
+    pub interval: u8,
+}
+
+impl EndpointDesc {
+    #[inline]
+    pub fn is_interrupt_in(&self) -> bool {
+        self.address & 0x80 != 0 && self.attributes & 0x3 == EP_ATTR_INTERRUPT
+    }
+
+    /// The device context index of the endpoint.
+    #[inline]
+    fn dci(&self) -> u8 {
+        (self.address & 0xf) * 2 + (self.address >> 7)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Interface {
+    pub number: u8,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub endpoints: Vec<EndpointDesc>,
+}
+
+/// Parse a full configuration descriptor, returning its configuration value
+/// and interfaces.
+pub fn parse_config(data: &[u8]) -> Result<(u8, Vec<Interface>)> {
+    let value = *data.get(5).ok_or(EINVAL)?;
+    let mut interfaces = Vec::<Interface>::new();
+
+    let mut rest = data;
+    while let [len, ty, ..] = *rest {
+        let len = len as usize;
+        if len < 2 || len > rest.len() {
+            return Err(EINVAL);
+        }
+        let desc = &rest[..len];
+        match ty {
+            DESC_INTERFACE if len >= 9 => interfaces.push(Interface {
+                number: desc[2],
+                class: desc[5],
+                subclass: desc[6],
+                protocol: desc[7],
+                endpoints: Vec::new(),
+            }),
+            DESC_ENDPOINT if len >= 7 => {
+                let ep = EndpointDesc {
+                    address: desc[2],
+                    attributes: desc[3],
+                    max_packet: u16::from_le_bytes([desc[4], desc[5]]) & 0x7ff,
+                    interval: desc[6],
+                };
+                if let Some(interface) = interfaces.last_mut() {
+                    interface.endpoints.push(ep);
+                }
+            }
+            _ => {}
+        }
+        rest = &rest[len..];
+    }
+    Ok((value, interfaces))
+}
+
+/// Check the completion code of a transfer event, returning the number of
+/// bytes not transferred.
+fn check_transfer(event: Trb) -> Result<usize> {
+    match event.completion_code() {
+        CC_SUCCESS | CC_SHORT_PACKET => Ok((event.status & 0xff_ffff) as usize),
+        code => {
+            log::warn!("transfer failed with code {code}");
+            Err(EIO)
+        }
+    }
+}
+
+struct Control {
+    ring: Ring,
+    events: Receiver<Trb>,
+    buf: DmaBuffer,
+}
+
+/// An addressed USB device attached to a root hub port.
+pub struct Device {
+    ctrl: Arsc<Controller>,
+    slot: u8,
+    port: usize,
+    speed: u32,
+    /// The output device context, owned by the controller.
+    context: DmaBuffer,
+    control: Mutex<Option<Control>>,
+}
+
+impl Device {
+    /// Reset the root hub `port` and address the device attached to it.
+    pub async fn attach(ctrl: &Arsc<Controller>, port: usize) -> Result<Self> {
+        let regs = &ctrl.regs;
+        regs.set_portsc(port, PORTSC_PP | PORTSC_PR);
+        wait(|| regs.portsc(port) & PORTSC_PRC != 0).await?;
+        regs.set_portsc(port, PORTSC_PP | PORTSC_PRC);
+
+        let portsc = regs.portsc(port);
+        if portsc & PORTSC_PED == 0 {
+            return Err(EIO);
+        }
+        let speed = (portsc >> PORTSC_SPEED_SHIFT) & PORTSC_SPEED_MASK;
+
+        let event = ctrl.command(Trb::new(TRB_ENABLE_SLOT, 0, 0, 0)).await?;
+        let slot = event.slot();
+        let context = ctrl.alloc_page().await?;
+        ctrl.set_device_context(slot, context.addr())?;
+
+        let device = Device {
+            ctrl: Arsc::clone(ctrl),
+            slot,
+            port,
+            speed,
+            context,
+            control: Mutex::new(None),
+        };
+        device.address().await?;
+        Ok(device)
+    }
+
+    #[inline]
+    pub fn port(&self) -> usize {
+        self.port
+    }
+
+    fn max_packet0(&self) -> u32 {
+        match self.speed {
+            SPEED_FULL | SPEED_LOW => 8,
+            SPEED_HIGH => 64,
+            _ => 512,
+        }
+    }
+
+    /// Write the context `index` of an input context, where 0 is the input
+    /// control context, 1 is the slot context and the others are endpoint
+    /// contexts.
+    fn write_context(&self, input: &DmaBuffer, index: usize, dwords: &[u32]) -> Result {
+        let size = self.ctrl.regs.context_size();
+        let bytes = dwords
+            .iter()
+            .flat_map(|dw| dw.to_le_bytes())
+            .collect::<Vec<_>>();
+        input.write(index * size, &bytes)
+    }
+
+    async fn address(&self) -> Result {
+        let ring = self.ctrl.ring().await?;
+        let events = self.ctrl.endpoint(self.slot, 1);
+        let buf = self.ctrl.alloc_page().await?;
+
+        let input = self.ctrl.alloc_page().await?;
+        self.write_context(&input, 0, &[0, 0b11])?;
+        self.write_context(
+            &input,
+            1,
+            &[(self.speed << 20) | (1 << 27), (self.port as u32) << 16],
+        )?;
+        let tr = ring.addr();
+        self.write_context(
+            &input,
+            2,
+            &[
+                0,
+                (EP_CERR << 1) | (EP_TYPE_CONTROL << 3) | (self.max_packet0() << 16),
+                tr as u32,
+                (tr >> 32) as u32,
+                8,
+            ],
+        )?;
+
+        let trb = Trb::new(
+            TRB_ADDRESS_DEVICE,
+            input.addr(),
+            0,
+            (self.slot as u32) << 24,
+        );
+        self.ctrl.command(trb).await?;
+
+        *self.control.lock() = Some(Control { ring, events, buf });
+        Ok(())
+    }
+
+    /// Perform a control transfer, with the data stage if `setup.length` is
+    /// not 0.
+    pub async fn control(&self, setup: Setup, data: &mut [u8]) -> Result {
+        let len = setup.length as usize;
+        let dir_in = setup.request_type & REQ_TYPE_IN != 0;
+        if len != data.len() || len > solvent::mem::PAGE_SIZE {
+            return Err(EINVAL);
+        }
+
+        // The control endpoint is taken during the transfer, so that
+        // transfers are serialized.
+        let mut control = self.control.lock().take().ok_or(EPIPE)?;
+        let res: Result = async {
+            control.ring.push(setup.to_trb())?;
+            let mut status_flags = TRB_IOC | TRB_DIR_IN;
+            if len > 0 {
+                if !dir_in {
+                    control.buf.write(0, data)?;
+                }
+                let flags = if dir_in { TRB_DIR_IN } else { 0 };
+                let trb = Trb::new(TRB_DATA, control.buf.addr(), len as u32, flags);
+                control.ring.push(trb)?;
+                if dir_in {
+                    status_flags = TRB_IOC;
+                }
+            }
+            control
+                .ring
+                .push(Trb::new(TRB_STATUS, 0, 0, status_flags))?;
+            self.ctrl.regs.ring(self.slot as usize, 1);
+
+            let event = control.events.recv().await.map_err(|_| EPIPE)?;
+            check_transfer(event)?;
+            if len > 0 && dir_in {
+                control.buf.read(0, data)?;
+            }
+            Ok(())
+        }
+        .await;
+        *self.control.lock() = Some(control);
+        res
+    }
+
+    pub async fn descriptor(&self, ty: u8, len: usize) -> Result<Vec<u8>> {
+        let mut data = vec![0; len];
+        let setup = Setup {
+            request_type: REQ_TYPE_IN,
+            request: REQ_GET_DESCRIPTOR,
+            value: (ty as u16) << 8,
+            index: 0,
+            length: len as u16,
+        };
+        self.control(setup, &mut data).await?;
+        Ok(data)
+    }
+
+    /// Read the full configuration descriptor and select the configuration.
+    pub async fn configure(&self) -> Result<Vec<Interface>> {
+        let header = self.descriptor(DESC_CONFIGURATION, 9).await?;
+        let total = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let config = self.descriptor(DESC_CONFIGURATION, total).await?;
+        let (value, interfaces) = parse_config(&config)?;
+
+        let setup = Setup {
+            request_type: REQ_TYPE_OUT,
+            request: REQ_SET_CONFIGURATION,
+            value: value as u16,
+            index: 0,
+            length: 0,
+        };
+        self.control(setup, &mut []).await?;
+        Ok(interfaces)
+    }
+
+    /// Configure an interrupt IN endpoint of the device.
+    pub async fn interrupt_in(self: &Arsc<Self>, desc: &EndpointDesc) -> Result<Endpoint> {
+        if !desc.is_interrupt_in() {
+            return Err(EINVAL);
+        }
+        let dci = desc.dci();
+        let ring = self.ctrl.ring().await?;
+        let events = self.ctrl.endpoint(self.slot, dci);
+        let buf = self.ctrl.alloc_page().await?;
+
+        // Copy the current slot context, making room for the new endpoint.
+        let mut slot = [0; 8];
+        self.context.read(0, &mut slot)?;
+        let dw0 = u32::from_le_bytes(slot[..4].try_into().unwrap());
+        let entries = (dw0 >> 27).max(dci as u32);
+        let dw1 = u32::from_le_bytes(slot[4..].try_into().unwrap());
+
+        // The interval is in 125-us units, as an exponent of 2.
+        let interval = match self.speed {
+            SPEED_FULL | SPEED_LOW => (desc.interval.max(1) as u32 * 8).ilog2().clamp(3, 10),
+            _ => (desc.interval.clamp(1, 16) - 1) as u32,
+        };
+        let mps = desc.max_packet as u32;
+
+        let input = self.ctrl.alloc_page().await?;
+        self.write_context(&input, 0, &[0, 1 | (1 << dci)])?;
+        self.write_context(&input, 1, &[(dw0 & !(0x1f << 27)) | (entries << 27), dw1])?;
+        let tr = ring.addr();
+        self.write_context(
+            &input,
+            dci as usize + 1,
+            &[
+                interval << 16,
+                (EP_CERR << 1) | (EP_TYPE_INTERRUPT_IN << 3) | (mps << 16),
+                tr as u32,
+                (tr >> 32) as u32,
+                mps | (mps << 16),
+            ],
+        )?;
+
+        let trb = Trb::new(
+            TRB_CONFIGURE_ENDPOINT,
+            input.addr(),
+            0,
+            (self.slot as u32) << 24,
+        );
+        self.ctrl.command(trb).await?;
+
+        Ok(Endpoint {
+            device: Arsc::clone(self),
+            dci,
+            max_packet: desc.max_packet as usize,
+            ring,
+            events,
+            buf,
+        })
+    }
+}
+
+impl Device {
+    /// Stop all the transfers of the device after it is disconnected.
+    pub fn detach(&self) {
+        self.ctrl.remove_endpoints(self.slot);
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        self.detach();
+    }
+}
+
+/// An interrupt IN endpoint.
+pub struct Endpoint {
+    device: Arsc<Device>,
+    dci: u8,
+    max_packet: usize,
+    ring: Ring,
+    events: Receiver<Trb>,
+    buf: DmaBuffer,
+}
+
+impl Endpoint {
+    #[inline]
+    pub fn device(&self) -> &Arsc<Device> {
+        &self.device
+    }
+
+    /// Wait for the next packet from the device.
+    pub async fn read(&mut self) -> Result<Vec<u8>> {
+        let len = self.max_packet;
+        let trb = Trb::new(TRB_NORMAL, self.buf.addr(), len as u32, TRB_IOC | TRB_ISP);
+        self.ring.push(trb)?;
+        let device = &self.device;
+        device.ctrl.regs.ring(device.slot as usize, self.dci as u32);
+
+        let event = self.events.recv().await.map_err(|_| EPIPE)?;
+        let len = len - check_transfer(event)?.min(len);
+        let mut data = vec![0; len];
+        self.buf.read(0, &mut data)?;
+        Ok(data)
+    }
+}
@@ -3,11 +3,14 @@ use std::{collections::VecDeque, io::Write, mem, vec::Vec};
 use plain::Plain;
 
 use crate::{
-    BootfsHeader, EntryType, ENTRY_LAYOUT, HEADER_SIZE, MAX_NAME_LEN, PAGE_LAYOUT, VERSION,
+    BootfsHeader, Compression, EntryType, ENTRY_LAYOUT, HEADER_SIZE, MAX_NAME_LEN, PAGE_LAYOUT,
+    VERSION,
 };
 
 pub enum Content {
     File(Vec<u8>),
+    /// A file whose raw content will be compressed in the image.
+    Compressed(Compression, Vec<u8>),
     Directory(Vec<Entry>),
 }
 
@@ -32,20 +35,45 @@ fn split(input: &Entry, entries: &mut Vec<super::Entry>, contents: &mut Vec<Vec<
                     version: VERSION,
                     name: name.try_into().expect("Name too long"),
                     ty: crate::EntryType::File,
+                    compression: Compression::None,
                     offset: 0,
                     len: content.len(),
+                    raw_len: content.len(),
+                    hash: crate::blake3::hash(content),
                 };
                 ent_index += 1;
                 entries.push(entry);
                 contents.push(content.clone());
             }
+            Content::Compressed(compression, raw) => {
+                let content = match compression {
+                    Compression::None => raw.clone(),
+                    Compression::Lz4 => crate::lz4::compress(raw),
+                };
+                let entry = super::Entry {
+                    version: VERSION,
+                    name: name.try_into().expect("Name too long"),
+                    ty: crate::EntryType::File,
+                    compression: *compression,
+                    offset: 0,
+                    len: content.len(),
+                    raw_len: raw.len(),
+                    hash: crate::blake3::hash(&content),
+                };
+                ent_index += 1;
+                entries.push(entry);
+                contents.push(content);
+            }
             Content::Directory(ent) => {
                 let entry = super::Entry {
                     version: VERSION,
                     name: name.try_into().expect("Name too long"),
                     ty: crate::EntryType::Directory,
+                    compression: Compression::None,
                     offset: HEADER_SIZE + (ent_index + q.len() + 1) * mem::size_of::<usize>(),
                     len: ent.len() * mem::size_of::<usize>(),
+                    raw_len: ent.len() * mem::size_of::<usize>(),
+                    hash: [0; crate::blake3::OUT_LEN],
                 };
                 ent_index += 1;
                 entries.push(entry);
@@ -14,9 +14,12 @@
 
 #[cfg(feature = "gen")]
 pub mod gen;
+pub mod lz4;
 pub mod parse;
 mod types;
 
+pub use crypto::blake3;
+
 pub use self::types::*;
 
 #[cfg(feature = "gen")]
@@ -0,0 +1,154 @@
+//! The LZ4 block format, without the frame header.
+
+#[cfg(feature = "gen")]
+use std::vec::Vec;
+
+const MIN_MATCH: usize = 4;
+/// The last literals of a block must be at least this long.
+#[cfg(feature = "gen")]
+const LAST_LITERALS: usize = 5;
+/// The last match must start at least this far from the end of a block.
+#[cfg(feature = "gen")]
+const MF_LIMIT: usize = 12;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Error;
+
+fn read_len(src: &[u8], pos: &mut usize) -> Result<usize, Error> {
+    let mut len = 0;
+    loop {
+        let byte = *src.get(*pos).ok_or(Error)?;
+        *pos += 1;
+        len += byte as usize;
+        if byte != u8::MAX {
+            break Ok(len);
+        }
+    }
+}
+
+/// Decompress a block into `dst`, returning the length of the decompressed
+/// data.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    let (mut i, mut o) = (0, 0);
+    loop {
+        let token = *src.get(i).ok_or(Error)?;
+        i += 1;
+
+        let mut lit_len = (token >> 4) as usize;
+        if lit_len == 15 {
+            lit_len += read_len(src, &mut i)?;
+        }
+        let literals = src.get(i..(i + lit_len)).ok_or(Error)?;
+        dst.get_mut(o..(o + lit_len))
+            .ok_or(Error)?
+            .copy_from_slice(literals);
+        i += lit_len;
+        o += lit_len;
+        if i == src.len() {
+            break Ok(o);
+        }
+
+        let offset = src.get(i..(i + 2)).ok_or(Error)?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        i += 2;
+        if offset == 0 || offset > o {
+            return Err(Error);
+        }
+
+        let mut match_len = (token & 0xf) as usize + MIN_MATCH;
+        if match_len == 15 + MIN_MATCH {
+            match_len += read_len(src, &mut i)?;
+        }
+        if o + match_len > dst.len() {
+            return Err(Error);
+        }
+        // The match may overlap with its own output, so copy byte by byte.
+        for k in o..(o + match_len) {
+            dst[k] = dst[k - offset];
+        }
+        o += match_len;
+    }
+}
+
+#[cfg(feature = "gen")]
+fn write_len(output: &mut Vec<u8>, mut len: usize) {
+    while len >= u8::MAX as usize {
+        output.push(u8::MAX);
+        len -= u8::MAX as usize;
+    }
+    output.push(len as u8);
+}
+
+#[cfg(feature = "gen")]
+fn write_seq(output: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let lit_len = literals.len();
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    output.push(((lit_len.min(15) as u8) << 4) | match_len.min(15) as u8);
+    if lit_len >= 15 {
+        write_len(output, lit_len - 15);
+    }
+    output.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            write_len(output, match_len - 15);
+        }
+    }
+}
+
+/// Compress the data into a block with a greedy matcher.
+#[cfg(feature = "gen")]
+pub fn compress(src: &[u8]) -> Vec<u8> {
+    const HASH_LOG: u32 = 12;
+
+    let mut output = Vec::with_capacity(src.len() / 2);
+    let mut table = std::vec![usize::MAX; 1 << HASH_LOG];
+    let read = |pos: usize| u32::from_le_bytes(src[pos..(pos + 4)].try_into().unwrap());
+
+    let mut anchor = 0;
+    let mut pos = 0;
+    while pos + MF_LIMIT < src.len() {
+        let seq = read(pos);
+        let hash = (seq.wrapping_mul(2654435761) >> (u32::BITS - HASH_LOG)) as usize;
+        let candidate = table[hash];
+        table[hash] = pos;
+
+        if candidate != usize::MAX && pos - candidate <= u16::MAX as usize && read(candidate) == seq
+        {
+            let max = src.len() - LAST_LITERALS;
+            let mut len = MIN_MATCH;
+            while pos + len < max && src[candidate + len] == src[pos + len] {
+                len += 1;
+            }
+            write_seq(&mut output, &src[anchor..pos], Some((pos - candidate, len)));
+            pos += len;
+            anchor = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    write_seq(&mut output, &src[anchor..], None);
+    output
+}
+
+#[cfg(all(test, feature = "gen"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut data = std::vec![0u8; 0];
+        data.extend_from_slice(b"The quick brown fox jumps over the lazy dog. ");
+        data.extend((0..5000u32).map(|i| (i % 7) as u8));
+        data.extend((0..3000u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8));
+        data.extend_from_slice(b"The quick brown fox jumps over the lazy dog. ");
+
+        for input in [&[][..], b"short", &data] {
+            let compressed = compress(input);
+            let mut output = std::vec![0; input.len()];
+            assert_eq!(decompress(&compressed, &mut output), Ok(input.len()));
+            assert_eq!(&output, input);
+        }
+        assert!(compress(&data).len() < data.len() / 2);
+    }
+}
@@ -3,7 +3,7 @@ use core::mem;
 use either::Either;
 use plain::Plain;
 
-use crate::{MAX_NAME_LEN, VERSION};
+use crate::{blake3, lz4, Compression, MAX_NAME_LEN, VERSION};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Directory<'a> {
@@ -40,17 +40,36 @@ impl<'a> Directory<'a> {
         self.iter().find(|ent| ent.name_eq(name))
     }
 
-    pub fn find(self, path: &[u8], separator: u8) -> Option<&'a [u8]> {
+    /// Split the path into names, skipping empty and `.` components.
+    fn names(path: &[u8], separator: u8) -> impl Iterator<Item = &[u8]> {
+        { path.split(move |&b| b == separator) }.filter(|name| !name.is_empty() && *name != b".")
+    }
+
+    /// Find the file entry of the path.
+    pub fn find_entry(self, path: &[u8], separator: u8) -> Option<Entry<'a>> {
         let mut dir = self;
-        let mut names = path.split(|&b| b == separator);
+        let mut names = Self::names(path, separator);
         loop {
             let entry: Entry<'a> = dir.get(names.next()?)?;
             dir = match entry.content() {
-                Either::Left(content) => break Some(content),
+                Either::Left(_) => break names.next().is_none().then_some(entry),
                 Either::Right(dir) => dir,
             };
         }
     }
+
+    /// Find the directory of the path, which is `self` if the path is empty.
+    pub fn find_dir(self, path: &[u8], separator: u8) -> Option<Directory<'a>> {
+        Self::names(path, separator).try_fold(self, |dir, name| dir.get(name)?.content().right())
+    }
+
+    /// Find the content of the path.
+    ///
+    /// Note that the content of a compressed file is returned as is; use
+    /// [`Entry::decompress`] to get its raw content.
+    pub fn find(self, path: &[u8], separator: u8) -> Option<&'a [u8]> {
+        self.find_entry(path, separator)?.content().left()
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -95,10 +114,63 @@ impl<'a> Entry<'a> {
         &self.metadata.name[..len] == name && self.metadata.name[len] == b'\0'
     }
 
+    /// The name of the entry without the trailing NULs.
+    pub fn name(&self) -> &[u8] {
+        let name = &self.metadata.name;
+        let len = name.iter().position(|&b| b == b'\0');
+        &name[..len.unwrap_or(MAX_NAME_LEN)]
+    }
+
     pub fn metadata(&self) -> &super::Entry {
         &self.metadata
     }
 
+    #[inline]
+    pub fn compression(&self) -> Compression {
+        self.metadata.compression
+    }
+
+    /// The length of the content after decompression.
+    #[inline]
+    pub fn raw_len(&self) -> usize {
+        self.metadata.raw_len
+    }
+
+    /// The BLAKE3 hash of the content in the image.
+    #[inline]
+    pub fn hash(&self) -> &[u8; blake3::OUT_LEN] {
+        &self.metadata.hash
+    }
+
+    /// Check the content of the file against its hash.
+    ///
+    /// Always returns `false` for directories.
+    pub fn verify(&self) -> bool {
+        match self.content() {
+            Either::Left(content) => blake3::hash(content) == self.metadata.hash,
+            Either::Right(_) => false,
+        }
+    }
+
+    /// Decompress the content of the file into `buf`, which must be at least
+    /// [`Entry::raw_len`] long.
+    ///
+    /// Returns the length of the raw content.
+    pub fn decompress(&self, buf: &mut [u8]) -> Result<usize, lz4::Error> {
+        let content = match self.content() {
+            Either::Left(content) => content,
+            Either::Right(_) => return Err(lz4::Error),
+        };
+        match self.metadata.compression {
+            Compression::None => {
+                let buf = buf.get_mut(..content.len()).ok_or(lz4::Error)?;
+                buf.copy_from_slice(content);
+                Ok(content.len())
+            }
+            Compression::Lz4 => lz4::decompress(content, buf),
+        }
+    }
+
     pub fn content(self) -> Either<&'a [u8], Directory<'a>> {
         let content = &self.image[self.metadata.offset..][..self.metadata.len];
         match self.metadata.ty {
@@ -14,14 +14,29 @@ pub enum EntryType {
     Directory,
 }
 
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Compression {
+    #[default]
+    None,
+    /// The LZ4 block format.
+    Lz4,
+}
+
 #[derive(Debug, Copy, Clone)]
 #[repr(C, align(128))]
 pub struct Entry {
     pub version: u32,
     pub name: [u8; 64],
     pub ty: EntryType,
+    pub compression: Compression,
     pub offset: usize,
+    /// The length of the (compressed) content in the image.
     pub len: usize,
+    /// The length of the content after decompression.
+    pub raw_len: usize,
+    /// The BLAKE3 hash of the content in the image, or zeros for directories.
+    pub hash: [u8; crate::blake3::OUT_LEN],
 }
 const_assert!(mem::size_of::<Entry>() <= 128);
 
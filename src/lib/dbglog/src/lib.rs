@@ -1,10 +1,14 @@
 #![no_std]
 
+pub mod ring;
+
 use core::fmt::{self, Write};
 
 use solvent::prelude::Instant;
 use spin::Mutex;
 
+use self::ring::Writer;
+
 fn cur_cpu() -> usize {
     let mut ret;
     unsafe { core::arch::asm!("rdtscp", out("rcx") ret, options(nostack)) };
@@ -19,15 +23,23 @@ static BUFFER: Mutex<Buffer> = Mutex::new(Buffer([0; BUFFER_SIZE], 0));
 
 struct Buffer([u8; BUFFER_SIZE], usize);
 
+/// The ring shared with the log service, replacing the syscall if attached.
+static RING: Mutex<Option<Writer>> = Mutex::new(None);
+
 struct Logger;
 
-impl Write for Buffer {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        let bytes = s.as_bytes();
+impl Buffer {
+    fn write_bytes(&mut self, bytes: &[u8]) {
         let start = self.1;
         let end = (start + bytes.len()).min(BUFFER_SIZE);
         self.0[start..end].copy_from_slice(&bytes[..(end - start)]);
         self.1 = end;
+    }
+}
+
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
         Ok(())
     }
 }
@@ -41,21 +53,13 @@ impl log::Log for Logger {
         let cur_time = Instant::now();
         let mut buffer = BUFFER.lock();
         if record.level() < log::Level::Debug {
-            write!(
-                &mut *buffer,
-                "[{}] {}: {}",
-                cur_time,
-                record.level(),
-                record.args()
-            )
+            write!(&mut *buffer, "{}", record.args())
         } else {
             let file = record.file().unwrap_or("<NULL>");
             let line = record.line().unwrap_or(0);
             write!(
                 &mut *buffer,
-                "[{}] {}: [#{} {}:{}] {}",
-                cur_time,
-                record.level(),
+                "[#{} {}:{}] {}",
                 cur_cpu(),
                 file,
                 line,
@@ -63,15 +67,60 @@ impl log::Log for Logger {
             )
         }
         .expect("Failed to write str");
-        let _ = unsafe { sv_call::sv_log(buffer.0.as_ptr(), buffer.1) };
+
+        // The log service stamps the records itself.
+        let msg = &buffer.0[..buffer.1];
+        let written = match &mut *RING.lock() {
+            Some(ring) => write_ring(ring, record.level(), cur_time, msg),
+            None => false,
+        };
+        if !written {
+            let mut line = Buffer([0; BUFFER_SIZE], 0);
+            write!(&mut line, "[{}] {}: ", cur_time, record.level()).expect("Failed to write str");
+            line.write_bytes(msg);
+            let _ = unsafe { sv_call::sv_log(line.0.as_ptr(), line.1) };
+        }
         *buffer = Buffer([0; BUFFER_SIZE], 0);
         drop(buffer);
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(ring) = &*RING.lock() {
+            ring.ring_doorbell()
+        }
+    }
+}
+
+/// Write the record to the ring, and ring the doorbell on warnings and errors
+/// or when the ring becomes half full. Other records are drained periodically
+/// by the log service.
+fn write_ring(ring: &mut Writer, level: log::Level, time: Instant, msg: &[u8]) -> bool {
+    let half = ring.capacity() / 2;
+    let before = ring.fill();
+    if !ring.write(level, time, msg) {
+        ring.ring_doorbell();
+        return false;
+    }
+    if level <= log::Level::Warn || (before < half && ring.fill() >= half) {
+        ring.ring_doorbell();
+    }
+    true
 }
 
 pub fn init(max_level: log::Level) {
     log::set_logger(&LOGGER).expect("Failed to set the logger");
     log::set_max_level(max_level.to_level_filter());
 }
+
+/// Write the records to `ring` instead of the kernel log from now on.
+///
+/// The records that don't fit in the ring still go to the kernel log.
+pub fn attach(ring: Writer) {
+    *RING.lock() = Some(ring);
+}
+
+/// Write `text` to the kernel log as is, such as the records merged by the log
+/// service.
+pub fn write_raw(text: &str) -> solvent::error::Result {
+    unsafe { sv_call::sv_log(text.as_ptr(), text.len()) }.into_res()
+}
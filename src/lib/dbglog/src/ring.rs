@@ -0,0 +1,298 @@
+//! The shared-memory rings of log records.
+//!
+//! A ring is a [`Header`] followed by the data area, written by the logger of
+//! a single task and read by the log service. The positions in the header
+//! count the bytes ever written and read, so the ring is full when they're
+//! `capacity` apart.
+//!
+//! Every record starts with a [`RecordHeader`] and is aligned to 8 bytes. A
+//! record never wraps around: if it doesn't fit before the end of the data
+//! area, the rest of the area is skipped, marked by a padding record if
+//! there's room for its header.
+//!
+//! Every record has the next sequence number of the writer, including the
+//! ones that don't fit in the ring, so the reader detects the losses by the
+//! gaps between the numbers.
+
+use core::{
+    mem,
+    ptr::{self, NonNull},
+    slice,
+    sync::atomic::{AtomicU64, Ordering::*},
+};
+
+use solvent::{
+    ipc::{Event, SIG_GENERIC},
+    time::Instant,
+};
+
+/// The size of the rings allocated by the log service.
+pub const RING_SIZE: usize = 64 * 1024;
+/// The signal notified on the doorbell when the reader should drain the ring.
+pub const SIG_DOORBELL: usize = SIG_GENERIC;
+
+const HEADER_SIZE: usize = 64;
+const RECORD_ALIGN: usize = 8;
+const PADDING: u32 = 0;
+
+#[repr(C)]
+pub struct Header {
+    /// The bytes ever written to the data area, published by the writer.
+    head: AtomicU64,
+    /// The bytes ever consumed from the data area, published by the reader.
+    tail: AtomicU64,
+}
+
+const _: () = assert!(mem::size_of::<Header>() <= HEADER_SIZE);
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct RecordHeader {
+    /// The length of the header and the message.
+    len: u32,
+    /// The level of the record as `log::Level as u32`, or [`PADDING`].
+    level: u32,
+    seq: u64,
+    /// The raw timestamp of the record in nanoseconds.
+    time: u64,
+}
+
+const RECORD_HEADER: usize = mem::size_of::<RecordHeader>();
+
+struct Ring {
+    base: NonNull<u8>,
+    capacity: usize,
+}
+
+impl Ring {
+    /// # Safety
+    ///
+    /// `ring` must be mapped, aligned to 8 bytes, and larger than
+    /// [`HEADER_SIZE`].
+    unsafe fn new(ring: NonNull<[u8]>) -> Self {
+        let capacity = (ring.len() - HEADER_SIZE) & !(RECORD_ALIGN - 1);
+        Ring {
+            base: ring.cast(),
+            capacity,
+        }
+    }
+
+    fn header(&self) -> &Header {
+        // SAFETY: The header is at the start of the ring.
+        unsafe { self.base.cast().as_ref() }
+    }
+
+    fn data(&self, offset: usize) -> *mut u8 {
+        debug_assert!(offset < self.capacity);
+        // SAFETY: The offset is inside the data area.
+        unsafe { self.base.as_ptr().add(HEADER_SIZE + offset) }
+    }
+
+    fn offset(&self, pos: u64) -> usize {
+        (pos % self.capacity as u64) as usize
+    }
+}
+
+/// The writing end of a ring, owned by the logger of a task.
+pub struct Writer {
+    ring: Ring,
+    seq: u64,
+    doorbell: Event,
+}
+
+// SAFETY: The ring is only written through `&mut self`.
+unsafe impl Send for Writer {}
+
+impl Writer {
+    /// # Safety
+    ///
+    /// `ring` must be a mapped and writable ring of [`RING_SIZE`] bytes, and
+    /// the only writer of it.
+    pub unsafe fn new(ring: NonNull<[u8]>, doorbell: Event) -> Self {
+        Writer {
+            ring: Ring::new(ring),
+            seq: 0,
+            doorbell,
+        }
+    }
+
+    /// The bytes not consumed by the reader yet.
+    pub fn fill(&self) -> usize {
+        let header = self.ring.header();
+        let head = header.head.load(Relaxed);
+        head.saturating_sub(header.tail.load(Acquire)) as usize
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity
+    }
+
+    /// Write a record of `msg`, truncated to fit in half of the ring.
+    ///
+    /// Returns `false` if the ring is full, in which case the sequence number
+    /// of the record is skipped.
+    pub fn write(&mut self, level: log::Level, time: Instant, msg: &[u8]) -> bool {
+        let seq = self.seq;
+        self.seq += 1;
+
+        let capacity = self.ring.capacity;
+        let msg = &msg[..msg.len().min(capacity / 2 - RECORD_HEADER)];
+        let len = RECORD_HEADER + msg.len();
+        let stride = len.next_multiple_of(RECORD_ALIGN);
+
+        let header = self.ring.header();
+        let head = header.head.load(Relaxed);
+        let tail = header.tail.load(Acquire);
+        let offset = self.ring.offset(head);
+        let rest = capacity - offset;
+        let skip = if stride > rest { rest } else { 0 };
+        if head + (skip + stride) as u64 - tail > capacity as u64 {
+            return false;
+        }
+
+        let record = RecordHeader {
+            len: len as u32,
+            level: level as u32,
+            seq,
+            // SAFETY: The timestamp is only displayed by the reader.
+            time: unsafe { time.raw() } as u64,
+        };
+        // SAFETY: The space from `head` is consumed by the reader and owned by
+        // the writer.
+        unsafe {
+            let mut ptr = self.ring.data(offset);
+            if skip > 0 {
+                if skip >= RECORD_HEADER {
+                    let padding = RecordHeader {
+                        len: skip as u32,
+                        level: PADDING,
+                        seq: 0,
+                        time: 0,
+                    };
+                    ptr.cast::<RecordHeader>().write(padding);
+                }
+                ptr = self.ring.data(0);
+            }
+            ptr.cast::<RecordHeader>().write(record);
+            ptr::copy_nonoverlapping(msg.as_ptr(), ptr.add(RECORD_HEADER), msg.len());
+        }
+        header.head.store(head + (skip + stride) as u64, Release);
+        true
+    }
+
+    /// Ask the reader to drain the ring.
+    pub fn ring_doorbell(&self) {
+        let _ = self.doorbell.notify(0, SIG_DOORBELL);
+    }
+}
+
+/// A record read from a ring.
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    pub level: log::Level,
+    pub seq: u64,
+    pub time: Instant,
+    pub msg: &'a [u8],
+}
+
+/// The ring is corrupted by its writer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Corrupted;
+
+/// The reading end of a ring, owned by the log service.
+pub struct Reader {
+    ring: Ring,
+}
+
+// SAFETY: The ring is only read through `&mut self`.
+unsafe impl Send for Reader {}
+
+impl Reader {
+    /// # Safety
+    ///
+    /// `ring` must be a mapped and writable ring of [`RING_SIZE`] bytes, and
+    /// the only reader of it.
+    pub unsafe fn new(ring: NonNull<[u8]>) -> Self {
+        Reader {
+            ring: Ring::new(ring),
+        }
+    }
+
+    /// Consume all the records in the ring with `f`.
+    ///
+    /// The writer is not trusted, so the ring is emptied if a record is
+    /// malformed.
+    pub fn drain(&mut self, mut f: impl FnMut(Record)) -> Result<(), Corrupted> {
+        let header = self.ring.header();
+        let head = header.head.load(Acquire);
+        let mut tail = header.tail.load(Relaxed);
+        let res = loop {
+            let len = match self.next(head, tail, &mut f) {
+                Ok(Some(len)) => len,
+                Ok(None) => break Ok(()),
+                Err(err) => {
+                    tail = head;
+                    break Err(err);
+                }
+            };
+            tail += len as u64;
+        };
+        header.tail.store(tail, Release);
+        res
+    }
+
+    fn next(
+        &self,
+        head: u64,
+        tail: u64,
+        f: &mut impl FnMut(Record),
+    ) -> Result<Option<usize>, Corrupted> {
+        let avail = head.checked_sub(tail).ok_or(Corrupted)?;
+        if avail == 0 {
+            return Ok(None);
+        }
+        if avail > self.ring.capacity as u64 {
+            return Err(Corrupted);
+        }
+        let offset = self.ring.offset(tail);
+        let rest = self.ring.capacity - offset;
+        if rest < RECORD_HEADER {
+            return Ok(Some(rest));
+        }
+
+        // SAFETY: The header is inside the data area. The writer doesn't touch
+        // the unconsumed records.
+        let record = unsafe { self.ring.data(offset).cast::<RecordHeader>().read() };
+        let len = record.len as usize;
+        let stride = len.next_multiple_of(RECORD_ALIGN);
+        if len < RECORD_HEADER || stride > rest || stride as u64 > avail {
+            return Err(Corrupted);
+        }
+        if record.level == PADDING {
+            return Ok(Some(stride));
+        }
+
+        let level = match record.level {
+            1 => log::Level::Error,
+            2 => log::Level::Warn,
+            3 => log::Level::Info,
+            4 => log::Level::Debug,
+            5 => log::Level::Trace,
+            _ => return Err(Corrupted),
+        };
+        // SAFETY: The message is inside the record.
+        let msg = unsafe {
+            let ptr = self.ring.data(offset).add(RECORD_HEADER);
+            slice::from_raw_parts(ptr, len - RECORD_HEADER)
+        };
+        f(Record {
+            level,
+            seq: record.seq,
+            // SAFETY: The timestamp is only displayed.
+            time: unsafe { Instant::from_raw(record.time as u128) },
+            msg,
+        });
+        Ok(Some(stride))
+    }
+}
@@ -6,7 +6,7 @@ use alloc::vec::Vec;
 use core::{mem, ops::Range};
 
 use goblin::elf64::{header::*, program_header::*, section_header::*};
-use solvent::prelude::{Flags, Phys, PhysOptions, Virt, PAGE_MASK};
+use solvent::prelude::{ErrorKind, Flags, Phys, PhysOptions, Virt, PAGE_MASK};
 
 #[derive(Debug)]
 pub enum Error {
@@ -20,6 +20,21 @@ pub enum Error {
     VirtMap(solvent::error::Error),
 }
 
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::ElfParse(_) => ErrorKind::InvalidData,
+            Error::NotSupported(_) => ErrorKind::Unsupported,
+            Error::PhysAlloc(err)
+            | Error::PhysRead(err)
+            | Error::PhysWrite(err)
+            | Error::PhysSub(err)
+            | Error::VirtAlloc(err)
+            | Error::VirtMap(err) => err.kind(),
+        }
+    }
+}
+
 pub struct LoadedElf {
     pub is_dyn: bool,
     pub virt: Virt,
@@ -31,6 +46,8 @@ pub struct LoadedElf {
     pub dynamic: Option<ProgramHeader>,
     pub tls: Option<ProgramHeader>,
     pub sym_len: usize,
+    /// The base address the object is prelinked at, if any.
+    pub prelink: Option<usize>,
 }
 
 pub fn parse_flags(flags: u32) -> Flags {
@@ -104,6 +121,17 @@ pub fn get_addr_range_info(segments: &[ProgramHeader]) -> (usize, usize) {
         })
 }
 
+/// Get the base address a dynamic object is prelinked at.
+///
+/// `cargo xtask dist --prelink` records it as the difference between the
+/// physical and the virtual addresses of the first loadable segment, which are
+/// otherwise the same.
+pub fn get_prelink_base(segments: &[ProgramHeader]) -> Option<usize> {
+    let first = segments.iter().find(|segment| segment.p_type == PT_LOAD)?;
+    let base = first.p_paddr.wrapping_sub(first.p_vaddr) as usize;
+    (base != 0).then_some(base)
+}
+
 pub fn map_segment(
     segment: &ProgramHeader,
     phys: &Phys,
@@ -129,12 +157,23 @@ pub fn map_segment(
     let csize = cend - fend;
     let asize = mend.saturating_sub(fend);
 
-    let flags = parse_flags(segment.p_flags);
+    // The segments are laid out back to back.
+    let flags = parse_flags(segment.p_flags) | Flags::NO_GUARD;
 
     if fsize > 0 {
-        let data = phys
-            .create_sub(offset, fsize, true)
-            .map_err(Error::PhysSub)?;
+        // The read-only segments share the pages of the object if it allows,
+        // so that the processes loading the same object share them too.
+        let shared = if flags.contains(Flags::WRITABLE) {
+            None
+        } else {
+            phys.create_sub(offset, fsize, false).ok()
+        };
+        let data = match shared {
+            Some(data) => data,
+            None => phys
+                .create_sub(offset, fsize, true)
+                .map_err(Error::PhysSub)?,
+        };
 
         log::trace!(
             "Map {:#x}~{:#x} -> {:#x}",
@@ -187,14 +226,24 @@ pub fn load(phys: &Phys, dyn_only: bool, root_virt: &Virt) -> Result<LoadedElf,
     let segments = parse_segments(phys, header.e_phoff as usize, header.e_phnum as usize)?;
     let sections = parse_sections(phys, header.e_shoff as usize, header.e_shnum as usize)?;
     let (min, max) = get_addr_range_info(&segments);
+    let prelink = is_dyn.then(|| get_prelink_base(&segments)).flatten();
 
     let virt = {
         let layout = unsafe { Virt::page_aligned(max - min) };
+        let root_base = root_virt.base().as_ptr() as usize;
         if is_dyn {
-            root_virt.allocate(None, layout).map_err(Error::VirtAlloc)?
+            // Try the prelinked address first, and fall back to a random one if
+            // it's taken by others.
+            let prelinked = prelink.and_then(|prelink| {
+                let offset = (prelink + min).checked_sub(root_base)?;
+                root_virt.allocate(Some(offset), layout).ok()
+            });
+            match prelinked {
+                Some(virt) => virt,
+                None => root_virt.allocate(None, layout).map_err(Error::VirtAlloc)?,
+            }
         } else {
-            let base = root_virt.base().as_ptr() as usize;
-            let offset = min.checked_sub(base).ok_or(Error::NotSupported(
+            let offset = min.checked_sub(root_base).ok_or(Error::NotSupported(
                 "The specified address is out of bounds",
             ))?;
             root_virt
@@ -247,5 +296,6 @@ pub fn load(phys: &Phys, dyn_only: bool, root_virt: &Virt) -> Result<LoadedElf,
         dynamic,
         tls,
         sym_len,
+        prelink,
     })
 }
@@ -0,0 +1,107 @@
+//! Cooperative scheduling of the async tasks.
+//!
+//! Every poll of a spawned task gets a budget of operations on the leaf
+//! futures, such as receiving from channels. Once it's exhausted, the leaf
+//! futures return `Pending` and wake the task right away, so that a task that
+//! always has something to do yields to the others on the same executor
+//! instead of starving them.
+//!
+//! Polls taking longer than a threshold are logged along with the type of the
+//! future, for finding the tasks that block the executors.
+
+use core::{
+    any,
+    cell::Cell,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use solvent::time::Instant;
+use solvent_core::thread_local;
+
+/// The number of the operations a task can do in a poll.
+const BUDGET: u8 = 128;
+
+/// The default threshold of the slow polls in microseconds.
+const DEFAULT_SLOW_POLL: u64 = 10_000;
+
+static SLOW_POLL: AtomicU64 = AtomicU64::new(DEFAULT_SLOW_POLL);
+
+thread_local! {
+    /// The remaining budget of the current poll, or `None` outside the
+    /// spawned tasks, which is unconstrained.
+    static REMAINING: Cell<Option<u8>> = const { Cell::new(None) };
+}
+
+/// Set the threshold above which the polls of the tasks are logged, or
+/// disable the logging with `Duration::MAX`.
+pub fn set_slow_poll_threshold(threshold: Duration) {
+    let us = u64::try_from(threshold.as_micros()).unwrap_or(u64::MAX);
+    SLOW_POLL.store(us, Relaxed);
+}
+
+/// Consume a unit of the budget of the current task, or return `Pending` and
+/// wake the task if it's exhausted.
+///
+/// Leaf futures call it before doing any operation.
+pub fn poll_proceed(cx: &mut Context<'_>) -> Poll<()> {
+    REMAINING.with(|remaining| match remaining.get() {
+        Some(0) => {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+        Some(n) => {
+            remaining.set(Some(n - 1));
+            Poll::Ready(())
+        }
+        None => Poll::Ready(()),
+    })
+}
+
+/// Consume a unit of the budget of the current task, yielding if it's
+/// exhausted.
+///
+/// Loops that never await leaf futures can call it to be cooperative.
+pub async fn consume_budget() {
+    core::future::poll_fn(poll_proceed).await
+}
+
+/// A future polled with a fresh budget every time.
+pub(crate) struct Budgeted<F> {
+    fut: F,
+}
+
+impl<F> Budgeted<F> {
+    #[inline]
+    pub(crate) fn new(fut: F) -> Self {
+        Budgeted { fut }
+    }
+}
+
+impl<F: Future> Future for Budgeted<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: The inner future is never moved.
+        let fut = unsafe { self.map_unchecked_mut(|this| &mut this.fut) };
+
+        let prev = REMAINING.with(|remaining| remaining.replace(Some(BUDGET)));
+        let start = Instant::now();
+        let ret = fut.poll(cx);
+        let elapsed = start.elapsed();
+        REMAINING.with(|remaining| remaining.set(prev));
+
+        let threshold = SLOW_POLL.load(Relaxed);
+        if elapsed.as_micros() > u128::from(threshold) {
+            log::warn!(
+                "Task {} polled for {:?}, starving the others",
+                any::type_name::<F>(),
+                elapsed
+            );
+        }
+        ret
+    }
+}
@@ -3,9 +3,11 @@ use core::{
     num::NonZeroUsize,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use solvent::{
+    dev::{IntrLatency, IntrPollStat},
     prelude::{PackIntrWait, Result, SerdeReg, Syscall, ENOENT, EPIPE, SIG_GENERIC},
     time::Instant,
 };
@@ -50,6 +52,26 @@ impl Interrupt {
         self.inner.last_time()
     }
 
+    #[inline]
+    pub fn latency(&self) -> Result<IntrLatency> {
+        self.inner.latency()
+    }
+
+    #[inline]
+    pub fn start_polling(&self, interval: Duration, budget: u32) -> Result {
+        self.inner.start_polling(interval, budget)
+    }
+
+    #[inline]
+    pub fn stop_polling(&self) -> Result {
+        self.inner.stop_polling()
+    }
+
+    #[inline]
+    pub fn poll_stat(&self) -> Result<IntrPollStat> {
+        self.inner.poll_stat()
+    }
+
     #[inline]
     pub fn wait_next(&self) -> WaitNext<'_> {
         WaitNext {
@@ -17,7 +17,7 @@ use async_task::{Runnable, Task};
 use futures_lite::{future::yield_now, pin, stream, Future, FutureExt, StreamExt};
 use solvent_core::sync::{Arsc, Injector, Lazy, Steal, Stealer, Worker};
 
-use crate::{disp::DispReceiver, sync::RwLock};
+use crate::{coop::Budgeted, disp::DispReceiver, sync::RwLock};
 
 struct Inner {
     global: Injector<Runnable>,
@@ -56,6 +56,7 @@ impl Executor {
         T: Send + 'static,
     {
         let inner = self.inner.clone();
+        let fut = Budgeted::new(fut);
         let (runnable, task) = async_task::spawn(fut, move |task| inner.global.push(task));
         runnable.schedule();
         task
@@ -107,6 +108,7 @@ impl LocalExecutor {
 
     pub fn spawn<T: 'static>(&self, fut: impl Future<Output = T> + 'static) -> Task<T> {
         let inner = self.exe.inner.clone();
+        let fut = Budgeted::new(fut);
         // SAFETY: The executor is not `Send`, so the future doesn't need to be `Send`.
         let (runnable, task) =
             unsafe { async_task::spawn_unchecked(fut, move |task| inner.global.push(task)) };
@@ -1,7 +1,7 @@
 use alloc::vec::Vec;
 use core::fmt;
 
-use solvent::prelude::{IoSlice, IoSliceMut};
+use solvent::prelude::{ErrorKind, IoSlice, IoSliceMut};
 use solvent_core::io::{RawStream, SeekFrom};
 
 use crate::{disp::DispSender, mem::Phys, sync::Mutex};
@@ -118,6 +118,15 @@ pub enum Error {
     InvalidSeek(SeekFrom),
 }
 
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Other(err) => err.kind(),
+            Error::InvalidSeek(_) => ErrorKind::InvalidInput,
+        }
+    }
+}
+
 struct Inner {
     phys: Phys,
     seeker: usize,
@@ -60,8 +60,7 @@ impl<T: Object> AsyncObject for T {
             disp,
             level_triggered,
             signal,
-            result: None,
-            key: None,
+            state: WaitState::default(),
         }
     }
 }
@@ -90,23 +89,57 @@ pub struct TryWait<'a, T> {
     disp: &'a DispSender,
     level_triggered: bool,
     signal: usize,
-    result: Option<oneshot::Receiver<Result<usize>>>,
-    key: Option<usize>,
+    state: WaitState,
 }
 
 impl<'a, T: Object> Future for TryWait<'a, T> {
     type Output = Result<usize>;
 
+    #[inline]
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let (obj, disp) = (this.obj, this.disp);
+        this.state
+            .poll_wait(obj, disp, this.level_triggered, this.signal, cx)
+    }
+}
+
+/// The state of a wait on an object, kept across the polls by its owner
+/// instead of a [`TryWait`] borrowing the object.
+///
+/// Every wait registers a request in the dispatcher, so the same state must be
+/// polled until the wait completes.
+#[derive(Default)]
+pub struct WaitState {
+    result: Option<oneshot::Receiver<Result<usize>>>,
+    key: Option<usize>,
+}
+
+impl WaitState {
+    /// Poll the wait for `signal` on `obj`, registering it in `disp` first if
+    /// it's not registered yet. The state is reset once the wait completes.
+    pub fn poll_wait(
+        &mut self,
+        obj: &impl Object,
+        disp: &DispSender,
+        level_triggered: bool,
+        signal: usize,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize>> {
         if let Some(ref rx) = self.result {
             match rx.try_recv() {
-                Ok(result) => return Poll::Ready(result),
+                Ok(result) => {
+                    *self = Default::default();
+                    return Poll::Ready(result);
+                }
                 Err(TryRecvError::Empty) => {
                     let Some(key) = self.key else {
+                        *self = Default::default();
                         return Poll::Ready(Err(ENOENT))
                     };
-                    if let Err(err) = self.disp.update(key, cx.waker()) {
+                    if let Err(err) = disp.update(key, cx.waker()) {
                         if let Ok(res) = rx.recv() {
+                            *self = Default::default();
                             return Poll::Ready(res);
                         }
                         panic!("Update future error with key {key}: {err:?}");
@@ -122,13 +155,7 @@ impl<'a, T: Object> Future for TryWait<'a, T> {
         let (mut tx, rx) = oneshot();
         self.result = Some(rx);
         loop {
-            match self.disp.poll_send(
-                self.obj,
-                self.level_triggered,
-                self.signal,
-                (PackWait, tx),
-                cx.waker(),
-            ) {
+            match disp.poll_send(obj, level_triggered, signal, (PackWait, tx), cx.waker()) {
                 Err(pack) => {
                     tx = pack.1;
                     backoff.snooze()
@@ -4,18 +4,22 @@ use core::{
     num::NonZeroUsize,
     ops::ControlFlow,
     pin::Pin,
-    task::{Context, Poll},
+    task::{ready, Context, Poll},
 };
 
 use solvent::prelude::{
-    Handle, PackRecv, Packet, Result, SerdeReg, Syscall, EBUFFER, ENOENT, EPIPE, SIG_READ,
+    Handle, PackRecv, Packet, Result, SerdeReg, Syscall, EAGAIN, EBUFFER, ENOENT, EPIPE, SIG_READ,
+    SIG_WRITE,
 };
 use solvent_core::{
     sync::channel::{oneshot, TryRecvError},
     thread::Backoff,
 };
 
-use crate::disp::{DispError, DispSender, PackedSyscall};
+use crate::{
+    disp::{DispError, DispSender, PackedSyscall},
+    ipc::{AsyncObject, TryWait, WaitState},
+};
 
 type Inner = solvent::ipc::Channel;
 
@@ -79,6 +83,35 @@ impl Channel {
             .map(|_| *packet = Default::default())
     }
 
+    /// Send a packet, waiting for the replenishment of the credit if the
+    /// channel is flow-controlled and the credit is exhausted.
+    pub async fn send_wait(&self, packet: &mut Packet) -> Result {
+        loop {
+            match self.send(packet) {
+                Err(EAGAIN) => {
+                    self.writable().await?;
+                }
+                res => break res,
+            }
+        }
+    }
+
+    /// Wait for the credit of a flow-controlled channel to be available.
+    #[inline]
+    pub fn writable(&self) -> TryWait<'_, Inner> {
+        self.inner.try_wait_with(&self.disp, true, SIG_WRITE)
+    }
+
+    /// Poll the wait of [`Channel::writable`] kept in `state`.
+    #[inline]
+    pub fn poll_writable(
+        &self,
+        state: &mut WaitState,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize>> {
+        state.poll_wait(&self.inner, &self.disp, true, SIG_WRITE, cx)
+    }
+
     #[inline]
     pub fn receive_with(&self, packet: Packet) -> Receive {
         Receive {
@@ -224,6 +257,7 @@ impl<'a> Future for Receive<'a> {
     type Output = Result<Packet>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Packet>> {
+        ready!(crate::coop::poll_proceed(cx));
         let mut packet = match self.result_recv(cx) {
             ControlFlow::Continue(packet) => packet,
             ControlFlow::Break(res) => return res,
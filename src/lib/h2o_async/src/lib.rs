@@ -2,6 +2,7 @@
 #![feature(control_flow_enum)]
 #![feature(error_in_core)]
 
+pub mod coop;
 pub mod dev;
 pub mod disp;
 pub mod exe;
@@ -9,6 +10,7 @@ pub mod io;
 pub mod ipc;
 pub mod mem;
 pub mod sync;
+pub mod task;
 pub mod time;
 mod utils;
 
@@ -89,10 +91,134 @@ pub mod test {
         assert_eq!(&buf[..len], [4, 5, 6, 7]);
     }
 
+    async fn test_sleep() {
+        use core::time::Duration;
+
+        use solvent::time::Instant;
+
+        for duration in [Duration::from_millis(20), Duration::from_millis(200)] {
+            let start = Instant::now();
+            crate::time::sleep(duration).await.expect("Failed to sleep");
+            assert!(start.elapsed() >= duration);
+        }
+
+        // Dropping a pending sleep cancels its timer.
+        let sleep = crate::time::Sleep::new(Instant::now() + Duration::from_secs(10));
+        let res = futures_lite::future::poll_once(sleep).await;
+        assert!(res.is_none());
+    }
+
+    async fn test_job_supervisor() {
+        use core::{
+            ptr::{addr_of_mut, NonNull},
+            time::Duration,
+        };
+
+        use futures_lite::StreamExt;
+        use solvent::{prelude::ENOENT, task::Task};
+
+        use crate::task::{Child, JobSupervisor};
+
+        const STACK_SIZE: usize = 0x4000;
+
+        #[repr(align(16))]
+        struct Stack([u8; STACK_SIZE]);
+        static mut STACKS: [Stack; 3] = [
+            Stack([0; STACK_SIZE]),
+            Stack([0; STACK_SIZE]),
+            Stack([0; STACK_SIZE]),
+        ];
+
+        extern "C" fn func(_: u64, retval: usize) {
+            if retval == 0 {
+                loop {
+                    let _ = solvent::task::sleep(Duration::from_secs(1));
+                }
+            }
+            unsafe { solvent::task::exit(retval, false) }
+        }
+
+        let exec = |index: usize, retval: u64| {
+            let stack = unsafe { addr_of_mut!(STACKS[index].0).cast::<u8>().add(STACK_SIZE) };
+            let task = Task::exec(
+                Some("job-child"),
+                None,
+                NonNull::new(func as *mut u8).unwrap(),
+                NonNull::new(stack).unwrap(),
+                None,
+                retval,
+            )
+            .expect("Failed to create a task");
+            Child::new(task)
+        };
+
+        // The tasks in the current space belong to the current job.
+        let job = solvent::task::job().expect("Failed to get the job");
+        let mut supervisor = JobSupervisor::new();
+        for (index, retval) in [(0, 3), (1, 4)] {
+            let res = supervisor.insert(retval, exec(index, retval as u64));
+            assert_eq!(res, Ok(job));
+        }
+        assert!(supervisor.contains(job));
+
+        let mut exits = alloc::vec![];
+        while let Some(exit) = supervisor.next().await {
+            assert_eq!(exit.job, job);
+            exits.push((exit.key, exit.retval));
+        }
+        exits.sort_unstable_by_key(|&(key, _)| key);
+        assert_eq!(exits, [(3, Ok(3)), (4, Ok(4))]);
+        assert!(!supervisor.contains(job));
+        assert_eq!(supervisor.kill(job), Err(ENOENT));
+
+        // Killed tasks are yielded as well.
+        supervisor
+            .insert(0, exec(2, 0))
+            .expect("Failed to supervise the task");
+        supervisor.kill(job).expect("Failed to kill the job");
+        let exit = supervisor.next().await.expect("Failed to get the exit");
+        assert!(exit.retval.is_ok());
+        assert!(supervisor.next().await.is_none());
+    }
+
+    #[cfg(feature = "test-util")]
+    async fn test_virtual_time() {
+        use core::time::Duration;
+
+        use futures_lite::future::poll_once;
+
+        use crate::time::{self, virt};
+
+        virt::pause();
+        let start = time::now();
+
+        let long = time::sleep_until(start + Duration::from_secs(3600));
+        let short = time::Sleep::new(start + Duration::from_secs(60));
+        futures_lite::pin!(long, short);
+        assert!(poll_once(long.as_mut()).await.is_none());
+        assert!(poll_once(short.as_mut()).await.is_none());
+
+        virt::advance(Duration::from_secs(59)).await;
+        assert!(poll_once(short.as_mut()).await.is_none());
+        virt::advance(Duration::from_secs(1)).await;
+        assert!(poll_once(short.as_mut()).await.is_some());
+        assert_eq!(time::now() - start, Duration::from_secs(60));
+
+        virt::advance(Duration::from_secs(3540)).await;
+        let res = poll_once(long).await;
+        assert!(matches!(res, Some(Ok(()))));
+
+        virt::resume();
+    }
+
     pub async fn test_disp() {
         log::debug!("Has {} cpus available", solvent::task::cpu_num());
 
         test_stream().await;
+        test_sleep().await;
+        test_job_supervisor().await;
+        #[cfg(feature = "test-util")]
+        test_virtual_time().await;
 
         let (send, recv) = test_tx();
         let recv = crate::spawn(recv);
@@ -1171,6 +1171,7 @@ impl<'a, T> Future for Send<'a, T> {
     type Output = Result<(), SendError<T>>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(crate::coop::poll_proceed(cx));
         self.run_with_strategy::<NonBlocking<'_>>(cx)
     }
 }
@@ -1229,6 +1230,7 @@ impl<'a, T> Future for Recv<'a, T> {
     type Output = Result<T, RecvError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(crate::coop::poll_proceed(cx));
         self.run_with_strategy::<NonBlocking<'_>>(cx)
     }
 }
@@ -0,0 +1,212 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_lite::Stream;
+use solvent::{
+    error::Result,
+    obj::Object,
+    prelude::{ENOENT, SIG_GENERIC},
+    task::Task,
+};
+
+use crate::{disp::DispSender, ipc::AsyncObject};
+
+/// A child task whose exit can be awaited.
+pub struct Child {
+    inner: Task,
+    disp: DispSender,
+}
+
+#[cfg(feature = "runtime")]
+impl From<Task> for Child {
+    #[inline]
+    fn from(inner: Task) -> Self {
+        Self::new(inner)
+    }
+}
+
+impl AsRef<Task> for Child {
+    #[inline]
+    fn as_ref(&self) -> &Task {
+        &self.inner
+    }
+}
+
+impl From<Child> for Task {
+    #[inline]
+    fn from(value: Child) -> Self {
+        value.inner
+    }
+}
+
+impl Child {
+    #[inline]
+    #[cfg(feature = "runtime")]
+    pub fn new(inner: Task) -> Self {
+        Self::with_disp(inner, crate::dispatch())
+    }
+
+    #[inline]
+    pub fn with_disp(inner: Task, disp: DispSender) -> Self {
+        Child { inner, disp }
+    }
+
+    #[inline]
+    pub fn into_inner(this: Self) -> Task {
+        this.inner
+    }
+
+    #[inline]
+    pub fn rebind(&mut self, disp: DispSender) {
+        self.disp = disp
+    }
+
+    #[inline]
+    pub fn kill(&self) -> Result {
+        self.inner.kill()
+    }
+
+    /// Get the return value of the task if it has already exited.
+    #[inline]
+    pub fn try_wait(&self) -> Result<Option<usize>> {
+        match self.inner.retval() {
+            Ok(retval) => Ok(Some(retval)),
+            Err(ENOENT) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Wait for the task to exit and get its return value.
+    ///
+    /// The returned future doesn't consume the task, so it can be raced with
+    /// other futures and polled again later.
+    pub async fn wait(&self) -> Result<usize> {
+        if let Some(retval) = self.try_wait()? {
+            return Ok(retval);
+        }
+        self.inner
+            .try_wait_with(&self.disp, true, SIG_GENERIC)
+            .await?;
+        self.inner.retval()
+    }
+}
+
+/// The exit of a child task supervised by a [`JobSupervisor`].
+pub struct Exit<K> {
+    pub key: K,
+    /// The job the child task belonged to.
+    pub job: u64,
+    pub child: Child,
+    pub retval: Result<usize>,
+}
+
+type ExitFuture<K> = Pin<Box<dyn Future<Output = Exit<K>> + Send>>;
+
+struct Entry<K> {
+    job: u64,
+    task: Task,
+    exit: ExitFuture<K>,
+}
+
+/// A set of child tasks, yielding their exits in the order they happen.
+///
+/// Every child is tracked with the kernel job it belongs to, so that the
+/// supervisor can tell the jobs apart and kill a job as a whole through its
+/// main task.
+///
+/// The stream ends when all the children have exited. The exited children are
+/// given back in [`Exit`] so that the supervisor can restart them if needed.
+pub struct JobSupervisor<K> {
+    children: Vec<Entry<K>>,
+}
+
+impl<K> Default for JobSupervisor<K> {
+    #[inline]
+    fn default() -> Self {
+        JobSupervisor {
+            children: Vec::new(),
+        }
+    }
+}
+
+impl<K: Send + 'static> JobSupervisor<K> {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Supervise `child` under `key`, returning the job it belongs to.
+    pub fn insert(&mut self, key: K, child: Child) -> Result<u64> {
+        let job = child.inner.job()?;
+        let task = Task::try_clone(&child.inner)?;
+        let exit = Box::pin(async move {
+            let retval = child.wait().await;
+            Exit {
+                key,
+                job,
+                child,
+                retval,
+            }
+        });
+        self.children.push(Entry { job, task, exit });
+        Ok(job)
+    }
+}
+
+impl<K> JobSupervisor<K> {
+    /// Check if any task of `job` is supervised.
+    #[inline]
+    pub fn contains(&self, job: u64) -> bool {
+        self.children.iter().any(|entry| entry.job == job)
+    }
+
+    /// Kill the supervised tasks of `job`.
+    ///
+    /// Their exits are still yielded by the stream afterwards.
+    pub fn kill(&self, job: u64) -> Result {
+        let mut found = false;
+        for entry in self.children.iter().filter(|entry| entry.job == job) {
+            entry.task.kill()?;
+            found = true;
+        }
+        found.then_some(()).ok_or(ENOENT)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+impl<K> Stream for JobSupervisor<K> {
+    type Item = Exit<K>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let children = &mut self.get_mut().children;
+        if children.is_empty() {
+            return Poll::Ready(None);
+        }
+        let ready = children.iter_mut().enumerate().find_map(|(index, entry)| {
+            match entry.exit.as_mut().poll(cx) {
+                Poll::Ready(exit) => Some((index, exit)),
+                Poll::Pending => None,
+            }
+        });
+        match ready {
+            Some((index, exit)) => {
+                drop(children.swap_remove(index));
+                Poll::Ready(Some(exit))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
@@ -1,4 +1,10 @@
-use core::time::Duration;
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering::*},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
 
 use futures_lite::{stream, Stream};
 use solvent::{
@@ -6,9 +12,27 @@ use solvent::{
     prelude::SIG_TIMER,
     time::{Instant, Timer as Inner},
 };
+use solvent_core::{
+    sync::{Arsc, Mutex},
+    time::{self as wheel, TimerToken},
+};
 
 use crate::{disp::DispSender, ipc::AsyncObject};
 
+#[cfg(feature = "test-util")]
+pub mod virt;
+
+/// The current time, which stands still while the virtual clock is paused
+/// (see [`virt`]).
+#[inline]
+pub fn now() -> Instant {
+    #[cfg(feature = "test-util")]
+    if let Some(now) = virt::now() {
+        return now;
+    }
+    Instant::now()
+}
+
 pub struct Timer {
     inner: Inner,
     disp: DispSender,
@@ -82,3 +106,134 @@ impl Timer {
         })
     }
 }
+
+/// Sleeps at least this long go to the timing wheel in [`solvent_core::time`]
+/// instead of a kernel timer of their own.
+pub const WHEEL_THRESHOLD: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+struct SleepState {
+    fired: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+enum Token {
+    Wheel(TimerToken),
+    #[cfg(feature = "test-util")]
+    Virtual(u64),
+}
+
+impl Token {
+    fn add<F>(deadline: Instant, callback: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        #[cfg(feature = "test-util")]
+        let callback = match virt::add(deadline, callback) {
+            Ok(id) => return Token::Virtual(id),
+            Err(callback) => callback,
+        };
+        Token::Wheel(wheel::add(deadline, callback))
+    }
+
+    fn cancel(self) {
+        match self {
+            Token::Wheel(token) => {
+                token.cancel();
+            }
+            #[cfg(feature = "test-util")]
+            Token::Virtual(id) => virt::cancel(id),
+        }
+    }
+}
+
+/// A future completed at a deadline on the timing wheel, with the wheel's
+/// coarse precision.
+pub struct Sleep {
+    deadline: Instant,
+    timer: Option<(Arsc<SleepState>, Token)>,
+}
+
+impl Sleep {
+    #[inline]
+    pub fn new(deadline: Instant) -> Self {
+        Sleep {
+            deadline,
+            timer: None,
+        }
+    }
+
+    #[inline]
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let deadline = self.deadline;
+        let (state, _) = self.timer.get_or_insert_with(|| {
+            let state = Arsc::new(SleepState::default());
+            let s2 = state.clone();
+            let token = Token::add(deadline, move || {
+                s2.fired.store(true, Release);
+                if let Some(waker) = s2.waker.lock().take() {
+                    waker.wake()
+                }
+            });
+            (state, token)
+        });
+
+        *state.waker.lock() = Some(cx.waker().clone());
+        let fired = state.fired.load(Acquire);
+
+        if fired {
+            self.timer = None;
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some((_, token)) = self.timer.take() {
+            token.cancel();
+        }
+    }
+}
+
+/// Sleep until `deadline`.
+///
+/// Long sleeps (see [`WHEEL_THRESHOLD`]) share the timing wheel, while short
+/// ones use a dedicated kernel timer for better precision. All of them follow
+/// the virtual clock while it's paused.
+#[cfg(feature = "runtime")]
+pub async fn sleep_until(deadline: Instant) -> Result {
+    let now = now();
+    if deadline <= now {
+        return Ok(());
+    }
+    #[cfg(feature = "test-util")]
+    let virtual_clock = virt::is_paused();
+    #[cfg(not(feature = "test-util"))]
+    let virtual_clock = false;
+    if virtual_clock || deadline - now >= WHEEL_THRESHOLD {
+        Sleep::new(deadline).await;
+        Ok(())
+    } else {
+        Timer::new(Inner::try_new()?).wait_until(deadline).await
+    }
+}
+
+/// Sleep for `duration`.
+///
+/// See [`sleep_until`] for more information.
+#[cfg(feature = "runtime")]
+#[inline]
+pub async fn sleep(duration: Duration) -> Result {
+    sleep_until(now() + duration).await
+}
@@ -0,0 +1,137 @@
+//! A virtual clock for deterministic tests.
+//!
+//! After [`pause`], [`super::now`] stops following the system clock, and the
+//! sleeps are registered here instead of on the timing wheel. They fire only
+//! when the test moves the clock forward with [`advance`], in the order of
+//! their deadlines, so timeouts of any length complete instantly.
+//!
+//! The clock is global to the process, so it shouldn't be paused while other
+//! code depends on real timeouts. The kernel timers of [`super::Timer`] are
+//! not affected.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::time::Duration;
+
+use futures_lite::future::yield_now;
+use solvent::time::Instant;
+use solvent_core::{sync::Mutex, time as wheel};
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+struct Entry {
+    deadline: Instant,
+    id: u64,
+    callback: Callback,
+}
+
+struct Clock {
+    now: Instant,
+    next_id: u64,
+    entries: Vec<Entry>,
+}
+
+static CLOCK: Mutex<Option<Clock>> = Mutex::new(None);
+
+/// Pause the clock at the current time. Does nothing if it's already paused.
+pub fn pause() {
+    let mut clock = CLOCK.lock();
+    if clock.is_none() {
+        *clock = Some(Clock {
+            now: Instant::now(),
+            next_id: 0,
+            entries: Vec::new(),
+        });
+    }
+}
+
+/// Let the clock follow the system clock again.
+///
+/// The pending sleeps are moved to the timing wheel with their remaining
+/// durations, and can no longer be cancelled.
+pub fn resume() {
+    let Some(clock) = CLOCK.lock().take() else {
+        return;
+    };
+    for entry in clock.entries {
+        let _ = wheel::add_after(entry.deadline.max(clock.now) - clock.now, entry.callback);
+    }
+}
+
+#[inline]
+pub fn is_paused() -> bool {
+    CLOCK.lock().is_some()
+}
+
+/// Move the paused clock forward by `duration`, firing the sleeps expired in
+/// the meantime one by one, and yielding after each of them so that the tasks
+/// woken up can run at that very moment.
+///
+/// # Panics
+///
+/// This function panics if the clock is not paused.
+pub async fn advance(duration: Duration) {
+    let target = {
+        let clock = CLOCK.lock();
+        clock.as_ref().expect("The clock is not paused").now + duration
+    };
+    loop {
+        let callback = {
+            let mut guard = CLOCK.lock();
+            let clock = guard
+                .as_mut()
+                .expect("The clock is resumed while advancing");
+            let earliest = clock
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.deadline <= target)
+                .min_by_key(|(_, entry)| (entry.deadline, entry.id))
+                .map(|(index, _)| index);
+            match earliest {
+                Some(index) => {
+                    let entry = clock.entries.swap_remove(index);
+                    clock.now = clock.now.max(entry.deadline);
+                    entry.callback
+                }
+                None => {
+                    clock.now = clock.now.max(target);
+                    break;
+                }
+            }
+        };
+        callback();
+        yield_now().await;
+    }
+    yield_now().await;
+}
+
+/// The current virtual time, or `None` if the clock isn't paused.
+pub(super) fn now() -> Option<Instant> {
+    CLOCK.lock().as_ref().map(|clock| clock.now)
+}
+
+/// Register `callback` to be called when the clock is advanced past
+/// `deadline`, or give it back if the clock isn't paused.
+pub(super) fn add<F>(deadline: Instant, callback: F) -> Result<u64, F>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let mut guard = CLOCK.lock();
+    let Some(clock) = guard.as_mut() else {
+        return Err(callback);
+    };
+    let id = clock.next_id;
+    clock.next_id += 1;
+    clock.entries.push(Entry {
+        deadline,
+        id,
+        callback: Box::new(callback),
+    });
+    Ok(id)
+}
+
+pub(super) fn cancel(id: u64) {
+    if let Some(clock) = CLOCK.lock().as_mut() {
+        clock.entries.retain(|entry| entry.id != id);
+    }
+}
@@ -7,6 +7,8 @@ pub struct VTable {
     /// one thread can execute thread-local tasks.
     pub local_exe: *const solvent_async::exe::LocalExecutor,
     pub local_fs: *const solvent_fs::fs::LocalFs,
+    pub dispatch: fn() -> solvent_async::disp::DispSender,
+    pub root_virt: solvent::obj::Handle,
 
     pub alloc: unsafe extern "C" fn(usize, usize) -> *mut (),
     pub dealloc: unsafe extern "C" fn(*mut (), usize, usize),
@@ -16,7 +18,11 @@ pub struct VTable {
 mod ddk {
     use core::sync::atomic;
 
-    use solvent_async::exe::{Executor, LocalExecutor};
+    use solvent::{mem::Virt, obj::Ref};
+    use solvent_async::{
+        disp::DispSender,
+        exe::{Executor, LocalExecutor},
+    };
     use solvent_fs::fs::LocalFs;
 
     use super::*;
@@ -39,6 +45,19 @@ mod ddk {
         unsafe { &*vtable().local_fs }
     }
 
+    /// Get the dispatcher of the driver host, with which the async I/O
+    /// objects of the driver are created.
+    pub fn dispatch() -> DispSender {
+        (vtable().dispatch)()
+    }
+
+    /// Get the root virt of the driver host, where the driver maps its memory.
+    pub fn root_virt() -> Ref<'static, Virt> {
+        // SAFETY: The handle is owned by the driver host, which outlives the
+        // driver.
+        unsafe { Ref::from_raw(vtable().root_virt) }
+    }
+
     /// # Safety
     ///
     /// This function must be called from `__h2o_ddk_enter` only once before
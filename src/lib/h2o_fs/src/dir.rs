@@ -6,7 +6,10 @@ use alloc::{boxed::Box, string::String};
 
 use async_trait::async_trait;
 use solvent_core::sync::Arsc;
-use solvent_rpc::io::{dir::DirEntry, Error};
+use solvent_rpc::io::{
+    dir::{DirEntry, Usage},
+    Error,
+};
 
 pub use self::{event::*, handle::*};
 use crate::entry::Entry;
@@ -14,6 +17,14 @@ use crate::entry::Entry;
 #[async_trait]
 pub trait Directory: Entry {
     async fn next_dirent(&self, last: Option<String>) -> Result<DirEntry, Error>;
+
+    /// Get the usage of the quota the directory is under.
+    ///
+    /// Returns [`Error::NotFound`] if the directory has no quota.
+    #[inline]
+    fn usage(&self) -> Result<Usage, Error> {
+        Err(Error::NotFound)
+    }
 }
 
 #[async_trait]
@@ -10,7 +10,7 @@ use solvent_rpc::{
 };
 
 use super::{Directory, DirectoryMut, EventTokens};
-use crate::spawn::Spawner;
+use crate::{entry::audit_open, spawn::Spawner};
 
 pub async fn handle<D: Directory>(
     dir: Arsc<D>,
@@ -108,6 +108,7 @@ async fn handle_request<D: Directory>(
             return HandleRequest::Break;
         }
         rpc::DirectoryRequest::Metadata { responder } => responder.send(dir.metadata()),
+        rpc::DirectoryRequest::Usage { responder } => responder.send(dir.usage()),
         rpc::DirectoryRequest::NextDirent { last, responder } => responder.send({
             if options.contains(OpenOptions::READ) {
                 dir.next_dirent(last).await
@@ -120,15 +121,18 @@ async fn handle_request<D: Directory>(
             options,
             conn,
             responder,
-        } => responder.send({
-            dir.clone()
+        } => {
+            let res = dir
+                .clone()
                 .open(spawner, tokens.clone(), &path, options, conn)
                 .map(|create| {
                     if create {
                         let _ = event.send(EventFlags::ADD);
                     }
-                })
-        }),
+                });
+            audit_open(&res, || responder.peer());
+            responder.send(res)
+        }
         request => return HandleRequest::Continue(request),
     };
     HandleRequest::Next(res)
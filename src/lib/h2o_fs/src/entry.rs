@@ -1,6 +1,9 @@
 use core::any::Any;
 
-use solvent::prelude::Channel;
+use solvent::{
+    audit::{self, AUDIT_OPEN_DENIED},
+    prelude::{ChanPeerId, Channel},
+};
 use solvent_core::{path::Path, sync::Arsc};
 use solvent_rpc::io::{Error, Metadata, OpenOptions};
 
@@ -28,3 +31,16 @@ impl<T: Any + Send + Sync> IntoAny for T {
         self as _
     }
 }
+
+/// Report the open denied for the lack of permissions to the audit log, along
+/// with the identity of the client given by `peer`.
+pub(crate) fn audit_open<T>(
+    res: &Result<T, Error>,
+    peer: impl FnOnce() -> solvent::error::Result<ChanPeerId>,
+) {
+    if let Err(Error::PermissionDenied(perm)) = res {
+        let client = peer().map_or_else(|_| Default::default(), |id| id.holder);
+        let args = [client.task, client.job, perm.bits().into()];
+        let _ = audit::report(AUDIT_OPEN_DENIED, args);
+    }
+}
@@ -4,7 +4,7 @@ mod stream;
 use alloc::boxed::Box;
 
 use async_trait::async_trait;
-use solvent::prelude::Phys;
+use solvent::prelude::{ChanOptions, Channel, Phys};
 use solvent_async::{disp::DispSender, io::Stream};
 use solvent_core::io::RawStream;
 use solvent_rpc::io::{file::PhysOptions, Error};
@@ -15,6 +15,13 @@ pub use self::{
 };
 use crate::entry::Entry;
 
+/// Create a pair of channels for a file connection, through which large
+/// contents read from the file are moved in pages instead of being copied.
+pub fn channel() -> (Channel, Channel) {
+    Channel::try_with_options(ChanOptions::ZERO_COPY, Default::default())
+        .expect("Failed to create a pair of channels")
+}
+
 #[async_trait]
 pub trait File: Entry {
     async fn lock(&self, stream: Option<(RawStream, DispSender)>) -> Result<Option<Stream>, Error>;
@@ -10,7 +10,11 @@ use solvent_rpc::{
 };
 
 use super::{stream::*, File};
-use crate::{dir::EventTokens, entry::Entry, spawn::Spawner};
+use crate::{
+    dir::EventTokens,
+    entry::{audit_open, Entry},
+    spawn::Spawner,
+};
 
 #[inline]
 pub async fn handle<F: File>(
@@ -89,10 +93,11 @@ async fn handle_impl<S: StreamIo>(
                 responder,
             } => {
                 let file = Arsc::clone(file.as_file());
-                responder.send(
-                    file.open(spawner.clone(), tokens.clone(), &path, options, conn)
-                        .map(drop),
-                )
+                let res = file
+                    .open(spawner.clone(), tokens.clone(), &path, options, conn)
+                    .map(drop);
+                audit_open(&res, || responder.peer());
+                responder.send(res)
             }
             FileRequest::Read { len, responder } => responder.send({
                 if !options.contains(OpenOptions::READ) {
@@ -6,7 +6,7 @@ use core::iter::Peekable;
 
 use solvent::{ipc::Channel, prelude::Handle};
 use solvent_core::{
-    path::{Component, Components, Path, PathBuf},
+    path::{Component, Components, Path, PathBuf, MAIN_SEPARATOR_STR},
     sync::{Arsc, Mutex, MutexGuard},
 };
 use solvent_rpc::io::{
@@ -235,7 +235,7 @@ impl Node {
         let dst_parent = DirectorySyncClient::from(t);
 
         {
-            let (t, conn) = Channel::new();
+            let (t, conn) = crate::file::channel();
             src_parent.open(
                 src_file.into(),
                 OpenOptions::READ | OpenOptions::WRITE,
@@ -286,28 +286,19 @@ impl LocalFs {
     }
 
     fn canonicalize_with(path: &Path, cwd: &Path) -> Result<PathBuf, Error> {
-        fn inner(path: &Path, cwd: &Path) -> Option<PathBuf> {
-            let mut out = PathBuf::new();
-            let path = if path.is_absolute() {
-                path.to_path_buf()
-            } else {
-                cwd.join(path)
-            };
-            for comp in path.components() {
-                match comp {
-                    Component::Prefix(_) => return None,
-                    Component::RootDir | Component::CurDir => {}
-                    Component::ParentDir => {
-                        if !out.pop() {
-                            return None;
-                        }
-                    }
-                    Component::Normal(comp) => out.push(comp.to_str()?),
-                }
-            }
-            Some(out)
+        let invalid = || Error::InvalidPath(path.to_path_buf());
+        let out = cwd
+            .join(path)
+            .normalize_lexically()
+            .map_err(|_| invalid())?;
+        if out.to_str().is_none() {
+            return Err(invalid());
         }
-        inner(path, cwd).ok_or_else(|| Error::InvalidPath(path.to_path_buf()))
+        // Paths in the local FS are relative to its root.
+        Ok(match out.strip_prefix(MAIN_SEPARATOR_STR) {
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) => out,
+        })
     }
 
     #[inline]
@@ -326,6 +317,12 @@ impl LocalFs {
         self.root.clone().open(&path, options, conn)
     }
 
+    /// The current directory, relative to the root of the local FS.
+    #[inline]
+    pub fn cwd(&self) -> PathBuf {
+        self.cwd.lock().clone()
+    }
+
     pub fn chdir<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
         let path = self.canonicalize(path)?;
         *self.cwd.lock() = path;
@@ -28,7 +28,7 @@ mod std_local {
     };
 
     use solvent::prelude::Channel;
-    use solvent_core::path::{Path, PathBuf};
+    use solvent_core::path::{Path, PathBuf, MAIN_SEPARATOR_STR};
     use solvent_rpc::io::{
         dir::DirectorySyncClient, entry::EntrySyncClient, file::FileSyncClient, Error, FileType,
         Metadata, OpenOptions,
@@ -41,8 +41,24 @@ mod std_local {
         fs::local().canonicalize(path)
     }
 
+    /// Returns the absolute path of the current directory.
+    #[inline]
+    pub fn current_dir() -> PathBuf {
+        Path::new(MAIN_SEPARATOR_STR).join(fs::local().cwd())
+    }
+
+    /// Changes the current directory to `path`, which must be a directory.
+    pub fn set_current_dir<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let metadata = metadata(path)?;
+        if metadata.file_type != FileType::Directory {
+            return Err(Error::InvalidType(metadata.file_type));
+        }
+        fs::local().chdir(path)
+    }
+
     pub fn open<P: AsRef<Path>>(path: P, options: OpenOptions) -> Result<FileSyncClient, Error> {
-        let (t, conn) = Channel::new();
+        let (t, conn) = crate::file::channel();
         fs::local().open(path, options | OpenOptions::EXPECT_FILE, conn)?;
         Ok(FileSyncClient::from(t))
     }
@@ -96,7 +112,7 @@ mod std_local {
         )?;
         for buf in buf.as_ref().chunks(CAP) {
             let mut written = 0;
-            loop {
+            while written < buf.len() {
                 let buf = Vec::from(&buf[written..]);
                 written += file.write(buf)??;
             }
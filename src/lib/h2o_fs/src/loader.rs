@@ -1,20 +1,98 @@
-use alloc::vec::Vec;
-use core::borrow::Borrow;
+//! The loader service backed by directories.
+//!
+//! The objects served are deduplicated by their BLAKE3 hashes in a cache
+//! shared by all the loaders of the process, so the processes loading the
+//! same library get the same physical object. Its read-only segments are then
+//! mapped without copies, and its writable ones are copied from it, on write
+//! if the object supports it.
+
+use alloc::{collections::BTreeMap, ffi::CString, vec::Vec};
+use core::{borrow::Borrow, fmt};
 
 use futures_lite::StreamExt;
-use solvent::prelude::Phys;
+use solvent::prelude::{Object, Phys, ENOENT};
 use solvent_async::disp::DispSender;
-use solvent_core::{ffi::OsStr, path::Path};
+use solvent_core::{ffi::OsStr, path::Path, sync::Mutex};
 use solvent_rpc::{
     io::{
         dir::DirectoryClient,
         file::{File, PhysOptions},
         Error, OpenOptions,
     },
-    loader::{LoaderRequest, LoaderServer},
+    loader::{LoaderRequest, LoaderServer, ObjectInfo, HASH_LEN},
     Protocol, Server,
 };
 
+/// The maximum number of the objects kept in the cache. The objects loaded
+/// after it's full are served without deduplication.
+const MAX_CACHED: usize = 256;
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache {
+    objects: BTreeMap::new(),
+    hits: 0,
+    saved: 0,
+});
+
+struct Cache {
+    objects: BTreeMap<[u8; HASH_LEN], Phys>,
+    hits: usize,
+    saved: usize,
+}
+
+/// The statistics of the object cache of the loaders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStat {
+    /// The number of the distinct objects cached.
+    pub objects: usize,
+    /// The total size of the cached objects.
+    pub bytes: usize,
+    /// The number of the loads served from the cache.
+    pub hits: usize,
+    /// The total size of the copies saved by the hits.
+    pub saved: usize,
+}
+
+impl fmt::Display for CacheStat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} objects of {} bytes cached, {} hits saving {} bytes",
+            self.objects, self.bytes, self.hits, self.saved
+        )
+    }
+}
+
+pub fn cache_stat() -> CacheStat {
+    let cache = CACHE.lock();
+    CacheStat {
+        objects: cache.objects.len(),
+        bytes: cache.objects.values().map(Phys::len).sum(),
+        hits: cache.hits,
+        saved: cache.saved,
+    }
+}
+
+/// Replace `phys` with the cached object of the same content if any, or
+/// cache it otherwise.
+fn dedup(phys: Phys) -> Result<(Phys, [u8; HASH_LEN]), Error> {
+    let content = phys.read(0, phys.len()).map_err(Error::Other)?;
+    let hash = bootfs::blake3::hash(&content);
+
+    let mut cache = CACHE.lock();
+    let cache = &mut *cache;
+    let cached = match cache.objects.get(&hash) {
+        Some(cached) => {
+            cache.hits += 1;
+            cache.saved += phys.len();
+            cached
+        }
+        None if cache.objects.len() < MAX_CACHED => cache.objects.entry(hash).or_insert(phys),
+        None => return Ok((phys, hash)),
+    };
+    let phys = Phys::try_clone(cached).map_err(Error::Other)?;
+    Ok((phys, hash))
+}
+
 pub async fn get_object_from_dir<D: Borrow<DirectoryClient>, P: AsRef<Path>>(
     disp: DispSender,
     dir: D,
@@ -31,21 +109,54 @@ pub async fn get_object_from_dir<D: Borrow<DirectoryClient>, P: AsRef<Path>>(
     file.phys(PhysOptions::Copy).await?
 }
 
+/// Get the object of `path` from the directory through the cache, along with
+/// its content hash.
+///
+/// The object may be shared with other processes, so it must not be written.
+pub async fn get_cached_object_from_dir<D: Borrow<DirectoryClient>, P: AsRef<Path>>(
+    disp: DispSender,
+    dir: D,
+    path: P,
+) -> Result<(Phys, [u8; HASH_LEN]), Error> {
+    dedup(get_object_from_dir(disp, dir, path).await?)
+}
+
 pub async fn get_object<D: Borrow<DirectoryClient>, P: AsRef<Path>>(
     disp: &DispSender,
     dir: impl Iterator<Item = D>,
     path: P,
-) -> Option<Phys> {
+) -> Option<(Phys, [u8; HASH_LEN])> {
     let path = path.as_ref();
     for dir in dir {
-        match get_object_from_dir(disp.clone(), dir, path).await {
-            Ok(phys) => return Some(phys),
+        match get_cached_object_from_dir(disp.clone(), dir, path).await {
+            Ok(ret) => return Some(ret),
             Err(err) => log::warn!("Failed to get object from {path:?}: {err}"),
         }
     }
     None
 }
 
+/// Get the object of `path` from the directories, looking it up in
+/// `search_paths` relative to them unless it contains a slash.
+pub async fn search_object<D: Borrow<DirectoryClient>>(
+    disp: &DispSender,
+    dir: impl Iterator<Item = D> + Clone,
+    search_paths: &[CString],
+    path: &CString,
+) -> Option<(Phys, [u8; HASH_LEN])> {
+    let path = Path::new(OsStr::from_bytes(path.as_bytes()));
+    if search_paths.is_empty() || path.as_os_str().as_bytes().contains(&b'/') {
+        return get_object(disp, dir, path).await;
+    }
+    for search_path in search_paths {
+        let search_path = Path::new(OsStr::from_bytes(search_path.as_bytes()));
+        if let Some(ret) = get_object(disp, dir.clone(), search_path.join(path)).await {
+            return Some(ret);
+        }
+    }
+    None
+}
+
 pub async fn serve<D: Borrow<DirectoryClient>>(
     disp: DispSender,
     server: LoaderServer,
@@ -70,7 +181,7 @@ pub async fn serve<D: Borrow<DirectoryClient>>(
                         match get_object(&disp, dir.clone(), OsStr::from_bytes(path.as_bytes()))
                             .await
                         {
-                            Some(obj) => ret.push(obj),
+                            Some((obj, _)) => ret.push(obj),
                             None => return Err(index),
                         }
                     }
@@ -81,6 +192,30 @@ pub async fn serve<D: Borrow<DirectoryClient>>(
                     log::warn!("RPC send error: {err}");
                 }
             }
+            LoaderRequest::GetObjects {
+                search_paths,
+                queries,
+                responder,
+            } => {
+                let dir = dir.clone();
+                let disp = disp.clone();
+                let fut = async move {
+                    let mut ret = Vec::with_capacity(queries.len());
+                    for query in queries {
+                        let obj =
+                            search_object(&disp, dir.clone(), &search_paths, &query.path).await;
+                        ret.push(obj.ok_or(ENOENT).map(|(phys, hash)| ObjectInfo {
+                            phys: (hash != query.cached).then_some(phys),
+                            hash,
+                        }));
+                    }
+                    ret
+                };
+                let res = responder.send(fut.await);
+                if let Err(err) = res {
+                    log::warn!("RPC send error: {err}");
+                }
+            }
             LoaderRequest::Unknown(_) => {
                 log::warn!("RPC received unknown request")
             }
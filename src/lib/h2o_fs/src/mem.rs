@@ -1,2 +1,4 @@
 pub mod dir;
 pub mod file;
+pub mod overlay;
+pub mod quota;
@@ -5,6 +5,7 @@ use alloc::{
     collections::{btree_map::Entry as MapEntry, BTreeMap},
     string::String,
 };
+use core::ops::Bound;
 
 use async_trait::async_trait;
 use solvent::prelude::Channel;
@@ -14,11 +15,12 @@ use solvent_core::{
     sync::{Arsc, Mutex},
 };
 use solvent_rpc::io::{
-    dir::{DirEntry, DirectoryServer},
+    dir::{DirEntry, DirectoryServer, Usage},
     Error, FileType, Metadata, OpenOptions, Permission,
 };
 
 pub use self::builder::*;
+use super::quota::{Inode, Quota};
 use crate::{
     dir::{handle, handle_mut, Directory, DirectoryMut, EventTokens},
     entry::Entry,
@@ -33,7 +35,7 @@ pub struct MemDir {
 }
 
 impl MemDir {
-    fn get(&self, name: &str) -> Result<Arsc<dyn Entry>, Error> {
+    pub(crate) fn get(&self, name: &str) -> Result<Arsc<dyn Entry>, Error> {
         if name.len() > MAX_NAME {
             return Err(Error::InvalidNameLength(name.len()));
         }
@@ -42,6 +44,14 @@ impl MemDir {
             None => Err(Error::NotFound),
         }
     }
+
+    /// The names of the entries after `last` in order.
+    pub(crate) fn names_after<'a>(&'a self, last: Option<&str>) -> impl Iterator<Item = &'a str> {
+        let start = last.map_or(Bound::Unbounded, Bound::Excluded);
+        self.entries
+            .range::<str, _>((start, Bound::Unbounded))
+            .map(|(name, _)| name.as_str())
+    }
 }
 
 impl Entry for MemDir {
@@ -107,14 +117,24 @@ impl Directory for MemDir {
     }
 }
 
-pub trait FileInserter: Fn(&str) -> Result<Arsc<dyn Entry>, Error> + Send + Sync {}
-impl<F: Fn(&str) -> Result<Arsc<dyn Entry>, Error> + Send + Sync> FileInserter for F {}
+/// Creates the files in [`MemDirMut`]s, which should be charged to the quota
+/// of the directory if any, e.g. with [`super::file::MemFile::with_quota`].
+pub trait FileInserter:
+    Fn(&str, Option<&Arsc<Quota>>) -> Result<Arsc<dyn Entry>, Error> + Send + Sync
+{
+}
+impl<F: Fn(&str, Option<&Arsc<Quota>>) -> Result<Arsc<dyn Entry>, Error> + Send + Sync> FileInserter
+    for F
+{
+}
 
 pub struct MemDirMut {
     entries: Mutex<BTreeMap<String, Arsc<dyn Entry>>>,
     perm: Permission,
     path: PathBuf,
     file_inserter: Arsc<dyn FileInserter>,
+    quota: Option<Arsc<Quota>>,
+    _inode: Option<Inode>,
 }
 
 impl MemDirMut {
@@ -128,6 +148,8 @@ impl MemDirMut {
             perm,
             path,
             file_inserter,
+            quota: None,
+            _inode: None,
         }
     }
 
@@ -141,10 +163,20 @@ impl MemDirMut {
             perm,
             path,
             file_inserter,
+            quota: None,
+            _inode: None,
         }
     }
 
-    fn get(&self, name: &str) -> Result<Arsc<dyn Entry>, Error> {
+    /// Limit the entries created in the directory and its new subdirectories
+    /// with `quota`.
+    #[inline]
+    pub fn with_quota(mut self, quota: Arsc<Quota>) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Result<Arsc<dyn Entry>, Error> {
         if name.len() > MAX_NAME {
             return Err(Error::InvalidNameLength(name.len()));
         }
@@ -172,19 +204,31 @@ impl MemDirMut {
         }
 
         let entry = if next != Path::new("") {
-            (self.file_inserter)(name)? as Arsc<dyn Entry>
+            (self.file_inserter)(name, self.quota.as_ref())? as Arsc<dyn Entry>
         } else {
-            Arsc::new(Self::new_unsized(
-                options.require(),
-                self.path.join(name),
-                self.file_inserter.clone(),
-            )) as Arsc<dyn Entry>
+            let inode = self.quota.as_ref().map(|quota| Inode::new(quota, 0));
+            Arsc::new(MemDirMut {
+                entries: Mutex::new(BTreeMap::new()),
+                perm: options.require(),
+                path: self.path.join(name),
+                file_inserter: self.file_inserter.clone(),
+                quota: self.quota.clone(),
+                _inode: inode.transpose()?,
+            }) as Arsc<dyn Entry>
         };
         entries.insert(name.into(), entry.clone());
         Ok((entry, true))
     }
 
-    fn insert(&self, name: String, ent: Arsc<dyn Entry>) -> Result<(), Error> {
+    /// The name of the first entry after `last`.
+    pub(crate) fn next_name(&self, last: Option<&str>) -> Option<String> {
+        let start = last.map_or(Bound::Unbounded, Bound::Excluded);
+        let entries = self.entries.lock();
+        let mut range = entries.range::<str, _>((start, Bound::Unbounded));
+        range.next().map(|(name, _)| name.clone())
+    }
+
+    pub(crate) fn insert(&self, name: String, ent: Arsc<dyn Entry>) -> Result<(), Error> {
         let mut entries = self.entries.lock();
         match entries.entry(name) {
             MapEntry::Vacant(vacant) => {
@@ -272,6 +316,14 @@ impl Directory for MemDirMut {
         let metadata = entry.metadata()?;
         Ok(DirEntry { name, metadata })
     }
+
+    #[inline]
+    fn usage(&self) -> Result<Usage, Error> {
+        self.quota
+            .as_ref()
+            .map(|quota| quota.usage())
+            .ok_or(Error::NotFound)
+    }
 }
 
 #[async_trait]
@@ -284,7 +336,8 @@ impl DirectoryMut for MemDirMut {
     ) -> Result<(), Error> {
         let dst_parent = dst_parent.into_any().downcast::<Self>().unwrap();
 
-        // Renaming `path/to` to `path/to/inner` will create dead cycle references.
+        // Renaming `path/to` to `path/to/inner` will create dead cycle
+        // references.
         let dst_full = dst_parent.path.join(dst);
         let src_full = self.path.join(src);
         if let Ok(next) = dst_full.strip_prefix(&src_full) {
@@ -11,12 +11,16 @@ use solvent_core::{
 use solvent_rpc::io::{Error, Permission};
 
 use super::{FileInserter, MemDir, MemDirMut};
-use crate::entry::Entry;
+use crate::{
+    entry::Entry,
+    mem::quota::{Inode, Quota},
+};
 
 #[derive(Default)]
 pub struct Builder {
     entries: BTreeMap<String, BuilderInner>,
     perm: Permission,
+    limits: Option<(usize, usize)>,
 }
 
 enum BuilderInner {
@@ -103,6 +107,39 @@ impl Builder {
         Ok(self)
     }
 
+    /// Limit the bytes and inodes of the subtree at `path`, or of the whole FS
+    /// if it's empty. Only takes effect in [`Builder::build_mut`].
+    ///
+    /// Limits of `usize::MAX` are unlimited.
+    pub fn quota(
+        &mut self,
+        path: &Path,
+        byte_limit: usize,
+        inode_limit: usize,
+    ) -> Result<&mut Self, Error> {
+        let mut comps = path.components();
+        let Some(comp) = comps.next() else {
+            self.limits = Some((byte_limit, inode_limit));
+            return Ok(self);
+        };
+        let comp = comp
+            .as_os_str()
+            .to_str()
+            .ok_or_else(|| Error::InvalidPath(path.into()))?;
+
+        let dir = self
+            .entries
+            .entry(comp.into())
+            .or_insert_with(|| BuilderInner::Dir(Builder::new()));
+        let dir = match dir {
+            BuilderInner::Dir(dir) => dir,
+            BuilderInner::Entry(_) => return Err(Error::LocalFs(comp.into())),
+        };
+        let path = PathBuf::from_iter(comps);
+        dir.quota(&path, byte_limit, inode_limit)?;
+        Ok(self)
+    }
+
     pub fn build(&mut self) -> Arsc<MemDir> {
         let entries = mem::take(&mut self.entries)
             .into_iter()
@@ -121,29 +158,38 @@ impl Builder {
         &mut self,
         file_inserter: Arsc<F>,
     ) -> Arsc<MemDirMut> {
-        mem::take(self).build_mut_inner("".into(), file_inserter)
+        mem::take(self).build_mut_inner("".into(), file_inserter, None)
     }
 
     fn build_mut_inner<F: FileInserter + 'static>(
         self,
         path: PathBuf,
         file_inserter: Arsc<F>,
+        parent: Option<&Arsc<Quota>>,
     ) -> Arsc<MemDirMut> {
+        let inode = parent.map(|quota| Inode::forced(quota, 0));
+        let quota = match self.limits {
+            Some((byte_limit, inode_limit)) => {
+                Some(Quota::new(parent.cloned(), byte_limit, inode_limit))
+            }
+            None => parent.cloned(),
+        };
         let entries = self.entries.into_iter().map(|(name, entry)| match entry {
             BuilderInner::Dir(builder) => {
                 let path = path.join(&name);
-                (
-                    name,
-                    builder.build_mut_inner(path, file_inserter.clone()) as Arsc<dyn Entry>,
-                )
+                let dir = builder.build_mut_inner(path, file_inserter.clone(), quota.as_ref());
+                (name, dir as Arsc<dyn Entry>)
             }
             BuilderInner::Entry(entry) => (name, entry),
         });
+        let entries = Mutex::new(entries.collect());
         Arsc::new(MemDirMut {
-            entries: Mutex::new(entries.collect()),
+            entries,
             perm: self.perm,
             path,
             file_inserter: file_inserter as _,
+            quota,
+            _inode: inode,
         })
     }
 }
@@ -174,6 +220,8 @@ pub trait RecursiveBuilder: Iterator<Item = RecursiveBuild> + Sized {
             perm: root_perm,
             path: "".into(),
             file_inserter: file_inserter.clone(),
+            quota: None,
+            _inode: None,
         };
         build_recursive_mut(&mut self, &mut root, file_inserter)?;
         Ok(Arsc::new(root))
@@ -225,6 +273,8 @@ fn build_recursive_mut<F: FileInserter + 'static>(
                         perm,
                         path: dir.path.join(name),
                         file_inserter: file_inserter.clone(),
+                        quota: None,
+                        _inode: None,
                     };
                     build_recursive_mut(iter, &mut sub, file_inserter.clone())?;
                     ent.insert(Arsc::new(sub));
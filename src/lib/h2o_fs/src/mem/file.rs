@@ -10,10 +10,11 @@ use solvent_rpc::io::{
     Error, FileType, Metadata, OpenOptions, Permission,
 };
 
+use super::quota::{Inode, Quota};
 use crate::{
     dir::EventTokens,
     entry::Entry,
-    file::{handle_mapped, File},
+    file::{handle, handle_mapped, File},
     spawn::Spawner,
 };
 
@@ -21,6 +22,7 @@ pub struct MemFile {
     phys: Phys,
     perm: Permission,
     locked: AtomicBool,
+    inode: Option<Inode>,
 }
 
 impl MemFile {
@@ -30,6 +32,35 @@ impl MemFile {
             phys,
             perm,
             locked: AtomicBool::new(false),
+            inode: None,
+        }
+    }
+
+    /// Create a file charged to `quota`.
+    ///
+    /// Its writes go through the file server instead of a mapped stream so
+    /// that they can be checked against the quota.
+    pub fn with_quota(phys: Phys, perm: Permission, quota: &Arsc<Quota>) -> Result<Self, Error> {
+        let inode = Inode::new(quota, phys.len())?;
+        Ok(MemFile {
+            phys,
+            perm,
+            locked: AtomicBool::new(false),
+            inode: Some(inode),
+        })
+    }
+
+    /// Copy the file to be written without affecting the original, charged to
+    /// `quota` if any.
+    pub(crate) fn copy_up(&self, quota: Option<&Arsc<Quota>>) -> Result<Self, Error> {
+        let phys = self
+            .phys
+            .create_sub(0, self.phys.len(), true)
+            .map_err(Error::Other)?;
+        let perm = self.perm | Permission::WRITE;
+        match quota {
+            Some(quota) => Self::with_quota(phys, perm, quota),
+            None => Ok(Self::new(phys, perm)),
         }
     }
 }
@@ -55,11 +86,16 @@ impl Entry for MemFile {
         if !self.perm.contains(require) {
             return Err(Error::PermissionDenied(require - self.perm));
         }
+        let server = FileServer::new(AsyncChannel::with_disp(conn, spawner.dispatch()));
+        if self.inode.is_some() {
+            let task = handle(self, spawner.clone(), tokens, 0, server, options);
+            spawner.spawn(task);
+            return Ok(false);
+        }
         let stream = RawStream {
             phys: self.phys.clone(),
             seeker: 0,
         };
-        let server = FileServer::new(AsyncChannel::with_disp(conn, spawner.dispatch()));
         let task = handle_mapped(
             self,
             spawner.clone(),
@@ -104,20 +140,38 @@ impl File for MemFile {
         Ok(())
     }
 
-    async fn read_at(&self, _: usize, _: &mut [u8]) -> Result<usize, Error> {
-        unimplemented!("Default implementation in `StreamFile`")
+    // The I/O methods below are only used by files with quotas, since the
+    // others are served with `StreamFile`.
+
+    async fn read_at(&self, pos: usize, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = self.phys.len().saturating_sub(pos).min(buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
+        self.phys
+            .read_into(pos, &mut buf[..len])
+            .map_err(Error::Other)
     }
 
-    async fn write_at(&self, _: usize, _: &[u8]) -> Result<usize, Error> {
-        unimplemented!("Default implementation in `StreamFile`")
+    async fn write_at(&self, pos: usize, buf: &[u8]) -> Result<usize, Error> {
+        let end = pos.checked_add(buf.len()).ok_or(Error::InvalidSeek)?;
+        if end > self.phys.len() {
+            self.resize(end).await?;
+        }
+        // SAFETY: The object is not mapped by the file server.
+        unsafe { self.phys.write(pos, buf) }.map_err(Error::Other)
     }
 
     async fn len(&self) -> Result<usize, Error> {
-        unimplemented!("Default implementation in `StreamFile`")
+        Ok(self.phys.len())
     }
 
-    async fn resize(&self, _: usize) -> Result<(), Error> {
-        unimplemented!("Default implementation in `StreamFile`")
+    async fn resize(&self, new_len: usize) -> Result<(), Error> {
+        let resize = || self.phys.resize(new_len, true).map_err(Error::Other);
+        match self.inode {
+            Some(ref inode) => inode.resize(new_len, resize),
+            None => resize(),
+        }
     }
 
     async fn phys(&self, options: PhysOptions) -> Result<Phys, Error> {
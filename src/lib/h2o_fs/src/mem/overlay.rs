@@ -0,0 +1,348 @@
+//! The overlay of a read-only [`MemDir`] tree with a writable one.
+//!
+//! Every directory of the overlay merges a directory of the lower layer, if
+//! any, with a [`MemDirMut`] of the upper layer, where all the changes are
+//! made. The upper entries shadow the lower ones of the same names, and the
+//! lower entries removed are hidden by whiteouts.
+//!
+//! A lower file is copied up when it's opened for writing, renamed or linked.
+//! Lower directories can't be renamed or linked, but their contents can be
+//! changed through the overlay.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec::Vec,
+};
+
+use async_trait::async_trait;
+use solvent::prelude::Channel;
+use solvent_async::ipc::Channel as AsyncChannel;
+use solvent_core::{
+    path::{Component, Path, PathBuf},
+    sync::{Arsc, Mutex},
+};
+use solvent_rpc::io::{
+    dir::{DirEntry, DirectoryServer, Usage},
+    Error, FileType, Metadata, OpenOptions, Permission,
+};
+
+use super::{
+    dir::{FileInserter, MemDir, MemDirMut},
+    file::MemFile,
+    quota::Quota,
+};
+use crate::{
+    dir::{handle_mut, Directory, DirectoryMut, EventTokens},
+    entry::Entry,
+    spawn::Spawner,
+};
+
+pub struct OverlayDir {
+    lower: Option<Arsc<MemDir>>,
+    upper: Arsc<MemDirMut>,
+    perm: Permission,
+    path: PathBuf,
+    /// The names of the lower entries removed.
+    whiteouts: Mutex<BTreeSet<String>>,
+    /// The merged lower subdirectories, kept so that the changes in them
+    /// persist.
+    children: Mutex<BTreeMap<String, Arsc<OverlayDir>>>,
+    file_inserter: Arsc<dyn FileInserter>,
+    quota: Option<Arsc<Quota>>,
+}
+
+enum Lookup {
+    Upper(Arsc<dyn Entry>),
+    Child(Arsc<OverlayDir>),
+    Lower(Arsc<dyn Entry>),
+}
+
+impl Lookup {
+    fn metadata(&self) -> Result<Metadata, Error> {
+        match self {
+            Lookup::Upper(entry) | Lookup::Lower(entry) => entry.metadata(),
+            Lookup::Child(child) => child.metadata(),
+        }
+    }
+}
+
+impl OverlayDir {
+    /// Create an overlay of `lower`, whose new files are created by
+    /// `file_inserter`.
+    pub fn new(
+        lower: Arsc<MemDir>,
+        perm: Permission,
+        file_inserter: Arsc<dyn FileInserter>,
+    ) -> Self {
+        let path = PathBuf::from("");
+        OverlayDir {
+            lower: Some(lower),
+            upper: Arsc::new(MemDirMut::new_unsized(
+                perm,
+                path.clone(),
+                file_inserter.clone(),
+            )),
+            perm,
+            path,
+            whiteouts: Mutex::new(BTreeSet::new()),
+            children: Mutex::new(BTreeMap::new()),
+            file_inserter,
+            quota: None,
+        }
+    }
+
+    /// Limit the entries created in the upper layer with `quota`.
+    pub fn with_quota(mut self, quota: Arsc<Quota>) -> Self {
+        self.upper = self.new_upper(self.path.clone(), Some(&quota));
+        self.quota = Some(quota);
+        self
+    }
+
+    fn new_upper(&self, path: PathBuf, quota: Option<&Arsc<Quota>>) -> Arsc<MemDirMut> {
+        let upper = MemDirMut::new_unsized(self.perm, path, self.file_inserter.clone());
+        Arsc::new(match quota {
+            Some(quota) => upper.with_quota(quota.clone()),
+            None => upper,
+        })
+    }
+
+    fn child(&self, name: &str, lower: Arsc<MemDir>) -> OverlayDir {
+        let path = self.path.join(name);
+        OverlayDir {
+            lower: Some(lower),
+            upper: self.new_upper(path.clone(), self.quota.as_ref()),
+            perm: self.perm,
+            path,
+            whiteouts: Mutex::new(BTreeSet::new()),
+            children: Mutex::new(BTreeMap::new()),
+            file_inserter: self.file_inserter.clone(),
+            quota: self.quota.clone(),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Lookup, Error> {
+        match self.upper.get(name) {
+            Ok(entry) => return Ok(Lookup::Upper(entry)),
+            Err(Error::NotFound) => {}
+            Err(err) => return Err(err),
+        }
+        if self.whiteouts.lock().contains(name) {
+            return Err(Error::NotFound);
+        }
+        let mut children = self.children.lock();
+        if let Some(child) = children.get(name) {
+            return Ok(Lookup::Child(child.clone()));
+        }
+        let lower = self.lower.as_ref().ok_or(Error::NotFound)?.get(name)?;
+        match lower.clone().into_any().downcast::<MemDir>() {
+            Ok(dir) => {
+                let child = Arsc::new(self.child(name, dir));
+                children.insert(name.into(), child.clone());
+                Ok(Lookup::Child(child))
+            }
+            Err(_) => Ok(Lookup::Lower(lower)),
+        }
+    }
+
+    /// Copy the lower file `name` to the upper layer.
+    fn copy_up(&self, name: &str, lower: Arsc<dyn Entry>) -> Result<Arsc<dyn Entry>, Error> {
+        let file = lower.into_any().downcast::<MemFile>();
+        let file = file.map_err(|_| Error::PermissionDenied(Permission::WRITE))?;
+        let copy = Arsc::new(file.copy_up(self.quota.as_ref())?) as Arsc<dyn Entry>;
+        match self.upper.insert(name.into(), copy.clone()) {
+            Ok(()) => Ok(copy),
+            // Copied up concurrently.
+            Err(Error::Exists) => self.upper.get(name),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Hide the lower entry `name`, if any.
+    fn whiteout(&self, name: &str) {
+        let lower = self.lower.as_ref();
+        if lower.map_or(false, |lower| lower.get(name).is_ok()) {
+            self.whiteouts.lock().insert(name.into());
+            self.children.lock().remove(name);
+        }
+    }
+
+    /// The first name after `last` in the merged directory.
+    fn next_name(&self, last: Option<&str>) -> Option<String> {
+        let upper = self.upper.next_name(last);
+        let lower = self.lower.as_ref().and_then(|lower| {
+            let whiteouts = self.whiteouts.lock();
+            let mut names = lower.names_after(last);
+            names.find(|name| !whiteouts.contains(*name) && self.upper.get(name).is_err())
+        });
+        match (upper, lower) {
+            (Some(upper), Some(lower)) if lower < upper.as_str() => Some(lower.into()),
+            (Some(upper), _) => Some(upper),
+            (None, lower) => lower.map(Into::into),
+        }
+    }
+
+    /// The names of the entries in the merged directory.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        while let Some(name) = self.next_name(names.last().map(String::as_str)) {
+            names.push(name);
+        }
+        names
+    }
+}
+
+impl Entry for OverlayDir {
+    fn open(
+        self: Arsc<Self>,
+        spawner: Spawner,
+        tokens: EventTokens,
+        path: &Path,
+        options: OpenOptions,
+        conn: Channel,
+    ) -> Result<bool, Error> {
+        let create = options.intersects(OpenOptions::CREATE | OpenOptions::CREATE_NEW);
+        match path.components().next() {
+            Some(Component::Normal(name)) => {
+                let name = name
+                    .to_str()
+                    .ok_or_else(|| Error::InvalidPath(path.into()))?;
+                let rest = path.strip_prefix(name).unwrap();
+                match self.lookup(name) {
+                    Ok(Lookup::Upper(_)) => {
+                        let upper = self.upper.clone();
+                        upper.open(spawner, tokens, path, options, conn)
+                    }
+                    Err(Error::NotFound) if create => {
+                        let upper = self.upper.clone();
+                        upper.open(spawner, tokens, path, options, conn)
+                    }
+                    Err(err) => Err(err),
+                    Ok(_) if options.contains(OpenOptions::CREATE_NEW) && rest == Path::new("") => {
+                        Err(Error::Exists)
+                    }
+                    Ok(Lookup::Child(child)) => child.open(spawner, tokens, rest, options, conn),
+                    Ok(Lookup::Lower(entry)) => {
+                        let entry = if rest == Path::new("") && options.contains(OpenOptions::WRITE)
+                        {
+                            self.copy_up(name, entry)?
+                        } else {
+                            entry
+                        };
+                        entry.open(spawner, tokens, rest, options, conn)
+                    }
+                }
+            }
+            Some(_) => Err(Error::InvalidPath(path.into())),
+            None => {
+                if options.intersects(OpenOptions::EXPECT_FILE | OpenOptions::EXPECT_RPC) {
+                    return Err(Error::InvalidType(FileType::Directory));
+                }
+                if options.contains(OpenOptions::CREATE_NEW) {
+                    return Err(Error::Exists);
+                }
+                let require = options.require();
+                if !self.perm.contains(require) {
+                    return Err(Error::PermissionDenied(require - self.perm));
+                }
+                let server =
+                    DirectoryServer::new(AsyncChannel::with_disp(conn, spawner.dispatch()));
+                let task = handle_mut(self, spawner.clone(), tokens, server, options);
+                spawner.spawn(task);
+                Ok(false)
+            }
+        }
+    }
+
+    fn metadata(&self) -> Result<Metadata, Error> {
+        let mut len = 0;
+        let mut last = None;
+        while let Some(name) = self.next_name(last.as_deref()) {
+            len += 1;
+            last = Some(name);
+        }
+        Ok(Metadata {
+            file_type: FileType::Directory,
+            perm: self.perm,
+            len,
+        })
+    }
+}
+
+#[async_trait]
+impl Directory for OverlayDir {
+    async fn next_dirent(&self, last: Option<String>) -> Result<DirEntry, Error> {
+        let name = self.next_name(last.as_deref()).ok_or(Error::IterEnd)?;
+        let metadata = self.lookup(&name)?.metadata()?;
+        Ok(DirEntry { name, metadata })
+    }
+
+    #[inline]
+    fn usage(&self) -> Result<Usage, Error> {
+        self.upper.usage()
+    }
+}
+
+#[async_trait]
+impl DirectoryMut for OverlayDir {
+    async fn rename(
+        self: Arsc<Self>,
+        src: &str,
+        dst_parent: Arsc<dyn DirectoryMut>,
+        dst: &str,
+    ) -> Result<(), Error> {
+        let dst_parent = dst_parent.into_any().downcast::<Self>().unwrap();
+        match self.lookup(src)? {
+            Lookup::Upper(_) => {}
+            Lookup::Lower(entry) => drop(self.copy_up(src, entry)?),
+            Lookup::Child(_) => return Err(Error::PermissionDenied(Permission::WRITE)),
+        }
+        if dst_parent.lookup(dst).is_ok() {
+            return Err(Error::Exists);
+        }
+
+        let upper = self.upper.clone();
+        upper.rename(src, dst_parent.upper.clone(), dst).await?;
+        self.whiteout(src);
+        Ok(())
+    }
+
+    async fn link(
+        self: Arsc<Self>,
+        src: &str,
+        dst_parent: Arsc<dyn DirectoryMut>,
+        dst: &str,
+    ) -> Result<(), Error> {
+        let dst_parent = dst_parent.into_any().downcast::<Self>().unwrap();
+        match self.lookup(src)? {
+            Lookup::Upper(_) => {}
+            Lookup::Lower(entry) => drop(self.copy_up(src, entry)?),
+            Lookup::Child(_) => return Err(Error::PermissionDenied(Permission::WRITE)),
+        }
+        if dst_parent.lookup(dst).is_ok() {
+            return Err(Error::Exists);
+        }
+
+        let upper = self.upper.clone();
+        upper.link(src, dst_parent.upper.clone(), dst).await
+    }
+
+    async fn unlink(&self, name: &str, expect_dir: bool) -> Result<(), Error> {
+        match self.lookup(name)? {
+            Lookup::Upper(_) => self.upper.unlink(name, expect_dir).await?,
+            lower => {
+                let metadata = lower.metadata()?;
+                if expect_dir && metadata.file_type != FileType::Directory {
+                    return Err(Error::InvalidType(metadata.file_type));
+                }
+                if metadata.file_type == FileType::Directory && metadata.len > 0 {
+                    return Err(Error::DirNotEmpty);
+                }
+            }
+        }
+        // A copied-up file would show the lower one again.
+        self.whiteout(name);
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use core::sync::atomic::{AtomicUsize, Ordering::*};
+
+use solvent_core::sync::{Arsc, Mutex};
+use solvent_rpc::io::{dir::Usage, Error};
+
+#[derive(Debug, Clone, Copy)]
+enum Resource {
+    Bytes,
+    Inodes,
+}
+
+/// The byte and inode limits of a directory subtree in the memory FS.
+///
+/// Every charge is also made to all the ancestor quotas, so a subtree can
+/// never use more than its mount. Entries stay charged to the quota they are
+/// created under until they are dropped, even if moved elsewhere.
+#[derive(Debug)]
+pub struct Quota {
+    parent: Option<Arsc<Quota>>,
+    byte_limit: usize,
+    inode_limit: usize,
+    bytes: AtomicUsize,
+    inodes: AtomicUsize,
+}
+
+impl Quota {
+    /// Create a quota nested in `parent`, or for a whole mount if it's `None`.
+    ///
+    /// Limits of `usize::MAX` are unlimited.
+    pub fn new(parent: Option<Arsc<Quota>>, byte_limit: usize, inode_limit: usize) -> Arsc<Self> {
+        Arsc::new(Quota {
+            parent,
+            byte_limit,
+            inode_limit,
+            bytes: AtomicUsize::new(0),
+            inodes: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn usage(&self) -> Usage {
+        Usage {
+            bytes: self.bytes.load(Acquire),
+            byte_limit: self.byte_limit,
+            inodes: self.inodes.load(Acquire),
+            inode_limit: self.inode_limit,
+        }
+    }
+
+    fn counter(&self, res: Resource) -> (&AtomicUsize, usize) {
+        match res {
+            Resource::Bytes => (&self.bytes, self.byte_limit),
+            Resource::Inodes => (&self.inodes, self.inode_limit),
+        }
+    }
+
+    fn ancestors(&self) -> impl Iterator<Item = &Quota> {
+        core::iter::successors(Some(self), |quota| quota.parent.as_deref())
+    }
+
+    fn charge(&self, res: Resource, amount: usize, force: bool) -> Result<(), Error> {
+        for (index, quota) in self.ancestors().enumerate() {
+            let (counter, limit) = quota.counter(res);
+            let ret = counter.fetch_update(AcqRel, Acquire, |used| {
+                let new = used.checked_add(amount)?;
+                (force || new <= limit).then_some(new)
+            });
+            if ret.is_err() {
+                // Roll back the charges of the descendants.
+                for quota in self.ancestors().take(index) {
+                    quota.counter(res).0.fetch_sub(amount, AcqRel);
+                }
+                return Err(match res {
+                    Resource::Bytes => Error::ByteQuotaExceeded,
+                    Resource::Inodes => Error::InodeQuotaExceeded,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn release(&self, res: Resource, amount: usize) {
+        for quota in self.ancestors() {
+            quota.counter(res).0.fetch_sub(amount, AcqRel);
+        }
+    }
+}
+
+/// An entry charged to a quota, which releases the inode and the bytes it
+/// holds when dropped.
+pub struct Inode {
+    quota: Arsc<Quota>,
+    bytes: Mutex<usize>,
+}
+
+impl Inode {
+    pub fn new(quota: &Arsc<Quota>, bytes: usize) -> Result<Self, Error> {
+        Self::charge(quota, bytes, false)
+    }
+
+    /// Charge an entry existing before the quota is set up, ignoring the
+    /// limits.
+    pub(crate) fn forced(quota: &Arsc<Quota>, bytes: usize) -> Self {
+        Self::charge(quota, bytes, true).unwrap()
+    }
+
+    fn charge(quota: &Arsc<Quota>, bytes: usize, force: bool) -> Result<Self, Error> {
+        quota.charge(Resource::Inodes, 1, force)?;
+        let ret = quota.charge(Resource::Bytes, bytes, force);
+        ret.inspect_err(|_| quota.release(Resource::Inodes, 1))?;
+        Ok(Inode {
+            quota: quota.clone(),
+            bytes: Mutex::new(bytes),
+        })
+    }
+
+    /// Charge or release the difference to `new_len` and run `f`, reverting
+    /// the charge if it fails.
+    pub fn resize<T>(
+        &self,
+        new_len: usize,
+        f: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut bytes = self.bytes.lock();
+        let old_len = *bytes;
+        if new_len > old_len {
+            self.quota
+                .charge(Resource::Bytes, new_len - old_len, false)?;
+        }
+        match f() {
+            Ok(ret) => {
+                if new_len < old_len {
+                    self.quota.release(Resource::Bytes, old_len - new_len);
+                }
+                *bytes = new_len;
+                Ok(ret)
+            }
+            Err(err) => {
+                if new_len > old_len {
+                    self.quota.release(Resource::Bytes, new_len - old_len);
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Drop for Inode {
+    fn drop(&mut self) {
+        self.quota.release(Resource::Bytes, *self.bytes.get_mut());
+        self.quota.release(Resource::Inodes, 1);
+    }
+}
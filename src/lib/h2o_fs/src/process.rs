@@ -2,8 +2,13 @@ mod builder;
 
 use core::{mem, ops::Deref, ptr::NonNull};
 
-use solvent::task::{SuspendToken, Task};
+use solvent::{
+    error::ErrorKind,
+    ipc::Channel,
+    task::{SuspendToken, Task},
+};
 use solvent_rpc::SerdePacket;
+use svrt::PanicReport;
 
 pub use self::builder::{Builder, Error as BuildError};
 
@@ -19,6 +24,21 @@ pub enum Error {
     Wait(solvent::error::Error),
 }
 
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Exited(_) => ErrorKind::NotFound,
+            Error::Started => ErrorKind::ResourceBusy,
+            Error::Start(err)
+            | Error::Join(err)
+            | Error::TryJoin(err)
+            | Error::Suspend(err)
+            | Error::Kill(err)
+            | Error::Wait(err) => err.kind(),
+        }
+    }
+}
+
 #[derive(SerdePacket)]
 pub struct InitProcess {
     task: Task,
@@ -26,6 +46,7 @@ pub struct InitProcess {
     stack: NonNull<u8>,
     vdso_base: NonNull<u8>,
     suspend_token: SuspendToken,
+    panic_chan: Channel,
 }
 
 unsafe impl Send for InitProcess {}
@@ -47,13 +68,14 @@ impl InitProcess {
             stack,
             vdso_base,
             suspend_token,
+            panic_chan,
         } = self;
         let mut gpr = suspend_token.read_gpr().map_err(Error::Start)?;
         gpr.rip = entry.as_ptr() as _;
         gpr.rsp = stack.as_ptr() as _;
         gpr.rsi = vdso_base.as_ptr() as _;
         suspend_token.write_gpr(&gpr).map_err(Error::Start)?;
-        Ok(Process::new(task))
+        Ok(Process::new(task, panic_chan))
     }
 }
 
@@ -94,14 +116,14 @@ impl ProcessState {
     }
 }
 
-pub struct Process(ProcessState);
+pub struct Process(ProcessState, Channel);
 
 unsafe impl Send for Process {}
 unsafe impl Sync for Process {}
 
 impl Process {
-    fn new(task: Task) -> Self {
-        Process(ProcessState::Started(task))
+    fn new(task: Task, panic_chan: Channel) -> Self {
+        Process(ProcessState::Started(task), panic_chan)
     }
 
     #[inline]
@@ -109,6 +131,15 @@ impl Process {
         Default::default()
     }
 
+    /// The main task of the process, or `None` if it has been joined.
+    #[inline]
+    pub fn task(&self) -> Option<&Task> {
+        match self.0 {
+            ProcessState::Started(ref task) => Some(task),
+            ProcessState::Exited(_) => None,
+        }
+    }
+
     pub fn suspend(&self) -> Result<SuspendToken, Error> {
         match self.0 {
             ProcessState::Started(ref task) => Ok(task.suspend().map_err(Error::Suspend)?),
@@ -130,12 +161,22 @@ impl Process {
     pub fn try_join(&mut self) -> Result<usize, Error> {
         self.0.try_join()
     }
+
+    /// Get the panic report sent by the process, if any.
+    ///
+    /// The report is only available after the process panicked, so this
+    /// function should usually be called after it exits with a non-zero
+    /// status.
+    #[inline]
+    pub fn panic_report(&self) -> Option<PanicReport> {
+        PanicReport::receive(&self.1).ok()
+    }
 }
 
 mod runtime {
     use core::mem;
 
-    use solvent::prelude::SIG_READ;
+    use solvent::prelude::SIG_GENERIC;
     use solvent_async::{disp::DispSender, ipc::AsyncObject};
 
     use super::{Error, Process};
@@ -153,7 +194,7 @@ mod runtime {
             // log::debug!("Polling");
             let status = match &self.0 {
                 ProcessState::Started(task) => {
-                    task.try_wait_with(disp, true, SIG_READ)
+                    task.try_wait_with(disp, true, SIG_GENERIC)
                         .await
                         .map_err(Error::Wait)?;
                     match mem::replace(&mut self.0, ProcessState::Exited(0)) {
@@ -8,8 +8,10 @@ use alloc::{
 use core::{mem, num::NonZeroUsize, ptr::NonNull};
 
 use solvent::{
-    prelude::{drop_raw, Channel, Feature, Flags, Handle, Object, Phys, Space, Virt, PAGE_SIZE},
-    task::{Task, DEFAULT_STACK_SIZE},
+    prelude::{
+        drop_raw, Channel, ErrorKind, Feature, Flags, Handle, Object, Phys, Space, Virt, PAGE_SIZE,
+    },
+    task::{HandleTransfer, SpawnArgs, Task, DEFAULT_STACK_SIZE},
 };
 use solvent_async::disp::DispSender;
 use solvent_core::{path::PathBuf, sync::Lazy};
@@ -40,6 +42,21 @@ pub enum Error {
     TaskExec(solvent::error::Error),
 }
 
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::LoadPhys(err) => err.kind(),
+            Error::FieldMissing(_) | Error::InvalidCStr(_) => ErrorKind::InvalidInput,
+            Error::DepNotFound(_) => ErrorKind::NotFound,
+            Error::Rpc(err) => err.kind(),
+            Error::VdsoMap(err)
+            | Error::StackAlloc(err)
+            | Error::SendStartupArgs(err)
+            | Error::TaskExec(err) => err.kind(),
+        }
+    }
+}
+
 impl From<elfload::Error> for Error {
     #[inline]
     fn from(value: elfload::Error) -> Self {
@@ -274,80 +291,22 @@ impl Builder {
     }
 
     pub fn build_sync(&mut self) -> Result<Process, Error> {
-        let build_args = self.build_args_sync()?;
-
-        let proc = Process::new(
-            Task::exec(
-                Some(&build_args.name),
-                Some(build_args.space),
-                build_args.entry,
-                build_args.stack,
-                Some(build_args.init_chan),
-                build_args.vdso_base.as_ptr() as _,
-            )
-            .map_err(Error::TaskExec)?,
-        );
-
-        Ok(proc)
+        self.build_args_sync()?.spawn()
     }
 
     pub fn build_non_start_sync(&mut self) -> Result<InitProcess, Error> {
-        let build_args = self.build_args_sync()?;
-
-        let (task, suspend_token) = Task::new(
-            Some(&build_args.name),
-            Some(build_args.space),
-            Some(build_args.init_chan),
-        );
-        let proc = InitProcess {
-            task,
-            entry: build_args.entry,
-            stack: build_args.stack,
-            vdso_base: build_args.vdso_base,
-            suspend_token,
-        };
-
-        Ok(proc)
+        self.build_args_sync()?.into_init()
     }
 
     pub async fn build_with_disp(&mut self, disp: DispSender) -> Result<Process, Error> {
-        let build_args = self.build_args(disp).await?;
-
-        let proc = Process::new(
-            Task::exec(
-                Some(&build_args.name),
-                Some(build_args.space),
-                build_args.entry,
-                build_args.stack,
-                Some(build_args.init_chan),
-                build_args.vdso_base.as_ptr() as _,
-            )
-            .map_err(Error::TaskExec)?,
-        );
-
-        Ok(proc)
+        self.build_args(disp).await?.spawn()
     }
 
     pub async fn build_non_start_with_disp(
         &mut self,
         disp: DispSender,
     ) -> Result<InitProcess, Error> {
-        let build_args = self.build_args(disp).await?;
-
-        let (task, suspend_token) = Task::new(
-            Some(&build_args.name),
-            Some(build_args.space),
-            Some(build_args.init_chan),
-        );
-        let proc = InitProcess {
-            task,
-            entry: build_args.entry,
-            stack: build_args.stack,
-            vdso_base: build_args.vdso_base,
-            suspend_token,
-        };
-
-        Ok(proc)
+        self.build_args(disp).await?.into_init()
     }
 
     #[cfg(feature = "runtime")]
@@ -369,6 +328,62 @@ struct BuildArgs {
     stack: NonNull<u8>,
     init_chan: Channel,
     vdso_base: NonNull<u8>,
+    panic_chan: Channel,
+    me: Channel,
+    startup_args: StartupArgs,
+}
+
+impl BuildArgs {
+    /// Start the process, with the kernel moving the handles in the startup
+    /// arguments into it.
+    fn spawn(self) -> Result<Process, Error> {
+        let StartupArgs { handles, args, env } = self.startup_args;
+        let transfers = handles
+            .into_iter()
+            .map(|(info, handle)| HandleTransfer {
+                handle,
+                features: Feature::all(),
+                info: u32::from_ne_bytes(info.into_bytes()),
+            })
+            .collect::<Vec<_>>();
+        let args = SpawnArgs {
+            transfers: &transfers,
+            args: &args,
+            env: &env,
+        };
+        // SAFETY: The handles in the startup arguments are owned by us.
+        let task = unsafe {
+            Task::spawn(
+                Some(&self.name),
+                Some(self.space),
+                self.entry,
+                self.stack,
+                Some(self.init_chan),
+                self.vdso_base.as_ptr() as _,
+                &args,
+            )
+        }
+        .map_err(Error::TaskExec)?;
+        Ok(Process::new(task, self.panic_chan))
+    }
+
+    fn into_init(self) -> Result<InitProcess, Error> {
+        let mut packet = Default::default();
+        self.startup_args
+            .send(&self.me, &mut packet)
+            .map_err(Error::SendStartupArgs)?;
+
+        let (task, suspend_token) =
+            Task::new(Some(&self.name), Some(self.space), Some(self.init_chan));
+        Ok(InitProcess {
+            task,
+            entry: self.entry,
+            stack: self.stack,
+            vdso_base: self.vdso_base,
+            suspend_token,
+            panic_chan: self.panic_chan,
+        })
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -429,9 +444,20 @@ fn build_end(
         .send(&me, &mut packet)
         .map_err(Error::SendStartupArgs)?;
 
-    startup_args(handles, local_fs, args, environ, root_virt, vdso)
-        .send(&me, &mut packet)
-        .map_err(Error::SendStartupArgs)?;
+    let (panic_chan, panic_child) = Channel::new();
+    let panic_child = panic_child
+        .reduce_features(Feature::SEND | Feature::WRITE)
+        .expect("Failed to reduce features for write");
+
+    let startup_args = startup_args(
+        handles,
+        local_fs,
+        args,
+        environ,
+        root_virt,
+        vdso,
+        panic_child,
+    );
 
     Ok(BuildArgs {
         name,
@@ -440,6 +466,9 @@ fn build_end(
         stack,
         init_chan: child,
         vdso_base: vdso_base.as_non_null_ptr(),
+        panic_chan,
+        me,
+        startup_args,
     })
 }
 
@@ -496,6 +525,7 @@ fn startup_args(
     mut environ: BTreeMap<String, String>,
     root_virt: Virt,
     vdso: Phys,
+    panic_chan: Channel,
 ) -> StartupArgs {
     local_fs
         .into_iter()
@@ -513,6 +543,12 @@ fn startup_args(
         });
     handles.insert(HandleType::RootVirt.into(), Virt::into_raw(root_virt));
     handles.insert(HandleType::VdsoPhys.into(), Phys::into_raw(vdso));
+    if let Some(old) = handles.insert(
+        HandleType::PanicReport.into(),
+        Channel::into_raw(panic_chan),
+    ) {
+        let _ = unsafe { drop_raw(old) };
+    }
     let args = args
         .into_iter()
         .flat_map(|arg| arg.into_bytes().into_iter().chain([0]))
@@ -1,12 +1,11 @@
 use core::{future::Future, marker::PhantomData};
 
-use futures_lite::StreamExt;
 use solvent::prelude::Channel;
 use solvent_async::ipc::Channel as AsyncChannel;
 use solvent_core::{path::Path, sync::Arsc};
 use solvent_rpc::{
     io::{
-        entry::{EntryRequest, EntryServer},
+        entry::{serve_entry_with, EntryServer},
         Error, FileType, Metadata, OpenOptions, Permission,
     },
     Server,
@@ -89,46 +88,18 @@ pub async fn handle_rpc<S, G, F>(
     G: Fn(S, Spawner) -> F + Sync + Send + 'static,
     F: Future<Output = ()> + Sync + Send + 'static,
 {
-    let (mut stream, _) = server.serve();
-
-    while let Some(request) = stream.next().await {
-        let request = match request {
-            Ok(request) => request,
-            Err(err) => {
-                log::warn!("RPC receive error: {err}");
-                continue;
-            }
-        };
-
-        let res = match request {
-            EntryRequest::CloseConnection { responder } => {
-                responder.close();
-                break;
-            }
-            EntryRequest::Open {
-                path,
-                options,
-                conn,
-                responder,
-            } => responder.send(
-                node.clone()
-                    .open(spawner.clone(), tokens.clone(), &path, options, conn)
-                    .map(drop),
-            ),
-            EntryRequest::CloneConnection { conn, responder } => {
-                node.clone()
-                    .open_conn(spawner.clone(), tokens.clone(), conn);
-                responder.send(())
-            }
-            EntryRequest::Metadata { responder } => responder.send(node.metadata()),
-            EntryRequest::Unknown(_) => {
-                log::warn!("unknown request received");
-                continue;
-            }
-        };
-
-        if let Err(err) = res {
-            log::warn!("RPC send error: {err}")
-        }
-    }
+    serve_entry_with(
+        server,
+        |path, options, conn| {
+            node.clone()
+                .open(spawner.clone(), tokens.clone(), &path, options, conn)
+                .map(drop)
+        },
+        || node.metadata(),
+        |conn| {
+            node.clone()
+                .open_conn(spawner.clone(), tokens.clone(), conn)
+        },
+    )
+    .await
 }
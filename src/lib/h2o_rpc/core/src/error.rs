@@ -4,7 +4,10 @@ use alloc::boxed::Box;
 use core as std; // Hacking `thiserror::Error`.
 use core::error::Error as Trait;
 
-use solvent::error::Error as RawError;
+use solvent::{
+    error::{Error as RawError, ErrorKind},
+    obj::Handle,
+};
 use thiserror_impl::Error;
 
 #[derive(Error, Debug)]
@@ -39,6 +42,9 @@ pub enum Error {
     #[error("invalid method: expected {expected}, found {found}")]
     InvalidMethod { expected: usize, found: usize },
 
+    #[error("mismatched protocol: expected {expected:#x}, found {found:#x}")]
+    ProtocolMismatch { expected: u128, found: u128 },
+
     #[error("extra buffer sized {extra_buffer_len} and {extra_handle_count} handles found")]
     SizeMismatch {
         extra_buffer_len: usize,
@@ -47,4 +53,33 @@ pub enum Error {
 
     #[error("The endpoint to be serialized is already in use")]
     EndpointInUse,
+
+    #[error("{0:?} is already owned by another object")]
+    HandleOwned(Handle),
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Disconnected => ErrorKind::BrokenPipe,
+            Error::ClientReceive(err)
+            | Error::ClientSend(err)
+            | Error::ServerReceive(err)
+            | Error::ServerSend(err) => err.kind(),
+            Error::BufferTooShort { .. }
+            | Error::TypeMismatch(_)
+            | Error::InvalidMagic(_)
+            | Error::InvalidMethod { .. }
+            | Error::ProtocolMismatch { .. }
+            | Error::SizeMismatch { .. } => ErrorKind::InvalidData,
+            Error::EndpointInUse | Error::HandleOwned(_) => ErrorKind::ResourceBusy,
+        }
+    }
+}
+
+impl From<&Error> for ErrorKind {
+    #[inline]
+    fn from(value: &Error) -> Self {
+        value.kind()
+    }
 }
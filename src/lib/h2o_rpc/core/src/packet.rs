@@ -22,6 +22,11 @@ impl<'a> Serializer<'a> {
     fn extend_from_slice(&mut self, slice: &[u8]) {
         self.0.buffer.extend_from_slice(slice);
     }
+
+    #[inline]
+    fn handle_len(&self) -> usize {
+        self.0.handles.len()
+    }
 }
 
 impl Extend<u8> for Serializer<'_> {
@@ -112,13 +117,44 @@ impl<'a> Deserializer<'a> {
         self.handles = self.handles.get_unchecked(1..);
         ret
     }
+
+    /// Run `func` with only the next `count` handles visible, which must be
+    /// consumed exactly.
+    pub fn with_handles<R>(
+        &mut self,
+        count: usize,
+        func: impl FnOnce(&mut Self) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        self.check_handles(count)?;
+        let (handles, rest) = self.handles.split_at(count);
+        self.handles = handles;
+        let ret = func(self)?;
+        if !self.handles.is_empty() {
+            return Err(Error::SizeMismatch {
+                extra_buffer_len: 0,
+                extra_handle_count: self.handles.len(),
+            });
+        }
+        self.handles = rest;
+        Ok(ret)
+    }
 }
 
 pub trait SerdePacket: Sized {
+    /// Whether the values may carry handles, in which case the collections of
+    /// them are prefixed with the exact number of the handles inside.
+    const HAS_HANDLES: bool = false;
+
     fn serialize(self, ser: &mut Serializer) -> Result<(), Error>;
 
     fn deserialize(de: &mut Deserializer) -> Result<Self, Error>;
 
+    /// The number of the handles to be serialized from the value.
+    #[inline]
+    fn handle_count(&self) -> usize {
+        0
+    }
+
     /// # Safety
     ///
     /// The deserializer must have enough buffer and handles to be deserialized.
@@ -183,6 +219,8 @@ macro_rules! serde_basic {
 serde_basic!(u8, u16, u32, usize, u64, u128, i8, i16, i32, isize, i64, i128, f32, f64);
 
 impl<T: SerdePacket, const N: usize> SerdePacket for [T; N] {
+    const HAS_HANDLES: bool = T::HAS_HANDLES;
+
     #[inline]
     fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
         self.into_iter().try_for_each(|elem| elem.serialize(ser))
@@ -192,12 +230,19 @@ impl<T: SerdePacket, const N: usize> SerdePacket for [T; N] {
     fn deserialize(de: &mut Deserializer) -> Result<Self, Error> {
         array::try_from_fn(|_| T::deserialize(de))
     }
+
+    #[inline]
+    fn handle_count(&self) -> usize {
+        self.iter().map(T::handle_count).sum()
+    }
 }
 
 macro_rules! serde_tuples {
     (@INNER $($ty:ident),+ $(,)?) => {
         #[allow(non_snake_case)]
         impl<$($ty : SerdePacket),+> SerdePacket for ($($ty,)+) {
+            const HAS_HANDLES: bool = false $(|| $ty::HAS_HANDLES)+;
+
             fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
                 let ($($ty,)+) = self;
                 $($ty.serialize(ser)?;)+
@@ -208,6 +253,11 @@ macro_rules! serde_tuples {
                 $(let $ty = <$ty>::deserialize(de)?;)+
                 Ok(($($ty,)+))
             }
+
+            fn handle_count(&self) -> usize {
+                let ($($ty,)+) = self;
+                0 $(+ $ty.handle_count())+
+            }
         }
     };
     () => {};
@@ -219,6 +269,8 @@ macro_rules! serde_tuples {
 serde_tuples!(A, B, C, D, E, F, G, H, I, J, K, L);
 
 impl<T: SerdePacket, E: SerdePacket> SerdePacket for Result<T, E> {
+    const HAS_HANDLES: bool = T::HAS_HANDLES || E::HAS_HANDLES;
+
     fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
         match self {
             Ok(t) => {
@@ -246,6 +298,14 @@ impl<T: SerdePacket, E: SerdePacket> SerdePacket for Result<T, E> {
         };
         Ok(ret)
     }
+
+    #[inline]
+    fn handle_count(&self) -> usize {
+        match self {
+            Ok(t) => t.handle_count(),
+            Err(e) => e.handle_count(),
+        }
+    }
 }
 
 impl SerdePacket for NonNull<u8> {
@@ -262,6 +322,8 @@ impl SerdePacket for NonNull<u8> {
 }
 
 impl<T: SerdePacket> SerdePacket for Box<T> {
+    const HAS_HANDLES: bool = T::HAS_HANDLES;
+
     #[inline]
     fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
         Box::into_inner(self).serialize(ser)
@@ -271,23 +333,75 @@ impl<T: SerdePacket> SerdePacket for Box<T> {
     fn deserialize(de: &mut Deserializer) -> Result<Self, Error> {
         T::deserialize(de).map(Box::new)
     }
+
+    #[inline]
+    fn handle_count(&self) -> usize {
+        (**self).handle_count()
+    }
 }
 
-impl<T: SerdePacket> SerdePacket for Vec<T> {
-    fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
-        self.len().serialize(ser)?;
-        self.into_iter().try_for_each(|elem| elem.serialize(ser))
+/// Serialize the `len` elements of a collection, with the number of the
+/// handles inside following the length if the elements may carry handles.
+fn serialize_seq<T: SerdePacket>(
+    len: usize,
+    handle_count: usize,
+    mut iter: impl Iterator<Item = T>,
+    ser: &mut Serializer,
+) -> Result<(), Error> {
+    len.serialize(ser)?;
+    if !T::HAS_HANDLES {
+        return iter.try_for_each(|elem| elem.serialize(ser));
+    }
+    handle_count.serialize(ser)?;
+    let start = ser.handle_len();
+    iter.try_for_each(|elem| elem.serialize(ser))?;
+    let actual = ser.handle_len() - start;
+    if actual != handle_count {
+        return Err(Error::TypeMismatch(
+            format!("expected {handle_count} handles to be serialized, found {actual}").into(),
+        ));
     }
+    Ok(())
+}
 
-    fn deserialize(de: &mut Deserializer) -> Result<Self, Error> {
-        let len = usize::deserialize(de)?;
+fn deserialize_seq<T: SerdePacket, C: FromIterator<T>>(de: &mut Deserializer) -> Result<C, Error> {
+    let len = usize::deserialize(de)?;
+    let collect = |de: &mut Deserializer| {
         iter::repeat_with(|| T::deserialize(de))
             .take(len)
             .try_collect()
+    };
+    if T::HAS_HANDLES {
+        let handle_count = usize::deserialize(de)?;
+        de.with_handles(handle_count, collect)
+    } else {
+        collect(de)
+    }
+}
+
+impl<T: SerdePacket> SerdePacket for Vec<T> {
+    const HAS_HANDLES: bool = T::HAS_HANDLES;
+
+    #[inline]
+    fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
+        let handle_count = self.handle_count();
+        serialize_seq(self.len(), handle_count, self.into_iter(), ser)
+    }
+
+    #[inline]
+    fn deserialize(de: &mut Deserializer) -> Result<Self, Error> {
+        deserialize_seq::<T, _>(de)
+    }
+
+    #[inline]
+    fn handle_count(&self) -> usize {
+        self.iter().map(T::handle_count).sum()
     }
 }
 
 impl<T: SerdePacket> SerdePacket for Option<Vec<T>> {
+    const HAS_HANDLES: bool = T::HAS_HANDLES;
+
     #[inline]
     fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
         self.unwrap_or_default().serialize(ser)
@@ -298,6 +412,11 @@ impl<T: SerdePacket> SerdePacket for Option<Vec<T>> {
         let vec = Vec::<T>::deserialize(de)?;
         Ok((!vec.is_empty()).then_some(vec))
     }
+
+    #[inline]
+    fn handle_count(&self) -> usize {
+        self.as_ref().map_or(0, Vec::handle_count)
+    }
 }
 
 impl SerdePacket for String {
@@ -351,16 +470,23 @@ impl SerdePacket for Option<CString> {
 }
 
 impl<K: Ord + SerdePacket, V: SerdePacket> SerdePacket for BTreeMap<K, V> {
+    const HAS_HANDLES: bool = K::HAS_HANDLES || V::HAS_HANDLES;
+
+    #[inline]
     fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
-        self.len().serialize(ser)?;
-        self.into_iter().try_for_each(|kv| kv.serialize(ser))
+        let handle_count = self.handle_count();
+        serialize_seq(self.len(), handle_count, self.into_iter(), ser)
     }
 
+    #[inline]
     fn deserialize(de: &mut Deserializer) -> Result<Self, Error> {
-        let len = usize::deserialize(de)?;
-        iter::repeat_with(|| <(K, V)>::deserialize(de))
-            .take(len)
-            .try_collect()
+        deserialize_seq::<(K, V), _>(de)
+    }
+
+    fn handle_count(&self) -> usize {
+        self.iter()
+            .map(|(k, v)| k.handle_count() + v.handle_count())
+            .sum()
     }
 }
 
@@ -378,6 +504,8 @@ impl SerdePacket for solvent::error::Error {
 }
 
 impl SerdePacket for Handle {
+    const HAS_HANDLES: bool = true;
+
     #[inline]
     fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
         ser.extend_one(self);
@@ -388,9 +516,16 @@ impl SerdePacket for Handle {
     fn deserialize(de: &mut Deserializer) -> Result<Self, Error> {
         de.next_handle()
     }
+
+    #[inline]
+    fn handle_count(&self) -> usize {
+        1
+    }
 }
 
 impl SerdePacket for Option<Handle> {
+    const HAS_HANDLES: bool = true;
+
     fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
         let handle = self.unwrap_or(Handle::NULL);
         handle.serialize(ser)
@@ -400,11 +535,19 @@ impl SerdePacket for Option<Handle> {
         let handle = Handle::deserialize(de)?;
         Ok(handle.check_null().ok())
     }
+
+    /// The null handle takes a slot as well.
+    #[inline]
+    fn handle_count(&self) -> usize {
+        1
+    }
 }
 
 macro_rules! serde_ko {
     ($ty:ty) => {
         impl SerdePacket for $ty {
+            const HAS_HANDLES: bool = true;
+
             fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
                 Self::ID.serialize(ser)?;
                 ser.extend_one(Self::into_raw(self));
@@ -419,12 +562,20 @@ macro_rules! serde_ko {
                     ));
                 }
                 let handle = de.next_handle()?;
-                Ok(unsafe { Self::from_raw(handle) })
+                unsafe { Self::try_from_raw(handle) }.map_err(|_| Error::HandleOwned(handle))
+            }
+
+            #[inline]
+            fn handle_count(&self) -> usize {
+                1
             }
         }
 
         impl SerdePacket for Option<$ty> {
+            const HAS_HANDLES: bool = true;
+
             fn serialize(self, ser: &mut Serializer) -> Result<(), Error> {
+                <$ty>::ID.serialize(ser)?;
                 self.map(<$ty>::into_raw).serialize(ser)
             }
 
@@ -436,7 +587,17 @@ macro_rules! serde_ko {
                     ));
                 }
                 let handle = Option::<Handle>::deserialize(de)?;
-                Ok(handle.map(|handle| unsafe { <$ty>::from_raw(handle) }))
+                handle
+                    .map(|handle| {
+                        unsafe { <$ty>::try_from_raw(handle) }
+                            .map_err(|_| Error::HandleOwned(handle))
+                    })
+                    .transpose()
+            }
+
+            #[inline]
+            fn handle_count(&self) -> usize {
+                1
             }
         }
     };
@@ -494,10 +655,12 @@ pub fn deserialize<T: SerdePacket>(
 
 #[cfg(test)]
 mod test {
-    use alloc::{collections::BTreeMap, string::String};
+    use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+    use solvent::prelude::{Handle, Packet};
 
-    use super::{deserialize_body, serialize, SerdePacket};
-    use crate::packet::{Deserializer, Serializer};
+    use super::{deserialize, serialize};
+    use crate::Error;
 
     #[test]
     fn test_btree_map() {
@@ -514,4 +677,35 @@ mod test {
 
         assert_eq!(de, ser);
     }
+    #[test]
+    fn test_handle_vec() {
+        type Data = (Vec<(u8, Handle)>, Option<Handle>);
+        let ser: Data = (
+            (1..4).map(|i| (i, Handle::new(i.into()))).collect(),
+            Some(Handle::new(9)),
+        );
+        let mut packet = Packet::default();
+        serialize(12345, ser.clone(), &mut packet).expect("Failed to serialize packet");
+        assert_eq!(packet.handles.len(), 4);
+
+        let de: Data = deserialize(12345, &packet, None).expect("Failed to deserialize packet");
+        assert_eq!(de, ser);
+
+        // The handle count follows the magic, the method id and the length.
+        let count = 24..32;
+        packet.buffer[count.clone()].copy_from_slice(&2usize.to_ne_bytes());
+        let res = deserialize::<Data>(12345, &packet, None);
+        assert!(matches!(res, Err(Error::BufferTooShort { .. })));
+
+        packet.buffer[count].copy_from_slice(&4usize.to_ne_bytes());
+        packet.handles.insert(3, Handle::new(10));
+        let res = deserialize::<Data>(12345, &packet, None);
+        assert!(matches!(
+            res,
+            Err(Error::SizeMismatch {
+                extra_handle_count: 1,
+                ..
+            })
+        ));
+    }
 }
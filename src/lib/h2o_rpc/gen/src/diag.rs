@@ -0,0 +1,90 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use proc_macro2::Span;
+use syn::Error;
+
+/// Attach a suggestion to an error message, rendered as a `help` note below
+/// the source snippet.
+pub fn help(span: Span, message: impl fmt::Display, help: impl fmt::Display) -> Error {
+    Error::new(span, format!("{message}\n{HELP}{help}"))
+}
+
+const HELP: &str = "help: ";
+
+/// A parse error in a protocol file, rendered in the style of rustc.
+#[derive(Debug)]
+pub struct Diagnostic {
+    path: PathBuf,
+    error: Error,
+}
+
+impl Diagnostic {
+    pub fn new(path: &Path, error: Error) -> Self {
+        Diagnostic {
+            path: path.to_path_buf(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = std::fs::read_to_string(&self.path).unwrap_or_default();
+        let path = self.path.display();
+        for (index, error) in self.error.clone().into_iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            let message = error.to_string();
+            let (message, help) = match message.split_once(&format!("\n{HELP}")) {
+                Some((message, help)) => (message, Some(help)),
+                None => (&*message, None),
+            };
+
+            let (start, end) = (error.span().start(), error.span().end());
+            let Some(line) = source.lines().nth(start.line.wrapping_sub(1)) else {
+                writeln!(f, "error: {message}")?;
+                writeln!(f, " --> {path}")?;
+                if let Some(help) = help {
+                    writeln!(f, "  = {HELP}{help}")?;
+                }
+                continue;
+            };
+            let width = start.line.to_string().len();
+            let len = if end.line == start.line {
+                end.column.saturating_sub(start.column).max(1)
+            } else {
+                line.chars().count().saturating_sub(start.column).max(1)
+            };
+
+            writeln!(f, "error: {message}")?;
+            writeln!(
+                f,
+                "{:width$}--> {path}:{}:{}",
+                "",
+                start.line,
+                start.column + 1
+            )?;
+            writeln!(f, "{:width$} |", "")?;
+            writeln!(f, "{} | {line}", start.line)?;
+            writeln!(
+                f,
+                "{:width$} | {:col$}{}",
+                "",
+                "",
+                "^".repeat(len),
+                col = start.column
+            )?;
+            if let Some(help) = help {
+                writeln!(f, "{:width$} |", "")?;
+                writeln!(f, "{:width$} = {HELP}{help}", "")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
@@ -6,11 +6,19 @@ use std::{
     path::Path,
 };
 
-use quote::ToTokens;
+use quote::{quote, ToTokens};
 
-use crate::parse::{ProtoItem, ProtoType};
+use crate::{
+    parse::{ProtoItem, ProtoType},
+    registry::Registry,
+};
 
-pub fn gen(items: Vec<ProtoItem>, target_root: &Path) -> Result<(), Box<dyn Error>> {
+pub fn gen(
+    items: Vec<ProtoItem>,
+    registry: &Registry,
+    root_mod: &Path,
+    target_root: &Path,
+) -> Result<(), Box<dyn Error>> {
     let mut map = HashMap::new();
 
     for item in items {
@@ -40,6 +48,20 @@ pub fn gen(items: Vec<ProtoItem>, target_root: &Path) -> Result<(), Box<dyn Erro
             ProtoType::Item(item) => write!(writer, "{}", item.to_token_stream())?,
         }
     }
+
+    let protocols = registry.iter().map(|(name, id)| quote!((#name, #id)));
+    let writer = map
+        .get_mut(root_mod)
+        .ok_or("The root module has no items")?;
+    write!(
+        writer,
+        "{}",
+        quote! {
+            /// The names and ids of all the protocols in the registry.
+            pub const PROTOCOLS: &[(&str, u128)] = &[#(#protocols,)*];
+        }
+    )?;
+
     map.into_iter()
         .try_for_each(|(_, mut writer)| writer.flush())?;
     Ok(())
@@ -1,14 +1,35 @@
 #![feature(box_into_inner)]
 
-use std::path::Path;
+use std::{error::Error, path::Path};
 
+mod diag;
 mod gen;
 mod parse;
+mod registry;
 mod resolve;
 mod types;
 
+fn load(src: &Path) -> Result<(Vec<parse::ProtoItem>, registry::Registry), Box<dyn Error>> {
+    let mut items = parse::parse_root(src)?;
+    let registry = registry::load(&src.join("registry.txt"))?;
+    registry::assign(src, &mut items, &registry)?;
+    resolve::resolve(&mut items)?;
+    Ok((items, registry))
+}
+
+/// Check the protocols in `src` without generating any code, returning the
+/// rendered diagnostics on failure.
+pub fn check(src: &Path) -> Result<(), String> {
+    load(src).map(drop).map_err(|err| err.to_string())
+}
+
 pub fn generate(src: &Path, dst: &Path) {
-    let mut items = parse::parse_root(src).expect("Failed to parse the directory");
-    resolve::resolve(&mut items).expect("Failed to resolve dependencies");
-    gen::gen(items, dst).expect("Failed to write to files");
+    let (items, registry) = match load(src) {
+        Ok(res) => res,
+        Err(err) => {
+            eprintln!("{err}");
+            panic!("Failed to load the protocols in {src:?}")
+        }
+    };
+    gen::gen(items, &registry, &src.join("mod.rs"), dst).expect("Failed to write to files");
 }
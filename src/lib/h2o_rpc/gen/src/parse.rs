@@ -1,8 +1,9 @@
 use std::fmt;
 
+use quote::ToTokens;
 use syn::*;
 
-use crate::types::Protocol;
+use crate::{diag::Diagnostic, types::Protocol};
 
 #[derive(Debug)]
 pub enum ProtoType {
@@ -32,13 +33,18 @@ impl fmt::Debug for ProtoItem {
 }
 
 type FilePath = std::path::Path;
+type Result<T> = std::result::Result<T, Diagnostic>;
+
+fn parse_path(path: &FilePath) -> Result<File> {
+    println!("cargo:rerun-if-changed={}", path.to_str().unwrap());
+    let content = std::fs::read_to_string(path).expect("Failed to read module file");
+    parse_file(&content).map_err(|err| Diagnostic::new(path, err))
+}
 
 pub fn parse_root(dir: &FilePath) -> Result<Vec<ProtoItem>> {
     const NAME: &str = "mod.rs";
     let mod_path = dir.join(NAME);
-    println!("cargo:rerun-if-changed={}", mod_path.to_str().unwrap());
-    let content = std::fs::read_to_string(&mod_path).expect("Failed to read module file");
-    let file = parse_file(&content)?;
+    let file = parse_path(&mod_path)?;
     parse_mod_items(&mod_path, file.items, false)
 }
 
@@ -79,10 +85,7 @@ fn parse_mod(
                 Some(path) => path,
                 None => panic!("Failed to find module file {name} in {mod_path:?}"),
             };
-            println!("cargo:rerun-if-changed={}", path.to_str().unwrap());
-            let content = std::fs::read_to_string(&path).expect("Failed to read module file");
-            let file = parse_file(&content)?;
-
+            let file = parse_path(&path)?;
             parse_mod_items(&path, file.items, false)
         }
     }
@@ -117,18 +120,15 @@ fn parse_mod_items(
                 )?)
             }
             Item::Trait(t) => {
-                let is_protocol = t.attrs.iter().any(|attr| {
-                    matches!(
-                        attr.parse_meta(),
-                        Ok(Meta::List(MetaList { path, .. })) | Ok(Meta::Path(path))
-                            if path.is_ident("protocol")
-                    )
-                });
-                // println!("{:#?}, {is_protocol}", t.attrs);
+                // Malformed `protocol` attributes are reported when parsing the
+                // protocol, instead of turning it into a plain trait silently.
+                let is_protocol = t.attrs.iter().any(|attr| attr.path.is_ident("protocol"));
                 ret.push(ProtoItem {
                     parent: mod_path.to_path_buf(),
                     ty: if is_protocol {
-                        ProtoType::Protocol(parse_quote!(#t))
+                        let proto = parse2(t.into_token_stream())
+                            .map_err(|err| Diagnostic::new(mod_path, err))?;
+                        ProtoType::Protocol(proto)
                     } else {
                         ProtoType::Item(Item::Trait(t))
                     },
@@ -0,0 +1,93 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    fs,
+    path::Path,
+};
+
+use crate::{
+    diag::{help, Diagnostic},
+    parse::{ProtoItem, ProtoType},
+};
+
+/// The globally unique ids of all the protocols, indexed by their paths.
+pub type Registry = BTreeMap<String, u128>;
+
+pub fn load(path: &Path) -> Result<Registry, Box<dyn Error>> {
+    println!("cargo:rerun-if-changed={}", path.to_str().unwrap());
+    let content = fs::read_to_string(path)?;
+
+    let mut registry = Registry::new();
+    let mut allocated = HashMap::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, uuid) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("Invalid registry entry at line {}", index + 1))?;
+        let uuid = uuid.trim();
+        let id = u128::from_str_radix(&uuid.replace('-', ""), 16)
+            .map_err(|err| format!("Invalid UUID {uuid:?} of `{name}`: {err}"))?;
+
+        if let Some(old) = allocated.insert(id, name) {
+            return Err(
+                format!("The id {uuid} of `{name}` is already allocated to `{old}`").into(),
+            );
+        }
+        if registry.insert(name.to_string(), id).is_some() {
+            return Err(format!("Protocol `{name}` is registered more than once").into());
+        }
+    }
+    Ok(registry)
+}
+
+fn proto_path(root: &Path, parent: &Path, ident: &str) -> String {
+    let module = parent
+        .strip_prefix(root)
+        .unwrap_or(parent)
+        .with_extension("");
+    let segments = module
+        .iter()
+        .map(|seg| seg.to_string_lossy().into_owned())
+        .filter(|seg| seg != "mod");
+    segments
+        .chain([ident.to_string()])
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// Assign every protocol its id in the registry, and check that the registry
+/// matches the protocols exactly.
+pub fn assign(
+    root: &Path,
+    items: &mut [ProtoItem],
+    registry: &Registry,
+) -> Result<(), Box<dyn Error>> {
+    let mut unused = registry.keys().collect::<BTreeSet<_>>();
+    for item in items {
+        let proto = match &mut item.ty {
+            ProtoType::Protocol(proto) => proto,
+            _ => continue,
+        };
+        let path = proto_path(root, &item.parent, &proto.ident.to_string());
+        proto.id = match registry.get(&path) {
+            Some(&id) => id,
+            None => {
+                let error = help(
+                    proto.ident.span(),
+                    format!("protocol `{path}` has no id"),
+                    format!("allocate a new random UUID for it in `registry.txt`: `{path} <UUID>`"),
+                );
+                return Err(Diagnostic::new(&item.parent, error).into());
+            }
+        };
+        unused.remove(&path);
+        proto.path = path;
+    }
+    match unused.first() {
+        Some(path) => Err(format!("Registered protocol `{path}` is not found").into()),
+        None => Ok(()),
+    }
+}
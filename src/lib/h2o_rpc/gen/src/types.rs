@@ -1,12 +1,15 @@
 use convert_case::{Case, Casing};
 use proc_macro2::TokenStream;
-use quote::{format_ident, quote, spanned::Spanned, ToTokens};
+use quote::{format_ident, quote, ToTokens};
 use syn::{
     parse::{Parse, ParseStream},
     punctuated::Punctuated,
+    spanned::Spanned,
     *,
 };
 
+use crate::diag::help;
+
 #[derive(Debug)]
 pub struct Protocol {
     pub vis: Visibility,
@@ -15,43 +18,70 @@ pub struct Protocol {
     pub ident: Ident,
     pub doc: Vec<Attribute>,
     pub method: Vec<Method>,
+    /// The path of the protocol relative to the root module.
+    pub path: String,
+    /// The globally unique id allocated in the registry.
+    pub id: u128,
+    /// The revision of the protocol, exchanged in the handshakes.
+    pub version: u32,
 }
 
 impl Parse for Protocol {
     fn parse(input: ParseStream) -> Result<Self> {
-        let mut attr = Attribute::parse_outer(input)?;
+        let attr = Attribute::parse_outer(input)?;
 
-        let mut multiple_proto = false;
+        let mut doc = Vec::with_capacity(attr.len());
         let mut event: Option<Punctuated<Path, Token![,]>> = None;
-        attr.retain(|attr| {
-            match attr.parse_meta() {
-                Ok(Meta::NameValue(MetaNameValue { path, .. })) if path.is_ident("doc") => {
-                    return true
-                }
-                Ok(Meta::List(MetaList { path, nested, .. })) if path.is_ident("protocol") => {
-                    let old = event.replace(parse_quote!(#nested));
-                    multiple_proto |= old.is_some();
-                }
-                Ok(Meta::Path(path)) if path.is_ident("protocol") => {
-                    let old = event.replace(Punctuated::new());
-                    multiple_proto |= old.is_some();
-                }
-                _ => {}
+        let mut version = None;
+        for attr in attr {
+            if attr.path.is_ident("doc") {
+                doc.push(attr);
+                continue;
             }
-            false
-        });
-        if multiple_proto {
-            return Err(Error::new(
-                input.span(),
-                "The protocol must have exact one protocol attribute",
-            ));
+            if attr.path.is_ident("version") {
+                if version.is_some() {
+                    return Err(Error::new(attr.span(), "duplicate `#[version]` attribute"));
+                }
+                let lit = attr.parse_args::<LitInt>().map_err(|err| {
+                    help(
+                        err.span(),
+                        "invalid arguments for `#[version]`",
+                        "use `#[version(N)]` with an integer revision",
+                    )
+                })?;
+                version = Some(lit.base10_parse::<u32>()?);
+                continue;
+            }
+            if !attr.path.is_ident("protocol") {
+                return Err(help(
+                    attr.span(),
+                    "unsupported attribute on protocols",
+                    "only `#[protocol]`, `#[version]` and doc comments are allowed here",
+                ));
+            }
+            if event.is_some() {
+                return Err(help(
+                    attr.span(),
+                    "duplicate `#[protocol]` attribute",
+                    "merge the event types into one `#[protocol(Event1, Event2)]`",
+                ));
+            }
+            let events = match attr.parse_meta() {
+                Ok(Meta::Path(_)) => Punctuated::new(),
+                Ok(Meta::List(_)) => attr.parse_args_with(Punctuated::parse_terminated)?,
+                _ => {
+                    return Err(help(
+                        attr.tokens.span(),
+                        "invalid arguments for `#[protocol]`",
+                        "use `#[protocol]`, or `#[protocol(Event, ..)]` with the types of the \
+                        events",
+                    ))
+                }
+            };
+            event = Some(events);
         }
-        let event = event.ok_or_else(|| {
-            Error::new(
-                input.span(),
-                "The protocol must have exact one protocol attribute",
-            )
-        })?;
+        let event =
+            event.ok_or_else(|| Error::new(input.span(), "missing the `#[protocol]` attribute"))?;
 
         let vis = Visibility::parse(input)?;
         <Token![trait]>::parse(input)?;
@@ -70,8 +100,11 @@ impl Parse for Protocol {
             event: event.into_iter().map(|event| (event, 0)).collect(),
             from,
             ident,
-            doc: attr,
+            doc,
             method: Vec::from_iter(method),
+            path: String::new(),
+            id: 0,
+            version: version.unwrap_or(0),
         })
     }
 }
@@ -221,17 +254,21 @@ impl Parse for Method {
                 match &*meta.path.to_token_stream().to_string() {
                     "close" => {
                         if !meta.tokens.is_empty() {
-                            return Err(Error::new_spanned(
-                                meta.tokens,
-                                "Invalid format for `#[close]`",
+                            return Err(help(
+                                meta.tokens.span(),
+                                "`#[close]` takes no arguments",
+                                "remove the arguments",
                             ));
                         }
                         close = true;
                     }
                     "doc" => doc.push(meta),
                     _ => {
-                        let message = format!("Unsupported attribute {meta:?}");
-                        return Err(Error::new_spanned(meta.tokens, message));
+                        return Err(help(
+                            meta.span(),
+                            "unsupported attribute on protocol methods",
+                            "only `#[close]` and doc comments are allowed here",
+                        ))
                     }
                 }
             }
@@ -239,33 +276,66 @@ impl Parse for Method {
             (close, doc)
         };
         let sig = Signature::parse(input)?;
+        if let Some(ref a) = sig.asyncness {
+            return Err(help(
+                a.span,
+                "protocol methods cannot be async",
+                "remove `async`, both async and blocking clients are generated",
+            ));
+        }
         if let Some(ref c) = sig.constness {
-            return Err(Error::new(c.span, "Protocol methods cannot be const"));
+            return Err(help(
+                c.span,
+                "protocol methods cannot be const",
+                "remove `const`",
+            ));
         }
         if let Some(ref u) = sig.unsafety {
-            return Err(Error::new(u.span, "Protocol methods cannot be unsafe"));
+            return Err(help(
+                u.span,
+                "protocol methods cannot be unsafe",
+                "remove `unsafe`",
+            ));
         }
-        if let Some(ref r) = sig.generics.lt_token {
-            return Err(Error::new(r.span, "Protocol methods cannot have generics"));
+        if sig.generics.lt_token.is_some() {
+            return Err(Error::new(
+                sig.generics.span(),
+                "protocol methods cannot have generics",
+            ));
         }
         if let Some(ref v) = sig.variadic {
             return Err(Error::new(
-                v.dots.spans[0],
-                "Protocol methods cannot have varadic args",
+                v.span(),
+                "protocol methods cannot have variadic args",
+            ));
+        }
+        if let Some(abi) = &sig.abi {
+            return Err(help(
+                abi.span(),
+                "protocol methods cannot have an ABI",
+                "remove the `extern` qualifier",
             ));
         }
 
         let ident = sig.ident;
         let ident_str = ident.to_string();
+        if ["handshake", "supports"].contains(&&*ident_str) {
+            return Err(help(
+                ident.span(),
+                "the method name is reserved",
+                "rename it, the clients have their own `handshake` and `supports`",
+            ));
+        }
         let const_ident = Ident::new(&ident_str.to_case(Case::UpperSnake), ident.span());
         let type_ident_prefix = ident_str.to_case(Case::UpperCamel);
 
         let args = sig.inputs;
         for arg in &args {
             if let FnArg::Receiver(receiver) = arg {
-                return Err(Error::new(
-                    receiver.__span(),
-                    "Protocol method cannot have receiver args (auto included)",
+                return Err(help(
+                    receiver.span(),
+                    "protocol methods cannot have receiver args",
+                    "remove it, the connection is passed implicitly",
                 ));
             }
         }
@@ -423,6 +493,12 @@ impl Method {
                 pub fn close(self) {
                     self.inner.close()
                 }
+
+                /// Get the identity of the client that sends the request.
+                #[inline]
+                pub fn peer(&self) -> solvent::error::Result<solvent::ipc::ChanPeerId> {
+                    self.inner.peer()
+                }
             }
         }
     }
@@ -437,6 +513,9 @@ impl Protocol {
             ident,
             doc,
             method,
+            path,
+            id,
+            version,
         } = self;
 
         let ident_str = ident.to_string();
@@ -458,6 +537,7 @@ impl Protocol {
 
         let constants = method.iter().map(|method| method.constant(&vis));
         let use_constants = method.iter().map(|method| &method.const_ident);
+        let method_consts = method.iter().map(|method| &method.const_ident);
         let calls = method.iter().map(|method| method.call());
         let sync_calls = method.iter().map(|method| method.sync_call());
         let requests = method.iter().map(|method| method.request(&ident_str));
@@ -468,7 +548,15 @@ impl Protocol {
 
         let token = quote! {
             pub mod #core_mod {
+                #vis const PROTOCOL_ID: u128 = #id;
+                #vis const PROTOCOL_VERSION: u32 = #version;
                 #(#constants;)*
+
+                #vis const PROTOCOL_INFO: solvent_rpc::handshake::Info = solvent_rpc::handshake::Info {
+                    id: PROTOCOL_ID,
+                    version: PROTOCOL_VERSION,
+                    methods: &[#(#method_consts,)*],
+                };
             }
 
             #event_def
@@ -493,6 +581,9 @@ impl Protocol {
                 pub struct #ident;
 
                 impl solvent_rpc::Protocol for #ident {
+                    const ID: u128 = #core_mod::PROTOCOL_ID;
+                    const NAME: &'static str = #path;
+
                     type Client = #client;
                     type Server = #server;
 
@@ -509,7 +600,10 @@ impl Protocol {
                 impl #server {
                     pub fn new(channel: solvent_async::ipc::Channel) -> Self {
                         #server {
-                            inner: solvent_rpc::ServerImpl::new(channel),
+                            inner: solvent_rpc::ServerImpl::with_handshake(
+                                channel,
+                                Some(&#core_mod::PROTOCOL_INFO),
+                            ),
                         }
                     }
                 }
@@ -631,6 +725,11 @@ impl Protocol {
                     fn close(self) {
                         self.inner.close()
                     }
+
+                    #[inline]
+                    fn shutdown(self, deadline: solvent::time::Instant) -> solvent_rpc::Shutdown {
+                        self.inner.shutdown(deadline)
+                    }
                 }
 
                 #(#responders)*
@@ -649,6 +748,20 @@ impl Protocol {
                         }
                     }
 
+                    /// Exchange a handshake with the server, returning its
+                    /// version, or `None` if it doesn't reply before `timeout`.
+                    #[inline]
+                    pub async fn handshake(&self, timeout: Duration) -> Result<Option<u32>, solvent_rpc::Error> {
+                        self.inner.handshake(&#core_mod::PROTOCOL_INFO, timeout).await
+                    }
+
+                    /// Whether the server supports the method of the id
+                    /// `method`, which is assumed without a handshake.
+                    #[inline]
+                    pub fn supports(&self, method: usize) -> bool {
+                        self.inner.supports(method)
+                    }
+
                     #(#calls)*
                 }
 
@@ -734,6 +847,20 @@ impl Protocol {
                         }
                     }
 
+                    /// Exchange a handshake with the server, returning its
+                    /// version, or `None` if it doesn't reply before `timeout`.
+                    #[inline]
+                    pub fn handshake(&self, timeout: Duration) -> Result<Option<u32>, solvent_rpc::Error> {
+                        self.inner.handshake(&#core_mod::PROTOCOL_INFO, timeout)
+                    }
+
+                    /// Whether the server supports the method of the id
+                    /// `method`, which is assumed without a handshake.
+                    #[inline]
+                    pub fn supports(&self, method: usize) -> bool {
+                        self.inner.supports(method)
+                    }
+
                     #(#sync_calls)*
                 }
 
@@ -0,0 +1,46 @@
+//! Negative tests of the protocol parser, comparing the rendered diagnostics
+//! of every protocol directory in `tests/ui` with the `.stderr` file next to
+//! it.
+//!
+//! Run with `OVERWRITE=1` to update the expected output.
+
+use std::{fs, path::Path};
+
+#[test]
+fn ui() {
+    let overwrite = std::env::var_os("OVERWRITE").is_some();
+
+    let mut cases = fs::read_dir("tests/ui")
+        .expect("Failed to read the test directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    cases.sort();
+    assert!(!cases.is_empty());
+
+    let mut failed = Vec::new();
+    for case in &cases {
+        let actual = match solvent_rpc_gen::check(case) {
+            Ok(()) => String::from("(no error)\n"),
+            Err(err) => err,
+        };
+        let expected_path = case.with_extension("stderr");
+        if overwrite {
+            fs::write(&expected_path, &actual).expect("Failed to write the expected output");
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if actual != expected {
+            eprintln!("{}: mismatched output", case.display());
+            eprintln!("EXPECTED:\n{expected}\nACTUAL:\n{actual}");
+            failed.push(case.as_path());
+        }
+    }
+    assert!(failed.is_empty(), "UI tests failed: {failed:?}");
+}
+
+#[test]
+fn pass() {
+    let imp = Path::new(env!("CARGO_MANIFEST_DIR")).join("../imp");
+    assert_eq!(solvent_rpc_gen::check(&imp), Ok(()));
+}
@@ -0,0 +1,4 @@
+#[protocol]
+pub trait Proto {
+    async fn ping() -> u32;
+}
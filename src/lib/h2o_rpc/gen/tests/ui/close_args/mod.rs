@@ -0,0 +1,5 @@
+#[protocol]
+pub trait Proto {
+    #[close(now)]
+    fn close();
+}
@@ -0,0 +1,6 @@
+/// A protocol with events.
+#[protocol(Event)]
+#[protocol]
+pub trait Proto {
+    fn ping();
+}
@@ -0,0 +1,4 @@
+#[protocol]
+pub trait Proto {
+    fn ping<T>(value: T);
+}
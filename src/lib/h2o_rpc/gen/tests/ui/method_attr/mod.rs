@@ -0,0 +1,6 @@
+#[protocol]
+pub trait Proto {
+    /// Ping the server.
+    #[inline]
+    fn ping();
+}
@@ -0,0 +1,4 @@
+#[protocol]
+pub trait Unregistered {
+    fn ping();
+}
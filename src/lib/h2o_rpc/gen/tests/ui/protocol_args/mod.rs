@@ -0,0 +1,4 @@
+#[protocol = "event"]
+pub trait Proto {
+    fn ping();
+}
@@ -0,0 +1,4 @@
+#[protocol]
+pub trait Proto {
+    fn supports(method: usize) -> bool;
+}
@@ -0,0 +1,5 @@
+#[protocol]
+#[version = 2]
+pub trait Proto {
+    fn ping();
+}
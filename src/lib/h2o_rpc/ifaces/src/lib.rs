@@ -0,0 +1,26 @@
+//! All the RPC protocols of the system, along with the registry of their
+//! globally unique ids.
+//!
+//! The ids are allocated in `h2o_rpc/imp/registry.txt`, and the wire formats
+//! are pinned down by the golden tests of this crate.
+
+#![no_std]
+
+pub use solvent_rpc::{
+    bench, config, core as common, ddk as device, display, input, io, loader, logging, metrics,
+    supervisor, watchdog, PROTOCOLS,
+};
+
+/// Get the id of a protocol by its path, e.g. `io::file::File`.
+pub fn id_of(name: &str) -> Option<u128> {
+    PROTOCOLS
+        .iter()
+        .find_map(|&(n, id)| (n == name).then_some(id))
+}
+
+/// Get the path of a protocol by its id.
+pub fn name_of(id: u128) -> Option<&'static str> {
+    PROTOCOLS
+        .iter()
+        .find_map(|&(name, i)| (i == id).then_some(name))
+}
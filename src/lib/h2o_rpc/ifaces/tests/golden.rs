@@ -0,0 +1,179 @@
+//! Golden tests of the protocol wire formats.
+//!
+//! Every value here is part of the wire format shared by separately built
+//! clients and servers. If one of them fails, the change breaks compatibility
+//! with existing peers: allocate a new protocol instead of updating the value.
+
+use std::{collections::HashSet, ffi::CString};
+
+use solvent::ipc::Packet;
+use solvent_rpc_ifaces::{common, device, io, loader, PROTOCOLS};
+
+#[test]
+fn protocol_ids() {
+    let golden: &[(&str, u128)] = &[
+        ("bench::Echo", 0x535a4247_0998_4db3_a61b_e61d9182105b),
+        ("config::Config", 0x40989155_74d2_4fba_b2d5_441d4e1e397c),
+        ("core::Cloneable", 0xa648da85_0896_4fa9_a13a_736df35c39e8),
+        ("core::Closeable", 0xd98b83f2_b01f_4ab7_b82a_d844d09e74b0),
+        ("ddk::block::Block", 0xda4f5fe5_46b9_40a7_b772_aa4132b5abc3),
+        ("ddk::bus::Bus", 0xf626a663_b9ec_4e19_b270_8f3269be7ece),
+        (
+            "ddk::driver::Driver",
+            0x2296e2b3_d747_4ad5_9c51_19fcd01a56db,
+        ),
+        ("ddk::power::Power", 0x7b146a2a_3cfb_4b08_93dd_453bf764280a),
+        ("ddk::power::System", 0xf3fc563e_7907_4922_99dd_fb4dd6b26468),
+        ("display::Display", 0xbc694b3d_434c_4d9a_babd_be94ae47c78c),
+        ("input::Device", 0x88cdb678_525e_41d0_be96_92f450dc6b91),
+        ("input::Manager", 0xdde77866_3b1d_46d3_84a2_6307c856937b),
+        ("io::dir::Directory", 0x63f20ac2_38a9_4d6c_9495_586df391756a),
+        ("io::entry::Entry", 0x66095ca8_742b_48d9_90a1_6ac12f0e6ba2),
+        ("io::file::File", 0xb2d0bc07_74d8_4486_b347_375be94a89b2),
+        ("loader::Loader", 0x5084b208_ba5f_49f6_aa47_bb7047aedc51),
+        ("logging::Log", 0x17b1e4a3_4aad_47f9_8d29_1784562ae4d8),
+        ("metrics::Metrics", 0x8b3c0881_3f7a_410d_afa5_6a97af386489),
+        (
+            "supervisor::Supervisor",
+            0x1f733276_5ca7_4711_893f_016ef4ef17f3,
+        ),
+        ("watchdog::Watchdog", 0x999f8145_d44f_4a8d_956e_df88266c1d55),
+    ];
+    assert_eq!(PROTOCOLS, golden);
+
+    assert_eq!(common::cloneable::PROTOCOL_ID, golden[2].1);
+    assert_eq!(common::closeable::PROTOCOL_ID, golden[3].1);
+    assert_eq!(device::driver::driver::PROTOCOL_ID, golden[6].1);
+    assert_eq!(io::dir::directory::PROTOCOL_ID, golden[12].1);
+    assert_eq!(io::entry::entry::PROTOCOL_ID, golden[13].1);
+    assert_eq!(io::file::file::PROTOCOL_ID, golden[14].1);
+    assert_eq!(loader::loader::PROTOCOL_ID, golden[15].1);
+}
+
+#[test]
+fn unique_protocol_ids() {
+    let ids = PROTOCOLS.iter().map(|&(_, id)| id).collect::<HashSet<_>>();
+    assert_eq!(ids.len(), PROTOCOLS.len());
+    for &(name, id) in PROTOCOLS {
+        assert_eq!(solvent_rpc_ifaces::id_of(name), Some(id));
+        assert_eq!(solvent_rpc_ifaces::name_of(id), Some(name));
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn protocol_impls() {
+    use solvent_rpc::Protocol;
+
+    fn check<P: Protocol>() {
+        assert_eq!(solvent_rpc_ifaces::id_of(P::NAME), Some(P::ID));
+    }
+    check::<io::file::File>();
+    check::<io::dir::Directory>();
+    check::<loader::Loader>();
+}
+
+#[test]
+fn method_ids() {
+    use common::{cloneable, closeable};
+    use io::{dir::directory, entry::entry, file::file};
+    use loader::loader;
+
+    let golden = [
+        (cloneable::CLONE_CONNECTION, 0x3231306562383134),
+        (closeable::CLOSE_CONNECTION, 0x3636323861323132),
+        (entry::OPEN, 0x3836373564386135),
+        (entry::METADATA, 0x3762666233646236),
+        (file::LOCK, 0x3030393536353430),
+        (file::FLUSH, 0x6139643365326239),
+        (file::READ, 0x3264336235346366),
+        (file::WRITE, 0x3531633338343066),
+        (file::SEEK, 0x3433343663653132),
+        (file::READ_AT, 0x3765373537393331),
+        (file::WRITE_AT, 0x6635333361386637),
+        (file::RESIZE, 0x3637363630353262),
+        (file::PHYS, 0x6631393738353361),
+        (directory::NEXT_DIRENT, 0x6663633862646236),
+        (directory::EVENT_TOKEN, 0x3933366336316632),
+        (directory::RENAME, 0x3439343038636661),
+        (directory::LINK, 0x3239633466613235),
+        (directory::UNLINK, 0x3963643239623432),
+        (loader::GET_OBJECT, 0x3137656562633339),
+    ];
+    for (index, (id, expected)) in golden.into_iter().enumerate() {
+        assert_eq!(id, expected, "method #{index} changed its id");
+    }
+
+    // Inherited methods keep the ids of their original protocols.
+    assert_eq!(file::OPEN, entry::OPEN);
+    assert_eq!(directory::CLONE_CONNECTION, cloneable::CLONE_CONNECTION);
+    assert_eq!(
+        device::driver::driver::CLOSE_CONNECTION,
+        closeable::CLOSE_CONNECTION
+    );
+}
+
+#[test]
+fn request_format() {
+    let mut packet = Packet::default();
+    let path = vec![CString::new("libc.so").unwrap()];
+    solvent_rpc::packet::serialize(loader::loader::GET_OBJECT, (path,), &mut packet)
+        .expect("Failed to serialize the request");
+    #[rustfmt::skip]
+    let golden = [
+        0x91, 0x03, 0x7c, 0xfb, 0x84, 0xac, 0x00, 0x00,
+        0x39, 0x33, 0x63, 0x62, 0x65, 0x65, 0x37, 0x31,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x6c, 0x69, 0x62, 0x63, 0x2e, 0x73, 0x6f, 0x00,
+    ];
+    assert_eq!(packet.buffer, golden);
+    assert!(packet.handles.is_empty());
+
+    solvent_rpc::packet::serialize(
+        io::file::file::READ_AT,
+        (0x1000usize, 0x200usize),
+        &mut packet,
+    )
+    .expect("Failed to serialize the request");
+    #[rustfmt::skip]
+    let golden = [
+        0x91, 0x03, 0x7c, 0xfb, 0x84, 0xac, 0x00, 0x00,
+        0x31, 0x33, 0x39, 0x37, 0x35, 0x37, 0x65, 0x37,
+        0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    assert_eq!(packet.buffer, golden);
+    assert!(packet.handles.is_empty());
+}
+
+#[test]
+fn handshake() {
+    use solvent_rpc::{handshake::*, packet::deserialize_metadata, Error};
+
+    assert_eq!(HANDSHAKE_ID, 0);
+    let info = &io::file::file::PROTOCOL_INFO;
+    let hello = info.hello().expect("Failed to create the handshake");
+    let reply = |server: &Info| {
+        let (m, de) = deserialize_metadata(&hello).expect("Failed to parse the handshake");
+        assert_eq!(m, HANDSHAKE_ID);
+        server.reply(de).expect("Failed to reply to the handshake")
+    };
+
+    let peer = info.peer(&reply(info)).expect("Failed to parse the reply");
+    assert!(info.methods.iter().all(|&m| peer.supports(m)));
+
+    // A server of an older revision without the first method.
+    let old = Info {
+        id: info.id,
+        version: info.version,
+        methods: &info.methods[1..],
+    };
+    let peer = info.peer(&reply(&old)).expect("Failed to parse the reply");
+    assert!(!peer.supports(info.methods[0]));
+    assert!(info.methods[1..].iter().all(|&m| peer.supports(m)));
+
+    let other = &loader::loader::PROTOCOL_INFO;
+    let res = info.peer(&reply(other));
+    assert!(matches!(res, Err(Error::ProtocolMismatch { .. })));
+}
@@ -0,0 +1,10 @@
+use alloc::vec::Vec;
+
+use crate as solvent_rpc;
+
+/// The echo service measured by the IPC benchmarks.
+#[protocol]
+pub trait Echo {
+    /// Send `data` back.
+    fn echo(data: Vec<u8>) -> Vec<u8>;
+}
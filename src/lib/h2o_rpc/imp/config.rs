@@ -0,0 +1,129 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core as std;
+
+use solvent_rpc_core::SerdePacket;
+use thiserror_impl::Error;
+
+use crate as solvent_rpc;
+use crate::thiserror;
+
+/// The maximum length of a key in bytes.
+pub const MAX_KEY_LEN: usize = 255;
+
+#[derive(SerdePacket, Debug, Error)]
+pub enum Error {
+    #[error("key not found: {0:?}")]
+    NotFound(String),
+
+    #[error("invalid key: {0:?}")]
+    InvalidKey(String),
+
+    #[error("the value of {0:?} is of another type")]
+    TypeMismatch(String),
+
+    #[error("failed to persist the configuration: {0}")]
+    Storage(String),
+
+    #[error("RPC error: {0}")]
+    RpcError(String),
+}
+
+impl From<solvent_rpc_core::Error> for Error {
+    fn from(value: solvent_rpc_core::Error) -> Self {
+        Error::RpcError(value.to_string())
+    }
+}
+
+/// Keys are non-empty paths of ASCII letters, digits, `_` and `-` separated
+/// by dots, such as `display.mode`.
+pub fn is_valid_key(key: &str) -> bool {
+    key.len() <= MAX_KEY_LEN
+        && key.split('.').all(|part| {
+            !part.is_empty()
+                && part
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+        })
+}
+
+/// Whether `key` is `prefix` itself or under it, where the empty prefix
+/// covers all the keys.
+pub fn is_under(key: &str, prefix: &str) -> bool {
+    prefix.is_empty()
+        || key
+            .strip_prefix(prefix)
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+#[derive(SerdePacket, Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+macro_rules! impl_value {
+    ($ty:ty, $variant:ident) => {
+        impl From<$ty> for Value {
+            #[inline]
+            fn from(value: $ty) -> Self {
+                Value::$variant(value)
+            }
+        }
+
+        impl TryFrom<Value> for $ty {
+            type Error = Value;
+
+            #[inline]
+            fn try_from(value: Value) -> Result<Self, Value> {
+                match value {
+                    Value::$variant(value) => Ok(value),
+                    value => Err(value),
+                }
+            }
+        }
+    };
+}
+impl_value!(bool, Bool);
+impl_value!(i64, Int);
+impl_value!(String, String);
+impl_value!(Vec<u8>, Bytes);
+
+impl From<&str> for Value {
+    #[inline]
+    fn from(value: &str) -> Self {
+        Value::String(value.into())
+    }
+}
+
+/// A change of a key under the watched prefixes.
+#[derive(SerdePacket, Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Set { key: String, value: Value },
+    Removed { key: String },
+}
+
+/// The configuration store of the system, served by `configd`.
+///
+/// Every connection receives a [`Change`] event whenever a key under the
+/// prefixes it watches is set or removed, including by itself.
+#[protocol(Change)]
+pub trait Config {
+    fn get(key: String) -> Result<Value, Error>;
+
+    /// Get all the keys under `prefix` and their values in order.
+    fn list(prefix: String) -> Vec<(String, Value)>;
+
+    fn set(key: String, value: Value) -> Result<(), Error>;
+
+    fn remove(key: String) -> Result<(), Error>;
+
+    /// Receive the changes of the keys under `prefix` on this connection.
+    fn watch(prefix: String) -> Result<(), Error>;
+
+    fn unwatch(prefix: String);
+}
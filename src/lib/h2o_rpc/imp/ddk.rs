@@ -1,3 +1,6 @@
+pub mod block;
+pub mod bus;
 pub mod driver;
+pub mod power;
 
 use crate as solvent_rpc;
@@ -0,0 +1,30 @@
+use alloc::vec::Vec;
+
+use solvent::error::Error;
+use solvent_rpc_core::SerdePacket;
+
+use crate as solvent_rpc;
+
+#[derive(SerdePacket, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// The size of a logical block in bytes.
+    pub block_size: usize,
+    pub block_count: u64,
+    pub read_only: bool,
+}
+
+/// A block device such as a disk.
+#[protocol]
+pub trait Block: super::driver::Driver {
+    fn info() -> BlockInfo;
+
+    /// Read `count` blocks starting from `block`.
+    fn read(block: u64, count: usize) -> Result<Vec<u8>, Error>;
+
+    /// Write `buf` to the blocks starting from `block`. The length of `buf`
+    /// must be a multiple of the block size.
+    fn write(block: u64, buf: Vec<u8>) -> Result<(), Error>;
+
+    /// Write the cached data back to the device.
+    fn flush() -> Result<(), Error>;
+}
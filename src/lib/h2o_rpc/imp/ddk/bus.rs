@@ -0,0 +1,51 @@
+use alloc::{string::String, vec::Vec};
+
+use solvent::{dev::Interrupt, error::Error, ipc::Channel, mem::Phys};
+use solvent_rpc_core::SerdePacket;
+
+use crate as solvent_rpc;
+
+/// The information of a device on a bus.
+#[derive(SerdePacket, Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub name: String,
+    /// The address of the device on the bus, such as the BDF of a PCI device.
+    pub addr: u64,
+    pub vendor: u16,
+    pub device: u16,
+    /// The class, the subclass and the programming interface of the device.
+    pub class: [u8; 3],
+}
+
+/// A physically contiguous region of memory for DMA.
+#[derive(SerdePacket, Debug)]
+pub struct DmaRegion {
+    pub phys: Phys,
+    /// The address of the region seen by the devices on the bus.
+    pub addr: u64,
+}
+
+/// The connection from a driver to the bus of its devices.
+#[protocol]
+pub trait Bus: super::driver::Driver {
+    fn devices() -> Vec<DeviceInfo>;
+
+    /// Get the memory-mapped I/O region `bar` of the device at `addr`.
+    fn mmio(addr: u64, bar: usize) -> Result<Phys, Error>;
+
+    /// Get the interrupt `index` of the device at `addr`.
+    fn interrupt(addr: u64, index: usize) -> Result<Interrupt, Error>;
+
+    fn dma(size: usize) -> Result<DmaRegion, Error>;
+
+    /// Publish a device node at `dev/<name>` in the device manager. `node` is
+    /// the client end of an entry connection to the node.
+    ///
+    /// Nodes named `input/<name>` must serve [`crate::input::Device`], and are
+    /// added to the input manager as well.
+    fn publish(name: String, node: Channel) -> Result<(), Error>;
+
+    /// Register the client end of a connection to the [`super::power::Power`]
+    /// of the driver, which is suspended before the system sleeps.
+    fn register_power(power: Channel) -> Result<(), Error>;
+}
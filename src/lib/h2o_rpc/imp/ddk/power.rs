@@ -0,0 +1,23 @@
+use solvent::error::Error;
+
+use crate as solvent_rpc;
+
+/// The power management of the devices of a driver, registered with
+/// [`super::bus::Bus::register_power`].
+#[protocol]
+pub trait Power {
+    /// Stop the devices and save their states before the system sleeps.
+    fn suspend() -> Result<(), Error>;
+
+    /// Restore the devices after the system wakes up.
+    fn resume() -> Result<(), Error>;
+}
+
+/// The power control of the system, served by the device manager at
+/// `dev/power`.
+#[protocol]
+pub trait System {
+    /// Suspend the drivers and put the system into S3 (suspend-to-RAM), and
+    /// return after it wakes up and the drivers are resumed.
+    fn suspend() -> Result<(), Error>;
+}
@@ -0,0 +1,89 @@
+use solvent::{error::Error, mem::Phys};
+use solvent_rpc_core::SerdePacket;
+
+use crate as solvent_rpc;
+
+/// The order of the bytes in a 32-bit pixel, the last of which is unused.
+#[derive(SerdePacket, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelFormat {
+    Bgrx,
+    Rgbx,
+}
+
+#[derive(SerdePacket, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Mode {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+}
+
+/// A rectangle in pixels, which may lie partially outside the screen.
+#[derive(SerdePacket, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The overlapping part of the two rectangles, which is empty if there's
+    /// none.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x as i64 + self.width as i64).min(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).min(other.y as i64 + other.height as i64);
+        Rect {
+            x,
+            y,
+            width: (right - x as i64).max(0) as u32,
+            height: (bottom - y as i64).max(0) as u32,
+        }
+    }
+
+    #[inline]
+    pub fn offset(&self, dx: i32, dy: i32) -> Rect {
+        Rect {
+            x: self.x.saturating_add(dx),
+            y: self.y.saturating_add(dy),
+            ..*self
+        }
+    }
+}
+
+/// A frame is scanned out, sent only to the connections subscribing to it.
+#[derive(SerdePacket, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Vsync {
+    /// The number of frames since the display started.
+    pub seq: u64,
+    pub timestamp_ns: u64,
+}
+
+/// A display, on which every connection owns at most one surface.
+///
+/// The surfaces are stacked in the order they are raised, and composed into
+/// the screen only where they are damaged.
+#[protocol(Vsync)]
+pub trait Display {
+    fn mode() -> Mode;
+
+    /// Place the surface of this connection at `rect`, creating it if absent,
+    /// and get its buffer of `width * height` pixels in the format of the
+    /// display. The old contents are lost if the size changes.
+    fn configure(rect: Rect) -> Result<Phys, Error>;
+
+    /// Compose the damaged part of the surface, relative to its origin, into
+    /// the screen on the next frame.
+    fn present(damage: Rect) -> Result<(), Error>;
+
+    /// Move the surface to the top of the stack.
+    fn raise() -> Result<(), Error>;
+
+    fn subscribe_vsync(enable: bool);
+}
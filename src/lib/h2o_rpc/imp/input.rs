@@ -0,0 +1,134 @@
+use alloc::{string::String, vec::Vec};
+
+use solvent::{error::Error, ipc::Channel};
+use solvent_rpc_core::SerdePacket;
+
+use crate as solvent_rpc;
+
+#[derive(SerdePacket, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeviceKind {
+    Keyboard,
+    /// A relative pointing device such as a mouse.
+    Pointer,
+}
+
+#[derive(SerdePacket, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputEvent {
+    /// A key is pressed or released. `usage` is the usage ID of the key in the
+    /// keyboard/keypad page of the HID usage tables.
+    Key {
+        usage: u16,
+        pressed: bool,
+    },
+    /// The pointer moves by the relative offsets.
+    Motion {
+        dx: i32,
+        dy: i32,
+    },
+    /// A pointer button is pressed or released, numbered from 0 for the
+    /// primary button.
+    Button {
+        button: u8,
+        pressed: bool,
+    },
+    Wheel {
+        delta: i32,
+    },
+}
+
+/// The id of the events carrying the input of a device, whose bodies are
+/// `Vec<InputEvent>` serialized with [`solvent_rpc::packet::serialize`].
+pub const INPUT_EVENT: usize = 0x1a9b_7e00_c4d2_0001;
+
+/// An input device, which sends every connection its input in
+/// [`INPUT_EVENT`]s.
+#[protocol]
+pub trait Device: crate::ddk::driver::Driver {
+    fn kind() -> DeviceKind;
+}
+
+bitflags::bitflags! {
+    /// The modifier keys held or locked when a key event is produced.
+    #[derive(Default, SerdePacket)]
+    pub struct Modifiers: u32 {
+        const SHIFT = 0b0000_0001;
+        const CONTROL = 0b0000_0010;
+        const ALT = 0b0000_0100;
+        const META = 0b0000_1000;
+        const CAPS_LOCK = 0b0001_0000;
+    }
+}
+
+#[derive(SerdePacket, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyState {
+    Released,
+    Pressed,
+    /// The key is held long enough to repeat.
+    Repeated,
+}
+
+/// The keyboard layouts translating keys into text.
+#[derive(SerdePacket, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Layout {
+    Us,
+    Dvorak,
+}
+
+#[derive(SerdePacket, Debug, Clone, PartialEq, Eq)]
+pub struct DeviceDesc {
+    pub id: u64,
+    pub name: String,
+    pub kind: DeviceKind,
+}
+
+/// The input delivered by the input manager.
+#[derive(SerdePacket, Debug, Clone, PartialEq, Eq)]
+pub enum FocusEvent {
+    /// A key event, where `text` is the translation of the key in the current
+    /// layout, or empty if it produces no text.
+    Key {
+        usage: u16,
+        state: KeyState,
+        modifiers: Modifiers,
+        text: String,
+    },
+    Motion {
+        dx: i32,
+        dy: i32,
+    },
+    Button {
+        button: u8,
+        pressed: bool,
+    },
+    Wheel {
+        delta: i32,
+    },
+    DeviceAdded(DeviceDesc),
+    DeviceRemoved {
+        id: u64,
+    },
+}
+
+/// The input manager, aggregating the input devices of the system.
+///
+/// The input of all the devices is sent as [`FocusEvent`]s to the focused
+/// connection only, while the additions and removals of devices are sent to
+/// all the connections.
+#[protocol(FocusEvent)]
+pub trait Manager {
+    /// Add an input device, given a connection to its [`Device`] node. The
+    /// device is removed when the connection is closed.
+    fn add_device(name: String, device: Channel) -> Result<u64, Error>;
+
+    fn devices() -> Vec<DeviceDesc>;
+
+    /// Move the focus to this connection until another connection is focused,
+    /// after which the focus returns here when that connection is closed.
+    fn focus();
+
+    fn set_layout(layout: Layout);
+
+    /// Set the delay before a held key repeats and the interval between the
+    /// repeats, or disable the repeats if `interval_ms` is 0.
+    fn set_repeat(delay_ms: u64, interval_ms: u64);
+}
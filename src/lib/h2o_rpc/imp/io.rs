@@ -8,7 +8,7 @@ use alloc::{
 };
 use core as std;
 
-use solvent::error::Error as RawError;
+use solvent::error::{Error as RawError, ErrorKind};
 #[cfg(feature = "std")]
 use solvent_core::{
     io::{RawStream, SeekFrom},
@@ -63,6 +63,12 @@ pub enum Error {
     #[error("Directory not empty, thus cannot be directly unlinked")]
     DirNotEmpty,
 
+    #[error("byte quota of the directory subtree exceeded")]
+    ByteQuotaExceeded,
+
+    #[error("inode quota of the directory subtree exceeded")]
+    InodeQuotaExceeded,
+
     #[error("RPC error: {0}")]
     RpcError(String),
 
@@ -73,6 +79,40 @@ pub enum Error {
     Other(#[source] RawError),
 }
 
+#[cfg(feature = "std")]
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::NotFound => ErrorKind::NotFound,
+            Error::Exists => ErrorKind::AlreadyExists,
+            Error::IterEnd => ErrorKind::UnexpectedEof,
+            Error::WouldBlock => ErrorKind::WouldBlock,
+            Error::LocalFs(_) => ErrorKind::Unsupported,
+            Error::InvalidType(FileType::Directory) => ErrorKind::IsADirectory,
+            Error::InvalidType(FileType::File) => ErrorKind::NotADirectory,
+            Error::InvalidType(FileType::RpcNode)
+            | Error::InvalidSeek
+            | Error::InvalidPath(_)
+            | Error::InvalidNameLength(_)
+            | Error::IsAncestorOrEquals { .. } => ErrorKind::InvalidInput,
+            Error::PermissionDenied(_) => ErrorKind::PermissionDenied,
+            Error::DirNotEmpty => ErrorKind::ResourceBusy,
+            Error::ByteQuotaExceeded | Error::InodeQuotaExceeded => ErrorKind::StorageFull,
+            Error::RpcError(_) => ErrorKind::Other,
+            Error::InvalidData(_) => ErrorKind::InvalidData,
+            Error::Other(err) => err.kind(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&Error> for ErrorKind {
+    #[inline]
+    fn from(value: &Error) -> Self {
+        value.kind()
+    }
+}
+
 #[cfg(feature = "std")]
 impl From<solvent_async::io::Error> for Error {
     fn from(value: solvent_async::io::Error) -> Self {
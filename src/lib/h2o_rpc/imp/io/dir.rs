@@ -24,6 +24,16 @@ pub struct DirEntry {
     pub metadata: Metadata,
 }
 
+/// The usage of the quota a directory is under, where limits of `usize::MAX`
+/// are unlimited.
+#[derive(SerdePacket, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub bytes: usize,
+    pub byte_limit: usize,
+    pub inodes: usize,
+    pub inode_limit: usize,
+}
+
 #[protocol(EventFlags)]
 pub trait Directory: entry::Entry {
     fn next_dirent(last: Option<String>) -> Result<DirEntry, Error>;
@@ -35,4 +45,6 @@ pub trait Directory: entry::Entry {
     fn link(src: String, dst_parent: Handle, dst: String) -> Result<(), Error>;
 
     fn unlink(name: String, expect_dir: bool) -> Result<(), Error>;
+
+    fn usage() -> Result<Usage, Error>;
 }
@@ -22,3 +22,61 @@ pub trait Entry: crate::core::Cloneable + crate::core::Closeable {
 
     fn metadata() -> Result<Metadata, Error>;
 }
+
+/// Serve the requests of an entry connection until it's closed.
+///
+/// The requests of opening, reading the metadata and cloning the connection
+/// are handled by `open`, `metadata` and `clone` respectively, so that simple
+/// nodes needn't write the request loop themselves.
+#[cfg(feature = "std")]
+pub async fn serve_entry_with<O, M, C>(
+    server: EntryServer,
+    mut open: O,
+    mut metadata: M,
+    mut clone: C,
+) where
+    O: FnMut(PathBuf, OpenOptions, Channel) -> Result<(), Error>,
+    M: FnMut() -> Result<Metadata, Error>,
+    C: FnMut(Channel),
+{
+    use futures::StreamExt;
+    use solvent_rpc::Server;
+
+    let (mut stream, _) = server.serve();
+
+    while let Some(request) = stream.next().await {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                log::warn!("RPC receive error: {err}");
+                continue;
+            }
+        };
+
+        let res = match request {
+            EntryRequest::CloseConnection { responder } => {
+                responder.close();
+                break;
+            }
+            EntryRequest::Open {
+                path,
+                options,
+                conn,
+                responder,
+            } => responder.send(open(path, options, conn)),
+            EntryRequest::CloneConnection { conn, responder } => {
+                clone(conn);
+                responder.send(())
+            }
+            EntryRequest::Metadata { responder } => responder.send(metadata()),
+            EntryRequest::Unknown(_) => {
+                log::warn!("unknown request received");
+                continue;
+            }
+        };
+
+        if let Err(err) = res {
+            log::warn!("RPC send error: {err}")
+        }
+    }
+}
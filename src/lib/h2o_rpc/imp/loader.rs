@@ -1,12 +1,52 @@
+use alloc::ffi::CString;
+
+use solvent::mem::Phys;
+use solvent_rpc_core::SerdePacket;
+
 use crate as solvent_rpc;
 cfg_if::cfg_if! {
     if #[cfg(feature = "std")] {
-        use alloc::{ffi::CString, vec::Vec};
+        use alloc::vec::Vec;
+
+        use solvent::error::Error;
+    }
+}
+
+/// The length of the content hashes of the objects.
+pub const HASH_LEN: usize = 32;
+
+/// A query of `Loader::get_objects`.
+#[derive(SerdePacket, Debug, Clone, PartialEq, Eq)]
+pub struct ObjectQuery {
+    /// The path of the object, which is looked up in the search paths if it
+    /// contains no slash.
+    pub path: CString,
+    /// The content hash of the copy cached by the client, or all zeros if
+    /// there's none.
+    pub cached: [u8; HASH_LEN],
+}
 
-        use solvent::prelude::Phys;
+impl From<CString> for ObjectQuery {
+    #[inline]
+    fn from(path: CString) -> Self {
+        ObjectQuery {
+            path,
+            cached: [0; HASH_LEN],
+        }
     }
 }
 
+/// An object found by `Loader::get_objects`.
+#[derive(SerdePacket, Debug)]
+pub struct ObjectInfo {
+    /// The physical object of the content, or `None` if the cached copy in
+    /// the query is still valid.
+    pub phys: Option<Phys>,
+    /// The content hash of the object, or all zeros if the provider doesn't
+    /// know it, in which case the object is always sent.
+    pub hash: [u8; HASH_LEN],
+}
+
 /// The loader service interface.
 #[protocol]
 pub trait Loader {
@@ -21,6 +61,19 @@ pub trait Loader {
     ///
     /// If one of the acquired objects is not found, then its index is returned.
     fn get_object(path: Vec<CString>) -> Result<Vec<Phys>, usize>;
+
+    /// Acquire a set of objects, looking up the paths without slashes in
+    /// `search_paths` in order, or in the default ones of the provider if
+    /// it's empty.
+    ///
+    /// # Returns
+    ///
+    /// The result of every query respectively, so a missing object doesn't
+    /// fail the others.
+    fn get_objects(
+        search_paths: Vec<CString>,
+        queries: Vec<ObjectQuery>,
+    ) -> Vec<Result<ObjectInfo, Error>>;
 }
 
 pub use loader::*;
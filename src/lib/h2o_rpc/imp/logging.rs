@@ -0,0 +1,15 @@
+use alloc::string::String;
+
+use solvent::{error::Error, ipc::Event, mem::Phys};
+
+use crate as solvent_rpc;
+
+/// The shared-memory log transport of `logd`.
+///
+/// See `dbglog::ring` for the layout of the rings.
+#[protocol]
+pub trait Log {
+    /// Allocate a ring for the task `name`, returning the ring to be mapped
+    /// writable and the doorbell to be notified when it should be drained.
+    fn attach(name: String) -> Result<(Phys, Event), Error>;
+}
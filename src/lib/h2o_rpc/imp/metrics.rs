@@ -0,0 +1,64 @@
+use alloc::{string::String, vec::Vec};
+
+use solvent_rpc_core::SerdePacket;
+
+use crate as solvent_rpc;
+
+/// The statistics of the calls of a protocol method in a process.
+#[derive(SerdePacket, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MethodStats {
+    /// Whether the calls are served by the process instead of made by it.
+    pub server: bool,
+    pub method: usize,
+    pub calls: u64,
+    pub errors: u64,
+    pub total_latency_ns: u64,
+    pub max_latency_ns: u64,
+}
+
+#[derive(SerdePacket, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MetricKind {
+    /// A monotonic value, to which the pushed samples are added.
+    Counter,
+    /// An instantaneous value, replaced by the pushed samples.
+    Gauge,
+}
+
+#[derive(SerdePacket, Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub name: String,
+    pub kind: MetricKind,
+    pub value: u64,
+}
+
+/// All the metrics of a source.
+#[derive(SerdePacket, Debug, Default, Clone)]
+pub struct Snapshot {
+    pub source: String,
+    pub samples: Vec<Sample>,
+    pub rpc: Vec<MethodStats>,
+}
+
+/// The id of the events carrying the periodic reports of the subscriptions,
+/// whose bodies are `Vec<Snapshot>` serialized with
+/// [`solvent_rpc::packet::serialize`].
+pub const REPORT_EVENT: usize = 0x5e7a_11c5_2b0f_0001;
+
+/// The metrics collection interface, consumed by `ocstat`.
+#[protocol]
+pub trait Metrics {
+    /// Publish the RPC call statistics of the caller process, identified by
+    /// `name`.
+    fn report(name: String, stats: Vec<MethodStats>);
+
+    /// Push the samples of the metrics of `source`.
+    fn push(source: String, samples: Vec<Sample>);
+
+    /// Query the snapshots of all the sources.
+    fn query() -> Vec<Snapshot>;
+
+    /// Receive the snapshots of all the sources in a [`REPORT_EVENT`] every
+    /// `interval_ms` milliseconds on this connection, or stop receiving them
+    /// if it's 0.
+    fn subscribe(interval_ms: u64);
+}
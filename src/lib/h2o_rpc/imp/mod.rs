@@ -1,4 +1,12 @@
+pub mod bench;
+pub mod config;
 pub mod core;
 pub mod ddk;
+pub mod display;
+pub mod input;
 pub mod io;
 pub mod loader;
+pub mod logging;
+pub mod metrics;
+pub mod supervisor;
+pub mod watchdog;
@@ -0,0 +1,39 @@
+use alloc::{string::String, vec::Vec};
+
+use solvent_rpc_core::SerdePacket;
+
+use crate as solvent_rpc;
+
+#[derive(SerdePacket, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ServiceState {
+    Running,
+    /// Waiting for the backoff to elapse before the next restart.
+    BackingOff,
+    /// Restarted too many times in a short period, and waiting for a longer
+    /// backoff before the next restart.
+    CrashLooping,
+    /// Exited successfully, and not restarted.
+    Stopped,
+}
+
+/// The status of a service started by the program manager.
+#[derive(SerdePacket, Debug, Clone, PartialEq, Eq)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub state: ServiceState,
+    /// The number of restarts since the program manager started.
+    pub restarts: u64,
+    /// The exit code of the last run, if any.
+    pub last_exit: Option<usize>,
+    /// The current backoff before the next restart, or 0 if running.
+    pub backoff_ms: u64,
+    /// The path of the latest diagnostics bundle, if any.
+    pub diagnostics: Option<String>,
+}
+
+/// The control interface of the program manager, consumed by `ocsh`.
+#[protocol]
+pub trait Supervisor {
+    /// Query the status of all the supervised services.
+    fn services() -> Vec<ServiceStatus>;
+}
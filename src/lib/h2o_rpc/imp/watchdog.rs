@@ -0,0 +1,12 @@
+use alloc::string::String;
+
+use crate as solvent_rpc;
+
+/// The keepalive interface of `watchdogd`, which stops petting the kernel
+/// watchdog once one of its clients misses the deadline.
+#[protocol]
+pub trait Watchdog {
+    /// Register the client `name`, or refresh its deadline, which is
+    /// `timeout_ms` milliseconds later. A timeout of 0 unregisters it.
+    fn keepalive(name: String, timeout_ms: u64);
+}
@@ -9,24 +9,33 @@ use syn::{
 
 pub(crate) fn derive(input: TokenStream) -> Result<TokenStream> {
     let input = syn::parse::<DeriveInput>(input)?;
+    if input.generics.lt_token.is_some() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "`SerdePacket` doesn't support generic types",
+        ));
+    }
     Ok(match input.data {
         syn::Data::Struct(ref s) => derive_struct(&input.ident, &s.fields),
         syn::Data::Enum(ref e) => derive_enum(&input.ident, &e.variants),
-        syn::Data::Union(_) => Err(Error::new_spanned(
-            input,
+        syn::Data::Union(ref u) => Err(Error::new(
+            u.union_token.span,
             "`SerdePacket` doesn't support unions",
         ))?,
     })
 }
 
-fn derive_fields(name: &Ident, fields: &Fields) -> [TokenStream2; 3] {
+fn field_ident(index: usize, ident: &Option<Ident>) -> Ident {
+    match ident {
+        Some(ident) => ident.clone(),
+        None => Ident::new(&format!("v{index}"), Span::call_site()),
+    }
+}
+
+fn derive_fields(name: &Ident, fields: &Fields) -> [TokenStream2; 5] {
     let pat = fields.iter().enumerate().map(|(index, field)| {
-        if let Some(ref ident) = field.ident {
-            quote!(#ident)
-        } else {
-            let ident = Ident::new(&format!("v{index}"), Span::call_site());
-            quote!(#ident)
-        }
+        let ident = field_ident(index, &field.ident);
+        quote!(#ident)
     });
     let pat = match fields {
         Fields::Named(_) => quote!(#name { #(#pat),* }),
@@ -34,12 +43,8 @@ fn derive_fields(name: &Ident, fields: &Fields) -> [TokenStream2; 3] {
         Fields::Unit => quote!(#name),
     };
     let ser = fields.iter().enumerate().map(|(index, field)| {
-        if let Some(ref ident) = field.ident {
-            quote!(SerdePacket::serialize(#ident, ser)?;)
-        } else {
-            let ident = Ident::new(&format!("v{index}"), Span::call_site());
-            quote!(SerdePacket::serialize(#ident, ser)?;)
-        }
+        let ident = field_ident(index, &field.ident);
+        quote!(SerdePacket::serialize(#ident, ser)?;)
     });
     let de = fields.iter().map(|field| {
         if let Some(ref ident) = field.ident {
@@ -53,13 +58,29 @@ fn derive_fields(name: &Ident, fields: &Fields) -> [TokenStream2; 3] {
         syn::Fields::Unnamed(_) => quote!(#name (#(#de)*)),
         syn::Fields::Unit => quote!(#name),
     };
-    [pat, quote!(#(#ser)*), de]
+    let count = fields.iter().enumerate().map(|(index, field)| {
+        let ident = field_ident(index, &field.ident);
+        quote!(+ SerdePacket::handle_count(#ident))
+    });
+    let has_handles = fields.iter().map(|field| {
+        let ty = &field.ty;
+        quote!(|| <#ty as solvent_rpc::packet::SerdePacket>::HAS_HANDLES)
+    });
+    [
+        pat,
+        quote!(#(#ser)*),
+        de,
+        quote!(0 #(#count)*),
+        quote!(#(#has_handles)*),
+    ]
 }
 
 fn derive_struct(name: &Ident, fields: &Fields) -> TokenStream {
-    let [pat, ser, de] = derive_fields(name, fields);
+    let [pat, ser, de, count, has_handles] = derive_fields(name, fields);
     quote! {
         impl solvent_rpc::packet::SerdePacket for #name {
+            const HAS_HANDLES: bool = false #has_handles;
+
             fn serialize(self, ser: &mut solvent_rpc::packet::Serializer)
                 -> Result<(), solvent_rpc::Error>
             {
@@ -78,6 +99,13 @@ fn derive_struct(name: &Ident, fields: &Fields) -> TokenStream {
                 let ret = #de;
                 Ok(ret)
             }
+
+            fn handle_count(&self) -> usize {
+                #[allow(dead_code)]
+                use solvent_rpc::packet::SerdePacket;
+                let #pat = self;
+                #count
+            }
         }
     }
     .into()
@@ -87,17 +115,29 @@ fn derive_enum(name: &Ident, variants: &Punctuated<Variant, Comma>) -> TokenStre
     let iter = variants.iter().enumerate().map(|(index, var)| {
         let ident = &var.ident;
         let fields = &var.fields;
-        let [pat, ser, de] = derive_fields(ident, fields);
+        let [pat, ser, de, count, has_handles] = derive_fields(ident, fields);
 
         let ser = quote!(#name ::#pat => { SerdePacket::serialize(#index, ser)?; #ser });
         let de = quote!(#index => #name ::#de,);
-        (ser, de)
+        let count = quote!(#name ::#pat => #count,);
+        (ser, de, count, has_handles)
     });
-    let (ser, de): (TokenStream2, TokenStream2) = iter.unzip();
+    let mut ser = TokenStream2::new();
+    let mut de = TokenStream2::new();
+    let mut count = TokenStream2::new();
+    let mut has_handles = TokenStream2::new();
+    for (s, d, c, h) in iter {
+        ser.extend(s);
+        de.extend(d);
+        count.extend(c);
+        has_handles.extend(h);
+    }
 
     let len = variants.len();
     let token_stream = quote! {
         impl solvent_rpc::packet::SerdePacket for #name {
+            const HAS_HANDLES: bool = false #has_handles;
+
             fn serialize(self, ser: &mut solvent_rpc::packet::Serializer)
                 -> Result<(), solvent_rpc::Error>
             {
@@ -123,6 +163,12 @@ fn derive_enum(name: &Ident, variants: &Punctuated<Variant, Comma>) -> TokenStre
                 };
                 Ok(ret)
             }
+
+            fn handle_count(&self) -> usize {
+                #[allow(dead_code)]
+                use solvent_rpc::packet::SerdePacket;
+                match self { #count }
+            }
         }
     };
     token_stream.into()
@@ -0,0 +1,9 @@
+use solvent_rpc_macros::SerdePacket;
+
+#[derive(SerdePacket)]
+enum Either<'a, L, R> {
+    Left(&'a L),
+    Right(R),
+}
+
+fn main() {}
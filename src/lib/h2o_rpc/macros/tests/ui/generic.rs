@@ -0,0 +1,8 @@
+use solvent_rpc_macros::SerdePacket;
+
+#[derive(SerdePacket)]
+struct Wrapper<T> {
+    value: T,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use solvent_rpc_macros::SerdePacket;
+
+#[derive(SerdePacket)]
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
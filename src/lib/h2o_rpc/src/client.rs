@@ -7,15 +7,31 @@ use core::{
     pin::Pin,
     sync::atomic::{AtomicBool, AtomicUsize, Ordering::*},
     task::{Context, Poll, Waker},
+    time::Duration,
 };
 
 use crossbeam::queue::SegQueue;
-use futures::{pin_mut, ready, stream::FusedStream, Stream};
-use solvent::{error::EPIPE, ipc::Packet};
-use solvent_async::ipc::Channel;
+use futures::{
+    future::{select, Either},
+    pin_mut, ready,
+    stream::FusedStream,
+    Stream,
+};
+use solvent::{
+    error::{ErrorKind, ETIME},
+    ipc::Packet,
+    time::Instant,
+};
+use solvent_async::{
+    ipc::Channel,
+    time::{self, Sleep},
+};
 use solvent_core::sync::{Arsc, Mutex};
 
-use crate::Error;
+use crate::{
+    handshake::{Info, Peer},
+    Error,
+};
 
 #[derive(Debug, Clone)]
 pub struct ClientImpl {
@@ -34,6 +50,7 @@ impl ClientImpl {
                 },
                 wakers: Mutex::new(BTreeMap::new()),
                 stop: AtomicBool::new(false),
+                peer: Mutex::new(None),
             }),
         }
     }
@@ -59,12 +76,21 @@ impl ClientImpl {
         })
     }
 
-    pub async fn call(&self, mut packet: Packet) -> Result<Packet, Error> {
+    pub async fn call(&self, packet: Packet) -> Result<Packet, Error> {
+        #[cfg(feature = "metrics")]
+        let span = crate::hook::Span::enter(false, &packet);
+        let res = self.call_raw(packet).await;
+        #[cfg(feature = "metrics")]
+        span.finish(&res);
+        res
+    }
+
+    async fn call_raw(&self, mut packet: Packet) -> Result<Packet, Error> {
         let id = self.inner.register();
         packet.id = NonZeroUsize::new(id);
 
-        match self.inner.channel.send(&mut packet) {
-            Err(EPIPE) => self.inner.receive().await?,
+        match self.inner.channel.send_wait(&mut packet).await {
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => self.inner.receive().await?,
             res => res.map_err(Error::ClientSend)?,
         };
 
@@ -74,6 +100,55 @@ impl ClientImpl {
         }
         .await
     }
+
+    /// Make a call, failing with `ETIME` if the response doesn't arrive before
+    /// `deadline`.
+    ///
+    /// The deadline is tracked by the coarse timing wheel, so it may be missed
+    /// by up to [`solvent_core::time::TICK`].
+    pub async fn call_deadline(&self, packet: Packet, deadline: Instant) -> Result<Packet, Error> {
+        #[cfg(feature = "metrics")]
+        let span = crate::hook::Span::enter(false, &packet);
+        let call = self.call_raw(packet);
+        let timeout = Sleep::new(deadline);
+        pin_mut!(call);
+        let res = match select(call, timeout).await {
+            Either::Left((res, _)) => res,
+            Either::Right(((), _)) => Err(Error::ClientReceive(ETIME)),
+        };
+        #[cfg(feature = "metrics")]
+        span.finish(&res);
+        res
+    }
+
+    #[inline]
+    pub async fn call_timeout(&self, packet: Packet, timeout: Duration) -> Result<Packet, Error> {
+        self.call_deadline(packet, time::now() + timeout).await
+    }
+
+    /// Exchange a handshake with the server of the protocol `info`, so that
+    /// [`Self::supports`] tells the methods it supports.
+    ///
+    /// Returns the version of the server, or `None` if it doesn't reply
+    /// before `timeout`, in which case all the methods are assumed to be
+    /// supported.
+    pub async fn handshake(&self, info: &Info, timeout: Duration) -> Result<Option<u32>, Error> {
+        let reply = match self.call_timeout(info.hello()?, timeout).await {
+            Err(Error::ClientReceive(err)) if err == ETIME => return Ok(None),
+            res => res?,
+        };
+        let peer = info.peer(&reply)?;
+        let version = peer.version;
+        *self.inner.peer.lock() = Some(peer);
+        Ok(Some(version))
+    }
+
+    /// Whether the server supports `method`, which is assumed without a
+    /// handshake.
+    pub fn supports(&self, method: usize) -> bool {
+        let peer = self.inner.peer.lock();
+        peer.as_ref().map_or(true, |peer| peer.supports(method))
+    }
 }
 
 impl AsRef<Channel> for ClientImpl {
@@ -282,6 +357,8 @@ struct Inner {
     event: Event,
     wakers: Mutex<BTreeMap<usize, WakerEntry>>,
     stop: AtomicBool,
+    /// The server seen in the last handshake.
+    peer: Mutex<Option<Peer>>,
 }
 
 impl fmt::Debug for Inner {
@@ -291,6 +368,7 @@ impl fmt::Debug for Inner {
             .field("event", &self.event)
             .field("wakers", &self.wakers)
             .field("stop", &self.stop)
+            .field("peer", &self.peer)
             .finish()
     }
 }
@@ -317,13 +395,17 @@ impl Inner {
         let mut packet = Default::default();
         let res = self.channel.receive(&mut packet).await;
         res.map_err(|err| {
-            if matches!(err, EPIPE) {
+            if err.kind() == ErrorKind::BrokenPipe {
                 self.stop.store(true, Release);
                 Error::Disconnected
             } else {
                 Error::ClientReceive(err)
             }
         })?;
+        if packet.id.map(NonZeroUsize::get) == Some(crate::GOING_AWAY_ID) {
+            self.stop.store(true, Release);
+            return Err(Error::Disconnected);
+        }
         if let Some(id) = packet.id {
             let mut wakers = self.wakers.lock();
             if let Entry::Occupied(mut entry) = wakers.entry(id.get()) {
@@ -0,0 +1,98 @@
+//! The optional handshake between the clients and the servers of a protocol.
+//!
+//! The client sends the id and the version of its protocol with the ids of the
+//! methods it knows, and the server replies with its own id and version and a
+//! bitmap of those methods it supports. Then the client can avoid the methods
+//! missing in a server of another revision instead of failing with
+//! deserialization errors.
+//!
+//! The generated servers reply to the handshakes by themselves. The servers
+//! built before never reply, so the client gives up after a timeout and
+//! assumes that all the methods are supported.
+
+use alloc::{vec, vec::Vec};
+
+use solvent::ipc::Packet;
+
+use crate::{
+    packet::{self, Deserializer},
+    Error,
+};
+
+/// The method id reserved for the handshakes.
+pub const HANDSHAKE_ID: usize = 0;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// The handshake information of a protocol, generated along with it.
+#[derive(Debug)]
+pub struct Info {
+    pub id: u128,
+    pub version: u32,
+    /// The ids of all the methods of the protocol.
+    pub methods: &'static [usize],
+}
+
+/// The server of a protocol as seen by the client after the handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    pub version: u32,
+    /// The methods of the client supported by the server.
+    methods: Vec<usize>,
+}
+
+impl Peer {
+    #[inline]
+    pub fn supports(&self, method: usize) -> bool {
+        self.methods.contains(&method)
+    }
+}
+
+impl Info {
+    /// Create the handshake packet of the client.
+    pub fn hello(&self) -> Result<Packet, Error> {
+        let mut packet = Default::default();
+        let hello = (self.id, self.version, self.methods.to_vec());
+        packet::serialize(HANDSHAKE_ID, hello, &mut packet)?;
+        Ok(packet)
+    }
+
+    /// Create the reply of the server to the handshake of a client, whose body
+    /// is left in `de`.
+    ///
+    /// None of the methods are supported if the protocols mismatch.
+    pub fn reply(&self, de: Deserializer) -> Result<Packet, Error> {
+        let (id, _, methods): (u128, u32, Vec<usize>) = packet::deserialize_body(de, None)?;
+        let mut bitmap = vec![0u64; methods.len().div_ceil(WORD_BITS)];
+        if id == self.id {
+            for (index, method) in methods.iter().enumerate() {
+                if self.methods.contains(method) {
+                    bitmap[index / WORD_BITS] |= 1 << (index % WORD_BITS);
+                }
+            }
+        }
+        let mut packet = Default::default();
+        packet::serialize(HANDSHAKE_ID, (self.id, self.version, bitmap), &mut packet)?;
+        Ok(packet)
+    }
+
+    /// Parse the reply of the server to the handshake.
+    pub fn peer(&self, reply: &Packet) -> Result<Peer, Error> {
+        let (id, version, bitmap): (u128, u32, Vec<u64>) =
+            packet::deserialize(HANDSHAKE_ID, reply, None)?;
+        if id != self.id {
+            return Err(Error::ProtocolMismatch {
+                expected: self.id,
+                found: id,
+            });
+        }
+        let methods = { self.methods.iter().enumerate() }
+            .filter(|&(index, _)| {
+                let word = bitmap.get(index / WORD_BITS).copied().unwrap_or(0);
+                word & (1 << (index % WORD_BITS)) != 0
+            })
+            .map(|(_, &method)| method)
+            .collect();
+        Ok(Peer { version, methods })
+    }
+}
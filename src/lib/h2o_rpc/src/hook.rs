@@ -0,0 +1,130 @@
+//! Hooks recording the RPC calls made and served by the process.
+//!
+//! Every finished call is traced with `log::trace!` and reported to the
+//! [`Sink`] installed with [`set_sink`]. The default [`Aggregator`] sink keeps
+//! the statistics of every method, which can be published to the metrics
+//! service with [`Aggregator::export`].
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::time::Duration;
+
+use solvent::{ipc::Packet, time::Instant};
+use solvent_core::sync::{Arsc, Mutex, OnceCell};
+
+use crate::{
+    metrics::{MethodStats, MetricsSyncClient},
+    packet, Error,
+};
+
+/// A finished call.
+#[derive(Debug, Copy, Clone)]
+pub struct CallRecord {
+    /// Whether the call is served by the process instead of made by it.
+    pub server: bool,
+    /// The method id of the request, or 0 if it's not a valid RPC packet.
+    pub method: usize,
+    pub latency: Duration,
+    pub is_err: bool,
+}
+
+/// The receiver of the records of all the finished calls in the process.
+pub trait Sink: Send + Sync {
+    fn record(&self, record: &CallRecord);
+}
+
+static SINK: OnceCell<Arsc<dyn Sink>> = OnceCell::new();
+
+/// Install the sink of the call records, which can be done only once.
+pub fn set_sink(sink: Arsc<dyn Sink>) -> Result<(), Arsc<dyn Sink>> {
+    SINK.set(sink)
+}
+
+#[inline]
+fn side(server: bool) -> &'static str {
+    if server {
+        "server"
+    } else {
+        "client"
+    }
+}
+
+/// An ongoing call, reported when finished.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Span {
+    server: bool,
+    method: usize,
+    start: Instant,
+}
+
+impl Span {
+    pub(crate) fn enter(server: bool, request: &Packet) -> Self {
+        let method = packet::deserialize_metadata(request).map_or(0, |(method, _)| method);
+        log::trace!("RPC {} {method:#x}: started", side(server));
+        Span {
+            server,
+            method,
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn finish<T>(self, res: &Result<T, Error>) {
+        let record = CallRecord {
+            server: self.server,
+            method: self.method,
+            latency: self.start.elapsed(),
+            is_err: res.is_err(),
+        };
+        log::trace!(
+            "RPC {} {:#x}: {} in {:?}",
+            side(record.server),
+            record.method,
+            if record.is_err { "failed" } else { "finished" },
+            record.latency
+        );
+        if let Some(sink) = SINK.get() {
+            sink.record(&record);
+        }
+    }
+}
+
+/// A sink keeping the statistics of every method.
+#[derive(Debug, Default)]
+pub struct Aggregator {
+    stats: Mutex<BTreeMap<(bool, usize), MethodStats>>,
+}
+
+impl Aggregator {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn snapshot(&self) -> Vec<MethodStats> {
+        self.stats.lock().values().copied().collect()
+    }
+
+    /// Publish a snapshot of the statistics to the metrics service on behalf
+    /// of the process `name`.
+    pub fn export(&self, name: impl Into<String>, client: &MetricsSyncClient) -> Result<(), Error> {
+        client.report(name.into(), self.snapshot())
+    }
+}
+
+impl Sink for Aggregator {
+    fn record(&self, record: &CallRecord) {
+        let latency = u64::try_from(record.latency.as_nanos()).unwrap_or(u64::MAX);
+
+        let mut stats = self.stats.lock();
+        let stats = stats
+            .entry((record.server, record.method))
+            .or_insert_with(|| MethodStats {
+                server: record.server,
+                method: record.method,
+                ..Default::default()
+            });
+        stats.calls += 1;
+        stats.errors += record.is_err as u64;
+        stats.total_latency_ns = stats.total_latency_ns.saturating_add(latency);
+        stats.max_latency_ns = stats.max_latency_ns.max(latency);
+    }
+}
@@ -1,8 +1,15 @@
-use solvent::prelude::Packet;
+use solvent::prelude::{ChanCredit, Packet, PAGE_SIZE};
+#[cfg(feature = "std")]
+use solvent_core::sync::Mutex;
 use solvent_rpc_core::packet::{Deserializer, SerdePacket, Serializer};
 
 #[cfg(feature = "std")]
 pub trait Protocol {
+    /// The globally unique id allocated in the protocol registry.
+    const ID: u128;
+    /// The path of the protocol, e.g. `io::file::File`.
+    const NAME: &'static str;
+
     type Client: crate::Client;
     type Server: crate::Server;
 
@@ -10,7 +17,7 @@ pub trait Protocol {
     // type SyncServer: crate::sync::Server;
 
     fn with_disp(disp: solvent_async::disp::DispSender) -> (Self::Client, Self::Server) {
-        let (tx, rx) = solvent::ipc::Channel::new();
+        let (tx, rx) = raw_channel();
         let (tx, rx) = (
             solvent_async::ipc::Channel::with_disp(tx, disp.clone()),
             solvent_async::ipc::Channel::with_disp(rx, disp),
@@ -27,7 +34,7 @@ pub trait Protocol {
     fn sync_client_with_disp(
         disp: solvent_async::disp::DispSender,
     ) -> (Self::SyncClient, Self::Server) {
-        let (tx, rx) = solvent::ipc::Channel::new();
+        let (tx, rx) = raw_channel();
         let rx = solvent_async::ipc::Channel::with_disp(rx, disp);
         (Self::SyncClient::from(tx), Self::Server::from(rx))
     }
@@ -39,6 +46,41 @@ pub trait Protocol {
     }
 }
 
+/// The recommended credit window of the channels created by [`Protocol`]s,
+/// to be passed to [`set_default_credit`].
+pub const DEFAULT_CREDIT: ChanCredit = ChanCredit {
+    packets: 256,
+    bytes: 256 * PAGE_SIZE,
+};
+
+#[cfg(feature = "std")]
+static CREDIT: Mutex<Option<ChanCredit>> = Mutex::new(None);
+
+/// Set the credit window of the channels created by [`Protocol`]s afterwards,
+/// or disable flow control if `None`, which is the default.
+///
+/// The clients wait for the credit before sending requests, and the servers
+/// queue the responses and events beyond the credit, sending them as the
+/// credit is granted back while their request streams are polled.
+#[cfg(feature = "std")]
+pub fn set_default_credit(credit: Option<ChanCredit>) {
+    *CREDIT.lock() = credit;
+}
+
+#[cfg(feature = "std")]
+pub fn default_credit() -> Option<ChanCredit> {
+    *CREDIT.lock()
+}
+
+#[cfg(feature = "std")]
+fn raw_channel() -> (solvent::ipc::Channel, solvent::ipc::Channel) {
+    match default_credit() {
+        Some(credit) => solvent::ipc::Channel::try_with_credit(credit)
+            .expect("Failed to create a pair of flow-controlled channels"),
+        None => solvent::ipc::Channel::new(),
+    }
+}
+
 #[cfg(feature = "std")]
 pub fn with_disp<P: Protocol>(disp: solvent_async::disp::DispSender) -> (P::Client, P::Server) {
     P::with_disp(disp)
@@ -7,6 +7,9 @@ extern crate alloc;
 
 #[cfg(feature = "std")]
 mod client;
+pub mod handshake;
+#[cfg(feature = "metrics")]
+pub mod hook;
 mod ifx;
 #[path ="../target/imp/mod.rs"]
 #[rustfmt::skip]
@@ -22,3 +25,72 @@ pub use solvent_rpc_core::*;
 #[cfg(feature = "std")]
 pub use self::{client::*, server::*};
 pub use self::{ifx::*, imp::*};
+
+#[cfg(feature = "runtime")]
+pub mod test {
+    use alloc::vec::Vec;
+
+    use futures::{future::join_all, StreamExt};
+    use solvent::prelude::{ChanCredit, Packet, PAGE_SIZE};
+    use solvent_async::ipc::Channel;
+
+    use crate::{ClientImpl, ServerImpl};
+
+    const NUM_CALLS: usize = 16;
+
+    /// The responses beyond the credit of a flow-controlled channel are
+    /// queued by the server instead of being dropped.
+    async fn test_flow_control() {
+        let credit = ChanCredit {
+            packets: 2,
+            bytes: 4 * PAGE_SIZE,
+        };
+        let (tx, rx) = solvent::ipc::Channel::try_with_credit(credit)
+            .expect("Failed to create a pair of flow-controlled channels");
+        let client = ClientImpl::new(Channel::new(tx));
+        let (mut stream, _) = ServerImpl::new(Channel::new(rx)).serve();
+
+        let server = async move {
+            // Receive all the requests before responding, so that the responses
+            // go beyond the credit.
+            let mut requests = Vec::new();
+            for _ in 0..NUM_CALLS {
+                let request = stream.next().await.expect("The client hung up");
+                requests.push(request.expect("Failed to receive the request"));
+            }
+            for request in requests {
+                let response = Packet {
+                    buffer: request.packet.buffer,
+                    ..Default::default()
+                };
+                request
+                    .responder
+                    .send(response, false)
+                    .expect("Failed to send the response");
+            }
+            // Flush the backlog until the client hangs up.
+            assert!(stream.next().await.is_none());
+        };
+
+        let client = async move {
+            let calls = (0..NUM_CALLS as u8).map(|index| {
+                let client = &client;
+                async move {
+                    let request = Packet {
+                        buffer: alloc::vec![index; 100],
+                        ..Default::default()
+                    };
+                    let response = client.call(request).await.expect("Failed to call");
+                    assert_eq!(response.buffer, [index; 100]);
+                }
+            });
+            join_all(calls).await;
+        };
+
+        futures::future::join(client, server).await;
+    }
+
+    pub async fn test_rpc() {
+        test_flow_control().await;
+    }
+}
@@ -1,19 +1,37 @@
+use alloc::collections::VecDeque;
 use core::{
     fmt,
     future::Future,
-    mem::ManuallyDrop,
+    mem,
     num::NonZeroUsize,
     pin::Pin,
-    sync::atomic::{AtomicBool, Ordering::*},
-    task::{ready, Context, Poll},
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering::*},
+    task::{ready, Context, Poll, Waker},
 };
 
 use futures::{pin_mut, stream::FusedStream, Stream};
-use solvent::prelude::{Handle, Object, Packet, EPIPE};
-use solvent_async::ipc::Channel;
-use solvent_core::sync::Arsc;
+use solvent::{
+    prelude::{ChanPeerId, ErrorKind, Handle, Object, Packet, Ref, EAGAIN, MAX_BUFFER_SIZE},
+    time::Instant,
+};
+use solvent_async::{
+    ipc::{Channel, WaitState},
+    time::Sleep,
+};
+use solvent_core::sync::{Arsc, Mutex};
 
-use crate::Error;
+use crate::{
+    handshake::{Info, HANDSHAKE_ID},
+    packet::deserialize_metadata,
+    Error,
+};
+
+/// The id of the packet sent by a server shutting down, after which no more
+/// responses nor events are sent. The clients treat it as a disconnection.
+///
+/// It's never allocated for calls.
+pub const GOING_AWAY_ID: usize = usize::MAX;
 
 #[derive(Debug)]
 #[repr(transparent)]
@@ -22,11 +40,25 @@ pub struct ServerImpl {
 }
 
 impl ServerImpl {
+    #[inline]
     pub fn new(channel: Channel) -> Self {
+        Self::with_handshake(channel, None)
+    }
+
+    /// Create a server replying to the handshakes of the clients with
+    /// `handshake`, or passing them on as requests if it's `None`.
+    pub fn with_handshake(channel: Channel, handshake: Option<&'static Info>) -> Self {
         ServerImpl {
             inner: Arsc::new(Inner {
                 channel,
+                handshake,
                 stop: AtomicBool::new(false),
+                draining: AtomicBool::new(false),
+                in_flight: AtomicUsize::new(0),
+                backlog: Mutex::new(VecDeque::new()),
+                writable: Mutex::new(WaitState::default()),
+                stream_waker: Mutex::new(None),
+                drain_waker: Mutex::new(None),
             }),
         }
     }
@@ -98,13 +130,30 @@ impl Stream for PacketStream {
     type Item = Result<Request, Error>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if self.inner.stop.load(Acquire) {
+        if self.is_terminated() {
             return Poll::Ready(None);
         }
+        // Woken up to stop accepting requests when shutting down, or to flush
+        // the backlog.
+        *self.inner.stream_waker.lock() = Some(cx.waker().clone());
+        if self.is_terminated() {
+            return Poll::Ready(None);
+        }
+        if let Poll::Ready(Err(err)) = self.inner.poll_flush(cx) {
+            return Poll::Ready(match err {
+                Error::Disconnected => None,
+                err => Some(Err(err)),
+            });
+        }
 
-        let fut = self.inner.receive();
-        pin_mut!(fut);
-        let res = ready!(fut.poll(cx));
+        let res = loop {
+            let fut = self.inner.receive();
+            pin_mut!(fut);
+            match ready!(fut.poll(cx)) {
+                Ok(packet) if self.inner.reply_handshake(&packet) => {}
+                res => break res,
+            }
+        };
         Poll::Ready(match res {
             Err(Error::Disconnected) => None,
             res => Some(res.map(|packet| Request {
@@ -112,7 +161,10 @@ impl Stream for PacketStream {
                     sender: EventSenderImpl {
                         inner: self.inner.clone(),
                     },
+                    _in_flight: InFlight::new(&self.inner),
                     id: packet.id,
+                    #[cfg(feature = "metrics")]
+                    span: crate::hook::Span::enter(true, &packet),
                 },
                 packet,
             })),
@@ -123,7 +175,7 @@ impl Stream for PacketStream {
 impl FusedStream for PacketStream {
     #[inline]
     fn is_terminated(&self) -> bool {
-        self.inner.stop.load(Acquire)
+        self.inner.stop.load(Acquire) || self.inner.draining.load(Acquire)
     }
 }
 
@@ -152,11 +204,83 @@ impl EventSenderImpl {
     pub fn close(self) {
         self.inner.stop.store(true, Release);
     }
+
+    /// Shut the server down gracefully.
+    ///
+    /// The request stream ends at once, and the requests already received
+    /// have until `deadline` to be responded to. Then a packet of
+    /// [`GOING_AWAY_ID`] is sent to the client and the server is closed.
+    pub fn shutdown(self, deadline: Instant) -> Shutdown {
+        self.inner.draining.store(true, Release);
+        if let Some(waker) = self.inner.stream_waker.lock().take() {
+            waker.wake()
+        }
+        Shutdown {
+            inner: Some(self.inner),
+            timeout: Sleep::new(deadline),
+        }
+    }
+}
+
+/// The future returned by [`EventSenderImpl::shutdown`], resolving to the
+/// number of the requests left without responses at the deadline.
+#[must_use = "futures do nothing unless polled"]
+pub struct Shutdown {
+    inner: Option<Arsc<Inner>>,
+    timeout: Sleep,
+}
+
+impl Future for Shutdown {
+    type Output = usize;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<usize> {
+        let this = &mut *self;
+        let inner = this.inner.as_ref().expect("Polled after completion");
+        *inner.drain_waker.lock() = Some(cx.waker().clone());
+        // The responses in the backlog are flushed before going away as well.
+        let drained = inner.in_flight.load(Acquire) == 0 && inner.poll_flush(cx).is_ready();
+        if !drained {
+            ready!(Pin::new(&mut this.timeout).poll(cx));
+        }
+
+        let inner = this.inner.take().unwrap();
+        let mut going_away = Packet {
+            id: NonZeroUsize::new(GOING_AWAY_ID),
+            ..Default::default()
+        };
+        // The client may be gone already.
+        let _ = inner.try_send(&mut going_away);
+        inner.stop.store(true, Release);
+        Poll::Ready(inner.in_flight.load(Acquire))
+    }
+}
+
+/// The guard of a request received but not responded to yet.
+struct InFlight(Arsc<Inner>);
+
+impl InFlight {
+    fn new(inner: &Arsc<Inner>) -> Self {
+        inner.in_flight.fetch_add(1, AcqRel);
+        InFlight(inner.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, AcqRel) == 1 {
+            if let Some(waker) = self.0.drain_waker.lock().take() {
+                waker.wake()
+            }
+        }
+    }
 }
 
 pub struct Responder {
     sender: EventSenderImpl,
+    _in_flight: InFlight,
     id: Option<NonZeroUsize>,
+    #[cfg(feature = "metrics")]
+    span: crate::hook::Span,
 }
 
 impl Responder {
@@ -164,6 +288,8 @@ impl Responder {
     pub fn send(self, mut packet: Packet, close: bool) -> Result<(), Error> {
         packet.id = self.id;
         let ret = self.sender.send(packet);
+        #[cfg(feature = "metrics")]
+        self.span.finish(&ret);
         if close {
             self.sender.close();
         }
@@ -174,17 +300,41 @@ impl Responder {
     pub fn close(self) {
         self.sender.close()
     }
+
+    /// Get the identity of the client that sends the request.
+    #[inline]
+    pub fn peer(&self) -> solvent::error::Result<ChanPeerId> {
+        self.sender.inner.channel.as_ref().peer_id()
+    }
 }
 
 struct Inner {
     channel: Channel,
+    handshake: Option<&'static Info>,
     stop: AtomicBool,
+    /// Whether the server is shutting down, accepting no more requests.
+    draining: AtomicBool,
+    /// The number of the requests received but not responded to yet.
+    in_flight: AtomicUsize,
+    /// The packets waiting for the credit of a flow-controlled channel, sent
+    /// in order before any new ones.
+    backlog: Mutex<VecDeque<Packet>>,
+    /// The wait for the replenishment of the credit while flushing the
+    /// backlog, kept until it completes.
+    writable: Mutex<WaitState>,
+    stream_waker: Mutex<Option<Waker>>,
+    drain_waker: Mutex<Option<Waker>>,
 }
 
 impl fmt::Debug for Inner {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Inner").field("stop", &self.stop).finish()
+        f.debug_struct("Inner")
+            .field("stop", &self.stop)
+            .field("draining", &self.draining)
+            .field("in_flight", &self.in_flight)
+            .field("backlog", &self.backlog.lock().len())
+            .finish()
     }
 }
 
@@ -193,7 +343,7 @@ impl Inner {
         let mut packet = Default::default();
         let res = self.channel.receive(&mut packet).await;
         res.map_err(|err| {
-            if err == EPIPE {
+            if err.kind() == ErrorKind::BrokenPipe {
                 self.stop.store(true, Release);
                 Error::Disconnected
             } else {
@@ -203,18 +353,110 @@ impl Inner {
         Ok(packet)
     }
 
+    /// Reply to `packet` if it's a handshake, returning whether it is.
+    fn reply_handshake(&self, packet: &Packet) -> bool {
+        let Some(info) = self.handshake else {
+            return false;
+        };
+        match deserialize_metadata(packet) {
+            Ok((HANDSHAKE_ID, de)) => {
+                let res = info.reply(de).and_then(|mut reply| {
+                    reply.id = packet.id;
+                    self.send(reply)
+                });
+                if let Err(err) = res {
+                    log::warn!("Failed to reply to the handshake: {err}");
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Send `packet`, or queue it in the backlog if the credit is exhausted.
+    ///
+    /// The backlog is flushed by the request stream, or by [`Shutdown`].
     fn send(&self, mut packet: Packet) -> Result<(), Error> {
-        let res = self.channel.send(&mut packet);
-        res.map_err(|err| {
-            if err == EPIPE {
-                self.stop.store(true, Release);
-                Error::Disconnected
-            } else {
-                Error::ServerSend(err)
+        let mut backlog = self.backlog.lock();
+        let res = if backlog.is_empty() {
+            self.try_send(&mut packet)
+        } else {
+            Err(EAGAIN)
+        };
+        match res {
+            Err(EAGAIN) => {
+                backlog.push_back(packet);
+                drop(backlog);
+                if let Some(waker) = self.stream_waker.lock().take() {
+                    waker.wake()
+                }
+                Ok(())
             }
-        })?;
-        Ok(())
+            res => res.map_err(|err| self.send_error(err)),
+        }
     }
+
+    /// Send the packets in the backlog, waiting for the replenishment of the
+    /// credit if it's exhausted again.
+    fn poll_flush(&self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        loop {
+            let mut backlog = self.backlog.lock();
+            let Some(packet) = backlog.front_mut() else {
+                return Poll::Ready(Ok(()));
+            };
+            match self.try_send(packet) {
+                Ok(()) => {
+                    backlog.pop_front();
+                }
+                Err(EAGAIN) => {
+                    drop(backlog);
+                    let mut writable = self.writable.lock();
+                    if let Err(err) = ready!(self.channel.poll_writable(&mut writable, cx)) {
+                        return Poll::Ready(Err(Error::ServerSend(err)));
+                    }
+                }
+                Err(err) => {
+                    backlog.clear();
+                    return Poll::Ready(Err(self.send_error(err)));
+                }
+            }
+        }
+    }
+
+    /// Try sending `packet`, leaving it intact on failure.
+    fn try_send(&self, packet: &mut Packet) -> solvent::error::Result {
+        if packet.buffer.len() > MAX_BUFFER_SIZE {
+            send_pages(self.channel.as_ref(), packet)
+        } else {
+            self.channel.send(packet)
+        }
+    }
+
+    fn send_error(&self, err: solvent::error::Error) -> Error {
+        if err.kind() == ErrorKind::BrokenPipe {
+            self.stop.store(true, Release);
+            Error::Disconnected
+        } else {
+            Error::ServerSend(err)
+        }
+    }
+}
+
+/// Move a packet too large for the kernel buffer to the peer along with the
+/// pages of the buffer, which requires the channel to be created with
+/// `ChanOptions::ZERO_COPY`.
+///
+/// The buffer is a large allocation, which the global allocator makes a whole
+/// mapping of its own, so it's moved in place without being copied.
+fn send_pages(channel: &solvent::ipc::Channel, packet: &mut Packet) -> solvent::error::Result {
+    let payload = NonNull::from(packet.buffer.as_mut_slice());
+    // SAFETY: The buffer is owned by the packet and not referred to by anyone
+    // else.
+    unsafe { channel.send_pages(packet.id, payload, &packet.handles) }?;
+    // The mapping of the buffer is moved to the peer, and must not be freed.
+    mem::forget(mem::take(&mut packet.buffer));
+    *packet = Default::default();
+    Ok(())
 }
 
 pub trait Server: AsRef<Channel> + From<Channel> {
@@ -251,11 +493,14 @@ pub trait EventSender {
     {
         // SAFETY: We don't take the ownership from the handle, and the handle is the
         // inner channel of this event sender.
-        let channel = unsafe { ManuallyDrop::new(solvent::ipc::Channel::from_raw(handle)) };
+        let channel = unsafe { Ref::<solvent::ipc::Channel>::from_raw(handle) };
         if let Ok(mut packet) = crate::Event::serialize(event.into()) {
             let _ = channel.send(&mut packet);
         }
     }
 
     fn close(self);
+
+    /// Shut the server down gracefully. See [`EventSenderImpl::shutdown`].
+    fn shutdown(self, deadline: Instant) -> Shutdown;
 }
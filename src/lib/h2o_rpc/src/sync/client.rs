@@ -3,21 +3,25 @@ use core::{
     iter::FusedIterator,
     mem,
     num::NonZeroUsize,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering::*},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::*},
     time::Duration,
 };
 
 use crossbeam::queue::SegQueue;
 use solvent::{
-    error::{ENOENT, EPIPE, ETIME},
-    ipc::{Channel, Packet, SIG_READ},
+    error::{ErrorKind, EAGAIN, ENOENT, ETIME},
+    ipc::{Channel, Packet, SIG_READ, SIG_WRITE},
     prelude::Object,
+    sync::{futex_wait, futex_wake},
     time::Instant,
 };
 use solvent_async::disp::DispSender;
 use solvent_core::sync::{Arsc, Mutex};
 
-use crate::Error;
+use crate::{
+    handshake::{Info, Peer},
+    Error,
+};
 
 #[derive(Debug, Clone)]
 pub struct ClientImpl {
@@ -31,9 +35,11 @@ impl ClientImpl {
                 next_id: AtomicUsize::new(1),
                 channel,
                 events: SegQueue::new(),
-                callers: Mutex::new(BTreeMap::new()),
+                slots: Mutex::new(BTreeMap::new()),
+                receiving: AtomicBool::new(false),
                 set_event_receiver: AtomicBool::new(false),
                 stop: AtomicBool::new(false),
+                peer: Mutex::new(None),
             }),
         }
     }
@@ -46,12 +52,46 @@ impl ClientImpl {
 
     #[inline]
     pub fn call(&self, packet: Packet) -> Result<Packet, Error> {
-        self.inner.call(packet)
+        #[cfg(feature = "metrics")]
+        let span = crate::hook::Span::enter(false, &packet);
+        let res = self.inner.call(packet);
+        #[cfg(feature = "metrics")]
+        span.finish(&res);
+        res
     }
 
     #[inline]
     pub fn call_timeout(&self, packet: Packet, timeout: Duration) -> Result<Packet, Error> {
-        self.inner.call_timeout(packet, timeout)
+        #[cfg(feature = "metrics")]
+        let span = crate::hook::Span::enter(false, &packet);
+        let res = self.inner.call_timeout(packet, timeout);
+        #[cfg(feature = "metrics")]
+        span.finish(&res);
+        res
+    }
+
+    /// Exchange a handshake with the server of the protocol `info`, so that
+    /// [`Self::supports`] tells the methods it supports.
+    ///
+    /// Returns the version of the server, or `None` if it doesn't reply
+    /// before `timeout`, in which case all the methods are assumed to be
+    /// supported.
+    pub fn handshake(&self, info: &Info, timeout: Duration) -> Result<Option<u32>, Error> {
+        let reply = match self.call_timeout(info.hello()?, timeout) {
+            Err(Error::ClientReceive(err)) if err == ETIME => return Ok(None),
+            res => res?,
+        };
+        let peer = info.peer(&reply)?;
+        let version = peer.version;
+        *self.inner.peer.lock() = Some(peer);
+        Ok(Some(version))
+    }
+
+    /// Whether the server supports `method`, which is assumed without a
+    /// handshake.
+    pub fn supports(&self, method: usize) -> bool {
+        let peer = self.inner.peer.lock();
+        peer.as_ref().map_or(true, |peer| peer.supports(method))
     }
 
     #[inline]
@@ -83,7 +123,7 @@ impl TryFrom<ClientImpl> for Channel {
     fn try_from(client: ClientImpl) -> Result<Self, Self::Error> {
         match Arsc::try_unwrap(client.inner) {
             Ok(mut inner) => {
-                if inner.callers.get_mut().is_empty() {
+                if inner.slots.get_mut().is_empty() {
                     Ok(inner.channel)
                 } else {
                     Err(ClientImpl {
@@ -109,11 +149,8 @@ impl Iterator for EventReceiverImpl {
             return None;
         }
 
-        let res = self.timeout.map_or_else(
-            || self.inner.receive_event(),
-            |timeout| self.inner.receive_event_timeout(timeout),
-        );
-        match res {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        match self.inner.receive_event(deadline) {
             Err(Error::Disconnected) => None,
             res => Some(res),
         }
@@ -122,143 +159,306 @@ impl Iterator for EventReceiverImpl {
 
 impl FusedIterator for EventReceiverImpl {}
 
+/// The slot key of the event receiver, which is never a call ID.
+const EVENT_KEY: usize = 0;
+
+const EMPTY: u64 = 0;
+const WAITING: u64 = 1;
+const NOTIFIED: u64 = 2;
+
+/// The slot of a pending call, or of the event receiver, where the other
+/// threads put the packets they received for it.
+#[derive(Debug, Default)]
+struct Slot {
+    /// The futex the owner waits on, notified when the packet arrives or the
+    /// receiver role is handed off to the owner.
+    state: AtomicU64,
+    packet: Mutex<Option<Packet>>,
+}
+
+impl Slot {
+    fn notify(&self) {
+        if self.state.swap(NOTIFIED, Release) == WAITING {
+            futex_wake(&self.state);
+        }
+    }
+
+    fn fill(&self, packet: Packet) {
+        *self.packet.lock() = Some(packet);
+        self.notify();
+    }
+
+    #[inline]
+    fn take(&self) -> Option<Packet> {
+        self.packet.lock().take()
+    }
+
+    #[inline]
+    fn is_filled(&self) -> bool {
+        self.packet.lock().is_some()
+    }
+}
+
+/// The shared state of a client.
+///
+/// Concurrent calls are multiplexed on the channel by their IDs: at most one
+/// thread receives from the channel at a time, putting the packets for the
+/// other threads into their slots, and the others wait on their slots until
+/// either their packets arrive or the receiver role is handed off to them.
 #[derive(Debug)]
 struct Inner {
     next_id: AtomicUsize,
     channel: Channel,
     events: SegQueue<Packet>,
-    callers: Mutex<BTreeMap<usize, Packet>>,
+    slots: Mutex<BTreeMap<usize, Arsc<Slot>>>,
+    /// Whether a thread is receiving from the channel for the others.
+    receiving: AtomicBool,
     set_event_receiver: AtomicBool,
     stop: AtomicBool,
+    /// The server seen in the last handshake.
+    peer: Mutex<Option<Peer>>,
 }
 
 impl Inner {
+    #[inline]
     fn call(&self, packet: Packet) -> Result<Packet, Error> {
-        self.call_inner(packet, |_| {
-            self.channel
-                .try_wait(Duration::MAX, true, false, SIG_READ)
-                .map_err(Error::ClientReceive)?;
-            Ok(())
-        })
+        self.call_inner(packet, None)
     }
 
+    #[inline]
     fn call_timeout(&self, packet: Packet, timeout: Duration) -> Result<Packet, Error> {
-        self.call_inner(packet, |instant| {
-            let elapsed = instant.elapsed();
-            if elapsed >= timeout {
-                return Err(Error::ClientReceive(ETIME));
-            }
-            self.channel
-                .try_wait(timeout - elapsed, true, false, SIG_READ)
-                .map_err(Error::ClientReceive)?;
-            Ok(())
-        })
+        // The deadline is fixed up front so that the retried waits don't
+        // stretch the timeout.
+        self.call_inner(packet, Some(Instant::now() + timeout))
     }
 
-    #[inline]
-    fn call_inner<F>(&self, mut packet: Packet, mut wait: F) -> Result<Packet, Error>
-    where
-        F: FnMut(Instant) -> Result<(), Error>,
-    {
-        let self_id = self.next_id.fetch_add(1, SeqCst);
-        packet.id = NonZeroUsize::new(self_id);
-        self.channel.send(&mut packet).map_err(|err| {
-            if err == EPIPE {
-                self.stop.store(true, Release);
-                Error::Disconnected
-            } else {
-                Error::ClientReceive(err)
+    fn call_inner(&self, mut packet: Packet, deadline: Option<Instant>) -> Result<Packet, Error> {
+        let id = self.next_id.fetch_add(1, SeqCst);
+        packet.id = NonZeroUsize::new(id);
+
+        let slot = self.register(id);
+        let res = match self.send(&mut packet, id, deadline) {
+            Ok(Some(reply)) => Ok(reply),
+            Ok(None) => self.wait(id, &slot, deadline),
+            Err(err) => Err(err),
+        };
+        self.deregister(id, &slot);
+        res
+    }
+
+    /// Send the request, receiving the first packet in the same call if no
+    /// other thread is receiving.
+    fn send(
+        &self,
+        packet: &mut Packet,
+        id: usize,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Packet>, Error> {
+        if !self.try_receiving() {
+            self.send_only(packet).map_err(|err| self.error(err))?;
+            return Ok(None);
+        }
+        let res = self.send_recv(packet, deadline);
+        let ret = match res {
+            Ok(()) => Ok(self.dispatch(mem::take(packet), id)),
+            Err(ENOENT) => Ok(None),
+            Err(err) => Err(self.error(err)),
+        };
+        self.stop_receiving(id);
+        ret
+    }
+
+    fn send_only(&self, packet: &mut Packet) -> solvent::error::Result {
+        loop {
+            match self.channel.send(packet) {
+                Err(EAGAIN) => {
+                    self.channel
+                        .try_wait(Duration::MAX, true, false, SIG_WRITE)?;
+                }
+                res => break res,
             }
-        })?;
+        }
+    }
 
-        let instant = Instant::now();
+    /// Send the request and receive the first packet in one call, waiting for
+    /// the credit if needed.
+    fn send_recv(&self, packet: &mut Packet, deadline: Option<Instant>) -> solvent::error::Result {
         loop {
-            match self.channel.receive(&mut packet) {
-                Ok(()) => {
-                    if let Some(id) = packet.id {
-                        if id.get() == self_id {
-                            break Ok(packet);
-                        } else {
-                            let mut callers = self.callers.lock();
-                            callers.insert(id.get(), mem::take(&mut packet));
-                        }
-                    } else {
-                        self.events.push(mem::take(&mut packet));
-                    }
+            let res = match deadline {
+                Some(deadline) => self.channel.send_recv_until(packet, None, deadline),
+                None => self.channel.send_recv(packet, None, Duration::MAX),
+            };
+            match res {
+                Err(EAGAIN) => {
+                    self.channel
+                        .try_wait(Duration::MAX, true, false, SIG_WRITE)?;
                 }
-                Err(ENOENT) => {
-                    let mut callers = self.callers.lock();
-                    if let Some(packet) = callers.remove(&self_id) {
-                        break Ok(packet);
-                    }
-                    wait(instant)?;
+                res => break res.map(drop),
+            }
+        }
+    }
+
+    #[inline]
+    fn try_receiving(&self) -> bool {
+        self.receiving
+            .compare_exchange(false, true, Acquire, Relaxed)
+            .is_ok()
+    }
+
+    /// Give up the receiver role, handing it off to another waiting thread.
+    fn stop_receiving(&self, key: usize) {
+        self.receiving.store(false, Release);
+        Self::hand_off(&self.slots.lock(), key);
+    }
+
+    fn hand_off(slots: &BTreeMap<usize, Arsc<Slot>>, key: usize) {
+        let next = slots
+            .iter()
+            .find(|&(&k, slot)| k != key && !slot.is_filled());
+        if let Some((_, slot)) = next {
+            slot.notify();
+        }
+    }
+
+    fn register(&self, key: usize) -> Arsc<Slot> {
+        let slot = Arsc::new(Slot::default());
+        self.slots.lock().insert(key, slot.clone());
+        slot
+    }
+
+    /// Remove the slot of `key`, passing on the receiver role if it's handed
+    /// off to the slot too late.
+    fn deregister(&self, key: usize, slot: &Slot) {
+        let mut slots = self.slots.lock();
+        slots.remove(&key);
+        if slot.state.load(Acquire) == NOTIFIED && !self.receiving.load(Acquire) {
+            Self::hand_off(&slots, key);
+        }
+    }
+
+    /// Route `packet` to its slot, or return it if it's for `key`.
+    fn dispatch(&self, packet: Packet, key: usize) -> Option<Packet> {
+        match packet.id {
+            Some(id) if id.get() == key => Some(packet),
+            Some(id) if id.get() == crate::GOING_AWAY_ID => {
+                self.stop.store(true, Release);
+                // Wake all the waiting threads to see the shutdown.
+                self.slots.lock().values().for_each(|slot| slot.notify());
+                None
+            }
+            Some(id) => {
+                // The packets of the calls timed out are dropped.
+                if let Some(slot) = self.slots.lock().get(&id.get()) {
+                    slot.fill(packet);
                 }
-                Err(err) => {
-                    if err == EPIPE {
-                        self.stop.store(true, Release);
-                        break Err(Error::Disconnected);
-                    }
-                    break Err(Error::ClientReceive(err));
+                None
+            }
+            None if key == EVENT_KEY => Some(packet),
+            None => {
+                self.events.push(packet);
+                if let Some(slot) = self.slots.lock().get(&EVENT_KEY) {
+                    slot.notify();
                 }
+                None
             }
         }
     }
 
-    fn receive_event(&self) -> Result<Packet, Error> {
-        self.receive_event_inner(|_| {
-            self.channel
-                .try_wait(Duration::MAX, true, false, SIG_READ)
-                .map_err(Error::ClientReceive)?;
-            Ok(())
-        })
+    fn error(&self, err: solvent::error::Error) -> Error {
+        if err.kind() == ErrorKind::BrokenPipe {
+            self.stop.store(true, Release);
+            // Wake all the waiting threads to see the disconnection.
+            self.slots.lock().values().for_each(|slot| slot.notify());
+            Error::Disconnected
+        } else {
+            Error::ClientReceive(err)
+        }
     }
 
-    fn receive_event_timeout(&self, timeout: Duration) -> Result<Packet, Error> {
-        self.receive_event_inner(|instant| {
-            let elapsed = instant.elapsed();
-            if elapsed >= timeout {
+    /// Wait for the packet of `key`, receiving for the others if no other
+    /// thread is receiving.
+    fn wait(&self, key: usize, slot: &Slot, deadline: Option<Instant>) -> Result<Packet, Error> {
+        loop {
+            // Any notification from here on either makes the checks below
+            // succeed or the futex wait return at once.
+            slot.state.store(EMPTY, Release);
+            if let Some(packet) = slot.take() {
+                return Ok(packet);
+            }
+            if key == EVENT_KEY {
+                if let Some(packet) = self.events.pop() {
+                    return Ok(packet);
+                }
+            }
+            if self.stop.load(Acquire) {
+                return Err(Error::Disconnected);
+            }
+
+            if self.try_receiving() {
+                let res = self.receive(key, slot, deadline);
+                self.stop_receiving(key);
+                match res {
+                    Ok(Some(packet)) => return Ok(packet),
+                    Ok(None) => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+
+            let timeout = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => Duration::MAX,
+            };
+            if timeout.is_zero() {
                 return Err(Error::ClientReceive(ETIME));
             }
-            self.channel
-                .try_wait(timeout - elapsed, true, false, SIG_READ)
-                .map_err(Error::ClientReceive)?;
-            Ok(())
-        })
+            let res = slot
+                .state
+                .compare_exchange(EMPTY, WAITING, Acquire, Relaxed);
+            if res.is_ok() {
+                futex_wait(&slot.state, WAITING, timeout);
+            }
+        }
     }
 
-    #[inline]
-    fn receive_event_inner<F>(&self, mut wait: F) -> Result<Packet, Error>
-    where
-        F: FnMut(Instant) -> Result<(), Error>,
-    {
-        let instant = Instant::now();
+    /// Receive from the channel as the receiver until the packet of `key`
+    /// arrives, which is returned, or its slot is filled before we took over.
+    fn receive(
+        &self,
+        key: usize,
+        slot: &Slot,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Packet>, Error> {
         let mut packet = Default::default();
         loop {
             match self.channel.receive(&mut packet) {
                 Ok(()) => {
-                    if let Some(id) = packet.id {
-                        let mut callers = self.callers.lock();
-                        callers.insert(id.get(), mem::take(&mut packet));
-                    } else {
-                        break Ok(packet);
+                    if let Some(packet) = self.dispatch(mem::take(&mut packet), key) {
+                        break Ok(Some(packet));
                     }
                 }
                 Err(ENOENT) => {
-                    if let Some(packet) = self.events.pop() {
-                        break Ok(packet);
+                    if slot.is_filled() || (key == EVENT_KEY && !self.events.is_empty()) {
+                        break Ok(None);
                     }
-                    wait(instant)?;
-                }
-                Err(err) => {
-                    if err == EPIPE {
-                        self.stop.store(true, Release);
-                        break Err(Error::Disconnected);
-                    }
-                    break Err(Error::ClientReceive(err));
+                    let res = match deadline {
+                        Some(deadline) => {
+                            self.channel.try_wait_until(deadline, true, false, SIG_READ)
+                        }
+                        None => self.channel.try_wait(Duration::MAX, true, false, SIG_READ),
+                    };
+                    res.map_err(|err| self.error(err))?;
                 }
+                Err(err) => break Err(self.error(err)),
             }
         }
     }
+
+    fn receive_event(&self, deadline: Option<Instant>) -> Result<Packet, Error> {
+        let slot = self.register(EVENT_KEY);
+        let res = self.wait(EVENT_KEY, &slot, deadline);
+        self.deregister(EVENT_KEY, &slot);
+        res
+    }
 }
 
 pub trait Client: From<Channel> + AsRef<Channel> {
@@ -0,0 +1,35 @@
+//! The audit log of the security-relevant events in the kernel.
+
+pub use sv_call::audit::*;
+
+use crate::{dev::MemRes, error::Result, obj::Object};
+
+/// Read the records from the sequence number `seq` into `records`, returning
+/// the number of the records read and updating `seq` to the next one to read.
+///
+/// `res` must be the root memory resource. Records overwritten before being
+/// read are skipped, which shows as gaps in their sequence numbers.
+pub fn read(res: &MemRes, seq: &mut u64, records: &mut [AuditRecord]) -> Result<usize> {
+    // SAFETY: We don't move the ownership of the handle.
+    unsafe {
+        sv_call::sv_audit_read(
+            unsafe { res.raw() },
+            seq,
+            records.as_mut_ptr(),
+            records.len(),
+        )
+        .into_res()
+    }
+}
+
+/// Record only the kinds whose bits are set in `mask`, where `res` must be
+/// the root memory resource.
+pub fn set_filter(res: &MemRes, mask: u32) -> Result {
+    // SAFETY: We don't move the ownership of the handle.
+    unsafe { sv_call::sv_audit_filter(unsafe { res.raw() }, mask).into_res() }
+}
+
+/// Report an event of `kind`, which must be one of [`AUDIT_USER_KINDS`].
+pub fn report(kind: u32, args: [u64; 3]) -> Result {
+    unsafe { sv_call::sv_audit_report(kind, args[0], args[1], args[2]).into_res() }
+}
@@ -1,9 +1,13 @@
 mod intr;
 mod pio;
+mod power;
 mod res;
+mod watchdog;
 
 pub use self::{
-    intr::{Interrupt, PackIntrWait},
+    intr::{Interrupt, IntrConfig, IntrLatency, IntrPollStat, PackIntrWait},
     pio::PortIo,
+    power::suspend,
     res::{GsiRes, MemRes, PioRes},
+    watchdog::Watchdog,
 };
@@ -1,4 +1,6 @@
-pub use sv_call::res::IntrConfig;
+use core::time::Duration;
+
+pub use sv_call::res::{IntrConfig, IntrLatency, IntrPollStat};
 use sv_call::{c_ty::Status, Syscall, ETIME, SV_INTERRUPT};
 
 use super::GsiRes;
@@ -32,6 +34,68 @@ impl Interrupt {
         Ok(unsafe { Instant::from_raw(ins) })
     }
 
+    /// Restrict the interrupt to the CPUs whose bits are set in `mask`.
+    pub fn set_affinity(&self, mask: &[u64]) -> Result {
+        unsafe {
+            // SAFETY: We don't move the ownership of the handle.
+            sv_call::sv_intr_set_affinity(unsafe { self.raw() }, mask.as_ptr(), mask.len())
+                .into_res()
+        }
+    }
+
+    /// Fill `counts` with the number of times the interrupt fired on each CPU,
+    /// returning the number of CPUs.
+    pub fn stat(&self, counts: &mut [u64]) -> Result<usize> {
+        let ret = unsafe {
+            // SAFETY: We don't move the ownership of the handle.
+            sv_call::sv_intr_stat(unsafe { self.raw() }, counts.as_mut_ptr(), counts.len())
+                .into_res()?
+        };
+        Ok(ret as usize)
+    }
+
+    /// The latency from the hard IRQ to the handler, i.e. the call of
+    /// [`Interrupt::last_time`], if the interrupt is acquired with
+    /// [`IntrConfig::THREADED`].
+    pub fn latency(&self) -> Result<IntrLatency> {
+        let mut latency = IntrLatency::default();
+        unsafe {
+            // SAFETY: We don't move the ownership of the handle.
+            sv_call::sv_intr_latency(unsafe { self.raw() }, &mut latency).into_res()?;
+        }
+        Ok(latency)
+    }
+
+    /// Switch the interrupt into polled mode, with its waiters notified every
+    /// `interval` instead of on the hard IRQs.
+    ///
+    /// Call it again to renew `budget` whenever a poll finds some work. The
+    /// interrupt is switched back once `budget` polls in a row find none.
+    pub fn start_polling(&self, interval: Duration, budget: u32) -> Result {
+        let interval = u64::try_from(interval.as_micros()).unwrap_or(u64::MAX);
+        unsafe {
+            // SAFETY: We don't move the ownership of the handle.
+            sv_call::sv_intr_poll(unsafe { self.raw() }, interval.max(1), budget).into_res()
+        }
+    }
+
+    /// Switch the interrupt back from polled mode at once.
+    pub fn stop_polling(&self) -> Result {
+        unsafe {
+            // SAFETY: We don't move the ownership of the handle.
+            sv_call::sv_intr_poll(unsafe { self.raw() }, 0, 0).into_res()
+        }
+    }
+
+    pub fn poll_stat(&self) -> Result<IntrPollStat> {
+        let mut stat = IntrPollStat::default();
+        unsafe {
+            // SAFETY: We don't move the ownership of the handle.
+            sv_call::sv_intr_poll_stat(unsafe { self.raw() }, &mut stat).into_res()?;
+        }
+        Ok(stat)
+    }
+
     pub fn pack_query(&self) -> Result<PackIntrWait> {
         let mut ins = 0u128;
         let syscall = unsafe {
@@ -0,0 +1,13 @@
+use super::MemRes;
+use crate::{error::Result, obj::Object};
+
+/// Put the system into S3 (suspend-to-RAM), and return after it wakes up.
+///
+/// `res` must be the root memory resource, and the devices should have been
+/// quiesced by their drivers.
+pub fn suspend(res: &MemRes) -> Result {
+    // SAFETY: We don't move the ownership of the handle.
+    unsafe {
+        sv_call::sv_system_ctl(unsafe { res.raw() }, sv_call::res::SYSTEM_CTL_SUSPEND).into_res()
+    }
+}
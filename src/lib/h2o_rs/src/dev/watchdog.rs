@@ -0,0 +1,36 @@
+use core::time::Duration;
+
+use sv_call::{res::WATCHDOG_RESET, SV_WATCHDOG};
+
+use super::MemRes;
+use crate::{error::Result, obj::Object, time::try_into_us};
+
+/// The software watchdog of the kernel, which must be petted within every
+/// period of its timeout until it's dropped.
+#[repr(transparent)]
+#[derive(Debug)]
+pub struct Watchdog(sv_call::Handle);
+crate::impl_obj!(Watchdog, SV_WATCHDOG);
+crate::impl_obj!(@DROP, Watchdog);
+
+impl Watchdog {
+    /// Arm the watchdog, which resets the system on expiry if `reset` is set.
+    ///
+    /// `res` must be the root memory resource, and there can be only one armed
+    /// watchdog in the system.
+    pub fn arm(res: &MemRes, timeout: Duration, reset: bool) -> Result<Self> {
+        let options = if reset { WATCHDOG_RESET } else { 0 };
+        let timeout_us = try_into_us(timeout)?;
+        // SAFETY: We don't move the ownership of the resource handle.
+        let handle = unsafe { sv_call::sv_wdog_new(unsafe { res.raw() }, timeout_us, options) }
+            .into_res()?;
+        // SAFETY: The handle is freshly allocated.
+        Ok(unsafe { Self::from_raw(handle) })
+    }
+
+    /// Restart the countdown of the watchdog.
+    pub fn pet(&self) -> Result {
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe { sv_call::sv_wdog_pet(unsafe { self.raw() }) }.into_res()
+    }
+}
@@ -1,10 +1,12 @@
 mod channel;
+mod dgram;
 mod event;
 #[cfg(feature = "alloc")]
 mod packet;
+mod port;
 
 pub use sv_call::ipc::*;
 
 #[cfg(feature = "alloc")]
 pub use self::packet::*;
-pub use self::{channel::*, event::Event};
+pub use self::{channel::*, dgram::Datagram, event::Event, port::Port};
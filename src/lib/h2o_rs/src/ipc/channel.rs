@@ -1,12 +1,36 @@
 #[cfg(feature = "alloc")]
 use alloc::{boxed::Box, vec::Vec};
-use core::{mem::MaybeUninit, num::NonZeroUsize};
+use core::{
+    mem::MaybeUninit,
+    num::NonZeroUsize,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicPtr, Ordering::*},
+    time::Duration,
+};
 
-use sv_call::{c_ty::Status, ipc::RawPacket, Syscall, SV_CHANNEL};
+#[cfg(feature = "alloc")]
+use sv_call::ipc::MAX_BUFFER_SIZE;
+use sv_call::{
+    c_ty::Status,
+    ipc::{
+        ChanCredit, ChanInfo, ChanOptions, ChanPeerId, RawPacket, RawSegment, MAX_SEGMENT_COUNT,
+    },
+    Syscall, SV_CHANNEL,
+};
 
 #[cfg(feature = "alloc")]
 use super::Packet;
-use crate::{error::*, obj::Object};
+use crate::{error::*, mem::Virt, obj::Object};
+#[cfg(feature = "alloc")]
+use crate::{
+    mem::{Flags, Phys},
+    time::Instant,
+};
+
+const EMPTY_SEGMENT: RawSegment = RawSegment {
+    ptr: ptr::null_mut(),
+    len: 0,
+};
 
 #[repr(transparent)]
 #[derive(Debug)]
@@ -28,6 +52,47 @@ impl Channel {
         Self::try_new().expect("Failed to create a pair of channels")
     }
 
+    /// Create a pair of channels with credit-based flow control.
+    ///
+    /// Sending returns `EAGAIN` if the credit is exhausted. Wait for
+    /// `SIG_WRITE` on the channel to get notified of the replenishment.
+    #[inline]
+    pub fn try_with_credit(credit: ChanCredit) -> Result<(Channel, Channel)> {
+        Self::try_with_options(ChanOptions::FLOW_CONTROL, credit)
+    }
+
+    /// Create a pair of channels with the specified options, where `credit`
+    /// is ignored unless flow control is enabled.
+    ///
+    /// See [`ChanOptions`] for more information.
+    pub fn try_with_options(
+        options: ChanOptions,
+        credit: ChanCredit,
+    ) -> Result<(Channel, Channel)> {
+        let (mut h1, mut h2) = (sv_call::Handle::NULL, sv_call::Handle::NULL);
+        unsafe { sv_call::sv_chan_new_with(options, &credit, &mut h1, &mut h2).into_res()? };
+
+        // SAFETY: The handles are freshly allocated.
+        Ok(unsafe { (Channel::from_raw(h1), Channel::from_raw(h2)) })
+    }
+
+    /// Get the traffic statistics of this side of the channel.
+    pub fn info(&self) -> Result<ChanInfo> {
+        let mut info = ChanInfo::default();
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe { sv_call::sv_chan_info(unsafe { self.raw() }, &mut info).into_res()? };
+        Ok(info)
+    }
+
+    /// Get the identity of the tasks using the peer of the channel, which
+    /// requires `Feature::IDENTIFY`.
+    pub fn peer_id(&self) -> Result<ChanPeerId> {
+        let mut id = ChanPeerId::default();
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe { sv_call::sv_chan_peer_id(unsafe { self.raw() }, &mut id).into_res()? };
+        Ok(id)
+    }
+
     pub fn send_raw(
         &self,
         id: Option<NonZeroUsize>,
@@ -47,6 +112,78 @@ impl Channel {
         unsafe { sv_call::sv_chan_send(unsafe { self.raw() }, &packet).into_res() }
     }
 
+    /// Send a packet whose buffer is gathered from `buffers` in order, which
+    /// are at most [`MAX_SEGMENT_COUNT`] and
+    /// [`MAX_BUFFER_SIZE`](sv_call::ipc::MAX_BUFFER_SIZE) bytes in total.
+    pub fn send_vectored(
+        &self,
+        id: Option<NonZeroUsize>,
+        buffers: &[&[u8]],
+        handles: &[sv_call::Handle],
+    ) -> Result {
+        let mut segments = [EMPTY_SEGMENT; MAX_SEGMENT_COUNT];
+        let segments = segments.get_mut(..buffers.len()).ok_or(EINVAL)?;
+        for (seg, buf) in segments.iter_mut().zip(buffers) {
+            *seg = RawSegment {
+                ptr: buf.as_ptr() as *mut _,
+                len: buf.len(),
+            };
+        }
+        let packet = RawPacket {
+            id: id.map_or(0, |id| id.get()),
+            handles: handles.as_ptr() as *mut _,
+            handle_count: handles.len(),
+            handle_cap: handles.len(),
+            buffer: ptr::null_mut(),
+            buffer_size: 0,
+            buffer_cap: 0,
+        };
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe {
+            sv_call::sv_chan_sendv(
+                unsafe { self.raw() },
+                &packet,
+                segments.as_ptr(),
+                segments.len(),
+            )
+            .into_res()
+        }
+    }
+
+    /// Send a packet whose payload is moved along with `pages` instead of
+    /// being copied, through a channel created with
+    /// [`ChanOptions::ZERO_COPY`].
+    ///
+    /// The payload is received as a physical object in the last handle of the
+    /// packet. The default receiving functions map it as the buffer, or read
+    /// it back if no virt is set with [`set_pages_virt`].
+    ///
+    /// # Safety
+    ///
+    /// `pages` must start at a whole writable mapping of a physical object
+    /// with no other references, and its size must exceed
+    /// [`MAX_BUFFER_SIZE`](sv_call::ipc::MAX_BUFFER_SIZE). The whole mapping
+    /// is removed if the sending succeeds, so it must not be accessed then.
+    /// Otherwise, the mapping and the handles are left intact.
+    pub unsafe fn send_pages(
+        &self,
+        id: Option<NonZeroUsize>,
+        pages: NonNull<[u8]>,
+        handles: &[sv_call::Handle],
+    ) -> Result {
+        let packet = RawPacket {
+            id: id.map_or(0, |id| id.get()),
+            handles: handles.as_ptr() as *mut _,
+            handle_count: handles.len(),
+            handle_cap: handles.len(),
+            buffer: pages.as_ptr().cast(),
+            buffer_size: pages.len(),
+            buffer_cap: pages.len(),
+        };
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe { sv_call::sv_chan_send(unsafe { self.raw() }, &packet).into_res() }
+    }
+
     #[cfg(feature = "alloc")]
     pub fn send(&self, packet: &mut Packet) -> Result {
         self.send_raw(packet.id, &packet.buffer, &packet.handles)
@@ -76,6 +213,53 @@ impl Channel {
         )
     }
 
+    /// Receive a packet, scattering its buffer into `buffers` in order, which
+    /// are at most [`MAX_SEGMENT_COUNT`].
+    ///
+    /// Returns the size of the packet as [`receive_raw`](Self::receive_raw)
+    /// does, taking the total size of `buffers` as the capacity.
+    pub fn receive_vectored(
+        &self,
+        buffers: &mut [&mut [u8]],
+        handles: &mut [MaybeUninit<sv_call::Handle>],
+    ) -> (Result<usize>, usize, usize) {
+        let mut segments = [EMPTY_SEGMENT; MAX_SEGMENT_COUNT];
+        let segments = match segments.get_mut(..buffers.len()) {
+            Some(segments) => segments,
+            None => return (Err(EINVAL), 0, 0),
+        };
+        for (seg, buf) in segments.iter_mut().zip(buffers) {
+            *seg = RawSegment {
+                ptr: buf.as_mut_ptr(),
+                len: buf.len(),
+            };
+        }
+        let mut packet = RawPacket {
+            id: 0,
+            handles: handles.as_mut_ptr().cast(),
+            handle_count: handles.len(),
+            handle_cap: handles.len(),
+            buffer: ptr::null_mut(),
+            buffer_size: 0,
+            buffer_cap: 0,
+        };
+        // SAFETY: We don't move the ownership of the handle.
+        let res = unsafe {
+            sv_call::sv_chan_recvv(
+                unsafe { self.raw() },
+                &mut packet,
+                segments.as_ptr(),
+                segments.len(),
+            )
+            .into_res()
+        };
+        (
+            res.map(|_| packet.id),
+            packet.buffer_size,
+            packet.handle_count,
+        )
+    }
+
     #[cfg(feature = "alloc")]
     pub fn pack_receive(&self, mut packet: Packet) -> PackRecv {
         let buffer = &mut packet.buffer;
@@ -114,6 +298,115 @@ impl Channel {
         Ok(())
     }
 
+    /// Send a packet and then receive one from this channel or `other` in a
+    /// single call, waiting until either of them is readable. Returns the
+    /// channel the packet is received from.
+    ///
+    /// Unlike sending and waiting separately, it takes only one kernel entry
+    /// for the usual case. The buffers of `packet` are reused for receiving,
+    /// so reserving enough space in them beforehand avoids another call for
+    /// larger packets.
+    ///
+    /// `packet` is consumed once sent, even if receiving fails afterwards.
+    #[cfg(feature = "alloc")]
+    pub fn send_recv<'a>(
+        &'a self,
+        packet: &mut Packet,
+        other: Option<&'a Channel>,
+        timeout: Duration,
+    ) -> Result<&'a Channel> {
+        let timeout = crate::time::try_into_us(timeout)?;
+        self.send_recv_with(packet, other, |hdl, send, other, recv| unsafe {
+            sv_call::sv_chan_send_recv(hdl, send, other, recv, timeout).into_res()
+        })
+    }
+
+    /// Like [`Channel::send_recv`], but receives until `deadline`.
+    #[cfg(feature = "alloc")]
+    pub fn send_recv_until<'a>(
+        &'a self,
+        packet: &mut Packet,
+        other: Option<&'a Channel>,
+        deadline: Instant,
+    ) -> Result<&'a Channel> {
+        let deadline = crate::time::into_ns(deadline);
+        self.send_recv_with(packet, other, |hdl, send, other, recv| unsafe {
+            sv_call::sv_chan_send_recv_until(hdl, send, other, recv, deadline).into_res()
+        })
+    }
+
+    #[cfg(feature = "alloc")]
+    fn send_recv_with<'a, F>(
+        &'a self,
+        packet: &mut Packet,
+        other: Option<&'a Channel>,
+        call: F,
+    ) -> Result<&'a Channel>
+    where
+        F: FnOnce(
+            sv_call::Handle,
+            *const RawPacket,
+            sv_call::Handle,
+            *mut RawPacket,
+        ) -> Result<sv_call::Handle>,
+    {
+        let send = RawPacket {
+            id: packet.id.map_or(0, |id| id.get()),
+            handles: packet.handles.as_mut_ptr(),
+            handle_count: packet.handles.len(),
+            handle_cap: packet.handles.len(),
+            buffer: packet.buffer.as_mut_ptr(),
+            buffer_size: packet.buffer.len(),
+            buffer_cap: packet.buffer.len(),
+        };
+        // The sizes are written back only if the packet is sent.
+        let mut recv = RawPacket {
+            handle_cap: packet.handles.capacity(),
+            buffer_size: usize::MAX,
+            buffer_cap: packet.buffer.capacity(),
+            ..send
+        };
+        let res = call(
+            // SAFETY: We don't move the ownership of the handles.
+            unsafe { self.raw() },
+            &send,
+            other.map_or(sv_call::Handle::NULL, |other| unsafe { other.raw() }),
+            &mut recv,
+        );
+        let hdl = match res {
+            Err(err) if recv.buffer_size == usize::MAX => return Err(err),
+            Ok(hdl) => hdl,
+            Err(err) => {
+                *packet = Default::default();
+                return match err {
+                    EBUFFER => match self.receive(packet) {
+                        Err(ENOENT) => {
+                            let other = other.ok_or(ENOENT)?;
+                            other.receive(packet).map(|_| other)
+                        }
+                        res => res.map(|_| self),
+                    },
+                    err => Err(err),
+                };
+            }
+        };
+
+        packet.id = NonZeroUsize::new(recv.id);
+        // SAFETY: The received packet is written into the buffers, and the sent
+        // handles are moved out.
+        unsafe { packet.handles.set_len(recv.handle_count) };
+        if recv.buffer_size > MAX_BUFFER_SIZE {
+            packet.buffer.clear();
+            read_pages(&mut packet.buffer, &mut packet.handles, recv.buffer_size)?;
+        } else {
+            // SAFETY: The received packet is written into the buffers.
+            unsafe { packet.buffer.set_len(recv.buffer_size) };
+        }
+        Ok(other
+            .filter(|other| unsafe { other.raw() } == hdl)
+            .unwrap_or(self))
+    }
+
     #[cfg(feature = "alloc")]
     pub fn handle<F, R>(&self, handler: F) -> Result<R>
     where
@@ -147,19 +440,23 @@ where
     let mut min_handles = [MaybeUninit::uninit(); 4];
     match receiver(&mut min_buffer, &mut min_handles) {
         (Ok(value), buffer_size, handle_count) => {
-            buffer.resize(buffer_size, 0);
-            if buffer_size > 0 {
-                buffer.copy_from_slice(&min_buffer[..buffer_size]);
-            }
-
             if handle_count > 0 {
                 handles.reserve(handle_count - handles.capacity());
                 handles
                     .spare_capacity_mut()
                     .copy_from_slice(&min_handles[..handle_count]);
             }
-            // SAFETY: `handles` is ensured to have the given numbers of elements.
+            // SAFETY: `handles` is ensured to have the given numbers of
+            // elements.
             unsafe { handles.set_len(handle_count) };
+
+            if buffer_size > MAX_BUFFER_SIZE {
+                return read_pages(buffer, handles, buffer_size).map(|_| value);
+            }
+            buffer.resize(buffer_size, 0);
+            if buffer_size > 0 {
+                buffer.copy_from_slice(&min_buffer[..buffer_size]);
+            }
             return Ok(value);
         }
         (Err(EBUFFER), buffer_size, handle_count) => {
@@ -177,12 +474,14 @@ where
         unsafe { buffer.set_len(buffer.capacity()) };
         match receiver(&mut *buffer, handles.spare_capacity_mut()) {
             (Ok(value), buffer_size, handle_count) => {
-                // SAFETY: `buffer` and `handles` are ensured to have the given numbers of
-                // elements.
-                unsafe {
-                    buffer.set_len(buffer_size);
-                    handles.set_len(handle_count);
+                // SAFETY: `handles` is ensured to have the given number.
+                unsafe { handles.set_len(handle_count) };
+                if buffer_size > MAX_BUFFER_SIZE {
+                    buffer.clear();
+                    break read_pages(buffer, handles, buffer_size).map(|_| value);
                 }
+                // SAFETY: `buffer` is ensured to have the given size.
+                unsafe { buffer.set_len(buffer_size) };
                 break Ok(value);
             }
             (Err(EBUFFER), buffer_size, handle_count) => {
@@ -194,6 +493,40 @@ where
     }
 }
 
+/// The virt into which the global allocator maps its pages.
+static PAGES_VIRT: AtomicPtr<Virt> = AtomicPtr::new(ptr::null_mut());
+
+/// Set the virt into which the global allocator maps the pages of large
+/// allocations, each of which is a whole mapping of its own unmapped when
+/// freed.
+///
+/// The pages moved along with large packets are then mapped into it as the
+/// buffers of the packets received, instead of being copied out.
+pub fn set_pages_virt(virt: &'static Virt) {
+    PAGES_VIRT.store(virt as *const _ as *mut _, Release);
+}
+
+/// Take the payload moved along with the last handle of a packet as the
+/// buffer.
+#[cfg(feature = "alloc")]
+fn read_pages(buffer: &mut Vec<u8>, handles: &mut Vec<sv_call::Handle>, size: usize) -> Result {
+    let handle = handles.pop().ok_or(EINVAL)?;
+    // SAFETY: The handle is received from the packet and owned by us.
+    let pages = unsafe { Phys::from_raw(handle) };
+    // SAFETY: The virt is set with a static reference.
+    *buffer = match unsafe { PAGES_VIRT.load(Acquire).as_ref() } {
+        Some(virt) => {
+            let flags = Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS;
+            let mapping = virt.map_phys(None, pages, flags)?;
+            // SAFETY: The mapping is a whole one as large allocations of the
+            // global allocator, so it's unmapped when the buffer is freed.
+            unsafe { Vec::from_raw_parts(mapping.as_mut_ptr(), size, mapping.len()) }
+        }
+        None => pages.read(0, size)?,
+    };
+    Ok(())
+}
+
 #[cfg(feature = "alloc")]
 pub struct PackRecv {
     pub packet: Packet,
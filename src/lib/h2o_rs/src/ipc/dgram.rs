@@ -0,0 +1,67 @@
+use sv_call::{Handle, SV_DATAGRAM};
+
+use crate::{error::*, obj::Object};
+
+/// A datagram endpoint, sending unordered messages to the other endpoints of
+/// its bus on a best-effort basis.
+///
+/// The datagrams sent to an endpoint whose queue is full are dropped. Wait for
+/// `SIG_READ` on the endpoint to get notified of the incoming ones.
+#[repr(transparent)]
+#[derive(Debug)]
+pub struct Datagram(Handle);
+
+crate::impl_obj!(Datagram, SV_DATAGRAM);
+crate::impl_obj!(@CLONE, Datagram);
+crate::impl_obj!(@DROP, Datagram);
+
+impl Datagram {
+    /// Create an endpoint on a new bus, returning it with its address.
+    pub fn try_new() -> Result<(Datagram, u64)> {
+        Self::new_impl(Handle::NULL)
+    }
+
+    /// Create another endpoint on the bus of this one, returning it with its
+    /// address.
+    pub fn try_join(&self) -> Result<(Datagram, u64)> {
+        // SAFETY: We don't move the ownership of the handle.
+        Self::new_impl(unsafe { self.raw() })
+    }
+
+    fn new_impl(bus: Handle) -> Result<(Datagram, u64)> {
+        let mut addr = 0;
+        let handle = unsafe { sv_call::sv_dgram_new(bus, &mut addr) }.into_res()?;
+        // SAFETY: The handle is freshly allocated.
+        Ok((unsafe { Datagram::from_raw(handle) }, addr))
+    }
+
+    /// Send `buffer` to the endpoint of `addr`, or to all the other endpoints
+    /// of the bus if it's [`DGRAM_BROADCAST`](sv_call::ipc::DGRAM_BROADCAST).
+    pub fn send_to(&self, addr: u64, buffer: &[u8]) -> Result {
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe {
+            sv_call::sv_dgram_send_to(unsafe { self.raw() }, addr, buffer.as_ptr(), buffer.len())
+                .into_res()
+        }
+    }
+
+    /// Receive a datagram into `buffer`, returning its whole size and the
+    /// address of its sender.
+    ///
+    /// The datagram is truncated if `buffer` is too small. Returns `ENOENT` if
+    /// there's none.
+    pub fn recv_from(&self, buffer: &mut [u8]) -> Result<(usize, u64)> {
+        let mut from = 0;
+        // SAFETY: We don't move the ownership of the handle.
+        let size = unsafe {
+            sv_call::sv_dgram_recv_from(
+                unsafe { self.raw() },
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut from,
+            )
+            .into_res()?
+        };
+        Ok((size as usize, from))
+    }
+}
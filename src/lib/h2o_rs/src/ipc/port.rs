@@ -0,0 +1,69 @@
+use sv_call::{
+    ipc::{PortRecord, WaitOptions},
+    Handle, SV_PORT,
+};
+
+use crate::{error::*, obj::Object};
+
+/// A port, queuing the readiness records of the objects bound to it.
+///
+/// An object is bound once with a key, and a record with the key is queued
+/// every time its signal matches. Its binding is re-armed when the record is
+/// dequeued. Wait for `SIG_READ` on the port to get notified of the records.
+#[repr(transparent)]
+#[derive(Debug)]
+pub struct Port(Handle);
+
+crate::impl_obj!(Port, SV_PORT);
+crate::impl_obj!(@CLONE, Port);
+crate::impl_obj!(@DROP, Port);
+
+impl Port {
+    /// Create a port with at most `capacity` bindings.
+    pub fn try_new(capacity: usize) -> Result<Self> {
+        let handle = unsafe { sv_call::sv_port_new(capacity) }.into_res()?;
+        // SAFETY: The handle is freshly allocated.
+        Ok(unsafe { Self::from_raw(handle) })
+    }
+
+    #[inline]
+    pub fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).expect("Failed to create a port")
+    }
+
+    /// Bind `obj` with `key`, queuing a record every time its signal matches
+    /// `signal` as specified by `options`.
+    ///
+    /// `WAKE_ALL` and `CONSUME` are not supported.
+    pub fn bind(&self, obj: &impl Object, key: u64, options: WaitOptions, signal: usize) -> Result {
+        // SAFETY: We don't move the ownership of the handles.
+        unsafe {
+            sv_call::sv_port_bind(
+                unsafe { self.raw() },
+                unsafe { obj.raw() },
+                key,
+                options,
+                signal,
+            )
+            .into_res()
+        }
+    }
+
+    /// Remove the binding of `key`, with its record queued if any.
+    pub fn unbind(&self, key: u64) -> Result {
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe { sv_call::sv_port_unbind(unsafe { self.raw() }, key).into_res() }
+    }
+
+    /// Dequeue the records into `records`, returning the number of them.
+    ///
+    /// Returns `ENOENT` if there's none.
+    pub fn dequeue(&self, records: &mut [PortRecord]) -> Result<usize> {
+        // SAFETY: We don't move the ownership of the handle.
+        let count = unsafe {
+            sv_call::sv_port_dequeue(unsafe { self.raw() }, records.as_mut_ptr(), records.len())
+                .into_res()?
+        };
+        Ok(count as usize)
+    }
+}
@@ -6,6 +6,7 @@
 #![feature(slice_ptr_get)]
 #![feature(slice_ptr_len)]
 
+pub mod audit;
 pub mod c_ty;
 pub mod dev;
 pub mod error;
@@ -26,6 +27,10 @@ pub fn random() -> u64 {
     unsafe { sv_call::sv_random().into_res() }.unwrap()
 }
 
+pub use sv_call::sys::KernelFeatures;
+#[cfg(feature = "stub")]
+pub use sv_call::sys::{features, has_syscall};
+
 pub mod prelude {
     pub use crate::{dev::*, error::*, ipc::*, mem::*, obj::*, task::*, time::*};
 }
@@ -6,6 +6,7 @@ macro_rules! impl_obj_for {
     ($macro:ident) => {
         $macro!($crate::ipc::Channel);
         $macro!($crate::ipc::Event);
+        $macro!($crate::ipc::Port);
         $macro!($crate::task::Task);
         $macro!($crate::task::SuspendToken);
         $macro!($crate::mem::Space);
@@ -15,6 +16,7 @@ macro_rules! impl_obj_for {
         $macro!($crate::dev::MemRes);
         $macro!($crate::dev::GsiRes);
         $macro!($crate::dev::PioRes);
+        $macro!($crate::dev::Watchdog);
         $macro!($crate::time::Timer);
         $macro!($crate::obj::Dispatcher);
     };
@@ -10,10 +10,11 @@ use core::{
     slice,
 };
 
-pub use sv_call::mem::Flags;
 use sv_call::mem::IoVec;
+pub use sv_call::mem::{Flags, MemInfo, MemRange, MemStat};
 
 pub use self::{phys::*, space::Space, virt::Virt};
+use crate::{dev::MemRes, obj::Object};
 
 cfg_if::cfg_if! { if #[cfg(target_arch = "x86_64")] {
 
@@ -25,6 +26,41 @@ pub const PAGE_MASK: usize = PAGE_SIZE - 1;
 // SAFETY: Both the size and the alignment are 2^n-bounded.
 pub const PAGE_LAYOUT: Layout = unsafe { Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE) };
 
+/// Query the memory usage of the whole system.
+pub fn mem_info() -> crate::error::Result<MemInfo> {
+    let mut info = MemInfo::default();
+    unsafe { sv_call::sv_mem_info(&mut info).into_res()? };
+    Ok(info)
+}
+
+/// Sample the usage of the pages of the current space.
+pub fn mem_stat() -> crate::error::Result<MemStat> {
+    let mut stat = MemStat::default();
+    unsafe { sv_call::sv_mem_stat(sv_call::Handle::NULL, &mut stat).into_res()? };
+    Ok(stat)
+}
+
+/// Read the usable physical memory ranges into `ranges`, returning the number
+/// of all the ranges.
+pub fn mem_map(ranges: &mut [MemRange]) -> crate::error::Result<usize> {
+    let ret = unsafe { sv_call::sv_mem_map(ranges.as_mut_ptr(), ranges.len()).into_res()? };
+    Ok(ret as usize)
+}
+
+/// Add a range of physical memory plugged after boot, usually reported by an
+/// ACPI memory device.
+pub fn hot_add(res: &MemRes, base: usize, size: usize) -> crate::error::Result {
+    // SAFETY: We don't move the ownership of the memory resource handle.
+    unsafe { sv_call::sv_mem_hot_add(unsafe { res.raw() }, base, size).into_res() }
+}
+
+/// Dump the memory objects that are alive but no longer in use, such as
+/// unmapped `Phys`s and the spaces of exited tasks, to the kernel log.
+pub fn inspect(res: &MemRes) -> crate::error::Result {
+    // SAFETY: We don't move the ownership of the memory resource handle.
+    unsafe { sv_call::sv_mem_inspect(unsafe { res.raw() }).into_res() }
+}
+
 #[derive(Debug, Copy, Clone)]
 #[repr(transparent)]
 pub struct IoSlice<'a> {
@@ -33,6 +33,12 @@ impl Phys {
         Ok(unsafe { Self::from_raw(handle) })
     }
 
+    /// Acquire the physical memory at `addr`, or allocate a contiguous block if
+    /// it's `None`.
+    ///
+    /// Ranges outside the RAM are the MMIO of devices, which are always mapped
+    /// uncached unless write-combining is requested, and can't be read,
+    /// written or copied through the object, nor moved along with packets.
     pub fn acquire(res: &MemRes, addr: Option<NonZeroUsize>, size: usize) -> Result<Self> {
         let len = size.next_multiple_of(PAGE_SIZE);
         let handle = unsafe {
@@ -1,6 +1,6 @@
 use sv_call::SV_SPACE;
 
-use super::Virt;
+use super::{MemStat, Virt};
 use crate::{error::Result, obj::Object};
 
 #[repr(transparent)]
@@ -21,4 +21,12 @@ impl Space {
     pub fn new() -> (Self, Virt) {
         Self::try_new().expect("Failed to create task space")
     }
+
+    /// Sample the usage of the pages of the space.
+    pub fn stat(&self) -> Result<MemStat> {
+        let mut stat = MemStat::default();
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe { sv_call::sv_mem_stat(unsafe { self.raw() }, &mut stat).into_res()? };
+        Ok(stat)
+    }
 }
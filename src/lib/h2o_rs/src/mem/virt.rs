@@ -38,6 +38,27 @@ impl Virt {
         Ok(unsafe { Self::from_raw(handle) })
     }
 
+    /// Keep a range from automatic placement without mapping anything, where
+    /// mappings can be placed later at explicit offsets.
+    pub fn reserve(&self, offset: Option<usize>, layout: Layout) -> Result<NonNull<[u8]>> {
+        let layout = layout.pad_to_align();
+        let value = unsafe {
+            sv_call::sv_virt_reserve(
+                // SAFETY: We don't move the ownership of the handle.
+                unsafe { self.raw() },
+                offset.unwrap_or(usize::MAX),
+                layout.size(),
+                layout.align(),
+            )
+        }
+        .into_res()?;
+        // SAFETY: The pointer range is freshly reserved.
+        Ok(unsafe {
+            let ptr = NonNull::new_unchecked(value as *mut u8);
+            NonNull::slice_from_raw_parts(ptr, layout.size())
+        })
+    }
+
     pub fn try_get_base(&self) -> Result<NonNull<u8>> {
         // SAFETY: We don't move the ownership of the handle.
         let value =
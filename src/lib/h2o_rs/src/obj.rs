@@ -1,9 +1,11 @@
+pub mod registry;
+
 use core::{fmt, marker::PhantomData, mem, mem::ManuallyDrop, ops::Deref, ptr, time::Duration};
 
 use sv_call::SV_DISPATCHER;
-pub use sv_call::{Feature, Handle, SerdeReg, Syscall};
+pub use sv_call::{ipc::WaitOptions, Feature, Handle, SerdeReg, Syscall};
 
-use crate::error::Result;
+use crate::{error::Result, time::Instant};
 
 pub(crate) mod private {
     pub trait Sealed {}
@@ -19,11 +21,48 @@ pub trait Object: private::Sealed + fmt::Debug {
     /// The ownership of the object must not be moved if it's still in use.
     unsafe fn raw(&self) -> sv_call::Handle;
 
+    /// Construct the object without claiming the ownership of the handle in
+    /// the [`registry`].
+    ///
+    /// # Safety
+    ///
+    /// The object must not be dropped or turned into the raw handle.
+    #[doc(hidden)]
+    unsafe fn from_raw_unowned(raw: sv_call::Handle) -> Self;
+
     /// # Safety
     ///
     /// The handle must be of the same type as the object and has its own
     /// ownership.
-    unsafe fn from_raw(raw: sv_call::Handle) -> Self;
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if the handle is already owned by another
+    /// object.
+    unsafe fn from_raw(raw: sv_call::Handle) -> Self
+    where
+        Self: Sized,
+    {
+        registry::claim(raw);
+        // SAFETY: The ownership is claimed.
+        unsafe { Self::from_raw_unowned(raw) }
+    }
+
+    /// # Errors
+    ///
+    /// Returns `EEXIST` if the handle is already owned by another object.
+    ///
+    /// # Safety
+    ///
+    /// The handle must be of the same type as the object.
+    unsafe fn try_from_raw(raw: sv_call::Handle) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        registry::try_claim(raw)?;
+        // SAFETY: The ownership is claimed.
+        Ok(unsafe { Self::from_raw_unowned(raw) })
+    }
 
     fn into_raw(this: Self) -> sv_call::Handle
     where
@@ -32,6 +71,7 @@ pub trait Object: private::Sealed + fmt::Debug {
         // SAFETY: We move the ownership and guarantee that the object is not used
         // anymore.
         let raw = unsafe { this.raw() };
+        registry::release(raw);
         mem::forget(this);
         raw
     }
@@ -53,7 +93,9 @@ pub trait Object: private::Sealed + fmt::Debug {
     unsafe fn try_drop(this: &mut Self) -> Result {
         // SAFETY: We move the ownership and guarantee that the object is not used
         // anymore because we're in the drop context.
-        sv_call::sv_obj_drop(unsafe { this.raw() }).into_res()
+        let raw = unsafe { this.raw() };
+        registry::release(raw);
+        sv_call::sv_obj_drop(raw).into_res()
     }
 
     fn try_wait(
@@ -77,6 +119,70 @@ pub trait Object: private::Sealed + fmt::Debug {
         }
     }
 
+    /// Like [`Object::try_wait`], but until `deadline`, which unlike a timeout
+    /// isn't stretched when the wait is interrupted and retried.
+    fn try_wait_until(
+        &self,
+        deadline: Instant,
+        level_triggered: bool,
+        wake_all: bool,
+        signal: usize,
+    ) -> Result<usize> {
+        unsafe {
+            sv_call::sv_obj_wait_until(
+                // SAFETY: We don't move the ownership of the handle.
+                unsafe { self.raw() },
+                crate::time::into_ns(deadline),
+                level_triggered,
+                wake_all,
+                signal,
+            )
+            .into_res()
+            .map(|value| value as usize)
+        }
+    }
+
+    /// Wait for the signal of the object to match `signal` as specified by
+    /// `options`, returning the signal matched.
+    fn try_wait_match(
+        &self,
+        timeout: Duration,
+        options: WaitOptions,
+        signal: usize,
+    ) -> Result<usize> {
+        unsafe {
+            sv_call::sv_obj_wait_match(
+                // SAFETY: We don't move the ownership of the handle.
+                unsafe { self.raw() },
+                crate::time::try_into_us(timeout)?,
+                options,
+                signal,
+            )
+            .into_res()
+            .map(|value| value as usize)
+        }
+    }
+
+    /// Like [`Object::try_wait_match`], but until `deadline`.
+    fn try_wait_match_until(
+        &self,
+        deadline: Instant,
+        options: WaitOptions,
+        signal: usize,
+    ) -> Result<usize> {
+        unsafe {
+            sv_call::sv_obj_wait_match_until(
+                // SAFETY: We don't move the ownership of the handle.
+                unsafe { self.raw() },
+                crate::time::into_ns(deadline),
+                options,
+                signal,
+            )
+            .into_res()
+            .map(|value| value as usize)
+        }
+    }
+
     fn reduce_features(self, features: Feature) -> Result<Self>
     where
         Self: Sized,
@@ -112,6 +218,7 @@ pub trait Object: private::Sealed + fmt::Debug {
 ///
 /// The caller must guarantee that the ownership is with the handle.
 pub unsafe fn drop_raw(handle: Handle) -> Result {
+    registry::release(handle);
     unsafe { sv_call::sv_obj_drop(handle) }.into_res()
 }
 
@@ -128,7 +235,7 @@ macro_rules! impl_obj {
                 self.0
             }
 
-            unsafe fn from_raw(raw: sv_call::Handle) -> Self {
+            unsafe fn from_raw_unowned(raw: sv_call::Handle) -> Self {
                 Self(raw)
             }
         }
@@ -168,17 +275,18 @@ impl<'a, T: Object> From<&'a T> for Ref<'a, T> {
 impl<'a, T: Object> Ref<'a, T> {
     /// # Safety
     ///
-    /// The handle must be of the same type as the object.u
+    /// The handle must be of the same type as the object.
     pub unsafe fn from_raw(raw: sv_call::Handle) -> Self {
         Ref {
             marker: PhantomData,
             // SAFETY: The ownership of the handle is not transferred.
-            inner: ManuallyDrop::new(unsafe { T::from_raw(raw) }),
+            inner: ManuallyDrop::new(unsafe { T::from_raw_unowned(raw) }),
         }
     }
 
     pub fn into_raw(this: Self) -> sv_call::Handle {
-        T::into_raw(ManuallyDrop::into_inner(this.inner))
+        // SAFETY: The reference never owns the handle.
+        unsafe { this.inner.raw() }
     }
 }
 
@@ -245,4 +353,17 @@ impl Dispatcher {
         res.key = key as usize;
         Ok(res)
     }
+    /// Change the signal a pending request waits for, returning the current
+    /// signal of the object.
+    ///
+    /// Calling it right after [`push_raw`](Self::push_raw) arms an
+    /// edge-triggered request with a snapshot of the current state. Returns
+    /// [`ENOENT`](sv_call::ENOENT) if the request has already fired.
+    pub fn update_raw(&self, key: usize, signal: usize) -> Result<usize> {
+        let mut snapshot = 0;
+        unsafe {
+            sv_call::sv_disp_update(unsafe { self.raw() }, key, signal, &mut snapshot).into_res()?
+        };
+        Ok(snapshot)
+    }
 }
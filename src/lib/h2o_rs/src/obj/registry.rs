@@ -0,0 +1,156 @@
+//! The registry of the handles owned by the objects in the process.
+//!
+//! Every object constructed with [`Object::from_raw`] claims its handle here
+//! until it's dropped or turned back into a raw handle, so that no two objects
+//! own the same handle and drop it twice or use it after it's dropped.
+//! Claiming a handle that's already owned panics in debug builds and is only
+//! logged in release builds, while [`Object::try_from_raw`] returns an error
+//! instead.
+//!
+//! The registry has a fixed capacity, beyond which the handles are not
+//! tracked.
+//!
+//! [`Object::from_raw`]: super::Object::from_raw
+//! [`Object::try_from_raw`]: super::Object::try_from_raw
+
+use core::{
+    sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering::*},
+    time::Duration,
+};
+
+use sv_call::{Handle, EEXIST};
+
+use crate::{
+    error::Result,
+    sync::{futex_wait, futex_wake},
+};
+
+const BITS: u32 = 14;
+const CAPACITY: usize = 1 << BITS;
+const MASK: usize = CAPACITY - 1;
+/// The limit of the number of the tracked handles, keeping the probes short.
+const MAX_LEN: usize = CAPACITY / 4 * 3;
+
+const EMPTY: u32 = 0;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: AtomicU32 = AtomicU32::new(EMPTY);
+/// The open-addressing hash set of the raw handles, only accessed with the
+/// lock held.
+static SLOTS: [AtomicU32; CAPACITY] = [EMPTY_SLOT; CAPACITY];
+static LEN: AtomicUsize = AtomicUsize::new(0);
+
+const UNLOCKED: u64 = 0;
+const LOCKED: u64 = 1;
+const CONTENDED: u64 = 2;
+static LOCK: AtomicU64 = AtomicU64::new(UNLOCKED);
+
+struct Guard;
+
+impl Guard {
+    fn lock() -> Self {
+        if LOCK
+            .compare_exchange(UNLOCKED, LOCKED, Acquire, Relaxed)
+            .is_err()
+        {
+            while LOCK.swap(CONTENDED, Acquire) != UNLOCKED {
+                futex_wait(&LOCK, CONTENDED, Duration::MAX);
+            }
+        }
+        Guard
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if LOCK.swap(UNLOCKED, Release) == CONTENDED {
+            futex_wake(&LOCK);
+        }
+    }
+}
+
+#[inline]
+fn home(raw: u32) -> usize {
+    (raw.wrapping_mul(0x9e37_79b9) >> (32 - BITS)) as usize
+}
+
+/// Find the slot of `raw`, or the empty slot where it should be.
+fn find(raw: u32) -> Option<(usize, bool)> {
+    let start = home(raw);
+    (0..CAPACITY)
+        .map(|offset| (start + offset) & MASK)
+        .find_map(|index| match SLOTS[index].load(Relaxed) {
+            EMPTY => Some((index, false)),
+            slot if slot == raw => Some((index, true)),
+            _ => None,
+        })
+}
+
+/// Claim the ownership of `handle`, failing with `EEXIST` if it's already
+/// owned by another object.
+#[doc(hidden)]
+pub fn try_claim(handle: Handle) -> Result {
+    if handle.is_null() {
+        return Ok(());
+    }
+    let raw = handle.raw();
+    let _guard = Guard::lock();
+    match find(raw) {
+        Some((_, true)) => Err(EEXIST),
+        Some((index, false)) if LEN.load(Relaxed) < MAX_LEN => {
+            SLOTS[index].store(raw, Relaxed);
+            LEN.fetch_add(1, Relaxed);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Claim the ownership of `handle` for
+/// [`Object::from_raw`](super::Object::from_raw).
+#[doc(hidden)]
+pub fn claim(handle: Handle) {
+    if try_claim(handle).is_err() {
+        if cfg!(debug_assertions) {
+            panic!("{handle:?} is already owned by another object");
+        }
+        log::error!("{handle:?} is already owned by another object");
+    }
+}
+
+/// Give up the ownership of `handle`, after which it's moved elsewhere or
+/// dropped.
+pub(crate) fn release(handle: Handle) {
+    let _guard = Guard::lock();
+    let mut hole = match find(handle.raw()) {
+        Some((index, true)) => index,
+        _ => return,
+    };
+    SLOTS[hole].store(EMPTY, Relaxed);
+    LEN.fetch_sub(1, Relaxed);
+
+    // Shift the following handles of the cluster backward to fill the hole,
+    // unless they're already at or before their home slots.
+    let mut index = hole;
+    loop {
+        index = (index + 1) & MASK;
+        let raw = SLOTS[index].load(Relaxed);
+        if raw == EMPTY {
+            break;
+        }
+        let distance = index.wrapping_sub(home(raw)) & MASK;
+        if distance >= (index.wrapping_sub(hole) & MASK) {
+            SLOTS[hole].store(raw, Relaxed);
+            SLOTS[index].store(EMPTY, Relaxed);
+            hole = index;
+        }
+    }
+}
+
+/// Whether `handle` is owned by an object in the process.
+///
+/// Handles beyond the capacity of the registry are never reported as owned.
+pub fn is_owned(handle: Handle) -> bool {
+    let _guard = Guard::lock();
+    matches!(find(handle.raw()), Some((_, true)))
+}
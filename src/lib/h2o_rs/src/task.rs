@@ -4,18 +4,28 @@ use core::{
     convert::TryInto,
     mem,
     ptr::{null, null_mut, NonNull},
+    sync::atomic::{AtomicU8, Ordering::Relaxed},
     time::Duration,
 };
 
 pub use sv_call::task::{ctx::Gpr, *};
-use sv_call::{ipc::SIG_READ, Error, Handle, SV_SUSPENDTOKEN, SV_TASK};
+use sv_call::{ipc::SIG_GENERIC, Error, Handle, SV_SUSPENDTOKEN, SV_TASK};
 
-use crate::{error::Result, ipc::Channel, mem::Space, obj::Object};
+use crate::{dev::MemRes, error::Result, ipc::Channel, mem::Space, obj::Object, time::Instant};
+
+/// The handles and arguments passed to [`Task::spawn`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpawnArgs<'a> {
+    pub transfers: &'a [HandleTransfer],
+    pub args: &'a [u8],
+    pub env: &'a [u8],
+}
 
 #[repr(transparent)]
 #[derive(Debug)]
 pub struct Task(sv_call::Handle);
 crate::impl_obj!(Task, SV_TASK);
+crate::impl_obj!(@CLONE, Task);
 crate::impl_obj!(@DROP, Task);
 
 impl Task {
@@ -71,6 +81,45 @@ impl Task {
         Ok(unsafe { Self::from_raw(handle) })
     }
 
+    /// Spawn a task with `args.transfers` moved into its handle table and
+    /// the startup arguments built by the kernel queued in its init channel.
+    ///
+    /// # Safety
+    ///
+    /// The handles in `args.transfers` must be owned by the caller and must
+    /// not be used after a successful call.
+    pub unsafe fn spawn(
+        name: Option<&str>,
+        space: Option<Space>,
+        entry: NonNull<u8>,
+        stack: NonNull<u8>,
+        init_chan: Option<Channel>,
+        arg2: u64,
+        args: &SpawnArgs,
+    ) -> Result<Self> {
+        let name = name.map(|name| name.as_bytes());
+        let ci = ExecInfo {
+            name: name.map_or(null(), |name| name.as_ptr()),
+            name_len: name.map_or(0, |name| name.len()),
+            space: space.map_or(Handle::NULL, Space::into_raw),
+            entry: entry.as_ptr(),
+            stack: stack.as_ptr(),
+            init_chan: init_chan.map_or(Handle::NULL, Channel::into_raw),
+            arg: arg2,
+        };
+        let si = SpawnInfo {
+            transfers: args.transfers.as_ptr(),
+            transfer_count: args.transfers.len(),
+            args: args.args.as_ptr(),
+            args_len: args.args.len(),
+            env: args.env.as_ptr(),
+            env_len: args.env.len(),
+        };
+        let handle = sv_call::sv_task_spawn(&ci, &si).into_res()?;
+        // SAFETY: The handle is freshly allocated.
+        Ok(Self::from_raw(handle))
+    }
+
     pub fn try_join(self) -> core::result::Result<usize, (Error, Self)> {
         // SAFETY: We don't move the ownership of the handle...
         let mut ret = Default::default();
@@ -78,7 +127,7 @@ impl Task {
         match res {
             Ok(()) => {
                 // ...unless the operation is successful.
-                mem::forget(self);
+                Self::into_raw(self);
                 Ok(ret)
             }
             Err(err) => Err((err, self)),
@@ -86,10 +135,95 @@ impl Task {
     }
 
     pub fn join(self) -> Result<usize> {
-        self.try_wait(Duration::MAX, true, false, SIG_READ)?;
+        self.try_wait(Duration::MAX, true, false, SIG_GENERIC)?;
         self.try_join().map_err(|(err, _)| err)
     }
 
+    /// Get the return value of the task without consuming the handle.
+    ///
+    /// Returns `ENOENT` if the task hasn't exited yet. Wait for `SIG_GENERIC`
+    /// on the handle to get notified of its exit.
+    pub fn retval(&self) -> Result<usize> {
+        // SAFETY: We don't move the ownership of the handle.
+        let ret = unsafe { sv_call::sv_task_retval(unsafe { self.raw() }).into_res()? };
+        Ok(ret as usize)
+    }
+
+    /// The total time the task has spent running on CPUs.
+    pub fn runtime(&self) -> Result<Duration> {
+        // SAFETY: We don't move the ownership of the handle.
+        let ret = unsafe { sv_call::sv_task_runtime(unsafe { self.raw() }).into_res()? };
+        Ok(Duration::from_nanos(ret))
+    }
+
+    /// The job of the task, shared by all the tasks running in its space.
+    pub fn job(&self) -> Result<u64> {
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe { sv_call::sv_task_job(unsafe { self.raw() }).into_res() }
+    }
+
+    /// The priority of the task (see `TASK_PRIO_*`), including the ones
+    /// inherited from the callers blocked on it.
+    pub fn priority(&self) -> Result<u32> {
+        // SAFETY: We don't move the ownership of the handle.
+        let ret = unsafe { sv_call::sv_task_priority(unsafe { self.raw() }).into_res()? };
+        Ok(ret as u32)
+    }
+
+    /// Set the priority of the task (see `TASK_PRIO_*`), no higher than the
+    /// one set for the current task.
+    pub fn set_priority(&self, priority: u32) -> Result {
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe { sv_call::sv_task_set_priority(unsafe { self.raw() }, priority).into_res() }
+    }
+
+    /// The histogram of the scheduling statistic `query` (see `TASK_STAT_*`)
+    /// of the task, where `index` selects the blocking reason.
+    pub fn stat(&self, query: u32, index: usize) -> Result<SchedStat> {
+        let mut stat = SchedStat::default();
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe {
+            sv_call::sv_task_stat(unsafe { self.raw() }, query, index, &mut stat).into_res()?
+        };
+        Ok(stat)
+    }
+
+    /// Rename the task, with at most `TASK_NAME_LEN` bytes.
+    pub fn set_name(&self, name: &str) -> Result {
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe {
+            sv_call::sv_task_set_name(unsafe { self.raw() }, name.as_ptr(), name.len()).into_res()
+        }
+    }
+
+    /// The name of the task.
+    #[cfg(feature = "alloc")]
+    pub fn name(&self) -> Result<alloc::string::String> {
+        // SAFETY: We don't move the ownership of the handle.
+        get_name(unsafe { self.raw() })
+    }
+
+    /// Restrict the syscalls of the task to those whose numbers are set in the
+    /// bitmap `allowed`, handling the others according to `action`
+    /// (`SYSCALL_FILTER_*`).
+    ///
+    /// The filter only narrows the one already installed, and should be
+    /// installed before starting the task.
+    pub fn filter_syscalls(&self, allowed: &[u64], action: u32) -> Result {
+        // SAFETY: We don't move the ownership of the handle.
+        filter_syscalls_impl(unsafe { self.raw() }, allowed, action)
+    }
+
+    /// Hint the scheduler to run this task right after the current one if
+    /// the current one wakes it up before being switched out.
+    ///
+    /// Call it right before sending a request to the task and waiting for the
+    /// reply.
+    pub fn yield_to(&self) -> Result {
+        // SAFETY: We don't move the ownership of the handle.
+        unsafe { sv_call::sv_task_yield_to(unsafe { self.raw() }).into_res() }
+    }
+
     pub fn kill(&self) -> Result {
         unsafe {
             // SAFETY: We don't move the ownership of the handle.
@@ -193,11 +327,154 @@ pub unsafe fn exit(retval: usize, kill_all: bool) -> ! {
     unreachable!("The task failed to exit");
 }
 
+/// Rename the current task, with at most `TASK_NAME_LEN` bytes.
+pub fn set_name(name: &str) -> Result {
+    unsafe { sv_call::sv_task_set_name(Handle::NULL, name.as_ptr(), name.len()).into_res() }
+}
+
+/// The job of the current task.
+pub fn job() -> Result<u64> {
+    unsafe { sv_call::sv_task_job(Handle::NULL).into_res() }
+}
+
+/// The priority of the current task (see `TASK_PRIO_*`), including the ones
+/// inherited from the callers blocked on it.
+pub fn priority() -> Result<u32> {
+    let ret = unsafe { sv_call::sv_task_priority(Handle::NULL).into_res()? };
+    Ok(ret as u32)
+}
+
+/// Set the priority of the current task (see `TASK_PRIO_*`), which can only
+/// be lowered.
+pub fn set_priority(priority: u32) -> Result {
+    unsafe { sv_call::sv_task_set_priority(Handle::NULL, priority).into_res() }
+}
+
+/// The name of the current task.
+#[cfg(feature = "alloc")]
+pub fn name() -> Result<alloc::string::String> {
+    get_name(Handle::NULL)
+}
+
+#[cfg(feature = "alloc")]
+fn get_name(hdl: Handle) -> Result<alloc::string::String> {
+    let mut buf = alloc::vec::Vec::new();
+    loop {
+        let len =
+            unsafe { sv_call::sv_task_get_name(hdl, buf.as_mut_ptr(), buf.capacity()).into_res()? }
+                as usize;
+        // The task may be renamed between the calls.
+        if len <= buf.capacity() {
+            // SAFETY: The kernel has written `len` bytes.
+            unsafe { buf.set_len(len) };
+            return alloc::string::String::from_utf8(buf).map_err(|_| sv_call::EINVAL);
+        }
+        buf.reserve_exact(len);
+    }
+}
+
+/// Restrict the syscalls of the current task to those whose numbers are set in
+/// the bitmap `allowed`, handling the others according to `action`
+/// (`SYSCALL_FILTER_*`).
+pub fn filter_syscalls(allowed: &[u64], action: u32) -> Result {
+    filter_syscalls_impl(Handle::NULL, allowed, action)
+}
+
+fn filter_syscalls_impl(hdl: Handle, allowed: &[u64], action: u32) -> Result {
+    unsafe { sv_call::sv_task_filter(hdl, allowed.as_ptr(), allowed.len(), action).into_res() }
+}
+
+/// The system-wide histogram of the scheduling statistic `query` of all the
+/// tasks, where `res` must be the root memory resource.
+pub fn sched_stat(res: &MemRes, query: u32, index: usize) -> Result<SchedStat> {
+    let mut stat = SchedStat::default();
+    // SAFETY: We don't move the ownership of the handle.
+    unsafe { sv_call::sv_sched_stat(unsafe { res.raw() }, query, index, &mut stat).into_res()? };
+    Ok(stat)
+}
+
+/// The time accounting of `cpu` and the statistics of its deferred work,
+/// where `res` must be the root memory resource.
+pub fn cpu_stat(res: &MemRes, cpu: usize) -> Result<CpuStat> {
+    let mut stat = CpuStat::default();
+    // SAFETY: We don't move the ownership of the handle.
+    unsafe { sv_call::sv_cpu_stat(unsafe { res.raw() }, cpu, &mut stat).into_res()? };
+    Ok(stat)
+}
+
+/// Sleep for `duration`, which isn't stretched if the task is suspended and
+/// resumed in between.
 pub fn sleep(duration: Duration) -> Result {
     let millis = duration.as_millis().try_into()?;
     unsafe { sv_call::sv_task_sleep(millis).into_res() }
 }
 
+/// Sleep until `deadline`, even if the task is suspended and resumed in
+/// between.
+pub fn sleep_until(deadline: Instant) -> Result {
+    unsafe { sv_call::sv_task_sleep_until(crate::time::into_ns(deadline)).into_res() }
+}
+
+/// Get the value of a task-local slot of the current task, which is zero
+/// until set.
+pub fn local_get(slot: u32) -> Result<usize> {
+    let mut value = 0;
+    unsafe { sv_call::sv_task_local_get(slot, &mut value).into_res()? };
+    Ok(value)
+}
+
+/// Set the value of a task-local slot of the current task.
+///
+/// The slots are meant for the early paths where TLS is not available yet,
+/// such as [`TASK_LOCAL_TCB`].
+pub fn local_set(slot: u32, value: usize) -> Result {
+    unsafe { sv_call::sv_task_local_set(slot, value).into_res() }
+}
+
+/// Whether the thread pointer can be accessed directly with
+/// `{rd,wr}fsbase`, which the kernel enables whenever the processor supports
+/// them.
+pub fn has_fsgsbase() -> bool {
+    static FSGSBASE: AtomicU8 = AtomicU8::new(0);
+    match FSGSBASE.load(Relaxed) {
+        0 => {
+            use core::arch::x86_64::{__cpuid_count, __get_cpuid_max};
+            // SAFETY: CPUID is always available on x86_64.
+            let ret = unsafe { __get_cpuid_max(0).0 >= 7 && __cpuid_count(7, 0).ebx & 1 != 0 };
+            FSGSBASE.store(if ret { 2 } else { 1 }, Relaxed);
+            ret
+        }
+        state => state == 2,
+    }
+}
+
+/// Get the thread pointer of the current task, or `None` if it cannot be read
+/// without the instructions.
+#[inline]
+pub fn get_tls() -> Option<usize> {
+    has_fsgsbase().then(|| {
+        let ret;
+        unsafe { core::arch::asm!("rdfsbase {}", out(reg) ret, options(nostack)) };
+        ret
+    })
+}
+
+/// Set the thread pointer of the current task, falling back to the syscall
+/// if `wrfsbase` is unavailable.
+///
+/// # Safety
+///
+/// The caller must ensure that all the thread-local variables accessed later
+/// are valid under the new thread pointer.
+pub unsafe fn set_tls(value: usize) -> Result {
+    if has_fsgsbase() {
+        core::arch::asm!("wrfsbase {}", in(reg) value, options(nostack));
+        Ok(())
+    } else {
+        sv_call::sv_task_set_tls(value).into_res()
+    }
+}
+
 #[cfg(feature = "stub")]
 #[inline]
 pub fn cpu_num() -> NonZeroUsize {
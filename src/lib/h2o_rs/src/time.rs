@@ -1,5 +1,6 @@
 use core::{ops::*, time::Duration};
 
+pub use sv_call::time::{ClockSource, TimeInfo};
 use sv_call::{ETIME, SV_TIMER};
 
 use crate::{
@@ -30,6 +31,17 @@ impl Instant {
         Self::now() - *self
     }
 
+    /// The time elapsed from `earlier` to `self`, or zero if `earlier` is
+    /// later.
+    #[inline]
+    pub fn saturating_duration_since(&self, earlier: Instant) -> Duration {
+        if *self > earlier {
+            *self - earlier
+        } else {
+            Duration::ZERO
+        }
+    }
+
     /// # Safety
     ///
     /// The underlying data can be inconsistent and should not be used with
@@ -86,6 +98,13 @@ impl Sub<Instant> for Instant {
     }
 }
 
+/// Get the information of the current clock source of the kernel.
+pub fn clock_info() -> Result<TimeInfo> {
+    let mut info = TimeInfo::default();
+    unsafe { sv_call::sv_time_info(&mut info) }.into_res()?;
+    Ok(info)
+}
+
 #[inline]
 pub fn from_us(us: u64) -> Duration {
     if us == u64::MAX {
@@ -103,6 +122,13 @@ pub fn try_into_us(duration: Duration) -> Result<u64> {
     }
 }
 
+/// Convert `deadline` into the nanoseconds taken by the `*_until` syscalls,
+/// saturating to `u64::MAX` which never expires.
+#[inline]
+pub fn into_ns(deadline: Instant) -> u64 {
+    u64::try_from(deadline.0).unwrap_or(u64::MAX)
+}
+
 impl core::fmt::Display for Instant {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let ns = unsafe { self.raw() };
@@ -10,6 +10,7 @@
 #![feature(error_in_core)]
 #![feature(extend_one)]
 #![feature(hashmap_internals)]
+#![feature(int_roundings)]
 #![feature(layout_for_ptr)]
 #![feature(never_type)]
 #![feature(pointer_byte_offsets)]
@@ -29,3 +30,4 @@ pub mod io;
 pub mod path;
 pub mod sync;
 pub mod thread;
+pub mod time;
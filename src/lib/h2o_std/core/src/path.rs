@@ -1829,6 +1829,11 @@ pub struct Path {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StripPrefixError(());
 
+/// An error returned from [`Path::normalize_lexically`] if a `..` component
+/// would move above the start of the path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizeError(());
+
 impl Path {
     // The following (private!) function allows construction of a path from a u8
     // slice, which is only safe when it is known to follow the OsStr encoding.
@@ -2381,6 +2386,54 @@ impl Path {
         buf
     }
 
+    /// Normalizes the path lexically, without accessing the file system.
+    ///
+    /// All `.` components are removed and each `..` component removes the
+    /// normal component before it. The root of the path is kept.
+    ///
+    /// Unlike [`components`], this treats `a/b/../c` and `a/c` as the same
+    /// path, so it should only be used where no symbolic links are involved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a `..` component would move above the root or the
+    /// start of a relative path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::{Path, PathBuf};
+    ///
+    /// let path = Path::new("/foo/./bar/../baz");
+    /// assert_eq!(path.normalize_lexically(), Ok(PathBuf::from("/foo/baz")));
+    ///
+    /// let path = Path::new("./foo/bar");
+    /// assert_eq!(path.normalize_lexically(), Ok(PathBuf::from("foo/bar")));
+    ///
+    /// assert!(Path::new("foo/../..").normalize_lexically().is_err());
+    /// ```
+    ///
+    /// [`components`]: Path::components
+    pub fn normalize_lexically(&self) -> Result<PathBuf, NormalizeError> {
+        let mut lexical = PathBuf::new();
+        let mut depth = 0usize;
+        for component in self.components() {
+            match component {
+                Component::Prefix(_) | Component::RootDir => lexical.push(component),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    depth = depth.checked_sub(1).ok_or(NormalizeError(()))?;
+                    lexical.pop();
+                }
+                Component::Normal(name) => {
+                    depth += 1;
+                    lexical.push(name);
+                }
+            }
+        }
+        Ok(lexical)
+    }
+
     /// Produces an iterator over the [`Component`]s of the path.
     ///
     /// When parsing the path, there is a small amount of normalization:
@@ -2779,3 +2832,11 @@ impl Error for StripPrefixError {
         "prefix not found"
     }
 }
+
+impl fmt::Display for NormalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("parent reference `..` points outside of the base directory")
+    }
+}
+
+impl Error for NormalizeError {}
@@ -0,0 +1,227 @@
+//! Coarse timers on a hashed timing wheel.
+//!
+//! Every timer added here shares one driver thread, which only sleeps until
+//! the earliest pending tick, instead of occupying a kernel timer object on its
+//! own. The precision is limited to [`TICK`], so it suits timeouts of
+//! second-level precision rather than short delays.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::time::Duration;
+
+use solvent::time::Instant;
+
+use crate::{
+    sync::{Arsc, Lazy, Mutex, Parker, Unparker},
+    thread,
+};
+
+/// The resolution of the timing wheel.
+pub const TICK: Duration = Duration::from_millis(10);
+const NUM_SLOTS: usize = 256;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+struct Entry {
+    tick: u64,
+    id: u64,
+    callback: Callback,
+}
+
+struct State {
+    slots: Vec<Vec<Entry>>,
+    /// The last tick that has been processed.
+    current: u64,
+    next_id: u64,
+    len: usize,
+    /// The tick the driver will wake up at, or `None` if it's parked
+    /// indefinitely.
+    armed: Option<u64>,
+}
+
+impl State {
+    fn new() -> Self {
+        State {
+            slots: (0..NUM_SLOTS).map(|_| Vec::new()).collect(),
+            current: 0,
+            next_id: 0,
+            len: 0,
+            armed: None,
+        }
+    }
+
+    /// Take out the callbacks of all the entries expired by `now`.
+    fn advance(&mut self, now: u64, expired: &mut Vec<Callback>) {
+        if now <= self.current {
+            return;
+        }
+        // A full turn of the wheel visits every slot, so there's no need to go
+        // further.
+        let end = now.min(self.current + NUM_SLOTS as u64);
+        for tick in (self.current + 1)..=end {
+            let slot = &mut self.slots[tick as usize % NUM_SLOTS];
+            let mut index = 0;
+            while index < slot.len() {
+                if slot[index].tick <= now {
+                    expired.push(slot.swap_remove(index).callback);
+                } else {
+                    index += 1;
+                }
+            }
+        }
+        self.len -= expired.len();
+        self.current = now;
+    }
+
+    /// The tick the driver should wake up at next.
+    fn next_tick(&self) -> Option<u64> {
+        if self.len == 0 {
+            return None;
+        }
+        let end = self.current.saturating_add(NUM_SLOTS as u64);
+        let next = ((self.current + 1)..=end).find(|&tick| {
+            let slot = &self.slots[tick as usize % NUM_SLOTS];
+            slot.iter().any(|entry| entry.tick == tick)
+        });
+        // The remaining entries are at least a turn away, so check again after
+        // a full turn.
+        Some(next.unwrap_or(end))
+    }
+}
+
+struct Inner {
+    start: Instant,
+    state: Mutex<State>,
+}
+
+impl Inner {
+    /// The first tick at or after `instant`.
+    fn tick_ceil(&self, instant: Instant) -> u64 {
+        if instant <= self.start {
+            return 0;
+        }
+        let nanos = (instant - self.start).as_nanos();
+        u64::try_from(nanos.div_ceil(TICK.as_nanos())).unwrap_or(u64::MAX)
+    }
+
+    /// The last tick at or before `instant`.
+    fn tick_floor(&self, instant: Instant) -> u64 {
+        if instant <= self.start {
+            return 0;
+        }
+        let nanos = (instant - self.start).as_nanos();
+        u64::try_from(nanos / TICK.as_nanos()).unwrap_or(u64::MAX)
+    }
+
+    fn drive(&self, parker: Parker) -> ! {
+        let mut expired = Vec::new();
+        loop {
+            let armed = {
+                let mut state = self.state.lock();
+                state.advance(self.tick_floor(Instant::now()), &mut expired);
+                state.armed = state.next_tick();
+                state.armed
+            };
+            // Run the callbacks without the lock so that they can add or cancel
+            // timers.
+            expired.drain(..).for_each(|callback| callback());
+
+            match armed {
+                Some(tick) => {
+                    let nanos = TICK.as_nanos() * tick as u128;
+                    // SAFETY: The timestamp is derived from a valid one.
+                    let deadline = unsafe { Instant::from_raw(self.start.raw() + nanos) };
+                    parker.park_deadline(deadline)
+                }
+                None => parker.park(),
+            }
+        }
+    }
+}
+
+struct Wheel {
+    inner: Arsc<Inner>,
+    driver: Unparker,
+}
+
+static WHEEL: Lazy<Wheel> = Lazy::new(|| {
+    let inner = Arsc::new(Inner {
+        start: Instant::now(),
+        state: Mutex::new(State::new()),
+    });
+    let parker = Parker::new();
+    let driver = parker.unparker().clone();
+
+    let i2 = inner.clone();
+    thread::Builder::new()
+        .name("timer-wheel".into())
+        .spawn(move || i2.drive(parker))
+        .expect("Failed to spawn the timer wheel driver");
+    Wheel { inner, driver }
+});
+
+/// The token of a timer in the wheel, used to cancel the timer.
+///
+/// Dropping the token doesn't cancel the timer.
+#[derive(Debug)]
+#[must_use = "dropping the token makes the timer uncancellable"]
+pub struct TimerToken {
+    tick: u64,
+    id: u64,
+}
+
+impl TimerToken {
+    /// Cancel the timer, returning `false` if it has already fired.
+    pub fn cancel(self) -> bool {
+        let mut state = WHEEL.inner.state.lock();
+        let slot = &mut state.slots[self.tick as usize % NUM_SLOTS];
+        match slot.iter().position(|entry| entry.id == self.id) {
+            Some(index) => {
+                slot.swap_remove(index);
+                state.len -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Call `callback` on the driver thread of the timing wheel no earlier than
+/// `deadline`.
+///
+/// The callback should be short (e.g. waking up a task), or it will delay the
+/// other timers.
+pub fn add<F>(deadline: Instant, callback: F) -> TimerToken
+where
+    F: FnOnce() + Send + 'static,
+{
+    let wheel = &*WHEEL;
+    let tick = wheel.inner.tick_ceil(deadline);
+
+    let mut state = wheel.inner.state.lock();
+    let tick = tick.max(state.current + 1);
+    let id = state.next_id;
+    state.next_id += 1;
+    state.slots[tick as usize % NUM_SLOTS].push(Entry {
+        tick,
+        id,
+        callback: Box::new(callback),
+    });
+    state.len += 1;
+
+    if state.armed.map_or(true, |armed| tick < armed) {
+        state.armed = Some(tick);
+        wheel.driver.unpark();
+    }
+    TimerToken { tick, id }
+}
+
+/// Call `callback` on the driver thread of the timing wheel after `duration`.
+///
+/// See [`add`] for more information.
+#[inline]
+pub fn add_after<F>(duration: Duration, callback: F) -> TimerToken
+where
+    F: FnOnce() + Send + 'static,
+{
+    add(Instant::now() + duration, callback)
+}
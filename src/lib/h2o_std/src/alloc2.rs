@@ -14,7 +14,8 @@ unsafe fn alloc_pages(n: usize) -> Option<NonNull<[heap::Page]>> {
         Flags::READABLE | Flags::WRITABLE | Flags::EXECUTABLE | Flags::USER_ACCESS
     };
     let (layout, _) = Layout::new::<heap::Page>().repeat(n).ok()?;
-    let phys = solvent::mem::Phys::allocate(layout.size(), Default::default()).ok()?;
+    let phys =
+        solvent::mem::Phys::allocate(layout.size(), solvent::mem::PhysOptions::DEMAND).ok()?;
     let ptr = svrt::root_virt().map(None, phys, 0, layout, flags).ok()?;
     Some(NonNull::slice_from_raw_parts(ptr.cast::<heap::Page>(), n))
 }
@@ -70,9 +71,15 @@ unsafe impl GlobalAlloc for Alloc {
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let range = (*self.temp_buffer.get()).as_ptr_range();
         if !range.contains(&(ptr as _)) {
-            if let Ok(Some(page)) = TCACHE.deallocate(ptr.into(), layout, self.memory.pool()) {
-                let mut pager = self.memory.pager().lock();
-                pager.dealloc_pages(NonNull::slice_from_raw_parts(page, 1))
+            match TCACHE.deallocate(ptr.into(), layout, self.memory.pool()) {
+                Ok(Some(page)) => {
+                    let mut pager = self.memory.pager().lock();
+                    pager.dealloc_pages(NonNull::slice_from_raw_parts(page, 1))
+                }
+                Ok(None) => {}
+                // Large allocations are whole mappings of their own, which are
+                // unmapped by the pager.
+                Err(_) => self.memory.dealloc(ptr, layout),
             }
         }
     }
@@ -0,0 +1,95 @@
+//! Typed access to the configuration service.
+//!
+//! ```ignore
+//! let config = solvent_std::config::Config::connect()?;
+//! let delay = config.get_or("input.repeat.delay_ms", 500i64)?;
+//! config.watch("input.repeat")?;
+//! for change in config.changes(None).unwrap() {
+//!     log::info!("{:?}", change?);
+//! }
+//! ```
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::time::Duration;
+
+use solvent::ipc::Channel;
+pub use solvent_rpc::config::{Change, Error, Value};
+use solvent_rpc::{
+    config::{ConfigEvent, ConfigSyncClient},
+    sync::Client,
+};
+
+/// The path where the program manager mounts the service.
+pub const PATH: &str = "use/configd";
+
+/// A connection to the configuration service.
+#[derive(Debug, Clone)]
+pub struct Config {
+    client: ConfigSyncClient,
+}
+
+impl Config {
+    pub fn connect() -> Result<Self, Error> {
+        let (client, server) = Channel::new();
+        solvent_fs::open_rpc(PATH, server).map_err(|err| Error::RpcError(err.to_string()))?;
+        Ok(Config {
+            client: client.into(),
+        })
+    }
+
+    /// Get the value of `key` as a `T`, or `None` if it's not set.
+    ///
+    /// Returns [`Error::TypeMismatch`] if the value is of another type.
+    pub fn get<T: TryFrom<Value>>(&self, key: &str) -> Result<Option<T>, Error> {
+        match self.client.get(key.into())? {
+            Ok(value) => T::try_from(value)
+                .map(Some)
+                .map_err(|_| Error::TypeMismatch(key.into())),
+            Err(Error::NotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Get the value of `key`, or `default` if it's not set.
+    pub fn get_or<T: TryFrom<Value>>(&self, key: &str, default: T) -> Result<T, Error> {
+        self.get(key).map(|value| value.unwrap_or(default))
+    }
+
+    pub fn set(&self, key: &str, value: impl Into<Value>) -> Result<(), Error> {
+        self.client.set(key.into(), value.into())?
+    }
+
+    pub fn remove(&self, key: &str) -> Result<(), Error> {
+        self.client.remove(key.into())?
+    }
+
+    pub fn list(&self, prefix: &str) -> Result<Vec<(String, Value)>, Error> {
+        Ok(self.client.list(prefix.into())?)
+    }
+
+    /// Receive the changes of the keys under `prefix` from [`Self::changes`].
+    pub fn watch(&self, prefix: &str) -> Result<(), Error> {
+        self.client.watch(prefix.into())?
+    }
+
+    pub fn unwatch(&self, prefix: &str) -> Result<(), Error> {
+        Ok(self.client.unwatch(prefix.into())?)
+    }
+
+    /// The changes of the watched keys, waiting at most `timeout` for each
+    /// one, or `None` if they're already being received.
+    pub fn changes(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Option<impl Iterator<Item = Result<Change, Error>>> {
+        let events = self.client.event_receiver(timeout)?;
+        Some(events.filter_map(|event| match event {
+            Ok(ConfigEvent::Change(change)) => Some(Ok(change)),
+            Ok(ConfigEvent::Unknown(_)) => None,
+            Err(err) => Some(Err(err.into())),
+        }))
+    }
+}
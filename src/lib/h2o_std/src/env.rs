@@ -1,12 +1,37 @@
 use alloc::{
     borrow::ToOwned,
+    collections::BTreeMap,
     string::{String, ToString},
 };
+use core::{
+    ffi::{c_char, c_int, c_void, CStr},
+    fmt,
+    mem::MaybeUninit,
+};
 
-use solvent_core::ffi::{OsStr, OsString};
+use solvent_core::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    sync::{Lazy, Mutex},
+};
+use solvent_rpc::io::Error;
 
 use crate::rt::ARGS;
 
+/// The environment variables of the process, initialized from the start-up
+/// arguments.
+static VARS: Lazy<Mutex<BTreeMap<OsString, OsString>>> = Lazy::new(|| {
+    let vars = svrt::envs().split(|&b| b == 0).filter_map(|s| {
+        let pos = memchr::memchr(b'=', s)?;
+        let (key, value) = s.split_at(pos);
+        Some((
+            OsString::from_vec(key.to_owned()),
+            OsString::from_vec(value[1..].to_owned()),
+        ))
+    });
+    Mutex::new(vars.collect())
+});
+
 pub fn args() -> impl Iterator<Item = String> {
     args_os().map(|s| s.to_str().unwrap().to_string())
 }
@@ -19,24 +44,120 @@ pub fn args_os() -> impl Iterator<Item = &'static OsStr> {
     }
 }
 
+/// Returns a snapshot of the environment variables.
 pub fn vars_os() -> impl Iterator<Item = (OsString, OsString)> {
-    svrt::envs().split(|&b| b == 0).filter_map(|s| {
-        let pos = memchr::memchr(b'=', s)?;
-        let (key, value) = s.split_at(pos);
-        Some((
-            OsString::from_vec(key.to_owned()),
-            OsString::from_vec(value[1..].to_owned()),
-        ))
-    })
+    let vars = VARS.lock().clone();
+    vars.into_iter()
 }
 
 pub fn vars() -> impl Iterator<Item = (String, String)> {
     vars_os().map(|(key, value)| (key.into_string().unwrap(), value.into_string().unwrap()))
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VarError {
+    NotPresent,
+    NotUnicode(OsString),
+}
+
+impl fmt::Display for VarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VarError::NotPresent => write!(f, "environment variable not found"),
+            VarError::NotUnicode(s) => {
+                write!(f, "environment variable was not valid unicode: {s:?}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for VarError {}
+
+pub fn var_os<K: AsRef<OsStr>>(key: K) -> Option<OsString> {
+    VARS.lock().get(key.as_ref()).cloned()
+}
+
+pub fn var<K: AsRef<OsStr>>(key: K) -> Result<String, VarError> {
+    let value = var_os(key).ok_or(VarError::NotPresent)?;
+    value.into_string().map_err(VarError::NotUnicode)
+}
+
+/// Sets the environment variable `key` to `value` for the current process,
+/// which is seen by the subsequent calls to [`var`] and [`vars`].
+///
+/// # Panics
+///
+/// This function panics if `key` is empty or contains `=` or NUL, or `value`
+/// contains NUL.
+pub fn set_var<K: AsRef<OsStr>, V: AsRef<OsStr>>(key: K, value: V) {
+    let (key, value) = (key.as_ref(), value.as_ref());
+    let key_bytes = key.as_bytes();
+    assert!(
+        !key_bytes.is_empty() && !key_bytes.contains(&b'=') && !key_bytes.contains(&0),
+        "Invalid environment variable name: {key:?}"
+    );
+    assert!(
+        !value.as_bytes().contains(&0),
+        "Invalid environment variable value: {value:?}"
+    );
+    VARS.lock().insert(key.to_owned(), value.to_owned());
+}
+
+pub fn remove_var<K: AsRef<OsStr>>(key: K) {
+    VARS.lock().remove(key.as_ref());
+}
+
+/// Returns the absolute path of the current directory in the local FS.
+#[inline]
+pub fn current_dir() -> Result<PathBuf, Error> {
+    Ok(solvent_fs::current_dir())
+}
+
+/// Changes the current directory, against which the relative paths are
+/// resolved by the functions in the local FS.
+///
+/// `CWD` is updated as well, so that it can be passed to child processes.
+pub fn set_current_dir<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    solvent_fs::set_current_dir(path)?;
+    let cwd = solvent_fs::current_dir();
+    VARS.lock().insert("CWD".into(), cwd.into_os_string());
+    Ok(())
+}
+
+#[repr(C)]
+struct DlInfo {
+    dli_fname: *const c_char,
+    dli_fbase: *mut c_void,
+    dli_sname: *const c_char,
+    dli_saddr: *mut c_void,
+}
+
+#[link(name = "ldso")]
+extern "C" {
+    fn dladdr(addr: *const c_void, info: *mut DlInfo) -> c_int;
+}
+
+fn resolve_addr(addr: usize) -> Option<(String, usize)> {
+    let mut info = MaybeUninit::uninit();
+    if unsafe { dladdr(addr as _, info.as_mut_ptr()) } == 0 {
+        return None;
+    }
+    let info = unsafe { info.assume_init() };
+    let name = unsafe { CStr::from_ptr(info.dli_fname) }.to_string_lossy();
+    // The main executable is loaded by the dynamic linker with a placeholder
+    // name, so use the process name instead.
+    let name = match args_os().next() {
+        Some(prog) if name == "<PROGRAM>" => prog.to_string_lossy().into_owned(),
+        _ => name.into_owned(),
+    };
+    Some((name, addr - info.dli_fbase as usize))
+}
+
 #[panic_handler]
 fn rust_begin_unwind(info: &core::panic::PanicInfo) -> ! {
-    log::error!("{}", info);
+    let report = svrt::PanicReport::capture(info.to_string(), resolve_addr);
+    log::error!("{}", report);
+    let _ = report.report();
 
     loop {
         unsafe { core::arch::asm!("pause; ud2") }
@@ -10,7 +10,9 @@
 
 extern crate alloc;
 
+pub mod config;
 pub mod env;
+pub mod logging;
 pub mod rt;
 pub use solvent_core::*;
 mod alloc2;
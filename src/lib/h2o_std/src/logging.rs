@@ -0,0 +1,44 @@
+//! The shared-memory log transport of the log service.
+//!
+//! A task calls [`attach`] once to write its records to a ring drained by
+//! `logd` instead of making a syscall per record:
+//!
+//! ```ignore
+//! if let Err(err) = solvent_std::logging::attach("configd") {
+//!     log::warn!("Failed to attach to the log service: {err:?}");
+//! }
+//! ```
+
+use core::mem;
+
+use solvent::{
+    error::{Error, EINVAL, ENOENT, EPIPE},
+    ipc::Channel,
+    mem::Flags,
+};
+use solvent_rpc::{logging::LogSyncClient, sync::Client};
+
+/// The path where the program manager mounts the service.
+pub const PATH: &str = "use/logd";
+
+/// Write the log records of this task to a ring of the log service from now
+/// on, labelled with `name`.
+pub fn attach(name: &str) -> Result<(), Error> {
+    let (client, server) = Channel::new();
+    solvent_fs::open_rpc(PATH, server).map_err(|_| ENOENT)?;
+    let client = LogSyncClient::from(client);
+    let (ring, doorbell) = client.attach(name.into()).map_err(|_| EPIPE)??;
+
+    let flags = Flags::READABLE | Flags::WRITABLE | Flags::USER_ACCESS;
+    let ring = svrt::root_virt().map_phys(None, ring, flags)?;
+    if ring.len() < dbglog::ring::RING_SIZE {
+        let _ = svrt::root_virt().unmap(ring.cast(), ring.len(), false);
+        return Err(EINVAL);
+    }
+    // SAFETY: The ring is freshly mapped, and only written by the logger.
+    let ring = unsafe { dbglog::ring::Writer::new(ring, doorbell) };
+    dbglog::attach(ring);
+    // The service drops the ring once the connection is closed.
+    mem::forget(client);
+    Ok(())
+}
@@ -66,8 +66,8 @@ pub fn lang_start<R: Termination>(channel: Channel, main: fn() -> R) -> R {
     }
 
     let ret = {
-        let (_, cwd) = env::vars().find(|(key, _)| key == "CWD").unzip();
-        let (_, paths) = env::vars().find(|(key, _)| key == "LFS").unzip();
+        let cwd = env::var("CWD").ok();
+        let paths = env::var("LFS").ok();
         svrt::with_startup_args(|sa| unsafe {
             if let Some(paths) = paths {
                 fs::init_rt(&mut sa.handles, paths.split(','), cwd.as_deref())
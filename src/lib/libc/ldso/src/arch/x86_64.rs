@@ -1,11 +1,8 @@
-use core::arch::asm;
-
 pub unsafe fn get_tls_reg() -> u64 {
-    let mut ret;
-    asm!("rdfsbase {}", out(reg) ret, options(nostack));
-    ret
+    // Without `rdfsbase`, the caller falls back to the task-local slot.
+    solvent::task::get_tls().unwrap_or(0) as u64
 }
 
 pub unsafe fn set_tls_reg(value: u64) {
-    asm!("wrfsbase {}", in(reg) value, options(nostack));
+    solvent::task::set_tls(value as usize).expect("Failed to set the thread pointer");
 }
@@ -12,16 +12,23 @@ use core::{
     marker::PhantomData,
     mem::{self, MaybeUninit},
     num::NonZeroUsize,
+    ops::Range,
     ptr::{self, NonNull},
     slice,
-    sync::atomic::{self, AtomicU32, AtomicUsize, Ordering::*},
+    sync::atomic::{self, AtomicBool, AtomicU32, AtomicUsize, Ordering::*},
     time::Duration,
 };
 
 use canary::Canary;
 use elfload::LoadedElf;
-use solvent::prelude::{Channel, Object, Phys, SIG_READ};
-use solvent_rpc::{loader::GET_OBJECT, packet};
+use solvent::{
+    prelude::{Channel, Object, Phys, SIG_READ},
+    task::TASK_LOCAL_TCB,
+};
+use solvent_rpc::{
+    loader::{ObjectInfo, ObjectQuery, GET_OBJECTS},
+    packet,
+};
 use spin::{Lazy, Mutex, Once, RwLock};
 use svrt::HandleType;
 
@@ -40,11 +47,20 @@ static LDRPC: Lazy<RwLock<Option<Channel>>> = Lazy::new(|| {
 });
 
 static mut DSO_LIST: MaybeUninit<Mutex<DsoList>> = MaybeUninit::uninit();
+static DSO_LIST_INIT: AtomicBool = AtomicBool::new(false);
 
 pub fn dso_list() -> &'static Mutex<DsoList> {
     unsafe { DSO_LIST.assume_init_ref() }
 }
 
+/// Get the DSO list if it's already initialized, used in contexts where
+/// [`init`] may have failed, e.g. the panic handler.
+pub fn try_dso_list() -> Option<&'static Mutex<DsoList>> {
+    DSO_LIST_INIT
+        .load(Acquire)
+        .then(|| unsafe { DSO_LIST.assume_init_ref() })
+}
+
 type IniFn = unsafe extern "C" fn();
 
 #[derive(Debug)]
@@ -112,6 +128,9 @@ pub struct Dso {
 
     _id: u32,
     base: DsoBase,
+    /// The base address the relative relocations are prelinked for.
+    prelink: Option<usize>,
+    range: Range<usize>,
     name: &'static CStr,
 
     dynamic: &'static [Dyn],
@@ -159,6 +178,9 @@ impl Dso {
             })
             .unwrap_or(&[]);
 
+        let (min, max) = elfload::get_addr_range_info(segments);
+        let range = (base.ptr::<u8>(min) as usize)..(base.ptr::<u8>(max) as usize);
+
         let syms = Symbols::from_dynamic(&base, dynamic, None).ok_or(Error::SymbolLoad)?;
 
         Ok(Dso {
@@ -168,6 +190,8 @@ impl Dso {
             fini_link: Default::default(),
             _id: Self::next_id(),
             base,
+            prelink: None,
+            range,
             name,
             dynamic,
             syms,
@@ -228,6 +252,8 @@ impl Dso {
             fini_link: Default::default(),
             _id: Self::next_id(),
             base,
+            prelink: elf.prelink,
+            range: elf.range.clone(),
             name,
             dynamic,
             syms,
@@ -271,6 +297,16 @@ impl Dso {
 }
 
 impl Dso {
+    #[inline]
+    pub fn name(&self) -> &'static CStr {
+        self.name
+    }
+
+    #[inline]
+    pub fn base(&self) -> usize {
+        self.base.get()
+    }
+
     fn dyn_val(&self, tag: u64) -> Option<usize> {
         self.dynamic
             .iter()
@@ -364,6 +400,11 @@ impl DsoList {
         unsafe { self.prog.map(|p| p.as_ref()) }
     }
 
+    /// Find the DSO whose loaded segments contain `addr`.
+    pub fn find_by_addr(&self, addr: usize) -> Option<&Dso> {
+        self.iter().find(|dso| dso.range.contains(&addr))
+    }
+
     pub fn tls(&mut self, id: usize) -> Option<&mut Tls> {
         self.tls.get_mut(id)
     }
@@ -537,9 +578,14 @@ impl DsoList {
         }
 
         dso.relocate.call_once(|| {
-            if dso.base.get() != load_address() {
+            // The relative relocations of a DSO loaded at its prelinked address
+            // are already applied.
+            let delta = dso.base.get().wrapping_sub(dso.prelink.unwrap_or_default());
+            if delta == 0 {
+                log::debug!("{:?}: prelinked at {:?}", dso.name, dso.base);
+            } else if dso.base.get() != load_address() {
                 if let Some((offset, size)) = dso.dyn_val(DT_RELR).zip(dso.dyn_val(DT_RELRSZ)) {
-                    unsafe { apply_relr(dso.base.ptr(0), dso.base.ptr(offset), size) }
+                    unsafe { apply_relr(dso.base.ptr(0), dso.base.ptr(offset), size, delta) }
                 }
             }
 
@@ -719,9 +765,9 @@ impl DsoList {
             data: Vec::new(),
             dtors: Vec::new(),
         });
-        unsafe {
-            crate::arch::set_tls_reg(self.threads.back_mut().unwrap() as *mut Tcb as u64);
-        }
+        let tcb = self.threads.back_mut().unwrap() as *mut Tcb;
+        unsafe { crate::arch::set_tls_reg(tcb as u64) };
+        let _ = solvent::task::local_set(TASK_LOCAL_TCB, tcb as usize);
         if init {
             unsafe { self.init_back_thread() };
         }
@@ -769,6 +815,7 @@ pub fn init() -> Result<(), Error> {
 
         dso_list.push_thread(false);
     }
+    DSO_LIST_INIT.store(true, Release);
     Ok(())
 }
 
@@ -777,9 +824,12 @@ pub fn get_object(path: Vec<CString>) -> Result<Vec<Phys>, Error> {
 
     let lock = LDRPC.read();
     let ldrpc = lock.as_ref().ok_or(Error::DepGet(solvent::error::ENOENT))?;
-    let resp: Result<_, usize> = {
+    let count = path.len();
+    let resp: Vec<Result<ObjectInfo, solvent::error::Error>> = {
+        let queries: Vec<_> = path.into_iter().map(ObjectQuery::from).collect();
         let mut packet = Default::default();
-        packet::serialize(GET_OBJECT, path, &mut packet).map_err(Error::Serde)?;
+        packet::serialize(GET_OBJECTS, (Vec::<CString>::new(), queries), &mut packet)
+            .map_err(Error::Serde)?;
         let id = ID.fetch_add(1, SeqCst);
         packet.id = NonZeroUsize::new(id);
 
@@ -790,15 +840,28 @@ pub fn get_object(path: Vec<CString>) -> Result<Vec<Phys>, Error> {
         ldrpc.receive(&mut packet).map_err(Error::DepGet)?;
         assert_eq!(packet.id, NonZeroUsize::new(id));
 
-        packet::deserialize(GET_OBJECT, &packet, None).map_err(Error::Serde)?
+        packet::deserialize(GET_OBJECTS, &packet, None).map_err(Error::Serde)?
     };
-    match resp {
-        Ok(objs) => Ok(objs),
-        Err(not_found_index) => {
-            log::error!("DT_NEEDED Library at index {} not found", not_found_index);
-            Err(Error::DepGet(solvent::error::ENOENT))
+    if resp.len() != count {
+        return Err(Error::DepGet(solvent::error::ETYPE));
+    }
+
+    let mut objs = Vec::with_capacity(count);
+    let mut first_err = None;
+    for (index, res) in resp.into_iter().enumerate() {
+        // No cached copy is given, so the object is always sent.
+        match res.and_then(|info| info.phys.ok_or(solvent::error::ENOENT)) {
+            Ok(phys) => objs.push(phys),
+            Err(err) => {
+                log::error!("DT_NEEDED Library at index {} not found: {:?}", index, err);
+                first_err.get_or_insert(err);
+            }
         }
     }
+    match first_err {
+        Some(err) => Err(Error::DepGet(err)),
+        None => Ok(objs),
+    }
 }
 
 #[inline]
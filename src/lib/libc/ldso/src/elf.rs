@@ -4,6 +4,7 @@ use core::{alloc::Layout, ffi::CStr, mem, ptr, ptr::NonNull, slice};
 pub use goblin::elf64::{
     dynamic::*, header::*, program_header::*, reloc::*, section_header::*, sym::*, Note,
 };
+use solvent::task::TASK_LOCAL_TCB;
 
 use crate::dso::DsoBase;
 
@@ -15,12 +16,15 @@ pub const DT_RELRENT: u64 = 37;
 
 pub const DT_NUM: u64 = 38;
 
+/// Add `delta` to every word relocated by the RELR entry `relr`, which is the
+/// base address for an object not prelinked.
+///
 /// # Safety
 ///
 /// `base` must contains a valid reference to a statically mapped ELF structure
 /// and `relr` must be the RELR entry in its dynamic section.
 #[inline(always)]
-pub unsafe fn apply_relr(base: *mut u8, relr: *const usize, size: usize) {
+pub unsafe fn apply_relr(base: *mut u8, relr: *const usize, size: usize, delta: usize) {
     let len = size / mem::size_of::<usize>();
 
     let mut i = 0;
@@ -28,7 +32,7 @@ pub unsafe fn apply_relr(base: *mut u8, relr: *const usize, size: usize) {
         let addr = base.add(*relr.add(i)).cast::<usize>();
         i += 1;
 
-        *addr += base as usize;
+        *addr = (*addr).wrapping_add(delta);
 
         let mut addr = addr.add(1);
         while i < len && *relr.add(i) & 1 != 0 {
@@ -40,7 +44,7 @@ pub unsafe fn apply_relr(base: *mut u8, relr: *const usize, size: usize) {
             while bitmask != 0 {
                 let skip = bitmask.trailing_zeros() as usize;
                 run = run.add(skip);
-                *run += base as usize;
+                *run = (*run).wrapping_add(delta);
                 run = run.add(1);
                 bitmask >>= skip + 1;
             }
@@ -311,7 +315,11 @@ impl Tcb {
     ///
     /// The caller must ensure that the current TCB is present.
     pub unsafe fn current() -> &'static mut Tcb {
-        let value = crate::arch::get_tls_reg();
+        let value = match crate::arch::get_tls_reg() {
+            // The thread pointer is not set up yet.
+            0 => solvent::task::local_get(TASK_LOCAL_TCB).unwrap_or_default() as u64,
+            value => value,
+        };
         &mut *(value as *mut Tcb)
     }
 }
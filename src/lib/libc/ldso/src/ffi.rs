@@ -141,6 +141,50 @@ pub extern "C" fn dlerror() -> *const c_char {
     STATUS.swap(ptr::null_mut(), SeqCst)
 }
 
+#[repr(C)]
+pub struct DlInfo {
+    pub dli_fname: *const c_char,
+    pub dli_fbase: *mut c_void,
+    pub dli_sname: *const c_char,
+    pub dli_saddr: *mut c_void,
+}
+
+/// Look up the DSO containing `addr`. Symbol names are not resolved, and
+/// `dli_sname` and `dli_saddr` are always set to null.
+///
+/// This function is called by panic handlers, so it fails instead of waiting
+/// if the DSO list is uninitialized or locked, e.g. by the panicking thread
+/// itself.
+///
+/// # Safety
+///
+/// The caller must ensure that `info` is a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn dladdr(addr: *const c_void, info: *mut DlInfo) -> c_int {
+    let list = match dso::try_dso_list().and_then(|list| list.try_lock()) {
+        Some(list) => list,
+        None => {
+            set_status_str("DSO list unavailable");
+            return 0;
+        }
+    };
+    match list.find_by_addr(addr as usize) {
+        Some(dso) => {
+            info.write(DlInfo {
+                dli_fname: dso.name().as_ptr(),
+                dli_fbase: dso.base() as *mut c_void,
+                dli_sname: ptr::null(),
+                dli_saddr: ptr::null_mut(),
+            });
+            1
+        }
+        None => {
+            set_status_str("Address not found in any DSO");
+            0
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn dldisconn() {
     crate::dso::disconnect_ldrpc()
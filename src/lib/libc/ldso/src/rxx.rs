@@ -1,15 +1,28 @@
+use alloc::string::ToString;
 use core::{
     mem,
     sync::atomic::{self, Ordering::SeqCst},
 };
 
 use solvent::prelude::{Channel, Handle, Object};
+use svrt::PanicReport;
 
-use crate::elf::*;
+use crate::{dso::try_dso_list, elf::*};
 
 #[panic_handler]
 fn rust_begin_unwind(info: &core::panic::PanicInfo) -> ! {
-    log::error!("{}", info);
+    // The DSO list may be locked by the panicking thread itself, so don't wait
+    // for it.
+    let list = try_dso_list().and_then(|list| list.try_lock());
+    let report = PanicReport::capture(info.to_string(), |addr| {
+        let dso = list.as_ref()?.find_by_addr(addr)?;
+        let name = dso.name().to_string_lossy().into_owned();
+        Some((name, addr - dso.base()))
+    });
+    drop(list);
+
+    log::error!("{}", report);
+    let _ = report.report();
 
     loop {
         unsafe { core::arch::asm!("pause; ud2") }
@@ -132,7 +145,7 @@ unsafe extern "C" fn dl_start(init_chan: Handle, vdso_map: usize) -> DlReturn {
     }
 
     if let (Some(relr), Some(size)) = (relr, szrelr) {
-        apply_relr(base.cast(), relr.cast(), size);
+        apply_relr(base.cast(), relr.cast(), size, base as usize);
     }
 
     atomic::fence(SeqCst);
@@ -0,0 +1,91 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use async_trait::async_trait;
+use solvent::{
+    error::{Result, EPIPE},
+    ipc::Channel,
+    mem::Phys,
+};
+use solvent_rpc::ddk::bus::BusClient;
+pub use solvent_rpc::ddk::bus::{DeviceInfo, DmaRegion};
+
+use crate::intr::IntrStream;
+
+/// The bus where the devices of a driver reside.
+#[async_trait(?Send)]
+pub trait Bus: 'static {
+    async fn devices(&self) -> Result<Vec<DeviceInfo>>;
+
+    /// Get the memory-mapped I/O region `bar` of the device at `addr`.
+    async fn mmio(&self, addr: u64, bar: usize) -> Result<Phys>;
+
+    /// Get the interrupt `index` of the device at `addr`.
+    async fn interrupt(&self, addr: u64, index: usize) -> Result<IntrStream>;
+
+    /// Allocate a physically contiguous region sized at least `size` for DMA.
+    async fn dma(&self, size: usize) -> Result<DmaRegion>;
+
+    /// Publish a device node named `name`, whose entry connection is `node`.
+    ///
+    /// See [`crate::publish`] for serving the node.
+    async fn publish(&self, name: &str, node: Channel) -> Result;
+
+    /// Register the power management of the driver, whose client connection is
+    /// `power`.
+    ///
+    /// See [`crate::register_power`] for serving it.
+    async fn register_power(&self, power: Channel) -> Result;
+}
+
+/// The bus served by the parent driver or devm.
+#[derive(Debug, Clone)]
+pub struct RpcBus {
+    client: BusClient,
+}
+
+impl RpcBus {
+    pub fn new(channel: Channel) -> Self {
+        let channel = solvent_async::ipc::Channel::with_disp(channel, crate::dispatch());
+        RpcBus {
+            client: BusClient::new(channel),
+        }
+    }
+}
+
+fn rpc_error(err: solvent_rpc::Error) -> solvent::error::Error {
+    log::warn!("bus RPC error: {err}");
+    EPIPE
+}
+
+#[async_trait(?Send)]
+impl Bus for RpcBus {
+    async fn devices(&self) -> Result<Vec<DeviceInfo>> {
+        self.client.devices().await.map_err(rpc_error)
+    }
+
+    async fn mmio(&self, addr: u64, bar: usize) -> Result<Phys> {
+        self.client.mmio(addr, bar).await.map_err(rpc_error)?
+    }
+
+    async fn interrupt(&self, addr: u64, index: usize) -> Result<IntrStream> {
+        let intr = self
+            .client
+            .interrupt(addr, index)
+            .await
+            .map_err(rpc_error)??;
+        Ok(IntrStream::new(intr))
+    }
+
+    async fn dma(&self, size: usize) -> Result<DmaRegion> {
+        self.client.dma(size).await.map_err(rpc_error)?
+    }
+
+    async fn publish(&self, name: &str, node: Channel) -> Result {
+        let name = name.into();
+        self.client.publish(name, node).await.map_err(rpc_error)?
+    }
+
+    async fn register_power(&self, power: Channel) -> Result {
+        self.client.register_power(power).await.map_err(rpc_error)?
+    }
+}
@@ -0,0 +1,131 @@
+use alloc::vec::Vec;
+
+use solvent::{
+    error::{Result, EBUFFER},
+    mem::PAGE_SIZE,
+};
+use solvent_core::sync::{Arsc, Mutex};
+
+use crate::bus::{Bus, DmaRegion};
+
+struct Pool {
+    bus: Arsc<dyn Bus>,
+    block_size: usize,
+    region_size: usize,
+    free: Mutex<Vec<(Arsc<DmaRegion>, usize)>>,
+}
+
+/// A pool of DMA buffers of the same size, carved from the regions allocated
+/// on a bus.
+#[derive(Clone)]
+pub struct DmaPool {
+    inner: Arsc<Pool>,
+}
+
+impl DmaPool {
+    /// Create a pool of buffers sized `size`, each of which is aligned to
+    /// `align` in the address space of the bus.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `align` is not a power of 2 or larger than the
+    /// size of a page.
+    pub fn new(bus: Arsc<dyn Bus>, size: usize, align: usize) -> Self {
+        assert!(align.is_power_of_two() && align <= PAGE_SIZE);
+        let block_size = size.max(1).next_multiple_of(align);
+        DmaPool {
+            inner: Arsc::new(Pool {
+                bus,
+                block_size,
+                region_size: block_size.next_multiple_of(PAGE_SIZE),
+                free: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    #[inline]
+    pub fn buffer_size(&self) -> usize {
+        self.inner.block_size
+    }
+
+    /// Get a free buffer from the pool, allocating a new region from the bus
+    /// if none is left.
+    pub async fn alloc(&self) -> Result<DmaBuffer> {
+        let block = self.inner.free.lock().pop();
+        let (region, offset) = match block {
+            Some(block) => block,
+            None => {
+                let region = Arsc::new(self.inner.bus.dma(self.inner.region_size).await?);
+                let count = self.inner.region_size / self.inner.block_size;
+
+                let mut free = self.inner.free.lock();
+                free.extend((1..count).map(|i| (Arsc::clone(&region), i * self.inner.block_size)));
+                (region, 0)
+            }
+        };
+        Ok(DmaBuffer {
+            pool: Arsc::clone(&self.inner),
+            region: Some(region),
+            offset,
+        })
+    }
+}
+
+/// A buffer shared with the devices, which returns to its pool when dropped.
+pub struct DmaBuffer {
+    pool: Arsc<Pool>,
+    region: Option<Arsc<DmaRegion>>,
+    offset: usize,
+}
+
+impl DmaBuffer {
+    #[inline]
+    fn region(&self) -> &DmaRegion {
+        self.region.as_ref().unwrap()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pool.block_size
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The address of the buffer seen by the devices.
+    #[inline]
+    pub fn addr(&self) -> u64 {
+        self.region().addr + self.offset as u64
+    }
+
+    fn check(&self, offset: usize, len: usize) -> Result<usize> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => Ok(self.offset + offset),
+            _ => Err(EBUFFER),
+        }
+    }
+
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> Result {
+        let offset = self.check(offset, buf.len())?;
+        self.region().phys.read_into(offset, buf)?;
+        Ok(())
+    }
+
+    pub fn write(&self, offset: usize, buf: &[u8]) -> Result {
+        let offset = self.check(offset, buf.len())?;
+        // SAFETY: The memory is meant to be shared with the devices, and every
+        // access to it is done through the physical object.
+        unsafe { self.region().phys.write(offset, buf) }?;
+        Ok(())
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        if let Some(region) = self.region.take() {
+            self.pool.free.lock().push((region, self.offset));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use async_trait::async_trait;
+use solvent::error::Result;
+use solvent_core::sync::Arsc;
+
+use crate::bus::{Bus, DeviceInfo};
+
+/// An identifier of the devices a driver can drive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceId {
+    /// The device of a specific vendor.
+    Pci { vendor: u16, device: u16 },
+    /// Any device of a class, with a specific programming interface if any.
+    Class {
+        class: u8,
+        subclass: u8,
+        prog_if: Option<u8>,
+    },
+}
+
+impl DeviceId {
+    pub fn matches(&self, info: &DeviceInfo) -> bool {
+        match *self {
+            DeviceId::Pci { vendor, device } => info.vendor == vendor && info.device == device,
+            DeviceId::Class {
+                class,
+                subclass,
+                prog_if,
+            } => {
+                let [c, s, p] = info.class;
+                c == class && s == subclass && prog_if.map_or(true, |prog_if| p == prog_if)
+            }
+        }
+    }
+}
+
+/// The metadata of a driver.
+#[derive(Debug, Clone, Copy)]
+pub struct DriverInfo {
+    pub name: &'static str,
+    pub ids: &'static [DeviceId],
+}
+
+impl DriverInfo {
+    #[inline]
+    pub fn matches(&self, info: &DeviceInfo) -> bool {
+        self.ids.iter().any(|id| id.matches(info))
+    }
+}
+
+#[async_trait(?Send)]
+pub trait Driver: Sized + 'static {
+    const INFO: DriverInfo;
+
+    /// Take over `device` on `bus`, usually by acquiring its resources and
+    /// resetting it.
+    async fn bind(bus: Arsc<dyn Bus>, device: DeviceInfo) -> Result<Self>;
+
+    /// Drive the bound device until it's gone.
+    async fn run(self);
+}
+
+/// Bind `D` to every matching device on `bus`, and run the bound instances
+/// until all of them finish.
+///
+/// Devices that fail to be bound are skipped.
+pub async fn serve<D: Driver>(bus: Arsc<dyn Bus>) -> Result {
+    let devices = bus.devices().await?;
+
+    let tasks = devices.into_iter().filter(|device| D::INFO.matches(device));
+    let tasks = tasks.map(|device| {
+        let bus = Arsc::clone(&bus);
+        crate::spawn(async move {
+            let addr = device.addr;
+            match D::bind(bus, device).await {
+                Ok(driver) => driver.run().await,
+                Err(err) => log::warn!("{}: failed to bind {addr:#x}: {err}", D::INFO.name),
+            }
+        })
+    });
+
+    for task in tasks.collect::<Vec<_>>() {
+        task.await
+    }
+    Ok(())
+}
+
+#[doc(hidden)]
+#[cfg(feature = "ddk")]
+pub async fn __init<D: Driver>(instance: solvent::ipc::Channel) {
+    let bus = Arsc::new(crate::bus::RpcBus::new(instance));
+    if let Err(err) = serve::<D>(bus).await {
+        log::error!("{}: failed to get the devices: {err}", D::INFO.name);
+    }
+}
@@ -0,0 +1,194 @@
+//! A bus living in the same process as the driver, for testing drivers
+//! without real devices.
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::future::Future;
+
+use async_trait::async_trait;
+use solvent::{
+    error::{Result, EBUSY, EEXIST, ENOENT},
+    ipc::Channel,
+    mem::{Phys, PhysOptions},
+};
+use solvent_core::sync::{Arsc, Mutex};
+
+use crate::{
+    bus::{Bus, DeviceInfo, DmaRegion},
+    intr::{IntrStream, IntrTrigger},
+    serve, Driver,
+};
+
+/// The fake bus addresses of DMA regions start from here, so that they're
+/// never mistaken for zero or small offsets.
+const DMA_BASE: u64 = 0x1_0000_0000;
+
+struct Device {
+    info: DeviceInfo,
+    mmio: Vec<Phys>,
+    intrs: Mutex<Vec<Option<IntrStream>>>,
+}
+
+/// The objects of a fake device with which tests play the device side.
+#[derive(Debug)]
+pub struct FakeDevice {
+    /// The MMIO regions backed by ordinary memory.
+    pub mmio: Vec<Phys>,
+    pub intrs: Vec<IntrTrigger>,
+}
+
+pub struct FakeBus {
+    devices: Vec<Device>,
+    dma: Mutex<Vec<DmaRegion>>,
+    nodes: Mutex<Vec<(String, Channel)>>,
+    power: Mutex<Option<Channel>>,
+}
+
+impl FakeBus {
+    pub fn new() -> Self {
+        FakeBus {
+            devices: Vec::new(),
+            dma: Mutex::new(Vec::new()),
+            nodes: Mutex::new(Vec::new()),
+            power: Mutex::new(None),
+        }
+    }
+
+    /// Add a device with MMIO regions sized as `bars` and `intrs` interrupts.
+    pub fn add_device(&mut self, info: DeviceInfo, bars: &[usize], intrs: usize) -> FakeDevice {
+        let mmio = { bars.iter() }
+            .map(|&size| Phys::allocate(size, PhysOptions::ZEROED))
+            .collect::<Result<Vec<_>>>()
+            .expect("Failed to allocate MMIO regions");
+        let (streams, triggers) = (0..intrs)
+            .map(|_| {
+                let (stream, trigger) = IntrStream::fake();
+                (Some(stream), trigger)
+            })
+            .unzip();
+
+        self.devices.push(Device {
+            info,
+            mmio: mmio.clone(),
+            intrs: Mutex::new(streams),
+        });
+        FakeDevice {
+            mmio,
+            intrs: triggers,
+        }
+    }
+
+    fn device(&self, addr: u64) -> Result<&Device> {
+        { self.devices.iter() }
+            .find(|device| device.info.addr == addr)
+            .ok_or(ENOENT)
+    }
+
+    /// Get the DMA region at the address `addr` seen by the device, and the
+    /// offset of the address in it.
+    pub fn dma_region(&self, addr: u64) -> Option<(Phys, usize)> {
+        let dma = self.dma.lock();
+        dma.iter().find_map(|region| {
+            let offset = addr.checked_sub(region.addr)? as usize;
+            (offset < region.phys.len()).then(|| (region.phys.clone(), offset))
+        })
+    }
+
+    /// Take the entry connection of the device node published as `name`.
+    pub fn take_node(&self, name: &str) -> Option<Channel> {
+        let mut nodes = self.nodes.lock();
+        let index = nodes.iter().position(|(n, _)| n == name)?;
+        Some(nodes.swap_remove(index).1)
+    }
+
+    /// Take the power management connection registered by the driver.
+    pub fn take_power(&self) -> Option<Channel> {
+        self.power.lock().take()
+    }
+}
+
+impl Default for FakeBus {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait(?Send)]
+impl Bus for FakeBus {
+    async fn devices(&self) -> Result<Vec<DeviceInfo>> {
+        Ok(self
+            .devices
+            .iter()
+            .map(|device| device.info.clone())
+            .collect())
+    }
+
+    async fn mmio(&self, addr: u64, bar: usize) -> Result<Phys> {
+        let device = self.device(addr)?;
+        device.mmio.get(bar).cloned().ok_or(ENOENT)
+    }
+
+    async fn interrupt(&self, addr: u64, index: usize) -> Result<IntrStream> {
+        let device = self.device(addr)?;
+        let mut intrs = device.intrs.lock();
+        intrs.get_mut(index).ok_or(ENOENT)?.take().ok_or(EBUSY)
+    }
+
+    async fn dma(&self, size: usize) -> Result<DmaRegion> {
+        let phys = Phys::allocate(size, PhysOptions::ZEROED)?;
+
+        let mut dma = self.dma.lock();
+        let addr = dma.last().map_or(DMA_BASE, |last| {
+            let end = last.addr + last.phys.len() as u64;
+            end.next_multiple_of(solvent::mem::PAGE_SIZE as u64)
+        });
+        dma.push(DmaRegion {
+            phys: phys.clone(),
+            addr,
+        });
+        Ok(DmaRegion { phys, addr })
+    }
+
+    async fn publish(&self, name: &str, node: Channel) -> Result {
+        let mut nodes = self.nodes.lock();
+        if nodes.iter().any(|(n, _)| n == name) {
+            return Err(EEXIST);
+        }
+        nodes.push((name.to_string(), node));
+        Ok(())
+    }
+
+    async fn register_power(&self, power: Channel) -> Result {
+        let mut slot = self.power.lock();
+        if slot.is_some() {
+            return Err(EEXIST);
+        }
+        *slot = Some(power);
+        Ok(())
+    }
+}
+
+/// Run `D` against `bus` in the current process until `test` finishes.
+///
+/// `test` plays the devices on the bus, with the objects returned from
+/// [`FakeBus::add_device`]. The driver is dropped along with its instances
+/// afterwards.
+pub fn run<D, F, G>(bus: FakeBus, test: F)
+where
+    D: Driver,
+    F: FnOnce(Arsc<FakeBus>) -> G,
+    G: Future<Output = ()> + 'static,
+{
+    let bus = Arsc::new(bus);
+    let driver = serve::<D>(Arsc::clone(&bus) as Arsc<dyn Bus>);
+    let test = test(bus);
+    solvent_async::block_on(Some(1), async move {
+        let driver = solvent_async::spawn_local(driver);
+        test.await;
+        drop(driver);
+    })
+}
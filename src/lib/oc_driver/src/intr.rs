@@ -0,0 +1,85 @@
+use alloc::boxed::Box;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures_lite::Stream;
+use solvent::{error::Result, time::Instant};
+use solvent_async::{
+    dev::Interrupt,
+    sync::channel::{self, Receiver, Sender},
+};
+use solvent_core::sync::Arsc;
+
+type WaitNext = Pin<Box<dyn Future<Output = Result<Instant>>>>;
+
+enum Inner {
+    Hardware {
+        intr: Arsc<Interrupt>,
+        wait: Option<WaitNext>,
+    },
+    Fake(Receiver<Instant>),
+}
+
+/// A stream of the times when an interrupt is triggered.
+///
+/// The stream ends when a fake interrupt has no [`IntrTrigger`] left, and
+/// yields errors if waiting for a hardware interrupt fails.
+pub struct IntrStream {
+    inner: Inner,
+}
+
+impl IntrStream {
+    pub fn new(intr: solvent::dev::Interrupt) -> Self {
+        let intr = Interrupt::with_disp(intr, crate::dispatch());
+        IntrStream {
+            inner: Inner::Hardware {
+                intr: Arsc::new(intr),
+                wait: None,
+            },
+        }
+    }
+
+    /// Create a stream of an interrupt triggered by software.
+    pub fn fake() -> (Self, IntrTrigger) {
+        let (tx, rx) = channel::unbounded();
+        (
+            IntrStream {
+                inner: Inner::Fake(rx),
+            },
+            IntrTrigger(tx),
+        )
+    }
+}
+
+impl Stream for IntrStream {
+    type Item = Result<Instant>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match &mut self.inner {
+            Inner::Hardware { intr, wait } => {
+                let fut = wait.get_or_insert_with(|| {
+                    let intr = Arsc::clone(intr);
+                    Box::pin(async move { intr.wait_next().await })
+                });
+                let res = ready!(fut.as_mut().poll(cx));
+                *wait = None;
+                Poll::Ready(Some(res))
+            }
+            Inner::Fake(rx) => Pin::new(rx).poll_next(cx).map(|time| time.map(Ok)),
+        }
+    }
+}
+
+/// The trigger of a fake interrupt.
+#[derive(Debug, Clone)]
+pub struct IntrTrigger(Sender<Instant>);
+
+impl IntrTrigger {
+    /// Trigger the interrupt, returning `false` if its stream is dropped.
+    pub fn trigger(&self) -> bool {
+        self.0.try_send(Instant::now()).is_ok()
+    }
+}
@@ -0,0 +1,129 @@
+//! The common scaffolding of the drivers in Oceanic.
+//!
+//! A driver implements [`Driver`] and declares itself with [`driver!`]. The
+//! crate then connects to the bus handed over by drvhost, binds the driver to
+//! every matching device on it and runs the bound instances.
+//!
+//! With the `fake` feature instead of `ddk`, drivers can be run against a
+//! [`fake::FakeBus`] in the current process.
+
+#![no_std]
+#![feature(slice_ptr_len)]
+
+pub mod bus;
+pub mod dma;
+mod driver;
+#[cfg(feature = "fake")]
+pub mod fake;
+pub mod intr;
+pub mod mmio;
+
+use core::future::Future;
+
+use async_task::Task;
+use solvent::{error::Result, ipc::Channel, mem::Virt, obj::Ref};
+use solvent_async::disp::DispSender;
+use solvent_fs::{rpc::RpcNode, Spawner};
+use solvent_rpc::{ddk::power::PowerServer, Server};
+
+#[cfg(feature = "ddk")]
+pub use self::driver::__init;
+pub use self::{
+    bus::Bus,
+    driver::{serve, DeviceId, Driver, DriverInfo},
+};
+
+extern crate alloc;
+
+#[cfg(not(any(feature = "ddk", feature = "fake")))]
+compile_error!("either the `ddk` or the `fake` feature must be enabled");
+
+#[doc(hidden)]
+#[cfg(feature = "ddk")]
+pub use solvent_ddk as __ddk;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "ddk")] {
+        /// Get the dispatcher for the async I/O objects of the driver.
+        #[inline]
+        pub fn dispatch() -> DispSender {
+            solvent_ddk::ffi::dispatch()
+        }
+
+        #[inline]
+        fn root_virt() -> Ref<'static, Virt> {
+            solvent_ddk::ffi::root_virt()
+        }
+
+        /// Spawn a task of the driver on its local executor.
+        pub fn spawn<T: 'static>(fut: impl Future<Output = T> + 'static) -> Task<T> {
+            solvent_ddk::ffi::local_executor(|exe| exe.spawn(fut))
+        }
+    } else {
+        /// Get the dispatcher for the async I/O objects of the driver.
+        #[inline]
+        pub fn dispatch() -> DispSender {
+            solvent_async::dispatch()
+        }
+
+        #[inline]
+        fn root_virt() -> Ref<'static, Virt> {
+            svrt::root_virt()
+        }
+
+        /// Spawn a task of the driver on its local executor.
+        pub fn spawn<T: 'static>(fut: impl Future<Output = T> + 'static) -> Task<T> {
+            solvent_async::spawn_local(fut)
+        }
+    }
+}
+
+/// Publish a device node named `name` on `bus`, every connection to which is
+/// served by `serve`.
+pub async fn publish<S, G, F>(bus: &dyn Bus, name: &str, serve: G) -> Result
+where
+    S: Server + Send + Sync + 'static,
+    G: Fn(S) -> F + Send + Sync + 'static,
+    F: Future<Output = ()> + Send + Sync + 'static,
+{
+    let spawner = Spawner::new(dispatch());
+    spawn(spawner.runner().run()).detach();
+
+    let (node, conn) = Channel::new();
+    let rpc = RpcNode::new(move |server, _| serve(server));
+    rpc.open_conn(spawner, Default::default(), conn);
+    bus.publish(name, node).await
+}
+
+/// Register the power management of the driver on `bus`, which is served by
+/// `serve` until the bus disconnects.
+pub async fn register_power<G, F>(bus: &dyn Bus, serve: G) -> Result
+where
+    G: FnOnce(PowerServer) -> F,
+    F: Future<Output = ()> + 'static,
+{
+    let (power, server) = Channel::new();
+    let server = solvent_async::ipc::Channel::with_disp(server, dispatch());
+    spawn(serve(PowerServer::new(server))).detach();
+    bus.register_power(power).await
+}
+
+/// Declare the driver of the current crate.
+///
+/// The driver type must implement [`Driver`]. Its metadata is exported as
+/// `__OC_DRIVER_INFO`, and the entry of the driver serves it on the bus given
+/// by drvhost.
+#[cfg(feature = "ddk")]
+#[macro_export]
+macro_rules! driver {
+    ($driver:ty) => {
+        #[no_mangle]
+        static __OC_DRIVER_INFO: $crate::DriverInfo = <$driver as $crate::Driver>::INFO;
+
+        async fn __oc_driver_init(instance: solvent::ipc::Channel) {
+            $crate::__init::<$driver>(instance).await
+        }
+
+        $crate::__ddk::entry!(__oc_driver_init);
+    };
+}
@@ -0,0 +1,94 @@
+use core::ptr::NonNull;
+
+use solvent::{
+    error::Result,
+    mem::{Flags, Phys},
+};
+
+/// A memory-mapped I/O region of a device.
+///
+/// The region is mapped uncached, and every access to it is volatile.
+#[derive(Debug)]
+pub struct Mmio {
+    base: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: The region is only accessed with volatile operations.
+unsafe impl Send for Mmio {}
+unsafe impl Sync for Mmio {}
+
+impl Mmio {
+    pub fn new(phys: Phys) -> Result<Self> {
+        let flags = Flags::READABLE | Flags::WRITABLE | Flags::UNCACHED;
+        let ptr = crate::root_virt().map_phys(None, phys, flags)?;
+        Ok(Mmio {
+            base: ptr.cast(),
+            len: ptr.len(),
+        })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    #[track_caller]
+    fn ptr<T>(&self, offset: usize) -> *mut T {
+        assert!(
+            offset % core::mem::align_of::<T>() == 0
+                && offset + core::mem::size_of::<T>() <= self.len,
+            "invalid MMIO offset {offset:#x}"
+        );
+        // SAFETY: The offset is checked above.
+        unsafe { self.base.as_ptr().add(offset).cast() }
+    }
+
+    /// # Panics
+    ///
+    /// This function panics if `offset` is not aligned or out of the region.
+    #[inline]
+    pub fn read32(&self, offset: usize) -> u32 {
+        // SAFETY: The pointer is valid and aligned.
+        unsafe { self.ptr::<u32>(offset).read_volatile() }
+    }
+
+    /// # Panics
+    ///
+    /// This function panics if `offset` is not aligned or out of the region.
+    #[inline]
+    pub fn write32(&self, offset: usize, value: u32) {
+        // SAFETY: The pointer is valid and aligned.
+        unsafe { self.ptr::<u32>(offset).write_volatile(value) }
+    }
+
+    /// # Panics
+    ///
+    /// This function panics if `offset` is not aligned or out of the region.
+    #[inline]
+    pub fn read64(&self, offset: usize) -> u64 {
+        // SAFETY: The pointer is valid and aligned.
+        unsafe { self.ptr::<u64>(offset).read_volatile() }
+    }
+
+    /// # Panics
+    ///
+    /// This function panics if `offset` is not aligned or out of the region.
+    #[inline]
+    pub fn write64(&self, offset: usize, value: u64) {
+        // SAFETY: The pointer is valid and aligned.
+        unsafe { self.ptr::<u64>(offset).write_volatile(value) }
+    }
+}
+
+impl Drop for Mmio {
+    fn drop(&mut self) {
+        let _ = crate::root_virt().unmap(self.base, self.len, false);
+    }
+}
@@ -1,9 +1,10 @@
 #![no_std]
 #![feature(iterator_try_collect)]
 
+mod panic;
 mod sa;
 mod statics;
 
 extern crate alloc;
 
-pub use self::{sa::*, statics::*};
+pub use self::{panic::*, sa::*, statics::*};
@@ -0,0 +1,133 @@
+use alloc::{string::String, vec::Vec};
+use core::{fmt, mem};
+
+use solvent::{
+    prelude::{Channel, Object, Packet, Result, ETYPE},
+    task::DEFAULT_STACK_SIZE,
+};
+use solvent_rpc::SerdePacket;
+use solvent_rpc_core as solvent_rpc;
+
+use crate::HandleType;
+
+pub(crate) const PANIC_REPORT: usize = 0x2b3d64c71;
+
+const MAX_FRAMES: usize = 32;
+
+#[derive(SerdePacket, Debug, Clone)]
+pub struct PanicFrame {
+    /// The address of the call site, i.e. the return address minus 1.
+    pub addr: usize,
+    /// The name of the module containing `addr`, if resolved.
+    pub module: Option<String>,
+    /// The offset of `addr` from the base of `module`.
+    pub offset: usize,
+}
+
+impl fmt::Display for PanicFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.module {
+            Some(ref module) => write!(f, "{module}+{:#x} ({:#x})", self.offset, self.addr),
+            None => write!(f, "<unknown> ({:#x})", self.addr),
+        }
+    }
+}
+
+#[derive(SerdePacket, Debug, Clone, Default)]
+pub struct PanicReport {
+    pub message: String,
+    pub frames: Vec<PanicFrame>,
+}
+
+impl fmt::Display for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for (index, frame) in self.frames.iter().enumerate() {
+            write!(f, "\n  #{index} {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl PanicReport {
+    /// Capture the backtrace of the current thread by walking the frame
+    /// pointer chain, resolving every address into its module name and offset
+    /// with `resolve`.
+    #[inline(never)]
+    pub fn capture<F>(message: String, mut resolve: F) -> Self
+    where
+        F: FnMut(usize) -> Option<(String, usize)>,
+    {
+        let mut frames = Vec::new();
+        trace(|addr| {
+            let (module, offset) = resolve(addr).unzip();
+            frames.push(PanicFrame {
+                addr,
+                module,
+                offset: offset.unwrap_or_default(),
+            });
+        });
+        PanicReport { message, frames }
+    }
+
+    pub fn send(self, channel: &Channel, storage: &mut Packet) -> Result {
+        solvent_rpc::packet::serialize(PANIC_REPORT, self, storage).map_err(|_| ETYPE)?;
+        channel.send(storage)
+    }
+
+    /// Send the report to the parent process through the start-up handle of
+    /// [`HandleType::PanicReport`].
+    pub fn report(self) -> Result {
+        let handle = crate::try_take_startup_handle(HandleType::PanicReport.into())?;
+        // SAFETY: The ownership of the handle is transferred by the start-up
+        // args.
+        let channel = unsafe { Channel::from_raw(handle) };
+        self.send(&channel, &mut Default::default())
+    }
+
+    pub fn receive(channel: &Channel) -> Result<Self> {
+        let mut packet = Default::default();
+        channel.receive(&mut packet)?;
+        solvent_rpc::packet::deserialize(PANIC_REPORT, &packet, None).map_err(|_| ETYPE)
+    }
+}
+
+/// Walk the frame pointer chain of the current thread, calling `f` with the
+/// call site of every frame.
+///
+/// The walk stops at a null or misaligned frame pointer, which is set up by
+/// `_start` as the outermost frame, or after `MAX_FRAMES` frames. Since the
+/// bounds of the stack are unknown here, it also stops when a frame leaves
+/// the range from the current stack pointer up to the default stack size, so
+/// that a corrupted chain can't fault in the middle of a panic.
+#[inline(never)]
+pub fn trace<F: FnMut(usize)>(mut f: F) {
+    let (mut rbp, rsp): (*const usize, usize);
+    unsafe {
+        core::arch::asm!("mov {}, rbp", "mov {}, rsp", out(reg) rbp, out(reg) rsp);
+    }
+    let stack = rsp..rsp.saturating_add(DEFAULT_STACK_SIZE);
+
+    for _ in 0..MAX_FRAMES {
+        let frame = rbp as usize;
+        if frame % mem::align_of::<usize>() != 0
+            || !stack.contains(&frame)
+            || !stack.contains(&(frame + 2 * mem::size_of::<usize>() - 1))
+        {
+            break;
+        }
+        // SAFETY: Every frame with frame pointers enabled stores the frame
+        // pointer of its caller followed by the return address, and both are
+        // inside the stack.
+        let (next, ret) = unsafe { (*rbp as *const usize, *rbp.add(1)) };
+        if ret == 0 {
+            break;
+        }
+        f(ret - 1);
+        // The stack grows downwards, so callers' frames must be above ours.
+        if next <= rbp {
+            break;
+        }
+        rbp = next;
+    }
+}
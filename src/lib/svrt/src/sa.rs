@@ -19,6 +19,14 @@ pub enum HandleType {
     LoadRpc,
     BootfsPhys,
     LocalFs,
+    PanicReport,
+    /// The connection to the entry served by the process.
+    ServiceEntry,
+    /// The framebuffer left by the boot loader, whose mode is described by
+    /// the `FB_MODE` environment variable.
+    FramebufferPhys,
+    /// The root memory resource, which also controls the power of the system.
+    MemRes,
 }
 
 #[derive(Copy, Clone)]
@@ -68,7 +76,7 @@ impl Ord for HandleInfo {
     }
 }
 
-pub(crate) const STARTUP_ARGS: usize = 0x1873ddab8;
+pub(crate) use solvent::task::STARTUP_ARGS;
 
 #[derive(SerdePacket, Default)]
 pub struct StartupArgs {
@@ -44,6 +44,9 @@ pub fn init_rt(init_chan: &Channel) -> Result<Vec<u8>> {
                         let args = unsafe {
                             let args = STARTUP_ARGS.write(args);
                             ROOT_VIRT = args.root_virt();
+                            if let Some(virt) = ROOT_VIRT.as_ref() {
+                                solvent::ipc::set_pages_virt(virt);
+                            }
                             ENVS = &args.env;
                             mem::take(&mut args.args)
                         };
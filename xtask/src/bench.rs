@@ -0,0 +1,182 @@
+use std::{collections::BTreeMap, fs, path::Path, time::Duration};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use crate::{dist::Dist, test::run_qemu, DEBUG_DIR};
+
+const BENCH_RESULT: &str = "bench result: done";
+const BENCH_LOG: &str = "bench.log";
+const BENCH_JSON: &str = "bench.json";
+/// The baseline of the results, relative to the source root.
+const BASELINE: &str = "bench.toml";
+const BASELINE_HEADER: &str = "\
+# The baseline of the IPC benchmarks checked by `cargo xtask bench`.
+#
+# A result regresses if it's worse than its baseline by more than `tolerance`
+# percent, which can be overridden per entry. The results in the units ending
+# with `/s` are higher-is-better, and the others are lower-is-better.
+#
+# Regenerate with `cargo xtask bench --update-baseline` on the reference
+# machine; the tolerances are kept.
+";
+
+/// Run the IPC benchmarks in QEMU and compare the results against the
+/// baseline.
+#[derive(Debug, StructOpt)]
+pub struct Bench {
+    #[structopt(long = "--release", parse(from_flag))]
+    release: bool,
+    /// The seconds to wait for the benchmarks to finish.
+    #[structopt(long = "--timeout", default_value = "300")]
+    timeout: u64,
+    /// Replace the values in the baseline with the results instead.
+    #[structopt(long = "--update-baseline", parse(from_flag))]
+    update_baseline: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    value: f64,
+    unit: String,
+    /// Overrides the default tolerance in percent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tolerance: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Baseline {
+    /// The default tolerance in percent.
+    tolerance: f64,
+    #[serde(default)]
+    entry: BTreeMap<String, Entry>,
+}
+
+impl Bench {
+    pub fn new(release: bool, timeout: u64) -> Self {
+        Bench {
+            release,
+            timeout,
+            update_baseline: false,
+        }
+    }
+
+    pub fn run(self) -> anyhow::Result<()> {
+        let src_root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+
+        Dist::bench(self.release)
+            .build()
+            .context("failed to build the system with the benchmarks")?;
+
+        println!("Running the benchmarks in QEMU");
+        let timeout = Duration::from_secs(self.timeout);
+        let output =
+            run_qemu(src_root, BENCH_LOG, timeout, &[BENCH_RESULT]).context("benchmarks failed")?;
+        let results = parse(&output);
+        if results.is_empty() {
+            bail!("no benchmark results found");
+        }
+        let json = serde_json::to_string_pretty(&results)?;
+        fs::write(src_root.join(DEBUG_DIR).join(BENCH_JSON), json)?;
+
+        let path = src_root.join(BASELINE);
+        let baseline =
+            fs::read_to_string(&path).with_context(|| format!("failed to read {path:?}"))?;
+        let mut baseline: Baseline =
+            toml::from_str(&baseline).with_context(|| format!("failed to parse {path:?}"))?;
+
+        if self.update_baseline {
+            for (name, result) in results {
+                let tolerance = baseline.entry.get(&name).and_then(|entry| entry.tolerance);
+                baseline.entry.insert(
+                    name,
+                    Entry {
+                        tolerance,
+                        ..result
+                    },
+                );
+            }
+            let content = BASELINE_HEADER.to_string() + &toml::to_string_pretty(&baseline)?;
+            fs::write(&path, content)?;
+            println!("Updated the baseline {path:?}");
+            return Ok(());
+        }
+        compare(&results, &baseline)
+    }
+}
+
+/// Collect the `bench: <name> = <value> <unit>` lines printed by the
+/// benchmarks.
+fn parse(output: &str) -> BTreeMap<String, Entry> {
+    let mut results = BTreeMap::new();
+    for line in output.lines() {
+        let Some((_, result)) = line.split_once("bench: ") else {
+            continue;
+        };
+        let Some((name, result)) = result.split_once(" = ") else {
+            continue;
+        };
+        let Some((value, unit)) = result.trim().split_once(' ') else {
+            continue;
+        };
+        let Ok(value) = value.parse() else { continue };
+        let entry = Entry {
+            value,
+            unit: unit.to_string(),
+            tolerance: None,
+        };
+        results.insert(name.trim().to_string(), entry);
+    }
+    results
+}
+
+fn compare(results: &BTreeMap<String, Entry>, baseline: &Baseline) -> anyhow::Result<()> {
+    let mut regressions = 0;
+    for (name, result) in results {
+        let Some(base) = baseline.entry.get(name) else {
+            println!(
+                "{name:<24} {:>12} {:<6} (not in the baseline)",
+                result.value, result.unit
+            );
+            continue;
+        };
+        if base.unit != result.unit {
+            println!(
+                "{name:<24} unit changed from {} to {}",
+                base.unit, result.unit
+            );
+            regressions += 1;
+            continue;
+        }
+
+        let change = (result.value - base.value) / base.value * 100.;
+        let worse = if result.unit.ends_with("/s") {
+            -change
+        } else {
+            change
+        };
+        let tolerance = base.tolerance.unwrap_or(baseline.tolerance);
+        let regressed = worse > tolerance;
+        println!(
+            "{name:<24} {:>12} {:<6} ({change:+.1}% from {}){}",
+            result.value,
+            result.unit,
+            base.value,
+            if regressed { " REGRESSED" } else { "" }
+        );
+        regressions += regressed as usize;
+    }
+    for name in baseline.entry.keys() {
+        if !results.contains_key(name) {
+            println!("{name:<24} missing from the results");
+            regressions += 1;
+        }
+    }
+
+    if regressions > 0 {
+        bail!("{regressions} benchmark(s) regressed beyond the tolerance");
+    }
+    println!("No benchmark regressed beyond the tolerance");
+    Ok(())
+}
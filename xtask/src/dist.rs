@@ -1,14 +1,14 @@
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, BTreeSet, HashSet},
     env,
     ffi::OsString,
-    fs,
+    fs, io,
     path::{Path, PathBuf},
     process::Command,
     sync::LazyLock,
 };
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use structopt::StructOpt;
 
 use crate::{
@@ -18,12 +18,12 @@ use crate::{
 static CARGO: LazyLock<OsString> =
     LazyLock::new(|| env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
 
-static LLVM: LazyLock<OsString> =
+pub(crate) static LLVM: LazyLock<OsString> =
     LazyLock::new(|| env::var_os("LLVM_PATH").unwrap_or_else(|| "/usr/lib/llvm-14".into()));
 
 static LLVM_OBJCOPY: LazyLock<PathBuf> =
     LazyLock::new(|| Path::new(&*LLVM).join("bin/llvm-objcopy"));
-static LLVM_OBJDUMP: LazyLock<PathBuf> =
+pub(crate) static LLVM_OBJDUMP: LazyLock<PathBuf> =
     LazyLock::new(|| Path::new(&*LLVM).join("bin/llvm-objdump"));
 static LLVM_IFS: LazyLock<PathBuf> = LazyLock::new(|| Path::new(&*LLVM).join("bin/llvm-ifs"));
 
@@ -38,9 +38,67 @@ pub struct Dist {
     ty: Type,
     #[structopt(long = "--release", parse(from_flag))]
     release: bool,
+    /// Build the kernel with the in-kernel unit tests.
+    #[structopt(long = "--ktest", parse(from_flag))]
+    ktest: bool,
+    /// Build the kernel with the fault injection for testing error paths.
+    #[structopt(long = "--fault-inject", parse(from_flag))]
+    fault_inject: bool,
+    /// Build the kernel to run the benchmarks after booting.
+    #[structopt(long = "--bench", parse(from_flag))]
+    bench: bool,
+    /// Prelink the shared libraries at fixed addresses to speed up the
+    /// process startup, at the cost of their address randomization.
+    #[structopt(long = "--prelink", parse(from_flag))]
+    prelink: bool,
+    /// Build the same artifacts from the same sources, with the paths of the
+    /// build machine stripped, no incremental compilation, and the syscalls
+    /// numbered with the seed of `SOURCE_DATE_EPOCH` (or 0).
+    #[structopt(long = "--reproducible", parse(from_flag))]
+    reproducible: bool,
+    /// Build reproducibly from scratch twice, and fail if any of the artifacts
+    /// differs.
+    #[structopt(long = "--verify-repro", parse(from_flag))]
+    verify_repro: bool,
 }
 
+/// The directories of the intermediate artifacts, relative to the target
+/// directory, removed before the second build of `--verify-repro`.
+const BUILD_DIRS: &[&str] = &[
+    "x86_64-unknown-uefi",
+    "x86_64-h2o-kernel",
+    "x86_64-h2o-tinit",
+    "x86_64-pc-oceanic",
+    "bootfs",
+];
+
 impl Dist {
+    pub fn ktest(release: bool) -> Self {
+        Dist {
+            ty: Type::Img,
+            release,
+            ktest: true,
+            fault_inject: true,
+            bench: false,
+            prelink: false,
+            reproducible: false,
+            verify_repro: false,
+        }
+    }
+
+    pub fn bench(release: bool) -> Self {
+        Dist {
+            ty: Type::Img,
+            release,
+            ktest: false,
+            fault_inject: false,
+            bench: true,
+            prelink: false,
+            reproducible: false,
+            verify_repro: false,
+        }
+    }
+
     fn profile(&self) -> &'static str {
         if self.release {
             "release"
@@ -50,6 +108,63 @@ impl Dist {
     }
 
     pub fn build(self) -> Result<(), anyhow::Error> {
+        if self.verify_repro {
+            let dist = Dist {
+                reproducible: true,
+                verify_repro: false,
+                ..self
+            };
+            return dist.verify_repro();
+        }
+        self.build_once()
+    }
+
+    /// Build twice from scratch and compare the artifacts.
+    fn verify_repro(self) -> anyhow::Result<()> {
+        let src_root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+        let target_root =
+            env::var_os("CARGO_TARGET_DIR").map_or_else(|| src_root.join("target"), PathBuf::from);
+        let snapshot = || -> anyhow::Result<BTreeMap<String, Vec<u8>>> {
+            let mut artifacts = crate::report::collect(&target_root)?;
+            artifacts.insert("img/efi.img".to_string(), target_root.join("img/efi.img"));
+            artifacts
+                .into_iter()
+                .map(|(name, path)| Ok((name, fs::read(path)?)))
+                .collect()
+        };
+
+        println!("Building for the first time");
+        self.build_once()?;
+        let first = snapshot()?;
+
+        println!("Building again from scratch");
+        for dir in BUILD_DIRS {
+            match fs::remove_dir_all(target_root.join(dir)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        self.build_once()?;
+        let second = snapshot()?;
+
+        let mut diffs = 0;
+        for name in first.keys().chain(second.keys()).collect::<BTreeSet<_>>() {
+            match (first.get(name), second.get(name)) {
+                (Some(a), Some(b)) if a == b => continue,
+                (Some(_), Some(_)) => println!("{name} differs"),
+                (Some(_), None) => println!("{name} is missing from the second build"),
+                (None, _) => println!("{name} is missing from the first build"),
+            }
+            diffs += 1;
+        }
+        if diffs > 0 {
+            bail!("{diffs} artifact(s) are not reproducible");
+        }
+        println!("All {} artifacts are reproducible", first.len());
+        Ok(())
+    }
+
+    fn build_once(&self) -> Result<(), anyhow::Error> {
         let src_root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
         let target_root = env::var("CARGO_TARGET_DIR")
             .unwrap_or_else(|_| src_root.join("target").to_string_lossy().to_string());
@@ -57,12 +172,22 @@ impl Dist {
         create_dir_all(&target_root, src_root)?;
 
         // Generate syscall stubs
+        let seed = self
+            .reproducible
+            .then(|| {
+                env::var("SOURCE_DATE_EPOCH").map_or(Ok(0), |epoch| {
+                    epoch.parse().context("invalid SOURCE_DATE_EPOCH")
+                })
+            })
+            .transpose()?;
         crate::gen::gen_syscall(
+            seed,
             src_root.join(H2O_KERNEL).join("syscall"),
             src_root.join(H2O_KERNEL).join("target/wrapper.rs"),
             src_root.join("h2o/libs/syscall/target/call.rs"),
             src_root.join("h2o/libs/syscall/target/stub.rs"),
             src_root.join("h2o/libs/syscall/target/num.rs"),
+            src_root.join("h2o/libs/syscall/target/sig.rs"),
         )
         .context("failed to generate syscalls")?;
 
@@ -81,12 +206,23 @@ impl Dist {
             .context("failed to build VDSO")?;
 
         // Build h2o_kernel
-        self.build_impl(
+        let mut cfg = Vec::new();
+        if self.ktest {
+            cfg.extend(["--cfg", "ktest"]);
+        }
+        if self.fault_inject {
+            cfg.extend(["--cfg", "fault_inject"]);
+        }
+        if self.bench {
+            cfg.extend(["--cfg", "bench"]);
+        }
+        self.build_impl_with(
             "h2o",
             "KERNEL",
             src_root.join(H2O_KERNEL),
             Path::new(&target_root).join("x86_64-h2o-kernel"),
             &target_root,
+            &cfg,
         )
         .context("failed to build h2o_kernel")?;
 
@@ -104,19 +240,18 @@ impl Dist {
             .context("failed to build libraries")?;
         self.build_bin(src_root, &target_root)
             .context("failed to build binaries or drivers")?;
+        if self.prelink {
+            crate::prelink::prelink(Path::new(&target_root).join("bootfs/lib"))
+                .context("failed to prelink libraries")?;
+        }
 
-        crate::gen::gen_bootfs(Path::new(BOOTFS).join("../BOOT.fs"))
+        crate::gen::gen_bootfs(src_root, Path::new(BOOTFS).join("../BOOT.fs"))
             .context("failed to generate BOOTFS")?;
 
         match &self.ty {
             Type::Img => {
-                // Generate img
                 println!("Generating a hard disk image file");
-                Command::new("sh")
-                    .current_dir(src_root)
-                    .arg("scripts/genimg.sh")
-                    .status()?
-                    .exit_ok()?;
+                crate::image::gen_image(&target_root).context("failed to generate the image")?;
             }
         }
         Ok(())
@@ -129,9 +264,9 @@ impl Dist {
 
         println!("Building VDSO");
 
-        let mut cmd = Command::new(&*CARGO);
+        let mut cmd = self.cargo(&cd)?;
 
-        let cmd = cmd.current_dir(&cd).arg("rustc").args([
+        let cmd = cmd.arg("rustc").args([
             "--crate-type=cdylib",
             &format!("--target={}", target_triple.to_string_lossy()),
             "-Zunstable-options",
@@ -236,38 +371,38 @@ impl Dist {
             .map(ToString::to_string)
             .collect::<HashSet<_>>();
 
-        let mut build = |src_root: PathBuf,
-                         dst_root: PathBuf,
-                         is_dylib: bool|
-         -> anyhow::Result<()> {
-            for ent in fs::read_dir(src_root)?.flatten() {
-                let ty = ent.file_type()?;
-                let name = ent.file_name();
-                if ty.is_dir() && name != ".cargo" {
-                    let dst_name = if is_dylib {
-                        let name = name.to_string_lossy().replace('-', "_");
-                        let name = "lib".to_string() + &name + ".so";
-                        OsString::from(name)
-                    } else {
-                        name
-                    };
-                    self.build_impl(&dst_name, &dst_name, ent.path(), &bin_dir, &dst_root)?;
-                    for dep in fs::read_dir(bin_dir.join(self.profile()).join("deps"))?.flatten() {
-                        let name = dep.file_name();
-                        match name.to_str() {
-                            Some(name)
-                                if name.ends_with(".so") && dep_lib.insert(name.to_string()) =>
-                            {
-                                fs::copy(dep.path(), dep_root.join(name))?;
-                                self.gen_debug(name, &dep_root, DEBUG_DIR)?;
+        let mut build =
+            |src_root: PathBuf, dst_root: PathBuf, is_dylib: bool| -> anyhow::Result<()> {
+                for ent in read_dir_sorted(src_root)? {
+                    let ty = ent.file_type()?;
+                    let name = ent.file_name();
+                    if ty.is_dir() && name != ".cargo" {
+                        let dst_name = if is_dylib {
+                            let name = name.to_string_lossy().replace('-', "_");
+                            let name = "lib".to_string() + &name + ".so";
+                            OsString::from(name)
+                        } else {
+                            name
+                        };
+                        self.build_impl(&dst_name, &dst_name, ent.path(), &bin_dir, &dst_root)?;
+                        let deps = bin_dir.join(self.profile()).join("deps");
+                        for dep in read_dir_sorted(deps)? {
+                            let name = dep.file_name();
+                            match name.to_str() {
+                                Some(name)
+                                    if name.ends_with(".so")
+                                        && dep_lib.insert(name.to_string()) =>
+                                {
+                                    fs::copy(dep.path(), dep_root.join(name))?;
+                                    self.gen_debug(name, &dep_root, DEBUG_DIR)?;
+                                }
+                                _ => {}
                             }
-                            _ => {}
                         }
                     }
                 }
-            }
-            Ok(())
-        };
+                Ok(())
+            };
 
         build(
             src_root.as_ref().join(OC_BIN),
@@ -290,14 +425,34 @@ impl Dist {
         src_dir: impl AsRef<Path>,
         bin_dir: impl AsRef<Path>,
         target_dir: impl AsRef<Path>,
+    ) -> anyhow::Result<()> {
+        self.build_impl_with(bin_name, dst_name, src_dir, bin_dir, target_dir, &[])
+    }
+
+    /// Build the crate with additional `rustc` flags for itself only.
+    fn build_impl_with(
+        &self,
+        bin_name: impl AsRef<Path>,
+        dst_name: impl AsRef<Path>,
+        src_dir: impl AsRef<Path>,
+        bin_dir: impl AsRef<Path>,
+        target_dir: impl AsRef<Path>,
+        rustc_flags: &[&str],
     ) -> anyhow::Result<()> {
         println!("Building {:?}", dst_name.as_ref());
 
-        let mut cmd = Command::new(&*CARGO);
-        let cmd = cmd.current_dir(src_dir).arg("build");
+        let mut cmd = self.cargo(src_dir)?;
+        if rustc_flags.is_empty() {
+            cmd.arg("build");
+        } else {
+            cmd.arg("rustc");
+        }
         if self.release {
             cmd.arg("--release");
         }
+        if !rustc_flags.is_empty() {
+            cmd.arg("--").args(rustc_flags);
+        }
         cmd.status()?.exit_ok()?;
         let bin_dir = bin_dir.as_ref().join(self.profile());
         fs::copy(bin_dir.join(bin_name), target_dir.as_ref().join(&dst_name))?;
@@ -305,6 +460,25 @@ impl Dist {
         Ok(())
     }
 
+    /// A cargo command in `dir`, configured for the reproducible builds if
+    /// enabled.
+    fn cargo(&self, dir: impl AsRef<Path>) -> anyhow::Result<Command> {
+        let mut cmd = Command::new(&*CARGO);
+        cmd.current_dir(dir);
+        if self.reproducible {
+            cmd.env("CARGO_INCREMENTAL", "0");
+            // Appended to the flags in the configuration files.
+            let flags = remap_flags()?
+                .iter()
+                .map(|flag| format!("{flag:?}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            cmd.arg("--config")
+                .arg(format!("build.rustflags = [{flags}]"));
+        }
+        Ok(cmd)
+    }
+
     fn gen_debug(
         &self,
         target_name: impl AsRef<Path>,
@@ -353,6 +527,42 @@ impl Dist {
     }
 }
 
+/// Read the entries of the directory `path` in the order of their names, so
+/// that the builds don't depend on the order of the file system.
+fn read_dir_sorted(path: impl AsRef<Path>) -> io::Result<Vec<fs::DirEntry>> {
+    let mut entries = fs::read_dir(path)?.flatten().collect::<Vec<_>>();
+    entries.sort_by_key(|ent| ent.file_name());
+    Ok(entries)
+}
+
+/// The flags replacing the paths of the build machine embedded in the
+/// artifacts, like the ones in the panic messages and the debug info.
+fn remap_flags() -> anyhow::Result<Vec<String>> {
+    let src_root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = Command::new(rustc)
+        .current_dir(src_root)
+        .args(["--print", "sysroot"])
+        .output()?;
+    output
+        .status
+        .exit_ok()
+        .context("failed to get the sysroot")?;
+    let sysroot = PathBuf::from(String::from_utf8(output.stdout)?.trim());
+    let cargo_home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")));
+
+    // The last one matched takes effect, so the more specific ones go later.
+    let mut prefixes = vec![(src_root.to_path_buf(), "/oceanic")];
+    prefixes.extend(cargo_home.map(|home| (home, "/cargo")));
+    prefixes.push((sysroot, "/rustc"));
+    Ok(prefixes
+        .into_iter()
+        .map(|(from, to)| format!("--remap-path-prefix={}={to}", from.display()))
+        .collect())
+}
+
 fn create_dir_all(target_root: &String, src_root: &Path) -> Result<(), anyhow::Error> {
     let create_dir = |path: &Path| -> anyhow::Result<()> {
         fs::create_dir_all(path).with_context(|| format!("failed to create dir {path:?}"))
@@ -3,25 +3,34 @@ mod syscall;
 
 use std::{fs, io::BufWriter, path::Path};
 
-use rand::{prelude::SliceRandom, thread_rng};
+use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
 
 use self::syscall::Syscall;
 
+/// Generate the syscall stubs, with the syscalls numbered in a random order.
+///
+/// The order is reproducible if `seed` is given.
 pub fn gen_syscall(
+    seed: Option<u64>,
     input: impl AsRef<Path>,
     wrapper_file: impl AsRef<Path>,
     call_file: impl AsRef<Path>,
     stub_file: impl AsRef<Path>,
     num_file: impl AsRef<Path>,
+    sig_file: impl AsRef<Path>,
 ) -> anyhow::Result<()> {
     let Syscall {
         mut types,
         mut funcs,
     } = crate::gen::syscall::parse_dir(input)?;
 
-    types.shuffle(&mut thread_rng());
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    types.shuffle(&mut rng);
 
-    funcs.shuffle(&mut thread_rng());
+    funcs.shuffle(&mut rng);
     let pos = funcs.iter().position(|func| &func.name == "sv_task_exit");
     if let Some(pos) = pos {
         funcs.swap(0, pos);
@@ -30,11 +39,12 @@ pub fn gen_syscall(
     syscall::gen_rust_calls(&funcs, call_file)?;
     syscall::gen_rust_stubs(&funcs, stub_file)?;
     syscall::gen_rust_nums(&types, &funcs, num_file)?;
+    syscall::gen_rust_sigs(&funcs, sig_file)?;
     Ok(())
 }
 
-pub fn gen_bootfs(output: impl AsRef<Path>) -> anyhow::Result<()> {
-    let data = bootfs::parse(crate::BOOTFS)?;
+pub fn gen_bootfs(src_root: impl AsRef<Path>, output: impl AsRef<Path>) -> anyhow::Result<()> {
+    let data = bootfs::parse(src_root, crate::BOOTFS_MANIFEST)?;
     let mut file = BufWriter::new(fs::File::create(output)?);
     ::bootfs::gen::generate(&data, &mut file)?;
     Ok(())
@@ -1,25 +1,83 @@
 use std::{fs, io::Read, os::unix::prelude::OsStringExt, path::Path};
 
-use bootfs::gen::{Content, Entry};
+use anyhow::{anyhow, bail, Context};
+use bootfs::{
+    gen::{Content, Entry},
+    Compression,
+};
+use serde::Deserialize;
 
-fn parse_file(path: impl AsRef<Path>, name: Vec<u8>) -> anyhow::Result<Entry> {
+/// The manifest of the bootfs, listing all the entries to be packed.
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    entry: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestEntry {
+    /// The file or directory to be packed, relative to the source root.
+    source: String,
+    /// The path in the bootfs, separated with `/`.
+    target: String,
+    #[serde(default)]
+    compression: ManifestCompression,
+}
+
+#[derive(Debug, Default, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ManifestCompression {
+    #[default]
+    None,
+    Lz4,
+}
+
+impl From<ManifestCompression> for Compression {
+    fn from(value: ManifestCompression) -> Self {
+        match value {
+            ManifestCompression::None => Compression::None,
+            ManifestCompression::Lz4 => Compression::Lz4,
+        }
+    }
+}
+
+fn parse_file(
+    path: impl AsRef<Path>,
+    name: Vec<u8>,
+    compression: Compression,
+) -> anyhow::Result<Entry> {
     let mut content = vec![];
     fs::File::open(path)?.read_to_end(&mut content)?;
-    Ok(Entry {
-        name,
-        content: Content::File(content),
-    })
+    let content = match compression {
+        Compression::None => Content::File(content),
+        _ => Content::Compressed(compression, content),
+    };
+    Ok(Entry { name, content })
 }
 
-fn parse_dir(path: impl AsRef<Path>, name: Vec<u8>) -> anyhow::Result<Entry> {
-    let content = fs::read_dir(path)?
-        .flatten()
+fn parse_dir(
+    path: impl AsRef<Path>,
+    name: Vec<u8>,
+    compression: Compression,
+) -> anyhow::Result<Entry> {
+    // Sorted so that the image doesn't depend on the order of the directory.
+    let mut entries = fs::read_dir(path)?.flatten().collect::<Vec<_>>();
+    entries.sort_by_key(|ent| ent.file_name());
+    let content = entries
+        .into_iter()
         .try_fold(Vec::<Entry>::new(), |mut acc, ent| {
             let ty = ent.file_type()?;
             if ty.is_file() {
-                acc.push(parse_file(ent.path(), ent.file_name().into_vec())?);
+                acc.push(parse_file(
+                    ent.path(),
+                    ent.file_name().into_vec(),
+                    compression,
+                )?);
             } else if ty.is_dir() {
-                acc.push(parse_dir(ent.path(), ent.file_name().into_vec())?);
+                acc.push(parse_dir(
+                    ent.path(),
+                    ent.file_name().into_vec(),
+                    compression,
+                )?);
             }
             Ok::<_, anyhow::Error>(acc)
         })?;
@@ -29,6 +87,71 @@ fn parse_dir(path: impl AsRef<Path>, name: Vec<u8>) -> anyhow::Result<Entry> {
     })
 }
 
-pub fn parse(root: impl AsRef<Path>) -> anyhow::Result<Entry> {
-    parse_dir(root, "bootfs".as_bytes().to_owned())
+/// Insert the entry into the directory tree, creating the missing
+/// intermediate directories.
+fn insert(root: &mut Entry, dirs: &[&str], entry: Entry) -> anyhow::Result<()> {
+    let children = match &mut root.content {
+        Content::Directory(children) => children,
+        _ => bail!(
+            "{:?} is not a directory",
+            String::from_utf8_lossy(&root.name)
+        ),
+    };
+    let (first, rest) = match dirs.split_first() {
+        Some(dirs) => dirs,
+        None => {
+            if children.iter().any(|child| child.name == entry.name) {
+                bail!("duplicate entry {:?}", String::from_utf8_lossy(&entry.name))
+            }
+            children.push(entry);
+            return Ok(());
+        }
+    };
+    let pos = match children
+        .iter()
+        .position(|child| child.name == first.as_bytes())
+    {
+        Some(pos) => pos,
+        None => {
+            children.push(Entry {
+                name: first.as_bytes().to_owned(),
+                content: Content::Directory(Vec::new()),
+            });
+            children.len() - 1
+        }
+    };
+    insert(&mut children[pos], rest, entry)
+}
+
+pub fn parse(src_root: impl AsRef<Path>, manifest: impl AsRef<Path>) -> anyhow::Result<Entry> {
+    let manifest = fs::read_to_string(src_root.as_ref().join(manifest))?;
+    let manifest: Manifest = toml::from_str(&manifest).context("invalid bootfs manifest")?;
+
+    let mut root = Entry {
+        name: "bootfs".as_bytes().to_owned(),
+        content: Content::Directory(Vec::new()),
+    };
+    for ent in manifest.entry {
+        let source = src_root.as_ref().join(&ent.source);
+        let mut dirs = ent
+            .target
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+        let name = dirs
+            .pop()
+            .ok_or_else(|| anyhow!("empty target path for {:?}", ent.source))?
+            .as_bytes()
+            .to_owned();
+
+        let compression = ent.compression.into();
+        let entry = if source.is_dir() {
+            parse_dir(&source, name, compression)
+        } else {
+            parse_file(&source, name, compression)
+        }
+        .with_context(|| format!("failed to pack {:?}", ent.source))?;
+        insert(&mut root, &dirs, entry)?;
+    }
+    Ok(root)
 }
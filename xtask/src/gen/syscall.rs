@@ -175,6 +175,21 @@ pub fn gen_rust_stubs(funcs: &[SyscallFn], output: impl AsRef<Path>) -> anyhow::
     Ok(())
 }
 
+pub fn gen_rust_sigs(funcs: &[SyscallFn], output: impl AsRef<Path>) -> anyhow::Result<()> {
+    let mut output = BufWriter::new(fs::File::create(output)?);
+
+    for func in funcs.iter().filter(|func| !func.vdso_only) {
+        write!(output, "pub type {} = fn(", &func.name[3..])?;
+        for arg in &func.args {
+            write!(output, "{}, ", arg.ty)?;
+        }
+        write!(output, ") -> {}; ", func.returns)?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
 pub fn gen_rust_nums(
     types: &[String],
     funcs: &[SyscallFn],
@@ -193,6 +208,7 @@ pub fn gen_rust_nums(
     for (i, ty) in types.iter().enumerate() {
         write!(output, "pub const SV_{}: usize = {i}; ", ty.to_uppercase())?;
     }
+    write!(output, "pub const SYSCALL_COUNT: usize = {}; ", funcs.len())?;
 
     output.flush()?;
     Ok(())
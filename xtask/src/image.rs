@@ -0,0 +1,170 @@
+//! Generate the hard disk image to boot from.
+//!
+//! The image has a GPT with a single EFI system partition formatted as FAT32,
+//! which contains the boot loader and the tarball of the kernel, TINIT and the
+//! BOOTFS. Everything in it is written from the inputs only, with fixed GUIDs,
+//! volume ID and timestamps, so the same inputs always produce the same image.
+
+mod fat;
+mod gpt;
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{ensure, Context};
+
+use self::fat::Entry;
+
+const SECTOR_SIZE: usize = 512;
+/// The alignment of the partition, and the space reserved after it for the
+/// backup GPT.
+const ALIGN: usize = 1 << 20;
+/// The minimal size of the partition, which makes it large enough for FAT32.
+const MIN_PART_SIZE: usize = 62 << 20;
+
+/// The files in the tarball loaded by the boot loader, relative to the target
+/// directory.
+const KERNEL_FILES: &[&str] = &["KERNEL", "TINIT", "BOOT.fs"];
+/// The signature of the kernel files, packed if present for the boot loader
+/// built with its `verify` feature.
+const SIGNATURE: &str = "SIGNATURE";
+
+/// Generate the image `img/efi.img` and its VMDK conversions in `target_root`.
+pub fn gen_image(target_root: impl AsRef<Path>) -> anyhow::Result<()> {
+    let target_root = target_root.as_ref();
+    let img_dir = target_root.join("img");
+    fs::create_dir_all(&img_dir)?;
+
+    let mut files = KERNEL_FILES
+        .iter()
+        .map(|name| {
+            let data = fs::read(target_root.join(name))
+                .with_context(|| format!("failed to read {name}"))?;
+            Ok((*name, data))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if let Ok(signature) = fs::read(target_root.join(SIGNATURE)) {
+        files.push((SIGNATURE, signature));
+    }
+    let tarball = tar(&files)?;
+    let boot_loader =
+        fs::read(target_root.join("BootX64.efi")).context("failed to read BootX64.efi")?;
+
+    let root = BTreeMap::from([(
+        "EFI".to_string(),
+        Entry::Dir(BTreeMap::from([
+            (
+                "BOOT".to_string(),
+                Entry::Dir(BTreeMap::from([(
+                    "BOOTX64.EFI".to_string(),
+                    Entry::File(boot_loader),
+                )])),
+            ),
+            (
+                "OCEANIC".to_string(),
+                Entry::Dir(BTreeMap::from([(
+                    "H2O.K".to_string(),
+                    Entry::File(tarball),
+                )])),
+            ),
+        ])),
+    )]);
+    let image = image(&root)?;
+    let path = img_dir.join("efi.img");
+    fs::write(&path, image).with_context(|| format!("failed to write {path:?}"))?;
+
+    // The VMDK images are only for virtual machines other than QEMU.
+    let convert = Command::new("qemu-img")
+        .current_dir(&img_dir)
+        .args(["convert", "efi.img", "-f", "raw", "-O", "vmdk", "efi.vmdk"])
+        .stdout(Stdio::null())
+        .status();
+    match convert {
+        Ok(status) if status.success() => {
+            fs::copy(img_dir.join("efi.vmdk"), img_dir.join("efi.vbox.vmdk"))?;
+        }
+        _ => println!("Skipped the VMDK images: failed to run `qemu-img`"),
+    }
+    Ok(())
+}
+
+/// Generate a disk image with an EFI system partition containing `root`.
+fn image(root: &BTreeMap<String, Entry>) -> anyhow::Result<Vec<u8>> {
+    // Leave room for the file system and the clusters partially used.
+    let content = fat::size_of(root);
+    let part_size = (content * 2 + ALIGN)
+        .next_multiple_of(ALIGN)
+        .max(MIN_PART_SIZE);
+    let mut image = vec![0; ALIGN + part_size + ALIGN];
+
+    let start = ALIGN / SECTOR_SIZE;
+    let end = start + part_size / SECTOR_SIZE;
+    gpt::write(&mut image, start as u64..end as u64)?;
+    fat::format(&mut image[ALIGN..ALIGN + part_size], start as u32, root)?;
+    Ok(image)
+}
+
+/// Archive `files` as a tarball in the format read by the boot loader, with
+/// the metadata zeroed.
+fn tar(files: &[(&str, Vec<u8>)]) -> anyhow::Result<Vec<u8>> {
+    const BLOCK: usize = 512;
+
+    let mut tar = Vec::new();
+    for (name, data) in files {
+        ensure!(name.len() < 100, "the file name {name:?} is too long");
+
+        let mut header = [0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[108..116].copy_from_slice(b"0000000\0");
+        header[116..124].copy_from_slice(b"0000000\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        header[136..148].copy_from_slice(b"00000000000\0");
+        header[156] = b'0';
+        // The GNU format, whose magic is checked by the boot loader.
+        header[257..265].copy_from_slice(b"ustar  \0");
+
+        // The checksum is calculated with its own field filled with spaces.
+        header[148..156].fill(b' ');
+        let checksum = header.iter().map(|&b| b as u32).sum::<u32>();
+        header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+        tar.extend_from_slice(&header);
+        tar.extend_from_slice(data);
+        tar.resize(tar.len().next_multiple_of(BLOCK), 0);
+    }
+    // The end of the archive.
+    tar.resize(tar.len() + 2 * BLOCK, 0);
+    Ok(tar)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn root() -> BTreeMap<String, Entry> {
+        let tarball = tar(&[("KERNEL", vec![0x90; 3000]), ("TINIT", vec![0xcc; 10])]).unwrap();
+        BTreeMap::from([(
+            "EFI".to_string(),
+            Entry::Dir(BTreeMap::from([(
+                "OCEANIC".to_string(),
+                Entry::Dir(BTreeMap::from([(
+                    "H2O.K".to_string(),
+                    Entry::File(tarball),
+                )])),
+            )])),
+        )])
+    }
+
+    #[test]
+    fn reproducible() {
+        let image1 = image(&root()).unwrap();
+        let image2 = image(&root()).unwrap();
+        assert_eq!(image1.len(), ALIGN + MIN_PART_SIZE + ALIGN);
+        assert!(image1 == image2, "the images differ");
+    }
+}
@@ -0,0 +1,357 @@
+//! A minimal FAT32 formatter, writing a file system with the given files.
+//!
+//! Only the short (8.3) names are written, and the clusters of every file and
+//! directory are contiguous. All the timestamps are 1980-01-01 00:00:00, the
+//! earliest date of FAT.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, ensure};
+
+use super::SECTOR_SIZE;
+
+const SECTORS_PER_CLUSTER: usize = 1;
+const CLUSTER_SIZE: usize = SECTOR_SIZE * SECTORS_PER_CLUSTER;
+const RESERVED_SECTORS: usize = 32;
+const NUM_FATS: usize = 2;
+const FSINFO_SECTOR: usize = 1;
+const BACKUP_BOOT_SECTOR: usize = 6;
+const ROOT_CLUSTER: u32 = 2;
+/// FAT32 requires at least this many clusters, or it's taken as FAT16.
+const MIN_CLUSTERS: usize = 65525;
+
+const FAT_MEDIA: u32 = 0x0fff_fff8;
+const FAT_EOC: u32 = 0x0fff_ffff;
+
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const DIR_ENTRY_SIZE: usize = 32;
+
+/// 1980-01-01.
+const DATE: u16 = (1 << 5) | 1;
+const VOLUME_ID: u32 = 0x0ce4_41c0;
+const VOLUME_LABEL: &[u8; 11] = b"OCEANIC    ";
+
+#[derive(Debug)]
+pub enum Entry {
+    File(Vec<u8>),
+    Dir(BTreeMap<String, Entry>),
+}
+
+/// The size of the clusters taken by the entries in `dir`, recursively.
+pub fn size_of(dir: &BTreeMap<String, Entry>) -> usize {
+    let entries = (dir.len() + 2) * DIR_ENTRY_SIZE;
+    dir.values()
+        .fold(entries.next_multiple_of(CLUSTER_SIZE), |acc, entry| {
+            acc + match entry {
+                Entry::File(data) => data.len().next_multiple_of(CLUSTER_SIZE),
+                Entry::Dir(dir) => size_of(dir),
+            }
+        })
+}
+
+/// Format `part` as FAT32 with the files in `root`, where `hidden` is the
+/// number of the sectors before the partition.
+pub fn format(part: &mut [u8], hidden: u32, root: &BTreeMap<String, Entry>) -> anyhow::Result<()> {
+    let sectors = part.len() / SECTOR_SIZE;
+    // The size of a FAT, calculated as in the specification of Microsoft.
+    let fat_size =
+        (sectors - RESERVED_SECTORS).div_ceil((256 * SECTORS_PER_CLUSTER + NUM_FATS) / 2);
+    let data_start = RESERVED_SECTORS + NUM_FATS * fat_size;
+    let clusters = (sectors - data_start) / SECTORS_PER_CLUSTER;
+    ensure!(
+        clusters >= MIN_CLUSTERS,
+        "the partition is too small for FAT32"
+    );
+
+    let (reserved, rest) = part.split_at_mut(RESERVED_SECTORS * SECTOR_SIZE);
+    let (fats, data) = rest.split_at_mut(NUM_FATS * fat_size * SECTOR_SIZE);
+
+    let mut writer = Writer {
+        data,
+        fat: vec![0; clusters + 2],
+        next: ROOT_CLUSTER,
+    };
+    writer.fat[0] = FAT_MEDIA;
+    writer.fat[1] = FAT_EOC;
+    let root_cluster = writer.dir(root, None)?;
+    debug_assert_eq!(root_cluster, ROOT_CLUSTER);
+
+    for fat in fats.chunks_exact_mut(fat_size * SECTOR_SIZE) {
+        for (raw, &entry) in fat.as_chunks_mut().0.iter_mut().zip(&writer.fat) {
+            *raw = entry.to_le_bytes();
+        }
+    }
+
+    let boot = boot_sector(sectors as u32, hidden, fat_size as u32);
+    let free = (clusters + 2) as u32 - writer.next;
+    let fsinfo = fsinfo_sector(free, writer.next);
+    for base in [0, BACKUP_BOOT_SECTOR] {
+        let boot_at = base * SECTOR_SIZE;
+        reserved[boot_at..][..SECTOR_SIZE].copy_from_slice(&boot);
+        let fsinfo_at = (base + FSINFO_SECTOR) * SECTOR_SIZE;
+        reserved[fsinfo_at..][..SECTOR_SIZE].copy_from_slice(&fsinfo);
+    }
+    Ok(())
+}
+
+struct Writer<'a> {
+    data: &'a mut [u8],
+    fat: Vec<u32>,
+    next: u32,
+}
+
+impl Writer<'_> {
+    /// Allocate the contiguous clusters for `size` bytes, at least one.
+    fn alloc(&mut self, size: usize) -> anyhow::Result<u32> {
+        let count = size.div_ceil(CLUSTER_SIZE).max(1) as u32;
+        let first = self.next;
+        if (first + count) as usize > self.fat.len() {
+            bail!("the partition is full");
+        }
+        for cluster in first..first + count - 1 {
+            self.fat[cluster as usize] = cluster + 1;
+        }
+        self.fat[(first + count - 1) as usize] = FAT_EOC;
+        self.next += count;
+        Ok(first)
+    }
+
+    fn write(&mut self, cluster: u32, data: &[u8]) {
+        let offset = (cluster - ROOT_CLUSTER) as usize * CLUSTER_SIZE;
+        self.data[offset..][..data.len()].copy_from_slice(data);
+    }
+
+    /// Write the directory `dir` and everything in it, where `parent` is the
+    /// cluster of the parent directory, or `None` for the root directory.
+    fn dir(&mut self, dir: &BTreeMap<String, Entry>, parent: Option<u32>) -> anyhow::Result<u32> {
+        let count = dir.len() + if parent.is_some() { 2 } else { 0 };
+        let cluster = self.alloc(count * DIR_ENTRY_SIZE)?;
+
+        let mut raw = Vec::with_capacity(count * DIR_ENTRY_SIZE);
+        if let Some(parent) = parent {
+            // The parent is referred to as cluster 0 if it's the root.
+            let parent = if parent == ROOT_CLUSTER { 0 } else { parent };
+            raw.extend(dir_entry(*b".          ", ATTR_DIRECTORY, cluster, 0));
+            raw.extend(dir_entry(*b"..         ", ATTR_DIRECTORY, parent, 0));
+        }
+        for (name, entry) in dir {
+            let short = short_name(name)?;
+            raw.extend(match entry {
+                Entry::File(data) if data.is_empty() => dir_entry(short, ATTR_ARCHIVE, 0, 0),
+                Entry::File(data) => {
+                    let first = self.alloc(data.len())?;
+                    self.write(first, data);
+                    dir_entry(short, ATTR_ARCHIVE, first, data.len() as u32)
+                }
+                Entry::Dir(children) => {
+                    let first = self.dir(children, Some(cluster))?;
+                    dir_entry(short, ATTR_DIRECTORY, first, 0)
+                }
+            });
+        }
+        self.write(cluster, &raw);
+        Ok(cluster)
+    }
+}
+
+/// Convert `name` to the 8.3 format, padded with spaces.
+fn short_name(name: &str) -> anyhow::Result<[u8; 11]> {
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    let valid = |s: &str| {
+        s.bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b"_-~".contains(&b))
+    };
+    ensure!(
+        (1..=8).contains(&base.len()) && ext.len() <= 3 && valid(base) && valid(ext),
+        "{name:?} is not a valid short name"
+    );
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..][..ext.len()].copy_from_slice(ext.as_bytes());
+    Ok(short)
+}
+
+fn dir_entry(name: [u8; 11], attr: u8, cluster: u32, size: u32) -> [u8; DIR_ENTRY_SIZE] {
+    let mut entry = [0; DIR_ENTRY_SIZE];
+    entry[..11].copy_from_slice(&name);
+    entry[11] = attr;
+    // The creation, last access and write dates.
+    for offset in [16, 18, 24] {
+        entry[offset..offset + 2].copy_from_slice(&DATE.to_le_bytes());
+    }
+    entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    entry[28..32].copy_from_slice(&size.to_le_bytes());
+    entry
+}
+
+fn boot_sector(sectors: u32, hidden: u32, fat_size: u32) -> [u8; SECTOR_SIZE] {
+    let mut boot = [0; SECTOR_SIZE];
+    // A jump over the BPB to an infinite loop, since it's never booted.
+    boot[..3].copy_from_slice(&[0xeb, 0x58, 0x90]);
+    boot[90..92].copy_from_slice(&[0xeb, 0xfe]);
+    boot[3..11].copy_from_slice(b"OCEANIC ");
+    boot[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+    boot[13] = SECTORS_PER_CLUSTER as u8;
+    boot[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+    boot[16] = NUM_FATS as u8;
+    // A fixed disk.
+    boot[21] = 0xf8;
+    boot[24..26].copy_from_slice(&32u16.to_le_bytes());
+    boot[26..28].copy_from_slice(&64u16.to_le_bytes());
+    boot[28..32].copy_from_slice(&hidden.to_le_bytes());
+    boot[32..36].copy_from_slice(&sectors.to_le_bytes());
+    boot[36..40].copy_from_slice(&fat_size.to_le_bytes());
+    boot[44..48].copy_from_slice(&ROOT_CLUSTER.to_le_bytes());
+    boot[48..50].copy_from_slice(&(FSINFO_SECTOR as u16).to_le_bytes());
+    boot[50..52].copy_from_slice(&(BACKUP_BOOT_SECTOR as u16).to_le_bytes());
+    boot[64] = 0x80;
+    boot[66] = 0x29;
+    boot[67..71].copy_from_slice(&VOLUME_ID.to_le_bytes());
+    boot[71..82].copy_from_slice(VOLUME_LABEL);
+    boot[82..90].copy_from_slice(b"FAT32   ");
+    boot[510..].copy_from_slice(&[0x55, 0xaa]);
+    boot
+}
+
+fn fsinfo_sector(free: u32, next: u32) -> [u8; SECTOR_SIZE] {
+    let mut fsinfo = [0; SECTOR_SIZE];
+    fsinfo[..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+    fsinfo[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+    fsinfo[488..492].copy_from_slice(&free.to_le_bytes());
+    fsinfo[492..496].copy_from_slice(&next.to_le_bytes());
+    fsinfo[508..].copy_from_slice(&0xaa55_0000u32.to_le_bytes());
+    fsinfo
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    const SECTORS: usize = 34 << 11;
+    const HIDDEN: u32 = 2048;
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn root() -> BTreeMap<String, Entry> {
+        BTreeMap::from([
+            (
+                "A.TXT".to_string(),
+                Entry::File(vec![0xa5; CLUSTER_SIZE + 88]),
+            ),
+            (
+                "DIR".to_string(),
+                Entry::Dir(BTreeMap::from([
+                    ("B.BIN".to_string(), Entry::File(Vec::new())),
+                    ("C".to_string(), Entry::File(vec![0x5a])),
+                ])),
+            ),
+        ])
+    }
+
+    fn formatted() -> Vec<u8> {
+        let mut part = vec![0; SECTORS * SECTOR_SIZE];
+        format(&mut part, HIDDEN, &root()).unwrap();
+        part
+    }
+
+    /// The clusters in the chain starting from `first`.
+    fn chain(fat: &[u8], first: u32) -> Vec<u32> {
+        let mut chain = vec![first];
+        loop {
+            let next = u32_at(fat, *chain.last().unwrap() as usize * 4);
+            if next == FAT_EOC {
+                break chain;
+            }
+            chain.push(next);
+        }
+    }
+
+    /// The first cluster and the size of the entry named `name` in the
+    /// directory `dir`.
+    fn lookup(dir: &[u8], name: &[u8; 11]) -> (u32, u32) {
+        let entry = dir
+            .chunks_exact(DIR_ENTRY_SIZE)
+            .find(|entry| &entry[..11] == name)
+            .unwrap();
+        let cluster = (u16_at(entry, 20) as u32) << 16 | u16_at(entry, 26) as u32;
+        (cluster, u32_at(entry, 28))
+    }
+
+    #[test]
+    fn bpb() {
+        let part = formatted();
+        let boot = &part[..SECTOR_SIZE];
+        assert_eq!(u16_at(boot, 11), SECTOR_SIZE as u16);
+        assert_eq!(boot[13], SECTORS_PER_CLUSTER as u8);
+        assert_eq!(u16_at(boot, 14), RESERVED_SECTORS as u16);
+        assert_eq!(boot[16], NUM_FATS as u8);
+        // No root directory entries, 16-bit sector count or 16-bit FAT size.
+        assert_eq!(u16_at(boot, 17), 0);
+        assert_eq!(u16_at(boot, 19), 0);
+        assert_eq!(u16_at(boot, 22), 0);
+        assert_eq!(u32_at(boot, 28), HIDDEN);
+        assert_eq!(u32_at(boot, 32), SECTORS as u32);
+        assert_eq!(u32_at(boot, 36), 540);
+        assert_eq!(u32_at(boot, 44), ROOT_CLUSTER);
+        assert_eq!(&boot[82..90], b"FAT32   ");
+        assert_eq!(&boot[510..], &[0x55, 0xaa]);
+
+        let backup = BACKUP_BOOT_SECTOR * SECTOR_SIZE;
+        assert_eq!(&part[backup..][..2 * SECTOR_SIZE], &part[..2 * SECTOR_SIZE]);
+
+        let fsinfo = &part[FSINFO_SECTOR * SECTOR_SIZE..][..SECTOR_SIZE];
+        let clusters = (SECTORS - RESERVED_SECTORS - NUM_FATS * 540) as u32;
+        // The root directory, 2 clusters of `A.TXT`, `DIR` and `C`.
+        assert_eq!(u32_at(fsinfo, 488), clusters - 5);
+        assert_eq!(u32_at(fsinfo, 492), ROOT_CLUSTER + 5);
+    }
+
+    #[test]
+    fn cluster_chains() {
+        let part = formatted();
+        let fat_size = u32_at(&part, 36) as usize * SECTOR_SIZE;
+        let (fat, backup) = part[RESERVED_SECTORS * SECTOR_SIZE..].split_at(fat_size);
+        assert_eq!(fat, &backup[..fat_size]);
+        assert_eq!(u32_at(fat, 0), FAT_MEDIA);
+        assert_eq!(u32_at(fat, 4), FAT_EOC);
+
+        let data = &part[(RESERVED_SECTORS * SECTOR_SIZE + NUM_FATS * fat_size)..];
+        let cluster = |cluster: u32| &data[(cluster - ROOT_CLUSTER) as usize * CLUSTER_SIZE..];
+        assert_eq!(chain(fat, ROOT_CLUSTER), [ROOT_CLUSTER]);
+
+        let (a, a_size) = lookup(cluster(ROOT_CLUSTER), b"A       TXT");
+        assert_eq!(a_size, CLUSTER_SIZE as u32 + 88);
+        assert_eq!(chain(fat, a), [a, a + 1]);
+        assert!(cluster(a)[..a_size as usize].iter().all(|&b| b == 0xa5));
+
+        let (dir, _) = lookup(cluster(ROOT_CLUSTER), b"DIR        ");
+        assert_eq!(chain(fat, dir), [dir]);
+        assert_eq!(lookup(cluster(dir), b".          "), (dir, 0));
+        assert_eq!(lookup(cluster(dir), b"..         "), (0, 0));
+        assert_eq!(lookup(cluster(dir), b"B       BIN"), (0, 0));
+
+        let (c, c_size) = lookup(cluster(dir), b"C          ");
+        assert_eq!(c_size, 1);
+        assert_eq!(chain(fat, c), [c]);
+        assert_eq!(cluster(c)[0], 0x5a);
+        // Nothing is allocated after the last file.
+        assert_eq!(u32_at(fat, (c as usize + 1) * 4), 0);
+    }
+
+    #[test]
+    fn too_small() {
+        let mut part = vec![0; (SECTORS / 2) * SECTOR_SIZE];
+        assert!(format(&mut part, HIDDEN, &root()).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Write a GPT with a single EFI system partition, and the protective MBR.
+
+use std::ops::Range;
+
+use anyhow::ensure;
+
+use super::SECTOR_SIZE;
+
+const NUM_ENTRIES: usize = 128;
+const ENTRY_SIZE: usize = 128;
+const HEADER_SIZE: usize = 92;
+/// The sectors taken by the partition entries.
+const ENTRY_SECTORS: u64 = (NUM_ENTRIES * ENTRY_SIZE / SECTOR_SIZE) as u64;
+
+const ESP_TYPE: &str = "C12A7328-F81F-11D2-BA4B-00A0C93EC93B";
+/// The GUIDs are fixed so that the image is reproducible.
+const DISK_GUID: &str = "5F3E8C41-2B7A-4D19-9C60-0CEA41C00001";
+const PART_GUID: &str = "5F3E8C41-2B7A-4D19-9C60-0CEA41C00002";
+const PART_NAME: &str = "EFI System Partition";
+
+/// Write the GPT of `disk` with an EFI system partition of the sectors in
+/// `part`.
+pub fn write(disk: &mut [u8], part: Range<u64>) -> anyhow::Result<()> {
+    let sectors = (disk.len() / SECTOR_SIZE) as u64;
+    let first_usable = 2 + ENTRY_SECTORS;
+    let last_usable = sectors - 2 - ENTRY_SECTORS;
+    ensure!(
+        first_usable <= part.start && part.end - 1 <= last_usable,
+        "the partition is out of the usable sectors"
+    );
+
+    let mut entries = vec![0; NUM_ENTRIES * ENTRY_SIZE];
+    entries[..16].copy_from_slice(&guid(ESP_TYPE));
+    entries[16..32].copy_from_slice(&guid(PART_GUID));
+    entries[32..40].copy_from_slice(&part.start.to_le_bytes());
+    entries[40..48].copy_from_slice(&(part.end - 1).to_le_bytes());
+    for (raw, unit) in entries[56..128]
+        .as_chunks_mut()
+        .0
+        .iter_mut()
+        .zip(PART_NAME.encode_utf16())
+    {
+        *raw = unit.to_le_bytes();
+    }
+    let entries_crc = crc32(&entries);
+
+    let header = |this: u64, other: u64, entries_at: u64| {
+        let mut header = [0; HEADER_SIZE];
+        header[..8].copy_from_slice(b"EFI PART");
+        header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        header[24..32].copy_from_slice(&this.to_le_bytes());
+        header[32..40].copy_from_slice(&other.to_le_bytes());
+        header[40..48].copy_from_slice(&first_usable.to_le_bytes());
+        header[48..56].copy_from_slice(&last_usable.to_le_bytes());
+        header[56..72].copy_from_slice(&guid(DISK_GUID));
+        header[72..80].copy_from_slice(&entries_at.to_le_bytes());
+        header[80..84].copy_from_slice(&(NUM_ENTRIES as u32).to_le_bytes());
+        header[84..88].copy_from_slice(&(ENTRY_SIZE as u32).to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let crc = crc32(&header);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+        header
+    };
+    let mut put = |lba: u64, data: &[u8]| {
+        let offset = lba as usize * SECTOR_SIZE;
+        disk[offset..][..data.len()].copy_from_slice(data);
+    };
+
+    put(0, &protective_mbr(sectors));
+    put(1, &header(1, sectors - 1, 2));
+    put(2, &entries);
+    put(sectors - 1 - ENTRY_SECTORS, &entries);
+    put(
+        sectors - 1,
+        &header(sectors - 1, 1, sectors - 1 - ENTRY_SECTORS),
+    );
+    Ok(())
+}
+
+/// The MBR covering the whole disk with a GPT protective partition.
+fn protective_mbr(sectors: u64) -> [u8; SECTOR_SIZE] {
+    let mut mbr = [0; SECTOR_SIZE];
+    let entry = &mut mbr[446..462];
+    // The CHS addresses are unused.
+    entry[1..4].copy_from_slice(&[0x00, 0x02, 0x00]);
+    entry[4] = 0xee;
+    entry[5..8].copy_from_slice(&[0xff, 0xff, 0xff]);
+    entry[8..12].copy_from_slice(&1u32.to_le_bytes());
+    let size = u32::try_from(sectors - 1).unwrap_or(u32::MAX);
+    entry[12..16].copy_from_slice(&size.to_le_bytes());
+    mbr[510..].copy_from_slice(&[0x55, 0xaa]);
+    mbr
+}
+
+/// Encode a GUID string in the mixed-endian layout on the disk.
+fn guid(s: &str) -> [u8; 16] {
+    let hex = s.replace('-', "");
+    let mut bytes = [0; 16];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).unwrap();
+    }
+    bytes[..4].reverse();
+    bytes[4..6].reverse();
+    bytes[6..8].reverse();
+    bytes
+}
+
+/// The CRC32 used by the GPT, the same as the one of zlib.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECTORS: u64 = 8192;
+    const PART: Range<u64> = 2048..6144;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(data: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn sector(disk: &[u8], lba: u64) -> &[u8] {
+        &disk[lba as usize * SECTOR_SIZE..]
+    }
+
+    fn written() -> Vec<u8> {
+        let mut disk = vec![0; SECTORS as usize * SECTOR_SIZE];
+        write(&mut disk, PART).unwrap();
+        disk
+    }
+
+    /// Check the header at `lba`, and return the LBA of its partition
+    /// entries.
+    fn check_header(disk: &[u8], lba: u64, other: u64) -> u64 {
+        let header = &sector(disk, lba)[..HEADER_SIZE];
+        assert_eq!(&header[..8], b"EFI PART");
+        let mut zeroed = header.to_vec();
+        zeroed[16..20].fill(0);
+        assert_eq!(u32_at(header, 16), crc32(&zeroed));
+        assert_eq!(u64_at(header, 24), lba);
+        assert_eq!(u64_at(header, 32), other);
+        assert_eq!(u64_at(header, 40), 2 + ENTRY_SECTORS);
+        assert_eq!(u64_at(header, 48), SECTORS - 2 - ENTRY_SECTORS);
+
+        let entries_at = u64_at(header, 72);
+        let entries = &sector(disk, entries_at)[..NUM_ENTRIES * ENTRY_SIZE];
+        assert_eq!(u32_at(header, 88), crc32(entries));
+        entries_at
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn headers() {
+        let disk = written();
+        assert_eq!(&disk[510..512], &[0x55, 0xaa]);
+        assert_eq!(disk[446 + 4], 0xee);
+
+        assert_eq!(check_header(&disk, 1, SECTORS - 1), 2);
+        let backup_entries = check_header(&disk, SECTORS - 1, 1);
+        assert_eq!(backup_entries, SECTORS - 1 - ENTRY_SECTORS);
+        assert_eq!(
+            sector(&disk, 2)[..NUM_ENTRIES * ENTRY_SIZE],
+            sector(&disk, backup_entries)[..NUM_ENTRIES * ENTRY_SIZE]
+        );
+        // The headers differ only in their CRCs and the LBAs.
+        let (primary, backup) = (sector(&disk, 1), sector(&disk, SECTORS - 1));
+        assert_eq!(primary[..16], backup[..16]);
+        assert_eq!(primary[40..72], backup[40..72]);
+        assert_eq!(primary[80..HEADER_SIZE], backup[80..HEADER_SIZE]);
+    }
+
+    #[test]
+    fn entry() {
+        let disk = written();
+        let entry = &sector(&disk, 2)[..ENTRY_SIZE];
+        assert_eq!(entry[..16], guid(ESP_TYPE));
+        assert_eq!(entry[..4], [0x28, 0x73, 0x2a, 0xc1]);
+        assert_eq!(u64_at(entry, 32), PART.start);
+        assert_eq!(u64_at(entry, 40), PART.end - 1);
+        let name = String::from_utf16(
+            &entry[56..]
+                .chunks_exact(2)
+                .map(|raw| u16::from_le_bytes([raw[0], raw[1]]))
+                .take_while(|&unit| unit != 0)
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        assert_eq!(name, PART_NAME);
+        // The other entries are unused.
+        assert!(sector(&disk, 2)[ENTRY_SIZE..NUM_ENTRIES * ENTRY_SIZE]
+            .iter()
+            .all(|&b| b == 0));
+    }
+
+    #[test]
+    fn out_of_usable() {
+        let mut disk = vec![0; SECTORS as usize * SECTOR_SIZE];
+        assert!(write(&mut disk, 2..PART.end).is_err());
+        assert!(write(&mut disk, PART.start..SECTORS - 1).is_err());
+    }
+}
@@ -3,9 +3,15 @@
 
 use structopt::StructOpt;
 
+mod bench;
 mod check;
 mod dist;
 mod gen;
+mod image;
+mod prelink;
+mod report;
+mod symbolize;
+mod test;
 const DEBUG_DIR: &str = "debug";
 
 const H2O_BOOT: &str = "h2o/boot";
@@ -18,11 +24,16 @@ const OC_BIN: &str = "src/bin";
 const OC_DRV: &str = "src/drv";
 
 const BOOTFS: &str = "target/bootfs";
+const BOOTFS_MANIFEST: &str = "bootfs.toml";
 
 #[derive(Debug, StructOpt)]
 enum Cmd {
     Dist(dist::Dist),
     Check,
+    Symbolize(symbolize::Symbolize),
+    Test(test::Test),
+    Bench(bench::Bench),
+    Report(report::Report),
 }
 
 fn main() -> anyhow::Result<()> {
@@ -30,5 +41,9 @@ fn main() -> anyhow::Result<()> {
     match args {
         Cmd::Dist(dist) => dist.build(),
         Cmd::Check => check::check(),
+        Cmd::Symbolize(symbolize) => symbolize.run(),
+        Cmd::Test(test) => test.run(),
+        Cmd::Bench(bench) => bench.run(),
+        Cmd::Report(report) => report.run(),
     }
 }
@@ -0,0 +1,215 @@
+//! Prelink the shared libraries in the BOOTFS.
+//!
+//! Every library but the dynamic linker gets a distinct preferred base
+//! address, and its relative relocations (RELR) are applied for the base in
+//! the file. The base is recorded as the difference between the physical and
+//! the virtual addresses of the loadable segments, which are the same
+//! otherwise.
+//!
+//! The loader tries the preferred address first, and the dynamic linker skips
+//! the relative relocations if it holds, or applies the difference from the
+//! preferred one if the library is loaded elsewhere.
+//!
+//! Prelinked libraries are placed at the same addresses in every process, so
+//! it's only enabled with `cargo xtask dist --prelink`.
+
+use std::{fs, ops::Range, path::Path};
+
+use anyhow::{anyhow, bail, ensure, Context};
+
+/// The preferred base address of the first library.
+const PRELINK_BASE: u64 = 0x7000_0000_0000;
+/// The alignment of the preferred base addresses.
+const PRELINK_ALIGN: u64 = 1 << 21;
+
+/// The dynamic linker relocates itself, so it's never prelinked.
+const LDSO: &str = "ld-oceanic.so";
+
+const ET_DYN: u16 = 3;
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const DT_NULL: u64 = 0;
+const DT_RELRSZ: u64 = 35;
+const DT_RELR: u64 = 36;
+
+const PHDR_SIZE: usize = 56;
+const DYN_SIZE: usize = 16;
+
+struct Segment {
+    ty: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+    memsz: u64,
+    /// The offset of the program header in the file.
+    header: usize,
+}
+
+/// Prelink the libraries in `lib_dir`, in the order of their names.
+pub fn prelink(lib_dir: impl AsRef<Path>) -> anyhow::Result<()> {
+    let mut libs = Vec::new();
+    for entry in fs::read_dir(lib_dir.as_ref())? {
+        let path = entry?.path();
+        let is_lib = path.extension().is_some_and(|ext| ext == "so");
+        if is_lib && path.file_name().is_some_and(|name| name != LDSO) {
+            libs.push(path);
+        }
+    }
+    libs.sort();
+
+    let mut base = PRELINK_BASE;
+    for path in libs {
+        let mut data = fs::read(&path)?;
+        let size =
+            prelink_one(&mut data, base).with_context(|| format!("failed to prelink {path:?}"))?;
+        fs::write(&path, data)?;
+
+        println!("Prelinked {:?} at {base:#x}", path.file_name().unwrap());
+        base += (size + PRELINK_ALIGN - 1) & !(PRELINK_ALIGN - 1);
+    }
+    Ok(())
+}
+
+/// Prelink the library `data` at `base`, returning the size of its address
+/// range.
+fn prelink_one(data: &mut [u8], base: u64) -> anyhow::Result<u64> {
+    ensure!(data.get(..4) == Some(b"\x7fELF"), "not an ELF file");
+    // ELFCLASS64 and ELFDATA2LSB.
+    ensure!(
+        data[4] == 2 && data[5] == 1,
+        "not a 64-bit little endian file"
+    );
+    ensure!(read16(data, 16)? == ET_DYN, "not a dynamic file");
+
+    let phoff = read64(data, 32)? as usize;
+    let phnum = read16(data, 56)? as usize;
+    let segments = (0..phnum)
+        .map(|index| {
+            let header = phoff + index * PHDR_SIZE;
+            Ok(Segment {
+                ty: read32(data, header)?,
+                offset: read64(data, header + 8)?,
+                vaddr: read64(data, header + 16)?,
+                filesz: read64(data, header + 32)?,
+                memsz: read64(data, header + 40)?,
+                header,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let loads = || segments.iter().filter(|seg| seg.ty == PT_LOAD);
+    let first = loads()
+        .next()
+        .ok_or_else(|| anyhow!("no loadable segment"))?;
+    ensure!(
+        first.vaddr == 0,
+        "the first loadable segment must start at 0"
+    );
+    let end = loads().map(|seg| seg.vaddr + seg.memsz).max().unwrap();
+
+    // Relocate from the base previously prelinked at, if any.
+    let old = read64(data, first.header + 24)?.wrapping_sub(first.vaddr);
+    let delta = base.wrapping_sub(old);
+
+    let relr = segments
+        .iter()
+        .find(|seg| seg.ty == PT_DYNAMIC)
+        .map(|seg| find_relr(data, seg))
+        .transpose()?
+        .flatten();
+    if let Some((relr, size)) = relr {
+        let relr = offset_of(&segments, relr..relr + size)?;
+        let entries = data[relr]
+            .as_chunks()
+            .0
+            .iter()
+            .map(|&entry| u64::from_le_bytes(entry))
+            .collect::<Vec<_>>();
+        for addr in decode_relr(&entries) {
+            let offset = offset_of(&segments, addr..addr + 8)?.start;
+            let value = read64(data, offset)?.wrapping_add(delta);
+            data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    for seg in loads() {
+        let paddr = seg.vaddr.wrapping_add(base);
+        data[seg.header + 24..seg.header + 32].copy_from_slice(&paddr.to_le_bytes());
+    }
+    Ok(end)
+}
+
+/// Find the address and the size of the RELR entry in the dynamic section.
+fn find_relr(data: &[u8], dynamic: &Segment) -> anyhow::Result<Option<(u64, u64)>> {
+    let (mut relr, mut size) = (None, None);
+    for index in 0..(dynamic.filesz as usize / DYN_SIZE) {
+        let offset = dynamic.offset as usize + index * DYN_SIZE;
+        match read64(data, offset)? {
+            DT_NULL => break,
+            DT_RELR => relr = Some(read64(data, offset + 8)?),
+            DT_RELRSZ => size = Some(read64(data, offset + 8)?),
+            _ => {}
+        }
+    }
+    Ok(relr.zip(size))
+}
+
+/// Decode the addresses to be relocated from the RELR entries.
+fn decode_relr(entries: &[u64]) -> Vec<u64> {
+    let mut addrs = Vec::new();
+    let mut next = 0;
+    for &entry in entries {
+        if entry & 1 == 0 {
+            addrs.push(entry);
+            next = entry + 8;
+        } else {
+            let mut bitmap = entry >> 1;
+            let mut addr = next;
+            while bitmap != 0 {
+                if bitmap & 1 != 0 {
+                    addrs.push(addr);
+                }
+                bitmap >>= 1;
+                addr += 8;
+            }
+            next += 63 * 8;
+        }
+    }
+    addrs
+}
+
+/// Convert the address range `range` to the offsets in the file.
+fn offset_of(segments: &[Segment], range: Range<u64>) -> anyhow::Result<Range<usize>> {
+    let seg = segments
+        .iter()
+        .filter(|seg| seg.ty == PT_LOAD)
+        .find(|seg| seg.vaddr <= range.start && range.end <= seg.vaddr + seg.filesz);
+    match seg {
+        Some(seg) => {
+            let start = (range.start - seg.vaddr + seg.offset) as usize;
+            Ok(start..start + (range.end - range.start) as usize)
+        }
+        None => bail!("{:#x}..{:#x} is not in the file", range.start, range.end),
+    }
+}
+
+fn read16(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or_else(|| anyhow!("truncated file"))?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read32(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or_else(|| anyhow!("truncated file"))?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read64(data: &[u8], offset: usize) -> anyhow::Result<u64> {
+    let bytes = data
+        .get(offset..offset + 8)
+        .ok_or_else(|| anyhow!("truncated file"))?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
@@ -0,0 +1,225 @@
+//! Report the sizes of the artifacts built by `dist`.
+//!
+//! The sizes of the boot loader, the kernel, TINIT, the BOOTFS image and every
+//! file in it are compared against the ones recorded by the previous report,
+//! which are replaced with the current ones afterwards.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::LazyLock,
+};
+
+use anyhow::{bail, Context};
+use structopt::StructOpt;
+
+use crate::{
+    dist::{LLVM, LLVM_OBJDUMP},
+    DEBUG_DIR,
+};
+
+static LLVM_SIZE: LazyLock<PathBuf> = LazyLock::new(|| Path::new(&*LLVM).join("bin/llvm-size"));
+
+/// The sizes recorded by the previous report.
+const SIZES_JSON: &str = "sizes.json";
+const DEPS_DOT: &str = "deps.dot";
+
+/// The artifacts outside the BOOTFS, relative to the target directory.
+const IMAGES: &[&str] = &["BootX64.efi", "KERNEL", "TINIT", "BOOT.fs"];
+/// The directories in the BOOTFS, relative to the target directory.
+const BOOTFS_DIRS: &[&str] = &["bootfs/lib", "bootfs/bin", "bootfs/drv"];
+
+/// Print the sizes of the artifacts and their changes since the previous
+/// report.
+#[derive(Debug, StructOpt)]
+pub struct Report {
+    /// Also break down every artifact into its sections, largest first.
+    #[structopt(long = "--sections", parse(from_flag))]
+    sections: bool,
+    /// Also write the dependency graph of the BOOTFS files in the DOT format
+    /// to `debug/deps.dot`.
+    #[structopt(long = "--graph", parse(from_flag))]
+    graph: bool,
+    /// Keep the sizes of the previous report for the next comparison.
+    #[structopt(long = "--no-save", parse(from_flag))]
+    no_save: bool,
+}
+
+impl Report {
+    pub fn run(self) -> anyhow::Result<()> {
+        let src_root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+        let target_root =
+            env::var_os("CARGO_TARGET_DIR").map_or_else(|| src_root.join("target"), PathBuf::from);
+
+        let artifacts = collect(&target_root)?;
+        if artifacts.is_empty() {
+            bail!("no artifacts found, run `cargo xtask dist` first");
+        }
+        let sizes = artifacts
+            .iter()
+            .map(|(name, path)| Ok((name.clone(), fs::metadata(path)?.len())))
+            .collect::<anyhow::Result<BTreeMap<_, _>>>()?;
+
+        let debug_dir = src_root.join(DEBUG_DIR);
+        fs::create_dir_all(&debug_dir)?;
+        let path = debug_dir.join(SIZES_JSON);
+        let prev: BTreeMap<String, u64> = match fs::read_to_string(&path) {
+            Ok(prev) => {
+                serde_json::from_str(&prev).with_context(|| format!("failed to parse {path:?}"))?
+            }
+            Err(_) => BTreeMap::new(),
+        };
+        print_sizes(&sizes, &prev);
+
+        if self.sections {
+            for (name, path) in &artifacts {
+                // The BOOTFS image is not an object file.
+                if name != "BOOT.fs" {
+                    print_sections(name, path)?;
+                }
+            }
+        }
+        if self.graph {
+            let dot = debug_dir.join(DEPS_DOT);
+            fs::write(&dot, graph(&artifacts, &sizes)?)?;
+            println!("Wrote the dependency graph to {dot:?}");
+        }
+        if !self.no_save {
+            fs::write(&path, serde_json::to_string_pretty(&sizes)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collect the paths of the artifacts, named with their paths in the BOOTFS
+/// prefixed by `BOOT.fs/` if they're in it.
+pub(crate) fn collect(target_root: &Path) -> anyhow::Result<BTreeMap<String, PathBuf>> {
+    let mut artifacts = BTreeMap::new();
+    for name in IMAGES {
+        let path = target_root.join(name);
+        if path.is_file() {
+            artifacts.insert(name.to_string(), path);
+        }
+    }
+    for dir in BOOTFS_DIRS {
+        let Ok(entries) = fs::read_dir(target_root.join(dir)) else {
+            continue;
+        };
+        let dir = dir.strip_prefix("bootfs/").unwrap();
+        for ent in entries.flatten() {
+            if ent.file_type()?.is_file() {
+                let name = format!("BOOT.fs/{dir}/{}", ent.file_name().to_string_lossy());
+                artifacts.insert(name, ent.path());
+            }
+        }
+    }
+    Ok(artifacts)
+}
+
+fn print_sizes(sizes: &BTreeMap<String, u64>, prev: &BTreeMap<String, u64>) {
+    println!(
+        "{:<40} {:>12} {:>12} {:>8}",
+        "artifact", "size", "delta", ""
+    );
+    for (name, &size) in sizes {
+        match prev.get(name) {
+            Some(&old) => print_delta(name, size, old),
+            None => println!("{name:<40} {size:>12} {:>12} {:>8}", "", "new"),
+        }
+    }
+    for (name, &old) in prev {
+        if !sizes.contains_key(name) {
+            println!(
+                "{name:<40} {:>12} {:>12} {:>8}",
+                0,
+                -(old as i64),
+                "removed"
+            );
+        }
+    }
+
+    // The files in the BOOTFS are already counted in its image.
+    let total = |sizes: &BTreeMap<String, u64>| -> u64 {
+        sizes
+            .iter()
+            .filter(|(name, _)| !name.contains('/'))
+            .map(|(_, size)| size)
+            .sum()
+    };
+    print_delta("total", total(sizes), total(prev));
+}
+
+fn print_delta(name: &str, size: u64, old: u64) {
+    let delta = size as i64 - old as i64;
+    let change = if old > 0 {
+        format!("{:+.1}%", delta as f64 / old as f64 * 100.)
+    } else {
+        String::new()
+    };
+    println!("{name:<40} {size:>12} {delta:>+12} {change:>8}");
+}
+
+/// Print the sections of the object file `path` like `bloaty`, with the
+/// shares of the total size.
+fn print_sections(name: &str, path: &Path) -> anyhow::Result<()> {
+    let output = Command::new(&*LLVM_SIZE).arg("-A").arg(path).output()?;
+    output
+        .status
+        .exit_ok()
+        .with_context(|| format!("failed to get the sections of {name}"))?;
+
+    // The lines of the sections are like `.text    1234    4096`.
+    let mut sections = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let section = columns.next()?;
+            let size = columns.next()?.parse::<u64>().ok()?;
+            columns.next()?.parse::<u64>().ok()?;
+            (section != "Total" && size > 0).then(|| (section.to_string(), size))
+        })
+        .collect::<Vec<_>>();
+    sections.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let total = sections.iter().map(|(_, size)| size).sum::<u64>().max(1);
+    println!();
+    println!("{name}:");
+    for (section, size) in sections {
+        let share = size as f64 / total as f64 * 100.;
+        println!("    {section:<32} {size:>12} {share:>7.1}%");
+    }
+    Ok(())
+}
+
+/// Generate the dependency graph of the BOOTFS files with the shared libraries
+/// they need.
+fn graph(
+    artifacts: &BTreeMap<String, PathBuf>,
+    sizes: &BTreeMap<String, u64>,
+) -> anyhow::Result<String> {
+    let mut dot = String::from("digraph bootfs {\n    rankdir=LR;\n    node [shape=box];\n");
+    for (name, path) in artifacts {
+        let Some(name) = name.strip_prefix("BOOT.fs/") else {
+            continue;
+        };
+        let size = sizes[&format!("BOOT.fs/{name}")];
+        dot += &format!("    \"{name}\" [label=\"{name}\\n{} KiB\"];\n", size / 1024);
+
+        let output = Command::new(&*LLVM_OBJDUMP).arg("-p").arg(path).output()?;
+        // Skip the files that are not objects.
+        if !output.status.success() {
+            continue;
+        }
+        // The lines of the needed libraries are like `NEEDED    libco2.so`.
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let mut columns = line.split_whitespace();
+            if let (Some("NEEDED"), Some(lib)) = (columns.next(), columns.next()) {
+                dot += &format!("    \"{name}\" -> \"lib/{lib}\";\n");
+            }
+        }
+    }
+    dot += "}\n";
+    Ok(dot)
+}
@@ -0,0 +1,76 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::LazyLock,
+};
+
+use structopt::StructOpt;
+
+use crate::{dist::LLVM, DEBUG_DIR};
+
+static LLVM_SYMBOLIZER: LazyLock<PathBuf> =
+    LazyLock::new(|| Path::new(&*LLVM).join("bin/llvm-symbolizer"));
+
+/// Symbolize the backtraces of panic reports in a log with the `.sym`
+/// artifacts generated by `dist`.
+#[derive(Debug, StructOpt)]
+pub struct Symbolize {
+    /// The log file to be symbolized. Reads from the standard input if not
+    /// specified.
+    #[structopt(parse(from_os_str))]
+    input: Option<PathBuf>,
+    /// The directory of the debug artifacts.
+    #[structopt(long = "--debug-dir", parse(from_os_str), default_value = DEBUG_DIR)]
+    debug_dir: PathBuf,
+}
+
+impl Symbolize {
+    pub fn run(self) -> anyhow::Result<()> {
+        let input: Box<dyn BufRead> = match self.input {
+            Some(ref path) => Box::new(BufReader::new(fs::File::open(path)?)),
+            None => Box::new(io::stdin().lock()),
+        };
+
+        for line in input.lines() {
+            let line = line?;
+            println!("{line}");
+            if let Some((module, offset)) = parse_frame(&line) {
+                self.symbolize(module, offset)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn symbolize(&self, module: &str, offset: usize) -> anyhow::Result<()> {
+        // Modules are named with their paths in the bootfs, while the debug
+        // artifacts are named with their file names.
+        let name = module.rsplit('/').next().unwrap_or(module);
+        let sym = self.debug_dir.join(format!("{name}.sym"));
+        if !sym.exists() {
+            return Ok(());
+        }
+
+        let output = Command::new(&*LLVM_SYMBOLIZER)
+            .arg("--demangle")
+            .arg(format!("--obj={}", sym.display()))
+            .arg(format!("{offset:#x}"))
+            .output()?;
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|s| !s.is_empty())
+            .for_each(|s| println!("        {s}"));
+        Ok(())
+    }
+}
+
+/// Parse a frame line like `#3 lib/libfoo.so+0x1234 (0x7f0000001234)`.
+fn parse_frame(line: &str) -> Option<(&str, usize)> {
+    let frame = &line[line.find('#')?..];
+    let (index, rest) = frame[1..].split_once(' ')?;
+    index.parse::<usize>().ok()?;
+    let (module, rest) = rest.split_once("+0x")?;
+    let offset = rest.split(' ').next()?;
+    Some((module, usize::from_str_radix(offset, 16).ok()?))
+}
@@ -0,0 +1,123 @@
+use std::{
+    env, fs,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use structopt::StructOpt;
+
+use crate::{bench::Bench, dist::Dist, DEBUG_DIR};
+
+/// The crates whose unit tests run on the host, relative to the source root.
+const HOST_TESTS: &[&str] = &["h2o/libs/pmm"];
+
+const KTEST_RESULT: &str = "ktest result: ok";
+/// Printed by TINIT after the error paths are exercised with injected faults.
+const FAULT_RESULT: &str = "fault injection result: ok";
+const KTEST_LOG: &str = "ktest.log";
+
+/// Run the unit tests on the host, and then the in-kernel unit tests and the
+/// fault injection tests in QEMU, optionally followed by the benchmarks.
+#[derive(Debug, StructOpt)]
+pub struct Test {
+    /// Only run the unit tests on the host.
+    #[structopt(long = "--host-only", parse(from_flag))]
+    host_only: bool,
+    #[structopt(long = "--release", parse(from_flag))]
+    release: bool,
+    /// The seconds to wait for the in-kernel tests to finish.
+    #[structopt(long = "--timeout", default_value = "120")]
+    timeout: u64,
+    /// Also run the benchmarks and fail on the regressions.
+    #[structopt(long = "--bench", parse(from_flag))]
+    bench: bool,
+}
+
+impl Test {
+    pub fn run(self) -> anyhow::Result<()> {
+        let src_root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+        let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+
+        for krate in HOST_TESTS {
+            println!("Testing {krate} on the host");
+            Command::new(&cargo)
+                .current_dir(src_root.join(krate))
+                .arg("test")
+                .status()?
+                .exit_ok()
+                .with_context(|| format!("host tests of {krate} failed"))?;
+        }
+        if self.host_only {
+            return Ok(());
+        }
+
+        Dist::ktest(self.release)
+            .build()
+            .context("failed to build the kernel with ktest")?;
+        self.run_ktest(src_root)?;
+
+        if self.bench {
+            Bench::new(self.release, self.timeout).run()?;
+        }
+        Ok(())
+    }
+
+    fn run_ktest(&self, src_root: &Path) -> anyhow::Result<()> {
+        println!("Running the in-kernel tests in QEMU");
+        let timeout = Duration::from_secs(self.timeout);
+        run_qemu(src_root, KTEST_LOG, timeout, &[KTEST_RESULT, FAULT_RESULT])
+            .context("in-kernel tests failed")?;
+        println!("In-kernel tests and fault injection tests passed");
+        Ok(())
+    }
+}
+
+/// Boot the image in QEMU with the serial output written to `log_name` in the
+/// debug directory, until all the `results` are printed.
+///
+/// Returns the serial output.
+pub(crate) fn run_qemu(
+    src_root: &Path,
+    log_name: &str,
+    timeout: Duration,
+    results: &[&str],
+) -> anyhow::Result<String> {
+    let log = src_root.join(DEBUG_DIR).join(log_name);
+    let _ = fs::remove_file(&log);
+
+    let mut qemu = Command::new("qemu-system-x86_64")
+        .current_dir(src_root)
+        .args(["-L", "/usr/share/ovmf", "-bios", "OVMF.fd"])
+        .args(["-m", "4096", "-cpu", "max", "-smp", "2"])
+        .args(["-drive", "format=raw,file=target/img/efi.img", "-boot", "c"])
+        .args(["-display", "none", "-monitor", "none"])
+        .arg("-serial")
+        .arg(format!("file:{}", log.to_string_lossy()))
+        .stdin(Stdio::null())
+        .spawn()
+        .context("failed to start QEMU")?;
+
+    let deadline = Instant::now() + timeout;
+    let result = loop {
+        let output = fs::read_to_string(&log).unwrap_or_default();
+        if results.iter().all(|result| output.contains(result)) {
+            break Ok(output);
+        }
+        if output.contains("panicked") {
+            break Err(anyhow!("the system panicked, see {log:?}"));
+        }
+        if qemu.try_wait()?.is_some() {
+            break Err(anyhow!("QEMU exited early, see {log:?}"));
+        }
+        if Instant::now() >= deadline {
+            break Err(anyhow!("timed out, see {log:?}"));
+        }
+        thread::sleep(Duration::from_millis(500));
+    };
+    let _ = qemu.kill();
+    let _ = qemu.wait();
+    result
+}